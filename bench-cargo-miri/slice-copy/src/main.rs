@@ -0,0 +1,58 @@
+//! Large `copy_nonoverlapping` and `copy` calls, on plain bytes, on memory that is only partially
+//! initialized, and on memory that contains pointers. Each of these exercises a different part of
+//! the per-byte metadata (init mask and provenance) that has to be copied along with the data.
+
+use std::mem::MaybeUninit;
+use std::ptr;
+
+const LEN: usize = 1 << 16;
+const ROUNDS: usize = 16;
+
+fn copy_bytes() {
+    let src = vec![0x42u8; LEN];
+    let mut dst = vec![0u8; LEN];
+    for _ in 0..ROUNDS {
+        unsafe { ptr::copy_nonoverlapping(src.as_ptr(), dst.as_mut_ptr(), LEN) };
+    }
+    assert_eq!(dst[LEN - 1], 0x42);
+}
+
+fn copy_partially_uninit() {
+    let mut src = vec![MaybeUninit::<u8>::uninit(); LEN];
+    // Initialize every other chunk of 64 bytes, so the init mask has many blocks.
+    for chunk in src.chunks_mut(128) {
+        for byte in &mut chunk[..64] {
+            byte.write(1);
+        }
+    }
+    let mut dst = vec![MaybeUninit::<u8>::uninit(); LEN];
+    for _ in 0..ROUNDS {
+        unsafe { ptr::copy_nonoverlapping(src.as_ptr(), dst.as_mut_ptr(), LEN) };
+    }
+    assert_eq!(unsafe { dst[0].assume_init() }, 1);
+}
+
+fn copy_pointers() {
+    let target = 0u64;
+    let src = vec![&target as *const u64; LEN / 8];
+    let mut dst = vec![ptr::null::<u64>(); LEN / 8];
+    for _ in 0..ROUNDS {
+        unsafe { ptr::copy_nonoverlapping(src.as_ptr(), dst.as_mut_ptr(), src.len()) };
+    }
+    assert_eq!(unsafe { *dst[dst.len() - 1] }, 0);
+}
+
+fn copy_overlapping() {
+    let mut buf = vec![7u8; 2 * LEN];
+    for _ in 0..ROUNDS {
+        unsafe { ptr::copy(buf.as_ptr(), buf.as_mut_ptr().add(LEN / 2), LEN) };
+    }
+    assert_eq!(buf[LEN], 7);
+}
+
+fn main() {
+    copy_bytes();
+    copy_partially_uninit();
+    copy_pointers();
+    copy_overlapping();
+}
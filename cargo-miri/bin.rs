@@ -12,6 +12,8 @@ use serde::{Deserialize, Serialize};
 
 use rustc_version::VersionMeta;
 
+mod report;
+
 const XARGO_MIN_VERSION: (u32, u32, u32) = (0, 3, 23);
 
 const CARGO_MIRI_HELP: &str = r#"Runs binary crates and tests in Miri
@@ -25,6 +27,9 @@ Subcommands:
     setup                    Only perform automatic setup, but without asking questions (for getting a proper libstd)
 
 The cargo options are exactly the same as for `cargo run` and `cargo test`, respectively.
+In addition, the following options are supported:
+
+    --html-report <file>     Write a report of all interpreted binaries to the HTML file <file>
 
 Examples:
     cargo miri run
@@ -394,20 +399,34 @@ fn setup(subcommand: MiriCommand) {
     if !dir.exists() {
         fs::create_dir_all(&dir).unwrap();
     }
+    // Determine architectures.
+    // We always need to set a target so rustc bootstrap can tell apart host from target crates.
+    let host = version_info().host;
+    let target = get_arg_flag_value("--target");
+    let target = target.as_ref().unwrap_or(&host);
+    // Targets without an operating system (and some others, like AVR) have no `std`, so we only
+    // build `core` and `alloc` for them.
+    let no_std = match env::var_os("MIRI_NO_STD") {
+        None => target.contains("-none"),
+        Some(val) => val != "0",
+    };
     // The interesting bit: Xargo.toml
-    File::create(dir.join("Xargo.toml"))
-        .unwrap()
-        .write_all(
-            br#"
+    let xargo_toml: &[u8] = if no_std {
+        br#"
+[dependencies.core]
+[dependencies.alloc]
+"#
+    } else {
+        br#"
 [dependencies.std]
 default_features = false
 # We support unwinding, so enable that panic runtime.
 features = ["panic_unwind", "backtrace"]
 
 [dependencies.test]
-"#,
-        )
-        .unwrap();
+"#
+    };
+    File::create(dir.join("Xargo.toml")).unwrap().write_all(xargo_toml).unwrap();
     // The boring bits: a dummy project for xargo.
     // FIXME: With xargo-check, can we avoid doing this?
     File::create(dir.join("Cargo.toml"))
@@ -426,11 +445,6 @@ path = "lib.rs"
         .unwrap();
     File::create(dir.join("lib.rs")).unwrap();
 
-    // Determine architectures.
-    // We always need to set a target so rustc bootstrap can tell apart host from target crates.
-    let host = version_info().host;
-    let target = get_arg_flag_value("--target");
-    let target = target.as_ref().unwrap_or(&host);
     // Now invoke xargo.
     let mut command = xargo_check();
     command.arg("check").arg("-q");
@@ -596,8 +610,11 @@ fn phase_cargo_miri(mut args: env::Args) {
     };
 
     let mut target_dir = None;
+    let mut html_report: Option<PathBuf> = None;
 
-    // Forward all arguments before `--` other than `--target-dir` and its value to Cargo.
+    // Forward all arguments before `--` other than `--target-dir`, `--html-report`, and their
+    // values to Cargo.
+    let mut cargo_args = Vec::new();
     for arg in ArgSplitFlagValue::new(&mut args, "--target-dir") {
         match arg {
             Ok(value) => {
@@ -606,6 +623,17 @@ fn phase_cargo_miri(mut args: env::Args) {
                 }
                 target_dir = Some(value.into());
             }
+            Err(arg) => cargo_args.push(arg),
+        }
+    }
+    for arg in ArgSplitFlagValue::new(cargo_args.into_iter(), "--html-report") {
+        match arg {
+            Ok(value) => {
+                if html_report.is_some() {
+                    show_error(format!("`--html-report` is provided more than once"));
+                }
+                html_report = Some(value.into());
+            }
             Err(arg) => {
                 cmd.arg(arg);
             }
@@ -619,7 +647,39 @@ fn phase_cargo_miri(mut args: env::Args) {
 
     // Set `--target-dir` to `miri` inside the original target directory.
     target_dir.push("miri");
-    cmd.arg("--target-dir").arg(target_dir);
+    cmd.arg("--target-dir").arg(&target_dir);
+
+    // The runner phase records the results of the interpreted binaries in this directory.
+    let report_dir = html_report.as_ref().map(|_| {
+        let report_dir = target_dir.join("html-report-runs");
+        if report_dir.exists() {
+            fs::remove_dir_all(&report_dir).expect("failed to clean up old run records");
+        }
+        fs::create_dir_all(&report_dir).expect("failed to create the run record directory");
+        cmd.env("MIRI_REPORT_DIR", &report_dir);
+        report_dir
+    });
+    let quiet = has_arg_flag("-q") || has_arg_flag("--quiet");
+    // A summary left over from an earlier run must not be mistaken for one of this run.
+    let shims_summary = target_dir.join("unsupported-shims.json");
+    if shims_summary.exists() {
+        fs::remove_file(&shims_summary).expect("failed to remove the old unsupported shim summary");
+    }
+    // The interpreted binaries record the foreign functions they called that Miri does not
+    // support in this directory, so that we can summarize them at the end. With `-q`, there is
+    // no summary.
+    let shims_dir = (!quiet).then(|| {
+        let shims_dir = target_dir.join("unsupported-shims");
+        if shims_dir.exists() {
+            fs::remove_dir_all(&shims_dir)
+                .expect("failed to clean up old unsupported shim records");
+        }
+        fs::create_dir_all(&shims_dir).expect("failed to create the unsupported shim directory");
+        // Miri changes its working directory before running the program.
+        let shims_dir = fs::canonicalize(&shims_dir).expect("failed to find the shim directory");
+        cmd.env("MIRI_UNSUPPORTED_SHIMS_DIR", &shims_dir);
+        shims_dir
+    });
 
     // Forward all further arguments after `--` to cargo.
     cmd.arg("--").args(args);
@@ -660,7 +720,29 @@ fn phase_cargo_miri(mut args: env::Args) {
         eprintln!("[cargo-miri miri] {:?}", cmd);
         cmd.env("MIRI_VERBOSE", ""); // This makes the other phases verbose.
     }
-    exec(cmd)
+    if report_dir.is_none() && shims_dir.is_none() {
+        // Nothing to do once cargo is done.
+        exec(cmd);
+        return;
+    }
+    // Cargo stops at the first failing binary, but we still want the reports.
+    let exit_status = cmd.status().expect("failed to run command");
+    if let (Some(html_report), Some(report_dir)) = (html_report, report_dir) {
+        report::write_html_report(&report_dir, &html_report).unwrap_or_else(|err| {
+            show_error(format!("cannot write `{}`: {}", html_report.display(), err))
+        });
+        if !quiet {
+            eprintln!("The HTML report was written to `{}`.", html_report.display());
+        }
+    }
+    if let Some(shims_dir) = shims_dir {
+        report::summarize_unsupported_shims(&shims_dir, &shims_summary).unwrap_or_else(|err| {
+            show_error(format!("cannot write `{}`: {}", shims_summary.display(), err))
+        });
+    }
+    if exit_status.success().not() {
+        std::process::exit(exit_status.code().unwrap_or(-1))
+    }
 }
 
 #[derive(Debug, Copy, Clone, PartialEq)]
@@ -930,6 +1012,11 @@ fn phase_runner(binary: &Path, binary_args: env::Args, phase: RunnerPhase) {
     // We also need to remove `--error-format` as cargo specifies that to be JSON,
     // but when we run here, cargo does not interpret the JSON any more. `--json`
     // then also nees to be dropped.
+    let crate_name = info
+        .args
+        .iter()
+        .position(|arg| arg == "--crate-name")
+        .and_then(|i| info.args.get(i + 1).cloned());
     let mut args = info.args.into_iter();
     let error_format_flag = "--error-format";
     let json_flag = "--json";
@@ -973,6 +1060,15 @@ fn phase_runner(binary: &Path, binary_args: env::Args, phase: RunnerPhase) {
         eprintln!("[cargo-miri runner] {:?}", cmd);
     }
 
+    if let Some(report_dir) = env::var_os("MIRI_REPORT_DIR") {
+        let mut name = crate_name.unwrap_or_else(|| binary.display().to_string());
+        if phase == RunnerPhase::Rustdoc {
+            name.push_str(" (doctest)");
+        }
+        let stdin = if phase == RunnerPhase::Rustdoc { Some(&info.stdin[..]) } else { None };
+        report::exec_recorded(cmd, stdin, name, Path::new(&report_dir));
+        return;
+    }
     match phase {
         RunnerPhase::Rustdoc => exec_with_pipe(cmd, &info.stdin),
         RunnerPhase::Cargo => exec(cmd),
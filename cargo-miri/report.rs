@@ -0,0 +1,389 @@
+//! Support for `cargo miri test --html-report=<file>`: every interpreted binary records its
+//! output, and once cargo is done the records are turned into a static HTML page.
+//!
+//! Independently of that, Miri records the foreign functions it does not support in a file per
+//! binary, which are summarized at the end of every `cargo miri` invocation without `-q`.
+
+use std::collections::BTreeMap;
+use std::fmt::Write as _;
+use std::fs::{self, File};
+use std::io::{self, BufReader, Read, Write};
+use std::path::Path;
+use std::process::{self, Command};
+use std::thread;
+use std::time::Instant;
+
+use serde::{Deserialize, Serialize};
+
+/// How many of the slowest binaries are listed in the report.
+const SLOWEST_N: usize = 10;
+
+/// The result of interpreting one binary.
+#[derive(Serialize, Deserialize)]
+pub struct RunRecord {
+    /// The crate that was interpreted.
+    name: String,
+    /// `None` if the process was killed by a signal.
+    exit_code: Option<i32>,
+    /// The wall-clock time the interpretation took, in seconds.
+    duration: f64,
+    stdout: String,
+    stderr: String,
+}
+
+/// The outcome of a single test, as printed by libtest.
+#[derive(PartialEq, Eq)]
+enum TestOutcome {
+    Ok,
+    Failed,
+    Ignored,
+}
+
+impl RunRecord {
+    /// The tests of this binary with their outcomes, as far as libtest printed them. With the
+    /// default terse output format, only failed tests are named.
+    fn tests(&self) -> Vec<(&str, TestOutcome)> {
+        let mut tests = Vec::new();
+        for line in self.stdout.lines() {
+            if let Some(line) = line.strip_prefix("test ") {
+                let outcome = if line.ends_with(" ... ok") {
+                    TestOutcome::Ok
+                } else if line.ends_with(" ... FAILED") {
+                    TestOutcome::Failed
+                } else if line.ends_with(" ... ignored") {
+                    TestOutcome::Ignored
+                } else {
+                    continue;
+                };
+                tests.push((line.rsplit_once(" ... ").unwrap().0, outcome));
+            } else if let Some(name) =
+                line.strip_prefix("---- ").and_then(|line| line.strip_suffix(" stdout ----"))
+            {
+                if !tests.iter().any(|(test, _)| *test == name) {
+                    tests.push((name, TestOutcome::Failed));
+                }
+            }
+        }
+        tests
+    }
+
+    /// The number of passed, failed, and ignored tests, summed over libtest's summary lines.
+    fn test_counts(&self) -> (u64, u64, u64) {
+        let (mut passed, mut failed, mut ignored) = (0, 0, 0);
+        for line in self.stdout.lines() {
+            let summary = match line.strip_prefix("test result: ") {
+                Some(summary) => summary,
+                None => continue,
+            };
+            for part in summary.split(|c| c == '.' || c == ';') {
+                let mut words = part.split_whitespace();
+                let (count, what) = match (words.next(), words.next()) {
+                    (Some(count), Some(what)) => (count, what),
+                    _ => continue,
+                };
+                let count: u64 = match count.parse() {
+                    Ok(count) => count,
+                    Err(_) => continue,
+                };
+                match what {
+                    "passed" => passed += count,
+                    "failed" => failed += count,
+                    "ignored" => ignored += count,
+                    _ => {}
+                }
+            }
+        }
+        (passed, failed, ignored)
+    }
+
+    /// The title of the first error Miri reported, such as `Undefined Behavior`.
+    fn error_kind(&self) -> Option<&str> {
+        self.stderr.lines().find_map(|line| {
+            let rest = line.strip_prefix("error")?;
+            // Skip the error code, if any.
+            let rest = match rest.strip_prefix('[') {
+                Some(rest) => rest.split_once(']')?.1,
+                None => rest,
+            };
+            let msg = rest.strip_prefix(": ")?;
+            Some(msg.split_once(": ").map_or(msg, |(title, _)| title))
+        })
+    }
+
+    /// The foreign functions that Miri reported as unsupported.
+    fn unsupported_shims(&self) -> Vec<&str> {
+        const PATTERNS: &[&str] =
+            &["can't call foreign function: ", "can't call foreign function `"];
+        let mut shims = Vec::new();
+        for pattern in PATTERNS {
+            for (start, _) in self.stderr.match_indices(pattern) {
+                let rest = &self.stderr[start + pattern.len()..];
+                let name = rest.split(|c: char| c == '`' || c.is_whitespace()).next().unwrap();
+                if !name.is_empty() {
+                    shims.push(name);
+                }
+            }
+        }
+        shims
+    }
+}
+
+/// Copy everything from `from` to `to`, and return what was copied.
+fn tee(
+    mut from: impl Read + Send + 'static,
+    mut to: impl Write + Send + 'static,
+) -> thread::JoinHandle<Vec<u8>> {
+    thread::spawn(move || {
+        let mut captured = Vec::new();
+        let mut buf = [0; 4096];
+        loop {
+            let n = match from.read(&mut buf) {
+                Ok(0) | Err(_) => break,
+                Ok(n) => n,
+            };
+            // The output is still shown if writing it fails; the record is what matters here.
+            let _ = to.write_all(&buf[..n]).and_then(|()| to.flush());
+            captured.extend_from_slice(&buf[..n]);
+        }
+        captured
+    })
+}
+
+/// Run `cmd`, passing `stdin` to it if given, while recording its output in a new file in
+/// `dir`. Like `exec`, fail this process with the same exit code if the command fails.
+pub fn exec_recorded(mut cmd: Command, stdin: Option<&[u8]>, name: String, dir: &Path) {
+    let start = Instant::now();
+    cmd.stdout(process::Stdio::piped()).stderr(process::Stdio::piped());
+    if stdin.is_some() {
+        cmd.stdin(process::Stdio::piped());
+    }
+    let mut child = cmd.spawn().expect("failed to spawn process");
+    let stdout = tee(child.stdout.take().unwrap(), io::stdout());
+    let stderr = tee(child.stderr.take().unwrap(), io::stderr());
+    if let Some(input) = stdin {
+        // Dropping `stdin` closes it, which the child might need to finish.
+        let mut stdin = child.stdin.take().unwrap();
+        stdin.write_all(input).expect("failed to write out test source");
+    }
+    let exit_status = child.wait().expect("failed to run command");
+    let record = RunRecord {
+        name,
+        exit_code: exit_status.code(),
+        duration: start.elapsed().as_secs_f64(),
+        stdout: String::from_utf8_lossy(&stdout.join().unwrap()).into_owned(),
+        stderr: String::from_utf8_lossy(&stderr.join().unwrap()).into_owned(),
+    };
+    // Binaries (e.g. doctests) may run concurrently, so use our process id to pick a unique name.
+    let filename = dir.join(format!("{}.json", process::id()));
+    let file = File::create(&filename).expect("failed to create the run record");
+    serde_json::to_writer(file, &record).expect("failed to write the run record");
+    if !exit_status.success() {
+        process::exit(exit_status.code().unwrap_or(-1))
+    }
+}
+
+/// Read all records in `dir`, sorted by name.
+fn read_records(dir: &Path) -> io::Result<Vec<RunRecord>> {
+    let mut records = Vec::new();
+    for entry in fs::read_dir(dir)? {
+        let file = BufReader::new(File::open(entry?.path())?);
+        let record: RunRecord = serde_json::from_reader(file)
+            .map_err(|err| io::Error::new(io::ErrorKind::InvalidData, err))?;
+        records.push(record);
+    }
+    records.sort_by(|a, b| a.name.cmp(&b.name));
+    Ok(records)
+}
+
+fn escape(s: &str) -> String {
+    s.replace('&', "&amp;").replace('<', "&lt;").replace('>', "&gt;").replace('"', "&quot;")
+}
+
+/// Turn the records in `dir` into an HTML report at `out`.
+pub fn write_html_report(dir: &Path, out: &Path) -> io::Result<()> {
+    let records = read_records(dir)?;
+    let mut html = String::new();
+    html.push_str(concat!(
+        "<!DOCTYPE html>\n<html>\n<head>\n<meta charset=\"utf-8\">\n",
+        "<title>Miri test report</title>\n<style>\n",
+        "body { font-family: sans-serif; margin: 2em; }\n",
+        "table { border-collapse: collapse; }\n",
+        "th, td { border: 1px solid #ccc; padding: 0.2em 0.6em; text-align: left; }\n",
+        ".pass { color: #080; } .fail { color: #c00; }\n",
+        "pre { background: #f4f4f4; padding: 0.5em; overflow-x: auto; }\n",
+        "</style>\n</head>\n<body>\n<h1>Miri test report</h1>\n",
+    ));
+
+    // Aggregate statistics.
+    let failed_runs = records.iter().filter(|r| r.exit_code != Some(0)).count();
+    let (mut passed, mut failed, mut ignored) = (0, 0, 0);
+    let mut shims: Vec<(&str, usize)> = Vec::new();
+    for record in &records {
+        let (p, f, i) = record.test_counts();
+        passed += p;
+        failed += f;
+        ignored += i;
+        for shim in record.unsupported_shims() {
+            match shims.iter_mut().find(|(name, _)| *name == shim) {
+                Some((_, count)) => *count += 1,
+                None => shims.push((shim, 1)),
+            }
+        }
+    }
+    shims.sort_by(|a, b| b.1.cmp(&a.1).then(a.0.cmp(b.0)));
+    let total_time: f64 = records.iter().map(|r| r.duration).sum();
+    writeln!(html, "<h2>Summary</h2>\n<ul>").unwrap();
+    writeln!(
+        html,
+        "<li>{} binaries interpreted, {} of them failed, in {:.2}s</li>",
+        records.len(),
+        failed_runs,
+        total_time
+    )
+    .unwrap();
+    writeln!(
+        html,
+        "<li>{} tests passed, {} failed, {} ignored</li>\n</ul>",
+        passed, failed, ignored
+    )
+    .unwrap();
+    if !shims.is_empty() {
+        writeln!(html, "<h3>Unsupported foreign functions</h3>\n<table>").unwrap();
+        writeln!(html, "<tr><th>function</th><th>binaries</th></tr>").unwrap();
+        for (shim, count) in &shims {
+            writeln!(html, "<tr><td><code>{}</code></td><td>{}</td></tr>", escape(shim), count)
+                .unwrap();
+        }
+        writeln!(html, "</table>").unwrap();
+    }
+    let mut slowest: Vec<&RunRecord> = records.iter().collect();
+    slowest.sort_by(|a, b| b.duration.partial_cmp(&a.duration).unwrap());
+    writeln!(html, "<h3>Slowest binaries</h3>\n<table>").unwrap();
+    writeln!(html, "<tr><th>crate</th><th>time</th></tr>").unwrap();
+    for record in slowest.into_iter().take(SLOWEST_N) {
+        writeln!(
+            html,
+            "<tr><td>{}</td><td>{:.2}s</td></tr>",
+            escape(&record.name),
+            record.duration
+        )
+        .unwrap();
+    }
+    writeln!(html, "</table>").unwrap();
+
+    // One table row and one section per binary.
+    writeln!(html, "<h2>Binaries</h2>\n<table>").unwrap();
+    writeln!(
+        html,
+        "<tr><th>crate</th><th>outcome</th><th>tests</th><th>error</th><th>time</th></tr>"
+    )
+    .unwrap();
+    for (i, record) in records.iter().enumerate() {
+        let (class, outcome) = match record.exit_code {
+            Some(0) => ("pass", "passed".to_string()),
+            Some(code) => ("fail", format!("failed (exit code {})", code)),
+            None => ("fail", "killed".to_string()),
+        };
+        let (passed, failed, ignored) = record.test_counts();
+        writeln!(
+            html,
+            "<tr><td><a href=\"#run{}\">{}</a></td><td class=\"{}\">{}</td>\
+            <td>{} passed, {} failed, {} ignored</td><td>{}</td><td>{:.2}s</td></tr>",
+            i,
+            escape(&record.name),
+            class,
+            outcome,
+            passed,
+            failed,
+            ignored,
+            escape(record.error_kind().unwrap_or("")),
+            record.duration
+        )
+        .unwrap();
+    }
+    writeln!(html, "</table>").unwrap();
+    for (i, record) in records.iter().enumerate() {
+        writeln!(html, "<h3 id=\"run{}\">{}</h3>", i, escape(&record.name)).unwrap();
+        let tests = record.tests();
+        if !tests.is_empty() {
+            writeln!(html, "<ul>").unwrap();
+            for (name, outcome) in tests {
+                let (class, outcome) = match outcome {
+                    TestOutcome::Ok => ("pass", "ok"),
+                    TestOutcome::Failed => ("fail", "FAILED"),
+                    TestOutcome::Ignored => ("", "ignored"),
+                };
+                writeln!(
+                    html,
+                    "<li><code>{}</code> <span class=\"{}\">{}</span></li>",
+                    escape(name),
+                    class,
+                    outcome
+                )
+                .unwrap();
+            }
+            writeln!(html, "</ul>").unwrap();
+        }
+        let open = if record.exit_code == Some(0) { "" } else { " open" };
+        for (what, output) in [("stdout", &record.stdout), ("stderr", &record.stderr)] {
+            if !output.is_empty() {
+                writeln!(
+                    html,
+                    "<details{}><summary>{}</summary><pre>{}</pre></details>",
+                    open,
+                    what,
+                    escape(output)
+                )
+                .unwrap();
+            }
+        }
+    }
+    html.push_str("</body>\n</html>\n");
+    fs::write(out, html)
+}
+
+/// How often a foreign function that Miri does not support was called.
+#[derive(Default, Serialize)]
+struct UnsupportedShim {
+    /// The number of calls, summed over all binaries.
+    calls: u64,
+    /// The number of binaries that called it.
+    binaries: u64,
+}
+
+/// Summarize the foreign functions that Miri does not support, as recorded by the interpreted
+/// binaries in `dir`: print a table of them, and write it to `out` as JSON. Does nothing if no
+/// binary called such a function.
+pub fn summarize_unsupported_shims(dir: &Path, out: &Path) -> io::Result<()> {
+    let mut shims: BTreeMap<String, UnsupportedShim> = BTreeMap::new();
+    for entry in fs::read_dir(dir)? {
+        let file = BufReader::new(File::open(entry?.path())?);
+        let calls: BTreeMap<String, u64> = serde_json::from_reader(file)
+            .map_err(|err| io::Error::new(io::ErrorKind::InvalidData, err))?;
+        for (name, calls) in calls {
+            let shim = shims.entry(name).or_default();
+            shim.calls += calls;
+            shim.binaries += 1;
+        }
+    }
+    if shims.is_empty() {
+        return Ok(());
+    }
+    serde_json::to_writer_pretty(File::create(out)?, &shims)?;
+
+    let mut sorted: Vec<_> = shims.iter().collect();
+    sorted.sort_by(|a, b| b.1.calls.cmp(&a.1.calls).then(a.0.cmp(b.0)));
+    eprintln!("Miri does not support these foreign functions, which were called:");
+    for (name, shim) in sorted {
+        eprintln!(
+            "    `{}`: {} call{} in {} binar{}",
+            name,
+            shim.calls,
+            if shim.calls == 1 { "" } else { "s" },
+            shim.binaries,
+            if shim.binaries == 1 { "y" } else { "ies" }
+        );
+    }
+    eprintln!("The summary was written to `{}`.", out.display());
+    Ok(())
+}
@@ -9,6 +9,7 @@ extern crate rustc_interface;
 extern crate rustc_metadata;
 extern crate rustc_middle;
 extern crate rustc_session;
+extern crate rustc_target;
 
 use std::env;
 use std::num::NonZeroU64;
@@ -20,17 +21,35 @@ use log::debug;
 use rustc_data_structures::sync::Lrc;
 use rustc_driver::Compilation;
 use rustc_errors::emitter::{ColorConfig, HumanReadableErrorType};
-use rustc_hir::{self as hir, def_id::LOCAL_CRATE, Node};
+use rustc_hir::{
+    self as hir,
+    def::DefKind,
+    def_id::{DefId, LOCAL_CRATE},
+    Node,
+};
 use rustc_interface::interface::Config;
 use rustc_middle::{
     middle::exported_symbols::{
         ExportedSymbol, SymbolExportInfo, SymbolExportKind, SymbolExportLevel,
     },
-    ty::{query::ExternProviders, TyCtxt},
+    ty::{query::ExternProviders, Instance, TyCtxt},
+};
+use rustc_session::{
+    config::{EntryFnType, ErrorOutputType},
+    search_paths::PathKind,
+    CtfeBacktrace,
 };
-use rustc_session::{config::ErrorOutputType, search_paths::PathKind, CtfeBacktrace};
+use rustc_target::{abi::Size, spec::abi::Abi};
 
-use miri::BacktraceStyle;
+use miri::{BacktraceStyle, ErrorSpan, LeakReport, WarningKind, WarningLevel};
+
+/// Prints an error about the command line and exits.
+macro_rules! show_error {
+    ($($tt:tt)*) => {{
+        eprintln!("fatal error: {}", format_args!($($tt)*));
+        std::process::exit(1)
+    }};
+}
 
 struct MiriCompilerCalls {
     miri_config: miri::MiriConfig,
@@ -61,17 +80,7 @@ impl rustc_driver::Callbacks for MiriCompilerCalls {
 
         queries.global_ctxt().unwrap().peek_mut().enter(|tcx| {
             init_late_loggers(tcx);
-            let (entry_def_id, entry_type) = if let Some(entry_def) = tcx.entry_fn(()) {
-                entry_def
-            } else {
-                let output_ty = ErrorOutputType::HumanReadable(HumanReadableErrorType::Default(
-                    ColorConfig::Auto,
-                ));
-                rustc_session::early_error(
-                    output_ty,
-                    "miri can only run programs that have a main function",
-                );
-            };
+            let (entry_def_id, entry_type) = entry_fn(tcx);
             let mut config = self.miri_config.clone();
 
             // Add filename to `miri` arguments.
@@ -81,6 +90,10 @@ impl rustc_driver::Callbacks for MiriCompilerCalls {
             if let Some(cwd) = env::var_os("MIRI_CWD") {
                 env::set_current_dir(cwd).unwrap();
             }
+            // `cargo miri` summarizes the unsupported foreign functions of all binaries it runs.
+            if let Ok(dir) = env::var("MIRI_UNSUPPORTED_SHIMS_DIR") {
+                config.unsupported_shims_dir = Some(dir);
+            }
 
             if let Some(return_code) = miri::eval_entry(tcx, entry_def_id, entry_type, config) {
                 std::process::exit(
@@ -95,6 +108,44 @@ impl rustc_driver::Callbacks for MiriCompilerCalls {
     }
 }
 
+/// The function that Miri starts the program with: `main` (or a `#[start]` function), or for
+/// `#![no_main]` programs, a function exported as `miri_start`. The latter is called like a
+/// `#[start]` function, without any of the runtime setup of `std`.
+fn entry_fn(tcx: TyCtxt<'_>) -> (DefId, EntryFnType) {
+    if let Some(entry_def) = tcx.entry_fn(()) {
+        return entry_def;
+    }
+    let output_ty =
+        ErrorOutputType::HumanReadable(HumanReadableErrorType::Default(ColorConfig::Auto));
+    let miri_start =
+        tcx.reachable_set(()).iter().map(|def_id| def_id.to_def_id()).find(|&def_id| {
+            tcx.def_kind(def_id) == DefKind::Fn
+                && tcx.codegen_fn_attrs(def_id).contains_extern_indicator()
+                && tcx.symbol_name(Instance::mono(tcx, def_id)).name == "miri_start"
+        });
+    let miri_start = match miri_start {
+        Some(miri_start) => miri_start,
+        None =>
+            rustc_session::early_error(
+                output_ty,
+                "miri can only run programs that have a main function, or that export a \
+                `miri_start` function with `#[no_mangle]`",
+            ),
+    };
+    let sig = tcx.fn_sig(miri_start).skip_binder();
+    let argv_ty = tcx.mk_imm_ptr(tcx.mk_imm_ptr(tcx.types.u8));
+    if sig.abi != Abi::Rust
+        || sig.inputs() != [tcx.types.isize, argv_ty]
+        || sig.output() != tcx.types.isize
+    {
+        rustc_session::early_error(
+            output_ty,
+            "`miri_start` must have the signature `fn(isize, *const *const u8) -> isize`",
+        );
+    }
+    (miri_start, EntryFnType::Start)
+}
+
 struct MiriBeRustCompilerCalls {
     target_crate: bool,
 }
@@ -268,6 +319,30 @@ fn parse_comma_list<T: FromStr>(input: &str) -> Result<Vec<T>, T::Err> {
     input.split(',').map(str::parse::<T>).collect()
 }
 
+/// Parses an offset from UTC of the form `+HH:MM` or `-HH:MM` into seconds.
+fn parse_utc_offset(input: &str) -> Option<i64> {
+    let (sign, rest) = match input.as_bytes().first()? {
+        b'+' => (1, &input[1..]),
+        b'-' => (-1, &input[1..]),
+        _ => return None,
+    };
+    let (hours, minutes) = rest.split_once(':')?;
+    let (hours, minutes): (i64, i64) = (hours.parse().ok()?, minutes.parse().ok()?);
+    if hours > 23 || minutes > 59 {
+        return None;
+    }
+    Some(sign * (hours * 60 + minutes) * 60)
+}
+
+fn parse_tty_size(input: &str) -> Option<(u16, u16)> {
+    let (cols, rows) = input.split_once('x')?;
+    let (cols, rows): (u16, u16) = (cols.parse().ok()?, rows.parse().ok()?);
+    if cols == 0 || rows == 0 {
+        return None;
+    }
+    Some((cols, rows))
+}
+
 fn main() {
     rustc_driver::install_ice_hook();
 
@@ -296,6 +371,23 @@ fn main() {
         )
     }
 
+    // `miri --explain <code>` prints the explanation of an error code, like `rustc --explain`.
+    let args: Vec<String> = env::args().collect();
+    if let [_, flag, code] = &args[..] {
+        if flag == "--explain" {
+            match miri::explain_error_code(code) {
+                Some(explanation) => {
+                    print!("{}", explanation);
+                    std::process::exit(0)
+                }
+                None => {
+                    eprintln!("error: `{}` is not a valid Miri error code", code);
+                    std::process::exit(1)
+                }
+            }
+        }
+    }
+
     // Init loggers the Miri way.
     init_early_loggers();
 
@@ -370,6 +462,115 @@ fn main() {
                             ),
                     };
                 }
+                arg if arg.starts_with("-Zmiri-realloc=") => {
+                    miri_config.realloc_policy = match arg.strip_prefix("-Zmiri-realloc=").unwrap()
+                    {
+                        "random" => miri::ReallocPolicy::Random,
+                        "move" => miri::ReallocPolicy::Move,
+                        "in-place" => miri::ReallocPolicy::InPlace,
+                        _ => panic!("-Zmiri-realloc must be `random`, `move`, or `in-place`"),
+                    };
+                }
+                arg if arg.starts_with("-Zmiri-process-spawn=") => {
+                    miri_config.process_spawn =
+                        match arg.strip_prefix("-Zmiri-process-spawn=").unwrap() {
+                            "error" => miri::ProcessSpawn::Error,
+                            "reject-silent" => miri::ProcessSpawn::RejectSilent,
+                            _ => panic!("-Zmiri-process-spawn must be `error` or `reject-silent`"),
+                        };
+                }
+                "-Zmiri-time-anomalies" => {
+                    miri_config.time_anomalies = true;
+                }
+                "-Zmiri-deliver-timer-signals" => {
+                    miri_config.deliver_timer_signals = true;
+                }
+                "-Zmiri-freeze-time" => {
+                    miri_config.freeze_time = Some(0);
+                }
+                arg if arg.starts_with("-Zmiri-utc-offset=") => {
+                    let offset = arg.strip_prefix("-Zmiri-utc-offset=").unwrap();
+                    miri_config.utc_offset = parse_utc_offset(offset).unwrap_or_else(|| {
+                        panic!("-Zmiri-utc-offset must be of the form `+HH:MM` or `-HH:MM`")
+                    });
+                }
+                arg if arg.starts_with("-Zmiri-hostname=") => {
+                    let hostname = arg.strip_prefix("-Zmiri-hostname=").unwrap();
+                    // This is the size of the fields of `utsname` on Linux, without the terminator.
+                    if hostname.is_empty() || hostname.len() > 64 {
+                        panic!("-Zmiri-hostname must be between 1 and 64 bytes long");
+                    }
+                    miri_config.hostname = hostname.to_owned();
+                }
+                arg if arg.starts_with("-Zmiri-home-dir=") => {
+                    let home_dir = arg.strip_prefix("-Zmiri-home-dir=").unwrap();
+                    miri_config.home_dir = Some(home_dir.to_owned());
+                }
+                arg if arg.starts_with("-Zmiri-temp-dir=") => {
+                    let temp_dir = arg.strip_prefix("-Zmiri-temp-dir=").unwrap();
+                    miri_config.temp_dir = Some(temp_dir.to_owned());
+                }
+                arg if arg.starts_with("-Zmiri-exe-path=") => {
+                    let exe_path = arg.strip_prefix("-Zmiri-exe-path=").unwrap();
+                    miri_config.exe_path = Some(exe_path.to_owned());
+                }
+                arg if arg.starts_with("-Zmiri-kernel-release=") => {
+                    let release = arg.strip_prefix("-Zmiri-kernel-release=").unwrap();
+                    if release.is_empty() || release.len() > 64 {
+                        panic!("-Zmiri-kernel-release must be between 1 and 64 bytes long");
+                    }
+                    miri_config.kernel_release = Some(release.to_owned());
+                }
+                "-Zmiri-stats" => {
+                    miri_config.stats = true;
+                }
+                "-Zmiri-strict-errno" => {
+                    miri_config.strict_errno = true;
+                }
+                arg if arg.starts_with("-Zmiri-pretend-tty=") => {
+                    let streams = arg.strip_prefix("-Zmiri-pretend-tty=").unwrap();
+                    for stream in streams.split(',') {
+                        let fd = match stream {
+                            "stdin" => 0,
+                            "stdout" => 1,
+                            "stderr" => 2,
+                            _ =>
+                                panic!(
+                                    "-Zmiri-pretend-tty must be a comma-separated list of `stdin`, `stdout` and `stderr`"
+                                ),
+                        };
+                        if !miri_config.pretend_tty.contains(&fd) {
+                            miri_config.pretend_tty.push(fd);
+                        }
+                    }
+                }
+                arg if arg.starts_with("-Zmiri-tty-size=") => {
+                    let size = arg.strip_prefix("-Zmiri-tty-size=").unwrap();
+                    miri_config.tty_size = parse_tty_size(size).unwrap_or_else(|| {
+                        panic!("-Zmiri-tty-size must be of the form `<columns>x<rows>`")
+                    });
+                }
+                arg if arg.starts_with("-Zmiri-freeze-time=") => {
+                    let timestamp = arg.strip_prefix("-Zmiri-freeze-time=").unwrap();
+                    miri_config.freeze_time = Some(timestamp.parse().unwrap_or_else(|err| {
+                        panic!("-Zmiri-freeze-time requires a Unix timestamp in seconds: {}", err)
+                    }));
+                }
+                "-Zmiri-compare-ctfe" => {
+                    miri_config.compare_ctfe = true;
+                }
+                "-Zmiri-print-alloc-ids" => {
+                    miri_config.print_alloc_ids = true;
+                }
+                "-Zmiri-track-value-origins" => {
+                    miri_config.track_value_origins = true;
+                }
+                "-Zmiri-track-exposures" => {
+                    miri_config.track_exposures = true;
+                }
+                "-Zmiri-debugger" => {
+                    miri_config.debugger = true;
+                }
                 "-Zmiri-ignore-leaks" => {
                     miri_config.ignore_leaks = true;
                 }
@@ -452,17 +653,44 @@ fn main() {
                     }
                 }
                 arg if arg.starts_with("-Zmiri-track-alloc-id=") => {
-                    let ids: Vec<miri::AllocId> = match parse_comma_list::<NonZeroU64>(
-                        arg.strip_prefix("-Zmiri-track-alloc-id=").unwrap(),
-                    ) {
-                        Ok(ids) => ids.into_iter().map(miri::AllocId).collect(),
-                        Err(err) =>
+                    // Each ID is either an `AllocId` or a stable ID of the form `<thread>:<n>`.
+                    for id in arg.strip_prefix("-Zmiri-track-alloc-id=").unwrap().split(',') {
+                        if id.contains(':') {
+                            match id.parse::<miri::StableAllocId>() {
+                                Ok(id) => miri_config.tracked_stable_alloc_ids.insert(id),
+                                Err(err) =>
+                                    panic!(
+                                        "-Zmiri-track-alloc-id requires stable IDs of the form `<thread>:<n>`: {}",
+                                        err
+                                    ),
+                            };
+                        } else {
+                            match id.parse::<NonZeroU64>() {
+                                Ok(id) => miri_config.tracked_alloc_ids.insert(miri::AllocId(id)),
+                                Err(err) =>
+                                    panic!(
+                                        "-Zmiri-track-alloc-id requires a comma separated list of valid non-zero `u64` arguments: {}",
+                                        err
+                                    ),
+                            };
+                        }
+                    }
+                }
+                arg if arg.starts_with("-Zmiri-watch=") => {
+                    let watch = arg.strip_prefix("-Zmiri-watch=").unwrap();
+                    let parts: Vec<_> = watch.split(':').map(|part| part.parse::<u64>()).collect();
+                    match parts[..] {
+                        [Ok(id), Ok(offset), Ok(len)] if id != 0 => {
+                            let id = miri::AllocId(NonZeroU64::new(id).unwrap());
+                            let range =
+                                miri::alloc_range(Size::from_bytes(offset), Size::from_bytes(len));
+                            miri_config.watchpoints.push((id, range));
+                        }
+                        _ =>
                             panic!(
-                                "-Zmiri-track-alloc-id requires a comma separated list of valid non-zero `u64` arguments: {}",
-                                err
+                                "-Zmiri-watch requires an argument of the form `<alloc-id>:<offset>:<len>`"
                             ),
-                    };
-                    miri_config.tracked_alloc_ids.extend(ids);
+                    }
                 }
                 arg if arg.starts_with("-Zmiri-compare-exchange-weak-failure-rate=") => {
                     let rate = match arg
@@ -487,6 +715,160 @@ fn main() {
                     let measureme_out = arg.strip_prefix("-Zmiri-measureme=").unwrap();
                     miri_config.measureme_out = Some(measureme_out.to_string());
                 }
+                arg if arg.starts_with("-Zmiri-scheduler=") => {
+                    miri_config.scheduling_policy = match arg
+                        .strip_prefix("-Zmiri-scheduler=")
+                        .unwrap()
+                    {
+                        "cooperative" => SchedulingPolicyKind::Cooperative,
+                        "round-robin" => SchedulingPolicyKind::RoundRobin,
+                        "priority" => SchedulingPolicyKind::Priority,
+                        "random" => SchedulingPolicyKind::Random,
+                        policy if policy.starts_with("exhaustive=") => {
+                            let bound = policy.strip_prefix("exhaustive=").unwrap();
+                            match bound.parse::<u64>() {
+                                Ok(bound) => SchedulingPolicyKind::Exhaustive(bound),
+                                Err(err) =>
+                                    panic!(
+                                        "-Zmiri-scheduler=exhaustive requires a `u64` argument: {}",
+                                        err
+                                    ),
+                            }
+                        }
+                        policy if policy.starts_with("bounded=") => {
+                            let bound = policy.strip_prefix("bounded=").unwrap();
+                            match bound.parse::<u64>() {
+                                Ok(bound) => SchedulingPolicyKind::PreemptionBounded(bound),
+                                Err(err) =>
+                                    panic!(
+                                        "-Zmiri-scheduler=bounded requires a `u64` argument: {}",
+                                        err
+                                    ),
+                            }
+                        }
+                        _ =>
+                            panic!(
+                                "-Zmiri-scheduler must be `cooperative`, `round-robin`, `priority`, `random`, `bounded=<n>` or `exhaustive=<n>`"
+                            ),
+                    };
+                }
+                arg if arg.starts_with("-Zmiri-thread-leaks=") => {
+                    miri_config.thread_leak_check = match arg.strip_prefix("-Zmiri-thread-leaks=") {
+                        Some("error") => ThreadLeakCheck::Error,
+                        Some("warn") => ThreadLeakCheck::Warn,
+                        Some("allow") => ThreadLeakCheck::Allow,
+                        Some("require-join") => ThreadLeakCheck::RequireJoin,
+                        _ =>
+                            panic!(
+                                "-Zmiri-thread-leaks must be `error`, `warn`, `allow` or `require-join`"
+                            ),
+                    };
+                }
+                arg if arg.starts_with("-Zmiri-core-dump=") => {
+                    let path = arg.strip_prefix("-Zmiri-core-dump=").unwrap();
+                    miri_config.core_dump = Some(path.to_string());
+                }
+                arg if arg.starts_with("-Zmiri-error-span=") => {
+                    miri_config.error_span = match arg.strip_prefix("-Zmiri-error-span=") {
+                        Some("std") => ErrorSpan::Std,
+                        Some("user") => ErrorSpan::User,
+                        _ => panic!("-Zmiri-error-span must be `std` or `user`"),
+                    };
+                }
+                arg if arg.starts_with("-Zmiri-expect-error=") => {
+                    let pattern = arg.strip_prefix("-Zmiri-expect-error=").unwrap();
+                    miri_config.expected_error = Some(pattern.to_string());
+                }
+                arg if arg.starts_with("-Zmiri-leak-report=") => {
+                    miri_config.leak_report = match arg.strip_prefix("-Zmiri-leak-report=") {
+                        Some("full") => LeakReport::Full,
+                        Some("summary") => LeakReport::Summary,
+                        Some("none") => LeakReport::None,
+                        _ => panic!("-Zmiri-leak-report must be `full`, `summary`, or `none`"),
+                    };
+                }
+                arg if arg.starts_with("-Zmiri-allow=")
+                    || arg.starts_with("-Zmiri-warn=")
+                    || arg.starts_with("-Zmiri-deny=") =>
+                {
+                    let (flag, kinds) = arg.split_once('=').unwrap();
+                    let level = match flag {
+                        "-Zmiri-allow" => WarningLevel::Allow,
+                        "-Zmiri-warn" => WarningLevel::Warn,
+                        _ => WarningLevel::Deny,
+                    };
+                    for name in kinds.split(',') {
+                        let kind = WarningKind::from_name(name).unwrap_or_else(|| {
+                            panic!(
+                                "{} must be a comma-separated list of `int2ptr`, `isolation`, or `unsupported`",
+                                flag
+                            )
+                        });
+                        miri_config.warning_levels.push((kind, level));
+                    }
+                }
+                arg if arg.starts_with("-Zmiri-max-threads=") => {
+                    let max_threads =
+                        match arg.strip_prefix("-Zmiri-max-threads=").unwrap().parse::<u64>() {
+                            Ok(max_threads) => max_threads,
+                            Err(err) =>
+                                panic!("-Zmiri-max-threads requires a `u64` argument: {}", err),
+                        };
+                    miri_config.max_threads = Some(max_threads);
+                }
+                arg if arg.starts_with("-Zmiri-record-schedule=") => {
+                    let path = arg.strip_prefix("-Zmiri-record-schedule=").unwrap();
+                    miri_config.record_schedule = Some(path.to_string());
+                }
+                arg if arg.starts_with("-Zmiri-replay-schedule=") => {
+                    let path = arg.strip_prefix("-Zmiri-replay-schedule=").unwrap();
+                    match miri::read_schedule(path) {
+                        Ok(events) => miri_config.replay_schedule = Some(events),
+                        Err(err) =>
+                            show_error!(
+                                "-Zmiri-replay-schedule: cannot read the schedule `{}`: {}",
+                                path,
+                                err
+                            ),
+                    }
+                }
+                arg if arg.starts_with("-Zmiri-sarif=") => {
+                    let path = arg.strip_prefix("-Zmiri-sarif=").unwrap();
+                    miri_config.sarif_out = Some(path.to_string());
+                }
+                arg if arg.starts_with("-Zmiri-sb-trace=") => {
+                    let trace = arg.strip_prefix("-Zmiri-sb-trace=").unwrap();
+                    match trace.split_once(':') {
+                        Some((id, path)) if !path.is_empty() =>
+                            match id.parse::<NonZeroU64>() {
+                                Ok(id) =>
+                                    miri_config.sb_trace =
+                                        Some((miri::AllocId(id), path.to_string())),
+                                Err(err) =>
+                                    panic!(
+                                        "-Zmiri-sb-trace requires a valid non-zero `u64` allocation id: {}",
+                                        err
+                                    ),
+                            },
+                        _ =>
+                            panic!(
+                                "-Zmiri-sb-trace requires an argument of the form `<alloc-id>:<file>`"
+                            ),
+                    }
+                }
+                arg if arg.starts_with("-Zmiri-spin-loop-limit=") => {
+                    let limit =
+                        match arg.strip_prefix("-Zmiri-spin-loop-limit=").unwrap().parse::<u64>() {
+                            Ok(limit) => limit,
+                            Err(err) =>
+                                panic!("-Zmiri-spin-loop-limit requires a `u64` argument: {}", err),
+                        };
+                    miri_config.spin_loop_limit = if limit == 0 { None } else { Some(limit) };
+                }
+                arg if arg.starts_with("-Zmiri-profile-interpreted=") => {
+                    let profile_out = arg.strip_prefix("-Zmiri-profile-interpreted=").unwrap();
+                    miri_config.profile_interpreted_out = Some(profile_out.to_string());
+                }
                 arg if arg.starts_with("-Zmiri-backtrace=") => {
                     miri_config.backtrace_style = match arg.strip_prefix("-Zmiri-backtrace=") {
                         Some("0") => BacktraceStyle::Off,
@@ -0,0 +1,174 @@
+//! The clocks of the machine.
+//!
+//! All shims that observe or wait for monotonic time (`clock_gettime(CLOCK_MONOTONIC)`,
+//! `mach_absolute_time`, `QueryPerformanceCounter`, timeouts of condition variables and futexes,
+//! `nanosleep`, ...) go through this clock, so they all agree on what time it is. The same holds
+//! for the system time (`clock_gettime(CLOCK_REALTIME)`, `gettimeofday`, ...).
+//!
+//! Miri never actually waits for time to pass. When every thread is blocked and some of them wait
+//! for a timeout, the clock jumps ahead to the earliest deadline instead. With isolation enabled,
+//! the clock does not follow the host clock at all, so that sleeping is deterministic. The system
+//! time then starts at the Unix epoch, and advances along with monotonic time.
+//!
+//! With `-Zmiri-time-anomalies`, the system time occasionally jumps backward or forward, like it
+//! can on a real system when the clock is adjusted, while monotonic time stays monotonic.
+//!
+//! CPU time (`CLOCK_PROCESS_CPUTIME_ID`, `getrusage`, ...) is not measured, but derived from the
+//! number of interpreter steps, so that it is deterministic.
+//!
+//! With `-Zmiri-freeze-time`, time does not pass at all: all clocks always return the same time.
+//! Timeouts expire as soon as no thread can make progress, without waiting.
+
+use std::cell::{Cell, RefCell};
+use std::time::{Duration, Instant, SystemTime};
+
+use rand::rngs::StdRng;
+use rand::{Rng, SeedableRng};
+
+use crate::*;
+
+/// The smallest amount by which the clock advances between two reads.
+const RESOLUTION: Duration = Duration::from_nanos(1);
+
+/// The CPU time that one interpreter step accounts for.
+const CPU_TIME_PER_STEP: Duration = Duration::from_nanos(10);
+
+/// The CPU time that the given number of interpreter steps accounts for.
+pub fn cpu_time(steps: u64) -> Duration {
+    Duration::from_nanos(steps.saturating_mul(CPU_TIME_PER_STEP.as_nanos() as u64))
+}
+
+/// With time anomalies enabled, the probability that a read of the system time jumps.
+const ANOMALY_PROBABILITY: f64 = 1.0 / 16.0;
+
+/// With time anomalies enabled, the largest jump of the system time, in either direction.
+const MAX_ANOMALY: Duration = Duration::from_secs(60 * 60);
+
+#[derive(Debug)]
+pub struct Clock {
+    /// The "time anchor": monotonic time as seen by the program is measured from here.
+    anchor: Instant,
+    /// The last time that was handed out.
+    last: Cell<Instant>,
+    /// Whether time passes along with the host clock. Otherwise, it only passes by skipping.
+    follows_host: bool,
+    /// How far the clock has jumped ahead of the host clock to skip waiting for timeouts.
+    skipped: Cell<Duration>,
+    /// How far the system time seen by the program is ahead of (`true`) or behind (`false`) the
+    /// host's system time.
+    system_offset: Cell<(bool, Duration)>,
+    /// Decides when and how far the system time jumps, if time anomalies are enabled. This is
+    /// separate from the machine's RNG, so that enabling anomalies does not change the other
+    /// random decisions.
+    anomalies: Option<RefCell<StdRng>>,
+    /// The system time that the program always sees, if time is frozen.
+    frozen: Option<SystemTime>,
+}
+
+impl Clock {
+    pub fn new(config: &MiriConfig) -> Self {
+        let anchor = Instant::now();
+        let anomalies = config
+            .time_anomalies
+            .then(|| RefCell::new(StdRng::seed_from_u64(config.seed.unwrap_or(0))));
+        let frozen =
+            config.freeze_time.map(|secs| SystemTime::UNIX_EPOCH + Duration::from_secs(secs));
+        Clock {
+            anchor,
+            last: Cell::new(anchor),
+            follows_host: config.isolated_op == IsolatedOp::Allow,
+            skipped: Cell::new(Duration::ZERO),
+            system_offset: Cell::new((true, Duration::ZERO)),
+            anomalies,
+            frozen,
+        }
+    }
+
+    /// Whether time is frozen. Then timeouts do not expire by waiting for them, but only when the
+    /// scheduler finds that every thread is blocked.
+    pub fn is_frozen(&self) -> bool {
+        self.frozen.is_some()
+    }
+
+    /// The current time. This follows the host clock (unless isolation is enabled) plus the time
+    /// that was skipped, but every call returns a time that is strictly later than the one
+    /// returned by the previous call, even if the host clock did not advance in the meantime. If
+    /// time is frozen, this is always the anchor.
+    pub fn now(&self) -> Instant {
+        if self.is_frozen() {
+            return self.anchor;
+        }
+        let host = if self.follows_host { Instant::now() } else { self.anchor };
+        let now = (host + self.skipped.get()).max(self.last.get() + RESOLUTION);
+        self.last.set(now);
+        now
+    }
+
+    /// The time elapsed since the anchor. This is what the program sees as the current
+    /// monotonic time.
+    pub fn elapsed(&self) -> Duration {
+        self.now().duration_since(self.anchor)
+    }
+
+    /// The point in time that the program sees as monotonic time `duration`.
+    pub fn instant_at(&self, duration: Duration) -> Instant {
+        self.anchor.checked_add(duration).unwrap()
+    }
+
+    /// The point in time `duration` from now.
+    pub fn instant_in(&self, duration: Duration) -> Instant {
+        self.now().checked_add(duration).unwrap()
+    }
+
+    /// The system time as seen by the program, for shims that report it. If time anomalies are
+    /// enabled, this may first make the system time jump.
+    pub fn read_system_time(&self) -> SystemTime {
+        if let Some(frozen) = self.frozen {
+            return frozen;
+        }
+        if let Some(rng) = &self.anomalies {
+            let mut rng = rng.borrow_mut();
+            if rng.gen_bool(ANOMALY_PROBABILITY) {
+                let jump = Duration::from_nanos(rng.gen_range(1..=MAX_ANOMALY.as_nanos() as u64));
+                let forward = rng.gen_bool(0.5);
+                let (ahead, offset) = self.system_offset.get();
+                // Add the signed `jump` to the signed `offset`.
+                let new_offset = if ahead == forward {
+                    (ahead, offset + jump)
+                } else if offset >= jump {
+                    (ahead, offset - jump)
+                } else {
+                    (forward, jump - offset)
+                };
+                self.system_offset.set(new_offset);
+            }
+        }
+        self.system_time()
+    }
+
+    /// The system time as seen by the program, without making it jump. This is used to check
+    /// timeouts that are specified in system time.
+    pub fn system_time(&self) -> SystemTime {
+        if let Some(frozen) = self.frozen {
+            return frozen;
+        }
+        let now = if self.follows_host {
+            SystemTime::now() + self.skipped.get()
+        } else {
+            // Like `now`, but without advancing the clock.
+            let now = (self.anchor + self.skipped.get()).max(self.last.get());
+            SystemTime::UNIX_EPOCH + now.duration_since(self.anchor)
+        };
+        match self.system_offset.get() {
+            (true, offset) => now.checked_add(offset).unwrap(),
+            (false, offset) => now.checked_sub(offset).unwrap(),
+        }
+    }
+
+    /// Skips `duration` instead of waiting for it to pass. This has no effect if time is frozen.
+    pub fn skip(&self, duration: Duration) {
+        if !self.is_frozen() {
+            self.skipped.set(self.skipped.get() + duration);
+        }
+    }
+}
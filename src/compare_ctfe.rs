@@ -0,0 +1,423 @@
+//! Compares the values that the compiler computes for constants and `const fn` calls with the
+//! values Miri computes for them at run time.
+//!
+//! The initializers of constants are where the compiler calls `const fn`s. Running them again
+//! with Miri's runtime machine calls the same `const fn`s with the same inputs, but with the
+//! runtime side of `const_eval_select`, the runtime implementations of the intrinsics, and
+//! Miri's checks for undefined behavior. A difference between the two values hence means that
+//! some `const fn` involved behaves differently at compile time and at run time.
+//!
+//! Most inputs of `const fn`s are only known at run time, so the calls of local `const fn`s that
+//! the program makes are recorded as well. The const-eval engine cannot run a `const fn` with
+//! such arguments, so for these calls the compile-time side is emulated by Miri: they are run
+//! once more with `const_eval_select` picking its compile-time side. Differences between the
+//! intrinsics of the two engines are hence only found for constants. Calls with arguments that
+//! contain pointers are not recorded, since what they point to may have changed by the time the
+//! calls are compared.
+
+use rustc_data_structures::fx::FxHashSet;
+use rustc_hir::def::DefKind;
+use rustc_hir::def_id::DefId;
+use rustc_middle::{
+    mir,
+    ty::{self, layout::LayoutOf},
+};
+use rustc_span::Span;
+use rustc_target::abi::{FieldsShape, VariantIdx, Variants};
+
+use crate::*;
+use helpers::check_arg_count;
+
+/// How many different inputs are recorded for every `const fn`.
+const MAX_RECORDED_INPUTS: usize = 8;
+
+/// A call of a local `const fn` that the program made.
+struct RecordedCall<'tcx> {
+    instance: ty::Instance<'tcx>,
+    /// Copies of the arguments, in allocations of their own.
+    args: Vec<MPlaceTy<'tcx, Tag>>,
+    /// Where the function was first called with these arguments.
+    span: Span,
+}
+
+/// The state of `-Zmiri-compare-ctfe` while the program runs.
+#[derive(Default)]
+pub struct CtfeComparison<'tcx> {
+    calls: Vec<RecordedCall<'tcx>>,
+    /// The instances and argument bytes of `calls`, so that every input is recorded once.
+    inputs: FxHashSet<(ty::Instance<'tcx>, Vec<u8>)>,
+    /// Set once the program ran and the comparison started, which stops recording calls.
+    comparing: bool,
+    /// Whether `const_eval_select` picks its compile-time side, like during const-eval.
+    compile_time: bool,
+}
+
+impl<'mir, 'tcx: 'mir> EvalContextExt<'mir, 'tcx> for crate::MiriEvalContext<'mir, 'tcx> {}
+pub trait EvalContextExt<'mir, 'tcx: 'mir>: crate::MiriEvalContextExt<'mir, 'tcx> {
+    /// Records the call of `instance` with `args` if it is a local `const fn`, to compare it after
+    /// the program ran.
+    fn record_const_fn_call(
+        &mut self,
+        instance: ty::Instance<'tcx>,
+        args: &[OpTy<'tcx, Tag>],
+    ) -> InterpResult<'tcx> {
+        let this = self.eval_context_mut();
+        let def_id = instance.def_id();
+        let comparison = this.machine.ctfe_comparison.as_ref().unwrap();
+        if comparison.comparing || !def_id.is_local() || !this.tcx.is_const_fn_raw(def_id) {
+            return Ok(());
+        }
+        let recorded = comparison.calls.iter().filter(|call| call.instance == instance).count();
+        if recorded == MAX_RECORDED_INPUTS {
+            return Ok(());
+        }
+
+        let mut copies = vec![];
+        let mut bytes = vec![];
+        let mut has_pointers = false;
+        for arg in args {
+            let copy = this.allocate(arg.layout, MiriMemoryKind::Machine.into())?;
+            this.copy_op(arg, &copy.into())?;
+            let (alloc_id, _, _) = this.ptr_get_alloc_id(copy.ptr)?;
+            let alloc = this.get_alloc_raw(alloc_id)?;
+            has_pointers |= !alloc.relocations().is_empty();
+            bytes.extend_from_slice(
+                alloc.inspect_with_uninit_and_ptr_outside_interpreter(0..alloc.len()),
+            );
+            copies.push(copy);
+        }
+        let comparison = this.machine.ctfe_comparison.as_mut().unwrap();
+        if has_pointers || !comparison.inputs.insert((instance, bytes)) {
+            for copy in copies {
+                this.deallocate_ptr(copy.ptr, None, MiriMemoryKind::Machine.into())?;
+            }
+            return Ok(());
+        }
+        let span = this.cur_span();
+        this.machine.ctfe_comparison.as_mut().unwrap().calls.push(RecordedCall {
+            instance,
+            args: copies,
+            span,
+        });
+        Ok(())
+    }
+
+    /// Calls the compile-time side of `const_eval_select` while the compile-time side of a
+    /// recorded call is run. Returns `false` if `instance` is to be called as usual.
+    fn call_const_eval_select(
+        &mut self,
+        instance: ty::Instance<'tcx>,
+        args: &[OpTy<'tcx, Tag>],
+        ret: Option<(&PlaceTy<'tcx, Tag>, mir::BasicBlock)>,
+        unwind: StackPopUnwind,
+    ) -> InterpResult<'tcx, bool> {
+        let this = self.eval_context_mut();
+        if !this.machine.ctfe_comparison.as_ref().unwrap().compile_time
+            || this.tcx.lang_items().const_eval_select() != Some(instance.def_id())
+        {
+            return Ok(false);
+        }
+        let [arg, called_in_const, _called_at_rt] = check_arg_count(args)?;
+        let called_in_const = match *called_in_const.layout.ty.kind() {
+            ty::FnDef(def_id, substs) =>
+                ty::Instance::resolve(*this.tcx, ty::ParamEnv::reveal_all(), def_id, substs)
+                    .ok()
+                    .flatten(),
+            _ => None,
+        };
+        let called_in_const = match called_in_const {
+            Some(instance) => instance,
+            None =>
+                throw_unsup_format!(
+                    "`const_eval_select` with a compile-time side that is not a function item"
+                ),
+        };
+        // The arguments are passed as a tuple, which the function takes apart.
+        let args = (0..arg.layout.fields.count())
+            .map(|i| this.operand_field(arg, i))
+            .collect::<InterpResult<'_, Vec<_>>>()?;
+        let stack_pop = StackPopCleanup::Goto { ret: ret.map(|(_, ret)| ret), unwind };
+        push_call(this, called_in_const, &args, ret.map(|(dest, _)| dest), stack_pop)?;
+        Ok(true)
+    }
+}
+
+/// Evaluates the initializer of every monomorphic constant of the local crate and every recorded
+/// call of a local `const fn` again with the machine of the program, after the program ran, and
+/// warns about the ones whose value differs between compile time and run time.
+///
+/// The allocations made for the comparison are not freed, so this has to happen after the leak
+/// check looked at the final state of the program.
+pub fn compare_ctfe<'mir, 'tcx>(ecx: &mut MiriEvalContext<'mir, 'tcx>) {
+    let tcx = *ecx.tcx;
+    ecx.machine.ctfe_comparison.as_mut().unwrap().comparing = true;
+    let mut compared = 0;
+    let mut differing = 0;
+    for &def_id in tcx.mir_keys(()) {
+        let def_id = def_id.to_def_id();
+        if !matches!(tcx.def_kind(def_id), DefKind::Const | DefKind::AssocConst)
+            || tcx.generics_of(def_id).requires_monomorphization(tcx)
+        {
+            continue;
+        }
+        // rustc reports the constants that fail to evaluate, if they are used.
+        let ctfe_val = match tcx.const_eval_poly(def_id) {
+            Ok(val) => val,
+            Err(_) => continue,
+        };
+        compared += 1;
+        if let Some(difference) = compare_const(ecx, def_id, ctfe_val) {
+            differing += 1;
+            tcx.sess
+                .struct_span_warn(
+                    tcx.def_ident_span(def_id).unwrap_or_else(|| tcx.def_span(def_id)),
+                    &format!(
+                        "constant `{}` has a different value at run time",
+                        tcx.def_path_str(def_id)
+                    ),
+                )
+                .note(&difference)
+                .emit();
+        }
+    }
+
+    let calls = std::mem::take(&mut ecx.machine.ctfe_comparison.as_mut().unwrap().calls);
+    for call in &calls {
+        compared += 1;
+        if let Some(difference) = compare_call(ecx, call) {
+            differing += 1;
+            tcx.sess
+                .struct_span_warn(
+                    call.span,
+                    &format!(
+                        "`{}` returns a different value at compile time for the arguments of \
+                        this call",
+                        tcx.def_path_str(call.instance.def_id())
+                    ),
+                )
+                .note(&difference)
+                .emit();
+        }
+    }
+    tcx.sess.note_without_error(&format!(
+        "evaluated {} constants and `const fn` calls again, {} of them differ between compile \
+        time and run time",
+        compared, differing
+    ));
+}
+
+/// Pushes a frame that calls `instance` with `args`, like `call_function` does for immediate
+/// arguments.
+fn push_call<'mir, 'tcx>(
+    ecx: &mut MiriEvalContext<'mir, 'tcx>,
+    instance: ty::Instance<'tcx>,
+    args: &[OpTy<'tcx, Tag>],
+    dest: Option<&PlaceTy<'tcx, Tag>>,
+    stack_pop: StackPopCleanup,
+) -> InterpResult<'tcx> {
+    let body = &*ecx.load_mir(instance.def, None)?;
+    ecx.push_stack_frame(instance, body, dest, stack_pop)?;
+    let mut callee_args = ecx.frame().body.args_iter();
+    for arg in args {
+        let callee_arg = ecx.local_place(
+            callee_args
+                .next()
+                .ok_or_else(|| err_ub_format!("callee has fewer arguments than expected"))?,
+        )?;
+        ecx.copy_op(arg, &callee_arg)?;
+    }
+    if callee_args.next().is_some() {
+        throw_ub_format!("callee has more arguments than expected");
+    }
+    Ok(())
+}
+
+/// Runs `instance` with `args` to completion on top of the stack of the active thread, with its
+/// result written to `dest`. If that fails, the frames it left behind are dropped.
+fn run_to_completion<'mir, 'tcx>(
+    ecx: &mut MiriEvalContext<'mir, 'tcx>,
+    instance: ty::Instance<'tcx>,
+    args: &[OpTy<'tcx, Tag>],
+    dest: &MPlaceTy<'tcx, Tag>,
+) -> InterpResult<'tcx> {
+    let height = ecx.active_thread_stack().len();
+    let stack_pop = StackPopCleanup::Root { cleanup: true };
+    let res = push_call(ecx, instance, args, Some(&(*dest).into()), stack_pop).and_then(|()| {
+        while ecx.active_thread_stack().len() > height {
+            ecx.step()?;
+        }
+        Ok(())
+    });
+    ecx.active_thread_stack_mut().truncate(height);
+    res
+}
+
+/// Runs the initializer of the constant `def_id`, and compares the result with `ctfe_val`.
+/// Returns a description of the difference, if any.
+fn compare_const<'mir, 'tcx>(
+    ecx: &mut MiriEvalContext<'mir, 'tcx>,
+    def_id: DefId,
+    ctfe_val: ConstValue<'tcx>,
+) -> Option<String> {
+    let tcx = *ecx.tcx;
+    let res: InterpResult<'tcx, Option<String>> = (|| {
+        let ty = tcx.type_of(def_id);
+        let dest = ecx.allocate(ecx.layout_of(ty)?, MiriMemoryKind::Machine.into())?;
+        // The MIR of a constant is its initializer, which we run like a function without
+        // arguments.
+        run_to_completion(ecx, ty::Instance::mono(tcx, def_id), &[], &dest)?;
+        let ctfe = ecx.mir_const_to_op(&mir::ConstantKind::Val(ctfe_val, ty), None)?;
+        compare_values(ecx, &dest.into(), &ctfe, tcx.def_path_str(def_id))
+    })();
+    match res {
+        Ok(difference) => difference,
+        Err(err) => Some(format!("evaluating the constant at run time failed: {}", err.kind())),
+    }
+}
+
+/// Runs the recorded `call` twice, once like at run time and once with the compile-time side of
+/// `const_eval_select`, and compares the results. Returns a description of the difference, if
+/// any.
+fn compare_call<'mir, 'tcx>(
+    ecx: &mut MiriEvalContext<'mir, 'tcx>,
+    call: &RecordedCall<'tcx>,
+) -> Option<String> {
+    let tcx = *ecx.tcx;
+    let args: Vec<OpTy<'tcx, Tag>> = call.args.iter().map(|&arg| arg.into()).collect();
+    let mut run = |compile_time: bool| -> InterpResult<'tcx, MPlaceTy<'tcx, Tag>> {
+        let ty = call.instance.ty(tcx, ty::ParamEnv::reveal_all());
+        let ret_ty = tcx.erase_late_bound_regions(ty.fn_sig(tcx).output());
+        let dest = ecx.allocate(ecx.layout_of(ret_ty)?, MiriMemoryKind::Machine.into())?;
+        ecx.machine.ctfe_comparison.as_mut().unwrap().compile_time = compile_time;
+        let res = run_to_completion(ecx, call.instance, &args, &dest);
+        ecx.machine.ctfe_comparison.as_mut().unwrap().compile_time = false;
+        res.map(|()| dest)
+    };
+    let runtime = run(false);
+    let ctfe = run(true);
+
+    let path = format!("{}(..)", tcx.def_path_str(call.instance.def_id()));
+    let res = match (runtime, ctfe) {
+        (Ok(runtime), Ok(ctfe)) => compare_values(ecx, &runtime.into(), &ctfe.into(), path),
+        // Calls that fail either way, e.g. because they panic, behave the same.
+        (Err(_), Err(_)) => Ok(None),
+        (Ok(_), Err(err)) =>
+            Ok(Some(format!("evaluating the call at compile time failed: {}", err.kind()))),
+        (Err(err), Ok(_)) =>
+            Ok(Some(format!("evaluating the call at run time failed: {}", err.kind()))),
+    };
+    match res {
+        Ok(difference) => difference,
+        Err(err) => Some(format!("comparing the results failed: {}", err.kind())),
+    }
+}
+
+/// Compares a value computed at run time with the value of the same type computed at compile
+/// time, recursing into fields and behind references. `path` describes where in the constant the
+/// values are, for the report.
+fn compare_values<'tcx>(
+    ecx: &mut MiriEvalContext<'_, 'tcx>,
+    runtime: &OpTy<'tcx, Tag>,
+    ctfe: &OpTy<'tcx, Tag>,
+    path: String,
+) -> InterpResult<'tcx, Option<String>> {
+    let difference = |path: &str, ctfe: &dyn std::fmt::Display, runtime: &dyn std::fmt::Display| {
+        format!("at `{}`, the value is {} at compile time but {} at run time", path, ctfe, runtime)
+    };
+    let layout = runtime.layout;
+    match layout.ty.kind() {
+        ty::Bool | ty::Char | ty::Int(_) | ty::Uint(_) | ty::Float(_) => {
+            let runtime = ecx.read_scalar(runtime)?.check_init()?;
+            let ctfe = ecx.read_scalar(ctfe)?.check_init()?;
+            if runtime.to_bits(layout.size)? != ctfe.to_bits(layout.size)? {
+                return Ok(Some(difference(&path, &ctfe, &runtime)));
+            }
+            return Ok(None);
+        }
+        ty::Ref(_, pointee, _) => {
+            // The two references point to different allocations, so we compare what they point to.
+            let runtime = ecx.deref_operand(runtime)?;
+            let ctfe = ecx.deref_operand(ctfe)?;
+            let path = format!("{}.<deref>", path);
+            return match pointee.kind() {
+                ty::Str => {
+                    let (runtime, ctfe) = (ecx.read_str(&runtime)?, ecx.read_str(&ctfe)?);
+                    if runtime != ctfe {
+                        let (runtime, ctfe) = (format!("{:?}", runtime), format!("{:?}", ctfe));
+                        return Ok(Some(difference(&path, &ctfe, &runtime)));
+                    }
+                    Ok(None)
+                }
+                ty::Slice(_) => {
+                    let (runtime_len, ctfe_len) = (runtime.len(ecx)?, ctfe.len(ecx)?);
+                    if runtime_len != ctfe_len {
+                        let path = format!("{}.len()", path);
+                        return Ok(Some(difference(&path, &ctfe_len, &runtime_len)));
+                    }
+                    for i in 0..runtime_len {
+                        let runtime = ecx.mplace_index(&runtime, i)?;
+                        let ctfe = ecx.mplace_index(&ctfe, i)?;
+                        let path = format!("{}[{}]", path, i);
+                        if let Some(difference) =
+                            compare_values(ecx, &runtime.into(), &ctfe.into(), path)?
+                        {
+                            return Ok(Some(difference));
+                        }
+                    }
+                    Ok(None)
+                }
+                // Trait objects would need to be compared through their vtables.
+                ty::Dynamic(..) => Ok(None),
+                _ => compare_values(ecx, &runtime.into(), &ctfe.into(), path),
+            };
+        }
+        // Raw pointers and function pointers point to different allocations, and there is no
+        // type we could use to compare what they point to.
+        ty::RawPtr(_) | ty::FnPtr(_) => return Ok(None),
+        _ => {}
+    }
+    // Unions do not say which of their fields is valid.
+    if matches!(layout.fields, FieldsShape::Union(..)) {
+        return Ok(None);
+    }
+
+    // For enums, the variants have to match before we can compare their fields.
+    let (runtime, ctfe, path) = match layout.variants {
+        Variants::Multiple { .. } => {
+            let (_, runtime_variant) = ecx.read_discriminant(runtime)?;
+            let (_, ctfe_variant) = ecx.read_discriminant(ctfe)?;
+            let variant_name = |variant: VariantIdx| {
+                match layout.ty.kind() {
+                    ty::Adt(adt, _) => adt.variant(variant).name.to_string(),
+                    _ => format!("{:?}", variant),
+                }
+            };
+            if runtime_variant != ctfe_variant {
+                return Ok(Some(difference(
+                    &path,
+                    &variant_name(ctfe_variant),
+                    &variant_name(runtime_variant),
+                )));
+            }
+            let path = format!("{}.<enum-variant({})>", path, variant_name(runtime_variant));
+            (
+                ecx.operand_downcast(runtime, runtime_variant)?,
+                ecx.operand_downcast(ctfe, ctfe_variant)?,
+                path,
+            )
+        }
+        Variants::Single { .. } => (runtime.clone(), ctfe.clone(), path),
+    };
+    for i in 0..runtime.layout.fields.count() {
+        let path = match runtime.layout.fields {
+            FieldsShape::Array { .. } => format!("{}[{}]", path, i),
+            _ => format!("{}.{}", path, i),
+        };
+        let runtime_field = ecx.operand_field(&runtime, i)?;
+        let ctfe_field = ecx.operand_field(&ctfe, i)?;
+        if let Some(difference) = compare_values(ecx, &runtime_field, &ctfe_field, path)? {
+            return Ok(Some(difference));
+        }
+    }
+    Ok(None)
+}
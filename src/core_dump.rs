@@ -0,0 +1,109 @@
+//! Writes a report of the state of the program when it stopped with an error, similar to a core
+//! file, enabled with `-Zmiri-core-dump=<file>`. The report contains the stacks of all threads
+//! with the values of their local variables, the contents and borrow stacks of the allocations
+//! that the error is about, and the most recent calls to foreign functions.
+
+use std::collections::VecDeque;
+use std::fmt::Write;
+
+use rustc_middle::mir::interpret::AllocId;
+use rustc_span::Symbol;
+use rustc_target::abi::Size;
+
+use crate::debugger::{describe_frame, frame_locals};
+use crate::*;
+
+/// How many calls to foreign functions are remembered.
+const RECENT_SHIM_CALLS: usize = 16;
+
+/// The most recent calls to foreign functions, oldest first.
+#[derive(Debug, Default)]
+pub struct RecentShimCalls {
+    calls: VecDeque<(ThreadId, Symbol)>,
+}
+
+impl RecentShimCalls {
+    pub fn push(&mut self, thread: ThreadId, link_name: Symbol) {
+        if self.calls.len() == RECENT_SHIM_CALLS {
+            self.calls.pop_front();
+        }
+        self.calls.push_back((thread, link_name));
+    }
+}
+
+fn describe_allocation(ecx: &MiriEvalContext<'_, '_>, id: AllocId, out: &mut String) {
+    let alloc = match ecx.get_alloc_raw(id) {
+        Ok(alloc) => alloc,
+        Err(_) => {
+            writeln!(out, "{:?}: not live (it was freed, or is a function)", id).unwrap();
+            return;
+        }
+    };
+    let kind = ecx
+        .machine
+        .live_allocs
+        .as_ref()
+        .and_then(|allocs| allocs.borrow().get(&id).map(|info| info.kind.to_string()));
+    writeln!(
+        out,
+        "{:?}: {} bytes, aligned to {} bytes{}",
+        id,
+        alloc.len(),
+        alloc.align.bytes(),
+        kind.map_or(String::new(), |kind| format!(", {}", kind))
+    )
+    .unwrap();
+    let bytes = alloc.inspect_with_uninit_and_ptr_outside_interpreter(0..alloc.len());
+    for (i, line) in bytes.chunks(16).enumerate() {
+        writeln!(out, "  {:#06x}: {:02x?}", 16 * i, line).unwrap();
+    }
+    for (offset, tag) in alloc.relocations().iter() {
+        writeln!(out, "  pointer at {:#x} to {:?}", offset.bytes(), tag.alloc_id).unwrap();
+    }
+    if let Some(stacks) = &alloc.extra.stacked_borrows {
+        let range = alloc_range(Size::ZERO, Size::from_bytes(alloc.len()));
+        for line in stacks.describe_stacks(id, range).lines() {
+            writeln!(out, "  {}", line).unwrap();
+        }
+    }
+}
+
+/// Writes the state of `ecx`, which stopped with the error `msg` about the allocations `allocs`,
+/// to the file `path`.
+pub fn write_core_dump(
+    ecx: &MiriEvalContext<'_, '_>,
+    msg: &str,
+    allocs: &[AllocId],
+    path: &str,
+) -> std::io::Result<()> {
+    let mut out = String::new();
+    writeln!(out, "error: {}", msg).unwrap();
+
+    let active = ecx.get_active_thread();
+    for (id, name, stack) in ecx.machine.threads.live_threads() {
+        let state = if id == active { " (active)" } else { "" };
+        writeln!(out, "\nthread {} `{}`{}:", id.to_u32(), name, state).unwrap();
+        for (i, frame) in stack.iter().rev().enumerate() {
+            writeln!(out, "  {}", describe_frame(ecx, i, frame)).unwrap();
+            for (name, value) in frame_locals(ecx, frame) {
+                writeln!(out, "      {} = {}", name, value).unwrap();
+            }
+        }
+    }
+
+    if !allocs.is_empty() {
+        writeln!(out, "\nallocations the error is about:").unwrap();
+        for &id in allocs {
+            describe_allocation(ecx, id, &mut out);
+        }
+    }
+
+    if let Some(calls) = &ecx.machine.recent_shim_calls {
+        writeln!(out, "\nmost recent calls to foreign functions, oldest first:").unwrap();
+        for (thread, link_name) in &calls.calls {
+            writeln!(out, "  thread {}: `{}`", thread.to_u32(), link_name).unwrap();
+        }
+    }
+
+    std::fs::write(path, out)
+}
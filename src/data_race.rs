@@ -56,6 +56,13 @@
 //! code some atomic operations may increment the timestamp when not necessary but this has no effect
 //! on the data-race detection code.
 //!
+//! The per-location clocks that are only updated one vector index at a time (the reads since the
+//! last write, and the atomic reads and writes) are stored as sparse vector clocks, and the write
+//! is stored as a single epoch. The memory cells of an allocation are kept in a `RangeMap`, which
+//! merges adjacent cells with identical clocks. Together this means that a large buffer that is
+//! only accessed by a few threads costs time and memory proportional to the number of threads that
+//! accessed it, rather than the total number of threads the program ever created.
+//!
 //! FIXME:
 //! currently we have our own local copy of the currently active thread index and names, this is due
 //! in part to the inability to access the current location of threads.active_thread inside the AllocExtra
@@ -75,7 +82,7 @@ use rustc_target::abi::Size;
 use crate::{
     AllocId, AllocRange, ImmTy, Immediate, InterpResult, MPlaceTy, MemPlaceMeta, MemoryKind,
     MiriEvalContext, MiriEvalContextExt, MiriMemoryKind, OpTy, Pointer, RangeMap, Scalar,
-    ScalarMaybeUninit, Tag, ThreadId, VClock, VTimestamp, VectorIdx,
+    ScalarMaybeUninit, SparseVClock, Tag, ThreadId, VClock, VTimestamp, VectorIdx,
 };
 
 pub type AllocExtra = VClockAlloc;
@@ -178,13 +185,13 @@ struct AtomicMemoryCellClocks {
     /// read operation performed by each thread.
     /// This detects potential data-races between atomic read
     /// and non-atomic write operations.
-    read_vector: VClock,
+    read_vector: SparseVClock,
 
     /// The clock-vector of the timestamp of the last atomic
     /// write operation performed by each thread.
     /// This detects potential data-races between atomic write
     /// and non-atomic read or write operations.
-    write_vector: VClock,
+    write_vector: SparseVClock,
 
     /// Synchronization vector for acquire-release semantics
     /// contains the vector of timestamps that will
@@ -241,7 +248,9 @@ struct MemoryCellClocks {
     /// The vector-clock of the timestamp of the last read operation
     /// performed by a thread since the last write operation occurred.
     /// It is reset to zero on each write operation.
-    read: VClock,
+    /// This is stored sparsely since most locations are only read by
+    /// a small number of threads between writes.
+    read: SparseVClock,
 
     /// Atomic acquire & release sequence tracking clocks.
     /// For non-atomic memory in the common case this
@@ -254,7 +263,7 @@ impl MemoryCellClocks {
     ///  at a given vector timestamp and index.
     fn new(alloc: VTimestamp, alloc_index: VectorIdx) -> Self {
         MemoryCellClocks {
-            read: VClock::default(),
+            read: SparseVClock::default(),
             write: alloc,
             write_index: alloc_index,
             write_type: WriteType::Allocate,
@@ -374,7 +383,7 @@ impl MemoryCellClocks {
         index: VectorIdx,
     ) -> Result<(), DataRace> {
         log::trace!("Atomic write with vectors: {:#?} :: {:#?}", self, clocks);
-        if self.write <= clocks.clock[self.write_index] && self.read <= clocks.clock {
+        if self.write <= clocks.clock[self.write_index] && self.read.le_clock(&clocks.clock) {
             let atomic = self.atomic_mut();
             atomic.write_vector.set_at_index(&clocks.clock, index);
             Ok(())
@@ -393,7 +402,7 @@ impl MemoryCellClocks {
         log::trace!("Unsynchronized read with vectors: {:#?} :: {:#?}", self, clocks);
         if self.write <= clocks.clock[self.write_index] {
             let race_free = if let Some(atomic) = self.atomic() {
-                atomic.write_vector <= clocks.clock
+                atomic.write_vector.le_clock(&clocks.clock)
             } else {
                 true
             };
@@ -417,9 +426,10 @@ impl MemoryCellClocks {
         write_type: WriteType,
     ) -> Result<(), DataRace> {
         log::trace!("Unsynchronized write with vectors: {:#?} :: {:#?}", self, clocks);
-        if self.write <= clocks.clock[self.write_index] && self.read <= clocks.clock {
+        if self.write <= clocks.clock[self.write_index] && self.read.le_clock(&clocks.clock) {
             let race_free = if let Some(atomic) = self.atomic() {
-                atomic.write_vector <= clocks.clock && atomic.read_vector <= clocks.clock
+                atomic.write_vector.le_clock(&clocks.clock)
+                    && atomic.read_vector.le_clock(&clocks.clock)
             } else {
                 true
             };
@@ -486,6 +496,7 @@ pub trait EvalContextExt<'mir, 'tcx: 'mir>: MiriEvalContextExt<'mir, 'tcx> {
     ) -> InterpResult<'tcx, ScalarMaybeUninit<Tag>> {
         let this = self.eval_context_ref();
         let scalar = this.allow_data_races_ref(move |this| this.read_scalar(&place.into()))?;
+        this.note_sync_point();
         this.validate_atomic_load(place, atomic)?;
         Ok(scalar)
     }
@@ -499,6 +510,7 @@ pub trait EvalContextExt<'mir, 'tcx: 'mir>: MiriEvalContextExt<'mir, 'tcx> {
     ) -> InterpResult<'tcx> {
         let this = self.eval_context_mut();
         this.allow_data_races_mut(move |this| this.write_scalar(val, &(*dest).into()))?;
+        this.note_sync_point();
         this.validate_atomic_store(dest, atomic)
     }
 
@@ -519,6 +531,7 @@ pub trait EvalContextExt<'mir, 'tcx: 'mir>: MiriEvalContextExt<'mir, 'tcx> {
         let val = this.binary_op(op, &old, rhs)?;
         let val = if neg { this.unary_op(mir::UnOp::Not, &val)? } else { val };
         this.allow_data_races_mut(|this| this.write_immediate(*val, &(*place).into()))?;
+        this.note_sync_point();
 
         this.validate_atomic_rmw(place, atomic)?;
         Ok(old)
@@ -536,6 +549,7 @@ pub trait EvalContextExt<'mir, 'tcx: 'mir>: MiriEvalContextExt<'mir, 'tcx> {
 
         let old = this.allow_data_races_mut(|this| this.read_scalar(&place.into()))?;
         this.allow_data_races_mut(|this| this.write_scalar(new, &(*place).into()))?;
+        this.note_sync_point();
         this.validate_atomic_rmw(place, atomic)?;
         Ok(old)
     }
@@ -561,6 +575,7 @@ pub trait EvalContextExt<'mir, 'tcx: 'mir>: MiriEvalContextExt<'mir, 'tcx> {
         };
 
         this.allow_data_races_mut(|this| this.write_immediate(**new_val, &(*place).into()))?;
+        this.note_sync_point();
 
         this.validate_atomic_rmw(place, atomic)?;
 
@@ -608,8 +623,10 @@ pub trait EvalContextExt<'mir, 'tcx: 'mir>: MiriEvalContextExt<'mir, 'tcx> {
         // otherwise treat this as an atomic load with the fail ordering.
         if cmpxchg_success {
             this.allow_data_races_mut(|this| this.write_scalar(new, &(*place).into()))?;
+            this.note_sync_point();
             this.validate_atomic_rmw(place, success)?;
         } else {
+            this.note_sync_point();
             this.validate_atomic_load(place, fail)?;
         }
 
@@ -689,6 +706,7 @@ pub trait EvalContextExt<'mir, 'tcx: 'mir>: MiriEvalContextExt<'mir, 'tcx> {
     /// Update the data-race detector for an atomic fence on the current thread.
     fn validate_atomic_fence(&mut self, atomic: AtomicFenceOp) -> InterpResult<'tcx> {
         let this = self.eval_context_mut();
+        this.note_sync_point();
         if let Some(data_race) = &mut this.machine.data_race {
             data_race.maybe_perform_sync_operation(move |index, mut clocks| {
                 log::trace!("Atomic fence on {:?} with ordering {:?}", index, atomic);
@@ -712,6 +730,18 @@ pub trait EvalContextExt<'mir, 'tcx: 'mir>: MiriEvalContextExt<'mir, 'tcx> {
             Ok(())
         }
     }
+
+    /// Update the data-race detector for a `membarrier` call, which acts as if every thread
+    /// that is still running executed a sequentially consistent fence, and synchronizes the
+    /// calling thread with all of them.
+    fn validate_membarrier(&mut self) -> InterpResult<'tcx> {
+        let this = self.eval_context_mut();
+        this.note_sync_point();
+        if let Some(data_race) = &mut this.machine.data_race {
+            data_race.membarrier();
+        }
+        Ok(())
+    }
 }
 
 /// Vector clock metadata for a logical memory allocation.
@@ -731,7 +761,10 @@ impl VClockAlloc {
         let (alloc_timestamp, alloc_index) = match kind {
             // User allocated and stack memory should track allocation.
             MemoryKind::Machine(
-                MiriMemoryKind::Rust | MiriMemoryKind::C | MiriMemoryKind::WinHeap,
+                MiriMemoryKind::Rust
+                | MiriMemoryKind::C
+                | MiriMemoryKind::WinHeap
+                | MiriMemoryKind::Mmap,
             )
             | MemoryKind::Stack => {
                 let (alloc_index, clocks) = global.current_thread_state();
@@ -756,37 +789,6 @@ impl VClockAlloc {
         }
     }
 
-    // Find an index, if one exists where the value
-    // in `l` is greater than the value in `r`.
-    fn find_gt_index(l: &VClock, r: &VClock) -> Option<VectorIdx> {
-        log::trace!("Find index where not {:?} <= {:?}", l, r);
-        let l_slice = l.as_slice();
-        let r_slice = r.as_slice();
-        l_slice
-            .iter()
-            .zip(r_slice.iter())
-            .enumerate()
-            .find_map(|(idx, (&l, &r))| if l > r { Some(idx) } else { None })
-            .or_else(|| {
-                if l_slice.len() > r_slice.len() {
-                    // By invariant, if l_slice is longer
-                    // then one element must be larger.
-                    // This just validates that this is true
-                    // and reports earlier elements first.
-                    let l_remainder_slice = &l_slice[r_slice.len()..];
-                    let idx = l_remainder_slice
-                        .iter()
-                        .enumerate()
-                        .find_map(|(idx, &r)| if r == 0 { None } else { Some(idx) })
-                        .expect("Invalid VClock Invariant");
-                    Some(idx + r_slice.len())
-                } else {
-                    None
-                }
-            })
-            .map(VectorIdx::new)
-    }
-
     /// Report a data-race found in the program.
     /// This finds the two racing threads and the type
     /// of data-race that occurred. This will also
@@ -802,25 +804,21 @@ impl VClockAlloc {
         ptr_dbg: Pointer<AllocId>,
     ) -> InterpResult<'tcx> {
         let (current_index, current_clocks) = global.current_thread_state();
-        let write_clock;
+        // The conflicting clocks are converted into the dense vector clock
+        // they represent for diagnostic purposes.
         let (other_action, other_thread, other_clock) = if range.write
             > current_clocks.clock[range.write_index]
         {
-            // Convert the write action into the vector clock it
-            // represents for diagnostic purposes.
-            write_clock = VClock::new_with_index(range.write_index, range.write);
-            (range.write_type.get_descriptor(), range.write_index, &write_clock)
-        } else if let Some(idx) = Self::find_gt_index(&range.read, &current_clocks.clock) {
-            ("Read", idx, &range.read)
+            let write_clock = VClock::new_with_index(range.write_index, range.write);
+            (range.write_type.get_descriptor(), range.write_index, write_clock)
+        } else if let Some(idx) = range.read.find_gt_index(&current_clocks.clock) {
+            ("Read", idx, range.read.to_vclock())
         } else if !is_atomic {
             if let Some(atomic) = range.atomic() {
-                if let Some(idx) = Self::find_gt_index(&atomic.write_vector, &current_clocks.clock)
-                {
-                    ("Atomic Store", idx, &atomic.write_vector)
-                } else if let Some(idx) =
-                    Self::find_gt_index(&atomic.read_vector, &current_clocks.clock)
-                {
-                    ("Atomic Load", idx, &atomic.read_vector)
+                if let Some(idx) = atomic.write_vector.find_gt_index(&current_clocks.clock) {
+                    ("Atomic Store", idx, atomic.write_vector.to_vclock())
+                } else if let Some(idx) = atomic.read_vector.find_gt_index(&current_clocks.clock) {
+                    ("Atomic Load", idx, atomic.read_vector.to_vclock())
                 } else {
                     unreachable!(
                         "Failed to report data-race for non-atomic operation: no race found"
@@ -1391,6 +1389,48 @@ impl GlobalState {
         Ok(())
     }
 
+    /// Apply the effects of a `membarrier` call by the current thread. The kernel interrupts
+    /// every thread that has not terminated and makes it execute a sequentially consistent fence,
+    /// and only returns once all of them did. So the call synchronizes with each of these
+    /// threads in both directions: everything the current thread did before the call
+    /// happens-before what the other threads do after their fence, and everything the other
+    /// threads did before their fence happens-before what the current thread does after the call.
+    /// Like `maybe_perform_sync_operation`, this does nothing if there is only one thread.
+    fn membarrier(&self) {
+        if !self.multi_threaded.get() {
+            return;
+        }
+        let current = self.current_index();
+        let terminated = self.terminated_threads.borrow();
+        let reuse = self.reuse_candidates.borrow();
+        let vector_info = self.vector_info.borrow();
+        let mut vector_clocks = self.vector_clocks.borrow_mut();
+        let running: Vec<VectorIdx> = vector_clocks
+            .indices()
+            .filter(|&index| {
+                !reuse.contains(&index) && !terminated.contains_key(&vector_info[index])
+            })
+            .collect();
+
+        let caller_clock = vector_clocks[current].clock.clone();
+        let mut all_clocks = caller_clock.clone();
+        for &index in &running {
+            all_clocks.join(&vector_clocks[index].clock);
+        }
+        for &index in &running {
+            log::trace!("Fence on {:?} due to membarrier", index);
+            let clocks = &mut vector_clocks[index];
+            if index == current {
+                clocks.clock.join(&all_clocks);
+            } else {
+                clocks.clock.join(&caller_clock);
+            }
+            clocks.apply_acquire_fence();
+            clocks.apply_release_fence();
+            clocks.increment_clock(index);
+        }
+    }
+
     /// Internal utility to identify a thread stored internally
     /// returns the id and the name for better diagnostics.
     fn print_thread_metadata(&self, vector: VectorIdx) -> String {
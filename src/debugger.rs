@@ -0,0 +1,468 @@
+//! A simple interactive debugger, enabled with `-Zmiri-debugger`. Before the first step and
+//! whenever a breakpoint is hit, Miri stops and reads commands from stdin; it also stops when the
+//! program runs into an error, so that the state at that point can be inspected. Type `help` at
+//! the prompt for the list of commands.
+
+use std::fmt;
+use std::io::{self, BufRead, Write};
+
+use rustc_data_structures::fx::FxHashMap;
+use rustc_middle::mir;
+use rustc_target::abi::Size;
+
+use crate::*;
+
+/// How many bytes `memory` shows if no length is given.
+const DEFAULT_MEMORY_LEN: u64 = 64;
+
+const HELP: &str = "\
+commands:
+  continue, c              continue until the next breakpoint
+  step, s [n]              execute `n` steps (default 1)
+  break, b <path>          stop when a function whose path ends in `path` is called
+  break, b <file>:<line>   stop when execution reaches `line` in a file whose path ends in `file`
+  breakpoints              list all breakpoints
+  delete, d <n>            delete breakpoint `n`
+  threads                  list all threads
+  thread, t <id>           select a thread to inspect
+  backtrace, bt            show the stack of the selected thread
+  frame, f <n>             select frame `n` of the selected thread (0 is the innermost frame)
+  locals, l                show the local variables of the selected frame
+  memory, m <addr> [len]   show `len` bytes of memory at the address `addr` (default 64)
+  stacks <addr> [len]      show the borrow stacks of `len` bytes at `addr` (default 1)
+  quit, q                  stop the program";
+
+/// Where to stop.
+#[derive(Debug, Clone, PartialEq, Eq)]
+enum Breakpoint {
+    /// The start of a function whose path ends in this string.
+    Function(String),
+    /// A line in a file whose path ends in `file`.
+    Line { file: String, line: usize },
+}
+
+impl Breakpoint {
+    fn parse(s: &str) -> Self {
+        if let Some((file, line)) = s.rsplit_once(':') {
+            if let Ok(line) = line.parse() {
+                return Breakpoint::Line { file: file.to_string(), line };
+            }
+        }
+        Breakpoint::Function(s.to_string())
+    }
+}
+
+impl fmt::Display for Breakpoint {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Breakpoint::Function(path) => write!(f, "function `{}`", path),
+            Breakpoint::Line { file, line } => write!(f, "{}:{}", file, line),
+        }
+    }
+}
+
+#[derive(Debug)]
+pub struct Debugger {
+    breakpoints: Vec<Breakpoint>,
+    /// How many more steps to execute before stopping. `None` if we only stop at breakpoints.
+    steps_until_stop: Option<u64>,
+    /// The line each thread was on before its last step, so that a line breakpoint only stops
+    /// once when execution reaches the line, not on every statement of that line.
+    last_lines: FxHashMap<ThreadId, Location>,
+    /// The thread and frame that commands inspect. Reset to the active thread and its innermost
+    /// frame whenever execution stops.
+    selected_thread: ThreadId,
+    selected_frame: usize,
+}
+
+/// What to do after the user is done entering commands.
+enum Command {
+    /// Resume execution.
+    Resume,
+    /// Stop the program.
+    Quit,
+}
+
+impl Default for Debugger {
+    fn default() -> Self {
+        Debugger {
+            breakpoints: Vec::new(),
+            // Stop before the first step.
+            steps_until_stop: Some(0),
+            last_lines: FxHashMap::default(),
+            selected_thread: ThreadId::from(0),
+            selected_frame: 0,
+        }
+    }
+}
+
+impl Debugger {
+    /// Stop before the next step, e.g. because watched memory was accessed.
+    pub fn stop_at_next_step(&mut self) {
+        self.steps_until_stop = Some(0);
+    }
+
+    /// Whether execution should stop before the next step of `thread`, whose topmost frame is
+    /// `frame`.
+    fn should_stop<'mir, 'tcx>(
+        &mut self,
+        ecx: &MiriEvalContext<'mir, 'tcx>,
+        thread: ThreadId,
+        frame: &Frame<'mir, 'tcx, Tag, FrameData<'tcx>>,
+    ) -> bool {
+        if let Some(steps) = &mut self.steps_until_stop {
+            if *steps == 0 {
+                return true;
+            }
+            *steps -= 1;
+        }
+        let mut stop = false;
+        if frame.current_loc() == Ok(mir::Location::START) {
+            let path = ecx.tcx.def_path_str(frame.instance.def_id());
+            stop |= self.breakpoints.iter().any(
+                |bp| matches!(bp, Breakpoint::Function(bp_path) if path_ends_with(&path, bp_path)),
+            );
+        }
+        if self.breakpoints.iter().any(|bp| matches!(bp, Breakpoint::Line { .. })) {
+            let location = match Location::from_span(*ecx.tcx, frame.current_span()) {
+                Some(location) => location,
+                None => return stop,
+            };
+            let last = self.last_lines.insert(thread, location.clone());
+            let new_line =
+                last.map_or(true, |last| last.file != location.file || last.line != location.line);
+            stop |= new_line
+                && self.breakpoints.iter().any(|bp| {
+                    matches!(
+                        bp,
+                        Breakpoint::Line { file, line }
+                            if *line == location.line && location.file.ends_with(file.as_str())
+                    )
+                });
+        }
+        stop
+    }
+
+    /// Read commands until one of them resumes or stops the program. `can_resume` is false if
+    /// the program already stopped with an error, in which case only inspection is possible.
+    fn prompt<'mir, 'tcx>(
+        &mut self,
+        ecx: &MiriEvalContext<'mir, 'tcx>,
+        can_resume: bool,
+    ) -> Command {
+        self.selected_thread = ecx.get_active_thread();
+        self.selected_frame = 0;
+        self.print_location(ecx);
+        let stdin = io::stdin();
+        loop {
+            eprint!("(miri) ");
+            io::stderr().flush().unwrap();
+            let mut line = String::new();
+            // Treat the end of the input like `continue`, so that a closed stdin does not hang.
+            if stdin.lock().read_line(&mut line).unwrap_or(0) == 0 {
+                eprintln!();
+                return Command::Resume;
+            }
+            let words: Vec<&str> = line.split_whitespace().collect();
+            let (&command, args) = match words.split_first() {
+                Some(split) => split,
+                None => continue,
+            };
+            match (command, args) {
+                ("help" | "h", []) => eprintln!("{}", HELP),
+                ("continue" | "c", []) if can_resume => {
+                    self.steps_until_stop = None;
+                    return Command::Resume;
+                }
+                ("step" | "s", []) if can_resume => {
+                    self.steps_until_stop = Some(0);
+                    return Command::Resume;
+                }
+                ("step" | "s", [n]) if can_resume =>
+                    match n.parse::<u64>() {
+                        Ok(n) if n > 0 => {
+                            self.steps_until_stop = Some(n - 1);
+                            return Command::Resume;
+                        }
+                        _ => eprintln!("`{}` is not a positive number of steps", n),
+                    },
+                ("continue" | "c" | "step" | "s", _) if !can_resume =>
+                    eprintln!("the program stopped with an error and cannot be resumed"),
+                ("break" | "b", [location]) => {
+                    let bp = Breakpoint::parse(location);
+                    eprintln!("breakpoint {} at {}", self.breakpoints.len(), bp);
+                    self.breakpoints.push(bp);
+                }
+                ("breakpoints", []) =>
+                    for (i, bp) in self.breakpoints.iter().enumerate() {
+                        eprintln!("{}: {}", i, bp);
+                    },
+                ("delete" | "d", [n]) =>
+                    match n.parse::<usize>() {
+                        Ok(n) if n < self.breakpoints.len() => {
+                            eprintln!("deleted breakpoint {} at {}", n, self.breakpoints.remove(n));
+                        }
+                        _ => eprintln!("there is no breakpoint `{}`", n),
+                    },
+                ("threads", []) => self.print_threads(ecx),
+                ("thread" | "t", [id]) => {
+                    let id = id.parse::<u32>().ok().map(ThreadId::from);
+                    match id.filter(|&id| self.thread_stack(ecx, id).is_some()) {
+                        Some(id) => {
+                            self.selected_thread = id;
+                            self.selected_frame = 0;
+                            self.print_location(ecx);
+                        }
+                        None => eprintln!("there is no live thread with that id"),
+                    }
+                }
+                ("backtrace" | "bt", []) => self.print_backtrace(ecx),
+                ("frame" | "f", [n]) => {
+                    let len = self.thread_stack(ecx, self.selected_thread).map_or(0, |s| s.len());
+                    match n.parse::<usize>() {
+                        Ok(n) if n < len => {
+                            self.selected_frame = n;
+                            self.print_location(ecx);
+                        }
+                        _ => eprintln!("there is no frame `{}`", n),
+                    }
+                }
+                ("locals" | "l", []) => self.print_locals(ecx),
+                ("memory" | "m", [addr, len @ ..]) if len.len() <= 1 =>
+                    match parse_range(addr, len.first(), DEFAULT_MEMORY_LEN) {
+                        Some((addr, len)) => print_memory(ecx, addr, len),
+                        None => eprintln!("invalid address or length"),
+                    },
+                ("stacks", [addr, len @ ..]) if len.len() <= 1 =>
+                    match parse_range(addr, len.first(), 1) {
+                        Some((addr, len)) => print_borrow_stacks(ecx, addr, len),
+                        None => eprintln!("invalid address or length"),
+                    },
+                ("quit" | "q", []) => return Command::Quit,
+                _ => eprintln!("invalid command, type `help` for a list of commands"),
+            }
+        }
+    }
+
+    fn thread_stack<'a, 'mir, 'tcx>(
+        &self,
+        ecx: &'a MiriEvalContext<'mir, 'tcx>,
+        thread: ThreadId,
+    ) -> Option<&'a [Frame<'mir, 'tcx, Tag, FrameData<'tcx>>]> {
+        ecx.machine.threads.live_threads().find(|(id, ..)| *id == thread).map(|(.., stack)| stack)
+    }
+
+    /// The selected frame, if the selected thread still has frames.
+    fn selected_frame<'a, 'mir, 'tcx>(
+        &self,
+        ecx: &'a MiriEvalContext<'mir, 'tcx>,
+    ) -> Option<&'a Frame<'mir, 'tcx, Tag, FrameData<'tcx>>> {
+        let stack = self.thread_stack(ecx, self.selected_thread)?;
+        stack.iter().rev().nth(self.selected_frame)
+    }
+
+    fn print_location(&self, ecx: &MiriEvalContext<'_, '_>) {
+        match self.selected_frame(ecx) {
+            Some(frame) => eprintln!("{}", describe_frame(ecx, self.selected_frame, frame)),
+            None => eprintln!("thread {} has no frames", self.selected_thread.to_u32()),
+        }
+    }
+
+    fn print_threads(&self, ecx: &MiriEvalContext<'_, '_>) {
+        let active = ecx.get_active_thread();
+        for (id, name, stack) in ecx.machine.threads.live_threads() {
+            let marker = if id == self.selected_thread { "*" } else { " " };
+            let function = stack.last().map_or(String::new(), |f| format!(" in `{}`", f.instance));
+            let state = if id == active { " (active)" } else { "" };
+            eprintln!("{} {}: `{}`{}{}", marker, id.to_u32(), name, function, state);
+        }
+    }
+
+    fn print_backtrace(&self, ecx: &MiriEvalContext<'_, '_>) {
+        let stack = self.thread_stack(ecx, self.selected_thread).unwrap_or(&[]);
+        for (i, frame) in stack.iter().rev().enumerate() {
+            let marker = if i == self.selected_frame { "*" } else { " " };
+            eprintln!("{} {}", marker, describe_frame(ecx, i, frame));
+        }
+    }
+
+    fn print_locals<'mir, 'tcx>(&self, ecx: &MiriEvalContext<'mir, 'tcx>) {
+        let frame = match self.selected_frame(ecx) {
+            Some(frame) => frame,
+            None => return,
+        };
+        for (name, value) in frame_locals(ecx, frame) {
+            eprintln!("  {} = {}", name, value);
+        }
+    }
+}
+
+/// Describe `frame`, which is frame number `idx` counting from the innermost frame.
+pub(crate) fn describe_frame<'mir, 'tcx>(
+    ecx: &MiriEvalContext<'mir, 'tcx>,
+    idx: usize,
+    frame: &Frame<'mir, 'tcx, Tag, FrameData<'tcx>>,
+) -> String {
+    match Location::from_span(*ecx.tcx, frame.current_span()) {
+        Some(loc) =>
+            format!("#{} `{}` at {}:{}:{}", idx, frame.instance, loc.file, loc.line, loc.column + 1),
+        None => format!("#{} `{}`", idx, frame.instance),
+    }
+}
+
+/// The user-visible local variables of `frame` with their current values.
+pub(crate) fn frame_locals<'mir, 'tcx>(
+    ecx: &MiriEvalContext<'mir, 'tcx>,
+    frame: &Frame<'mir, 'tcx, Tag, FrameData<'tcx>>,
+) -> Vec<(String, String)> {
+    let mut locals = Vec::new();
+    for info in &frame.body.var_debug_info {
+        let local = match info.value {
+            mir::VarDebugInfoContents::Place(place) =>
+                match place.as_local() {
+                    Some(local) => local,
+                    None => continue,
+                },
+            mir::VarDebugInfoContents::Const(_) => continue,
+        };
+        let value = ecx.local_to_op(frame, local, None).map(|op| {
+            match op.try_as_mplace() {
+                // Only show the bytes of values in memory, so that they can be read without
+                // running the memory access hooks; `memory` shows more.
+                Ok(mplace) => {
+                    let bytes = match raw_bytes(ecx, mplace.ptr, op.layout.size) {
+                        Ok(bytes) if bytes.len() <= 16 => format!(": {:02x?}", bytes),
+                        _ => String::new(),
+                    };
+                    format!("<{} at {:?}{}>", op.layout.ty, mplace.ptr, bytes)
+                }
+                Err(imm) => imm.to_string(),
+            }
+        });
+        let value = value.unwrap_or_else(|err| format!("<{}>", err));
+        locals.push((info.name.to_string(), value));
+    }
+    locals
+}
+
+/// Whether the last segments of the path `path` are `suffix`.
+fn path_ends_with(path: &str, suffix: &str) -> bool {
+    path == suffix || path.strip_suffix(suffix).map_or(false, |prefix| prefix.ends_with("::"))
+}
+
+/// Parse an address and an optional length, both either decimal or hexadecimal with `0x`.
+fn parse_range(addr: &str, len: Option<&&str>, default_len: u64) -> Option<(u64, u64)> {
+    fn parse(s: &str) -> Option<u64> {
+        match s.strip_prefix("0x") {
+            Some(hex) => u64::from_str_radix(hex, 16).ok(),
+            None => s.parse().ok(),
+        }
+    }
+    let len = match len {
+        Some(len) => parse(len)?,
+        None => default_len,
+    };
+    Some((parse(addr)?, len))
+}
+
+/// The allocation and range of the `size` bytes at `ptr`. This does not run the memory access
+/// hooks, so that the debugger can inspect memory without affecting how the program continues,
+/// e.g. by changing the borrow stacks or data race clocks, or by triggering watchpoints.
+fn raw_range<'tcx>(
+    ecx: &MiriEvalContext<'_, 'tcx>,
+    ptr: Pointer<Option<Tag>>,
+    size: Size,
+) -> InterpResult<'tcx, (AllocId, AllocRange)> {
+    let (alloc_id, offset, _) = ecx.ptr_get_alloc_id(ptr)?;
+    let (alloc_size, _) = ecx.get_alloc_size_and_align(alloc_id, AllocCheck::Dereferenceable)?;
+    if offset.bytes().checked_add(size.bytes()).map_or(true, |end| end > alloc_size.bytes()) {
+        throw_ub!(PointerOutOfBounds {
+            alloc_id,
+            alloc_size,
+            ptr_offset: ecx.machine_usize_to_isize(offset.bytes()),
+            ptr_size: size,
+            msg: CheckInAllocMsg::MemoryAccessTest,
+        });
+    }
+    Ok((alloc_id, alloc_range(offset, size)))
+}
+
+/// The `size` bytes at `ptr`, read without running the memory access hooks. Uninitialized bytes
+/// are shown as 0.
+fn raw_bytes<'tcx>(
+    ecx: &MiriEvalContext<'_, 'tcx>,
+    ptr: Pointer<Option<Tag>>,
+    size: Size,
+) -> InterpResult<'tcx, Vec<u8>> {
+    let (alloc_id, range) = raw_range(ecx, ptr, size)?;
+    let range = range.start.bytes_usize()..range.end().bytes_usize();
+    Ok(ecx.get_alloc_raw(alloc_id)?.inspect_with_uninit_and_ptr_outside_interpreter(range).to_vec())
+}
+
+fn print_memory(ecx: &MiriEvalContext<'_, '_>, addr: u64, len: u64) {
+    let ptr = intptrcast::GlobalStateInner::ptr_from_addr(addr, ecx);
+    let bytes = match raw_bytes(ecx, ptr, Size::from_bytes(len)) {
+        Ok(bytes) => bytes,
+        Err(err) => {
+            eprintln!("cannot read {} bytes at {:#x}: {}", len, addr, err);
+            return;
+        }
+    };
+    for (i, line) in bytes.chunks(16).enumerate() {
+        eprintln!("{:#x}: {:02x?}", addr + 16 * i as u64, line);
+    }
+}
+
+fn print_borrow_stacks(ecx: &MiriEvalContext<'_, '_>, addr: u64, len: u64) {
+    if ecx.machine.stacked_borrows.is_none() {
+        eprintln!("Stacked Borrows is disabled");
+        return;
+    }
+    let ptr = intptrcast::GlobalStateInner::ptr_from_addr(addr, ecx);
+    let res = raw_range(ecx, ptr, Size::from_bytes(len)).and_then(|(alloc_id, range)| {
+        let stacks = ecx.get_alloc_extra(alloc_id)?.stacked_borrows.as_ref().unwrap();
+        stacks.print_stacks(alloc_id, range);
+        Ok(())
+    });
+    if let Err(err) = res {
+        eprintln!("cannot access {} bytes at {:#x}: {}", len, addr, err);
+    }
+}
+
+impl<'mir, 'tcx: 'mir> EvalContextExt<'mir, 'tcx> for crate::MiriEvalContext<'mir, 'tcx> {}
+pub trait EvalContextExt<'mir, 'tcx: 'mir>: crate::MiriEvalContextExt<'mir, 'tcx> {
+    /// Stop in the debugger if it is enabled and the step that is about to be executed should be
+    /// stopped at.
+    fn debugger_step(&mut self) -> InterpResult<'tcx> {
+        let this = self.eval_context_mut();
+        let mut debugger = match this.machine.debugger.take() {
+            Some(debugger) => debugger,
+            None => return Ok(()),
+        };
+        let thread = this.get_active_thread();
+        let stop = match this.active_thread_stack().last() {
+            Some(frame) => debugger.should_stop(this, thread, frame),
+            None => false,
+        };
+        let command = if stop { debugger.prompt(this, true) } else { Command::Resume };
+        this.machine.debugger = Some(debugger);
+        match command {
+            Command::Quit =>
+                throw_machine_stop!(TerminationInfo::Abort(
+                    "the program was stopped from the debugger".to_string()
+                )),
+            Command::Resume => Ok(()),
+        }
+    }
+
+    /// Let the user inspect the state of the program after it stopped with an error, which has
+    /// already been reported.
+    fn debugger_after_error(&mut self) {
+        let this = self.eval_context_mut();
+        if let Some(mut debugger) = this.machine.debugger.take() {
+            eprintln!("the program stopped with the error above; its state can still be inspected");
+            debugger.prompt(this, false);
+            this.machine.debugger = Some(debugger);
+        }
+    }
+}
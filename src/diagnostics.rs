@@ -1,11 +1,15 @@
 use std::cell::RefCell;
 use std::fmt;
+use std::mem;
 use std::num::NonZeroU64;
 
 use log::trace;
 
-use rustc_middle::ty;
+use rustc_data_structures::fx::FxHashMap;
+use rustc_errors::DiagnosticId;
+use rustc_middle::{mir, ty};
 use rustc_span::{source_map::DUMMY_SP, Span, SpanData, Symbol};
+use rustc_target::abi::Size;
 
 use crate::stacked_borrows::{AccessKind, SbTag};
 use crate::*;
@@ -19,8 +23,30 @@ pub enum TerminationInfo {
         msg: String,
         help: Option<String>,
         url: String,
+        /// The allocation that the error is about, if any.
+        alloc_id: Option<AllocId>,
+        /// Whether the allocation was accessed with a pointer created by an int2ptr cast.
+        untagged_access: bool,
     },
     Deadlock,
+    /// All threads kept yielding to each other without making progress.
+    SpinDeadlock {
+        /// Where the other threads were when we gave up.
+        other_threads: Vec<(Option<SpanData>, String)>,
+    },
+    /// The program tried to have more than `limit` threads alive at the same time.
+    TooManyThreads {
+        limit: u64,
+        /// Where the threads that are alive are.
+        live_threads: Vec<(Option<SpanData>, String)>,
+    },
+    /// The allocation `alloc_id` of `size` bytes does not fit into the `bits`-bit address space of
+    /// the target.
+    AddressSpaceExhausted {
+        alloc_id: AllocId,
+        size: Size,
+        bits: u64,
+    },
     MultipleSymbolDefinitions {
         link_name: Symbol,
         first: SpanData,
@@ -32,6 +58,18 @@ pub enum TerminationInfo {
         link_name: Symbol,
         span: SpanData,
     },
+    /// The program wrote to memory that it froze with `miri_freeze`.
+    WriteToFrozenMemory {
+        alloc_id: AllocId,
+        range: AllocRange,
+        frozen_at: SpanData,
+    },
+    /// A warning of a kind that was denied with `-Zmiri-deny`. The warning itself has already
+    /// been reported as an error.
+    DeniedWarning {
+        kind: WarningKind,
+        msg: String,
+    },
 }
 
 impl fmt::Display for TerminationInfo {
@@ -43,6 +81,19 @@ impl fmt::Display for TerminationInfo {
             UnsupportedInIsolation(msg) => write!(f, "{}", msg),
             ExperimentalUb { msg, .. } => write!(f, "{}", msg),
             Deadlock => write!(f, "the evaluated program deadlocked"),
+            SpinDeadlock { .. } =>
+                write!(f, "the evaluated program seems to be stuck in a spin loop"),
+            TooManyThreads { limit, .. } =>
+                write!(f, "cannot spawn a thread: the limit of {} live threads was reached", limit),
+            AddressSpaceExhausted { alloc_id, size, bits } =>
+                write!(
+                    f,
+                    "the program used up the address space of the target: \
+                    allocation {:?} of size {} does not fit in {} bits",
+                    alloc_id,
+                    size.bytes(),
+                    bits,
+                ),
             MultipleSymbolDefinitions { link_name, .. } =>
                 write!(f, "multiple definitions of symbol `{}`", link_name),
             SymbolShimClashing { link_name, .. } =>
@@ -51,6 +102,15 @@ impl fmt::Display for TerminationInfo {
                     "found `{}` symbol definition that clashes with a built-in shim",
                     link_name
                 ),
+            WriteToFrozenMemory { alloc_id, range, .. } =>
+                write!(
+                    f,
+                    "writing to memory at {:?}[{:#x}..{:#x}], which was frozen by `miri_freeze`",
+                    alloc_id,
+                    range.start.bytes(),
+                    range.end().bytes(),
+                ),
+            DeniedWarning { msg, .. } => write!(f, "{}", msg),
         }
     }
 }
@@ -64,9 +124,106 @@ pub enum NonHaltingDiagnostic {
     /// `AccessKind` to `SbTag` or a deallocation when the second argument is `None`.
     PoppedPointerTag(Item, Option<(SbTag, AccessKind)>),
     CreatedCallId(CallId),
-    CreatedAlloc(AllocId),
-    FreedAlloc(AllocId),
+    CreatedAlloc(AllocId, StableAllocId),
+    FreedAlloc(AllocId, StableAllocId),
     RejectedIsolatedOp(String),
+    /// An operation that would spawn a process was made to return an error.
+    RejectedProcessSpawn(String),
+    /// An integer was cast to a pointer, and the allocation it points to was guessed.
+    Int2Ptr(u64),
+    /// An unsupported operation was turned into a panic by `-Zmiri-panic-on-unsupported`.
+    UnsupportedPanic(String),
+    /// Memory watched with `-Zmiri-watch` or `miri_watch` was accessed.
+    WatchedAccess(WatchedAccess),
+}
+
+impl NonHaltingDiagnostic {
+    /// The kind of warning this is, if it is a warning rather than a tracking note.
+    fn warning_kind(&self) -> Option<WarningKind> {
+        use NonHaltingDiagnostic::*;
+        match self {
+            RejectedIsolatedOp(_) => Some(WarningKind::Isolation),
+            RejectedProcessSpawn(_) => Some(WarningKind::ProcessSpawn),
+            Int2Ptr(_) => Some(WarningKind::Int2Ptr),
+            UnsupportedPanic(_) => Some(WarningKind::Unsupported),
+            _ => None,
+        }
+    }
+}
+
+/// The kinds of warnings that can be allowed, shown, or denied individually.
+#[derive(Copy, Clone, Debug, PartialEq, Eq, Hash)]
+pub enum WarningKind {
+    /// Integer-to-pointer casts, whose provenance Miri has to guess.
+    Int2Ptr,
+    /// Operations that returned an error because of isolation (with `-Zmiri-isolation-error=warn`).
+    Isolation,
+    /// Operations that spawn a process, which returned an error.
+    ProcessSpawn,
+    /// Unsupported operations that were turned into panics (with `-Zmiri-panic-on-unsupported`).
+    Unsupported,
+}
+
+impl WarningKind {
+    pub const ALL: [WarningKind; 4] = [
+        WarningKind::Int2Ptr,
+        WarningKind::Isolation,
+        WarningKind::ProcessSpawn,
+        WarningKind::Unsupported,
+    ];
+
+    /// The name of this kind in flags like `-Zmiri-deny=<name>`, and in the code of diagnostics.
+    pub fn name(self) -> &'static str {
+        match self {
+            WarningKind::Int2Ptr => "int2ptr",
+            WarningKind::Isolation => "isolation",
+            WarningKind::ProcessSpawn => "process-spawn",
+            WarningKind::Unsupported => "unsupported",
+        }
+    }
+
+    pub fn from_name(name: &str) -> Option<Self> {
+        WarningKind::ALL.iter().copied().find(|kind| kind.name() == name)
+    }
+
+    /// The level of warnings of this kind, unless it is configured otherwise.
+    pub fn default_level(self) -> WarningLevel {
+        match self {
+            WarningKind::Int2Ptr | WarningKind::Unsupported => WarningLevel::Allow,
+            WarningKind::Isolation | WarningKind::ProcessSpawn => WarningLevel::Warn,
+        }
+    }
+}
+
+/// What to do about a kind of warning.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum WarningLevel {
+    /// Do not show the warning.
+    Allow,
+    /// Show the warning and continue.
+    Warn,
+    /// Report the warning as an error, and stop the program.
+    Deny,
+}
+
+/// How often a warning is shown at the same location. Further occurrences are only counted, and
+/// summarized when the program terminates. Notes are never limited.
+const MAX_REPEATED_DIAGNOSTICS: u64 = 3;
+
+/// How often each kind of warning was emitted at each location, and its title.
+#[derive(Default)]
+pub struct DiagnosticCounts(
+    RefCell<FxHashMap<(mem::Discriminant<NonHaltingDiagnostic>, Span), (&'static str, u64)>>,
+);
+
+impl DiagnosticCounts {
+    /// Count an occurrence of `diagnostic` at `span`, and return how often it occurred there.
+    fn record(&self, diagnostic: &NonHaltingDiagnostic, span: Span, title: &'static str) -> u64 {
+        let mut counts = self.0.borrow_mut();
+        let (_, count) = counts.entry((mem::discriminant(diagnostic), span)).or_insert((title, 0));
+        *count += 1;
+        *count
+    }
 }
 
 /// Level of Miri specific diagnostics
@@ -127,26 +284,89 @@ fn prune_stacktrace<'mir, 'tcx>(
     }
 }
 
-/// Emit a custom diagnostic without going through the miri-engine machinery
+/// Explains where pointers to `alloc_id` were cast to integers, for an error caused by a pointer
+/// that was cast back from an integer.
+fn exposure_helps<'tcx, 'mir>(
+    ecx: &InterpCx<'mir, 'tcx, Evaluator<'mir, 'tcx>>,
+    alloc_id: AllocId,
+) -> Vec<(Option<SpanData>, String)> {
+    let intptrcast = ecx.machine.intptrcast.borrow();
+    let exposures = match intptrcast.exposures(alloc_id) {
+        Some(exposures) => exposures,
+        None =>
+            return vec![(
+                None,
+                format!(
+                    "the pointer was cast from an integer; pass `-Zmiri-track-exposures` to see \
+                    where pointers to {:?} were cast to integers",
+                    alloc_id
+                ),
+            )],
+    };
+    if exposures.is_empty() {
+        return vec![(
+            None,
+            format!(
+                "the pointer was cast from an integer, but no pointer to {:?} was ever cast to an integer",
+                alloc_id
+            ),
+        )];
+    }
+    let mut helps = vec![(
+        None,
+        format!(
+            "the pointer was cast from an integer; these casts of pointers to {:?} to an integer could have provided its provenance:",
+            alloc_id
+        ),
+    )];
+    helps.extend(exposures.iter().map(|exposure| {
+        (
+            Some(exposure.span),
+            format!("a pointer with tag {:?} was cast to an integer here", exposure.tag),
+        )
+    }));
+    helps
+}
+
+/// Emit a custom diagnostic without going through the miri-engine machinery.
+/// Returns the exit code if the program called `exit`, and a description of the error otherwise.
 pub fn report_error<'tcx, 'mir>(
     ecx: &InterpCx<'mir, 'tcx, Evaluator<'mir, 'tcx>>,
     e: InterpErrorInfo<'tcx>,
-) -> Option<i64> {
+) -> Result<i64, ErrorReport> {
     use InterpError::*;
 
     let mut msg = vec![];
 
-    let (title, helps) = match &e.kind() {
+    let (kind, title, helps) = match &e.kind() {
         MachineStop(info) => {
             let info = info.downcast_ref::<TerminationInfo>().expect("invalid MachineStop payload");
             use TerminationInfo::*;
-            let title = match info {
-                Exit(code) => return Some(*code),
-                Abort(_) => Some("abnormal termination"),
-                UnsupportedInIsolation(_) => Some("unsupported operation"),
-                ExperimentalUb { .. } => Some("Undefined Behavior"),
-                Deadlock => Some("deadlock"),
-                MultipleSymbolDefinitions { .. } | SymbolShimClashing { .. } => None,
+            let (kind, title) = match info {
+                Exit(code) => {
+                    if let Some(pattern) = &ecx.machine.expected_error {
+                        return Err(report_missing_expected_error(ecx, pattern));
+                    }
+                    return Ok(*code);
+                }
+                Abort(_) | WriteToFrozenMemory { .. } =>
+                    (ErrorKind::Abort, Some("abnormal termination")),
+                UnsupportedInIsolation(_) =>
+                    (ErrorKind::Unsupported, Some("unsupported operation")),
+                ExperimentalUb { .. } => (ErrorKind::UndefinedBehavior, Some("Undefined Behavior")),
+                Deadlock | SpinDeadlock { .. } => (ErrorKind::Deadlock, Some("deadlock")),
+                TooManyThreads { .. } | AddressSpaceExhausted { .. } =>
+                    (ErrorKind::ResourceExhaustion, Some("resource exhaustion")),
+                MultipleSymbolDefinitions { .. } | SymbolShimClashing { .. } =>
+                    (ErrorKind::Linking, None),
+                DeniedWarning { msg, .. } =>
+                    return Err(ErrorReport {
+                        kind: ErrorKind::DeniedWarning,
+                        code: None,
+                        message: msg.clone(),
+                        stacktrace: vec![],
+                        related: vec![],
+                    }),
             };
             #[rustfmt::skip]
             let helps = match info {
@@ -155,12 +375,16 @@ pub fn report_error<'tcx, 'mir>(
                         (None, format!("pass the flag `-Zmiri-disable-isolation` to disable isolation;")),
                         (None, format!("or pass `-Zmiri-isolation-error=warn` to configure Miri to return an error code from isolated operations (if supported for that operation) and continue with a warning")),
                     ],
-                ExperimentalUb { url, help, .. } => {
+                ExperimentalUb { url, help, alloc_id, untagged_access } => {
                     msg.extend(help.clone());
-                    vec![
+                    let mut helps = vec![
                         (None, format!("this indicates a potential bug in the program: it performed an invalid operation, but the rules it violated are still experimental")),
                         (None, format!("see {} for further information", url))
-                    ]
+                    ];
+                    if let (Some(alloc_id), true) = (alloc_id, untagged_access) {
+                        helps.extend(exposure_helps(ecx, *alloc_id));
+                    }
+                    helps
                 }
                 MultipleSymbolDefinitions { first, first_crate, second, second_crate, .. } =>
                     vec![
@@ -169,21 +393,38 @@ pub fn report_error<'tcx, 'mir>(
                     ],
                 SymbolShimClashing { link_name, span } =>
                     vec![(Some(*span), format!("the `{}` symbol is defined here", link_name))],
+                WriteToFrozenMemory { frozen_at, .. } =>
+                    vec![(Some(*frozen_at), format!("the memory was frozen here"))],
+                SpinDeadlock { other_threads } => {
+                    let mut helps = vec![
+                        (None, format!("the threads kept yielding (e.g. via `spin_loop` or `yield_now`) without any of them making progress")),
+                        (None, format!("pass `-Zmiri-spin-loop-limit=<n>` to allow more spins, or `0` to disable this check")),
+                    ];
+                    helps.extend(other_threads.iter().cloned());
+                    helps
+                }
+                TooManyThreads { live_threads, .. } => {
+                    let mut helps = vec![
+                        (None, format!("pass `-Zmiri-max-threads=<n>` to allow more threads")),
+                    ];
+                    helps.extend(live_threads.iter().cloned());
+                    helps
+                }
                 _ => vec![],
             };
-            (title, helps)
+            (kind, title, helps)
         }
         _ => {
             #[rustfmt::skip]
-            let title = match e.kind() {
+            let (kind, title) = match e.kind() {
                 Unsupported(_) =>
-                    "unsupported operation",
+                    (ErrorKind::Unsupported, "unsupported operation"),
                 UndefinedBehavior(_) =>
-                    "Undefined Behavior",
+                    (ErrorKind::UndefinedBehavior, "Undefined Behavior"),
                 ResourceExhaustion(_) =>
-                    "resource exhaustion",
+                    (ErrorKind::ResourceExhaustion, "resource exhaustion"),
                 InvalidProgram(InvalidProgramInfo::AlreadyReported(_) | InvalidProgramInfo::Layout(..)) =>
-                    "post-monomorphization error",
+                    (ErrorKind::PostMonomorphization, "post-monomorphization error"),
                 kind =>
                     bug!("This error should be impossible in Miri: {:?}", kind),
             };
@@ -207,7 +448,7 @@ pub fn report_error<'tcx, 'mir>(
                     ],
                 _ => vec![],
             };
-            (Some(title), helps)
+            (kind, Some(title), helps)
         }
     };
 
@@ -215,14 +456,59 @@ pub fn report_error<'tcx, 'mir>(
     let (stacktrace, was_pruned) = prune_stacktrace(ecx, stacktrace);
     e.print_backtrace();
     msg.insert(0, e.to_string());
-    report_msg(
-        ecx,
-        DiagLevel::Error,
-        &if let Some(title) = title { format!("{}: {}", title, msg[0]) } else { msg[0].clone() },
-        msg,
-        helps,
-        &stacktrace,
-    );
+    let full_msg =
+        if let Some(title) = title { format!("{}: {}", title, msg[0]) } else { msg[0].clone() };
+    if let Some(pattern) = &ecx.machine.expected_error {
+        if full_msg.contains(pattern.as_str()) {
+            ecx.tcx.sess.note_without_error(&format!(
+                "the evaluated program stopped with an error matching `{}`, as expected",
+                pattern
+            ));
+            return Ok(0);
+        }
+    }
+    let code = error_code(e.kind());
+    let mut helps = helps;
+    helps.extend(allocation_site(ecx, e.kind()));
+    helps.extend(value_origin(ecx, e.kind()));
+    helps.extend(poisoned_errno(ecx, e.kind()));
+    if let Some(code) = code {
+        helps.push((
+            None,
+            format!("for more information about this error, try `miri --explain {}`", code),
+        ));
+    }
+    let report = ErrorReport {
+        kind,
+        code,
+        message: msg[0].clone(),
+        stacktrace: stacktrace.iter().map(|frame| ReportFrame::new(*ecx.tcx, frame)).collect(),
+        related: helps
+            .iter()
+            .filter_map(|(span, msg)| {
+                let location = Location::from_span(*ecx.tcx, span?.span())?;
+                Some((location, msg.clone()))
+            })
+            .collect(),
+    };
+    let diagnostic_id = code.map(|code| DiagnosticId::Error(code.to_string()));
+    report_msg(ecx, DiagLevel::Error, diagnostic_id, &full_msg, msg, helps, &stacktrace);
+    if let Some(path) = &ecx.machine.core_dump {
+        match write_core_dump(ecx, &full_msg, &error_allocations(e.kind()), path) {
+            Ok(()) =>
+                ecx.tcx.sess.note_without_error(&format!(
+                    "the state of the program was written to `{}`",
+                    path
+                )),
+            Err(err) => ecx.tcx.sess.warn(&format!("failed to write the core dump: {}", err)),
+        }
+    }
+    if let Some(pattern) = &ecx.machine.expected_error {
+        ecx.tcx.sess.note_without_error(&format!(
+            "the evaluated program was expected to stop with an error matching `{}`",
+            pattern
+        ));
+    }
 
     // Include a note like `std` does when we omit frames from a backtrace
     if was_pruned {
@@ -232,12 +518,14 @@ pub fn report_error<'tcx, 'mir>(
     }
 
     // Debug-dump all locals.
-    for (i, frame) in ecx.active_thread_stack().iter().enumerate() {
-        trace!("-------------------");
-        trace!("Frame {}", i);
-        trace!("    return: {:?}", frame.return_place.map(|p| *p));
-        for (i, local) in frame.locals.iter().enumerate() {
-            trace!("    local {}: {:?}", i, local.value);
+    if log::log_enabled!(log::Level::Trace) {
+        for (i, frame) in ecx.active_thread_stack().iter().enumerate() {
+            trace!("-------------------");
+            trace!("Frame {}", i);
+            trace!("    return: {:?}", frame.return_place.map(|p| *p));
+            for (i, local) in frame.locals.iter().enumerate() {
+                trace!("    local {}: {:?}", i, local.value);
+            }
         }
     }
 
@@ -254,29 +542,242 @@ pub fn report_error<'tcx, 'mir>(
         _ => {}
     }
 
-    None
+    Err(report)
 }
 
-/// Report an error or note (depending on the `error` argument) with the given stacktrace.
-/// Also emits a full stacktrace of the interpreter stack.
+/// Point to where the allocation that `e` is about was created, if that was recorded (see
+/// `MiriConfig::record_alloc_backtraces`).
+fn allocation_site<'tcx, 'mir>(
+    ecx: &InterpCx<'mir, 'tcx, Evaluator<'mir, 'tcx>>,
+    e: &InterpError<'tcx>,
+) -> Option<(Option<SpanData>, String)> {
+    use UndefinedBehaviorInfo::*;
+    let alloc_id = match e {
+        InterpError::UndefinedBehavior(
+            PointerUseAfterFree(alloc_id)
+            | PointerOutOfBounds { alloc_id, .. }
+            | InvalidUninitBytes(Some((alloc_id, _))),
+        ) => *alloc_id,
+        _ => return None,
+    };
+    let live = ecx.machine.live_allocs.as_ref()?.borrow();
+    let backtrace = match live.get(&alloc_id).and_then(|info| info.backtrace.as_ref()) {
+        Some(backtrace) => backtrace,
+        None => ecx.machine.freed_alloc_backtraces.get(&alloc_id)?,
+    };
+    let frame = backtrace.iter().find(|frame| ecx.machine.is_local(frame)).or(backtrace.first())?;
+    Some((Some(frame.span.data()), format!("{:?} was allocated here", alloc_id)))
+}
+
+/// The allocations that `e` is about, for the core dump.
+fn error_allocations(e: &InterpError<'_>) -> Vec<AllocId> {
+    use UndefinedBehaviorInfo::*;
+    match e {
+        InterpError::UndefinedBehavior(
+            PointerUseAfterFree(alloc_id)
+            | PointerOutOfBounds { alloc_id, .. }
+            | InvalidUninitBytes(Some((alloc_id, _)))
+            | DerefFunctionPointer(alloc_id)
+            | WriteToReadOnly(alloc_id),
+        ) => vec![*alloc_id],
+        InterpError::MachineStop(info) =>
+            match info.downcast_ref::<TerminationInfo>() {
+                Some(TerminationInfo::ExperimentalUb { alloc_id: Some(alloc_id), .. }) =>
+                    vec![*alloc_id],
+                _ => vec![],
+            },
+        _ => vec![],
+    }
+}
+
+/// For an error about reading `errno` after `-Zmiri-strict-errno` made it uninitialized, explain
+/// which call did that.
+fn poisoned_errno<'tcx, 'mir>(
+    ecx: &InterpCx<'mir, 'tcx, Evaluator<'mir, 'tcx>>,
+    e: &InterpError<'tcx>,
+) -> Option<(Option<SpanData>, String)> {
+    use UndefinedBehaviorInfo::*;
+    let alloc_id = match e {
+        InterpError::UndefinedBehavior(InvalidUninitBytes(Some((alloc_id, _)))) => *alloc_id,
+        _ => return None,
+    };
+    let thread = ecx.active_thread_ref();
+    let errno_place = thread.last_error?;
+    if errno_place.ptr.provenance.map(|tag| tag.alloc_id) != Some(alloc_id) {
+        return None;
+    }
+    let poisoned_after = thread.last_error_poisoned_after?;
+    let mut msg = format!(
+        "`errno` was made uninitialized by `-Zmiri-strict-errno` after the successful call to `{}`; `errno` is only meaningful after a call that indicated an error",
+        poisoned_after
+    );
+    if let Some(setter) = thread.last_error_setter {
+        msg.push_str(&format!(" (it was last set by `{}`)", setter));
+    }
+    Some((None, msg))
+}
+
+/// For an error about an invalid value, explain where the value came from (if
+/// `-Zmiri-track-value-origins` is set).
+fn value_origin<'tcx, 'mir>(
+    ecx: &InterpCx<'mir, 'tcx, Evaluator<'mir, 'tcx>>,
+    e: &InterpError<'tcx>,
+) -> Vec<(Option<SpanData>, String)> {
+    use UndefinedBehaviorInfo::*;
+    let value_origins = match &ecx.machine.value_origins {
+        Some(value_origins) => value_origins.borrow(),
+        None => return vec![],
+    };
+    match e {
+        InterpError::UndefinedBehavior(
+            ValidationFailure { .. }
+            | InvalidBool(_)
+            | InvalidChar(_)
+            | InvalidTag(_)
+            | InvalidFunctionPointer(_)
+            | InvalidUninitBytes(_)
+            | PointerUseAfterFree(_)
+            | PointerOutOfBounds { .. }
+            | DanglingIntPointer(..),
+        ) => value_origins::origin_helps(&value_origins),
+        _ => vec![],
+    }
+}
+
+/// Point to where each of the `leaked` threads (see `ThreadManager::leaked_threads`) was spawned.
+pub fn report_leaked_threads<'tcx, 'mir>(
+    ecx: &InterpCx<'mir, 'tcx, Evaluator<'mir, 'tcx>>,
+    leaked: Vec<(String, LeakedThread, Vec<FrameInfo<'tcx>>)>,
+) {
+    for (name, kind, stacktrace) in leaked {
+        let (stacktrace, _) = prune_stacktrace(ecx, stacktrace);
+        let title = match kind {
+            LeakedThread::Running => format!("thread `{}` was still running", name),
+            LeakedThread::NotJoined => format!("thread `{}` was never joined or detached", name),
+            LeakedThread::Detached => format!("thread `{}` was detached instead of joined", name),
+        };
+        report_msg(
+            ecx,
+            DiagLevel::Note,
+            None,
+            &title,
+            vec![format!("the thread was spawned here")],
+            vec![],
+            &stacktrace,
+        );
+    }
+}
+
+/// Print a summary of the leaked allocations, grouped by the function that created them, and if
+/// `full` is set, the contents of each allocation.
+pub fn report_leaked_allocations<'tcx, 'mir>(
+    ecx: &InterpCx<'mir, 'tcx, Evaluator<'mir, 'tcx>>,
+    final_state: &FinalState,
+    full: bool,
+) {
+    eprintln!("Leaked memory, grouped by the function that allocated it:");
+    eprintln!("{:>8} {:>12}  allocated by", "count", "bytes");
+    for group in final_state.leak_groups() {
+        eprintln!(
+            "{:>8} {:>12}  {} ({})",
+            group.count,
+            group.bytes,
+            group.site.as_deref().unwrap_or("<unknown>"),
+            group.kind
+        );
+    }
+    if full {
+        let leaked = final_state.leaked_allocations().map(|leak| leak.id).collect();
+        eprintln!("The following memory was leaked: {:?}", ecx.dump_allocs(leaked));
+    }
+}
+
+/// Print the stable ID of each allocation, for `-Zmiri-print-alloc-ids`.
+pub fn report_alloc_ids<'tcx, 'mir>(ecx: &InterpCx<'mir, 'tcx, Evaluator<'mir, 'tcx>>) {
+    let stable_alloc_ids = match &ecx.machine.stable_alloc_ids {
+        Some(stable_alloc_ids) => stable_alloc_ids.borrow(),
+        None => return,
+    };
+    let mut note = ecx.tcx.sess.diagnostic().span_note_diag(DUMMY_SP, "allocation ids");
+    for (AllocId(id), stable_id) in stable_alloc_ids.table() {
+        note.note(&format!("{} -> stable id {}", id, stable_id));
+    }
+    note.emit();
+}
+
+/// Report that the program terminated although it was expected to stop with an error matching
+/// `pattern`.
+pub fn report_missing_expected_error<'tcx, 'mir>(
+    ecx: &InterpCx<'mir, 'tcx, Evaluator<'mir, 'tcx>>,
+    pattern: &str,
+) -> ErrorReport {
+    let msg = format!(
+        "the evaluated program terminated, but it was expected to stop with an error matching `{}`",
+        pattern
+    );
+    ecx.tcx.sess.err(&msg);
+    ErrorReport {
+        kind: ErrorKind::MissingExpectedError,
+        code: None,
+        message: msg,
+        stacktrace: vec![],
+        related: vec![],
+    }
+}
+
+/// Report how many occurrences of repeated warnings were not shown.
+pub fn report_suppressed_diagnostics<'tcx, 'mir>(
+    ecx: &InterpCx<'mir, 'tcx, Evaluator<'mir, 'tcx>>,
+) {
+    let mut suppressed: Vec<(Span, &str, u64)> = ecx
+        .machine
+        .diagnostic_counts
+        .0
+        .borrow()
+        .iter()
+        .filter(|(_, (_, count))| *count > MAX_REPEATED_DIAGNOSTICS)
+        .map(|((_, span), (title, count))| (*span, *title, count - MAX_REPEATED_DIAGNOSTICS))
+        .collect();
+    suppressed.sort();
+    for (span, title, count) in suppressed {
+        ecx.tcx.sess.span_note_without_error(
+            span,
+            &format!("`{}` occurred {} more times here, which were not shown", title, count),
+        );
+    }
+}
+
+/// Report an error or note (depending on the `error` argument) with the given stacktrace and
+/// error code. Also emits a full stacktrace of the interpreter stack.
 /// We want to present a multi-line span message for some errors. Diagnostics do not support this
 /// directly, so we pass the lines as a `Vec<String>` and display each line after the first with an
 /// additional `span_label` or `note` call.
 fn report_msg<'mir, 'tcx>(
     ecx: &InterpCx<'mir, 'tcx, Evaluator<'mir, 'tcx>>,
     diag_level: DiagLevel,
+    code: Option<DiagnosticId>,
     title: &str,
     span_msg: Vec<String>,
     mut helps: Vec<(Option<SpanData>, String)>,
     stacktrace: &[FrameInfo<'tcx>],
 ) {
-    let span = stacktrace.first().map_or(DUMMY_SP, |fi| fi.span);
+    // Point at the innermost local frame if that was requested, since that is usually where the
+    // bug is, even if the problem surfaced inside the standard library.
+    let user_frame = match ecx.machine.error_span {
+        ErrorSpan::Std => None,
+        ErrorSpan::User =>
+            stacktrace.iter().position(|frame| ecx.machine.is_local(frame)).filter(|&idx| idx > 0),
+    };
+    let span = stacktrace.get(user_frame.unwrap_or(0)).map_or(DUMMY_SP, |fi| fi.span);
     let sess = ecx.tcx.sess;
     let mut err = match diag_level {
         DiagLevel::Error => sess.struct_span_err(span, title).forget_guarantee(),
         DiagLevel::Warning => sess.struct_span_warn(span, title),
         DiagLevel::Note => sess.diagnostic().span_note_diag(span, title),
     };
+    if let Some(code) = code {
+        err.code(code);
+    }
 
     // Show main message.
     if span != DUMMY_SP {
@@ -290,6 +791,17 @@ fn report_msg<'mir, 'tcx>(
         }
         err.note("(no span available)");
     }
+    if user_frame.is_some() {
+        let frame = &stacktrace[0];
+        err.span_note(
+            frame.span,
+            &format!(
+                "this happened inside `{}`, which was called from the code above",
+                frame.instance
+            ),
+        );
+        err.note("pass `-Zmiri-error-span=std` to point at this location instead");
+    }
 
     // Show help messages.
     if !helps.is_empty() {
@@ -306,8 +818,9 @@ fn report_msg<'mir, 'tcx>(
     // Add backtrace
     for (idx, frame_info) in stacktrace.iter().enumerate() {
         let is_local = ecx.machine.is_local(frame_info);
-        // No span for non-local frames and the first frame (which is the error site).
-        if is_local && idx > 0 {
+        // No span for non-local frames, the first frame (which is the error site), and the frame
+        // that the main span points to.
+        if is_local && idx > 0 && Some(idx) != user_frame {
             err.span_note(frame_info.span, &frame_info.to_string());
         } else {
             err.note(&frame_info.to_string());
@@ -329,39 +842,52 @@ pub fn register_diagnostic(e: NonHaltingDiagnostic) {
 
 /// Remember enough about the topmost frame so that we can restore the stack
 /// after a step was taken.
-pub struct TopFrameInfo<'tcx> {
+pub struct TopFrameInfo<'mir, 'tcx> {
     stack_size: usize,
     instance: Option<ty::Instance<'tcx>>,
-    span: Span,
+    /// The body and location of the topmost frame. This is recorded on every step,
+    /// so we only compute the actual span if there are diagnostics to report.
+    loc: Option<(&'mir mir::Body<'tcx>, Result<mir::Location, Span>)>,
+}
+
+impl<'mir, 'tcx> TopFrameInfo<'mir, 'tcx> {
+    fn span(&self) -> Span {
+        match self.loc {
+            Some((body, Ok(loc))) => body.source_info(loc).span,
+            Some((_, Err(span))) => span,
+            None => DUMMY_SP,
+        }
+    }
 }
 
 impl<'mir, 'tcx: 'mir> EvalContextExt<'mir, 'tcx> for crate::MiriEvalContext<'mir, 'tcx> {}
 pub trait EvalContextExt<'mir, 'tcx: 'mir>: crate::MiriEvalContextExt<'mir, 'tcx> {
-    fn preprocess_diagnostics(&self) -> TopFrameInfo<'tcx> {
+    fn preprocess_diagnostics(&self) -> TopFrameInfo<'mir, 'tcx> {
         // Ensure we have no lingering diagnostics.
         DIAGNOSTICS.with(|diagnostics| assert!(diagnostics.borrow().is_empty()));
 
         let this = self.eval_context_ref();
         if this.active_thread_stack().is_empty() {
             // Diagnostics can happen even with the empty stack (e.g. deallocation of thread-local statics).
-            return TopFrameInfo { stack_size: 0, instance: None, span: DUMMY_SP };
+            return TopFrameInfo { stack_size: 0, instance: None, loc: None };
         }
         let frame = this.frame();
 
         TopFrameInfo {
             stack_size: this.active_thread_stack().len(),
             instance: Some(frame.instance),
-            span: frame.current_span(),
+            loc: Some((frame.body, frame.current_loc())),
         }
     }
 
-    /// Emit all diagnostics that were registed with `register_diagnostics`
-    fn process_diagnostics(&self, info: TopFrameInfo<'tcx>) {
+    /// Emit all diagnostics that were registed with `register_diagnostics`. Fails if one of them
+    /// is a warning of a kind that was denied.
+    fn process_diagnostics(&self, info: TopFrameInfo<'mir, 'tcx>) -> InterpResult<'tcx> {
         let this = self.eval_context_ref();
         DIAGNOSTICS.with(|diagnostics| {
             let mut diagnostics = diagnostics.borrow_mut();
             if diagnostics.is_empty() {
-                return;
+                return Ok(());
             }
             // We need to fix up the stack trace, because the machine has already
             // stepped to the next statement.
@@ -378,13 +904,13 @@ pub trait EvalContextExt<'mir, 'tcx: 'mir>: crate::MiriEvalContextExt<'mir, 'tcx
                 );
                 let frame_info = FrameInfo {
                     instance: info.instance.unwrap(),
-                    span: info.span,
+                    span: info.span(),
                     lint_root: None,
                 };
                 stacktrace.insert(0, frame_info);
             } else if let Some(instance) = info.instance {
                 // Adjust topmost frame.
-                stacktrace[0].span = info.span;
+                stacktrace[0].span = info.span();
                 assert_eq!(
                     stacktrace[0].instance, instance,
                     "we should not pop and push a frame in one step"
@@ -413,20 +939,119 @@ pub trait EvalContextExt<'mir, 'tcx: 'mir>: crate::MiriEvalContextExt<'mir, 'tcx
                             }
                         },
                     CreatedCallId(id) => format!("function call with id {id}"),
-                    CreatedAlloc(AllocId(id)) => format!("created allocation with id {id}"),
-                    FreedAlloc(AllocId(id)) => format!("freed allocation with id {id}"),
+                    CreatedAlloc(AllocId(id), stable_id) =>
+                        format!("created allocation with id {id} (stable id {stable_id})"),
+                    FreedAlloc(AllocId(id), stable_id) =>
+                        format!("freed allocation with id {id} (stable id {stable_id})"),
                     RejectedIsolatedOp(ref op) =>
                         format!("{op} was made to return an error due to isolation"),
+                    RejectedProcessSpawn(ref op) =>
+                        format!(
+                            "{op} was made to return an error since Miri cannot spawn processes"
+                        ),
+                    Int2Ptr(addr) =>
+                        format!(
+                            "the allocation that the pointer created from address {addr:#x} \
+                            points to was guessed"
+                        ),
+                    UnsupportedPanic(ref msg) =>
+                        format!(
+                            "{msg}; it was turned into a panic by `-Zmiri-panic-on-unsupported`"
+                        ),
+                    WatchedAccess(ref access) => {
+                        let kind = match (access.read, &access.write) {
+                            (true, Some(_)) => "read and written",
+                            (false, Some(_)) => "written",
+                            _ => "read",
+                        };
+                        let mut msg = format!(
+                            "{:?}[{:#x}..{:#x}] was {} by thread {}",
+                            access.alloc_id,
+                            access.range.start.bytes(),
+                            access.range.end().bytes(),
+                            kind,
+                            access.thread.to_u32(),
+                        );
+                        match &access.write {
+                            Some((Some(old), new)) =>
+                                msg.push_str(&format!(": {:02x?} -> {:02x?}", old, new)),
+                            Some((None, new)) => msg.push_str(&format!(": now {:02x?}", new)),
+                            None => {}
+                        }
+                        msg
+                    }
                 };
 
-                let (title, diag_level) = match e {
-                    RejectedIsolatedOp(_) =>
-                        ("operation rejected by isolation", DiagLevel::Warning),
-                    _ => ("tracking was triggered", DiagLevel::Note),
+                let title = match e {
+                    RejectedIsolatedOp(_) => "operation rejected by isolation",
+                    RejectedProcessSpawn(_) => "process spawn rejected",
+                    Int2Ptr(_) => "integer-to-pointer cast",
+                    UnsupportedPanic(_) => "unsupported operation",
+                    WatchedAccess(_) => "watched memory was accessed",
+                    _ => "tracking was triggered",
                 };
+                let (diag_level, code) = match e.warning_kind() {
+                    Some(kind) => {
+                        let code = DiagnosticId::Lint {
+                            name: format!("miri::{}", kind.name()),
+                            has_future_breakage: false,
+                            is_force_warn: false,
+                        };
+                        match this.machine.warning_level(kind) {
+                            WarningLevel::Allow => continue,
+                            WarningLevel::Warn => (DiagLevel::Warning, Some(code)),
+                            WarningLevel::Deny => {
+                                report_msg(
+                                    this,
+                                    DiagLevel::Error,
+                                    Some(code),
+                                    title,
+                                    vec![msg.clone()],
+                                    vec![(
+                                        None,
+                                        format!(
+                                            "this warning was turned into an error by \
+                                            `-Zmiri-deny={}`",
+                                            kind.name()
+                                        ),
+                                    )],
+                                    &stacktrace,
+                                );
+                                // Dropping the iterator discards the remaining diagnostics.
+                                throw_machine_stop!(TerminationInfo::DeniedWarning {
+                                    kind,
+                                    msg: format!("{}: {}", title, msg),
+                                });
+                            }
+                        }
+                    }
+                    None => (DiagLevel::Note, None),
+                };
+
+                // Do not flood the output with warnings that are emitted in a loop. Tracking and
+                // watchpoint notes were explicitly asked for, so they are always shown.
+                let mut helps = vec![];
+                if e.warning_kind().is_some() {
+                    let span = stacktrace.first().map_or(DUMMY_SP, |frame| frame.span);
+                    let count = this.machine.diagnostic_counts.record(&e, span, title);
+                    if count > MAX_REPEATED_DIAGNOSTICS {
+                        continue;
+                    }
+                    if count == MAX_REPEATED_DIAGNOSTICS {
+                        helps.push((
+                            None,
+                            format!(
+                                "this was reported {} times here; further occurrences will only \
+                                be counted",
+                                count
+                            ),
+                        ));
+                    }
+                }
 
-                report_msg(this, diag_level, title, vec![msg], vec![], &stacktrace);
+                report_msg(this, diag_level, code, title, vec![msg], helps, &stacktrace);
             }
-        });
+            Ok(())
+        })
     }
 }
@@ -0,0 +1,55 @@
+//! Stable codes for the most common kinds of errors that Miri reports. `miri --explain <code>`
+//! prints a detailed explanation of each code, like `rustc --explain` does for compiler errors.
+
+use crate::*;
+
+/// The code of memory leak errors.
+pub const MEMORY_LEAK: &str = "MIRI0005";
+
+/// All error codes, with their explanations in Markdown.
+pub const ERROR_CODES: &[(&str, &str)] = &[
+    ("MIRI0001", include_str!("error_codes/MIRI0001.md")),
+    ("MIRI0002", include_str!("error_codes/MIRI0002.md")),
+    ("MIRI0003", include_str!("error_codes/MIRI0003.md")),
+    ("MIRI0004", include_str!("error_codes/MIRI0004.md")),
+    (MEMORY_LEAK, include_str!("error_codes/MIRI0005.md")),
+    ("MIRI0006", include_str!("error_codes/MIRI0006.md")),
+    ("MIRI0007", include_str!("error_codes/MIRI0007.md")),
+    ("MIRI0008", include_str!("error_codes/MIRI0008.md")),
+    ("MIRI0009", include_str!("error_codes/MIRI0009.md")),
+];
+
+/// Returns the explanation of `code`, which is matched case-insensitively.
+pub fn explain_error_code(code: &str) -> Option<&'static str> {
+    ERROR_CODES
+        .iter()
+        .find(|(known, _)| known.eq_ignore_ascii_case(code))
+        .map(|(_, explanation)| *explanation)
+}
+
+/// Returns the code of `e`, if it is one of the errors that have a code.
+pub fn error_code(e: &InterpError<'_>) -> Option<&'static str> {
+    use InterpError::*;
+    use UndefinedBehaviorInfo::*;
+    let code = match e {
+        MachineStop(info) =>
+            match info.downcast_ref::<TerminationInfo>()? {
+                TerminationInfo::ExperimentalUb { .. } => "MIRI0001",
+                TerminationInfo::UnsupportedInIsolation(_) => "MIRI0004",
+                TerminationInfo::Deadlock | TerminationInfo::SpinDeadlock { .. } => "MIRI0006",
+                _ => return None,
+            },
+        UndefinedBehavior(Ub(msg)) if msg.starts_with("Data race detected") => "MIRI0002",
+        UndefinedBehavior(InvalidUninitBytes(_)) => "MIRI0003",
+        UndefinedBehavior(
+            PointerUseAfterFree(_) | PointerOutOfBounds { .. } | DanglingIntPointer(..),
+        ) => "MIRI0007",
+        UndefinedBehavior(AlignmentCheckFailed { .. }) => "MIRI0008",
+        Unsupported(UnsupportedOpInfo::Unsupported(msg))
+            if msg.starts_with("can't call foreign function")
+                || msg.starts_with("can't call (diverging) foreign function") =>
+            "MIRI0009",
+        _ => return None,
+    };
+    Some(code)
+}
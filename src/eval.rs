@@ -5,6 +5,7 @@ use std::iter;
 
 use log::info;
 
+use rustc_errors::DiagnosticId;
 use rustc_hir::def_id::DefId;
 use rustc_middle::ty::{
     self,
@@ -15,8 +16,12 @@ use rustc_target::spec::abi::Abi;
 
 use rustc_session::config::EntryFnType;
 
+use std::cell::RefCell;
 use std::collections::HashSet;
+use std::rc::Rc;
+use std::sync::Arc;
 
+use crate::error_codes::MEMORY_LEAK;
 use crate::*;
 
 #[derive(Copy, Clone, Debug, PartialEq)]
@@ -58,6 +63,26 @@ pub enum IsolatedOp {
     Allow,
 }
 
+/// What to do when the program tries to create a process.
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub enum ProcessSpawn {
+    /// Make the operation fail with `ENOSYS`, and warn about it.
+    Error,
+    /// Make the operation fail with `ENOSYS` without warning.
+    RejectSilent,
+}
+
+/// Whether reallocations keep the address of the allocation.
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub enum ReallocPolicy {
+    /// Randomly (based on the seed) resize in place when there is room, or move.
+    Random,
+    /// Always move the allocation to a new address.
+    Move,
+    /// Resize in place whenever there is room.
+    InPlace,
+}
+
 #[derive(Copy, Clone, PartialEq, Eq)]
 pub enum BacktraceStyle {
     /// Prints a terser backtrace which ideally only contains relevant information.
@@ -68,6 +93,61 @@ pub enum BacktraceStyle {
     Off,
 }
 
+/// Which frame the primary span of an error points to.
+#[derive(Copy, Clone, PartialEq, Eq)]
+pub enum ErrorSpan {
+    /// The frame the error occurred in, even if it is part of the standard library.
+    Std,
+    /// The innermost frame of a local crate, if there is one. Errors inside the standard library
+    /// are usually caused by the code that called it.
+    User,
+}
+
+/// How much detail to show about leaked memory.
+#[derive(Copy, Clone, PartialEq, Eq)]
+pub enum LeakReport {
+    /// A summary of the leaks, grouped by the function that created them, followed by the
+    /// contents of each leaked allocation.
+    Full,
+    /// Only the summary.
+    Summary,
+    /// Only the error.
+    None,
+}
+
+/// What to do about threads that are still running (or were never joined) when the main thread
+/// terminates.
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub enum ThreadLeakCheck {
+    /// Report an error.
+    Error,
+    /// Report a warning, but do not fail the execution.
+    Warn,
+    /// Do not report anything.
+    Allow,
+    /// Like `Error`, but also report threads that were detached instead of being joined.
+    RequireJoin,
+}
+
+/// The built-in scheduling policies, see `crate::scheduling` for details.
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub enum SchedulingPolicyKind {
+    /// Run a thread until it blocks or yields.
+    Cooperative,
+    /// Switch to the next thread every now and then.
+    RoundRobin,
+    /// Run the enabled thread with the highest priority; priorities are random, and the running
+    /// thread is demoted at random synchronization points.
+    Priority,
+    /// Preempt threads at random points, and switch to random threads.
+    Random,
+    /// Like `Random`, but with at most the given number of preemptions.
+    PreemptionBounded(u64),
+    /// Run the program once for every interleaving of atomic and synchronization operations
+    /// with at most the given number of preemptions, until one execution fails.
+    Exhaustive(u64),
+}
+
 /// Configuration needed to spawn a Miri instance.
 #[derive(Clone)]
 pub struct MiriConfig {
@@ -83,8 +163,12 @@ pub struct MiriConfig {
     pub check_abi: bool,
     /// Action for an op requiring communication with the host.
     pub isolated_op: IsolatedOp,
+    /// How attempts to spawn a process are handled.
+    pub process_spawn: ProcessSpawn,
     /// Determines if memory leaks should be ignored.
     pub ignore_leaks: bool,
+    /// How much detail to show about leaked memory.
+    pub leak_report: LeakReport,
     /// Environment variables that should always be isolated from the host.
     pub excluded_env_vars: Vec<String>,
     /// Environment variables that should always be forwarded from the host.
@@ -92,6 +176,7 @@ pub struct MiriConfig {
     /// Command-line arguments passed to the interpreted program.
     pub args: Vec<String>,
     /// The seed to use when non-determinism or randomness are required (e.g. ptr-to-int cast, `getrandom()`).
+    /// If it is set, it also determines all entropy the program sees, even without isolation.
     pub seed: Option<u64>,
     /// The stacked borrows pointer ids to report about
     pub tracked_pointer_tags: HashSet<PtrId>,
@@ -99,23 +184,122 @@ pub struct MiriConfig {
     pub tracked_call_ids: HashSet<CallId>,
     /// The allocation ids to report about.
     pub tracked_alloc_ids: HashSet<AllocId>,
+    /// The allocations to report about, identified by their stable ID.
+    pub tracked_stable_alloc_ids: HashSet<StableAllocId>,
+    /// Whether to print which stable ID each allocation ID corresponds to at the end.
+    pub print_alloc_ids: bool,
+    /// The memory whose accesses are reported.
+    pub watchpoints: Vec<(AllocId, AllocRange)>,
     /// Whether to track raw pointers in stacked borrows.
     pub tag_raw: bool,
+    /// If `Some`, trace the Stacked Borrows operations on this allocation, and write the trace to
+    /// this file at the end.
+    pub sb_trace: Option<(AllocId, String)>,
     /// Determine if data race detection should be enabled
     pub data_race_detector: bool,
     /// Rate of spurious failures for compare_exchange_weak atomic operations,
     /// between 0.0 and 1.0, defaulting to 0.8 (80% chance of failure).
     pub cmpxchg_weak_failure_rate: f64,
+    /// How many times threads may yield to each other without making progress before we report
+    /// a spin deadlock. `None` disables the check.
+    pub spin_loop_limit: Option<u64>,
+    /// The maximum number of threads (including the main thread) that may be alive at the same
+    /// time. `None` means there is no limit.
+    pub max_threads: Option<u64>,
+    /// Creates the memory observers of each execution; see `crate::observer`.
+    pub memory_observers: Vec<ObserverFactory>,
+    /// Providers of foreign items that are asked before Miri's own shims.
+    pub foreign_item_providers: Vec<Arc<dyn ForeignItemProvider>>,
+    /// Track all live allocations, so that `Report::final_state` can be filled in.
+    pub collect_final_state: bool,
+    /// Record where heap allocations were created, so that leaked allocations in
+    /// `Report::final_state` come with a backtrace, and errors about an allocation point to where
+    /// it was created.
+    pub record_alloc_backtraces: bool,
+    /// Track where the values in memory were written, so that errors about invalid values can
+    /// point to where the value came from.
+    pub track_value_origins: bool,
+    /// Record where pointers are cast to integers, so that errors about pointers cast back from
+    /// integers can list the casts that could have provided their provenance.
+    pub track_exposures: bool,
+    /// How to report threads that are still running when the main thread terminates. Has no
+    /// effect if `ignore_leaks` is set.
+    pub thread_leak_check: ThreadLeakCheck,
+    /// If `Some`, the program is expected to stop with an error whose message contains this
+    /// string. Such an error makes the run succeed, and every other outcome makes it fail.
+    pub expected_error: Option<String>,
+    /// The scheduling policy used to decide which thread runs next.
+    pub scheduling_policy: SchedulingPolicyKind,
+    /// If `Some`, record the scheduling decisions and write them to this file if the program
+    /// fails.
+    pub record_schedule: Option<String>,
+    /// If `Some`, replay these scheduling decisions (see `read_schedule`) instead of using
+    /// `scheduling_policy`.
+    pub replay_schedule: Option<Vec<ScheduleEvent>>,
+    /// If `Some`, creates the scheduling policy of each execution instead of
+    /// `scheduling_policy`. Replaying a schedule and exhaustive exploration take precedence.
+    pub custom_scheduling_policy: Option<PolicyFactory>,
     /// If `Some`, enable the `measureme` profiler, writing results to a file
     /// with the specified prefix.
     pub measureme_out: Option<String>,
+    /// If `Some`, count the interpreter steps executed in each function, and write a report of
+    /// the most expensive functions to the given file at exit.
+    pub profile_interpreted_out: Option<String>,
+    /// Make the system time occasionally jump backward or forward.
+    pub time_anomalies: bool,
+    /// If `Some`, time is frozen at this many seconds after the Unix epoch.
+    pub freeze_time: Option<u64>,
+    /// Deliver `SIGALRM` when a timer armed with `alarm` or `setitimer` expires.
+    pub deliver_timer_signals: bool,
+    /// The offset of the local time zone from UTC, in seconds.
+    pub utc_offset: i64,
+    /// The host name reported by `uname` and `gethostname`.
+    pub hostname: String,
+    /// If `Some`, the home directory of the user with isolation, instead of a default for the
+    /// target.
+    pub home_dir: Option<String>,
+    /// If `Some`, the directory for temporary files on Windows targets with isolation, instead of
+    /// `C:\Temp`.
+    pub temp_dir: Option<String>,
+    /// If `Some`, the path of the executable on Windows targets, instead of
+    /// `C:\miri\<program name>.exe`.
+    pub exe_path: Option<String>,
+    /// If `Some`, the kernel release reported by `uname` instead of a default for the target.
+    pub kernel_release: Option<String>,
+    /// The standard streams (as file descriptors 0, 1 and 2) that are reported to be a terminal.
+    pub pretend_tty: Vec<i32>,
+    /// The size of the pretended terminal, as columns and rows.
+    pub tty_size: (u16, u16),
+    /// Make `errno` uninitialized after successful calls to shims.
+    pub strict_errno: bool,
+    /// Print statistics about the execution at exit.
+    pub stats: bool,
+    /// Whether `realloc` and `__rust_realloc` resize allocations in place.
+    pub realloc_policy: ReallocPolicy,
+    /// If `Some`, write the result of the analysis to this file in the SARIF format.
+    pub sarif_out: Option<String>,
+    /// If `Some`, write the foreign functions that the program called but Miri does not support
+    /// to a new file in this directory, as JSON. `cargo miri` uses this to summarize them.
+    pub unsupported_shims_dir: Option<String>,
+    /// If `Some`, write the state of the program to this file if it stops with an error.
+    pub core_dump: Option<String>,
+    /// Stop in an interactive debugger before the first step, at breakpoints, and on errors.
+    pub debugger: bool,
     /// Panic when unsupported functionality is encountered.
     pub panic_on_unsupported: bool,
     /// Which style to use for printing backtraces.
     pub backtrace_style: BacktraceStyle,
+    /// Which frame the primary span of diagnostics points to.
+    pub error_span: ErrorSpan,
+    /// The levels of kinds of warnings, overriding their defaults. Later entries take precedence.
+    pub warning_levels: Vec<(WarningKind, WarningLevel)>,
     /// Whether to enforce "strict provenance" rules. Enabling this means int2ptr casts return
     /// pointers with an invalid provenance, i.e., not valid for any memory access.
     pub strict_provenance: bool,
+    /// After running the program, evaluate the initializers of its constants and the calls of its
+    /// `const fn`s again and report the ones whose value differs between compile time and run
+    /// time.
+    pub compare_ctfe: bool,
 }
 
 impl Default for MiriConfig {
@@ -127,7 +311,9 @@ impl Default for MiriConfig {
             check_number_validity: false,
             check_abi: true,
             isolated_op: IsolatedOp::Reject(RejectOpWith::Abort),
+            process_spawn: ProcessSpawn::Error,
             ignore_leaks: false,
+            leak_report: LeakReport::Full,
             excluded_env_vars: vec![],
             forwarded_env_vars: vec![],
             args: vec![],
@@ -135,13 +321,53 @@ impl Default for MiriConfig {
             tracked_pointer_tags: HashSet::default(),
             tracked_call_ids: HashSet::default(),
             tracked_alloc_ids: HashSet::default(),
+            tracked_stable_alloc_ids: HashSet::default(),
+            print_alloc_ids: false,
+            watchpoints: vec![],
             tag_raw: false,
+            sb_trace: None,
             data_race_detector: true,
             cmpxchg_weak_failure_rate: 0.8,
+            spin_loop_limit: Some(100_000),
+            max_threads: None,
+            memory_observers: vec![],
+            foreign_item_providers: vec![],
+            collect_final_state: false,
+            record_alloc_backtraces: false,
+            track_value_origins: false,
+            track_exposures: false,
+            thread_leak_check: ThreadLeakCheck::Error,
+            expected_error: None,
+            scheduling_policy: SchedulingPolicyKind::Cooperative,
+            record_schedule: None,
+            replay_schedule: None,
+            custom_scheduling_policy: None,
             measureme_out: None,
+            profile_interpreted_out: None,
+            time_anomalies: false,
+            freeze_time: None,
+            deliver_timer_signals: false,
+            utc_offset: 0,
+            hostname: shims::posix::DEFAULT_HOSTNAME.to_owned(),
+            home_dir: None,
+            temp_dir: None,
+            exe_path: None,
+            kernel_release: None,
+            pretend_tty: vec![],
+            tty_size: (80, 24),
+            strict_errno: false,
+            stats: false,
+            realloc_policy: ReallocPolicy::Random,
+            sarif_out: None,
+            unsupported_shims_dir: None,
+            core_dump: None,
+            debugger: false,
             panic_on_unsupported: false,
             backtrace_style: BacktraceStyle::Short,
+            error_span: ErrorSpan::User,
+            warning_levels: vec![],
             strict_provenance: false,
+            compare_ctfe: false,
         }
     }
 }
@@ -168,7 +394,7 @@ pub fn create_ecx<'mir, 'tcx: 'mir>(
     Evaluator::late_init(&mut ecx, config)?;
 
     // Make sure we have MIR. We check MIR for some stable monomorphic function in libcore.
-    let sentinel = ecx.resolve_path(&["core", "ascii", "escape_default"]);
+    let sentinel = ecx.resolve_path(&["core", "ascii", "escape_default"])?;
     if !tcx.is_mir_available(sentinel.def.def_id()) {
         tcx.sess.fatal("the current sysroot was built without `-Zalways-encode-mir`. Use `cargo miri setup` to prepare a sysroot that is suitable for Miri.");
     }
@@ -292,16 +518,97 @@ pub fn eval_entry<'tcx>(
     entry_type: EntryFnType,
     config: MiriConfig,
 ) -> Option<i64> {
-    // Copy setting before we move `config`.
+    let sarif_out = config.sarif_out.clone();
+    let unsupported_shims_dir = config.unsupported_shims_dir.clone();
+    let report = run_analysis(tcx, entry_id, entry_type, config);
+    if let Some(path) = sarif_out {
+        if let Err(err) = write_sarif(&report, &path) {
+            tcx.sess.warn(&format!("failed to write the SARIF report: {}", err));
+        }
+    }
+    if let Some(dir) = unsupported_shims_dir {
+        if let Err(err) = write_unsupported_shims(&report, &dir) {
+            tcx.sess.warn(&format!("failed to write the unsupported foreign functions: {}", err));
+        }
+    }
+    report.return_code()
+}
+
+/// Evaluates the entry function specified by `entry_id`, and returns what happened.
+///
+/// This is the entry point for tools that embed Miri: diagnostics are emitted through `tcx.sess`
+/// like for the `miri` binary, and are also summarized in the returned `Report`.
+pub fn run_analysis<'tcx>(
+    tcx: TyCtxt<'tcx>,
+    entry_id: DefId,
+    entry_type: EntryFnType,
+    mut config: MiriConfig,
+) -> Report {
+    let bound = match config.scheduling_policy {
+        SchedulingPolicyKind::Exhaustive(bound) => bound,
+        _ => return eval_entry_once(tcx, entry_id, entry_type, &config, None),
+    };
+
+    // Explore the interleavings by running the program from scratch for each of them.
+    let exploration = Rc::new(RefCell::new(Exploration::new(bound)));
+    let mut executions = 1;
+    loop {
+        let policy = Box::new(Explore(Rc::clone(&exploration)));
+        let mut report = eval_entry_once(tcx, entry_id, entry_type, &config, Some(policy));
+        report.statistics.executions = executions;
+        // The constants and `const fn` calls are compared once, not for every execution.
+        config.compare_ctfe = false;
+        // Stop at the first execution that failed, either with an error or with a non-zero exit
+        // code (e.g. because of a panic).
+        if report.return_code() != Some(0) {
+            tcx.sess.note_without_error(&format!(
+                "execution {} of the exhaustive exploration failed",
+                executions
+            ));
+            return report;
+        }
+        if !exploration.borrow_mut().next_execution() {
+            tcx.sess.note_without_error(&format!(
+                "explored {} executions with at most {} preemptions",
+                executions, bound
+            ));
+            return report;
+        }
+        executions += 1;
+    }
+}
+
+/// Runs the program once. If `policy` is set, it replaces the policy selected by `config`.
+fn eval_entry_once<'tcx>(
+    tcx: TyCtxt<'tcx>,
+    entry_id: DefId,
+    entry_type: EntryFnType,
+    config: &MiriConfig,
+    policy: Option<Box<dyn SchedulingPolicy>>,
+) -> Report {
     let ignore_leaks = config.ignore_leaks;
+    let mut report = Report::default();
+    report.statistics.executions = 1;
 
-    let (mut ecx, ret_place) = match create_ecx(tcx, entry_id, entry_type, &config) {
+    let (mut ecx, ret_place) = match create_ecx(tcx, entry_id, entry_type, config) {
         Ok(v) => v,
         Err(err) => {
             err.print_backtrace();
-            panic!("Miri initialization error: {}", err.kind())
+            let msg = format!("Miri initialization error: {}", err.kind());
+            tcx.sess.err(&msg);
+            report.error = Some(ErrorReport {
+                kind: ErrorKind::Unsupported,
+                code: None,
+                message: msg,
+                stacktrace: vec![],
+                related: vec![],
+            });
+            return report;
         }
     };
+    if let Some(policy) = policy {
+        ecx.machine.threads.set_policy(policy);
+    }
 
     // Perform the main execution.
     let res: InterpResult<'_, i64> = (|| {
@@ -310,14 +617,19 @@ pub fn eval_entry<'tcx>(
             let info = ecx.preprocess_diagnostics();
             match ecx.schedule()? {
                 SchedulingAction::ExecuteStep => {
+                    report.statistics.steps += 1;
+                    ecx.count_cpu_step();
+                    ecx.profile_step();
+                    ecx.debugger_step()?;
+                    ecx.deliver_timer_signals()?;
+                    if ecx.machine.intptrcast.borrow().tracks_exposures() {
+                        ecx.record_exposure()?;
+                    }
+                    ecx.start_value_origin_step();
+                    ecx.start_sb_trace_step();
                     assert!(ecx.step()?, "a terminated thread was scheduled for execution");
                 }
                 SchedulingAction::ExecuteTimeoutCallback => {
-                    assert!(
-                        ecx.machine.communicate(),
-                        "scheduler callbacks require disabled isolation, but the code \
-                        that created the callback did not check it"
-                    );
                     ecx.run_timeout_callback()?;
                 }
                 SchedulingAction::ExecuteDtors => {
@@ -330,41 +642,169 @@ pub fn eval_entry<'tcx>(
                     break;
                 }
             }
-            ecx.process_diagnostics(info);
+            if let Some(err) = ecx.machine.deferred_error.take() {
+                return Err(err);
+            }
+            ecx.report_watched_accesses()?;
+            ecx.process_diagnostics(info)?;
         }
         let return_code = ecx.read_scalar(&ret_place.into())?.to_machine_isize(&ecx)?;
         Ok(return_code)
     })();
+    // A step that failed may have done so only because of the dummy value that a machine hook
+    // returned after an error, so the hook's error is the one to report.
+    let res = match ecx.machine.deferred_error.take() {
+        Some(err) => Err(err),
+        None => res,
+    };
 
     // Machine cleanup.
     EnvVars::cleanup(&mut ecx).unwrap();
+    report.statistics.threads = ecx.get_total_thread_count();
+    report.unsupported_shims =
+        ecx.machine.unsupported_shims.iter().map(|(name, &calls)| (name.clone(), calls)).collect();
+    let mut roots = ecx.machine.static_roots.clone();
+    roots.extend(ecx.machine.threads.remaining_thread_roots());
+    // This is also used by the leak check below.
+    let final_state = FinalState::collect(&ecx, &roots);
+    if config.collect_final_state {
+        report.final_state = final_state.clone();
+    }
+    report_suppressed_diagnostics(&ecx);
+
+    // This runs code after the final state was collected, so that it does not affect the leak
+    // check.
+    if ecx.machine.ctfe_comparison.is_some() && res.is_ok() {
+        compare_ctfe(&mut ecx);
+    }
+
+    if config.print_alloc_ids {
+        report_alloc_ids(&ecx);
+    }
+
+    if let Some(stats) = &ecx.machine.stats {
+        report.statistics.allocations = stats.allocations();
+        report.statistics.deallocations = stats.deallocations();
+        report.statistics.peak_memory = stats.peak_live_bytes();
+        eprint!("{}", stats.report(report.statistics.steps));
+    }
+
+    if let Some(stacked_borrows) = &ecx.machine.stacked_borrows {
+        if let Err(err) = stacked_borrows.borrow().write_trace(tcx) {
+            tcx.sess.warn(&format!("failed to write the Stacked Borrows trace: {}", err));
+        }
+    }
+
+    if let Some(profiler) = &ecx.machine.step_profiler {
+        if let Err(err) = profiler.write_report(&ecx.machine.threads) {
+            tcx.sess.warn(&format!("failed to write the step profile: {}", err));
+        }
+    }
 
     // Process the result.
     match res {
         Ok(return_code) => {
+            report.exit_code = Some(return_code);
+            if let Some(pattern) = &ecx.machine.expected_error {
+                report.error = Some(report_missing_expected_error(&ecx, pattern));
+                return report;
+            }
             if !ignore_leaks {
                 // Check for thread leaks.
-                if !ecx.have_all_terminated() {
-                    tcx.sess.err(
-                        "the main thread terminated without waiting for all remaining threads",
-                    );
-                    tcx.sess.note_without_error("pass `-Zmiri-ignore-leaks` to disable this check");
-                    return None;
+                let require_join = config.thread_leak_check == ThreadLeakCheck::RequireJoin;
+                let leaked_threads = ecx.machine.threads.leaked_threads(require_join);
+                report.leaks.leaked_threads =
+                    leaked_threads.iter().map(|(name, ..)| name.clone()).collect();
+                if !leaked_threads.is_empty() {
+                    let msg =
+                        "the main thread terminated without waiting for all remaining threads";
+                    match config.thread_leak_check {
+                        ThreadLeakCheck::Error | ThreadLeakCheck::RequireJoin => {
+                            tcx.sess.err(msg);
+                            report_leaked_threads(&ecx, leaked_threads);
+                            tcx.sess.note_without_error(
+                                "pass `-Zmiri-thread-leaks=warn` or `-Zmiri-ignore-leaks` to \
+                                disable this check",
+                            );
+                            report.error = Some(ErrorReport {
+                                kind: ErrorKind::ThreadLeak,
+                                code: None,
+                                message: msg.to_string(),
+                                stacktrace: vec![],
+                                related: vec![],
+                            });
+                            return report;
+                        }
+                        ThreadLeakCheck::Warn => {
+                            tcx.sess.warn(msg);
+                            report_leaked_threads(&ecx, leaked_threads);
+                        }
+                        ThreadLeakCheck::Allow => {}
+                    }
                 }
                 // Check for memory leaks.
                 info!("Additonal static roots: {:?}", ecx.machine.static_roots);
-                let leaks = ecx.leak_report(&ecx.machine.static_roots);
+                // Threads that are still running were killed by the process exit, so whatever
+                // they are still using is not leaked (`roots` includes them).
+                let final_state =
+                    final_state.expect("the leak check requires tracking all live allocations");
+                let leaks = final_state.leaked_allocations().count();
+                report.leaks.leaked_allocations = leaks;
                 if leaks != 0 {
-                    tcx.sess.err("the evaluated program leaked memory");
-                    tcx.sess.note_without_error("pass `-Zmiri-ignore-leaks` to disable this check");
+                    if config.leak_report != LeakReport::None {
+                        report_leaked_allocations(
+                            &ecx,
+                            &final_state,
+                            config.leak_report == LeakReport::Full,
+                        );
+                    }
+                    let msg = "the evaluated program leaked memory";
+                    let mut err = tcx.sess.struct_err(msg);
+                    err.code(DiagnosticId::Error(MEMORY_LEAK.to_string()));
+                    err.note("pass `-Zmiri-ignore-leaks` to disable this check");
+                    err.help(&format!(
+                        "for more information about this error, try `miri --explain {}`",
+                        MEMORY_LEAK
+                    ));
+                    err.emit();
                     // Ignore the provided return code - let the reported error
                     // determine the return code.
-                    return None;
+                    report.error = Some(ErrorReport {
+                        kind: ErrorKind::MemoryLeak,
+                        code: Some(MEMORY_LEAK),
+                        message: msg.to_string(),
+                        stacktrace: vec![],
+                        related: vec![],
+                    });
+                }
+            }
+            report
+        }
+        Err(e) => {
+            // Only concurrent programs have interesting schedules.
+            if let Some(path) = &config.record_schedule {
+                if ecx.get_total_thread_count() > 1 {
+                    match ecx.machine.threads.write_schedule(path) {
+                        Ok(()) =>
+                            tcx.sess.note_without_error(&format!(
+                                "the thread schedule was written to `{}`; pass \
+                                `-Zmiri-replay-schedule={}` to replay it",
+                                path, path
+                            )),
+                        Err(err) =>
+                            tcx.sess.warn(&format!("failed to write the thread schedule: {}", err)),
+                    }
+                }
+            }
+            match report_error(&ecx, e) {
+                Ok(exit_code) => report.exit_code = Some(exit_code),
+                Err(error) => {
+                    report.error = Some(error);
+                    ecx.debugger_after_error();
                 }
             }
-            Some(return_code)
+            report
         }
-        Err(e) => report_error(&ecx, e),
     }
 }
 
@@ -0,0 +1,203 @@
+//! The state of the machine after the program terminated, for tools that embed Miri, and the
+//! leak check.
+//!
+//! Collecting this requires tracking all live allocations, so it is only done if
+//! `MiriConfig::collect_final_state` is set or the leak check is enabled.
+
+use rustc_data_structures::fx::{FxHashMap, FxHashSet};
+use rustc_hir::def_id::DefId;
+use rustc_middle::mir::interpret::GlobalAlloc;
+use rustc_middle::ty;
+use rustc_target::abi::Align;
+
+use crate::*;
+
+/// What the machine remembers about a live allocation.
+#[derive(Debug)]
+pub struct AllocationInfo<'tcx> {
+    pub kind: MemoryKind<MiriMemoryKind>,
+    /// For heap allocations, the innermost function of a local crate (or the innermost function,
+    /// if none is local) that was executing when the allocation was created. This is only
+    /// recorded if `MiriConfig::collect_final_state` is set or the leaks are grouped by it.
+    pub site: Option<ty::Instance<'tcx>>,
+    /// Where the allocation was created, if `MiriConfig::record_alloc_backtraces` is set and
+    /// this is a heap allocation.
+    pub backtrace: Option<Vec<FrameInfo<'tcx>>>,
+}
+
+impl<'tcx> AllocationInfo<'tcx> {
+    /// Describes an allocation of `kind` that is being created by the active thread.
+    pub fn new<'mir>(ecx: &MiriEvalContext<'mir, 'tcx>, kind: MemoryKind<MiriMemoryKind>) -> Self {
+        if !Self::is_heap(kind) {
+            return AllocationInfo { kind, site: None, backtrace: None };
+        }
+        let site = if ecx.machine.record_alloc_sites {
+            let stack = ecx.active_thread_stack();
+            stack
+                .iter()
+                .rev()
+                .map(|frame| frame.instance)
+                .find(|instance| ecx.machine.is_local_def(instance.def_id()))
+                .or_else(|| stack.last().map(|frame| frame.instance))
+        } else {
+            None
+        };
+        let backtrace = ecx.machine.record_alloc_backtraces.then(|| ecx.generate_stacktrace());
+        AllocationInfo { kind, site, backtrace }
+    }
+
+    /// Whether allocations of `kind` are heap allocations, whose backtraces are worth recording.
+    pub fn is_heap(kind: MemoryKind<MiriMemoryKind>) -> bool {
+        matches!(
+            kind,
+            MemoryKind::Machine(
+                MiriMemoryKind::Rust
+                    | MiriMemoryKind::C
+                    | MiriMemoryKind::WinHeap
+                    | MiriMemoryKind::Mmap
+            )
+        )
+    }
+}
+
+/// An allocation that was neither freed nor reachable from static memory when the program
+/// terminated.
+#[derive(Debug, Clone)]
+pub struct LeakedAllocation {
+    pub id: AllocId,
+    pub kind: MemoryKind<MiriMemoryKind>,
+    /// The contents of the allocation. Uninitialized bytes and the bytes of pointers have
+    /// unspecified values.
+    pub bytes: Vec<u8>,
+    pub align: Align,
+    /// The function that created the allocation; see `AllocationInfo::site`.
+    pub site: Option<String>,
+    /// Where the allocation was created, innermost frame first, if it was recorded.
+    pub backtrace: Option<Vec<ReportFrame>>,
+}
+
+/// Leaked allocations of the same kind that were created by the same function.
+#[derive(Debug, Clone)]
+pub struct LeakGroup {
+    pub site: Option<String>,
+    pub kind: MemoryKind<MiriMemoryKind>,
+    pub count: usize,
+    /// The total size of the allocations.
+    pub bytes: u64,
+}
+
+/// The final state of the machine.
+#[derive(Debug, Clone, Default)]
+pub struct FinalState {
+    leaked: Vec<LeakedAllocation>,
+    /// The final contents of the statics that the program accessed.
+    statics: FxHashMap<DefId, Vec<u8>>,
+    /// The open file descriptors, with a description of what they refer to.
+    file_descriptors: Vec<(i32, String)>,
+}
+
+impl FinalState {
+    /// Collects the final state of `ecx`. Allocations that are reachable from `roots` or from
+    /// global memory are not considered leaked.
+    pub fn collect<'mir, 'tcx>(
+        ecx: &MiriEvalContext<'mir, 'tcx>,
+        roots: &[AllocId],
+    ) -> Option<Self> {
+        // Looking at global allocations can create new allocations, so we cannot keep the map
+        // borrowed.
+        let mut live_allocs: Vec<(AllocId, MemoryKind<MiriMemoryKind>, _, _)> = ecx
+            .machine
+            .live_allocs
+            .as_ref()?
+            .borrow()
+            .iter()
+            .map(|(&id, info)| (id, info.kind, info.site, info.backtrace.clone()))
+            .collect();
+        live_allocs.sort_by_key(|(id, ..)| *id);
+
+        // Find everything that is reachable from the roots.
+        let mut reachable = FxHashSet::default();
+        let mut todo: Vec<AllocId> = live_allocs
+            .iter()
+            .filter(|(_, kind, ..)| *kind == MiriMemoryKind::Global.into())
+            .map(|(id, ..)| *id)
+            .chain(roots.iter().copied())
+            .collect();
+        while let Some(id) = todo.pop() {
+            if reachable.insert(id) {
+                // Function pointers and dangling pointers do not point to memory.
+                if let Ok(alloc) = ecx.get_alloc_raw(id) {
+                    todo.extend(alloc.relocations().values().map(|tag| tag.alloc_id));
+                }
+            }
+        }
+
+        let mut state = FinalState::default();
+        for (id, kind, site, backtrace) in live_allocs {
+            let alloc = match ecx.get_alloc_raw(id) {
+                Ok(alloc) => alloc,
+                Err(_) => continue,
+            };
+            let bytes = alloc.inspect_with_uninit_and_ptr_outside_interpreter(0..alloc.len());
+            if let Some(GlobalAlloc::Static(def_id)) = ecx.tcx.get_global_alloc(id) {
+                state.statics.insert(def_id, bytes.to_vec());
+            }
+            if kind.may_leak() || reachable.contains(&id) {
+                continue;
+            }
+            state.leaked.push(LeakedAllocation {
+                id,
+                kind,
+                bytes: bytes.to_vec(),
+                align: alloc.align,
+                site: site.map(|site| site.to_string()),
+                backtrace: backtrace.map(|backtrace| {
+                    backtrace.iter().map(|frame| ReportFrame::new(*ecx.tcx, frame)).collect()
+                }),
+            });
+        }
+        state.file_descriptors = ecx.machine.file_handler.describe();
+        Some(state)
+    }
+
+    /// The allocations that were leaked, in the order they were created.
+    pub fn leaked_allocations(&self) -> impl Iterator<Item = &LeakedAllocation> {
+        self.leaked.iter()
+    }
+
+    /// The leaked allocations grouped by kind and the function that created them, with the groups
+    /// that leaked the most bytes first.
+    pub fn leak_groups(&self) -> Vec<LeakGroup> {
+        let mut groups: Vec<LeakGroup> = vec![];
+        for leak in &self.leaked {
+            let bytes = leak.bytes.len() as u64;
+            match groups.iter_mut().find(|group| group.site == leak.site && group.kind == leak.kind)
+            {
+                Some(group) => {
+                    group.count += 1;
+                    group.bytes += bytes;
+                }
+                None =>
+                    groups.push(LeakGroup {
+                        site: leak.site.clone(),
+                        kind: leak.kind,
+                        count: 1,
+                        bytes,
+                    }),
+            }
+        }
+        groups.sort_by(|a, b| b.bytes.cmp(&a.bytes).then(b.count.cmp(&a.count)));
+        groups
+    }
+
+    /// The final contents of the memory of the static `def_id`. Returns `None` if the program
+    /// never accessed the static, in which case it still has its initial value.
+    pub fn static_memory(&self, def_id: DefId) -> Option<&[u8]> {
+        self.statics.get(&def_id).map(|bytes| &bytes[..])
+    }
+
+    /// The file descriptors that were still open, with a description of what they refer to.
+    pub fn file_descriptors(&self) -> impl Iterator<Item = (i32, &str)> {
+        self.file_descriptors.iter().map(|(fd, description)| (*fd, &description[..]))
+    }
+}
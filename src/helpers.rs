@@ -41,11 +41,13 @@ const UNIX_IO_ERROR_TABLE: &[(std::io::ErrorKind, &str)] = {
         (AlreadyExists, "EEXIST"),
         (WouldBlock, "EWOULDBLOCK"),
         (DirectoryNotEmpty, "ENOTEMPTY"),
+        (NotADirectory, "ENOTDIR"),
+        (IsADirectory, "EISDIR"),
     ]
 };
 
 /// Gets an instance for a path.
-fn try_resolve_did<'tcx>(tcx: TyCtxt<'tcx>, path: &[&str]) -> Option<DefId> {
+pub(crate) fn try_resolve_did<'tcx>(tcx: TyCtxt<'tcx>, path: &[&str]) -> Option<DefId> {
     tcx.crates(()).iter().find(|&&krate| tcx.crate_name(krate).as_str() == path[0]).and_then(
         |krate| {
             let krate = DefId { krate: *krate, index: CRATE_DEF_INDEX };
@@ -71,21 +73,41 @@ fn try_resolve_did<'tcx>(tcx: TyCtxt<'tcx>, path: &[&str]) -> Option<DefId> {
 
 pub trait EvalContextExt<'mir, 'tcx: 'mir>: crate::MiriEvalContextExt<'mir, 'tcx> {
     /// Gets an instance for a path.
-    fn resolve_path(&self, path: &[&str]) -> ty::Instance<'tcx> {
-        let did = try_resolve_did(self.eval_context_ref().tcx.tcx, path)
-            .unwrap_or_else(|| panic!("failed to find required Rust item: {:?}", path));
-        ty::Instance::mono(self.eval_context_ref().tcx.tcx, did)
+    /// Fails if the item does not exist, e.g. because the program does not link the crate.
+    fn resolve_path(&self, path: &[&str]) -> InterpResult<'tcx, ty::Instance<'tcx>> {
+        let this = self.eval_context_ref();
+        let key = machine::PathCache::key(path);
+        if let Some(&instance) = this.machine.path_cache.borrow().instances.get(&key) {
+            return Ok(instance);
+        }
+        let did = match try_resolve_did(this.tcx.tcx, path) {
+            Some(did) => did,
+            None =>
+                throw_unsup_format!(
+                    "failed to find `{}`, which Miri needs to emulate this operation",
+                    path.join("::")
+                ),
+        };
+        let instance = ty::Instance::mono(this.tcx.tcx, did);
+        this.machine.path_cache.borrow_mut().instances.insert(key, instance);
+        Ok(instance)
     }
 
     /// Evaluates the scalar at the specified path. Returns Some(val)
     /// if the path could be resolved, and None otherwise
     fn eval_path_scalar(&self, path: &[&str]) -> InterpResult<'tcx, Scalar<Tag>> {
         let this = self.eval_context_ref();
-        let instance = this.resolve_path(path);
+        let key = machine::PathCache::key(path);
+        if let Some(&scalar) = this.machine.path_cache.borrow().scalars.get(&key) {
+            return Ok(scalar);
+        }
+        let instance = this.resolve_path(path)?;
         let cid = GlobalId { instance, promoted: None };
         let const_val = this.eval_to_allocation(cid)?;
         let const_val = this.read_scalar(&const_val.into())?;
-        const_val.check_init()
+        let scalar = const_val.check_init()?;
+        this.machine.path_cache.borrow_mut().scalars.insert(key, scalar);
+        Ok(scalar)
     }
 
     /// Helper function to get a `libc` constant as a `Scalar`.
@@ -95,7 +117,6 @@ pub trait EvalContextExt<'mir, 'tcx: 'mir>: crate::MiriEvalContextExt<'mir, 'tcx
 
     /// Helper function to get a `libc` constant as an `i32`.
     fn eval_libc_i32(&self, name: &str) -> InterpResult<'tcx, i32> {
-        // TODO: Cache the result.
         self.eval_libc(name)?.to_i32()
     }
 
@@ -106,24 +127,30 @@ pub trait EvalContextExt<'mir, 'tcx: 'mir>: crate::MiriEvalContextExt<'mir, 'tcx
 
     /// Helper function to get a `windows` constant as a `u64`.
     fn eval_windows_u64(&self, module: &str, name: &str) -> InterpResult<'tcx, u64> {
-        // TODO: Cache the result.
         self.eval_windows(module, name)?.to_u64()
     }
 
+    /// Helper function to get the `TyAndLayout` of the type at the specified path.
+    fn path_ty_layout(&self, path: &[&str]) -> InterpResult<'tcx, TyAndLayout<'tcx>> {
+        let this = self.eval_context_ref();
+        let key = machine::PathCache::key(path);
+        if let Some(&layout) = this.machine.path_cache.borrow().layouts.get(&key) {
+            return Ok(layout);
+        }
+        let ty = this.resolve_path(path)?.ty(*this.tcx, ty::ParamEnv::reveal_all());
+        let layout = this.layout_of(ty)?;
+        this.machine.path_cache.borrow_mut().layouts.insert(key, layout);
+        Ok(layout)
+    }
+
     /// Helper function to get the `TyAndLayout` of a `libc` type
     fn libc_ty_layout(&self, name: &str) -> InterpResult<'tcx, TyAndLayout<'tcx>> {
-        let this = self.eval_context_ref();
-        let ty = this.resolve_path(&["libc", name]).ty(*this.tcx, ty::ParamEnv::reveal_all());
-        this.layout_of(ty)
+        self.path_ty_layout(&["libc", name])
     }
 
     /// Helper function to get the `TyAndLayout` of a `windows` type
     fn windows_ty_layout(&self, name: &str) -> InterpResult<'tcx, TyAndLayout<'tcx>> {
-        let this = self.eval_context_ref();
-        let ty = this
-            .resolve_path(&["std", "sys", "windows", "c", name])
-            .ty(*this.tcx, ty::ParamEnv::reveal_all());
-        this.layout_of(ty)
+        self.path_ty_layout(&["std", "sys", "windows", "c", name])
     }
 
     /// Project to the given *named* field of the mplace (which must be a struct or union type).
@@ -142,6 +169,15 @@ pub trait EvalContextExt<'mir, 'tcx: 'mir>: crate::MiriEvalContextExt<'mir, 'tcx
         bug!("No field named {} in type {}", name, mplace.layout.ty);
     }
 
+    /// The offset of the given *named* field in `layout` (which must be a struct or union type).
+    fn field_offset_named(&self, layout: TyAndLayout<'tcx>, name: &str) -> Size {
+        let adt = layout.ty.ty_adt_def().unwrap();
+        match adt.non_enum_variant().fields.iter().position(|field| field.name.as_str() == name) {
+            Some(idx) => layout.fields.offset(idx),
+            None => bug!("No field named {} in type {}", name, layout.ty),
+        }
+    }
+
     /// Write an int of the appropriate size to `dest`. The target type may be signed or unsigned,
     /// we try to do the right thing anyway. `i128` can fit all integer types except for `u128` so
     /// this method is fine for almost all integer types.
@@ -183,6 +219,30 @@ pub trait EvalContextExt<'mir, 'tcx: 'mir>: crate::MiriEvalContextExt<'mir, 'tcx
         Ok(())
     }
 
+    /// Write the given fields of the given place, unless one of the values does not fit into its
+    /// field. Returns whether the fields were written.
+    fn write_int_fields_named_checked(
+        &mut self,
+        values: &[(&str, i128)],
+        dest: &MPlaceTy<'tcx, Tag>,
+    ) -> InterpResult<'tcx, bool> {
+        let this = self.eval_context_mut();
+        for &(name, val) in values.iter() {
+            let layout = this.mplace_field_named(dest, name)?.layout;
+            let size = layout.size;
+            let fits = if layout.abi.is_signed() {
+                size.signed_int_min() <= val && val <= size.signed_int_max()
+            } else {
+                u128::try_from(val).map_or(false, |val| val <= size.unsigned_int_max())
+            };
+            if !fits {
+                return Ok(false);
+            }
+        }
+        this.write_int_fields_named(values, dest)?;
+        Ok(true)
+    }
+
     /// Write a 0 of the appropriate size to `dest`.
     fn write_null(&mut self, dest: &PlaceTy<'tcx, Tag>) -> InterpResult<'tcx> {
         self.write_int(0, dest)
@@ -216,7 +276,7 @@ pub trait EvalContextExt<'mir, 'tcx: 'mir>: crate::MiriEvalContextExt<'mir, 'tcx
 
         let mut data = vec![0; usize::try_from(len).unwrap()];
 
-        if this.machine.communicate() {
+        if this.machine.host_entropy {
             // Fill the buffer using the host's rng.
             getrandom::getrandom(&mut data)
                 .map_err(|err| err_unsup_format!("host getrandom failed: {}", err))?;
@@ -504,13 +564,43 @@ pub trait EvalContextExt<'mir, 'tcx: 'mir>: crate::MiriEvalContextExt<'mir, 'tcx
         }
     }
 
-    /// Sets the last error variable.
+    /// Sets the last error variable. All shims set `errno` (or the Windows last error) through
+    /// this function, which records the shim that did so.
     fn set_last_error(&mut self, scalar: Scalar<Tag>) -> InterpResult<'tcx> {
         let this = self.eval_context_mut();
         let errno_place = this.last_error_place()?;
+        this.machine.last_error_set_in_shim = true;
+        let setter = this.machine.current_shim;
+        let thread = this.active_thread_mut();
+        thread.last_error_setter = setter;
+        thread.last_error_poisoned_after = None;
         this.write_scalar(scalar, &errno_place.into())
     }
 
+    /// With `-Zmiri-strict-errno`, makes `errno` uninitialized after the successful call to the
+    /// shim `link_name`, i.e. a call that did not set `errno`. Real systems leave `errno`
+    /// unspecified after successful calls, so a program that reads it then is buggy.
+    fn poison_last_error_after(&mut self, link_name: Symbol) -> InterpResult<'tcx> {
+        let this = self.eval_context_mut();
+        if !this.machine.strict_errno
+            || this.machine.last_error_set_in_shim
+            || !this.tcx.sess.target.families.iter().any(|f| f == "unix")
+        {
+            return Ok(());
+        }
+        // These shims give access to `errno` itself, or are documented to leave `errno`
+        // unchanged on success, so that programs can detect errors by setting it to 0 first.
+        if matches!(
+            &*link_name.as_str(),
+            "__errno_location" | "__error" | "readdir" | "readdir64" | "sysconf"
+        ) {
+            return Ok(());
+        }
+        let errno_place = this.last_error_place()?;
+        this.active_thread_mut().last_error_poisoned_after = Some(link_name);
+        this.write_scalar(ScalarMaybeUninit::Uninit, &errno_place.into())
+    }
+
     /// Gets the last error variable.
     fn get_last_error(&mut self) -> InterpResult<'tcx, Scalar<Tag>> {
         let this = self.eval_context_mut();
@@ -538,6 +628,12 @@ pub trait EvalContextExt<'mir, 'tcx: 'mir>: crate::MiriEvalContextExt<'mir, 'tcx
                 match err_kind {
                     NotFound => "ERROR_FILE_NOT_FOUND",
                     PermissionDenied => "ERROR_ACCESS_DENIED",
+                    AlreadyExists => "ERROR_ALREADY_EXISTS",
+                    InvalidInput => "ERROR_INVALID_PARAMETER",
+                    DirectoryNotEmpty => "ERROR_DIR_NOT_EMPTY",
+                    NotADirectory => "ERROR_DIRECTORY",
+                    // Windows does not delete directories as files, but denies it.
+                    IsADirectory => "ERROR_ACCESS_DENIED",
                     _ =>
                         throw_unsup_format!(
                             "io error {:?} cannot be translated into a raw os error",
@@ -713,6 +809,47 @@ pub trait EvalContextExt<'mir, 'tcx: 'mir>: crate::MiriEvalContextExt<'mir, 'tcx
         Ok(())
     }
 
+    /// Checks that `body`, the MIR of `instance`, does not contain constructs that Miri cannot
+    /// execute. Every body is only checked the first time it is called.
+    fn check_body_supported(
+        &mut self,
+        instance: ty::Instance<'tcx>,
+        body: &mir::Body<'tcx>,
+    ) -> InterpResult<'tcx> {
+        let this = self.eval_context_mut();
+        if !this.machine.checked_bodies.insert(instance) {
+            return Ok(());
+        }
+        // Generators are lowered to state machines before they reach us; a `yield` that is left
+        // over cannot be executed.
+        for block in body.basic_blocks() {
+            if let mir::TerminatorKind::Yield { .. } | mir::TerminatorKind::GeneratorDrop =
+                block.terminator().kind
+            {
+                throw_unsup_format!(
+                    "generator `{}` was not lowered to a state machine and cannot be executed; \
+                    see https://github.com/rust-lang/rust/issues/43122",
+                    instance,
+                );
+            }
+        }
+        let param_env = ty::ParamEnv::reveal_all();
+        for (local, decl) in body.local_decls.iter_enumerated() {
+            let ty =
+                instance.subst_mir_and_normalize_erasing_regions(*this.tcx, param_env, decl.ty);
+            if !ty.is_sized(this.tcx, param_env) {
+                throw_unsup_format!(
+                    "unsized local `{:?}` of type `{}` in `{}` is not supported; \
+                    see https://github.com/rust-lang/rust/issues/48055",
+                    local,
+                    ty,
+                    instance,
+                );
+            }
+        }
+        Ok(())
+    }
+
     fn frame_in_std(&self) -> bool {
         let this = self.eval_context_ref();
         this.tcx.lang_items().start_fn().map_or(false, |start_fn| {
@@ -730,6 +867,9 @@ pub trait EvalContextExt<'mir, 'tcx: 'mir>: crate::MiriEvalContextExt<'mir, 'tcx
         let this = self.eval_context_mut();
         if this.machine.panic_on_unsupported {
             // message is slightly different here to make automated analysis easier
+            register_diagnostic(NonHaltingDiagnostic::UnsupportedPanic(
+                error_msg.as_ref().to_string(),
+            ));
             let error_msg = format!("unsupported Miri functionality: {}", error_msg.as_ref());
             this.start_panic(error_msg.as_ref(), StackPopUnwind::Skip)?;
             Ok(())
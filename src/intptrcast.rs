@@ -4,13 +4,25 @@ use std::collections::hash_map::Entry;
 use log::trace;
 use rand::Rng;
 
-use rustc_data_structures::fx::FxHashMap;
-use rustc_target::abi::{HasDataLayout, Size};
+use rustc_data_structures::fx::{FxHashMap, FxIndexSet};
+use rustc_middle::{mir, ty};
+use rustc_span::{Span, SpanData};
+use rustc_target::abi::{Align, HasDataLayout, Size};
 
 use crate::*;
 
 pub type GlobalState = RefCell<GlobalStateInner>;
 
+/// A pointer was cast to an integer, which is what a later int2ptr cast would take its
+/// provenance from.
+#[derive(Clone, Debug, PartialEq, Eq, Hash)]
+pub struct Exposure {
+    /// Where the cast happened.
+    pub span: SpanData,
+    /// The Stacked Borrows tag of the pointer that was cast.
+    pub tag: SbTag,
+}
+
 #[derive(Clone, Debug)]
 pub struct GlobalStateInner {
     /// This is used as a map between the address of each allocation and its `AllocId`.
@@ -27,15 +39,55 @@ pub struct GlobalStateInner {
     /// Whether to enforce "strict provenance" rules. Enabling this means int2ptr casts return
     /// pointers with an invalid provenance, i.e., not valid for any memory access.
     strict_provenance: bool,
+    /// The ptr2int casts of pointers to each allocation, so that an access through a pointer that
+    /// was cast from an integer can be traced back to the casts that could justify it. Only
+    /// recorded with `-Zmiri-track-exposures`.
+    exposures: Option<FxHashMap<AllocId, FxIndexSet<Exposure>>>,
+    /// If set, the next allocation that needs a base address takes over the base address of the
+    /// given allocation, which is about to be freed. This is how reallocation in place works.
+    reuse_base_addr: Option<(u64, AllocId)>,
 }
 
 impl GlobalStateInner {
-    pub fn new(config: &MiriConfig) -> Self {
+    pub fn new(config: &MiriConfig, stack_addr: u64) -> Self {
         GlobalStateInner {
             int_to_ptr_map: Vec::default(),
             base_addr: FxHashMap::default(),
-            next_base_addr: STACK_ADDR,
+            next_base_addr: stack_addr,
             strict_provenance: config.strict_provenance,
+            exposures: config.track_exposures.then(FxHashMap::default),
+            reuse_base_addr: None,
+        }
+    }
+
+    /// Whether int2ptr casts return pointers with an invalid provenance.
+    pub fn strict_provenance(&self) -> bool {
+        self.strict_provenance
+    }
+
+    /// Whether ptr2int casts are recorded. Int2ptr casts never have provenance with strict
+    /// provenance, so there is nothing to trace them back to then.
+    pub fn tracks_exposures(&self) -> bool {
+        self.exposures.is_some() && !self.strict_provenance
+    }
+
+    /// The ptr2int casts of pointers to `alloc_id`, oldest first, or `None` if they are not
+    /// tracked.
+    pub fn exposures(&self, alloc_id: AllocId) -> Option<Vec<&Exposure>> {
+        let exposures = self.exposures.as_ref()?;
+        Some(exposures.get(&alloc_id).map_or_else(Vec::new, |set| set.iter().collect()))
+    }
+
+    /// Stops the next allocation from taking over a base address, if it did not happen yet.
+    pub fn cancel_base_addr_reuse(&mut self) {
+        self.reuse_base_addr = None;
+    }
+
+    /// Records that `ptr` was cast to an integer at `span`.
+    fn expose(&mut self, ptr: Tag, span: Span) {
+        if let Some(exposures) = &mut self.exposures {
+            let exposure = Exposure { span: span.data(), tag: ptr.sb };
+            exposures.entry(ptr.alloc_id).or_default().insert(exposure);
         }
     }
 }
@@ -73,6 +125,12 @@ impl<'mir, 'tcx> GlobalStateInner {
                 }
             }
         };
+        // We had to guess the provenance if the address is in some allocation.
+        if alloc_id.is_some()
+            && ecx.machine.warning_level(WarningKind::Int2Ptr) != WarningLevel::Allow
+        {
+            register_diagnostic(NonHaltingDiagnostic::Int2Ptr(addr));
+        }
         // Pointers created from integers are untagged.
         Pointer::new(
             alloc_id.map(|alloc_id| Tag { alloc_id, sb: SbTag::Untagged }),
@@ -80,17 +138,44 @@ impl<'mir, 'tcx> GlobalStateInner {
         )
     }
 
-    fn alloc_base_addr(ecx: &MiriEvalContext<'mir, 'tcx>, alloc_id: AllocId) -> u64 {
+    fn alloc_base_addr(
+        ecx: &MiriEvalContext<'mir, 'tcx>,
+        alloc_id: AllocId,
+    ) -> InterpResult<'tcx, u64> {
         let mut global_state = ecx.machine.intptrcast.borrow_mut();
         let global_state = &mut *global_state;
 
         match global_state.base_addr.entry(alloc_id) {
-            Entry::Occupied(entry) => *entry.get(),
+            Entry::Occupied(entry) => Ok(*entry.get()),
+            Entry::Vacant(entry) if global_state.reuse_base_addr.is_some() => {
+                let (base_addr, old_alloc_id) = global_state.reuse_base_addr.take().unwrap();
+                entry.insert(base_addr);
+                let (size, _) = ecx.get_alloc_size_and_align(alloc_id, AllocCheck::MaybeDead)?;
+                trace!(
+                    "Assigning base address {:#x} of allocation {:?} to allocation {:?} (size: {})",
+                    base_addr,
+                    old_alloc_id,
+                    alloc_id,
+                    size.bytes(),
+                );
+                // The old allocation is freed right away, so from now on the address belongs to
+                // the new one.
+                let pos = global_state
+                    .int_to_ptr_map
+                    .binary_search_by_key(&base_addr, |(addr, _)| *addr)
+                    .unwrap();
+                global_state.int_to_ptr_map[pos].1 = alloc_id;
+                // `reserve_in_place` checked that there is room for the new size.
+                let size_plus_1 = size.bytes().checked_add(1).unwrap();
+                let end_plus_1 = base_addr.checked_add(size_plus_1).unwrap();
+                global_state.next_base_addr = global_state.next_base_addr.max(end_plus_1);
+                Ok(base_addr)
+            }
             Entry::Vacant(entry) => {
                 // There is nothing wrong with a raw pointer being cast to an integer only after
                 // it became dangling.  Hence `MaybeDead`.
                 let (size, align) =
-                    ecx.get_alloc_size_and_align(alloc_id, AllocCheck::MaybeDead).unwrap();
+                    ecx.get_alloc_size_and_align(alloc_id, AllocCheck::MaybeDead)?;
 
                 // This allocation does not have a base address yet, pick one.
                 // Leave some space to the previous allocation, to give it some chance to be less aligned.
@@ -117,33 +202,82 @@ impl<'mir, 'tcx> GlobalStateInner {
                 // allocations (also see https://github.com/rust-lang/unsafe-code-guidelines/issues/313).
                 let size_plus_1 = size.bytes().checked_add(1).unwrap();
                 global_state.next_base_addr = base_addr.checked_add(size_plus_1).unwrap();
+                // Addresses beyond the target's address space would silently wrap around and alias
+                // other allocations. This can only happen on targets with small pointers.
+                if global_state.next_base_addr > ecx.machine_usize_max() {
+                    throw_machine_stop!(TerminationInfo::AddressSpaceExhausted {
+                        alloc_id,
+                        size,
+                        bits: ecx.pointer_size().bits(),
+                    });
+                }
                 // Given that `next_base_addr` increases in each allocation, pushing the
                 // corresponding tuple keeps `int_to_ptr_map` sorted
                 global_state.int_to_ptr_map.push((base_addr, alloc_id));
 
-                base_addr
+                Ok(base_addr)
             }
         }
     }
 
+    /// Makes the next allocation take over the base address of `old_alloc_id`, so that
+    /// reallocating it to `new_size` bytes with alignment `new_align` happens in place. Returns
+    /// `false` (and changes nothing) if that is not possible because the address is not suitably
+    /// aligned or the next allocation starts too close to it.
+    pub fn reserve_in_place(
+        ecx: &MiriEvalContext<'mir, 'tcx>,
+        old_alloc_id: AllocId,
+        new_size: Size,
+        new_align: Align,
+    ) -> InterpResult<'tcx, bool> {
+        let base_addr = Self::alloc_base_addr(ecx, old_alloc_id)?;
+        let mut global_state = ecx.machine.intptrcast.borrow_mut();
+        if base_addr % new_align.bytes() != 0 {
+            return Ok(false);
+        }
+        let pos = global_state
+            .int_to_ptr_map
+            .binary_search_by_key(&base_addr, |(addr, _)| *addr)
+            .unwrap();
+        let end_plus_1 = base_addr.checked_add(new_size.bytes()).and_then(|end| end.checked_add(1));
+        let end_plus_1 = match end_plus_1 {
+            Some(end_plus_1) if end_plus_1 <= ecx.machine_usize_max() => end_plus_1,
+            _ => return Ok(false),
+        };
+        // Keep the same gap to the next allocation as `alloc_base_addr` does.
+        if let Some(&(next_addr, _)) = global_state.int_to_ptr_map.get(pos + 1) {
+            if end_plus_1 > next_addr {
+                return Ok(false);
+            }
+        }
+        global_state.reuse_base_addr = Some((base_addr, old_alloc_id));
+        Ok(true)
+    }
+
     /// Convert a relative (tcx) pointer to an absolute address.
-    pub fn rel_ptr_to_addr(ecx: &MiriEvalContext<'mir, 'tcx>, ptr: Pointer<AllocId>) -> u64 {
+    pub fn rel_ptr_to_addr(
+        ecx: &MiriEvalContext<'mir, 'tcx>,
+        ptr: Pointer<AllocId>,
+    ) -> InterpResult<'tcx, u64> {
         let (alloc_id, offset) = ptr.into_parts(); // offset is relative (AllocId provenance)
-        let base_addr = GlobalStateInner::alloc_base_addr(ecx, alloc_id);
+        let base_addr = GlobalStateInner::alloc_base_addr(ecx, alloc_id)?;
 
         // Add offset with the right kind of pointer-overflowing arithmetic.
         let dl = ecx.data_layout();
-        dl.overflowing_offset(base_addr, offset.bytes()).0
+        Ok(dl.overflowing_offset(base_addr, offset.bytes()).0)
     }
 
-    pub fn abs_ptr_to_rel(ecx: &MiriEvalContext<'mir, 'tcx>, ptr: Pointer<Tag>) -> Size {
+    pub fn abs_ptr_to_rel(
+        ecx: &MiriEvalContext<'mir, 'tcx>,
+        ptr: Pointer<Tag>,
+    ) -> InterpResult<'tcx, Size> {
         let (tag, addr) = ptr.into_parts(); // addr is absolute (Tag provenance)
-        let base_addr = GlobalStateInner::alloc_base_addr(ecx, tag.alloc_id);
+        let base_addr = GlobalStateInner::alloc_base_addr(ecx, tag.alloc_id)?;
 
         // Wrapping "addr - base_addr"
         let dl = ecx.data_layout();
         let neg_base_addr = (base_addr as i64).wrapping_neg();
-        Size::from_bytes(dl.overflowing_signed_offset(addr.bytes(), neg_base_addr).0)
+        Ok(Size::from_bytes(dl.overflowing_signed_offset(addr.bytes(), neg_base_addr).0))
     }
 
     /// Shifts `addr` to make it aligned with `align` by rounding `addr` to the smallest multiple
@@ -156,6 +290,86 @@ impl<'mir, 'tcx> GlobalStateInner {
     }
 }
 
+impl<'mir, 'tcx: 'mir> EvalContextExt<'mir, 'tcx> for crate::MiriEvalContext<'mir, 'tcx> {}
+pub trait EvalContextExt<'mir, 'tcx: 'mir>: crate::MiriEvalContextExt<'mir, 'tcx> {
+    /// Called before each step with `-Zmiri-track-exposures`: if the active thread is about to
+    /// cast a pointer to an integer, record that the pointer's allocation was exposed there.
+    fn record_exposure(&mut self) -> InterpResult<'tcx> {
+        let this = self.eval_context_mut();
+        let (body, loc) = match this.active_thread_stack().last() {
+            Some(frame) => (frame.body, frame.current_loc()),
+            None => return Ok(()),
+        };
+        let stmt = match loc {
+            Ok(loc) => body.basic_blocks()[loc.block].statements.get(loc.statement_index),
+            Err(_) => None,
+        };
+        let (rvalue, span) = match stmt {
+            Some(mir::Statement { kind: mir::StatementKind::Assign(assign), source_info }) =>
+                (&assign.1, source_info.span),
+            _ => return Ok(()),
+        };
+        let operand = match rvalue {
+            mir::Rvalue::Cast(_, operand, cast_ty)
+                if is_ptr(operand.ty(&body.local_decls, *this.tcx)) && cast_ty.is_integral() =>
+                operand,
+            _ => return Ok(()),
+        };
+        // Evaluating a place behind a pointer would read that pointer, which must not run the
+        // memory access hooks before the statement itself does.
+        if let mir::Operand::Copy(place) | mir::Operand::Move(place) = operand {
+            if place.projection.iter().any(|elem| matches!(elem, mir::ProjectionElem::Deref)) {
+                return Ok(());
+            }
+        }
+        let ptr = this.eval_operand(operand, None)?;
+        this.expose_ptr(&ptr, span)
+    }
+
+    /// With `-Zmiri-track-exposures`, record that the pointer `op` is cast to an integer by a
+    /// `transmute` to `dest_ty`.
+    fn record_transmute_exposure(
+        &mut self,
+        op: &OpTy<'tcx, Tag>,
+        dest_ty: ty::Ty<'tcx>,
+    ) -> InterpResult<'tcx> {
+        let this = self.eval_context_mut();
+        if this.machine.intptrcast.borrow().tracks_exposures()
+            && is_ptr(op.layout.ty)
+            && dest_ty.is_integral()
+        {
+            this.expose_ptr(op, this.cur_span())?;
+        }
+        Ok(())
+    }
+
+    /// Records that the pointer `op` is cast to an integer at `span`. The pointer is read without
+    /// running the memory access hooks, since the cast itself reads it again.
+    fn expose_ptr(&mut self, op: &OpTy<'tcx, Tag>, span: Span) -> InterpResult<'tcx> {
+        let this = self.eval_context_mut();
+        let tag = match **op {
+            Operand::Immediate(Immediate::Scalar(ScalarMaybeUninit::Scalar(Scalar::Ptr(
+                ptr,
+                _,
+            )))) => Some(ptr.provenance),
+            Operand::Immediate(_) => None,
+            Operand::Indirect(mplace) => {
+                let (alloc_id, offset, _) = this.ptr_get_alloc_id(mplace.ptr)?;
+                this.get_alloc_raw(alloc_id)?.relocations().get(&offset).copied()
+            }
+        };
+        if let Some(tag) = tag {
+            this.machine.intptrcast.borrow_mut().expose(tag, span);
+        }
+        Ok(())
+    }
+}
+
+/// Whether casting a value of type `ty` to an integer turns a pointer into an integer.
+fn is_ptr(ty: ty::Ty<'_>) -> bool {
+    matches!(ty.kind(), ty::RawPtr(_) | ty::FnPtr(_))
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
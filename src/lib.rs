@@ -29,26 +29,42 @@ extern crate rustc_ast;
 extern crate rustc_middle;
 extern crate rustc_const_eval;
 extern crate rustc_data_structures;
+extern crate rustc_errors;
 extern crate rustc_hir;
 extern crate rustc_index;
 extern crate rustc_session;
 extern crate rustc_span;
 extern crate rustc_target;
 
+mod clock;
+mod compare_ctfe;
+mod core_dump;
 mod data_race;
+mod debugger;
 mod diagnostics;
+mod error_codes;
 mod eval;
+mod final_state;
 mod helpers;
 mod intptrcast;
 mod machine;
 mod mono_hash_map;
+mod observer;
 mod operator;
 mod range_map;
+mod report;
+mod sarif;
+mod scheduling;
 mod shims;
+mod stable_alloc_id;
 mod stacked_borrows;
+mod stats;
+mod step_profile;
 mod sync;
 mod thread;
+mod value_origins;
 mod vector_clock;
+mod watchpoints;
 
 // Establish a "crate-wide prelude": we often import `crate::*`.
 
@@ -57,44 +73,78 @@ pub use rustc_const_eval::interpret::*;
 // Resolve ambiguity.
 pub use rustc_const_eval::interpret::{self, AllocMap, PlaceTy};
 
+pub use crate::shims::backtrace::{EvalContextExt as _, UnwindBacktraceData};
 pub use crate::shims::dlsym::{Dlsym, EvalContextExt as _};
 pub use crate::shims::env::{EnvVars, EvalContextExt as _};
-pub use crate::shims::foreign_items::EvalContextExt as _;
+pub use crate::shims::foreign_items::{
+    EmulateByNameResult, EvalContextExt as _, ForeignItemProvider,
+};
 pub use crate::shims::intrinsics::EvalContextExt as _;
 pub use crate::shims::os_str::EvalContextExt as _;
 pub use crate::shims::panic::{CatchUnwindData, EvalContextExt as _};
+pub use crate::shims::posix::SignalEvalContextExt as _;
 pub use crate::shims::time::EvalContextExt as _;
 pub use crate::shims::tls::{EvalContextExt as _, TlsData};
 pub use crate::shims::EvalContextExt as _;
 
+pub use crate::clock::Clock;
+pub use crate::compare_ctfe::{
+    compare_ctfe, CtfeComparison, EvalContextExt as CompareCtfeEvalContextExt,
+};
+pub use crate::core_dump::{write_core_dump, RecentShimCalls};
 pub use crate::data_race::{
     AtomicFenceOp, AtomicReadOp, AtomicRwOp, AtomicWriteOp,
     EvalContextExt as DataRaceEvalContextExt,
 };
+pub use crate::debugger::{Debugger, EvalContextExt as DebuggerEvalContextExt};
 pub use crate::diagnostics::{
-    register_diagnostic, report_error, EvalContextExt as DiagnosticsEvalContextExt,
-    NonHaltingDiagnostic, TerminationInfo,
+    register_diagnostic, report_alloc_ids, report_error, report_leaked_allocations,
+    report_leaked_threads, report_missing_expected_error, report_suppressed_diagnostics,
+    DiagnosticCounts, EvalContextExt as DiagnosticsEvalContextExt, NonHaltingDiagnostic,
+    TerminationInfo, WarningKind, WarningLevel,
 };
+pub use crate::error_codes::{error_code, explain_error_code, ERROR_CODES};
 pub use crate::eval::{
-    create_ecx, eval_entry, AlignmentCheck, BacktraceStyle, IsolatedOp, MiriConfig, RejectOpWith,
+    create_ecx, eval_entry, run_analysis, AlignmentCheck, BacktraceStyle, ErrorSpan, IsolatedOp,
+    LeakReport, MiriConfig, ProcessSpawn, ReallocPolicy, RejectOpWith, SchedulingPolicyKind,
+    ThreadLeakCheck,
 };
+pub use crate::final_state::{AllocationInfo, FinalState, LeakGroup, LeakedAllocation};
 pub use crate::helpers::EvalContextExt as HelpersEvalContextExt;
+pub use crate::intptrcast::{EvalContextExt as IntPtrCastEvalContextExt, Exposure};
 pub use crate::machine::{
     AllocExtra, Evaluator, FrameData, MiriEvalContext, MiriEvalContextExt, MiriMemoryKind, Tag,
-    NUM_CPUS, PAGE_SIZE, STACK_ADDR, STACK_SIZE,
+    NUM_CPUS, PAGE_SIZE, PROCESS_ID, STACK_ADDR, STACK_SIZE,
 };
 pub use crate::mono_hash_map::MonoHashMap;
+pub use crate::observer::{MemoryObserver, ObserverFactory, Observers};
 pub use crate::operator::EvalContextExt as OperatorEvalContextExt;
 pub use crate::range_map::RangeMap;
+pub use crate::report::{
+    ErrorKind, ErrorReport, LeakSummary, Location, Report, ReportFrame, Statistics,
+};
+pub use crate::sarif::{write_sarif, write_unsupported_shims};
+pub use crate::scheduling::{
+    new_scheduling_policy, read_schedule, scheduling_policy, Exploration, Explore, PolicyFactory,
+    Replay, ScheduleEvent, ScheduleRecorder, SchedulingPolicy,
+};
+pub use crate::stable_alloc_id::{StableAllocId, StableAllocIds};
 pub use crate::stacked_borrows::{
     CallId, EvalContextExt as StackedBorEvalContextExt, Item, Permission, PtrId, SbTag, Stack,
     Stacks,
 };
+pub use crate::stats::{Detector, DetectorTimer, Stats};
+pub use crate::step_profile::{EvalContextExt as StepProfileEvalContextExt, StepProfiler};
 pub use crate::sync::{CondvarId, EvalContextExt as SyncEvalContextExt, MutexId, RwLockId};
 pub use crate::thread::{
-    EvalContextExt as ThreadsEvalContextExt, SchedulingAction, ThreadId, ThreadManager, ThreadState,
+    EvalContextExt as ThreadsEvalContextExt, LeakedThread, SchedulingAction, ThreadId,
+    ThreadManager, ThreadState,
+};
+pub use crate::value_origins::{
+    EvalContextExt as ValueOriginsEvalContextExt, Origin, OriginRead, ValueOrigin,
 };
-pub use crate::vector_clock::{VClock, VTimestamp, VectorIdx};
+pub use crate::vector_clock::{SparseVClock, VClock, VTimestamp, VectorIdx};
+pub use crate::watchpoints::{EvalContextExt as WatchpointsEvalContextExt, WatchedAccess};
 
 /// Insert rustc arguments at the beginning of the argument list that Miri wants to be
 /// set per default, for maximal validation power.
@@ -2,17 +2,20 @@
 //! `Machine` trait.
 
 use std::borrow::Cow;
-use std::cell::RefCell;
-use std::collections::HashSet;
+use std::cell::{Cell, RefCell};
+use std::collections::{BTreeMap, HashSet};
 use std::fmt;
 use std::num::NonZeroU64;
+use std::rc::Rc;
+use std::sync::Arc;
 use std::time::Instant;
 
 use rand::rngs::StdRng;
 use rand::SeedableRng;
+use smallvec::SmallVec;
 
 use rustc_ast::ast::Mutability;
-use rustc_data_structures::fx::FxHashMap;
+use rustc_data_structures::fx::{FxHashMap, FxHashSet};
 #[allow(unused)]
 use rustc_data_structures::static_assert_size;
 use rustc_middle::{
@@ -25,6 +28,7 @@ use rustc_middle::{
 };
 use rustc_span::def_id::{CrateNum, DefId};
 use rustc_span::symbol::{sym, Symbol};
+use rustc_span::SpanData;
 use rustc_target::abi::Size;
 use rustc_target::spec::abi::Abi;
 
@@ -32,9 +36,13 @@ use crate::*;
 
 // Some global facts about the emulated machine.
 pub const PAGE_SIZE: u64 = 4 * 1024; // FIXME: adjust to target architecture
-pub const STACK_ADDR: u64 = 32 * PAGE_SIZE; // not really about the "stack", but where we start assigning integer addresses to allocations
+/// Not really about the "stack", but where we start assigning integer addresses to allocations
+/// on targets with at least 32-bit pointers.
+pub const STACK_ADDR: u64 = 32 * PAGE_SIZE;
 pub const STACK_SIZE: u64 = 16 * PAGE_SIZE; // whatever
 pub const NUM_CPUS: u64 = 1;
+/// The id of the process, as returned by `getpid`.
+pub const PROCESS_ID: u32 = 100;
 
 /// Extra data stored with each stack frame
 pub struct FrameData<'tcx> {
@@ -46,19 +54,30 @@ pub struct FrameData<'tcx> {
     /// we stop unwinding, use the `CatchUnwindData` to handle catching.
     pub catch_unwind: Option<CatchUnwindData<'tcx>>,
 
+    /// If this is Some(), then this is a frame of the trace function called by
+    /// `_Unwind_Backtrace`. When this frame returns, we use the `UnwindBacktraceData` to call
+    /// the trace function with the next frame.
+    pub unwind_backtrace: Option<UnwindBacktraceData<'tcx>>,
+
     /// If `measureme` profiling is enabled, holds timing information
     /// for the start of this frame. When we finish executing this frame,
     /// we use this to register a completed event with `measureme`.
     pub timing: Option<measureme::DetachedTiming>,
+
+    /// If step profiling is enabled, holds the number of steps the current thread had executed
+    /// when this frame was pushed.
+    pub profile_steps: Option<u64>,
 }
 
 impl<'tcx> std::fmt::Debug for FrameData<'tcx> {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
         // Omitting `timing`, it does not support `Debug`.
-        let FrameData { call_id, catch_unwind, timing: _ } = self;
+        let FrameData { call_id, catch_unwind, unwind_backtrace, timing: _, profile_steps } = self;
         f.debug_struct("FrameData")
             .field("call_id", call_id)
             .field("catch_unwind", catch_unwind)
+            .field("unwind_backtrace", unwind_backtrace)
+            .field("profile_steps", profile_steps)
             .finish()
     }
 }
@@ -87,6 +106,9 @@ pub enum MiriMemoryKind {
     /// Memory for thread-local statics.
     /// This memory may leak.
     Tls,
+    /// Memory mapped with `mmap` or `VirtualAlloc`.
+    /// This memory may leak.
+    Mmap,
 }
 
 impl Into<MemoryKind<MiriMemoryKind>> for MiriMemoryKind {
@@ -102,7 +124,7 @@ impl MayLeak for MiriMemoryKind {
         use self::MiriMemoryKind::*;
         match self {
             Rust | C | WinHeap | Runtime => false,
-            Machine | Global | ExternStatic | Tls => true,
+            Machine | Global | ExternStatic | Tls | Mmap => true,
         }
     }
 }
@@ -119,6 +141,7 @@ impl fmt::Display for MiriMemoryKind {
             Global => write!(f, "global (static or const)"),
             ExternStatic => write!(f, "extern static"),
             Tls => write!(f, "thread-local static"),
+            Mmap => write!(f, "memory mapping"),
         }
     }
 }
@@ -171,6 +194,52 @@ pub struct AllocExtra {
     /// Data race detection via the use of a vector-clock,
     ///  this is only added if it is enabled.
     pub data_race: Option<data_race::AllocExtra>,
+    /// The ranges that were poisoned with `miri_poison_memory`.
+    pub poisoned: Vec<AllocRange>,
+    /// The ranges that were frozen with `miri_freeze`, and where that happened.
+    pub frozen: Vec<(AllocRange, SpanData)>,
+    /// Where the values in the allocation were written, if value origins are tracked.
+    pub value_origins: Option<value_origins::AllocExtra>,
+    /// The threads that read from the allocation since it was last written, to detect spin
+    /// loops.
+    pub readers: thread::Readers,
+    /// For `malloc` and `HeapAlloc` allocations, which of the two heaps they belong to.
+    pub heap: Option<MiriMemoryKind>,
+    /// The state of the pages of the allocations created by `mmap` and `VirtualAlloc`.
+    pub mapping: Option<shims::mapping::Mapping>,
+}
+
+impl AllocExtra {
+    /// Report an error if `range` overlaps memory that was poisoned with `miri_poison_memory`.
+    #[inline(always)]
+    fn check_poisoned<'tcx>(&self, alloc_id: AllocId, range: AllocRange) -> InterpResult<'tcx> {
+        for poisoned in &self.poisoned {
+            if poisoned.start < range.end() && range.start < poisoned.end() {
+                throw_ub_format!(
+                    "accessing memory at {:?}[{:#x}..{:#x}], which was poisoned by `miri_poison_memory`",
+                    alloc_id,
+                    range.start.bytes(),
+                    range.end().bytes(),
+                );
+            }
+        }
+        Ok(())
+    }
+
+    /// Stop the program if `range` overlaps memory that was frozen with `miri_freeze`.
+    #[inline(always)]
+    fn check_frozen<'tcx>(&self, alloc_id: AllocId, range: AllocRange) -> InterpResult<'tcx> {
+        for &(frozen, frozen_at) in &self.frozen {
+            if frozen.start < range.end() && range.start < frozen.end() {
+                throw_machine_stop!(TerminationInfo::WriteToFrozenMemory {
+                    alloc_id,
+                    range,
+                    frozen_at,
+                });
+            }
+        }
+        Ok(())
+    }
 }
 
 /// Precomputed layouts of primitive types
@@ -204,11 +273,33 @@ impl<'mir, 'tcx: 'mir> PrimitiveLayouts<'tcx> {
     }
 }
 
+/// Caches for the items that shims look up by path (such as `libc` constants and types),
+/// keyed by the interned segments of the path. Resolving a path requires walking the module tree
+/// of a crate, which is slow, and shims look up the same few paths over and over again.
+#[derive(Default)]
+pub struct PathCache<'tcx> {
+    pub(crate) instances: FxHashMap<PathKey, Instance<'tcx>>,
+    pub(crate) scalars: FxHashMap<PathKey, Scalar<Tag>>,
+    pub(crate) layouts: FxHashMap<PathKey, TyAndLayout<'tcx>>,
+}
+
+/// The key of the `PathCache`. Unlike joining the path into a `String`, building the key does not
+/// allocate: interning a segment that was seen before just looks it up.
+pub(crate) type PathKey = SmallVec<[Symbol; 6]>;
+
+impl PathCache<'_> {
+    pub(crate) fn key(path: &[&str]) -> PathKey {
+        path.iter().map(|segment| Symbol::intern(segment)).collect()
+    }
+}
+
 /// The machine itself.
 pub struct Evaluator<'mir, 'tcx> {
     pub stacked_borrows: Option<stacked_borrows::GlobalState>,
     pub data_race: Option<data_race::GlobalState>,
     pub intptrcast: intptrcast::GlobalState,
+    /// The state of value origin tracking, if `-Zmiri-track-value-origins` is set.
+    pub value_origins: Option<value_origins::GlobalState>,
 
     /// Environment variables set by `setenv`.
     /// Miri does not expose env vars from the host to the emulated program.
@@ -229,6 +320,12 @@ pub struct Evaluator<'mir, 'tcx> {
     /// file system access.
     pub(crate) isolated_op: IsolatedOp,
 
+    /// What should Miri do when the program tries to spawn a process.
+    pub(crate) process_spawn: ProcessSpawn,
+
+    /// Whether reallocations resize allocations in place.
+    pub(crate) realloc_policy: ReallocPolicy,
+
     /// Whether to enforce the validity invariant.
     pub(crate) validate: bool,
 
@@ -238,11 +335,49 @@ pub struct Evaluator<'mir, 'tcx> {
     /// Whether to enforce [ABI](Abi) of function calls.
     pub(crate) enforce_abi: bool,
 
-    pub(crate) file_handler: shims::posix::FileHandler,
+    pub(crate) file_handler: shims::posix::FileHandler<'tcx>,
     pub(crate) dir_handler: shims::posix::DirHandler,
-
-    /// The "time anchor" for this machine's monotone clock (for `Instant` simulation).
-    pub(crate) time_anchor: Instant,
+    /// The kernel objects of a Windows program.
+    pub(crate) windows_handles: shims::windows::Handles<'tcx>,
+
+    /// The interval timers and signal handlers of the program.
+    pub(crate) signals: shims::posix::Signals,
+    /// Whether expired timers deliver their signal.
+    pub(crate) deliver_timer_signals: bool,
+
+    /// The offset of the local time zone from UTC, in seconds.
+    pub(crate) utc_offset: i64,
+    /// The `tm_zone` strings that were handed out, by UTC offset.
+    pub(crate) time_zone_names: FxHashMap<i64, Pointer<Option<Tag>>>,
+
+    /// The host name reported by `uname` and `gethostname`.
+    pub(crate) hostname: String,
+    /// The kernel release reported by `uname`, if it was configured.
+    pub(crate) kernel_release: Option<String>,
+    /// The home directory of the user with isolation, if it was configured.
+    pub(crate) home_dir: Option<String>,
+    /// The directory for temporary files on Windows targets with isolation, if it was configured.
+    pub(crate) temp_dir: Option<String>,
+    /// The path of the executable on Windows targets.
+    pub(crate) exe_path: String,
+
+    /// The standard streams (as file descriptors) that are reported to be a terminal.
+    pub(crate) pretend_tty: Vec<i32>,
+    /// The size of the pretended terminal, as columns and rows.
+    pub(crate) tty_size: (u16, u16),
+
+    /// Whether `errno` is made uninitialized after successful calls to shims.
+    pub(crate) strict_errno: bool,
+    /// The shim that is currently being executed, if any.
+    pub(crate) current_shim: Option<Symbol>,
+    /// Whether the shim that is currently being executed set the OS error.
+    pub(crate) last_error_set_in_shim: bool,
+
+    /// Whether the process registered for private expedited `membarrier` commands.
+    pub(crate) membarrier_private_expedited: bool,
+
+    /// The monotonic clock of this machine (for `Instant` simulation).
+    pub(crate) clock: Clock,
 
     /// The set of threads.
     pub(crate) threads: ThreadManager<'mir, 'tcx>,
@@ -250,9 +385,22 @@ pub struct Evaluator<'mir, 'tcx> {
     /// Precomputed `TyLayout`s for primitive data types that are commonly used inside Miri.
     pub(crate) layouts: PrimitiveLayouts<'tcx>,
 
+    /// Where we start assigning integer addresses to allocations, which is also reported as the
+    /// address of the stack of every thread.
+    pub(crate) stack_addr: u64,
+    /// The size of the stack reported for every thread.
+    pub(crate) stack_size: u64,
+
+    /// An error raised by a machine hook that cannot fail, such as running out of addresses
+    /// while giving an allocation its base address. The interpreter loop reports it.
+    pub(crate) deferred_error: RefCell<Option<InterpErrorInfo<'tcx>>>,
+
     /// Allocations that are considered roots of static memory (that may leak).
     pub(crate) static_roots: Vec<AllocId>,
 
+    /// The seed of `rng`.
+    pub(crate) seed: u64,
+
     /// The `measureme` profiler used to record timing information about
     /// the emulated program.
     profiler: Option<measureme::Profiler>,
@@ -260,18 +408,45 @@ pub struct Evaluator<'mir, 'tcx> {
     /// uesd with `measureme`.
     string_cache: FxHashMap<String, measureme::StringId>,
 
+    /// The profiler counting interpreter steps per function, if enabled.
+    pub(crate) step_profiler: Option<StepProfiler<'tcx>>,
+
+    /// Where to write the state of the program if it stops with an error
+    /// (`-Zmiri-core-dump=<file>`).
+    pub(crate) core_dump: Option<String>,
+
+    /// The most recent calls to foreign functions, if a core dump was requested.
+    pub(crate) recent_shim_calls: Option<RecentShimCalls>,
+
+    /// The interactive debugger, if enabled.
+    pub(crate) debugger: Option<Debugger>,
+
+    /// The calls of local `const fn`s that are compared after the program ran, with
+    /// `-Zmiri-compare-ctfe`.
+    pub(crate) ctfe_comparison: Option<CtfeComparison<'tcx>>,
+
     /// Cache of `Instance` exported under the given `Symbol` name.
     /// `None` means no `Instance` exported under the given name is found.
     pub(crate) exported_symbols_cache: FxHashMap<Symbol, Option<Instance<'tcx>>>,
 
+    /// Cache of items resolved by path, see `PathCache`.
+    pub(crate) path_cache: RefCell<PathCache<'tcx>>,
+
     /// Whether to raise a panic in the context of the evaluated process when unsupported
     /// functionality is encountered. If `false`, an error is propagated in the Miri application context
     /// instead (default behavior)
     pub(crate) panic_on_unsupported: bool,
 
+    /// The foreign functions that were called but are not supported, with how often they were
+    /// called. There is more than one call only with `-Zmiri-panic-on-unsupported`.
+    pub(crate) unsupported_shims: BTreeMap<String, u64>,
+
     /// Equivalent setting as RUST_BACKTRACE on encountering an error.
     pub(crate) backtrace_style: BacktraceStyle,
 
+    /// Which frame the primary span of diagnostics points to.
+    pub(crate) error_span: ErrorSpan,
+
     /// Crates which are considered local for the purposes of error reporting.
     pub(crate) local_crates: Vec<CrateNum>,
 
@@ -282,15 +457,82 @@ pub struct Evaluator<'mir, 'tcx> {
     /// Needs to be queried by ptr_to_int, hence needs interior mutability.
     pub(crate) rng: RefCell<StdRng>,
 
+    /// Whether entropy is taken from the host. This is only the case when isolation is disabled and
+    /// no seed was given; otherwise all entropy is derived from `rng`.
+    pub(crate) host_entropy: bool,
+
     /// The allocation IDs to report when they are being allocated
     /// (helps for debugging memory leaks and use after free bugs).
-    tracked_alloc_ids: HashSet<AllocId>,
+    /// Allocations tracked by their stable ID are added when they are created.
+    tracked_alloc_ids: RefCell<HashSet<AllocId>>,
+
+    /// The allocations to report, identified by their stable ID.
+    tracked_stable_alloc_ids: HashSet<StableAllocId>,
+
+    /// The stable IDs of the allocations, if allocations are tracked or the IDs are printed.
+    pub(crate) stable_alloc_ids: Option<RefCell<StableAllocIds>>,
+
+    /// The watched memory, see `crate::watchpoints`.
+    pub(crate) watchpoints: watchpoints::GlobalState,
 
     /// Controls whether alignment of memory accesses is being checked.
     pub(crate) check_alignment: AlignmentCheck,
 
     /// Failure rate of compare_exchange_weak, between 0.0 and 1.0
     pub(crate) cmpxchg_weak_failure_rate: f64,
+
+    /// How often threads may yield without progress before we report a spin deadlock.
+    pub(crate) spin_loop_limit: Option<u64>,
+
+    /// The maximum number of threads that may be alive at the same time.
+    pub(crate) max_threads: Option<u64>,
+
+    /// What the error that is expected to stop the program must contain, if any.
+    pub(crate) expected_error: Option<String>,
+
+    /// External analyses observing memory accesses.
+    pub(crate) observers: Observers,
+
+    /// Statistics about the execution, if `-Zmiri-stats` is set.
+    pub(crate) stats: Option<Stats>,
+
+    /// How often each non-halting diagnostic was reported, to avoid repeating it too often.
+    pub(crate) diagnostic_counts: DiagnosticCounts,
+
+    /// The levels of the kinds of warnings that are not at their default level.
+    warning_levels: FxHashMap<WarningKind, WarningLevel>,
+
+    /// The providers that are asked to emulate foreign items, in order.
+    pub(crate) foreign_item_providers: Rc<[Arc<dyn ForeignItemProvider>]>,
+
+    /// The functions whose MIR was checked by `check_body_supported`.
+    pub(crate) checked_bodies: FxHashSet<Instance<'tcx>>,
+
+    /// Cache of the link names of the foreign items that were called.
+    pub(crate) foreign_item_link_names: FxHashMap<DefId, Symbol>,
+
+    /// Cache of the index in `foreign_item_providers` of the first provider that supports the
+    /// foreign item with the given link name, or the number of providers if none does. The
+    /// providers before it are not asked again.
+    pub(crate) foreign_item_provider_cache: FxHashMap<Symbol, usize>,
+
+    /// All live allocations, if `MiriConfig::collect_final_state` is set or the leak check is
+    /// enabled.
+    pub(crate) live_allocs: Option<RefCell<FxHashMap<AllocId, AllocationInfo<'tcx>>>>,
+
+    /// Whether to record which function created each heap allocation in `live_allocs`, for
+    /// grouping the leaks.
+    pub(crate) record_alloc_sites: bool,
+
+    /// Whether to record where heap allocations were created in `live_allocs`.
+    pub(crate) record_alloc_backtraces: bool,
+
+    /// Where the heap allocations that were freed were created, if `record_alloc_backtraces` is
+    /// set, so that errors about them can point there.
+    pub(crate) freed_alloc_backtraces: FxHashMap<AllocId, Vec<FrameInfo<'tcx>>>,
+
+    /// The total size of the live `malloc` allocations, for `mallinfo`.
+    pub(crate) c_heap_size: Cell<u64>,
 }
 
 impl<'mir, 'tcx> Evaluator<'mir, 'tcx> {
@@ -301,22 +543,44 @@ impl<'mir, 'tcx> Evaluator<'mir, 'tcx> {
         let profiler = config.measureme_out.as_ref().map(|out| {
             measureme::Profiler::new(out).expect("Couldn't create `measureme` profiler")
         });
-        let rng = StdRng::seed_from_u64(config.seed.unwrap_or(0));
+        let seed = config.seed.unwrap_or(0);
+        // SARIF output points to where the allocations that errors are about were created.
+        let record_alloc_backtraces = config.record_alloc_backtraces || config.sarif_out.is_some();
+        let rng = StdRng::seed_from_u64(seed);
+        // On targets with less than 32 bits of address space, 32 pages would already be more than
+        // half of all addresses.
+        let (stack_addr, stack_size) = if layout_cx.tcx.data_layout.pointer_size.bits() < 32 {
+            (PAGE_SIZE, 4 * PAGE_SIZE)
+        } else {
+            (STACK_ADDR, STACK_SIZE)
+        };
+        let policy = scheduling_policy(config);
         let stacked_borrows = if config.stacked_borrows {
             Some(RefCell::new(stacked_borrows::GlobalStateInner::new(
                 config.tracked_pointer_tags.clone(),
                 config.tracked_call_ids.clone(),
                 config.tag_raw,
+                config.sb_trace.clone(),
             )))
         } else {
             None
         };
         let data_race =
             if config.data_race_detector { Some(data_race::GlobalState::new()) } else { None };
+        let foreign_item_providers = config
+            .foreign_item_providers
+            .iter()
+            .cloned()
+            .chain(
+                shims::foreign_items::target_foreign_item_providers(&layout_cx.tcx.sess.target.os)
+                    .unwrap_or_default(),
+            )
+            .collect();
         Evaluator {
             stacked_borrows,
             data_race,
-            intptrcast: RefCell::new(intptrcast::GlobalStateInner::new(config)),
+            intptrcast: RefCell::new(intptrcast::GlobalStateInner::new(config, stack_addr)),
+            value_origins: config.track_value_origins.then(Default::default),
             // `env_vars` depends on a full interpreter so we cannot properly initialize it yet.
             env_vars: EnvVars::default(),
             argc: None,
@@ -324,35 +588,115 @@ impl<'mir, 'tcx> Evaluator<'mir, 'tcx> {
             cmd_line: None,
             tls: TlsData::default(),
             isolated_op: config.isolated_op,
+            process_spawn: config.process_spawn,
+            realloc_policy: config.realloc_policy,
             validate: config.validate,
             enforce_number_validity: config.check_number_validity,
             enforce_abi: config.check_abi,
             file_handler: Default::default(),
             dir_handler: Default::default(),
-            time_anchor: Instant::now(),
+            windows_handles: Default::default(),
+            signals: Default::default(),
+            deliver_timer_signals: config.deliver_timer_signals,
+            utc_offset: config.utc_offset,
+            time_zone_names: FxHashMap::default(),
+            hostname: config.hostname.clone(),
+            kernel_release: config.kernel_release.clone(),
+            home_dir: config.home_dir.clone(),
+            temp_dir: config.temp_dir.clone(),
+            exe_path: config.exe_path.clone().unwrap_or_else(|| {
+                // The first argument is the name of the program, see `miri.rs`.
+                let name = config.args.first().map_or("miri", String::as_str);
+                format!(r"C:\miri\{}.exe", name)
+            }),
+            pretend_tty: config.pretend_tty.clone(),
+            tty_size: config.tty_size,
+            strict_errno: config.strict_errno,
+            current_shim: None,
+            last_error_set_in_shim: false,
+            membarrier_private_expedited: false,
+            clock: Clock::new(config),
             layouts,
-            threads: ThreadManager::default(),
+            stack_addr,
+            stack_size,
+            deferred_error: RefCell::new(None),
+            threads: ThreadManager::new(policy, config.record_schedule.is_some()),
             static_roots: Vec::new(),
+            seed,
             profiler,
             string_cache: Default::default(),
+            step_profiler: config.profile_interpreted_out.clone().map(StepProfiler::new),
+            core_dump: config.core_dump.clone(),
+            recent_shim_calls: config.core_dump.as_ref().map(|_| RecentShimCalls::default()),
+            debugger: if config.debugger { Some(Debugger::default()) } else { None },
+            ctfe_comparison: config.compare_ctfe.then(CtfeComparison::default),
             exported_symbols_cache: FxHashMap::default(),
+            path_cache: RefCell::new(PathCache::default()),
             panic_on_unsupported: config.panic_on_unsupported,
+            unsupported_shims: BTreeMap::new(),
             backtrace_style: config.backtrace_style,
+            error_span: config.error_span,
             local_crates,
             extern_statics: FxHashMap::default(),
             rng: RefCell::new(rng),
-            tracked_alloc_ids: config.tracked_alloc_ids.clone(),
+            host_entropy: config.isolated_op == IsolatedOp::Allow && config.seed.is_none(),
+            tracked_alloc_ids: RefCell::new(config.tracked_alloc_ids.clone()),
+            tracked_stable_alloc_ids: config.tracked_stable_alloc_ids.clone(),
+            stable_alloc_ids: (config.print_alloc_ids
+                || !config.tracked_alloc_ids.is_empty()
+                || !config.tracked_stable_alloc_ids.is_empty())
+            .then(|| RefCell::new(StableAllocIds::new(config.print_alloc_ids))),
+            watchpoints: RefCell::new(watchpoints::GlobalStateInner::new(&config.watchpoints)),
             check_alignment: config.check_alignment,
             cmpxchg_weak_failure_rate: config.cmpxchg_weak_failure_rate,
+            spin_loop_limit: config.spin_loop_limit,
+            max_threads: config.max_threads,
+            expected_error: config.expected_error.clone(),
+            observers: Observers::new(&config.memory_observers),
+            stats: config.stats.then(Stats::default),
+            diagnostic_counts: DiagnosticCounts::default(),
+            warning_levels: config.warning_levels.iter().copied().collect(),
+            foreign_item_providers,
+            checked_bodies: FxHashSet::default(),
+            foreign_item_link_names: FxHashMap::default(),
+            foreign_item_provider_cache: FxHashMap::default(),
+            live_allocs: (config.collect_final_state
+                || !config.ignore_leaks
+                || record_alloc_backtraces)
+                .then(Default::default),
+            record_alloc_sites: config.collect_final_state
+                || (!config.ignore_leaks && config.leak_report != LeakReport::None),
+            record_alloc_backtraces,
+            freed_alloc_backtraces: FxHashMap::default(),
+            c_heap_size: Cell::new(0),
         }
     }
 
+    /// Registers an observer that is notified about the memory accesses of the program.
+    pub fn register_memory_observer(&self, observer: Box<dyn for<'a> MemoryObserver<'a>>) {
+        self.observers.register(observer);
+    }
+
     pub(crate) fn late_init(
         this: &mut MiriEvalContext<'mir, 'tcx>,
         config: &MiriConfig,
     ) -> InterpResult<'tcx> {
         EnvVars::init(this, config)?;
         Evaluator::init_extern_statics(this)?;
+        Evaluator::init_path_cache(this)?;
+        Ok(())
+    }
+
+    /// Pre-resolves the `libc` types that are used by the most common shims.
+    fn init_path_cache(this: &mut MiriEvalContext<'mir, 'tcx>) -> InterpResult<'tcx> {
+        if this.tcx.sess.target.families.iter().any(|f| f == "unix") {
+            for name in &["c_int", "size_t", "ssize_t", "off_t", "time_t", "timespec"] {
+                // Programs that do not link `libc` do not need these types.
+                if helpers::try_resolve_did(this.tcx.tcx, &["libc", name]).is_some() {
+                    this.libc_ty_layout(name)?;
+                }
+            }
+        }
         Ok(())
     }
 
@@ -400,13 +744,29 @@ impl<'mir, 'tcx> Evaluator<'mir, 'tcx> {
         Ok(())
     }
 
+    /// Remembers `err` for the interpreter loop to report, if no earlier error is pending, and
+    /// returns `dummy` for the hook to continue with.
+    pub(crate) fn defer_error<T>(&self, err: InterpErrorInfo<'tcx>, dummy: T) -> T {
+        self.deferred_error.borrow_mut().get_or_insert(err);
+        dummy
+    }
+
     pub(crate) fn communicate(&self) -> bool {
         self.isolated_op == IsolatedOp::Allow
     }
 
     /// Check whether the stack frame that this `FrameInfo` refers to is part of a local crate.
     pub(crate) fn is_local(&self, frame: &FrameInfo<'_>) -> bool {
-        let def_id = frame.instance.def_id();
+        self.is_local_def(frame.instance.def_id())
+    }
+
+    /// What to do about warnings of `kind`.
+    pub(crate) fn warning_level(&self, kind: WarningKind) -> WarningLevel {
+        self.warning_levels.get(&kind).copied().unwrap_or_else(|| kind.default_level())
+    }
+
+    /// Check whether `def_id` is part of a local crate.
+    pub(crate) fn is_local_def(&self, def_id: DefId) -> bool {
         def_id.is_local() || self.local_crates.contains(&def_id.krate)
     }
 }
@@ -561,12 +921,43 @@ impl<'mir, 'tcx> Machine<'mir, 'tcx> for Evaluator<'mir, 'tcx> {
         alloc: Cow<'b, Allocation>,
         kind: Option<MemoryKind<Self::MemoryKind>>,
     ) -> Cow<'b, Allocation<Self::PointerTag, Self::AllocExtra>> {
-        if ecx.machine.tracked_alloc_ids.contains(&id) {
-            register_diagnostic(NonHaltingDiagnostic::CreatedAlloc(id));
+        if let Some(stable_alloc_ids) = &ecx.machine.stable_alloc_ids {
+            let stable_id = stable_alloc_ids.borrow_mut().assign(id, ecx.get_active_thread());
+            if ecx.machine.tracked_stable_alloc_ids.contains(&stable_id) {
+                ecx.machine.tracked_alloc_ids.borrow_mut().insert(id);
+            }
+            if ecx.machine.tracked_alloc_ids.borrow().contains(&id) {
+                register_diagnostic(NonHaltingDiagnostic::CreatedAlloc(id, stable_id));
+            }
         }
 
-        let kind = kind.expect("we set our STATIC_KIND so this cannot be None");
+        // Global allocations come with our `GLOBAL_KIND`.
+        let kind = kind.unwrap_or(MemoryKind::Machine(MiriMemoryKind::Global));
         let alloc = alloc.into_owned();
+        if ecx.machine.watchpoints.borrow().is_watched(id) {
+            ecx.machine.watchpoints.borrow_mut().created(id, &alloc);
+        }
+        ecx.machine
+            .observers
+            .notify(|observer| {
+                observer.on_alloc(id, alloc.size(), kind);
+                Ok(())
+            })
+            .unwrap();
+        if let Some(live_allocs) = &ecx.machine.live_allocs {
+            live_allocs.borrow_mut().insert(id, AllocationInfo::new(ecx, kind));
+        }
+        if let Some(stats) = &ecx.machine.stats {
+            stats.allocated(alloc.size());
+        }
+        let heap = match kind {
+            MemoryKind::Machine(kind @ (MiriMemoryKind::C | MiriMemoryKind::WinHeap)) => Some(kind),
+            _ => None,
+        };
+        if heap == Some(MiriMemoryKind::C) {
+            let c_heap_size = &ecx.machine.c_heap_size;
+            c_heap_size.set(c_heap_size.get() + alloc.size().bytes());
+        }
         let stacks = if let Some(stacked_borrows) = &ecx.machine.stacked_borrows {
             Some(Stacks::new_allocation(id, alloc.size(), stacked_borrows, kind))
         } else {
@@ -577,9 +968,23 @@ impl<'mir, 'tcx> Machine<'mir, 'tcx> for Evaluator<'mir, 'tcx> {
         } else {
             None
         };
+        let value_origins = ecx
+            .machine
+            .value_origins
+            .as_ref()
+            .map(|_| value_origins::AllocExtra::new_allocation(alloc.size()));
         let alloc: Allocation<Tag, Self::AllocExtra> = alloc.convert_tag_add_extra(
             &ecx.tcx,
-            AllocExtra { stacked_borrows: stacks, data_race: race_alloc },
+            AllocExtra {
+                stacked_borrows: stacks,
+                data_race: race_alloc,
+                poisoned: Vec::new(),
+                frozen: Vec::new(),
+                value_origins,
+                readers: Default::default(),
+                heap,
+                mapping: None,
+            },
             |ptr| Evaluator::tag_alloc_base_pointer(ecx, ptr),
         );
         Cow::Owned(alloc)
@@ -589,7 +994,8 @@ impl<'mir, 'tcx> Machine<'mir, 'tcx> for Evaluator<'mir, 'tcx> {
         ecx: &MiriEvalContext<'mir, 'tcx>,
         ptr: Pointer<AllocId>,
     ) -> Pointer<Tag> {
-        let absolute_addr = intptrcast::GlobalStateInner::rel_ptr_to_addr(ecx, ptr);
+        let absolute_addr = intptrcast::GlobalStateInner::rel_ptr_to_addr(ecx, ptr)
+            .unwrap_or_else(|err| ecx.machine.defer_error(err, 0));
         let sb_tag = if let Some(stacked_borrows) = &ecx.machine.stacked_borrows {
             stacked_borrows.borrow_mut().base_tag(ptr.provenance)
         } else {
@@ -612,7 +1018,8 @@ impl<'mir, 'tcx> Machine<'mir, 'tcx> for Evaluator<'mir, 'tcx> {
         ecx: &MiriEvalContext<'mir, 'tcx>,
         ptr: Pointer<Self::PointerTag>,
     ) -> (AllocId, Size, Self::TagExtra) {
-        let rel = intptrcast::GlobalStateInner::abs_ptr_to_rel(ecx, ptr);
+        let rel = intptrcast::GlobalStateInner::abs_ptr_to_rel(ecx, ptr)
+            .unwrap_or_else(|err| ecx.machine.defer_error(err, Size::ZERO));
         (ptr.provenance.alloc_id, rel, ptr.provenance.sb)
     }
 
@@ -624,19 +1031,33 @@ impl<'mir, 'tcx> Machine<'mir, 'tcx> for Evaluator<'mir, 'tcx> {
         (alloc_id, tag): (AllocId, Self::TagExtra),
         range: AllocRange,
     ) -> InterpResult<'tcx> {
+        alloc_extra.check_poisoned(alloc_id, range)?;
+        if let Some(mapping) = &alloc_extra.mapping {
+            mapping.check_access(alloc_id, range, /* write */ false)?;
+        }
         if let Some(data_race) = &alloc_extra.data_race {
+            let _timer = machine.stats.as_ref().map(|stats| stats.time(Detector::DataRace));
             data_race.read(alloc_id, range, machine.data_race.as_ref().unwrap())?;
         }
         if let Some(stacked_borrows) = &alloc_extra.stacked_borrows {
+            let _timer = machine.stats.as_ref().map(|stats| stats.time(Detector::StackedBorrows));
             stacked_borrows.memory_read(
                 alloc_id,
                 tag,
                 range,
                 machine.stacked_borrows.as_ref().unwrap(),
-            )
-        } else {
-            Ok(())
+            )?;
+        }
+        if let Some(value_origins) = &alloc_extra.value_origins {
+            value_origins.read(alloc_id, range, machine.value_origins.as_ref().unwrap());
+        }
+        if machine.watchpoints.borrow().is_watched(alloc_id) {
+            machine.watchpoints.borrow_mut().access(alloc_id, range, /* write */ false);
         }
+        if machine.spin_loop_limit.is_some() {
+            machine.threads.note_read(&alloc_extra.readers);
+        }
+        machine.observers.notify(|observer| observer.on_read(alloc_id, range))
     }
 
     #[inline(always)]
@@ -647,19 +1068,35 @@ impl<'mir, 'tcx> Machine<'mir, 'tcx> for Evaluator<'mir, 'tcx> {
         (alloc_id, tag): (AllocId, Self::TagExtra),
         range: AllocRange,
     ) -> InterpResult<'tcx> {
+        alloc_extra.check_poisoned(alloc_id, range)?;
+        alloc_extra.check_frozen(alloc_id, range)?;
+        if let Some(mapping) = &alloc_extra.mapping {
+            mapping.check_access(alloc_id, range, /* write */ true)?;
+        }
         if let Some(data_race) = &mut alloc_extra.data_race {
+            let _timer = machine.stats.as_ref().map(|stats| stats.time(Detector::DataRace));
             data_race.write(alloc_id, range, machine.data_race.as_mut().unwrap())?;
         }
         if let Some(stacked_borrows) = &mut alloc_extra.stacked_borrows {
+            let _timer = machine.stats.as_ref().map(|stats| stats.time(Detector::StackedBorrows));
             stacked_borrows.memory_written(
                 alloc_id,
                 tag,
                 range,
                 machine.stacked_borrows.as_mut().unwrap(),
-            )
-        } else {
-            Ok(())
+            )?;
+        }
+        if let Some(value_origins) = &mut alloc_extra.value_origins {
+            value_origins.write(alloc_id, range, machine.value_origins.as_ref().unwrap());
+        }
+        let watchpoints = machine.watchpoints.get_mut();
+        if watchpoints.is_watched(alloc_id) {
+            watchpoints.access(alloc_id, range, /* write */ true);
         }
+        if machine.spin_loop_limit.is_some() {
+            machine.threads.note_write(&alloc_extra.readers);
+        }
+        machine.observers.notify(|observer| observer.on_write(alloc_id, range))
     }
 
     #[inline(always)]
@@ -670,22 +1107,41 @@ impl<'mir, 'tcx> Machine<'mir, 'tcx> for Evaluator<'mir, 'tcx> {
         (alloc_id, tag): (AllocId, Self::TagExtra),
         range: AllocRange,
     ) -> InterpResult<'tcx> {
-        if machine.tracked_alloc_ids.contains(&alloc_id) {
-            register_diagnostic(NonHaltingDiagnostic::FreedAlloc(alloc_id));
+        if let Some(stable_alloc_ids) = &mut machine.stable_alloc_ids {
+            let stable_alloc_ids = stable_alloc_ids.get_mut();
+            if machine.tracked_alloc_ids.get_mut().contains(&alloc_id) {
+                let stable_id = stable_alloc_ids.get(alloc_id).unwrap();
+                register_diagnostic(NonHaltingDiagnostic::FreedAlloc(alloc_id, stable_id));
+            }
+            stable_alloc_ids.deallocated(alloc_id);
+        }
+        machine.watchpoints.get_mut().deallocated(alloc_id);
+        if let Some(live_allocs) = &machine.live_allocs {
+            let info = live_allocs.borrow_mut().remove(&alloc_id);
+            if let Some(backtrace) = info.and_then(|info| info.backtrace) {
+                machine.freed_alloc_backtraces.insert(alloc_id, backtrace);
+            }
+        }
+        if alloc_extra.heap == Some(MiriMemoryKind::C) {
+            *machine.c_heap_size.get_mut() -= range.size.bytes();
+        }
+        if let Some(stats) = &machine.stats {
+            stats.deallocated(range.size);
         }
         if let Some(data_race) = &mut alloc_extra.data_race {
+            let _timer = machine.stats.as_ref().map(|stats| stats.time(Detector::DataRace));
             data_race.deallocate(alloc_id, range, machine.data_race.as_mut().unwrap())?;
         }
         if let Some(stacked_borrows) = &mut alloc_extra.stacked_borrows {
+            let _timer = machine.stats.as_ref().map(|stats| stats.time(Detector::StackedBorrows));
             stacked_borrows.memory_deallocated(
                 alloc_id,
                 tag,
                 range,
                 machine.stacked_borrows.as_mut().unwrap(),
-            )
-        } else {
-            Ok(())
+            )?;
         }
+        machine.observers.notify(|observer| observer.on_dealloc(alloc_id, range))
     }
 
     #[inline(always)]
@@ -694,7 +1150,15 @@ impl<'mir, 'tcx> Machine<'mir, 'tcx> for Evaluator<'mir, 'tcx> {
         kind: mir::RetagKind,
         place: &PlaceTy<'tcx, Tag>,
     ) -> InterpResult<'tcx> {
-        if ecx.machine.stacked_borrows.is_some() { ecx.retag(kind, place) } else { Ok(()) }
+        if ecx.machine.stacked_borrows.is_some() {
+            // The timer cannot borrow the machine while retagging, so measure by hand.
+            let start = ecx.machine.stats.as_ref().map(|_| Instant::now());
+            ecx.retag(kind, place)?;
+            if let (Some(stats), Some(start)) = (&ecx.machine.stats, start) {
+                stats.add_time(Detector::StackedBorrows, start.elapsed());
+            }
+        }
+        ecx.machine.observers.notify(|observer| observer.on_retag(kind, place))
     }
 
     #[inline(always)]
@@ -702,6 +1166,8 @@ impl<'mir, 'tcx> Machine<'mir, 'tcx> for Evaluator<'mir, 'tcx> {
         ecx: &mut InterpCx<'mir, 'tcx, Self>,
         frame: Frame<'mir, 'tcx, Tag>,
     ) -> InterpResult<'tcx, Frame<'mir, 'tcx, Tag, FrameData<'tcx>>> {
+        ecx.check_body_supported(frame.instance, frame.body)?;
+
         // Start recording our event before doing anything else
         let timing = if let Some(profiler) = ecx.machine.profiler.as_ref() {
             let fn_name = frame.instance.to_string();
@@ -722,7 +1188,19 @@ impl<'mir, 'tcx> Machine<'mir, 'tcx> for Evaluator<'mir, 'tcx> {
             stacked_borrows.borrow_mut().new_call()
         });
 
-        let extra = FrameData { call_id, catch_unwind: None, timing };
+        let profile_steps = ecx
+            .machine
+            .step_profiler
+            .as_ref()
+            .map(|profiler| profiler.current_steps(ecx.get_active_thread()));
+
+        let extra = FrameData {
+            call_id,
+            catch_unwind: None,
+            unwind_backtrace: None,
+            timing,
+            profile_steps,
+        };
         Ok(frame.with_extra(extra))
     }
 
@@ -750,10 +1228,18 @@ impl<'mir, 'tcx> Machine<'mir, 'tcx> for Evaluator<'mir, 'tcx> {
         unwinding: bool,
     ) -> InterpResult<'tcx, StackPopJump> {
         let timing = frame.extra.timing.take();
+        let profile_steps = frame.extra.profile_steps.take();
         let res = ecx.handle_stack_pop(frame.extra, unwinding);
         if let Some(profiler) = ecx.machine.profiler.as_ref() {
             profiler.finish_recording_interval_event(timing.unwrap());
         }
+        if let Some(steps_at_push) = profile_steps {
+            let thread = ecx.get_active_thread();
+            let recursive =
+                ecx.active_thread_stack().iter().any(|outer| outer.instance == frame.instance);
+            let profiler = ecx.machine.step_profiler.as_mut().unwrap();
+            profiler.frame_popped(thread, frame.instance, steps_at_push, recursive);
+        }
         res
     }
 }
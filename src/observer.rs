@@ -0,0 +1,90 @@
+//! Hooks that let external analyses observe the memory accesses of the interpreted program.
+//!
+//! Observers are created from the factories in `MiriConfig::memory_observers` whenever a machine
+//! is created, and are called by the machine after its own checks (Stacked Borrows, data race
+//! detection) have accepted an access. When no observer is registered, the only cost is one
+//! emptiness check per hook.
+
+use std::cell::RefCell;
+use std::fmt;
+use std::sync::Arc;
+
+use rustc_middle::mir::RetagKind;
+use rustc_target::abi::Size;
+
+use crate::*;
+
+/// Callbacks for memory events. All methods have empty default implementations, so an observer
+/// only has to implement what it is interested in. Returning an error stops the program with
+/// that error, which lets observers implement custom checkers.
+pub trait MemoryObserver<'tcx> {
+    /// A new allocation of `size` bytes was created.
+    fn on_alloc(&mut self, _id: AllocId, _size: Size, _kind: MemoryKind<MiriMemoryKind>) {}
+
+    /// `range` of allocation `id` was read.
+    fn on_read(&mut self, _id: AllocId, _range: AllocRange) -> InterpResult<'tcx> {
+        Ok(())
+    }
+
+    /// `range` of allocation `id` was written.
+    fn on_write(&mut self, _id: AllocId, _range: AllocRange) -> InterpResult<'tcx> {
+        Ok(())
+    }
+
+    /// Allocation `id`, covering `range`, is being deallocated.
+    fn on_dealloc(&mut self, _id: AllocId, _range: AllocRange) -> InterpResult<'tcx> {
+        Ok(())
+    }
+
+    /// The reference stored in `place` was retagged.
+    fn on_retag(&mut self, _kind: RetagKind, _place: &PlaceTy<'tcx, Tag>) -> InterpResult<'tcx> {
+        Ok(())
+    }
+}
+
+/// Creates a fresh observer for each execution of the program. The factory is part of the
+/// `MiriConfig`, which the driver hands to rustc, so it has to be thread-safe.
+#[derive(Clone)]
+pub struct ObserverFactory(
+    pub Arc<dyn Fn() -> Box<dyn for<'tcx> MemoryObserver<'tcx>> + Send + Sync>,
+);
+
+impl fmt::Debug for ObserverFactory {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str("ObserverFactory")
+    }
+}
+
+/// The observers registered with a machine.
+#[derive(Default)]
+pub struct Observers {
+    observers: RefCell<Vec<Box<dyn for<'tcx> MemoryObserver<'tcx>>>>,
+}
+
+impl Observers {
+    pub fn new(factories: &[ObserverFactory]) -> Self {
+        Observers {
+            observers: RefCell::new(factories.iter().map(|factory| (factory.0)()).collect()),
+        }
+    }
+
+    /// Registers another observer.
+    pub fn register(&self, observer: Box<dyn for<'tcx> MemoryObserver<'tcx>>) {
+        self.observers.borrow_mut().push(observer);
+    }
+
+    /// Calls `f` on each observer, stopping at the first error.
+    #[inline(always)]
+    pub fn notify<'tcx>(
+        &self,
+        mut f: impl FnMut(&mut dyn for<'a> MemoryObserver<'a>) -> InterpResult<'tcx>,
+    ) -> InterpResult<'tcx> {
+        if self.observers.borrow().is_empty() {
+            return Ok(());
+        }
+        for observer in self.observers.borrow_mut().iter_mut() {
+            f(&mut **observer)?;
+        }
+        Ok(())
+    }
+}
@@ -202,6 +202,31 @@ impl<T> RangeMap<T> {
         };
         slice.iter_mut().map(|elem| (Size::from_bytes(elem.range.start), &mut elem.data))
     }
+
+    /// Merges all neighbouring elements that overlap with the given range and for which `eq`
+    /// holds into one element. Unlike the opportunistic merging in `iter_mut`, this goes all the
+    /// way to the end of the range, so it should be used with a cheap `eq`.
+    pub fn merge_adjacent(&mut self, offset: Size, len: Size, eq: impl Fn(&T, &T) -> bool) {
+        let offset = offset.bytes();
+        let len = len.bytes();
+        if len == 0 {
+            return;
+        }
+        let first_idx = self.find_offset(offset);
+        let last_idx = self.find_offset(offset + len - 1);
+        // Compact `first_idx..=last_idx` in place: `write` is the element that the next one is
+        // merged into if they are equal.
+        let mut write = first_idx;
+        for read in first_idx + 1..=last_idx {
+            if eq(&self.v[write].data, &self.v[read].data) {
+                self.v[write].range.end = self.v[read].range.end;
+            } else {
+                write += 1;
+                self.v.swap(write, read);
+            }
+        }
+        self.v.drain(write + 1..=last_idx);
+    }
 }
 
 #[cfg(test)]
@@ -279,4 +304,27 @@ mod tests {
         assert_eq!(map.v.len(), 5);
         assert_eq!(to_vec(&map, 10, 10), vec![23, 42, 23, 23, 23, 19, 19, 19, 19, 19]);
     }
+
+    #[test]
+    fn merge_adjacent() {
+        let mut map = RangeMap::<i32>::new(Size::from_bytes(20), -1);
+        for i in 0..20 {
+            for (_, x) in map.iter_mut(Size::from_bytes(i), Size::from_bytes(1)) {
+                *x = if (5..15).contains(&i) { 42 } else { -1 };
+            }
+        }
+        let offsets = |map: &RangeMap<i32>, offset: u64, len: u64| {
+            map.iter(Size::from_bytes(offset), Size::from_bytes(len))
+                .map(|(offset, _)| offset.bytes())
+                .collect::<Vec<_>>()
+        };
+        // Only merge inside the given range.
+        map.merge_adjacent(Size::from_bytes(6), Size::from_bytes(4), |a, b| a == b);
+        assert_eq!(offsets(&map, 4, 8), vec![4, 5, 6, 10, 11]);
+        // Elements that are only partially in the range are merged as well.
+        map.merge_adjacent(Size::from_bytes(4), Size::from_bytes(13), |a, b| a == b);
+        assert_eq!(map.v.len(), 10);
+        assert_eq!(offsets(&map, 3, 14), vec![3, 4, 5, 15]);
+        assert_eq!(to_vec(&map, 3, 14), [vec![-1; 2], vec![42; 10], vec![-1; 2]].concat());
+    }
 }
@@ -0,0 +1,184 @@
+//! The structured result of running a program, for tools that embed Miri as a library.
+//!
+//! Diagnostics are still emitted through the compiler session as usual; the `Report` carries the
+//! same information in a form that does not require parsing them.
+
+use rustc_middle::ty::TyCtxt;
+use rustc_span::{Span, DUMMY_SP};
+
+use crate::*;
+
+/// What kind of problem stopped the program.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ErrorKind {
+    UndefinedBehavior,
+    /// The program did something that Miri does not support.
+    Unsupported,
+    ResourceExhaustion,
+    PostMonomorphization,
+    /// All threads were blocked, or kept yielding to each other without making progress.
+    Deadlock,
+    /// The program aborted.
+    Abort,
+    /// Symbols were defined multiple times, or clash with a built-in shim.
+    Linking,
+    /// Threads were still running (or were not joined) when the main thread terminated.
+    ThreadLeak,
+    /// Memory was still allocated when the program terminated.
+    MemoryLeak,
+    /// The program was expected to stop with an error, but it did not (or it stopped with a
+    /// different error, which is reported with its own kind).
+    MissingExpectedError,
+    /// A warning of a kind that was denied with `-Zmiri-deny`.
+    DeniedWarning,
+}
+
+impl ErrorKind {
+    /// A stable identifier of the kind, used as the rule id in SARIF output.
+    pub fn rule_id(self) -> &'static str {
+        match self {
+            ErrorKind::UndefinedBehavior => "undefined-behavior",
+            ErrorKind::Unsupported => "unsupported",
+            ErrorKind::ResourceExhaustion => "resource-exhaustion",
+            ErrorKind::PostMonomorphization => "post-monomorphization",
+            ErrorKind::Deadlock => "deadlock",
+            ErrorKind::Abort => "abort",
+            ErrorKind::Linking => "linking",
+            ErrorKind::ThreadLeak => "thread-leak",
+            ErrorKind::MemoryLeak => "memory-leak",
+            ErrorKind::MissingExpectedError => "missing-expected-error",
+            ErrorKind::DeniedWarning => "denied-warning",
+        }
+    }
+
+    /// A short description of the kind.
+    pub fn description(self) -> &'static str {
+        match self {
+            ErrorKind::UndefinedBehavior => "the program has Undefined Behavior",
+            ErrorKind::Unsupported => "the program did something that Miri does not support",
+            ErrorKind::ResourceExhaustion => "the program exhausted a resource",
+            ErrorKind::PostMonomorphization =>
+                "the program failed to compile after monomorphization",
+            ErrorKind::Deadlock => "the threads of the program deadlocked",
+            ErrorKind::Abort => "the program aborted",
+            ErrorKind::Linking => "the program defines clashing symbols",
+            ErrorKind::ThreadLeak => "the program did not wait for all of its threads",
+            ErrorKind::MemoryLeak => "the program leaked memory",
+            ErrorKind::MissingExpectedError =>
+                "the program did not stop with the error it was expected to",
+            ErrorKind::DeniedWarning => "the program triggered a denied warning",
+        }
+    }
+}
+
+/// A source location.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Location {
+    pub file: String,
+    /// 1-based line number.
+    pub line: usize,
+    /// 0-based column, in characters.
+    pub column: usize,
+}
+
+impl Location {
+    /// Look up the location of `span`. Returns `None` for dummy spans.
+    pub fn from_span(tcx: TyCtxt<'_>, span: Span) -> Option<Self> {
+        if span == DUMMY_SP {
+            return None;
+        }
+        let loc = tcx.sess.source_map().lookup_char_pos(span.lo());
+        Some(Location {
+            file: loc.file.name.prefer_local().to_string(),
+            line: loc.line,
+            column: loc.col.0,
+        })
+    }
+}
+
+/// A frame of the stacktrace of an error.
+#[derive(Debug, Clone)]
+pub struct ReportFrame {
+    /// The function that was executing, as printed in diagnostics.
+    pub function: String,
+    pub location: Option<Location>,
+}
+
+impl ReportFrame {
+    pub fn new<'tcx>(tcx: TyCtxt<'tcx>, frame: &FrameInfo<'tcx>) -> Self {
+        ReportFrame {
+            function: frame.instance.to_string(),
+            location: Location::from_span(tcx, frame.span),
+        }
+    }
+}
+
+/// The error that stopped the program.
+#[derive(Debug, Clone)]
+pub struct ErrorReport {
+    pub kind: ErrorKind,
+    /// The error code, for errors that have one; see `miri --explain`.
+    pub code: Option<&'static str>,
+    /// The main message of the diagnostic, without the title that describes the kind.
+    pub message: String,
+    /// The (pruned) stacktrace of the active thread, innermost frame first.
+    pub stacktrace: Vec<ReportFrame>,
+    /// Other locations that the diagnostic points to, such as where the allocation that was
+    /// accessed was created, with a message that describes each of them.
+    pub related: Vec<(Location, String)>,
+}
+
+/// Leaks detected after the program terminated. Only filled in if the leak checks ran.
+#[derive(Debug, Clone, Default)]
+pub struct LeakSummary {
+    /// The number of allocations that were leaked.
+    pub leaked_allocations: usize,
+    /// The names of the threads that were reported by the thread leak check.
+    pub leaked_threads: Vec<String>,
+}
+
+/// Statistics about the execution.
+#[derive(Debug, Clone, Default)]
+pub struct Statistics {
+    /// How many times the program was executed; more than once with `-Zmiri-scheduler=exhaustive`.
+    pub executions: u64,
+    /// Interpreter steps taken by the last execution, across all threads.
+    pub steps: u64,
+    /// Threads that were created by the last execution, including the main thread.
+    pub threads: usize,
+    /// Allocations that were created by the last execution (only counted with
+    /// `MiriConfig::stats`).
+    pub allocations: u64,
+    /// Allocations that were freed by the last execution (only counted with `MiriConfig::stats`).
+    pub deallocations: u64,
+    /// The largest total size of the live allocations of the last execution, in bytes (only
+    /// measured with `MiriConfig::stats`).
+    pub peak_memory: u64,
+}
+
+/// The result of running a program with `run_analysis`.
+#[derive(Debug, Clone, Default)]
+pub struct Report {
+    /// The exit code of the program, if it returned from `main` or called `exit`.
+    pub exit_code: Option<i64>,
+    /// The error that stopped the program, if any. Leaks are reported here too, in which case
+    /// `exit_code` is the code the program exited with before the leak check failed.
+    pub error: Option<ErrorReport>,
+    pub leaks: LeakSummary,
+    pub statistics: Statistics,
+    /// The foreign functions that the program called but Miri does not support, sorted by name,
+    /// with how often they were called. Without `MiriConfig::panic_on_unsupported`, the first
+    /// such call stops the program.
+    pub unsupported_shims: Vec<(String, u64)>,
+    /// The state of the machine after the program terminated, if
+    /// `MiriConfig::collect_final_state` is set.
+    pub final_state: Option<FinalState>,
+}
+
+impl Report {
+    /// The exit code Miri uses for this result: the program's exit code if it terminated
+    /// without errors, `None` otherwise.
+    pub fn return_code(&self) -> Option<i64> {
+        if self.error.is_some() { None } else { self.exit_code }
+    }
+}
@@ -0,0 +1,146 @@
+//! Writes the result of an analysis in the [SARIF] format, which code scanning services such as
+//! GitHub's can display, enabled with `-Zmiri-sarif=<file>`. There is one result for the error
+//! that stopped the program, if any, with the error kind as its rule id. The foreign functions
+//! that Miri does not support are listed in the `unsupportedShims` property of the run.
+//!
+//! `cargo miri` gets the same list of foreign functions as a plain JSON object, written by
+//! `write_unsupported_shims`.
+//!
+//! [SARIF]: https://docs.oasis-open.org/sarif/sarif/v2.1.0/sarif-v2.1.0.html
+
+use std::fmt::Write;
+
+use crate::*;
+
+/// `s` as a JSON string literal.
+pub(crate) fn json_str(s: &str) -> String {
+    let mut out = String::with_capacity(s.len() + 2);
+    out.push('"');
+    for c in s.chars() {
+        match c {
+            '"' => out.push_str("\\\""),
+            '\\' => out.push_str("\\\\"),
+            '\n' => out.push_str("\\n"),
+            '\r' => out.push_str("\\r"),
+            '\t' => out.push_str("\\t"),
+            c if c.is_control() => write!(out, "\\u{:04x}", c as u32).unwrap(),
+            c => out.push(c),
+        }
+    }
+    out.push('"');
+    out
+}
+
+fn physical_location(location: &Location) -> String {
+    format!(
+        r#"{{"artifactLocation":{{"uri":{}}},"region":{{"startLine":{},"startColumn":{}}}}}"#,
+        json_str(&location.file.replace('\\', "/")),
+        location.line,
+        location.column + 1
+    )
+}
+
+fn result(error: &ErrorReport) -> String {
+    let mut out = format!(
+        r#"{{"ruleId":{},"level":"error","message":{{"text":{}}}"#,
+        json_str(error.kind.rule_id()),
+        json_str(&error.message)
+    );
+    // The error is reported at the innermost frame that has a location.
+    if let Some(location) = error.stacktrace.iter().find_map(|frame| frame.location.as_ref()) {
+        write!(out, r#","locations":[{{"physicalLocation":{}}}]"#, physical_location(location))
+            .unwrap();
+    }
+    if !error.related.is_empty() {
+        let related: Vec<String> = error
+            .related
+            .iter()
+            .enumerate()
+            .map(|(id, (location, msg))| {
+                format!(
+                    r#"{{"id":{},"physicalLocation":{},"message":{{"text":{}}}}}"#,
+                    id,
+                    physical_location(location),
+                    json_str(msg)
+                )
+            })
+            .collect();
+        write!(out, r#","relatedLocations":[{}]"#, related.join(",")).unwrap();
+    }
+    if !error.stacktrace.is_empty() {
+        let frames: Vec<String> = error
+            .stacktrace
+            .iter()
+            .map(|frame| {
+                let mut location = format!(
+                    r#"{{"logicalLocations":[{{"fullyQualifiedName":{}}}]"#,
+                    json_str(&frame.function)
+                );
+                if let Some(loc) = &frame.location {
+                    write!(location, r#","physicalLocation":{}"#, physical_location(loc)).unwrap();
+                }
+                format!(r#"{{"location":{}}}}}"#, location)
+            })
+            .collect();
+        write!(out, r#","stacks":[{{"frames":[{}]}}]"#, frames.join(",")).unwrap();
+    }
+    if let Some(code) = error.code {
+        write!(out, r#","properties":{{"code":{}}}"#, json_str(code)).unwrap();
+    }
+    out.push('}');
+    out
+}
+
+/// The unsupported foreign functions of `report` as a JSON object, mapping their names to how
+/// often they were called.
+fn unsupported_shims_json(report: &Report) -> String {
+    let shims: Vec<String> = report
+        .unsupported_shims
+        .iter()
+        .map(|(name, calls)| format!("{}:{}", json_str(name), calls))
+        .collect();
+    format!("{{{}}}", shims.join(","))
+}
+
+/// Writes `report` to the file `path` in the SARIF format.
+pub fn write_sarif(report: &Report, path: &str) -> std::io::Result<()> {
+    let (rules, results) = match &report.error {
+        Some(error) => {
+            let rule = format!(
+                r#"{{"id":{},"shortDescription":{{"text":{}}}}}"#,
+                json_str(error.kind.rule_id()),
+                json_str(error.kind.description())
+            );
+            (rule, result(error))
+        }
+        None => (String::new(), String::new()),
+    };
+    let properties = if report.unsupported_shims.is_empty() {
+        String::new()
+    } else {
+        format!(r#","properties":{{"unsupportedShims":{}}}"#, unsupported_shims_json(report))
+    };
+    let sarif = format!(
+        concat!(
+            r#"{{"version":"2.1.0","#,
+            r#""$schema":"https://json.schemastore.org/sarif-2.1.0.json","#,
+            r#""runs":[{{"tool":{{"driver":{{"name":"miri","#,
+            r#""informationUri":"https://github.com/rust-lang/miri","rules":[{}]}}}},"#,
+            r#""results":[{}]{}}}]}}"#,
+            "\n"
+        ),
+        rules, results, properties
+    );
+    std::fs::write(path, sarif)
+}
+
+/// Writes the unsupported foreign functions of `report`, if there are any, to a new file in the
+/// directory `dir`, as a JSON object that maps their names to how often they were called.
+pub fn write_unsupported_shims(report: &Report, dir: &str) -> std::io::Result<()> {
+    if report.unsupported_shims.is_empty() {
+        return Ok(());
+    }
+    // Binaries (e.g. doctests) may run concurrently, so use our process id to pick a unique name.
+    let path = std::path::Path::new(dir).join(format!("{}.json", std::process::id()));
+    std::fs::write(path, unsupported_shims_json(report) + "\n")
+}
@@ -0,0 +1,505 @@
+//! Scheduling policies: the part of the scheduler that decides which thread runs next.
+//!
+//! The `ThreadManager` takes care of everything that does not depend on the policy (thread
+//! termination, timeouts, detecting deadlocks); it asks the policy whether to preempt the active
+//! thread, and which thread to switch to.
+
+use std::cell::RefCell;
+use std::collections::VecDeque;
+use std::fmt;
+use std::fmt::Write as _;
+use std::io;
+use std::rc::Rc;
+use std::sync::Arc;
+
+use rand::rngs::StdRng;
+use rand::{Rng, SeedableRng};
+use rustc_index::vec::Idx;
+
+use crate::*;
+
+/// How often the round-robin policy switches threads, in interpreter steps.
+const ROUND_ROBIN_QUANTUM: u64 = 1000;
+
+/// The chance of the random policies to preempt the active thread before each step.
+const RANDOM_PREEMPTION_RATE: f64 = 0.01;
+
+/// Decides which thread to run next.
+pub trait SchedulingPolicy: fmt::Debug {
+    /// Called before each step of the active thread while it is enabled and did not yield.
+    /// Returning `true` switches to another thread (if there is one) even though the active
+    /// thread could continue. `at_sync_point` is set if the last step performed an atomic or
+    /// synchronization operation and there is another thread that could run instead.
+    fn preempt(&mut self, _active: ThreadId, _at_sync_point: bool) -> bool {
+        false
+    }
+
+    /// Picks the thread to run next among `enabled`, which is sorted by thread id and never
+    /// empty. `active` is the thread that ran last. `yielded` is set when `active` yielded or was
+    /// preempted, rather than being blocked; in that case it is only part of `enabled` if there
+    /// is no other thread to switch to.
+    fn pick_next(&mut self, active: ThreadId, yielded: bool, enabled: &[ThreadId]) -> ThreadId;
+}
+
+/// Creates the scheduling policy of each execution, for policies that are not built into Miri.
+/// The argument is the seed of the execution. The factory is part of the `MiriConfig`, which the
+/// driver hands to rustc, so it has to be thread-safe.
+#[derive(Clone)]
+pub struct PolicyFactory(pub Arc<dyn Fn(u64) -> Box<dyn SchedulingPolicy> + Send + Sync>);
+
+impl fmt::Debug for PolicyFactory {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str("PolicyFactory")
+    }
+}
+
+/// Creates the policy of an execution: the replayed schedule if there is one, then the custom
+/// policy if there is one, and otherwise the built-in policy selected by the configuration.
+pub fn scheduling_policy(config: &MiriConfig) -> Box<dyn SchedulingPolicy> {
+    let seed = config.seed.unwrap_or(0);
+    match (&config.replay_schedule, &config.custom_scheduling_policy) {
+        (Some(events), _) => Box::new(Replay::new(events.clone())),
+        (None, Some(factory)) => (factory.0)(seed),
+        (None, None) => new_scheduling_policy(config.scheduling_policy, seed),
+    }
+}
+
+/// Creates the policy selected by `kind`. `seed` is used by the randomized policies.
+pub fn new_scheduling_policy(kind: SchedulingPolicyKind, seed: u64) -> Box<dyn SchedulingPolicy> {
+    match kind {
+        SchedulingPolicyKind::Cooperative => Box::new(Cooperative),
+        SchedulingPolicyKind::RoundRobin => Box::new(RoundRobin { steps: 0 }),
+        SchedulingPolicyKind::Priority =>
+            Box::new(Priority {
+                rng: StdRng::seed_from_u64(seed),
+                priorities: Vec::new(),
+                last_demoted: 0,
+            }),
+        SchedulingPolicyKind::Random =>
+            Box::new(Random { rng: StdRng::seed_from_u64(seed), preemptions_left: None }),
+        SchedulingPolicyKind::PreemptionBounded(bound) =>
+            Box::new(Random { rng: StdRng::seed_from_u64(seed), preemptions_left: Some(bound) }),
+        // `eval_entry` replaces this with an `Explore` policy for each execution.
+        SchedulingPolicyKind::Exhaustive(_) => Box::new(Cooperative),
+    }
+}
+
+/// The first thread in `enabled` that comes after `active`, wrapping around.
+fn next_after(active: ThreadId, enabled: &[ThreadId]) -> ThreadId {
+    enabled.iter().copied().find(|&id| id > active).unwrap_or(enabled[0])
+}
+
+/// The policy that is commonly used in stateless model checkers such as Loom: run the active
+/// thread as long as we can and switch only when we have to (the active thread was blocked,
+/// terminated, or has explicitly asked to be preempted). Then we pick the thread with the lowest
+/// id, unless the active thread yielded: in that case we continue with the threads after it, so
+/// that threads that keep yielding to each other cannot starve the remaining threads.
+#[derive(Debug)]
+struct Cooperative;
+
+impl SchedulingPolicy for Cooperative {
+    fn pick_next(&mut self, active: ThreadId, yielded: bool, enabled: &[ThreadId]) -> ThreadId {
+        if yielded { next_after(active, enabled) } else { enabled[0] }
+    }
+}
+
+/// Switches to the next thread every `ROUND_ROBIN_QUANTUM` steps.
+#[derive(Debug)]
+struct RoundRobin {
+    /// Steps taken by the active thread since it was scheduled.
+    steps: u64,
+}
+
+impl SchedulingPolicy for RoundRobin {
+    fn preempt(&mut self, _active: ThreadId, _at_sync_point: bool) -> bool {
+        self.steps += 1;
+        self.steps > ROUND_ROBIN_QUANTUM
+    }
+
+    fn pick_next(&mut self, active: ThreadId, _yielded: bool, enabled: &[ThreadId]) -> ThreadId {
+        self.steps = 0;
+        next_after(active, enabled)
+    }
+}
+
+/// Probabilistic concurrency testing: every thread gets a random priority when the policy first
+/// sees it, and the enabled thread with the highest priority runs. At random synchronization
+/// points, the priority of the active thread drops below that of all other threads, which
+/// preempts it. Unlike `Random`, this runs the same thread for long stretches, which is good at
+/// finding bugs that need a few well-placed preemptions.
+#[derive(Debug)]
+struct Priority {
+    rng: StdRng,
+    /// The priorities of the threads the policy has seen so far, indexed by thread id.
+    priorities: Vec<i64>,
+    /// The priority of the thread that was demoted last, which is lower than all others.
+    last_demoted: i64,
+}
+
+impl Priority {
+    fn priority(&mut self, thread: ThreadId) -> i64 {
+        while self.priorities.len() <= thread.index() {
+            let priority = self.rng.gen_range(0..i64::MAX);
+            self.priorities.push(priority);
+        }
+        self.priorities[thread.index()]
+    }
+}
+
+impl SchedulingPolicy for Priority {
+    fn preempt(&mut self, active: ThreadId, at_sync_point: bool) -> bool {
+        if !at_sync_point || !self.rng.gen_bool(RANDOM_PREEMPTION_RATE) {
+            return false;
+        }
+        self.priority(active);
+        self.last_demoted -= 1;
+        self.priorities[active.index()] = self.last_demoted;
+        true
+    }
+
+    fn pick_next(&mut self, _active: ThreadId, _yielded: bool, enabled: &[ThreadId]) -> ThreadId {
+        // `enabled` is sorted, so ties go to the thread with the lowest id.
+        let mut next = enabled[0];
+        for &thread in &enabled[1..] {
+            if self.priority(thread) > self.priority(next) {
+                next = thread;
+            }
+        }
+        next
+    }
+}
+
+/// Preempts the active thread at random points, and picks a random thread to continue with.
+/// If `preemptions_left` is set, at most that many preemptions happen during the execution.
+#[derive(Debug)]
+struct Random {
+    rng: StdRng,
+    preemptions_left: Option<u64>,
+}
+
+impl SchedulingPolicy for Random {
+    fn preempt(&mut self, _active: ThreadId, _at_sync_point: bool) -> bool {
+        if self.preemptions_left == Some(0) || !self.rng.gen_bool(RANDOM_PREEMPTION_RATE) {
+            return false;
+        }
+        if let Some(preemptions_left) = &mut self.preemptions_left {
+            *preemptions_left -= 1;
+        }
+        true
+    }
+
+    fn pick_next(&mut self, _active: ThreadId, _yielded: bool, enabled: &[ThreadId]) -> ThreadId {
+        enabled[self.rng.gen_range(0..enabled.len())]
+    }
+}
+
+/// One scheduling decision: after the active thread took `steps` steps, we switched to `next`,
+/// either because the policy preempted the active thread, or because it blocked, terminated or
+/// yielded.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct ScheduleEvent {
+    steps: u64,
+    preempted: bool,
+    next: ThreadId,
+}
+
+/// Records the scheduling decisions of an execution, so that they can be replayed later with
+/// `-Zmiri-replay-schedule`.
+#[derive(Debug, Default)]
+pub struct ScheduleRecorder {
+    /// The steps the active thread took since the last switch.
+    steps: u64,
+    /// Whether the policy decided to preempt the active thread.
+    preempted: bool,
+    events: Vec<ScheduleEvent>,
+}
+
+impl ScheduleRecorder {
+    /// Records that the policy was asked whether to preempt the active thread.
+    pub fn step(&mut self, preempted: bool) {
+        self.steps += 1;
+        self.preempted |= preempted;
+    }
+
+    /// Records that the scheduler switched to `next`.
+    pub fn switch(&mut self, next: ThreadId) {
+        self.events.push(ScheduleEvent { steps: self.steps, preempted: self.preempted, next });
+        self.steps = 0;
+        self.preempted = false;
+    }
+
+    /// Writes the schedule to `path`, one decision per line.
+    pub fn write(&self, path: &str) -> io::Result<()> {
+        let mut out = String::new();
+        for event in &self.events {
+            let kind = if event.preempted { "preempt" } else { "switch" };
+            writeln!(out, "{} {} {}", event.steps, kind, event.next.to_u32()).unwrap();
+        }
+        std::fs::write(path, out)
+    }
+}
+
+/// Reads a schedule written by `ScheduleRecorder::write`.
+pub fn read_schedule(path: &str) -> io::Result<Vec<ScheduleEvent>> {
+    let invalid = |line: &str| {
+        io::Error::new(io::ErrorKind::InvalidData, format!("invalid schedule entry `{}`", line))
+    };
+    let mut events = Vec::new();
+    for line in std::fs::read_to_string(path)?.lines() {
+        let parts: Vec<&str> = line.split_whitespace().collect();
+        let (steps, preempted, next) = match parts.as_slice() {
+            [steps, "preempt", next] => (steps, true, next),
+            [steps, "switch", next] => (steps, false, next),
+            _ => return Err(invalid(line)),
+        };
+        let steps = steps.parse::<u64>().map_err(|_| invalid(line))?;
+        let next = next.parse::<usize>().map_err(|_| invalid(line))?;
+        events.push(ScheduleEvent { steps, preempted, next: ThreadId::new(next) });
+    }
+    Ok(events)
+}
+
+/// Replays a recorded schedule. Threads are identified by the order in which they were spawned,
+/// so the schedule can still be followed (as far as it makes sense) after small changes to the
+/// program. Where it cannot, and once the schedule is exhausted, we fall back to `Cooperative`.
+#[derive(Debug)]
+pub struct Replay {
+    events: VecDeque<ScheduleEvent>,
+    /// The steps the active thread took since the last switch.
+    steps: u64,
+}
+
+impl Replay {
+    pub fn new(events: Vec<ScheduleEvent>) -> Self {
+        Replay { events: events.into(), steps: 0 }
+    }
+}
+
+impl SchedulingPolicy for Replay {
+    fn preempt(&mut self, _active: ThreadId, _at_sync_point: bool) -> bool {
+        self.steps += 1;
+        match self.events.front() {
+            Some(event) => event.preempted && self.steps >= event.steps,
+            None => false,
+        }
+    }
+
+    fn pick_next(&mut self, active: ThreadId, yielded: bool, enabled: &[ThreadId]) -> ThreadId {
+        self.steps = 0;
+        match self.events.pop_front() {
+            Some(event) if enabled.contains(&event.next) => event.next,
+            _ => Cooperative.pick_next(active, yielded, enabled),
+        }
+    }
+}
+
+/// A scheduling decision made during exhaustive exploration.
+#[derive(Debug, Clone, Copy)]
+struct Choice {
+    /// The option we take in the current execution.
+    taken: usize,
+    /// How many options there were.
+    options: usize,
+}
+
+/// The state of an exhaustive exploration of all interleavings with at most `bound` preemptions,
+/// shared between the driver in `eval_entry` and the `Explore` policy of each execution.
+///
+/// Every execution starts from scratch and follows the choices made by the previous execution,
+/// up to the last choice that still has options left, which is advanced by `next_execution`.
+/// This relies on the program being deterministic apart from scheduling; if an execution
+/// diverges from the recorded choices, exploration continues from the point of divergence.
+#[derive(Debug)]
+pub struct Exploration {
+    bound: u64,
+    choices: Vec<Choice>,
+    /// The index of the next choice in the current execution.
+    pos: usize,
+    /// How many preemptions happened in the current execution.
+    preemptions: u64,
+}
+
+impl Exploration {
+    pub fn new(bound: u64) -> Self {
+        Exploration { bound, choices: Vec::new(), pos: 0, preemptions: 0 }
+    }
+
+    /// Picks one of `options` options; option `0` is the one the `Cooperative` policy would take.
+    fn choose(&mut self, options: usize) -> usize {
+        if let Some(choice) = self.choices.get(self.pos) {
+            if choice.options == options {
+                self.pos += 1;
+                return choice.taken;
+            }
+            // The execution diverged, forget about the rest of the previous execution.
+            self.choices.truncate(self.pos);
+        }
+        self.choices.push(Choice { taken: 0, options });
+        self.pos += 1;
+        0
+    }
+
+    /// Prepares the next execution. Returns `false` if all executions have been explored.
+    pub fn next_execution(&mut self) -> bool {
+        self.pos = 0;
+        self.preemptions = 0;
+        while let Some(last) = self.choices.last_mut() {
+            if last.taken + 1 < last.options {
+                last.taken += 1;
+                return true;
+            }
+            self.choices.pop();
+        }
+        false
+    }
+}
+
+/// Follows and extends an `Exploration`.
+#[derive(Debug)]
+pub struct Explore(pub Rc<RefCell<Exploration>>);
+
+impl SchedulingPolicy for Explore {
+    fn preempt(&mut self, _active: ThreadId, at_sync_point: bool) -> bool {
+        let mut exploration = self.0.borrow_mut();
+        if !at_sync_point || exploration.preemptions >= exploration.bound {
+            return false;
+        }
+        let preempt = exploration.choose(2) == 1;
+        if preempt {
+            exploration.preemptions += 1;
+        }
+        preempt
+    }
+
+    fn pick_next(&mut self, active: ThreadId, yielded: bool, enabled: &[ThreadId]) -> ThreadId {
+        let first = Cooperative.pick_next(active, yielded, enabled);
+        if enabled.len() == 1 {
+            return first;
+        }
+        // Rotate the options so that the first option is the `Cooperative` choice.
+        let start = enabled.iter().position(|&id| id == first).unwrap();
+        let taken = self.0.borrow_mut().choose(enabled.len());
+        enabled[(start + taken) % enabled.len()]
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn threads(ids: &[u32]) -> Vec<ThreadId> {
+        ids.iter().copied().map(ThreadId::from).collect()
+    }
+
+    #[test]
+    fn test_cooperative() {
+        let mut policy = new_scheduling_policy(SchedulingPolicyKind::Cooperative, 0);
+        assert!(!policy.preempt(ThreadId::from(0), true));
+        assert_eq!(
+            policy.pick_next(ThreadId::from(1), false, &threads(&[0, 2])),
+            ThreadId::from(0)
+        );
+        assert_eq!(policy.pick_next(ThreadId::from(1), true, &threads(&[0, 2])), ThreadId::from(2));
+        assert_eq!(policy.pick_next(ThreadId::from(2), true, &threads(&[0, 1])), ThreadId::from(0));
+    }
+
+    #[test]
+    fn test_round_robin() {
+        let mut policy = new_scheduling_policy(SchedulingPolicyKind::RoundRobin, 0);
+        for _ in 0..ROUND_ROBIN_QUANTUM {
+            assert!(!policy.preempt(ThreadId::from(0), false));
+        }
+        assert!(policy.preempt(ThreadId::from(0), false));
+        assert_eq!(policy.pick_next(ThreadId::from(0), true, &threads(&[1, 2])), ThreadId::from(1));
+        // Picking the next thread starts a new quantum.
+        assert!(!policy.preempt(ThreadId::from(1), false));
+    }
+
+    #[test]
+    fn test_priority() {
+        let enabled = threads(&[0, 1, 2, 3]);
+        let picks = |seed| {
+            let mut policy = new_scheduling_policy(SchedulingPolicyKind::Priority, seed);
+            let first = policy.pick_next(ThreadId::from(0), false, &enabled);
+            // Without preemptions, the same thread keeps the highest priority.
+            assert_eq!(policy.pick_next(first, false, &enabled), first);
+            // Only synchronization points are preemption points.
+            for _ in 0..10_000 {
+                assert!(!policy.preempt(first, false));
+            }
+            // Once the thread is demoted, every other thread comes first.
+            while !policy.preempt(first, true) {}
+            let second = policy.pick_next(first, true, &enabled);
+            assert_ne!(second, first);
+            let others: Vec<_> = enabled.iter().copied().filter(|&id| id != second).collect();
+            assert_ne!(policy.pick_next(second, false, &others), first);
+            (first, second)
+        };
+        // The priorities only depend on the seed.
+        assert_eq!(picks(1), picks(1));
+        let firsts: Vec<_> = (0..16).map(|seed| picks(seed).0).collect();
+        assert!(firsts.iter().any(|&first| first != firsts[0]), "priorities are not random");
+    }
+
+    #[test]
+    fn test_preemption_bounded() {
+        let mut policy = new_scheduling_policy(SchedulingPolicyKind::PreemptionBounded(3), 0);
+        let preemptions = (0..10_000).filter(|_| policy.preempt(ThreadId::from(0), true)).count();
+        assert_eq!(preemptions, 3);
+    }
+
+    #[test]
+    fn test_replay() {
+        let events = vec![
+            ScheduleEvent { steps: 2, preempted: true, next: ThreadId::from(1) },
+            ScheduleEvent { steps: 1, preempted: false, next: ThreadId::from(0) },
+        ];
+        let mut policy = Replay::new(events);
+        assert!(!policy.preempt(ThreadId::from(0), false));
+        assert!(policy.preempt(ThreadId::from(0), false));
+        assert_eq!(policy.pick_next(ThreadId::from(0), true, &threads(&[1])), ThreadId::from(1));
+        assert!(!policy.preempt(ThreadId::from(1), false));
+        assert_eq!(policy.pick_next(ThreadId::from(1), false, &threads(&[0])), ThreadId::from(0));
+        // Once the schedule is exhausted, we fall back to `Cooperative`.
+        assert_eq!(policy.pick_next(ThreadId::from(0), true, &threads(&[0, 1])), ThreadId::from(1));
+    }
+
+    /// Always switches to the thread with the highest id.
+    #[derive(Debug)]
+    struct Highest;
+
+    impl SchedulingPolicy for Highest {
+        fn pick_next(
+            &mut self,
+            _active: ThreadId,
+            _yielded: bool,
+            enabled: &[ThreadId],
+        ) -> ThreadId {
+            *enabled.last().unwrap()
+        }
+    }
+
+    #[test]
+    fn test_custom_policy() {
+        let factory = PolicyFactory(Arc::new(|seed| {
+            assert_eq!(seed, 42);
+            Box::new(Highest)
+        }));
+        let mut config = MiriConfig {
+            seed: Some(42),
+            custom_scheduling_policy: Some(factory),
+            ..MiriConfig::default()
+        };
+        let mut policy = scheduling_policy(&config);
+        assert_eq!(
+            policy.pick_next(ThreadId::from(0), false, &threads(&[0, 1])),
+            ThreadId::from(1)
+        );
+
+        // A replayed schedule takes precedence.
+        let next = ThreadId::from(0);
+        config.replay_schedule = Some(vec![ScheduleEvent { steps: 0, preempted: false, next }]);
+        let mut policy = scheduling_policy(&config);
+        assert_eq!(policy.pick_next(ThreadId::from(1), false, &threads(&[0, 1])), next);
+    }
+}
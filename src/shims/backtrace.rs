@@ -1,10 +1,107 @@
+use std::ffi::OsStr;
+
 use crate::*;
 use rustc_ast::ast::Mutability;
+use rustc_middle::mir;
 use rustc_middle::ty::layout::LayoutOf as _;
 use rustc_middle::ty::{self, Instance};
 use rustc_span::{BytePos, Loc, Symbol};
 use rustc_target::{abi::Size, spec::abi::Abi};
 
+/// `_URC_NO_REASON`: the trace function wants to see the next frame.
+const URC_NO_REASON: i32 = 0;
+/// `_URC_FATAL_PHASE1_ERROR`: the trace function stopped the backtrace.
+const URC_FATAL_PHASE1_ERROR: i32 = 3;
+/// `_URC_END_OF_STACK`: all frames have been passed to the trace function.
+const URC_END_OF_STACK: i32 = 5;
+
+/// Holds the state of an `_Unwind_Backtrace` call while its trace function runs.
+#[derive(Debug)]
+pub struct UnwindBacktraceData<'tcx> {
+    /// The trace function, called with each frame and `arg`.
+    trace_fn: Instance<'tcx>,
+    /// The `arg` argument for the trace function.
+    arg: Scalar<Tag>,
+    /// The frames that have not been passed to the trace function yet, innermost first.
+    frames: Vec<Pointer<Option<Tag>>>,
+    /// Where the trace function writes its `_Unwind_Reason_Code`.
+    trace_fn_ret: MPlaceTy<'tcx, Tag>,
+    /// The return place from the original call to `_Unwind_Backtrace`.
+    dest: PlaceTy<'tcx, Tag>,
+    /// The return block from the original call to `_Unwind_Backtrace`.
+    ret: mir::BasicBlock,
+}
+
+impl<'mir, 'tcx: 'mir> EvalContextExtPrivate<'mir, 'tcx> for crate::MiriEvalContext<'mir, 'tcx> {}
+trait EvalContextExtPrivate<'mir, 'tcx: 'mir>: crate::MiriEvalContextExt<'mir, 'tcx> {
+    /// The frame pointers of the active thread, innermost first.
+    fn backtrace_frame_pointers(&mut self) -> Vec<Pointer<Option<Tag>>> {
+        let this = self.eval_context_mut();
+        let tcx = this.tcx;
+
+        let mut data = Vec::new();
+        for frame in this.active_thread_stack().iter().rev() {
+            let mut span = frame.current_span();
+            // Match the behavior of runtime backtrace spans
+            // by using a non-macro span in our backtrace. See `FunctionCx::debug_loc`.
+            if span.from_expansion() && !tcx.sess.opts.debugging_opts.debug_macros {
+                span = rustc_span::hygiene::walk_chain(span, frame.body.span.ctxt())
+            }
+            data.push((frame.instance, span.lo()));
+        }
+
+        data.into_iter()
+            .map(|(instance, pos)| {
+                // We represent a frame pointer by using the `span.lo` value
+                // as an offset into the function's allocation. This gives us an
+                // opaque pointer that we can return to user code, and allows us
+                // to reconstruct the needed frame information in `handle_miri_resolve_frame`.
+                // Note that we never actually read or write anything from/to this pointer -
+                // all of the data is represented by the pointer value itself.
+                let fn_ptr = this.create_fn_alloc_ptr(FnVal::Instance(instance));
+                fn_ptr.wrapping_offset(Size::from_bytes(pos.0), this).into()
+            })
+            .collect()
+    }
+
+    /// The function that a frame pointer (or any other pointer into a function) points into, or
+    /// `None` if it is not such a pointer.
+    fn frame_pointer_instance(
+        &self,
+        ptr: Pointer<Option<Tag>>,
+    ) -> InterpResult<'tcx, Option<Instance<'tcx>>> {
+        let this = self.eval_context_ref();
+        Ok(match this.ptr_try_get_alloc_id(ptr) {
+            Ok((alloc_id, _offset, _tag)) =>
+                match this.tcx.get_global_alloc(alloc_id) {
+                    Some(GlobalAlloc::Function(instance)) => Some(instance),
+                    _ => None,
+                },
+            Err(_) => None,
+        })
+    }
+
+    /// Pass the next frame to the trace function of `_Unwind_Backtrace`.
+    fn call_unwind_backtrace_trace_fn(
+        &mut self,
+        mut data: UnwindBacktraceData<'tcx>,
+    ) -> InterpResult<'tcx> {
+        let this = self.eval_context_mut();
+
+        // The frame pointer doubles as the `_Unwind_Context`, see `_Unwind_GetIP`.
+        let context = data.frames.remove(0);
+        this.call_function(
+            data.trace_fn,
+            Abi::C { unwind: false },
+            &[Scalar::from_maybe_pointer(context, this).into(), data.arg.into()],
+            Some(&data.trace_fn_ret.into()),
+            StackPopCleanup::Goto { ret: Some(data.ret), unwind: StackPopUnwind::NotAllowed },
+        )?;
+        this.frame_mut().extra.unwind_backtrace = Some(data);
+        Ok(())
+    }
+}
+
 impl<'mir, 'tcx: 'mir> EvalContextExt<'mir, 'tcx> for crate::MiriEvalContext<'mir, 'tcx> {}
 pub trait EvalContextExt<'mir, 'tcx: 'mir>: crate::MiriEvalContextExt<'mir, 'tcx> {
     fn handle_miri_backtrace_size(
@@ -43,30 +140,7 @@ pub trait EvalContextExt<'mir, 'tcx: 'mir>: crate::MiriEvalContextExt<'mir, 'tcx
             throw_ub_format!("expected at least 1 argument")
         };
 
-        let mut data = Vec::new();
-        for frame in this.active_thread_stack().iter().rev() {
-            let mut span = frame.current_span();
-            // Match the behavior of runtime backtrace spans
-            // by using a non-macro span in our backtrace. See `FunctionCx::debug_loc`.
-            if span.from_expansion() && !tcx.sess.opts.debugging_opts.debug_macros {
-                span = rustc_span::hygiene::walk_chain(span, frame.body.span.ctxt())
-            }
-            data.push((frame.instance, span.lo()));
-        }
-
-        let ptrs: Vec<_> = data
-            .into_iter()
-            .map(|(instance, pos)| {
-                // We represent a frame pointer by using the `span.lo` value
-                // as an offset into the function's allocation. This gives us an
-                // opaque pointer that we can return to user code, and allows us
-                // to reconstruct the needed frame information in `handle_miri_resolve_frame`.
-                // Note that we never actually read or write anything from/to this pointer -
-                // all of the data is represented by the pointer value itself.
-                let fn_ptr = this.create_fn_alloc_ptr(FnVal::Instance(instance));
-                fn_ptr.wrapping_offset(Size::from_bytes(pos.0), this)
-            })
-            .collect();
+        let ptrs = this.backtrace_frame_pointers();
 
         let len: u64 = ptrs.len().try_into().unwrap();
 
@@ -248,4 +322,125 @@ pub trait EvalContextExt<'mir, 'tcx: 'mir>: crate::MiriEvalContextExt<'mir, 'tcx
 
         Ok(())
     }
+
+    /// `_Unwind_Backtrace`, which calls the trace function with each frame of the active thread.
+    /// Each call is a separate stack frame, so the next one is pushed by
+    /// `handle_unwind_backtrace_pop` when the previous one returns.
+    fn handle_unwind_backtrace(
+        &mut self,
+        trace_fn_op: &OpTy<'tcx, Tag>,
+        arg_op: &OpTy<'tcx, Tag>,
+        dest: &PlaceTy<'tcx, Tag>,
+        ret: mir::BasicBlock,
+    ) -> InterpResult<'tcx> {
+        let this = self.eval_context_mut();
+
+        let trace_fn = this.read_pointer(trace_fn_op)?;
+        let trace_fn = this.get_ptr_fn(trace_fn)?.as_instance()?;
+        let arg = this.read_scalar(arg_op)?.check_init()?;
+
+        // There is at least one frame, the caller of `_Unwind_Backtrace`.
+        let frames = this.backtrace_frame_pointers();
+        let trace_fn_ret =
+            this.allocate(this.machine.layouts.i32, MiriMemoryKind::Machine.into())?;
+        this.call_unwind_backtrace_trace_fn(UnwindBacktraceData {
+            trace_fn,
+            arg,
+            frames,
+            trace_fn_ret,
+            dest: *dest,
+            ret,
+        })
+    }
+
+    /// Called when a trace function frame pushed by `_Unwind_Backtrace` returns: either pass it
+    /// the next frame, or return from `_Unwind_Backtrace`.
+    fn handle_unwind_backtrace_pop(
+        &mut self,
+        data: UnwindBacktraceData<'tcx>,
+    ) -> InterpResult<'tcx, StackPopJump> {
+        let this = self.eval_context_mut();
+
+        let code = this.read_scalar(&data.trace_fn_ret.into())?.to_i32()?;
+        if code == URC_NO_REASON && !data.frames.is_empty() {
+            this.call_unwind_backtrace_trace_fn(data)?;
+            // We pushed a new stack frame, the engine should not do any jumping now!
+            return Ok(StackPopJump::NoJump);
+        }
+
+        let result = if code == URC_NO_REASON { URC_END_OF_STACK } else { URC_FATAL_PHASE1_ERROR };
+        this.deallocate_ptr(data.trace_fn_ret.ptr, None, MiriMemoryKind::Machine.into())?;
+        this.write_scalar(Scalar::from_i32(result), &data.dest)?;
+        // Return to the caller of `_Unwind_Backtrace`.
+        Ok(StackPopJump::Normal)
+    }
+
+    /// `_Unwind_GetIP` and `_Unwind_GetIPInfo`. The `_Unwind_Context` passed to the trace function
+    /// is the frame pointer itself, so this just returns it (with its provenance, so that it can
+    /// be passed to `dladdr`).
+    fn unwind_get_ip(
+        &mut self,
+        context_op: &OpTy<'tcx, Tag>,
+        ip_before_insn_op: Option<&OpTy<'tcx, Tag>>,
+    ) -> InterpResult<'tcx, Scalar<Tag>> {
+        let this = self.eval_context_mut();
+
+        let context = this.read_pointer(context_op)?;
+        if this.frame_pointer_instance(context)?.is_none() {
+            throw_ub_format!(
+                "expected an `_Unwind_Context` from `_Unwind_Backtrace`, found {:?}",
+                context
+            );
+        }
+        if let Some(ip_before_insn_op) = ip_before_insn_op {
+            let ip_before_insn = this.deref_operand(ip_before_insn_op)?;
+            this.write_null(&ip_before_insn.into())?;
+        }
+        Ok(Scalar::from_maybe_pointer(context, this))
+    }
+
+    /// `_Unwind_FindEnclosingFunction`: the start of the function that `pc` points into, or null.
+    fn unwind_find_enclosing_function(
+        &mut self,
+        pc_op: &OpTy<'tcx, Tag>,
+    ) -> InterpResult<'tcx, Pointer<Option<Tag>>> {
+        let this = self.eval_context_mut();
+
+        let pc = this.read_pointer(pc_op)?;
+        Ok(match this.frame_pointer_instance(pc)? {
+            Some(instance) => this.create_fn_alloc_ptr(FnVal::Instance(instance)).into(),
+            None => Pointer::null(),
+        })
+    }
+
+    /// `dladdr`, which describes the function that `addr` points into. The "shared object" of a
+    /// function is the crate it is defined in. Returns 0 if `addr` does not point into a function.
+    fn dladdr(
+        &mut self,
+        addr_op: &OpTy<'tcx, Tag>,
+        info_op: &OpTy<'tcx, Tag>,
+    ) -> InterpResult<'tcx, i32> {
+        let this = self.eval_context_mut();
+
+        let addr = this.read_pointer(addr_op)?;
+        let info = this.deref_operand(info_op)?;
+        let instance = match this.frame_pointer_instance(addr)? {
+            Some(instance) => instance,
+            None => return Ok(0),
+        };
+
+        let crate_name = this.tcx.crate_name(instance.def_id().krate).to_string();
+        let name = instance.to_string();
+        let fname =
+            this.alloc_os_str_as_c_str(OsStr::new(&crate_name), MiriMemoryKind::Machine.into())?;
+        let sname =
+            this.alloc_os_str_as_c_str(OsStr::new(&name), MiriMemoryKind::Machine.into())?;
+        let saddr = this.create_fn_alloc_ptr(FnVal::Instance(instance));
+
+        this.write_pointer(fname, &this.mplace_field_named(&info, "dli_fname")?.into())?;
+        this.write_null(&this.mplace_field_named(&info, "dli_fbase")?.into())?;
+        this.write_pointer(sname, &this.mplace_field_named(&info, "dli_sname")?.into())?;
+        this.write_pointer(saddr, &this.mplace_field_named(&info, "dli_saddr")?.into())?;
+        Ok(1)
+    }
 }
@@ -20,7 +20,7 @@ impl Dlsym {
         Ok(match target_os {
             "linux" | "macos" => posix::Dlsym::from_str(name, target_os)?.map(Dlsym::Posix),
             "windows" => windows::Dlsym::from_str(name)?.map(Dlsym::Windows),
-            os => bug!("dlsym not implemented for target_os {}", os),
+            os => throw_unsup_format!("`dlsym` is not supported on target OS `{}`", os),
         })
     }
 }
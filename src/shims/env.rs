@@ -12,8 +12,8 @@ use crate::*;
 
 /// Check whether an operation that writes to a target buffer was successful.
 /// Accordingly select return value.
-/// Local helper function to be used in Windows shims.
-fn windows_check_buffer_size((success, len): (bool, u64)) -> u32 {
+/// Helper function to be used in Windows shims.
+pub fn windows_check_buffer_size((success, len): (bool, u64)) -> u32 {
     if success {
         // If the function succeeds, the return value is the number of characters stored in the target buffer,
         // not including the terminating null character.
@@ -1,4 +1,4 @@
-use std::{collections::hash_map::Entry, iter};
+use std::{borrow::Cow, collections::hash_map::Entry, iter, rc::Rc, sync::Arc};
 
 use log::trace;
 
@@ -21,7 +21,8 @@ use rustc_target::{
     spec::abi::Abi,
 };
 
-use super::backtrace::EvalContextExt as _;
+use super::os_str::PathConversion;
+use super::windows::exception::EvalContextExt as _;
 use crate::helpers::convert::Truncate;
 use crate::*;
 
@@ -37,22 +38,83 @@ pub enum EmulateByNameResult<'mir, 'tcx> {
     NotSupported,
 }
 
+/// Emulates foreign items by name. The machine asks its providers in order, starting with the
+/// ones in `MiriConfig::foreign_item_providers`, until one of them supports the item. This allows
+/// overriding shims and adding support for more targets without changing Miri itself. Providers
+/// are part of the `MiriConfig`, which the driver hands to rustc, so they have to be thread-safe.
+/// Whether a provider supports an item may only depend on the link name of the item: the machine
+/// remembers which provider emulated an item, and asks that one right away the next time.
+pub trait ForeignItemProvider: Send + Sync {
+    /// Emulates a call to `link_name`, or returns `NotSupported` to let the next provider try.
+    fn emulate_foreign_item_by_name<'mir, 'tcx>(
+        &self,
+        ecx: &mut MiriEvalContext<'mir, 'tcx>,
+        link_name: Symbol,
+        abi: Abi,
+        args: &[OpTy<'tcx, Tag>],
+        dest: &PlaceTy<'tcx, Tag>,
+        ret: mir::BasicBlock,
+    ) -> InterpResult<'tcx, EmulateByNameResult<'mir, 'tcx>>;
+}
+
+/// The shims that are available on all targets.
+pub struct CommonForeignItems;
+
+impl ForeignItemProvider for CommonForeignItems {
+    fn emulate_foreign_item_by_name<'mir, 'tcx>(
+        &self,
+        ecx: &mut MiriEvalContext<'mir, 'tcx>,
+        link_name: Symbol,
+        abi: Abi,
+        args: &[OpTy<'tcx, Tag>],
+        dest: &PlaceTy<'tcx, Tag>,
+        ret: mir::BasicBlock,
+    ) -> InterpResult<'tcx, EmulateByNameResult<'mir, 'tcx>> {
+        ecx.emulate_common_foreign_item_by_name(link_name, abi, args, dest, ret)
+    }
+}
+
+/// The built-in providers for `target_os`, in the order they are asked. Returns `None` if Miri
+/// does not support the target.
+pub fn target_foreign_item_providers(target_os: &str) -> Option<Vec<Arc<dyn ForeignItemProvider>>> {
+    let os_specific: Vec<Arc<dyn ForeignItemProvider>> = match target_os {
+        "linux" =>
+            vec![
+                Arc::new(shims::posix::foreign_items::PosixForeignItems),
+                Arc::new(shims::posix::linux::foreign_items::LinuxForeignItems),
+            ],
+        "macos" =>
+            vec![
+                Arc::new(shims::posix::foreign_items::PosixForeignItems),
+                Arc::new(shims::posix::macos::foreign_items::MacOsForeignItems),
+            ],
+        "windows" => vec![Arc::new(shims::windows::foreign_items::WindowsForeignItems)],
+        _ => return None,
+    };
+    let common: Arc<dyn ForeignItemProvider> = Arc::new(CommonForeignItems);
+    Some(iter::once(common).chain(os_specific).collect())
+}
+
 impl<'mir, 'tcx: 'mir> EvalContextExt<'mir, 'tcx> for crate::MiriEvalContext<'mir, 'tcx> {}
 pub trait EvalContextExt<'mir, 'tcx: 'mir>: crate::MiriEvalContextExt<'mir, 'tcx> {
     /// Returns the minimum alignment for the target architecture for allocations of the given size.
-    fn min_align(&self, size: u64, kind: MiriMemoryKind) -> Align {
+    fn min_align(&self, size: u64, kind: MiriMemoryKind) -> InterpResult<'tcx, Align> {
         let this = self.eval_context_ref();
         // List taken from `libstd/sys_common/alloc.rs`.
         let min_align = match this.tcx.sess.target.arch.as_ref() {
             "x86" | "arm" | "mips" | "powerpc" | "powerpc64" | "asmjs" | "wasm32" => 8,
             "x86_64" | "aarch64" | "mips64" | "s390x" | "sparc64" | "loongarch64" => 16,
-            arch => bug!("Unsupported target architecture: {}", arch),
+            arch =>
+                throw_unsup_format!(
+                    "heap allocation is not supported on the target architecture `{}`",
+                    arch
+                ),
         };
         // Windows always aligns, even small allocations.
         // Source: <https://support.microsoft.com/en-us/help/286470/how-to-use-pageheap-exe-in-windows-xp-windows-2000-and-windows-server>
         // But jemalloc does not, so for the C heap we only align if the allocation is sufficiently big.
         if kind == MiriMemoryKind::WinHeap || size >= min_align {
-            return Align::from_bytes(min_align).unwrap();
+            return Ok(Align::from_bytes(min_align).unwrap());
         }
         // We have `size < min_align`. Round `size` *down* to the next power of two and use that.
         fn prev_power_of_two(x: u64) -> u64 {
@@ -65,7 +127,7 @@ pub trait EvalContextExt<'mir, 'tcx: 'mir>: crate::MiriEvalContextExt<'mir, 'tcx
                 next_pow2 / 2
             }
         }
-        Align::from_bytes(prev_power_of_two(size)).unwrap()
+        Ok(Align::from_bytes(prev_power_of_two(size)).unwrap())
     }
 
     fn malloc(
@@ -78,7 +140,7 @@ pub trait EvalContextExt<'mir, 'tcx: 'mir>: crate::MiriEvalContextExt<'mir, 'tcx
         if size == 0 {
             Ok(Pointer::null())
         } else {
-            let align = this.min_align(size, kind);
+            let align = this.min_align(size, kind)?;
             let ptr = this.allocate_ptr(Size::from_bytes(size), align, kind.into())?;
             if zero_init {
                 // We just allocated this, the access is definitely in-bounds.
@@ -103,7 +165,7 @@ pub trait EvalContextExt<'mir, 'tcx: 'mir>: crate::MiriEvalContextExt<'mir, 'tcx
         kind: MiriMemoryKind,
     ) -> InterpResult<'tcx, Pointer<Option<Tag>>> {
         let this = self.eval_context_mut();
-        let new_align = this.min_align(new_size, kind);
+        let new_align = this.min_align(new_size, kind)?;
         if this.ptr_is_null(old_ptr)? {
             if new_size == 0 {
                 Ok(Pointer::null())
@@ -117,7 +179,7 @@ pub trait EvalContextExt<'mir, 'tcx: 'mir>: crate::MiriEvalContextExt<'mir, 'tcx
                 this.deallocate_ptr(old_ptr, None, kind.into())?;
                 Ok(Pointer::null())
             } else {
-                let new_ptr = this.reallocate_ptr(
+                let new_ptr = this.reallocate_with_policy(
                     old_ptr,
                     None,
                     Size::from_bytes(new_size),
@@ -129,6 +191,67 @@ pub trait EvalContextExt<'mir, 'tcx: 'mir>: crate::MiriEvalContextExt<'mir, 'tcx
         }
     }
 
+    /// Like `reallocate_ptr`, but the new allocation keeps the address of the old one if
+    /// `-Zmiri-realloc` says so and there is room. The old allocation is freed either way, so
+    /// using a pointer to it is still an error, but code that compares the addresses sees both
+    /// behaviors of real allocators.
+    fn reallocate_with_policy(
+        &mut self,
+        ptr: Pointer<Option<Tag>>,
+        old_size_and_align: Option<(Size, Align)>,
+        new_size: Size,
+        new_align: Align,
+        kind: MemoryKind<MiriMemoryKind>,
+    ) -> InterpResult<'tcx, Pointer<Tag>> {
+        use rand::Rng as _;
+
+        let this = self.eval_context_mut();
+        let in_place = match this.machine.realloc_policy {
+            ReallocPolicy::Move => false,
+            ReallocPolicy::InPlace => true,
+            ReallocPolicy::Random => this.machine.rng.get_mut().gen_bool(0.5),
+        };
+        if let (true, Some(tag)) = (in_place, ptr.provenance) {
+            // If the pointer is invalid, `reallocate_ptr` reports that below.
+            intptrcast::GlobalStateInner::reserve_in_place(
+                this,
+                tag.alloc_id,
+                new_size,
+                new_align,
+            )?;
+        }
+        let new_ptr = this.reallocate_ptr(ptr, old_size_and_align, new_size, new_align, kind);
+        this.machine.intptrcast.borrow_mut().cancel_base_addr_reuse();
+        new_ptr
+    }
+
+    /// The size of the allocation of `kind` that `ptr` points to, for `malloc_usable_size` and
+    /// friends. `ptr` must have been returned by the allocator; it is UB to pass any other pointer.
+    fn heap_alloc_size(
+        &self,
+        ptr: Pointer<Option<Tag>>,
+        kind: MiriMemoryKind,
+        op_name: &str,
+    ) -> InterpResult<'tcx, u64> {
+        let this = self.eval_context_ref();
+        if this.ptr_is_null(ptr)? {
+            return Ok(0);
+        }
+        let (alloc_id, offset, _) = this.ptr_get_alloc_id(ptr)?;
+        // Freed allocations and functions have no extra state.
+        let heap = this.get_alloc_extra(alloc_id).ok().and_then(|extra| extra.heap);
+        match heap {
+            Some(heap) if heap == kind && offset.bytes() == 0 =>
+                Ok(this.get_alloc_size_and_align(alloc_id, AllocCheck::Live)?.0.bytes()),
+            _ =>
+                throw_ub_format!(
+                    "`{}` called on a pointer that does not point to the start of a live {} allocation",
+                    op_name,
+                    kind,
+                ),
+        }
+    }
+
     /// Lookup the body of a function that has `link_name` as the symbol name.
     fn lookup_exported_symbol(
         &mut self,
@@ -220,6 +343,13 @@ pub trait EvalContextExt<'mir, 'tcx: 'mir>: crate::MiriEvalContextExt<'mir, 'tcx
         }
     }
 
+    /// Records a call to the foreign function `link_name`, which is not supported, for the
+    /// summary in `Report::unsupported_shims`.
+    fn record_unsupported_shim(&mut self, link_name: Symbol) {
+        let this = self.eval_context_mut();
+        *this.machine.unsupported_shims.entry(link_name.to_string()).or_insert(0) += 1;
+    }
+
     /// Emulates calling a foreign item, failing if the item is not supported.
     /// This function will handle `goto_block` if needed.
     /// Returns Ok(None) if the foreign item was completely handled
@@ -235,13 +365,17 @@ pub trait EvalContextExt<'mir, 'tcx: 'mir>: crate::MiriEvalContextExt<'mir, 'tcx
         unwind: StackPopUnwind,
     ) -> InterpResult<'tcx, Option<(&'mir mir::Body<'tcx>, ty::Instance<'tcx>)>> {
         let this = self.eval_context_mut();
-        let attrs = this.tcx.get_attrs(def_id);
-        let link_name = this
-            .tcx
-            .sess
-            .first_attr_value_str_by_name(attrs, sym::link_name)
-            .unwrap_or_else(|| this.tcx.item_name(def_id));
         let tcx = this.tcx.tcx;
+        let link_name = *this.machine.foreign_item_link_names.entry(def_id).or_insert_with(|| {
+            let attrs = tcx.get_attrs(def_id);
+            tcx.sess
+                .first_attr_value_str_by_name(attrs, sym::link_name)
+                .unwrap_or_else(|| tcx.item_name(def_id))
+        });
+        let thread = this.get_active_thread();
+        if let Some(calls) = &mut this.machine.recent_shim_calls {
+            calls.push(thread, link_name);
+        }
 
         // First: functions that diverge.
         let (dest, ret) = match ret {
@@ -286,10 +420,20 @@ pub trait EvalContextExt<'mir, 'tcx: 'mir>: crate::MiriEvalContextExt<'mir, 'tcx
                             "the program aborted execution".to_owned()
                         ))
                     }
+                    #[rustfmt::skip]
+                    | "RaiseException"
+                    | "RtlUnwind"
+                    | "RtlUnwindEx"
+                    | "_CxxThrowException"
+                    if this.tcx.sess.target.os == "windows"
+                    => {
+                        this.emulate_seh_shim(link_name, abi, args)?;
+                    }
                     _ => {
                         if let Some(body) = this.lookup_exported_symbol(link_name)? {
                             return Ok(Some(body));
                         }
+                        this.record_unsupported_shim(link_name);
                         this.handle_unsupported(format!(
                             "can't call (diverging) foreign function: {}",
                             link_name
@@ -301,9 +445,14 @@ pub trait EvalContextExt<'mir, 'tcx: 'mir>: crate::MiriEvalContextExt<'mir, 'tcx
         };
 
         // Second: functions that return.
-        match this.emulate_foreign_item_by_name(link_name, abi, args, dest, ret)? {
+        this.machine.current_shim = Some(link_name);
+        this.machine.last_error_set_in_shim = false;
+        let result = this.emulate_foreign_item_by_name(link_name, abi, args, dest, ret);
+        this.machine.current_shim = None;
+        match result? {
             EmulateByNameResult::NeedsJumping => {
                 trace!("{:?}", this.dump_place(**dest));
+                this.poison_last_error_after(link_name)?;
                 this.go_to_block(ret);
             }
             EmulateByNameResult::AlreadyJumped => (),
@@ -313,6 +462,7 @@ pub trait EvalContextExt<'mir, 'tcx: 'mir>: crate::MiriEvalContextExt<'mir, 'tcx
                     return Ok(Some(body));
                 }
 
+                this.record_unsupported_shim(link_name);
                 this.handle_unsupported(format!("can't call foreign function: {}", link_name))?;
                 return Ok(None);
             }
@@ -351,7 +501,7 @@ pub trait EvalContextExt<'mir, 'tcx: 'mir>: crate::MiriEvalContextExt<'mir, 'tcx
         }
     }
 
-    /// Emulates calling a foreign item using its name.
+    /// Emulates calling a foreign item using its name, by asking the providers of the machine.
     fn emulate_foreign_item_by_name(
         &mut self,
         link_name: Symbol,
@@ -362,6 +512,42 @@ pub trait EvalContextExt<'mir, 'tcx: 'mir>: crate::MiriEvalContextExt<'mir, 'tcx
     ) -> InterpResult<'tcx, EmulateByNameResult<'mir, 'tcx>> {
         let this = self.eval_context_mut();
 
+        // The providers need mutable access to the machine, so we cannot borrow the list.
+        let providers = Rc::clone(&this.machine.foreign_item_providers);
+        // Most calls are to items that were called before. The providers before the one that
+        // emulated the item last time do not support it, so we go on where that lookup ended
+        // instead of asking them again. This asks the providers in the same order as the first
+        // lookup, and for items that no provider supports, it asks none at all.
+        let start = this.machine.foreign_item_provider_cache.get(&link_name).copied();
+        for (idx, provider) in providers.iter().enumerate().skip(start.unwrap_or(0)) {
+            match provider.emulate_foreign_item_by_name(this, link_name, abi, args, dest, ret)? {
+                EmulateByNameResult::NotSupported => {}
+                result => {
+                    if start != Some(idx) {
+                        this.machine.foreign_item_provider_cache.insert(link_name, idx);
+                    }
+                    return Ok(result);
+                }
+            }
+        }
+        this.machine.foreign_item_provider_cache.insert(link_name, providers.len());
+        if target_foreign_item_providers(&this.tcx.sess.target.os).is_none() {
+            throw_unsup_format!("the target `{}` is not supported", this.tcx.sess.target.os);
+        }
+        Ok(EmulateByNameResult::NotSupported)
+    }
+
+    /// Emulates calling a foreign item that is available on all targets.
+    fn emulate_common_foreign_item_by_name(
+        &mut self,
+        link_name: Symbol,
+        abi: Abi,
+        args: &[OpTy<'tcx, Tag>],
+        dest: &PlaceTy<'tcx, Tag>,
+        _ret: mir::BasicBlock,
+    ) -> InterpResult<'tcx, EmulateByNameResult<'mir, 'tcx>> {
+        let this = self.eval_context_mut();
+
         // Here we dispatch all the shims for foreign functions. If you have a platform specific
         // shim, add it to the corresponding submodule.
         match &*link_name.as_str() {
@@ -371,11 +557,158 @@ pub trait EvalContextExt<'mir, 'tcx: 'mir>: crate::MiriEvalContextExt<'mir, 'tcx
                 let ptr = this.read_pointer(ptr)?;
                 let (alloc_id, offset, _) = this.ptr_get_alloc_id(ptr)?;
                 if offset != Size::ZERO {
-                    throw_unsup_format!("pointer passed to miri_static_root must point to beginning of an allocated block");
+                    throw_unsup_format!(
+                        "pointer passed to miri_static_root must point to beginning of an allocated block"
+                    );
                 }
                 this.machine.static_roots.push(alloc_id);
             }
 
+            // Queries about how Miri is configured. See the README for details.
+            "miri_get_seed" => {
+                let [] = this.check_shim(abi, Abi::Rust, link_name, args)?;
+                this.write_scalar(Scalar::from_u64(this.machine.seed), dest)?;
+            }
+            "miri_isolation_enabled" => {
+                let [] = this.check_shim(abi, Abi::Rust, link_name, args)?;
+                this.write_scalar(Scalar::from_bool(!this.machine.communicate()), dest)?;
+            }
+            "miri_aliasing_model" => {
+                let [] = this.check_shim(abi, Abi::Rust, link_name, args)?;
+                let model = match &this.machine.stacked_borrows {
+                    None => 0,
+                    Some(stacked_borrows) if !stacked_borrows.borrow().tag_raw() => 1,
+                    Some(_) => 2,
+                };
+                this.write_scalar(Scalar::from_u8(model), dest)?;
+            }
+            "miri_strict_provenance" => {
+                let [] = this.check_shim(abi, Abi::Rust, link_name, args)?;
+                let strict = this.machine.intptrcast.borrow().strict_provenance();
+                this.write_scalar(Scalar::from_bool(strict), dest)?;
+            }
+
+            // Declares that the program is expected to stop with a certain error. See the README
+            // for details.
+            "miri_expect_error" => {
+                let [ptr, len] = this.check_shim(abi, Abi::Rust, link_name, args)?;
+                let ptr = this.read_pointer(ptr)?;
+                let len = this.read_scalar(len)?.to_machine_usize(this)?;
+                let pattern = this.read_bytes_ptr(ptr, Size::from_bytes(len))?;
+                let pattern = match std::str::from_utf8(pattern) {
+                    Ok(pattern) => pattern.to_owned(),
+                    Err(_) => throw_ub_format!("`miri_expect_error` called with invalid UTF-8"),
+                };
+                this.machine.expected_error = Some(pattern);
+            }
+
+            // Makes every later access to the given memory an error. See the README for details.
+            "miri_poison_memory" => {
+                let [ptr, size] = this.check_shim(abi, Abi::Rust, link_name, args)?;
+                let ptr = this.read_pointer(ptr)?;
+                let size = Size::from_bytes(this.read_scalar(size)?.to_machine_usize(this)?);
+                // Check that the range is in bounds.
+                if this.get_ptr_alloc(ptr, size, Align::ONE)?.is_some() {
+                    let (alloc_id, offset, _) = this.ptr_get_alloc_id(ptr)?;
+                    let (alloc_extra, _) = this.get_alloc_extra_mut(alloc_id)?;
+                    alloc_extra.poisoned.push(alloc_range(offset, size));
+                }
+            }
+            // Happens-before annotations for synchronization that Miri cannot see. See the README
+            // for details.
+            "miri_release" => {
+                let [addr] = this.check_shim(abi, Abi::Rust, link_name, args)?;
+                let addr = this.read_scalar(addr)?.to_machine_usize(this)?;
+                this.annotate_release(addr);
+            }
+            "miri_acquire" => {
+                let [addr] = this.check_shim(abi, Abi::Rust, link_name, args)?;
+                let addr = this.read_scalar(addr)?.to_machine_usize(this)?;
+                this.annotate_acquire(addr);
+            }
+            // Makes every later write to the given memory an error. See the README for details.
+            "miri_freeze" => {
+                let [ptr, size] = this.check_shim(abi, Abi::Rust, link_name, args)?;
+                let ptr = this.read_pointer(ptr)?;
+                let size = Size::from_bytes(this.read_scalar(size)?.to_machine_usize(this)?);
+                // Check that the range is in bounds.
+                if this.get_ptr_alloc(ptr, size, Align::ONE)?.is_some() {
+                    let span = this.cur_span().data();
+                    let (alloc_id, offset, _) = this.ptr_get_alloc_id(ptr)?;
+                    let (alloc_extra, _) = this.get_alloc_extra_mut(alloc_id)?;
+                    alloc_extra.frozen.push((alloc_range(offset, size), span));
+                }
+            }
+            // Reports every later access to the given memory. See the README for details.
+            "miri_watch" => {
+                let [ptr, size] = this.check_shim(abi, Abi::Rust, link_name, args)?;
+                let ptr = this.read_pointer(ptr)?;
+                let size = Size::from_bytes(this.read_scalar(size)?.to_machine_usize(this)?);
+                this.watch_memory(ptr, size)?;
+            }
+            // Asserts that a pointer has unique access to some memory. See the README for
+            // details.
+            "miri_assert_unique" => {
+                let [ptr, size] = this.check_shim(abi, Abi::Rust, link_name, args)?;
+                let ptr = this.read_pointer(ptr)?;
+                let size = Size::from_bytes(this.read_scalar(size)?.to_machine_usize(this)?);
+                if this.machine.stacked_borrows.is_none() {
+                    throw_unsup_format!(
+                        "`miri_assert_unique` requires Stacked Borrows to be enabled"
+                    );
+                }
+                if this.get_ptr_alloc(ptr, size, Align::ONE)?.is_some() {
+                    let (alloc_id, offset, tag) = this.ptr_get_alloc_id(ptr)?;
+                    let stacks = this.get_alloc_extra(alloc_id)?.stacked_borrows.as_ref().unwrap();
+                    if !stacks.has_unique_access(tag, alloc_range(offset, size)) {
+                        throw_machine_stop!(TerminationInfo::Abort(format!(
+                            "`miri_assert_unique` failed: {:?} does not have unique access to {:?}[{:#x}..{:#x}]",
+                            tag,
+                            alloc_id,
+                            offset.bytes(),
+                            (offset + size).bytes(),
+                        )));
+                    }
+                }
+            }
+
+            // Prints the borrow stacks of some memory. See the README for details.
+            "miri_print_borrow_stacks" => {
+                let [ptr, size] = this.check_shim(abi, Abi::Rust, link_name, args)?;
+                let ptr = this.read_pointer(ptr)?;
+                let size = Size::from_bytes(this.read_scalar(size)?.to_machine_usize(this)?);
+                if this.machine.stacked_borrows.is_none() {
+                    throw_unsup_format!(
+                        "`miri_print_borrow_stacks` requires Stacked Borrows to be enabled"
+                    );
+                }
+                if this.get_ptr_alloc(ptr, size, Align::ONE)?.is_some() {
+                    let (alloc_id, offset, _) = this.ptr_get_alloc_id(ptr)?;
+                    let stacks = this.get_alloc_extra(alloc_id)?.stacked_borrows.as_ref().unwrap();
+                    stacks.print_stacks(alloc_id, alloc_range(offset, size));
+                }
+            }
+
+            // Converts a path between the host and the target conventions. See the README for
+            // details.
+            "miri_host_to_target_path" | "miri_target_to_host_path" => {
+                let [ptr, out, out_size] = this.check_shim(abi, Abi::Rust, link_name, args)?;
+                let ptr = this.read_pointer(ptr)?;
+                let out = this.read_pointer(out)?;
+                let out_size = this.read_scalar(out_size)?.to_machine_usize(this)?;
+                let direction = if &*link_name.as_str() == "miri_host_to_target_path" {
+                    PathConversion::HostToTarget
+                } else {
+                    PathConversion::TargetToHost
+                };
+                let path = this.read_os_str_from_c_str(ptr)?.to_owned();
+                let path = this.convert_path_separator(Cow::Owned(path), direction);
+                let (success, len) = this.write_os_str_to_c_str(&path, out, out_size)?;
+                // On failure, return the required size including the null terminator.
+                let res = if success { 0 } else { len.checked_add(1).unwrap() };
+                this.write_scalar(Scalar::from_machine_usize(res, this), dest)?;
+            }
+
             // Obtains the size of a Miri backtrace. See the README for details.
             "miri_backtrace_size" => {
                 this.handle_miri_backtrace_size(abi, link_name, args, dest)?;
@@ -406,11 +739,13 @@ pub trait EvalContextExt<'mir, 'tcx: 'mir>: crate::MiriEvalContextExt<'mir, 'tcx
                 this.write_pointer(res, dest)?;
             }
             "calloc" => {
-                let [items, len] = this.check_shim(abi, Abi::C { unwind: false }, link_name, args)?;
+                let [items, len] =
+                    this.check_shim(abi, Abi::C { unwind: false }, link_name, args)?;
                 let items = this.read_scalar(items)?.to_machine_usize(this)?;
                 let len = this.read_scalar(len)?.to_machine_usize(this)?;
-                let size =
-                    items.checked_mul(len).ok_or_else(|| err_ub_format!("overflow during calloc size computation"))?;
+                let size = items
+                    .checked_mul(len)
+                    .ok_or_else(|| err_ub_format!("overflow during calloc size computation"))?;
                 let res = this.malloc(size, /*zero_init:*/ true, MiriMemoryKind::C)?;
                 this.write_pointer(res, dest)?;
             }
@@ -420,7 +755,8 @@ pub trait EvalContextExt<'mir, 'tcx: 'mir>: crate::MiriEvalContextExt<'mir, 'tcx
                 this.free(ptr, MiriMemoryKind::C)?;
             }
             "realloc" => {
-                let [old_ptr, new_size] = this.check_shim(abi, Abi::C { unwind: false }, link_name, args)?;
+                let [old_ptr, new_size] =
+                    this.check_shim(abi, Abi::C { unwind: false }, link_name, args)?;
                 let old_ptr = this.read_pointer(old_ptr)?;
                 let new_size = this.read_scalar(new_size)?.to_machine_usize(this)?;
                 let res = this.realloc(old_ptr, new_size, MiriMemoryKind::C)?;
@@ -460,7 +796,11 @@ pub trait EvalContextExt<'mir, 'tcx: 'mir>: crate::MiriEvalContextExt<'mir, 'tcx
                     )?;
 
                     // We just allocated this, the access is definitely in-bounds.
-                    this.write_bytes_ptr(ptr.into(), iter::repeat(0u8).take(usize::try_from(size).unwrap())).unwrap();
+                    this.write_bytes_ptr(
+                        ptr.into(),
+                        iter::repeat(0u8).take(usize::try_from(size).unwrap()),
+                    )
+                    .unwrap();
                     this.write_pointer(ptr, dest)
                 });
             }
@@ -480,7 +820,8 @@ pub trait EvalContextExt<'mir, 'tcx: 'mir>: crate::MiriEvalContextExt<'mir, 'tcx
                 });
             }
             "__rust_realloc" => {
-                let [ptr, old_size, align, new_size] = this.check_shim(abi, Abi::Rust, link_name, args)?;
+                let [ptr, old_size, align, new_size] =
+                    this.check_shim(abi, Abi::Rust, link_name, args)?;
                 let ptr = this.read_pointer(ptr)?;
                 let old_size = this.read_scalar(old_size)?.to_machine_usize(this)?;
                 let align = this.read_scalar(align)?.to_machine_usize(this)?;
@@ -491,7 +832,7 @@ pub trait EvalContextExt<'mir, 'tcx: 'mir>: crate::MiriEvalContextExt<'mir, 'tcx
                     Self::check_alloc_request(new_size, align)?;
 
                     let align = Align::from_bytes(align).unwrap();
-                    let new_ptr = this.reallocate_ptr(
+                    let new_ptr = this.reallocate_with_policy(
                         ptr,
                         Some((Size::from_bytes(old_size), align)),
                         Size::from_bytes(new_size),
@@ -503,8 +844,11 @@ pub trait EvalContextExt<'mir, 'tcx: 'mir>: crate::MiriEvalContextExt<'mir, 'tcx
             }
 
             // C memory handling functions
-            "memcmp" => {
-                let [left, right, n] = this.check_shim(abi, Abi::C { unwind: false }, link_name, args)?;
+            "memcmp" | "bcmp" => {
+                // `bcmp` only has to tell whether the bytes are equal, so the result of `memcmp`
+                // works for it as well.
+                let [left, right, n] =
+                    this.check_shim(abi, Abi::C { unwind: false }, link_name, args)?;
                 let left = this.read_pointer(left)?;
                 let right = this.read_pointer(right)?;
                 let n = Size::from_bytes(this.read_scalar(n)?.to_machine_usize(this)?);
@@ -524,7 +868,8 @@ pub trait EvalContextExt<'mir, 'tcx: 'mir>: crate::MiriEvalContextExt<'mir, 'tcx
                 this.write_scalar(Scalar::from_i32(result), dest)?;
             }
             "memrchr" => {
-                let [ptr, val, num] = this.check_shim(abi, Abi::C { unwind: false }, link_name, args)?;
+                let [ptr, val, num] =
+                    this.check_shim(abi, Abi::C { unwind: false }, link_name, args)?;
                 let ptr = this.read_pointer(ptr)?;
                 let val = this.read_scalar(val)?.to_i32()? as u8;
                 let num = this.read_scalar(num)?.to_machine_usize(this)?;
@@ -541,14 +886,30 @@ pub trait EvalContextExt<'mir, 'tcx: 'mir>: crate::MiriEvalContextExt<'mir, 'tcx
                 }
             }
             "memchr" => {
-                let [ptr, val, num] = this.check_shim(abi, Abi::C { unwind: false }, link_name, args)?;
+                let [ptr, val, num] =
+                    this.check_shim(abi, Abi::C { unwind: false }, link_name, args)?;
                 let ptr = this.read_pointer(ptr)?;
                 let val = this.read_scalar(val)?.to_i32()? as u8;
                 let num = this.read_scalar(num)?.to_machine_usize(this)?;
+                // "The implementation shall behave as if it reads the characters sequentially and
+                // stops as soon as a matching character is found", so `num` may be larger than the
+                // buffer if the character occurs in it. Search the part that is in bounds first.
+                let in_bounds = match this.ptr_try_get_alloc_id(ptr) {
+                    Ok((alloc_id, offset, _)) => {
+                        let (size, _) =
+                            this.get_alloc_size_and_align(alloc_id, AllocCheck::Dereferenceable)?;
+                        num.min(size.bytes().saturating_sub(offset.bytes()))
+                    }
+                    Err(_) => num,
+                };
                 let idx = this
-                    .read_bytes_ptr(ptr, Size::from_bytes(num))?
+                    .read_bytes_ptr(ptr, Size::from_bytes(in_bounds))?
                     .iter()
                     .position(|&c| c == val);
+                if idx.is_none() && in_bounds < num {
+                    // Report the out-of-bounds access.
+                    this.read_bytes_ptr(ptr, Size::from_bytes(num))?;
+                }
                 if let Some(idx) = idx {
                     let new_ptr = ptr.offset(Size::from_bytes(idx as u64), this)?;
                     this.write_pointer(new_ptr, dest)?;
@@ -560,7 +921,10 @@ pub trait EvalContextExt<'mir, 'tcx: 'mir>: crate::MiriEvalContextExt<'mir, 'tcx
                 let [ptr] = this.check_shim(abi, Abi::C { unwind: false }, link_name, args)?;
                 let ptr = this.read_pointer(ptr)?;
                 let n = this.read_c_str(ptr)?.len();
-                this.write_scalar(Scalar::from_machine_usize(u64::try_from(n).unwrap(), this), dest)?;
+                this.write_scalar(
+                    Scalar::from_machine_usize(u64::try_from(n).unwrap(), this),
+                    dest,
+                )?;
             }
 
             // math functions
@@ -686,7 +1050,9 @@ pub trait EvalContextExt<'mir, 'tcx: 'mir>: crate::MiriEvalContextExt<'mir, 'tcx
                 let sum_field = this.place_field(dest, 1)?;
                 this.write_scalar(Scalar::from_u64(sum), &sum_field)?;
             }
-            "llvm.x86.sse2.pause" if this.tcx.sess.target.arch == "x86" || this.tcx.sess.target.arch == "x86_64" => {
+            "llvm.x86.sse2.pause"
+                if this.tcx.sess.target.arch == "x86" || this.tcx.sess.target.arch == "x86_64" =>
+            {
                 let [] = this.check_shim(abi, Abi::C { unwind: false }, link_name, args)?;
                 this.yield_active_thread();
             }
@@ -694,7 +1060,8 @@ pub trait EvalContextExt<'mir, 'tcx: 'mir>: crate::MiriEvalContextExt<'mir, 'tcx
                 let [arg] = this.check_shim(abi, Abi::Unadjusted, link_name, args)?;
                 let arg = this.read_scalar(arg)?.to_i32()?;
                 match arg {
-                    15 => { // SY ("full system scope")
+                    15 => {
+                        // SY ("full system scope")
                         this.yield_active_thread();
                     }
                     _ => {
@@ -703,12 +1070,8 @@ pub trait EvalContextExt<'mir, 'tcx: 'mir>: crate::MiriEvalContextExt<'mir, 'tcx
                 }
             }
 
-            // Platform-specific shims
-            _ => match this.tcx.sess.target.os.as_ref() {
-                "linux" | "macos" => return shims::posix::foreign_items::EvalContextExt::emulate_foreign_item_by_name(this, link_name, abi, args, dest, ret),
-                "windows" => return shims::windows::foreign_items::EvalContextExt::emulate_foreign_item_by_name(this, link_name, abi, args, dest, ret),
-                target => throw_unsup_format!("the target `{}` is not supported", target),
-            }
+            // Platform-specific shims are handled by the other providers.
+            _ => return Ok(EmulateByNameResult::NotSupported),
         };
 
         // We only fall through to here if we did *not* hit the `_` arm above,
@@ -5,6 +5,7 @@ use log::trace;
 use rustc_apfloat::{Float, Round};
 use rustc_middle::ty::layout::{HasParamEnv, IntegerExt, LayoutOf};
 use rustc_middle::{mir, mir::BinOp, ty, ty::FloatTy};
+use rustc_span::symbol::sym;
 use rustc_target::abi::{Align, Endian, HasDataLayout, Integer, Size};
 
 use crate::*;
@@ -27,6 +28,12 @@ pub trait EvalContextExt<'mir, 'tcx: 'mir>: crate::MiriEvalContextExt<'mir, 'tcx
     ) -> InterpResult<'tcx> {
         let this = self.eval_context_mut();
 
+        if let (sym::transmute, [arg], Some((dest, _))) =
+            (this.tcx.item_name(instance.def_id()), args, ret)
+        {
+            this.record_transmute_exposure(arg, dest.layout.ty)?;
+        }
+
         if this.emulate_intrinsic(instance, args, ret)? {
             return Ok(());
         }
@@ -0,0 +1,136 @@
+//! The bookkeeping for memory mappings, shared by `mmap` on POSIX and `VirtualAlloc` on Windows.
+//!
+//! Every mapping is an allocation of its own, of kind `MiriMemoryKind::Mmap`, that covers whole
+//! pages. Besides the allocation, Miri remembers the state of each page in the extra state of the
+//! allocation: whether it may be read or written, and (for `VirtualAlloc`) whether it is committed
+//! at all. Accesses that real hardware would turn into a fault are reported as UB.
+
+use rustc_target::abi::Size;
+
+use crate::*;
+
+/// The state of a page of a mapping.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Page {
+    /// Reserved by `VirtualAlloc`, but not committed.
+    Reserved,
+    /// Committed, but neither readable nor writable (`PAGE_NOACCESS`).
+    NoAccess,
+    /// Readable, but not writable (`PROT_READ`, `PAGE_READONLY`).
+    ReadOnly,
+    /// Readable and writable (`PROT_READ | PROT_WRITE`, `PAGE_READWRITE`).
+    ReadWrite,
+}
+
+/// A mapping created by `mmap` or `VirtualAlloc`.
+#[derive(Debug, Clone)]
+pub struct Mapping {
+    /// The state of each page. The mapping is exactly as long as these pages.
+    pub pages: Vec<Page>,
+    /// Whether the mapping has the contents of a file rather than zeros.
+    pub file_backed: bool,
+}
+
+impl Mapping {
+    /// A mapping of `size` bytes, which must be a multiple of the page size, whose pages are all
+    /// in state `page`.
+    pub fn new(size: Size, page: Page, file_backed: bool) -> Self {
+        assert_eq!(size.bytes() % PAGE_SIZE, 0, "mappings must consist of whole pages");
+        let num_pages = usize::try_from(size.bytes() / PAGE_SIZE).unwrap();
+        Mapping { pages: vec![page; num_pages], file_backed }
+    }
+
+    /// The length of the mapping.
+    pub fn size(&self) -> Size {
+        Size::from_bytes(u64::try_from(self.pages.len()).unwrap() * PAGE_SIZE)
+    }
+
+    /// Changes the length of the mapping to `size`, a multiple of the page size. New pages get
+    /// the state of the last page.
+    pub fn resize(&mut self, size: Size) {
+        let last = *self.pages.last().unwrap();
+        self.pages.resize(usize::try_from(size.bytes() / PAGE_SIZE).unwrap(), last);
+    }
+
+    /// The pages that the `len` bytes at `offset` overlap, or `None` if the range is not within
+    /// the mapping.
+    pub fn pages_mut(&mut self, offset: u64, len: u64) -> Option<&mut [Page]> {
+        let end = offset.checked_add(len)?;
+        if end > self.size().bytes() {
+            return None;
+        }
+        let first = usize::try_from(offset / PAGE_SIZE).unwrap();
+        let last = usize::try_from((end + PAGE_SIZE - 1) / PAGE_SIZE).unwrap();
+        Some(&mut self.pages[first..last])
+    }
+
+    /// Accessing a page that is not accessible raises `SIGSEGV` (or an access violation on
+    /// Windows), and it is UB for a Rust program to do that.
+    pub fn check_access<'tcx>(
+        &self,
+        alloc_id: AllocId,
+        range: AllocRange,
+        write: bool,
+    ) -> InterpResult<'tcx> {
+        if range.size.bytes() == 0 {
+            return Ok(());
+        }
+        let first = usize::try_from(range.start.bytes() / PAGE_SIZE).unwrap();
+        let last = usize::try_from((range.end().bytes() - 1) / PAGE_SIZE).unwrap();
+        for page in &self.pages[first..=last] {
+            match page {
+                Page::Reserved =>
+                    throw_ub_format!(
+                        "accessing memory of {:?} that is reserved but not committed",
+                        alloc_id
+                    ),
+                Page::NoAccess =>
+                    throw_ub_format!(
+                        "accessing memory of {:?} that is mapped without access permissions",
+                        alloc_id
+                    ),
+                Page::ReadOnly if write =>
+                    throw_ub_format!("writing to {:?}, which is mapped read-only", alloc_id),
+                Page::ReadOnly | Page::ReadWrite => {}
+            }
+        }
+        Ok(())
+    }
+}
+
+impl<'mir, 'tcx: 'mir> EvalContextExt<'mir, 'tcx> for crate::MiriEvalContext<'mir, 'tcx> {}
+pub trait EvalContextExt<'mir, 'tcx: 'mir>: crate::MiriEvalContextExt<'mir, 'tcx> {
+    /// Rounds `length` up to a multiple of the page size. Returns `None` if the result does not
+    /// fit into the address space.
+    fn round_to_pages(&self, length: u64) -> Option<Size> {
+        let this = self.eval_context_ref();
+        let rounded = length.checked_add(PAGE_SIZE - 1)? / PAGE_SIZE * PAGE_SIZE;
+        (rounded <= this.machine_usize_max()).then(|| Size::from_bytes(rounded))
+    }
+
+    /// The state of the mapping `alloc_id`, or `None` if it is not a live mapping.
+    fn mapping(&self, alloc_id: AllocId) -> Option<&Mapping> {
+        let this = self.eval_context_ref();
+        this.get_alloc_extra(alloc_id).ok()?.mapping.as_ref()
+    }
+
+    /// The mutable state of the mapping `alloc_id`, which must be a live mapping.
+    fn mapping_mut(&mut self, alloc_id: AllocId) -> InterpResult<'tcx, &mut Mapping> {
+        let this = self.eval_context_mut();
+        Ok(this.get_alloc_extra_mut(alloc_id)?.0.mapping.as_mut().unwrap())
+    }
+
+    /// Turns the new allocation `alloc_id` into a mapping with the state `mapping`.
+    fn set_mapping(&mut self, alloc_id: AllocId, mapping: Mapping) -> InterpResult<'tcx> {
+        let this = self.eval_context_mut();
+        this.get_alloc_extra_mut(alloc_id)?.0.mapping = Some(mapping);
+        Ok(())
+    }
+
+    /// Takes the state out of the mapping `alloc_id`, which must be a live mapping. Until it is
+    /// put back with `set_mapping`, all of its pages can be accessed.
+    fn take_mapping(&mut self, alloc_id: AllocId) -> InterpResult<'tcx, Mapping> {
+        let this = self.eval_context_mut();
+        Ok(this.get_alloc_extra_mut(alloc_id)?.0.mapping.take().unwrap())
+    }
+}
@@ -1,4 +1,4 @@
-mod backtrace;
+pub mod backtrace;
 pub mod foreign_items;
 pub mod intrinsics;
 pub mod posix;
@@ -6,6 +6,7 @@ pub mod windows;
 
 pub mod dlsym;
 pub mod env;
+pub mod mapping;
 pub mod os_str;
 pub mod panic;
 pub mod time;
@@ -21,6 +22,19 @@ use rustc_target::spec::abi::Abi;
 use crate::*;
 use helpers::check_arg_count;
 
+/// The tracking issue of `abi`, if it is an ABI for interrupt handlers or GPU kernels, which Miri
+/// cannot call.
+fn unsupported_abi_tracking_issue(abi: Abi) -> Option<u32> {
+    Some(match abi {
+        Abi::X86Interrupt => 40180,
+        Abi::Msp430Interrupt => 38487,
+        Abi::AvrInterrupt | Abi::AvrNonBlockingInterrupt => 69664,
+        Abi::PtxKernel => 38788,
+        Abi::AmdGpuKernel => 51575,
+        _ => return None,
+    })
+}
+
 impl<'mir, 'tcx: 'mir> EvalContextExt<'mir, 'tcx> for crate::MiriEvalContext<'mir, 'tcx> {}
 pub trait EvalContextExt<'mir, 'tcx: 'mir>: crate::MiriEvalContextExt<'mir, 'tcx> {
     fn find_mir_or_eval_fn(
@@ -34,6 +48,16 @@ pub trait EvalContextExt<'mir, 'tcx: 'mir>: crate::MiriEvalContextExt<'mir, 'tcx
         let this = self.eval_context_mut();
         trace!("eval_fn_call: {:#?}, {:?}", instance, ret.map(|p| p.0));
 
+        if let Some(issue) = unsupported_abi_tracking_issue(abi) {
+            throw_unsup_format!(
+                "calling `{}` with the `{}` ABI is not supported; \
+                see https://github.com/rust-lang/rust/issues/{}",
+                instance,
+                abi.name(),
+                issue,
+            );
+        }
+
         // There are some more lang items we want to hook that CTFE does not hook (yet).
         if this.tcx.lang_items().align_offset_fn() == Some(instance.def.def_id()) {
             let [ptr, align] = check_arg_count(args)?;
@@ -42,6 +66,16 @@ pub trait EvalContextExt<'mir, 'tcx: 'mir>: crate::MiriEvalContextExt<'mir, 'tcx
             }
         }
 
+        // With `-Zmiri-compare-ctfe`, the calls of local `const fn`s are recorded to be compared
+        // after the program ran, which runs them with the compile-time side of
+        // `const_eval_select`.
+        if this.machine.ctfe_comparison.is_some() {
+            if this.call_const_eval_select(instance, args, ret, unwind)? {
+                return Ok(None);
+            }
+            this.record_const_fn_call(instance, args)?;
+        }
+
         // Try to see if we can do something about foreign items.
         if this.tcx.is_foreign_item(instance.def_id()) {
             // An external function call that does not have a MIR body. We either find MIR elsewhere
@@ -127,6 +127,11 @@ pub trait EvalContextExt<'mir, 'tcx: 'mir>: crate::MiriEvalContextExt<'mir, 'tcx
             stacked_borrows.borrow_mut().end_call(extra.call_id);
         }
 
+        if let (false, Some(unwind_backtrace)) = (unwinding, extra.unwind_backtrace.take()) {
+            // We've just popped a trace function frame pushed by `_Unwind_Backtrace`.
+            return this.handle_unwind_backtrace_pop(unwind_backtrace);
+        }
+
         // We only care about `catch_panic` if we're unwinding - if we're doing a normal
         // return, then we don't need to do anything special.
         if let (true, Some(catch_unwind)) = (unwinding, extra.catch_unwind.take()) {
@@ -9,11 +9,34 @@ use rustc_target::abi::{Align, Size};
 use rustc_target::spec::abi::Abi;
 
 use crate::*;
-use shims::foreign_items::EmulateByNameResult;
+use shims::foreign_items::{EmulateByNameResult, ForeignItemProvider};
 use shims::posix::fs::EvalContextExt as _;
+use shims::posix::passwd::EvalContextExt as _;
+use shims::posix::pipe::EvalContextExt as _;
+use shims::posix::process::EvalContextExt as _;
+use shims::posix::signal::EvalContextExt as _;
+use shims::posix::socket::EvalContextExt as _;
 use shims::posix::sync::EvalContextExt as _;
+use shims::posix::system::EvalContextExt as _;
 use shims::posix::thread::EvalContextExt as _;
 
+/// The shims that are available on all POSIX targets.
+pub struct PosixForeignItems;
+
+impl ForeignItemProvider for PosixForeignItems {
+    fn emulate_foreign_item_by_name<'mir, 'tcx>(
+        &self,
+        ecx: &mut MiriEvalContext<'mir, 'tcx>,
+        link_name: Symbol,
+        abi: Abi,
+        args: &[OpTy<'tcx, Tag>],
+        dest: &PlaceTy<'tcx, Tag>,
+        ret: mir::BasicBlock,
+    ) -> InterpResult<'tcx, EmulateByNameResult<'mir, 'tcx>> {
+        EvalContextExt::emulate_foreign_item_by_name(ecx, link_name, abi, args, dest, ret)
+    }
+}
+
 impl<'mir, 'tcx: 'mir> EvalContextExt<'mir, 'tcx> for crate::MiriEvalContext<'mir, 'tcx> {}
 pub trait EvalContextExt<'mir, 'tcx: 'mir>: crate::MiriEvalContextExt<'mir, 'tcx> {
     fn emulate_foreign_item_by_name(
@@ -39,13 +62,15 @@ pub trait EvalContextExt<'mir, 'tcx: 'mir>: crate::MiriEvalContextExt<'mir, 'tcx
                 this.write_scalar(Scalar::from_i32(result), dest)?;
             }
             "setenv" => {
-                let [name, value, overwrite] = this.check_shim(abi, Abi::C { unwind: false }, link_name, args)?;
+                let [name, value, overwrite] =
+                    this.check_shim(abi, Abi::C { unwind: false }, link_name, args)?;
                 this.read_scalar(overwrite)?.to_i32()?;
                 let result = this.setenv(name, value)?;
                 this.write_scalar(Scalar::from_i32(result), dest)?;
             }
             "getcwd" => {
-                let [buf, size] = this.check_shim(abi, Abi::C { unwind: false }, link_name, args)?;
+                let [buf, size] =
+                    this.check_shim(abi, Abi::C { unwind: false }, link_name, args)?;
                 let result = this.getcwd(buf, size)?;
                 this.write_pointer(result, dest)?;
             }
@@ -69,22 +94,70 @@ pub trait EvalContextExt<'mir, 'tcx: 'mir>: crate::MiriEvalContextExt<'mir, 'tcx
                 let result = this.fcntl(args)?;
                 this.write_scalar(Scalar::from_i32(result), dest)?;
             }
+            "dup" => {
+                let [oldfd] = this.check_shim(abi, Abi::C { unwind: false }, link_name, args)?;
+                let result = this.dup(oldfd)?;
+                this.write_scalar(Scalar::from_i32(result), dest)?;
+            }
+            "dup2" => {
+                let [oldfd, newfd] =
+                    this.check_shim(abi, Abi::C { unwind: false }, link_name, args)?;
+                let result = this.dup2(oldfd, newfd)?;
+                this.write_scalar(Scalar::from_i32(result), dest)?;
+            }
+            "ioctl" => {
+                // `ioctl` is variadic. The argument count is checked based on the request in
+                // `this.ioctl()`, so we do not use `check_shim` here.
+                this.check_abi_and_shim_symbol_clash(abi, Abi::C { unwind: false }, link_name)?;
+                let result = this.ioctl(args)?;
+                this.write_scalar(Scalar::from_i32(result), dest)?;
+            }
             "read" => {
-                let [fd, buf, count] = this.check_shim(abi, Abi::C { unwind: false }, link_name, args)?;
+                let [fd, buf, count] =
+                    this.check_shim(abi, Abi::C { unwind: false }, link_name, args)?;
                 let fd = this.read_scalar(fd)?.to_i32()?;
                 let buf = this.read_pointer(buf)?;
                 let count = this.read_scalar(count)?.to_machine_usize(this)?;
-                let result = this.read(fd, buf, count)?;
-                this.write_scalar(Scalar::from_machine_isize(result, this), dest)?;
+                this.read(fd, buf, count, dest)?;
             }
             "write" => {
-                let [fd, buf, n] = this.check_shim(abi, Abi::C { unwind: false }, link_name, args)?;
+                let [fd, buf, n] =
+                    this.check_shim(abi, Abi::C { unwind: false }, link_name, args)?;
                 let fd = this.read_scalar(fd)?.to_i32()?;
                 let buf = this.read_pointer(buf)?;
                 let count = this.read_scalar(n)?.to_machine_usize(this)?;
                 trace!("Called write({:?}, {:?}, {:?})", fd, buf, count);
-                let result = this.write(fd, buf, count)?;
-                // Now, `result` is the value we return back to the program.
+                this.write(fd, buf, count, dest)?;
+            }
+            "readv" => {
+                let [fd, iov, iovcnt] =
+                    this.check_shim(abi, Abi::C { unwind: false }, link_name, args)?;
+                this.readv(fd, iov, iovcnt, dest)?;
+            }
+            "writev" => {
+                let [fd, iov, iovcnt] =
+                    this.check_shim(abi, Abi::C { unwind: false }, link_name, args)?;
+                this.writev(fd, iov, iovcnt, dest)?;
+            }
+            "pread" | "pread64" => {
+                let [fd, buf, count, offset] =
+                    this.check_shim(abi, Abi::C { unwind: false }, link_name, args)?;
+                let fd = this.read_scalar(fd)?.to_i32()?;
+                let buf = this.read_pointer(buf)?;
+                let count = this.read_scalar(count)?.to_machine_usize(this)?;
+                // `off_t` is 32 bits wide on some targets.
+                let offset = this.read_scalar(offset)?.to_int(offset.layout.size)?;
+                let result = this.pread(fd, buf, count, i64::try_from(offset).unwrap())?;
+                this.write_scalar(Scalar::from_machine_isize(result, this), dest)?;
+            }
+            "pwrite" | "pwrite64" => {
+                let [fd, buf, count, offset] =
+                    this.check_shim(abi, Abi::C { unwind: false }, link_name, args)?;
+                let fd = this.read_scalar(fd)?.to_i32()?;
+                let buf = this.read_pointer(buf)?;
+                let count = this.read_scalar(count)?.to_machine_usize(this)?;
+                let offset = this.read_scalar(offset)?.to_int(offset.layout.size)?;
+                let result = this.pwrite(fd, buf, count, i64::try_from(offset).unwrap())?;
                 this.write_scalar(Scalar::from_machine_isize(result, this), dest)?;
             }
             "unlink" => {
@@ -93,17 +166,43 @@ pub trait EvalContextExt<'mir, 'tcx: 'mir>: crate::MiriEvalContextExt<'mir, 'tcx
                 this.write_scalar(Scalar::from_i32(result), dest)?;
             }
             "symlink" => {
-                let [target, linkpath] = this.check_shim(abi, Abi::C { unwind: false }, link_name, args)?;
+                let [target, linkpath] =
+                    this.check_shim(abi, Abi::C { unwind: false }, link_name, args)?;
                 let result = this.symlink(target, linkpath)?;
                 this.write_scalar(Scalar::from_i32(result), dest)?;
             }
             "rename" => {
-                let [oldpath, newpath] = this.check_shim(abi, Abi::C { unwind: false }, link_name, args)?;
+                let [oldpath, newpath] =
+                    this.check_shim(abi, Abi::C { unwind: false }, link_name, args)?;
                 let result = this.rename(oldpath, newpath)?;
                 this.write_scalar(Scalar::from_i32(result), dest)?;
             }
+            "stat" | "stat64" => {
+                let [path, buf] =
+                    this.check_shim(abi, Abi::C { unwind: false }, link_name, args)?;
+                let result = this.stat(path, buf)?;
+                this.write_scalar(Scalar::from_i32(result), dest)?;
+            }
+            "lstat" | "lstat64" => {
+                let [path, buf] =
+                    this.check_shim(abi, Abi::C { unwind: false }, link_name, args)?;
+                let result = this.lstat(path, buf)?;
+                this.write_scalar(Scalar::from_i32(result), dest)?;
+            }
+            "fstat" | "fstat64" => {
+                let [fd, buf] = this.check_shim(abi, Abi::C { unwind: false }, link_name, args)?;
+                let result = this.fstat(fd, buf)?;
+                this.write_scalar(Scalar::from_i32(result), dest)?;
+            }
+            "fstatat" | "fstatat64" => {
+                let [dirfd, path, buf, flags] =
+                    this.check_shim(abi, Abi::C { unwind: false }, link_name, args)?;
+                let result = this.fstatat(dirfd, path, buf, flags)?;
+                this.write_scalar(Scalar::from_i32(result), dest)?;
+            }
             "mkdir" => {
-                let [path, mode] = this.check_shim(abi, Abi::C { unwind: false }, link_name, args)?;
+                let [path, mode] =
+                    this.check_shim(abi, Abi::C { unwind: false }, link_name, args)?;
                 let result = this.mkdir(path, mode)?;
                 this.write_scalar(Scalar::from_i32(result), dest)?;
             }
@@ -118,7 +217,8 @@ pub trait EvalContextExt<'mir, 'tcx: 'mir>: crate::MiriEvalContextExt<'mir, 'tcx
                 this.write_scalar(Scalar::from_i32(result), dest)?;
             }
             "lseek" | "lseek64" => {
-                let [fd, offset, whence] = this.check_shim(abi, Abi::C { unwind: false }, link_name, args)?;
+                let [fd, offset, whence] =
+                    this.check_shim(abi, Abi::C { unwind: false }, link_name, args)?;
                 let result = this.lseek64(fd, offset, whence)?;
                 // "lseek" is only used on macOS which is 64bit-only, so `i64` always works.
                 this.write_scalar(Scalar::from_i64(result), dest)?;
@@ -134,20 +234,126 @@ pub trait EvalContextExt<'mir, 'tcx: 'mir>: crate::MiriEvalContextExt<'mir, 'tcx
                 this.write_scalar(Scalar::from_i32(result), dest)?;
             }
             "readlink" => {
-                let [pathname, buf, bufsize] = this.check_shim(abi, Abi::C { unwind: false }, link_name, args)?;
+                let [pathname, buf, bufsize] =
+                    this.check_shim(abi, Abi::C { unwind: false }, link_name, args)?;
                 let result = this.readlink(pathname, buf, bufsize)?;
                 this.write_scalar(Scalar::from_machine_isize(result, this), dest)?;
             }
+            "openat" | "openat64" => {
+                // `openat` is variadic, like `open`.
+                this.check_abi_and_shim_symbol_clash(abi, Abi::C { unwind: false }, link_name)?;
+                let result = this.openat(args)?;
+                this.write_scalar(Scalar::from_i32(result), dest)?;
+            }
+            "unlinkat" => {
+                let [dirfd, path, flags] =
+                    this.check_shim(abi, Abi::C { unwind: false }, link_name, args)?;
+                let result = this.unlinkat(dirfd, path, flags)?;
+                this.write_scalar(Scalar::from_i32(result), dest)?;
+            }
+            "symlinkat" => {
+                let [target, newdirfd, linkpath] =
+                    this.check_shim(abi, Abi::C { unwind: false }, link_name, args)?;
+                let result = this.symlinkat(target, newdirfd, linkpath)?;
+                this.write_scalar(Scalar::from_i32(result), dest)?;
+            }
+            "renameat" => {
+                let [olddirfd, oldpath, newdirfd, newpath] =
+                    this.check_shim(abi, Abi::C { unwind: false }, link_name, args)?;
+                let result = this.renameat(olddirfd, oldpath, newdirfd, newpath)?;
+                this.write_scalar(Scalar::from_i32(result), dest)?;
+            }
+            "mkdirat" => {
+                let [dirfd, path, mode] =
+                    this.check_shim(abi, Abi::C { unwind: false }, link_name, args)?;
+                let result = this.mkdirat(dirfd, path, mode)?;
+                this.write_scalar(Scalar::from_i32(result), dest)?;
+            }
+            "readlinkat" => {
+                let [dirfd, pathname, buf, bufsize] =
+                    this.check_shim(abi, Abi::C { unwind: false }, link_name, args)?;
+                let result = this.readlinkat(dirfd, pathname, buf, bufsize)?;
+                this.write_scalar(Scalar::from_machine_isize(result, this), dest)?;
+            }
+
+            // Pipes
+            "pipe" => {
+                let [fds] = this.check_shim(abi, Abi::C { unwind: false }, link_name, args)?;
+                let result = this.pipe2(fds, None)?;
+                this.write_scalar(Scalar::from_i32(result), dest)?;
+            }
+
+            // Sockets
+            "socket" => {
+                let [domain, ty, protocol] =
+                    this.check_shim(abi, Abi::C { unwind: false }, link_name, args)?;
+                let result = this.socket(domain, ty, protocol)?;
+                this.write_scalar(Scalar::from_i32(result), dest)?;
+            }
+            "socketpair" => {
+                let [domain, ty, protocol, sv] =
+                    this.check_shim(abi, Abi::C { unwind: false }, link_name, args)?;
+                let result = this.socketpair(domain, ty, protocol, sv)?;
+                this.write_scalar(Scalar::from_i32(result), dest)?;
+            }
+            "bind" => {
+                let [fd, addr, addrlen] =
+                    this.check_shim(abi, Abi::C { unwind: false }, link_name, args)?;
+                let result = this.bind(fd, addr, addrlen)?;
+                this.write_scalar(Scalar::from_i32(result), dest)?;
+            }
+            "listen" => {
+                let [fd, backlog] =
+                    this.check_shim(abi, Abi::C { unwind: false }, link_name, args)?;
+                let result = this.listen(fd, backlog)?;
+                this.write_scalar(Scalar::from_i32(result), dest)?;
+            }
+            "connect" => {
+                let [fd, addr, addrlen] =
+                    this.check_shim(abi, Abi::C { unwind: false }, link_name, args)?;
+                let result = this.connect(fd, addr, addrlen)?;
+                this.write_scalar(Scalar::from_i32(result), dest)?;
+            }
+            "accept" => {
+                let [fd, addr, addrlen] =
+                    this.check_shim(abi, Abi::C { unwind: false }, link_name, args)?;
+                this.accept4(fd, addr, addrlen, None, dest)?;
+            }
+            "shutdown" => {
+                let [fd, how] = this.check_shim(abi, Abi::C { unwind: false }, link_name, args)?;
+                let result = this.shutdown(fd, how)?;
+                this.write_scalar(Scalar::from_i32(result), dest)?;
+            }
+            "send" => {
+                let [fd, buf, len, flags] =
+                    this.check_shim(abi, Abi::C { unwind: false }, link_name, args)?;
+                this.send(fd, buf, len, flags, dest)?;
+            }
+            "recv" => {
+                let [fd, buf, len, flags] =
+                    this.check_shim(abi, Abi::C { unwind: false }, link_name, args)?;
+                this.recv(fd, buf, len, flags, dest)?;
+            }
+            "setsockopt" => {
+                let [fd, level, name, value, len] =
+                    this.check_shim(abi, Abi::C { unwind: false }, link_name, args)?;
+                let result = this.setsockopt(fd, level, name, value, len)?;
+                this.write_scalar(Scalar::from_i32(result), dest)?;
+            }
 
             // Allocation
             "posix_memalign" => {
-                let [ret, align, size] = this.check_shim(abi, Abi::C { unwind: false }, link_name, args)?;
+                let [ret, align, size] =
+                    this.check_shim(abi, Abi::C { unwind: false }, link_name, args)?;
                 let ret = this.deref_operand(ret)?;
                 let align = this.read_scalar(align)?.to_machine_usize(this)?;
                 let size = this.read_scalar(size)?.to_machine_usize(this)?;
                 // Align must be power of 2, and also at least ptr-sized (POSIX rules).
                 if !align.is_power_of_two() {
-                    throw_ub_format!("posix_memalign: alignment must be a power of two, but is {}", align);
+                    throw_ub_format!(
+                        "posix_memalign: alignment must be a power of two, but is {}",
+                        align
+                    );
                 }
                 if align < this.pointer_size().bytes() {
                     throw_ub_format!(
@@ -171,7 +377,8 @@ pub trait EvalContextExt<'mir, 'tcx: 'mir>: crate::MiriEvalContextExt<'mir, 'tcx
 
             // Dynamic symbol loading
             "dlsym" => {
-                let [handle, symbol] = this.check_shim(abi, Abi::C { unwind: false }, link_name, args)?;
+                let [handle, symbol] =
+                    this.check_shim(abi, Abi::C { unwind: false }, link_name, args)?;
                 this.read_scalar(handle)?.to_machine_usize(this)?;
                 let symbol = this.read_pointer(symbol)?;
                 let symbol_name = this.read_c_str(symbol)?;
@@ -182,6 +389,42 @@ pub trait EvalContextExt<'mir, 'tcx: 'mir>: crate::MiriEvalContextExt<'mir, 'tcx
                     this.write_null(dest)?;
                 }
             }
+            "dladdr" => {
+                let [addr, info] =
+                    this.check_shim(abi, Abi::C { unwind: false }, link_name, args)?;
+                let result = this.dladdr(addr, info)?;
+                this.write_scalar(Scalar::from_i32(result), dest)?;
+            }
+
+            // Stack unwinding, as used by the `backtrace` crate to walk the stack
+            "_Unwind_Backtrace" => {
+                let [trace_fn, arg] =
+                    this.check_shim(abi, Abi::C { unwind: false }, link_name, args)?;
+                this.handle_unwind_backtrace(trace_fn, arg, dest, ret)?;
+                return Ok(EmulateByNameResult::AlreadyJumped);
+            }
+            "_Unwind_GetIP" => {
+                let [context] = this.check_shim(abi, Abi::C { unwind: false }, link_name, args)?;
+                let result = this.unwind_get_ip(context, None)?;
+                this.write_scalar(result, dest)?;
+            }
+            "_Unwind_GetIPInfo" => {
+                let [context, ip_before_insn] =
+                    this.check_shim(abi, Abi::C { unwind: false }, link_name, args)?;
+                let result = this.unwind_get_ip(context, Some(ip_before_insn))?;
+                this.write_scalar(result, dest)?;
+            }
+            "_Unwind_GetCFA" => {
+                let [context] = this.check_shim(abi, Abi::C { unwind: false }, link_name, args)?;
+                this.unwind_get_ip(context, None)?;
+                // There is no stack memory to point to.
+                this.write_null(dest)?;
+            }
+            "_Unwind_FindEnclosingFunction" => {
+                let [pc] = this.check_shim(abi, Abi::C { unwind: false }, link_name, args)?;
+                let result = this.unwind_find_enclosing_function(pc)?;
+                this.write_pointer(result, dest)?;
+            }
 
             // Querying system information
             "sysconf" => {
@@ -192,6 +435,8 @@ pub trait EvalContextExt<'mir, 'tcx: 'mir>: crate::MiriEvalContextExt<'mir, 'tcx
                     ("_SC_PAGESIZE", Scalar::from_int(PAGE_SIZE, this.pointer_size())),
                     ("_SC_NPROCESSORS_CONF", Scalar::from_int(NUM_CPUS, this.pointer_size())),
                     ("_SC_NPROCESSORS_ONLN", Scalar::from_int(NUM_CPUS, this.pointer_size())),
+                    // Clock ticks of 10ms, as used by `times`.
+                    ("_SC_CLK_TCK", Scalar::from_int(100, this.pointer_size())),
                 ];
                 let mut result = None;
                 for &(sysconf_name, value) in sysconfs {
@@ -210,7 +455,8 @@ pub trait EvalContextExt<'mir, 'tcx: 'mir>: crate::MiriEvalContextExt<'mir, 'tcx
 
             // Thread-local storage
             "pthread_key_create" => {
-                let [key, dtor] = this.check_shim(abi, Abi::C { unwind: false }, link_name, args)?;
+                let [key, dtor] =
+                    this.check_shim(abi, Abi::C { unwind: false }, link_name, args)?;
                 let key_place = this.deref_operand(key)?;
                 let dtor = this.read_pointer(dtor)?;
 
@@ -253,11 +499,17 @@ pub trait EvalContextExt<'mir, 'tcx: 'mir>: crate::MiriEvalContextExt<'mir, 'tcx
                 this.write_scalar(ptr, dest)?;
             }
             "pthread_setspecific" => {
-                let [key, new_ptr] = this.check_shim(abi, Abi::C { unwind: false }, link_name, args)?;
+                let [key, new_ptr] =
+                    this.check_shim(abi, Abi::C { unwind: false }, link_name, args)?;
                 let key = this.read_scalar(key)?.check_init()?.to_bits(key.layout.size)?;
                 let active_thread = this.get_active_thread();
                 let new_data = this.read_scalar(new_ptr)?;
-                this.machine.tls.store_tls(key, active_thread, new_data.check_init()?, &*this.tcx)?;
+                this.machine.tls.store_tls(
+                    key,
+                    active_thread,
+                    new_data.check_init()?,
+                    &*this.tcx,
+                )?;
 
                 // Return success (`0`).
                 this.write_null(dest)?;
@@ -270,7 +522,8 @@ pub trait EvalContextExt<'mir, 'tcx: 'mir>: crate::MiriEvalContextExt<'mir, 'tcx
                 this.write_scalar(Scalar::from_i32(result), dest)?;
             }
             "pthread_mutexattr_settype" => {
-                let [attr, kind] = this.check_shim(abi, Abi::C { unwind: false }, link_name, args)?;
+                let [attr, kind] =
+                    this.check_shim(abi, Abi::C { unwind: false }, link_name, args)?;
                 let result = this.pthread_mutexattr_settype(attr, kind)?;
                 this.write_scalar(Scalar::from_i32(result), dest)?;
             }
@@ -280,7 +533,8 @@ pub trait EvalContextExt<'mir, 'tcx: 'mir>: crate::MiriEvalContextExt<'mir, 'tcx
                 this.write_scalar(Scalar::from_i32(result), dest)?;
             }
             "pthread_mutex_init" => {
-                let [mutex, attr] = this.check_shim(abi, Abi::C { unwind: false }, link_name, args)?;
+                let [mutex, attr] =
+                    this.check_shim(abi, Abi::C { unwind: false }, link_name, args)?;
                 let result = this.pthread_mutex_init(mutex, attr)?;
                 this.write_scalar(Scalar::from_i32(result), dest)?;
             }
@@ -345,7 +599,8 @@ pub trait EvalContextExt<'mir, 'tcx: 'mir>: crate::MiriEvalContextExt<'mir, 'tcx
                 this.write_scalar(Scalar::from_i32(result), dest)?;
             }
             "pthread_cond_init" => {
-                let [cond, attr] = this.check_shim(abi, Abi::C { unwind: false }, link_name, args)?;
+                let [cond, attr] =
+                    this.check_shim(abi, Abi::C { unwind: false }, link_name, args)?;
                 let result = this.pthread_cond_init(cond, attr)?;
                 this.write_scalar(Scalar::from_i32(result), dest)?;
             }
@@ -360,12 +615,14 @@ pub trait EvalContextExt<'mir, 'tcx: 'mir>: crate::MiriEvalContextExt<'mir, 'tcx
                 this.write_scalar(Scalar::from_i32(result), dest)?;
             }
             "pthread_cond_wait" => {
-                let [cond, mutex] = this.check_shim(abi, Abi::C { unwind: false }, link_name, args)?;
+                let [cond, mutex] =
+                    this.check_shim(abi, Abi::C { unwind: false }, link_name, args)?;
                 let result = this.pthread_cond_wait(cond, mutex)?;
                 this.write_scalar(Scalar::from_i32(result), dest)?;
             }
             "pthread_cond_timedwait" => {
-                let [cond, mutex, abstime] = this.check_shim(abi, Abi::C { unwind: false }, link_name, args)?;
+                let [cond, mutex, abstime] =
+                    this.check_shim(abi, Abi::C { unwind: false }, link_name, args)?;
                 this.pthread_cond_timedwait(cond, mutex, abstime, dest)?;
             }
             "pthread_cond_destroy" => {
@@ -376,12 +633,14 @@ pub trait EvalContextExt<'mir, 'tcx: 'mir>: crate::MiriEvalContextExt<'mir, 'tcx
 
             // Threading
             "pthread_create" => {
-                let [thread, attr, start, arg] = this.check_shim(abi, Abi::C { unwind: false }, link_name, args)?;
+                let [thread, attr, start, arg] =
+                    this.check_shim(abi, Abi::C { unwind: false }, link_name, args)?;
                 let result = this.pthread_create(thread, attr, start, arg)?;
                 this.write_scalar(Scalar::from_i32(result), dest)?;
             }
             "pthread_join" => {
-                let [thread, retval] = this.check_shim(abi, Abi::C { unwind: false }, link_name, args)?;
+                let [thread, retval] =
+                    this.check_shim(abi, Abi::C { unwind: false }, link_name, args)?;
                 let result = this.pthread_join(thread, retval)?;
                 this.write_scalar(Scalar::from_i32(result), dest)?;
             }
@@ -401,22 +660,177 @@ pub trait EvalContextExt<'mir, 'tcx: 'mir>: crate::MiriEvalContextExt<'mir, 'tcx
             }
             "nanosleep" => {
                 let [req, rem] = this.check_shim(abi, Abi::C { unwind: false }, link_name, args)?;
-                let result = this.nanosleep(req, rem)?;
+                this.nanosleep(req, rem, dest)?;
+            }
+
+            // Time related shims
+            "gettimeofday" => {
+                let [tv, tz] = this.check_shim(abi, Abi::C { unwind: false }, link_name, args)?;
+                let result = this.gettimeofday(tv, tz)?;
+                this.write_scalar(Scalar::from_i32(result), dest)?;
+            }
+            "gmtime_r" => {
+                let [timep, result] =
+                    this.check_shim(abi, Abi::C { unwind: false }, link_name, args)?;
+                this.gmtime_r(timep, result, dest)?;
+            }
+            "localtime_r" => {
+                let [timep, result] =
+                    this.check_shim(abi, Abi::C { unwind: false }, link_name, args)?;
+                this.localtime_r(timep, result, dest)?;
+            }
+            "times" => {
+                let [buf] = this.check_shim(abi, Abi::C { unwind: false }, link_name, args)?;
+                let result = this.times(buf)?;
+                this.write_int(result, dest)?;
+            }
+            "getrusage" => {
+                let [who, usage] =
+                    this.check_shim(abi, Abi::C { unwind: false }, link_name, args)?;
+                let result = this.getrusage(who, usage)?;
+                this.write_scalar(Scalar::from_i32(result), dest)?;
+            }
+            "tzset" => {
+                let [] = this.check_shim(abi, Abi::C { unwind: false }, link_name, args)?;
+                // The time zone is fixed by `-Zmiri-utc-offset`, there is nothing to read.
+            }
+
+            // Timers and signals
+            "alarm" => {
+                let [seconds] = this.check_shim(abi, Abi::C { unwind: false }, link_name, args)?;
+                let result = this.alarm(seconds)?;
+                this.write_scalar(Scalar::from_u32(result), dest)?;
+            }
+            "setitimer" => {
+                let [which, new, old] =
+                    this.check_shim(abi, Abi::C { unwind: false }, link_name, args)?;
+                let result = this.setitimer(which, new, old)?;
+                this.write_scalar(Scalar::from_i32(result), dest)?;
+            }
+            "getitimer" => {
+                let [which, curr] =
+                    this.check_shim(abi, Abi::C { unwind: false }, link_name, args)?;
+                let result = this.getitimer(which, curr)?;
+                this.write_scalar(Scalar::from_i32(result), dest)?;
+            }
+
+            // Processes
+            "getpid" => {
+                let [] = this.check_shim(abi, Abi::C { unwind: false }, link_name, args)?;
+                this.write_scalar(Scalar::from_u32(PROCESS_ID), dest)?;
+            }
+            "fork" | "vfork" => {
+                let [] = this.check_shim(abi, Abi::C { unwind: false }, link_name, args)?;
+                let result = this.fork(&link_name.as_str())?;
+                this.write_scalar(Scalar::from_i32(result), dest)?;
+            }
+            "execv" | "execvp" => {
+                let [path, argv] =
+                    this.check_shim(abi, Abi::C { unwind: false }, link_name, args)?;
+                let result = this.execve(&link_name.as_str(), path, argv)?;
+                this.write_scalar(Scalar::from_i32(result), dest)?;
+            }
+            "execve" | "execvpe" => {
+                let [path, argv, envp] =
+                    this.check_shim(abi, Abi::C { unwind: false }, link_name, args)?;
+                this.read_pointer(envp)?;
+                let result = this.execve(&link_name.as_str(), path, argv)?;
+                this.write_scalar(Scalar::from_i32(result), dest)?;
+            }
+            "posix_spawn" | "posix_spawnp" => {
+                let [pid, path, file_actions, attrp, argv, envp] =
+                    this.check_shim(abi, Abi::C { unwind: false }, link_name, args)?;
+                this.read_pointer(file_actions)?;
+                this.read_pointer(attrp)?;
+                this.read_pointer(envp)?;
+                let result = this.posix_spawn(&link_name.as_str(), pid, path, argv)?;
+                this.write_scalar(result, dest)?;
+            }
+            // The spawn configuration is never used, so these only need to succeed.
+            | "posix_spawnattr_init"
+            | "posix_spawnattr_destroy"
+            | "posix_spawn_file_actions_init"
+            | "posix_spawn_file_actions_destroy" => {
+                let [obj] = this.check_shim(abi, Abi::C { unwind: false }, link_name, args)?;
+                this.read_pointer(obj)?;
+                this.write_null(dest)?;
+            }
+            | "posix_spawnattr_setflags"
+            | "posix_spawnattr_setpgroup"
+            | "posix_spawnattr_setsigmask"
+            | "posix_spawnattr_setsigdefault"
+            | "posix_spawn_file_actions_addclose" => {
+                let [obj, _] = this.check_shim(abi, Abi::C { unwind: false }, link_name, args)?;
+                this.read_pointer(obj)?;
+                this.write_null(dest)?;
+            }
+            "posix_spawn_file_actions_adddup2" => {
+                let [file_actions, fd, newfd] =
+                    this.check_shim(abi, Abi::C { unwind: false }, link_name, args)?;
+                this.read_pointer(file_actions)?;
+                this.read_scalar(fd)?.to_i32()?;
+                this.read_scalar(newfd)?.to_i32()?;
+                this.write_null(dest)?;
+            }
+            "waitpid" => {
+                let [pid, status, options] =
+                    this.check_shim(abi, Abi::C { unwind: false }, link_name, args)?;
+                let result = this.waitpid(pid, status, options)?;
+                this.write_scalar(Scalar::from_i32(result), dest)?;
+            }
+            "sigemptyset" => {
+                let [set] = this.check_shim(abi, Abi::C { unwind: false }, link_name, args)?;
+                let result = this.sigemptyset(set)?;
+                this.write_scalar(Scalar::from_i32(result), dest)?;
+            }
+            "sigaddset" => {
+                let [set, signum] =
+                    this.check_shim(abi, Abi::C { unwind: false }, link_name, args)?;
+                let result = this.sigaddset(set, signum)?;
+                this.write_scalar(Scalar::from_i32(result), dest)?;
+            }
+
+            // System identification
+            "uname" => {
+                let [buf] = this.check_shim(abi, Abi::C { unwind: false }, link_name, args)?;
+                let result = this.uname(buf)?;
+                this.write_scalar(Scalar::from_i32(result), dest)?;
+            }
+            "gethostname" => {
+                let [name, len] =
+                    this.check_shim(abi, Abi::C { unwind: false }, link_name, args)?;
+                let result = this.gethostname(name, len)?;
+                this.write_scalar(Scalar::from_i32(result), dest)?;
+            }
+
+            // Users
+            "getuid" | "geteuid" => {
+                let [] = this.check_shim(abi, Abi::C { unwind: false }, link_name, args)?;
+                let result = this.getuid();
+                this.write_scalar(Scalar::from_u32(result), dest)?;
+            }
+            "getpwuid_r" => {
+                let [uid, pwd, buf, buflen, result] =
+                    this.check_shim(abi, Abi::C { unwind: false }, link_name, args)?;
+                let result = this.getpwuid_r(uid, pwd, buf, buflen, result)?;
+                this.write_scalar(Scalar::from_i32(result), dest)?;
+            }
+            "getpwnam_r" => {
+                let [name, pwd, buf, buflen, result] =
+                    this.check_shim(abi, Abi::C { unwind: false }, link_name, args)?;
+                let result = this.getpwnam_r(name, pwd, buf, buflen, result)?;
                 this.write_scalar(Scalar::from_i32(result), dest)?;
             }
 
             // Miscellaneous
             "isatty" => {
                 let [fd] = this.check_shim(abi, Abi::C { unwind: false }, link_name, args)?;
-                this.read_scalar(fd)?.to_i32()?;
-                // "returns 1 if fd is an open file descriptor referring to a terminal; otherwise 0 is returned, and errno is set to indicate the error"
-                // FIXME: we just say nothing is a terminal.
-                let enotty = this.eval_libc("ENOTTY")?;
-                this.set_last_error(enotty)?;
-                this.write_null(dest)?;
+                let result = this.isatty(fd)?;
+                this.write_scalar(Scalar::from_i32(result), dest)?;
             }
             "pthread_atfork" => {
-                let [prepare, parent, child] = this.check_shim(abi, Abi::C { unwind: false }, link_name, args)?;
+                let [prepare, parent, child] =
+                    this.check_shim(abi, Abi::C { unwind: false }, link_name, args)?;
                 this.read_pointer(prepare)?;
                 this.read_pointer(parent)?;
                 this.read_pointer(child)?;
@@ -424,64 +838,69 @@ pub trait EvalContextExt<'mir, 'tcx: 'mir>: crate::MiriEvalContextExt<'mir, 'tcx
                 this.write_null(dest)?;
             }
             "strerror_r" | "__xpg_strerror_r" => {
-                let [errnum, buf, buflen] = this.check_shim(abi, Abi::C { unwind: false }, link_name, args)?;
+                let [errnum, buf, buflen] =
+                    this.check_shim(abi, Abi::C { unwind: false }, link_name, args)?;
                 let errnum = this.read_scalar(errnum)?.check_init()?;
                 let buf = this.read_pointer(buf)?;
                 let buflen = this.read_scalar(buflen)?.to_machine_usize(this)?;
 
                 let error = this.errnum_to_io_error(errnum)?;
                 let formatted = error.to_string();
-                let (complete, _) = this.write_os_str_to_c_str(OsStr::new(&formatted), buf, buflen)?;
+                let (complete, _) =
+                    this.write_os_str_to_c_str(OsStr::new(&formatted), buf, buflen)?;
                 let ret = if complete { 0 } else { this.eval_libc_i32("ERANGE")? };
                 this.write_int(ret, dest)?;
             }
 
             // Incomplete shims that we "stub out" just to get pre-main initialization code to work.
             // These shims are enabled only when the caller is in the standard library.
-            "pthread_attr_getguardsize"
-            if this.frame_in_std() => {
-                let [_attr, guard_size] = this.check_shim(abi, Abi::C { unwind: false }, link_name, args)?;
+            "pthread_attr_getguardsize" if this.frame_in_std() => {
+                let [_attr, guard_size] =
+                    this.check_shim(abi, Abi::C { unwind: false }, link_name, args)?;
                 let guard_size = this.deref_operand(guard_size)?;
                 let guard_size_layout = this.libc_ty_layout("size_t")?;
-                this.write_scalar(Scalar::from_uint(crate::PAGE_SIZE, guard_size_layout.size), &guard_size.into())?;
+                this.write_scalar(
+                    Scalar::from_uint(crate::PAGE_SIZE, guard_size_layout.size),
+                    &guard_size.into(),
+                )?;
 
                 // Return success (`0`).
                 this.write_null(dest)?;
             }
 
-            | "pthread_attr_init"
-            | "pthread_attr_destroy"
-            if this.frame_in_std() => {
+            | "pthread_attr_init" | "pthread_attr_destroy" if this.frame_in_std() => {
                 let [_] = this.check_shim(abi, Abi::C { unwind: false }, link_name, args)?;
                 this.write_null(dest)?;
             }
-            | "pthread_attr_setstacksize"
-            if this.frame_in_std() => {
+            | "pthread_attr_setstacksize" if this.frame_in_std() => {
                 let [_, _] = this.check_shim(abi, Abi::C { unwind: false }, link_name, args)?;
                 this.write_null(dest)?;
             }
 
-            | "signal"
-            | "sigaltstack"
-            if this.frame_in_std() => {
+            | "signal" | "sigaltstack" if this.frame_in_std() => {
                 let [_, _] = this.check_shim(abi, Abi::C { unwind: false }, link_name, args)?;
                 this.write_null(dest)?;
             }
-            | "sigaction"
-            | "mprotect"
-            if this.frame_in_std() => {
+            | "sigaction" | "mprotect" if this.frame_in_std() => {
                 let [_, _, _] = this.check_shim(abi, Abi::C { unwind: false }, link_name, args)?;
                 this.write_null(dest)?;
             }
-
-            // Platform-specific shims
-            _ => {
-                match this.tcx.sess.target.os.as_ref() {
-                    "linux" => return shims::posix::linux::foreign_items::EvalContextExt::emulate_foreign_item_by_name(this, link_name, abi, args, dest, ret),
-                    "macos" => return shims::posix::macos::foreign_items::EvalContextExt::emulate_foreign_item_by_name(this, link_name, abi, args, dest, ret),
-                    _ => unreachable!(),
-                }
+            // Outside of std, signal handlers are recorded so that timers can call them.
+            "signal" => {
+                let [signum, handler] =
+                    this.check_shim(abi, Abi::C { unwind: false }, link_name, args)?;
+                let old = this.signal(signum, handler)?;
+                this.write_scalar(old, dest)?;
+            }
+            "sigaction" => {
+                let [signum, act, oldact] =
+                    this.check_shim(abi, Abi::C { unwind: false }, link_name, args)?;
+                let result = this.sigaction(signum, act, oldact)?;
+                this.write_scalar(Scalar::from_i32(result), dest)?;
             }
+
+            // Platform-specific shims are handled by the other providers.
+            _ => return Ok(EmulateByNameResult::NotSupported),
         };
 
         Ok(EmulateByNameResult::NeedsJumping)
@@ -1,29 +1,40 @@
 use std::borrow::Cow;
+use std::cell::{Cell, RefCell};
 use std::collections::BTreeMap;
+use std::ffi::OsString;
 use std::fs::{
-    read_dir, remove_dir, remove_file, rename, DirBuilder, File, FileType, OpenOptions, ReadDir,
+    read_dir, remove_dir, remove_file, rename, DirBuilder, File, FileType, Metadata, OpenOptions,
+    ReadDir,
 };
 use std::io::{self, ErrorKind, Read, Seek, SeekFrom, Write};
-use std::path::Path;
-use std::time::SystemTime;
+use std::path::{Path, PathBuf};
+use std::rc::{Rc, Weak};
+use std::time::{Duration, SystemTime};
 
 use log::trace;
+use rand::rngs::StdRng;
+use rand::{Rng, RngCore, SeedableRng};
 
 use rustc_data_structures::fx::FxHashMap;
 use rustc_middle::ty::{self, layout::LayoutOf};
-use rustc_target::abi::{Align, Size};
+use rustc_target::abi::{Align, Endian, Size};
 
 use crate::*;
 use shims::os_str::os_str_to_bytes;
+use shims::posix::socket::{EvalContextExt as _, Socket, SocketState};
 use shims::time::system_time_to_duration;
+use thread::Time;
 
 #[derive(Debug)]
-struct FileHandle {
+pub(super) struct FileHandle {
     file: File,
     writable: bool,
+    /// The absolute path the file was opened with, for resolving the paths relative to it if it
+    /// is a directory. Renaming the file does not change it.
+    path: PathBuf,
 }
 
-trait FileDescriptor: std::fmt::Debug {
+pub(super) trait FileDescriptor: std::fmt::Debug {
     fn as_file_handle<'tcx>(&self) -> InterpResult<'tcx, &FileHandle>;
 
     fn read<'tcx>(
@@ -36,6 +47,27 @@ trait FileDescriptor: std::fmt::Debug {
         communicate_allowed: bool,
         bytes: &[u8],
     ) -> InterpResult<'tcx, io::Result<usize>>;
+
+    /// Reads into `bytes` at `offset`, without changing the file offset.
+    fn pread<'tcx>(
+        &self,
+        _communicate_allowed: bool,
+        _bytes: &mut [u8],
+        _offset: u64,
+    ) -> InterpResult<'tcx, io::Result<usize>> {
+        throw_unsup_format!("cannot read at an offset from {:?}", self);
+    }
+
+    /// Writes `bytes` at `offset`, without changing the file offset.
+    fn pwrite<'tcx>(
+        &self,
+        _communicate_allowed: bool,
+        _bytes: &[u8],
+        _offset: u64,
+    ) -> InterpResult<'tcx, io::Result<usize>> {
+        throw_unsup_format!("cannot write at an offset to {:?}", self);
+    }
+
     fn seek<'tcx>(
         &mut self,
         communicate_allowed: bool,
@@ -47,6 +79,49 @@ trait FileDescriptor: std::fmt::Debug {
     ) -> InterpResult<'tcx, io::Result<i32>>;
 
     fn dup(&mut self) -> io::Result<Box<dyn FileDescriptor>>;
+
+    /// Whether reading and writing would not block (and not fail with `EAGAIN`), as reported by
+    /// `epoll_wait`.
+    fn readiness(&self) -> (bool, bool) {
+        (true, true)
+    }
+
+    /// For descriptors that threads synchronize through, the clock that makes the writes happen
+    /// before the reads that observe them.
+    fn sync_clock(&self) -> Option<&RefCell<VClock>> {
+        None
+    }
+
+    /// Called after a successful write: everything the writing thread did so far happens before
+    /// the reads that observe the write. By default this releases into the `sync_clock`.
+    fn release_write(&self, data_race: &data_race::GlobalState, thread: ThreadId) {
+        if let Some(clock) = self.sync_clock() {
+            data_race.validate_lock_release_shared(&mut clock.borrow_mut(), thread);
+        }
+    }
+
+    /// Called after a successful read, and when `epoll_wait` reports the descriptor as ready:
+    /// the reading thread synchronizes with the writes that made the data available. By default
+    /// this acquires the `sync_clock`.
+    fn acquire_read(&self, data_race: &data_race::GlobalState, thread: ThreadId) {
+        if let Some(clock) = self.sync_clock() {
+            data_race.validate_lock_acquire(&clock.borrow(), thread);
+        }
+    }
+
+    fn as_epoll(&self) -> Option<&Epoll> {
+        None
+    }
+
+    fn as_socket(&self) -> Option<&Socket> {
+        None
+    }
+
+    /// For the standard streams, their file descriptor number. This is used to decide whether the
+    /// descriptor is reported as a terminal.
+    fn std_stream(&self) -> Option<i32> {
+        None
+    }
 }
 
 impl FileDescriptor for FileHandle {
@@ -72,6 +147,30 @@ impl FileDescriptor for FileHandle {
         Ok((&mut &self.file).write(bytes))
     }
 
+    fn pread<'tcx>(
+        &self,
+        communicate_allowed: bool,
+        bytes: &mut [u8],
+        offset: u64,
+    ) -> InterpResult<'tcx, io::Result<usize>> {
+        assert!(communicate_allowed, "isolation should have prevented even opening a file");
+        let len = u64::try_from(bytes.len()).unwrap();
+        Ok(read_at(&self.file, offset, len).map(|read| {
+            bytes[..read.len()].copy_from_slice(&read);
+            read.len()
+        }))
+    }
+
+    fn pwrite<'tcx>(
+        &self,
+        communicate_allowed: bool,
+        bytes: &[u8],
+        offset: u64,
+    ) -> InterpResult<'tcx, io::Result<usize>> {
+        assert!(communicate_allowed, "isolation should have prevented even opening a file");
+        Ok(write_at(&self.file, offset, bytes).map(|()| bytes.len()))
+    }
+
     fn seek<'tcx>(
         &mut self,
         communicate_allowed: bool,
@@ -109,7 +208,11 @@ impl FileDescriptor for FileHandle {
 
     fn dup(&mut self) -> io::Result<Box<dyn FileDescriptor>> {
         let duplicated = self.file.try_clone()?;
-        Ok(Box::new(FileHandle { file: duplicated, writable: self.writable }))
+        Ok(Box::new(FileHandle {
+            file: duplicated,
+            writable: self.writable,
+            path: self.path.clone(),
+        }))
     }
 }
 
@@ -156,6 +259,10 @@ impl FileDescriptor for io::Stdin {
     fn dup(&mut self) -> io::Result<Box<dyn FileDescriptor>> {
         Ok(Box::new(io::stdin()))
     }
+
+    fn std_stream(&self) -> Option<i32> {
+        Some(0)
+    }
 }
 
 impl FileDescriptor for io::Stdout {
@@ -206,6 +313,10 @@ impl FileDescriptor for io::Stdout {
     fn dup(&mut self) -> io::Result<Box<dyn FileDescriptor>> {
         Ok(Box::new(io::stdout()))
     }
+
+    fn std_stream(&self) -> Option<i32> {
+        Some(1)
+    }
 }
 
 impl FileDescriptor for io::Stderr {
@@ -249,29 +360,343 @@ impl FileDescriptor for io::Stderr {
     fn dup(&mut self) -> io::Result<Box<dyn FileDescriptor>> {
         Ok(Box::new(io::stderr()))
     }
+
+    fn std_stream(&self) -> Option<i32> {
+        Some(2)
+    }
+}
+
+/// `/dev/urandom` (and `/dev/random`) when the program may not use the host's entropy. The bytes
+/// are taken from an RNG that is seeded from the machine's RNG, so they are determined by the seed.
+/// Duplicated descriptors share the RNG, like they share the position of a file.
+struct DevRandom {
+    rng: Rc<RefCell<StdRng>>,
+}
+
+impl std::fmt::Debug for DevRandom {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str("DevRandom")
+    }
+}
+
+impl FileDescriptor for DevRandom {
+    fn as_file_handle<'tcx>(&self) -> InterpResult<'tcx, &FileHandle> {
+        throw_unsup_format!("/dev/urandom cannot be used as FileHandle");
+    }
+
+    fn read<'tcx>(
+        &mut self,
+        _communicate_allowed: bool,
+        bytes: &mut [u8],
+    ) -> InterpResult<'tcx, io::Result<usize>> {
+        self.rng.borrow_mut().fill_bytes(bytes);
+        Ok(Ok(bytes.len()))
+    }
+
+    fn write<'tcx>(
+        &self,
+        _communicate_allowed: bool,
+        bytes: &[u8],
+    ) -> InterpResult<'tcx, io::Result<usize>> {
+        // Writing adds to the entropy pool of the host; here the data is simply ignored.
+        Ok(Ok(bytes.len()))
+    }
+
+    fn seek<'tcx>(
+        &mut self,
+        _communicate_allowed: bool,
+        _offset: SeekFrom,
+    ) -> InterpResult<'tcx, io::Result<u64>> {
+        // Seeking on a character device has no effect.
+        Ok(Ok(0))
+    }
+
+    fn close<'tcx>(
+        self: Box<Self>,
+        _communicate_allowed: bool,
+    ) -> InterpResult<'tcx, io::Result<i32>> {
+        Ok(Ok(0))
+    }
+
+    fn dup(&mut self) -> io::Result<Box<dyn FileDescriptor>> {
+        Ok(Box::new(DevRandom { rng: Rc::clone(&self.rng) }))
+    }
+}
+
+/// An eventfd, created by `eventfd`: a counter that threads use to signal each other, e.g. in the
+/// wakers of async runtimes. Duplicated descriptors share the counter.
+#[derive(Debug)]
+struct EventFd {
+    counter: Rc<Cell<u64>>,
+    /// With `EFD_SEMAPHORE`, a read decrements the counter by one instead of resetting it.
+    semaphore: bool,
+    /// The byte order of the target, in which the counter is read and written.
+    endian: Endian,
+    clock: Rc<RefCell<VClock>>,
+}
+
+impl EventFd {
+    /// The counter can hold values up to `u64::MAX - 1`.
+    const MAX: u64 = u64::MAX - 1;
+}
+
+impl FileDescriptor for EventFd {
+    fn as_file_handle<'tcx>(&self) -> InterpResult<'tcx, &FileHandle> {
+        throw_unsup_format!("an eventfd cannot be used as FileHandle");
+    }
+
+    fn read<'tcx>(
+        &mut self,
+        _communicate_allowed: bool,
+        bytes: &mut [u8],
+    ) -> InterpResult<'tcx, io::Result<usize>> {
+        if bytes.len() < 8 {
+            return Ok(Err(ErrorKind::InvalidInput.into()));
+        }
+        let counter = self.counter.get();
+        if counter == 0 {
+            return Ok(Err(ErrorKind::WouldBlock.into()));
+        }
+        let value = if self.semaphore { 1 } else { counter };
+        self.counter.set(counter - value);
+        let value = match self.endian {
+            Endian::Little => value.to_le_bytes(),
+            Endian::Big => value.to_be_bytes(),
+        };
+        bytes[..8].copy_from_slice(&value);
+        Ok(Ok(8))
+    }
+
+    fn write<'tcx>(
+        &self,
+        _communicate_allowed: bool,
+        bytes: &[u8],
+    ) -> InterpResult<'tcx, io::Result<usize>> {
+        let value = match bytes.get(..8) {
+            Some(value) => value.try_into().unwrap(),
+            None => return Ok(Err(ErrorKind::InvalidInput.into())),
+        };
+        let value = match self.endian {
+            Endian::Little => u64::from_le_bytes(value),
+            Endian::Big => u64::from_be_bytes(value),
+        };
+        if value == u64::MAX {
+            return Ok(Err(ErrorKind::InvalidInput.into()));
+        }
+        match self.counter.get().checked_add(value) {
+            Some(counter) if counter <= EventFd::MAX => {
+                self.counter.set(counter);
+                Ok(Ok(8))
+            }
+            _ => Ok(Err(ErrorKind::WouldBlock.into())),
+        }
+    }
+
+    fn seek<'tcx>(
+        &mut self,
+        _communicate_allowed: bool,
+        _offset: SeekFrom,
+    ) -> InterpResult<'tcx, io::Result<u64>> {
+        throw_unsup_format!("cannot seek on an eventfd");
+    }
+
+    fn close<'tcx>(
+        self: Box<Self>,
+        _communicate_allowed: bool,
+    ) -> InterpResult<'tcx, io::Result<i32>> {
+        Ok(Ok(0))
+    }
+
+    fn dup(&mut self) -> io::Result<Box<dyn FileDescriptor>> {
+        Ok(Box::new(EventFd {
+            counter: Rc::clone(&self.counter),
+            semaphore: self.semaphore,
+            endian: self.endian,
+            clock: Rc::clone(&self.clock),
+        }))
+    }
+
+    fn readiness(&self) -> (bool, bool) {
+        let counter = self.counter.get();
+        (counter > 0, counter < EventFd::MAX)
+    }
+
+    fn sync_clock(&self) -> Option<&RefCell<VClock>> {
+        Some(&*self.clock)
+    }
+}
+
+/// An epoll instance, created by `epoll_create1`. Duplicated descriptors share the interest list.
+#[derive(Debug)]
+struct Epoll {
+    /// The registered file descriptors.
+    interest: Rc<RefCell<BTreeMap<i32, EpollInterest>>>,
+}
+
+/// What a file descriptor is registered for in an epoll instance.
+#[derive(Debug, Clone, Copy)]
+struct EpollInterest {
+    /// The events that are reported. With `EPOLLONESHOT`, this is cleared when they are reported.
+    events: u32,
+    /// The data that is reported along with the events.
+    data: u64,
+}
+
+impl FileDescriptor for Epoll {
+    fn as_file_handle<'tcx>(&self) -> InterpResult<'tcx, &FileHandle> {
+        throw_unsup_format!("an epoll instance cannot be used as FileHandle");
+    }
+
+    fn read<'tcx>(
+        &mut self,
+        _communicate_allowed: bool,
+        _bytes: &mut [u8],
+    ) -> InterpResult<'tcx, io::Result<usize>> {
+        Ok(Err(ErrorKind::InvalidInput.into()))
+    }
+
+    fn write<'tcx>(
+        &self,
+        _communicate_allowed: bool,
+        _bytes: &[u8],
+    ) -> InterpResult<'tcx, io::Result<usize>> {
+        Ok(Err(ErrorKind::InvalidInput.into()))
+    }
+
+    fn seek<'tcx>(
+        &mut self,
+        _communicate_allowed: bool,
+        _offset: SeekFrom,
+    ) -> InterpResult<'tcx, io::Result<u64>> {
+        throw_unsup_format!("cannot seek on an epoll instance");
+    }
+
+    fn close<'tcx>(
+        self: Box<Self>,
+        _communicate_allowed: bool,
+    ) -> InterpResult<'tcx, io::Result<i32>> {
+        Ok(Ok(0))
+    }
+
+    fn dup(&mut self) -> io::Result<Box<dyn FileDescriptor>> {
+        Ok(Box::new(Epoll { interest: Rc::clone(&self.interest) }))
+    }
+
+    fn readiness(&self) -> (bool, bool) {
+        // Nested epoll instances are not supported, so they are never ready.
+        (false, false)
+    }
+
+    fn as_epoll(&self) -> Option<&Epoll> {
+        Some(self)
+    }
+}
+
+/// A thread that is blocked in `epoll_wait` until one of the registered file descriptors is ready.
+#[derive(Debug)]
+struct EpollWaiter<'tcx> {
+    thread: ThreadId,
+    epfd: i32,
+    events: Pointer<Option<Tag>>,
+    maxevents: u32,
+    /// Where the number of reported events is written when the thread is woken up.
+    dest: PlaceTy<'tcx, Tag>,
+}
+
+/// An operation that a thread is blocked in until a file descriptor becomes ready: writable for
+/// `Write`, readable for the others.
+#[derive(Debug)]
+pub(super) enum BlockedOp {
+    /// A `read` or `readv` into the buffers `bufs`, given as pointers and lengths.
+    Read {
+        bufs: Vec<(Pointer<Option<Tag>>, u64)>,
+    },
+    /// A `write` or `writev` of `bytes`, which were gathered from the buffers when it was called.
+    Write {
+        bytes: Vec<u8>,
+    },
+    Accept {
+        addr: Pointer<Option<Tag>>,
+        addrlen: Pointer<Option<Tag>>,
+        flags: i32,
+    },
+}
+
+/// A thread that is blocked in `read`, `write` or `accept` because the file descriptor is not
+/// ready for it yet, and the descriptor is in blocking mode.
+#[derive(Debug)]
+struct BlockedThread<'tcx> {
+    thread: ThreadId,
+    fd: i32,
+    op: BlockedOp,
+    /// Where the result of the operation is written when it completes.
+    dest: PlaceTy<'tcx, Tag>,
+}
+
+/// An entry of the file descriptor table.
+#[derive(Debug)]
+pub(super) struct OpenFd {
+    pub(super) file_descriptor: Box<dyn FileDescriptor>,
+    /// The file status flags of the open file description: its access mode, `O_APPEND` and
+    /// `O_NONBLOCK`. Duplicated descriptors share them, like they share the offset of a file.
+    pub(super) status_flags: Rc<Cell<i32>>,
+    /// The file descriptor flags, which belong to this descriptor only: `FD_CLOEXEC` or nothing.
+    /// Miri cannot execute other programs, so they are only reported by `fcntl(F_GETFD)`.
+    pub(super) fd_flags: i32,
+}
+
+impl OpenFd {
+    fn new(file_descriptor: Box<dyn FileDescriptor>, status_flags: i32, fd_flags: i32) -> Self {
+        OpenFd { file_descriptor, status_flags: Rc::new(Cell::new(status_flags)), fd_flags }
+    }
 }
 
 #[derive(Debug)]
-pub struct FileHandler {
-    handles: BTreeMap<i32, Box<dyn FileDescriptor>>,
+pub struct FileHandler<'tcx> {
+    pub(super) handles: BTreeMap<i32, OpenFd>,
+    epoll_waiters: Vec<EpollWaiter<'tcx>>,
+    blocked_threads: Vec<BlockedThread<'tcx>>,
+    /// The sockets that are bound to an address, by their address. Closing the last descriptor
+    /// of a socket frees its address.
+    pub(super) socket_addresses: BTreeMap<Vec<u8>, Weak<RefCell<SocketState>>>,
 }
 
-impl<'tcx> Default for FileHandler {
+impl<'tcx> Default for FileHandler<'tcx> {
     fn default() -> Self {
-        let mut handles: BTreeMap<_, Box<dyn FileDescriptor>> = BTreeMap::new();
-        handles.insert(0i32, Box::new(io::stdin()));
-        handles.insert(1i32, Box::new(io::stdout()));
-        handles.insert(2i32, Box::new(io::stderr()));
-        FileHandler { handles }
+        // The access modes are the same on all supported targets, see `open`.
+        let (o_rdonly, o_wronly) = (0, 1);
+        let mut handles = BTreeMap::new();
+        handles.insert(0i32, OpenFd::new(Box::new(io::stdin()), o_rdonly, 0));
+        handles.insert(1i32, OpenFd::new(Box::new(io::stdout()), o_wronly, 0));
+        handles.insert(2i32, OpenFd::new(Box::new(io::stderr()), o_wronly, 0));
+        FileHandler {
+            handles,
+            epoll_waiters: Vec::new(),
+            blocked_threads: Vec::new(),
+            socket_addresses: BTreeMap::new(),
+        }
     }
 }
 
-impl<'tcx> FileHandler {
-    fn insert_fd(&mut self, file_handle: Box<dyn FileDescriptor>) -> i32 {
-        self.insert_fd_with_min_fd(file_handle, 0)
+impl<'tcx> FileHandler<'tcx> {
+    /// Lists the open file descriptors, together with a description of what they refer to.
+    pub fn describe(&self) -> Vec<(i32, String)> {
+        self.handles
+            .iter()
+            .map(|(&fd, open_fd)| (fd, format!("{:?}", open_fd.file_descriptor)))
+            .collect()
+    }
+
+    pub(super) fn insert_fd(
+        &mut self,
+        file_handle: Box<dyn FileDescriptor>,
+        status_flags: i32,
+        fd_flags: i32,
+    ) -> i32 {
+        self.insert_fd_with_min_fd(OpenFd::new(file_handle, status_flags, fd_flags), 0)
     }
 
-    fn insert_fd_with_min_fd(&mut self, file_handle: Box<dyn FileDescriptor>, min_fd: i32) -> i32 {
+    fn insert_fd_with_min_fd(&mut self, open_fd: OpenFd, min_fd: i32) -> i32 {
         // Find the lowest unused FD, starting from min_fd. If the first such unused FD is in
         // between used FDs, the find_map combinator will return it. If the first such unused FD
         // is after all other used FDs, the find_map combinator will return None, and we will use
@@ -296,56 +721,238 @@ impl<'tcx> FileHandler {
                 .unwrap_or(min_fd)
         });
 
-        self.handles.try_insert(new_fd, file_handle).unwrap();
+        self.handles.try_insert(new_fd, open_fd).unwrap();
         new_fd
     }
+
+    /// Blocks `thread` in `op` on `fd` until `fd` becomes ready for it. The caller blocks the
+    /// thread.
+    pub(super) fn block_until_ready(
+        &mut self,
+        thread: ThreadId,
+        fd: i32,
+        op: BlockedOp,
+        dest: &PlaceTy<'tcx, Tag>,
+    ) {
+        self.blocked_threads.push(BlockedThread { thread, fd, op, dest: *dest });
+    }
+
+    /// Removes `fd` from the file descriptor table and from the interest lists of all epoll
+    /// instances, and returns what it referred to.
+    fn remove_fd(&mut self, fd: i32) -> Option<OpenFd> {
+        let open_fd = self.handles.remove(&fd)?;
+        for OpenFd { file_descriptor, .. } in self.handles.values() {
+            if let Some(epoll) = file_descriptor.as_epoll() {
+                epoll.interest.borrow_mut().remove(&fd);
+            }
+        }
+        Some(open_fd)
+    }
 }
 
 impl<'mir, 'tcx: 'mir> EvalContextExtPrivate<'mir, 'tcx> for crate::MiriEvalContext<'mir, 'tcx> {}
-trait EvalContextExtPrivate<'mir, 'tcx: 'mir>: crate::MiriEvalContextExt<'mir, 'tcx> {
-    fn macos_stat_write_buf(
+pub(super) trait EvalContextExtPrivate<'mir, 'tcx: 'mir>:
+    crate::MiriEvalContextExt<'mir, 'tcx>
+{
+    /// Writes `metadata` to the `struct stat` that `buf_op` points to.
+    fn stat_write_buf(
         &mut self,
         metadata: FileMetadata,
         buf_op: &OpTy<'tcx, Tag>,
     ) -> InterpResult<'tcx, i32> {
         let this = self.eval_context_mut();
 
-        let mode: u16 = metadata.mode.to_u16()?;
+        // `mode_t` is a `u16` on macOS.
+        let file_type = if this.tcx.sess.target.os == "macos" {
+            metadata.mode.to_u16()?.into()
+        } else {
+            metadata.mode.to_u32()?
+        };
 
         let (access_sec, access_nsec) = metadata.accessed.unwrap_or((0, 0));
-        let (created_sec, created_nsec) = metadata.created.unwrap_or((0, 0));
         let (modified_sec, modified_nsec) = metadata.modified.unwrap_or((0, 0));
+        // Without a Unix host, we only know the file type and write a zero for the other fields.
+        let unix = metadata.unix.unwrap_or(UnixMetadata::ZERO);
+        let mode = file_type | unix.permissions;
+        let (changed_sec, changed_nsec) = unix.changed;
 
         let buf = this.deref_operand(buf_op)?;
-        this.write_int_fields_named(
+        // The fields are only 32 bits wide on some targets, which is too small for the values of
+        // some files. `stat` fails with `EOVERFLOW` then, before writing anything.
+        let fits = this.write_int_fields_named_checked(
             &[
-                ("st_dev", 0),
+                ("st_dev", unix.dev.into()),
                 ("st_mode", mode.into()),
-                ("st_nlink", 0),
-                ("st_ino", 0),
-                ("st_uid", 0),
-                ("st_gid", 0),
-                ("st_rdev", 0),
+                ("st_nlink", unix.nlink.into()),
+                ("st_ino", unix.ino.into()),
+                ("st_uid", unix.uid.into()),
+                ("st_gid", unix.gid.into()),
+                ("st_rdev", unix.rdev.into()),
                 ("st_atime", access_sec.into()),
                 ("st_atime_nsec", access_nsec.into()),
                 ("st_mtime", modified_sec.into()),
                 ("st_mtime_nsec", modified_nsec.into()),
-                ("st_ctime", 0),
-                ("st_ctime_nsec", 0),
-                ("st_birthtime", created_sec.into()),
-                ("st_birthtime_nsec", created_nsec.into()),
+                ("st_ctime", changed_sec.into()),
+                ("st_ctime_nsec", changed_nsec.into()),
                 ("st_size", metadata.size.into()),
-                ("st_blocks", 0),
-                ("st_blksize", 0),
-                ("st_flags", 0),
-                ("st_gen", 0),
+                ("st_blocks", unix.blocks.into()),
+                ("st_blksize", unix.blksize.into()),
             ],
             &buf,
         )?;
+        if !fits {
+            return this.fail_with_errno("EOVERFLOW");
+        }
+        // Only macOS has the creation time and the file flags.
+        if this.tcx.sess.target.os == "macos" {
+            let (created_sec, created_nsec) = metadata.created.unwrap_or((0, 0));
+            this.write_int_fields_named(
+                &[
+                    ("st_birthtime", created_sec.into()),
+                    ("st_birthtime_nsec", created_nsec.into()),
+                    ("st_flags", 0),
+                    ("st_gen", 0),
+                ],
+                &buf,
+            )?;
+        }
 
         Ok(0)
     }
 
+    /// Collects the events of the file descriptors registered with the epoll instance `epfd` that
+    /// are ready, at most `maxevents` of them, on behalf of `thread`.
+    fn epoll_ready_events(
+        &mut self,
+        epfd: i32,
+        maxevents: u32,
+        thread: ThreadId,
+    ) -> InterpResult<'tcx, Vec<(u32, u64)>> {
+        let this = self.eval_context_mut();
+        // The event flags are `c_int`, but `EPOLLET` does not fit in a positive one.
+        let epollin = this.eval_libc_i32("EPOLLIN")? as u32;
+        let epollout = this.eval_libc_i32("EPOLLOUT")? as u32;
+        let epolloneshot = this.eval_libc_i32("EPOLLONESHOT")? as u32;
+
+        let handles = &this.machine.file_handler.handles;
+        let interest = match handles.get(&epfd).and_then(|fd| fd.file_descriptor.as_epoll()) {
+            Some(epoll) => Rc::clone(&epoll.interest),
+            None => return Ok(vec![]),
+        };
+        let mut ready = vec![];
+        for (fd, interest) in interest.borrow_mut().iter_mut() {
+            if ready.len() == maxevents as usize {
+                break;
+            }
+            let file_descriptor = &handles[fd].file_descriptor;
+            let (readable, writable) = file_descriptor.readiness();
+            let mut events = 0;
+            if readable {
+                events |= epollin;
+            }
+            if writable {
+                events |= epollout;
+            }
+            // Edge-triggered registrations are reported like level-triggered ones, which is
+            // allowed since a program using them has to cope with spurious wakeups anyway.
+            events &= interest.events;
+            if events == 0 {
+                continue;
+            }
+            if interest.events & epolloneshot != 0 {
+                interest.events = 0;
+            }
+            // Finding the file descriptor readable synchronizes with the write that made it
+            // readable. Being writable is not caused by another thread in a way that the program
+            // could rely on, so it does not synchronize.
+            if events & epollin != 0 {
+                if let Some(data_race) = &this.machine.data_race {
+                    file_descriptor.acquire_read(data_race, thread);
+                }
+            }
+            ready.push((events, interest.data));
+        }
+        Ok(ready)
+    }
+
+    /// Writes the `epoll_event`s that `epoll_wait` reports to the array at `events`.
+    fn epoll_write_events(
+        &mut self,
+        events: Pointer<Option<Tag>>,
+        ready: &[(u32, u64)],
+    ) -> InterpResult<'tcx> {
+        let this = self.eval_context_mut();
+        let event_layout = this.libc_ty_layout("epoll_event")?;
+        for (i, &(events_mask, data)) in ready.iter().enumerate() {
+            let ptr = events.offset(event_layout.size * u64::try_from(i).unwrap(), this)?;
+            this.write_int_fields_named(
+                &[("events", events_mask.into()), ("u64", data.into())],
+                &MPlaceTy::from_aligned_ptr(ptr, event_layout),
+            )?;
+        }
+        Ok(())
+    }
+
+    /// Wakes up the threads blocked in `epoll_wait` that now have events to report, and finishes
+    /// the reads and writes of the threads whose file descriptor became ready for them. Called
+    /// after anything that can make a file descriptor ready.
+    fn notify_readiness(&mut self) -> InterpResult<'tcx> {
+        let this = self.eval_context_mut();
+
+        // Finishing an operation can make other file descriptors ready, e.g. a read from a full
+        // eventfd lets a blocked write through, so the blocked threads are looked at again after
+        // each one. Every thread is retried at most once, in case its operation blocks again.
+        let mut retried = vec![];
+        loop {
+            let file_handler = &this.machine.file_handler;
+            let ready = file_handler.blocked_threads.iter().position(|blocked| {
+                let (readable, writable) = match file_handler.handles.get(&blocked.fd) {
+                    Some(open_fd) => open_fd.file_descriptor.readiness(),
+                    None => return false,
+                };
+                let ready =
+                    if let BlockedOp::Write { .. } = blocked.op { writable } else { readable };
+                ready && !retried.contains(&blocked.thread)
+            });
+            let blocked = match ready {
+                Some(index) => this.machine.file_handler.blocked_threads.remove(index),
+                None => break,
+            };
+            retried.push(blocked.thread);
+            // The operation is redone by the thread that is blocked in it, and blocks again only
+            // if the file descriptor is still not ready enough for it.
+            this.unblock_thread(blocked.thread);
+            let old_thread = this.set_active_thread(blocked.thread);
+            let (fd, dest) = (blocked.fd, &blocked.dest);
+            match blocked.op {
+                BlockedOp::Read { bufs } => this.read_to_buffers(fd, bufs, dest)?,
+                BlockedOp::Write { bytes } => this.write_to_fd(fd, bytes, dest)?,
+                BlockedOp::Accept { addr, addrlen, flags } =>
+                    this.accept_connection(fd, addr, addrlen, flags, dest)?,
+            }
+            this.set_active_thread(old_thread);
+        }
+
+        let waiters = std::mem::take(&mut this.machine.file_handler.epoll_waiters);
+        let mut still_waiting = vec![];
+        for waiter in waiters {
+            let ready = this.epoll_ready_events(waiter.epfd, waiter.maxevents, waiter.thread)?;
+            if ready.is_empty() {
+                still_waiting.push(waiter);
+                continue;
+            }
+            // The results are written by the thread that called `epoll_wait`.
+            let old_thread = this.set_active_thread(waiter.thread);
+            this.epoll_write_events(waiter.events, &ready)?;
+            this.write_scalar(Scalar::from_i32(ready.len().try_into().unwrap()), &waiter.dest)?;
+            this.set_active_thread(old_thread);
+            this.unblock_thread(waiter.thread);
+            this.unregister_timeout_callback_if_exists(waiter.thread);
+        }
+        this.machine.file_handler.epoll_waiters = still_waiting;
+        Ok(())
+    }
+
     /// Function used when a handle is not found inside `FileHandler`. It returns `Ok(-1)`and sets
     /// the last OS error to `libc::EBADF` (invalid file descriptor). This function uses
     /// `T: From<i32>` instead of `i32` directly because some fs functions return different integer
@@ -357,17 +964,830 @@ trait EvalContextExtPrivate<'mir, 'tcx: 'mir>: crate::MiriEvalContextExt<'mir, '
         Ok((-1).into())
     }
 
-    fn file_type_to_d_type(
+    /// Sets the last error to `errno` and returns -1.
+    fn fail_with_errno<T: From<i32>>(&mut self, errno: &str) -> InterpResult<'tcx, T> {
+        let this = self.eval_context_mut();
+        let errno = this.eval_libc(errno)?;
+        this.set_last_error(errno)?;
+        Ok((-1).into())
+    }
+
+    /// Resolves `path` relative to the directory that `dirfd` refers to, like the `*at` functions
+    /// do: absolute paths are used as they are, and `AT_FDCWD` stands for the current working
+    /// directory. Sets the last error and returns `None` if `path` is empty or `dirfd` is not an
+    /// open directory.
+    fn resolve_path_at(
         &mut self,
-        file_type: std::io::Result<FileType>,
-    ) -> InterpResult<'tcx, i32> {
+        dirfd: i32,
+        path: PathBuf,
+    ) -> InterpResult<'tcx, Option<PathBuf>> {
         let this = self.eval_context_mut();
-        match file_type {
-            Ok(file_type) => {
-                if file_type.is_dir() {
-                    Ok(this.eval_libc("DT_DIR")?.to_u8()?.into())
-                } else if file_type.is_file() {
-                    Ok(this.eval_libc("DT_REG")?.to_u8()?.into())
+
+        if path.as_os_str().is_empty() {
+            let enoent = this.eval_libc("ENOENT")?;
+            this.set_last_error(enoent)?;
+            return Ok(None);
+        }
+        if path.is_absolute() || dirfd == this.eval_libc_i32("AT_FDCWD")? {
+            return Ok(Some(path));
+        }
+        let dir = match this.machine.file_handler.handles.get(&dirfd) {
+            Some(OpenFd { file_descriptor, .. }) =>
+                match file_descriptor.as_file_handle() {
+                    Ok(FileHandle { file, path: dir, .. })
+                        if file.metadata().map_or(false, |metadata| metadata.is_dir()) =>
+                        Some(dir.clone()),
+                    _ => None,
+                },
+            None => return this.handle_not_found::<i32>().map(|_| None),
+        };
+        match dir {
+            Some(dir) => Ok(Some(dir.join(path))),
+            None => {
+                let enotdir = this.eval_libc("ENOTDIR")?;
+                this.set_last_error(enotdir)?;
+                Ok(None)
+            }
+        }
+    }
+
+    /// Implements `open` and `openat`, whose arguments are `args`. The path is the argument at
+    /// `path_index`, followed by the flags and the optional mode.
+    fn open_at(
+        &mut self,
+        shim: &str,
+        dirfd: i32,
+        args: &[OpTy<'tcx, Tag>],
+        path_index: usize,
+    ) -> InterpResult<'tcx, i32> {
+        let this = self.eval_context_mut();
+
+        let path = this.read_pointer(&args[path_index])?;
+        let flag = this.read_scalar(&args[path_index + 1])?.to_i32()?;
+
+        let mut options = OpenOptions::new();
+
+        let o_rdonly = this.eval_libc_i32("O_RDONLY")?;
+        let o_wronly = this.eval_libc_i32("O_WRONLY")?;
+        let o_rdwr = this.eval_libc_i32("O_RDWR")?;
+        // The first two bits of the flag correspond to the access mode in linux, macOS and
+        // windows. We need to check that in fact the access mode flags for the current target
+        // only use these two bits, otherwise we are in an unsupported target and should error.
+        if (o_rdonly | o_wronly | o_rdwr) & !0b11 != 0 {
+            throw_unsup_format!("access mode flags on this target are unsupported");
+        }
+        let mut writable = true;
+
+        // Now we check the access mode
+        let access_mode = flag & 0b11;
+
+        if access_mode == o_rdonly {
+            writable = false;
+            options.read(true);
+        } else if access_mode == o_wronly {
+            options.write(true);
+        } else if access_mode == o_rdwr {
+            options.read(true).write(true);
+        } else {
+            throw_unsup_format!("unsupported access mode {:#x}", access_mode);
+        }
+        // We need to check that there aren't unsupported options in `flag`. For this we try to
+        // reproduce the content of `flag` in the `mirror` variable using only the supported
+        // options.
+        let mut mirror = access_mode;
+
+        let o_append = this.eval_libc_i32("O_APPEND")?;
+        if flag & o_append != 0 {
+            options.append(true);
+            mirror |= o_append;
+        }
+        let o_trunc = this.eval_libc_i32("O_TRUNC")?;
+        if flag & o_trunc != 0 {
+            options.truncate(true);
+            mirror |= o_trunc;
+        }
+        let o_creat = this.eval_libc_i32("O_CREAT")?;
+        if flag & o_creat != 0 {
+            // Get the mode.  On macOS, the argument type `mode_t` is actually `u16`, but
+            // C integer promotion rules mean that on the ABI level, it gets passed as `u32`
+            // (see https://github.com/rust-lang/rust/issues/71915).
+            let mode = if let Some(arg) = args.get(path_index + 2) {
+                this.read_scalar(arg)?.to_u32()?
+            } else {
+                throw_ub_format!(
+                    "incorrect number of arguments for `{}` with `O_CREAT`: got {}, expected at least {}",
+                    shim,
+                    args.len(),
+                    path_index + 3
+                );
+            };
+
+            if mode != 0o666 {
+                throw_unsup_format!("non-default mode 0o{:o} is not supported", mode);
+            }
+
+            mirror |= o_creat;
+
+            let o_excl = this.eval_libc_i32("O_EXCL")?;
+            if flag & o_excl != 0 {
+                mirror |= o_excl;
+                options.create_new(true);
+            } else {
+                options.create(true);
+            }
+        }
+        // Non-blocking mode makes no difference for files. It is recorded in the status flags, so
+        // that `fcntl(F_GETFL)` reports it.
+        let o_nonblock = this.eval_libc_i32("O_NONBLOCK")?;
+        if flag & o_nonblock != 0 {
+            mirror |= o_nonblock;
+        }
+        // Whether the path is a directory is checked after opening it.
+        let o_directory = this.eval_libc_i32("O_DIRECTORY")?;
+        if flag & o_directory != 0 {
+            mirror |= o_directory;
+        }
+        let o_cloexec = this.eval_libc_i32("O_CLOEXEC")?;
+        let mut fd_flags = 0;
+        if flag & o_cloexec != 0 {
+            fd_flags = this.eval_libc_i32("FD_CLOEXEC")?;
+            mirror |= o_cloexec;
+        }
+        // If `flag` is not equal to `mirror`, there is an unsupported option enabled in `flag`,
+        // then we throw an error.
+        if flag != mirror {
+            throw_unsup_format!("unsupported flags {:#x}", flag & !mirror);
+        }
+        let status_flags = flag & (0b11 | o_append | o_nonblock);
+
+        let path = this.read_path_from_c_str(path)?.into_owned();
+        let path = match this.resolve_path_at(dirfd, path)? {
+            Some(path) => path,
+            None => return Ok(-1),
+        };
+
+        // Unless the program may use the host's entropy, the entropy devices are emulated (even
+        // with isolation enabled) so that what they produce is determined by the seed.
+        if !this.machine.host_entropy
+            && (path == Path::new("/dev/urandom") || path == Path::new("/dev/random"))
+        {
+            let rng = StdRng::seed_from_u64(this.machine.rng.get_mut().gen());
+            let dev_random = DevRandom { rng: Rc::new(RefCell::new(rng)) };
+            let fh = &mut this.machine.file_handler;
+            let fd = fh.insert_fd(Box::new(dev_random), status_flags, fd_flags);
+            return Ok(fd);
+        }
+
+        // Reject if isolation is enabled.
+        if let IsolatedOp::Reject(reject_with) = this.machine.isolated_op {
+            this.reject_in_isolation(&format!("`{}`", shim), reject_with)?;
+            this.set_last_error_from_io_error(ErrorKind::PermissionDenied)?;
+            return Ok(-1);
+        }
+
+        let file = match options.open(&path) {
+            Ok(file) => file,
+            Err(e) => {
+                this.set_last_error_from_io_error(e.kind())?;
+                return Ok(-1);
+            }
+        };
+        if flag & o_directory != 0 && !file.metadata().map_or(false, |metadata| metadata.is_dir()) {
+            let enotdir = this.eval_libc("ENOTDIR")?;
+            this.set_last_error(enotdir)?;
+            return Ok(-1);
+        }
+        // Relative paths are relative to the current working directory, which can change later.
+        let path = match std::env::current_dir() {
+            Ok(cwd) => cwd.join(path),
+            Err(_) => path,
+        };
+
+        let fh = &mut this.machine.file_handler;
+        Ok(fh.insert_fd(Box::new(FileHandle { file, writable, path }), status_flags, fd_flags))
+    }
+
+    /// Implements `unlink`, `rmdir`, and `unlinkat`. `dir` says whether the path has to be a
+    /// directory or must not be one.
+    fn unlink_at(
+        &mut self,
+        shim: &str,
+        dirfd: i32,
+        path_op: &OpTy<'tcx, Tag>,
+        dir: bool,
+    ) -> InterpResult<'tcx, i32> {
+        let this = self.eval_context_mut();
+
+        let path = this.read_path_from_c_str(this.read_pointer(path_op)?)?.into_owned();
+        let path = match this.resolve_path_at(dirfd, path)? {
+            Some(path) => path,
+            None => return Ok(-1),
+        };
+
+        // Reject if isolation is enabled.
+        if let IsolatedOp::Reject(reject_with) = this.machine.isolated_op {
+            this.reject_in_isolation(&format!("`{}`", shim), reject_with)?;
+            this.set_last_error_from_io_error(ErrorKind::PermissionDenied)?;
+            return Ok(-1);
+        }
+
+        let result = if dir { remove_dir(path) } else { remove_file(path) };
+        this.try_unwrap_io_result(result.map(|_| 0i32))
+    }
+
+    /// Implements `symlink` and `symlinkat`. The target is stored as it is, so it is not resolved.
+    fn symlink_at(
+        &mut self,
+        shim: &str,
+        target_op: &OpTy<'tcx, Tag>,
+        newdirfd: i32,
+        linkpath_op: &OpTy<'tcx, Tag>,
+    ) -> InterpResult<'tcx, i32> {
+        #[cfg(unix)]
+        fn create_link(src: &Path, dst: &Path) -> std::io::Result<()> {
+            std::os::unix::fs::symlink(src, dst)
+        }
+
+        #[cfg(windows)]
+        fn create_link(src: &Path, dst: &Path) -> std::io::Result<()> {
+            use std::os::windows::fs;
+            if src.is_dir() { fs::symlink_dir(src, dst) } else { fs::symlink_file(src, dst) }
+        }
+
+        let this = self.eval_context_mut();
+        let target = this.read_path_from_c_str(this.read_pointer(target_op)?)?.into_owned();
+        let linkpath = this.read_path_from_c_str(this.read_pointer(linkpath_op)?)?.into_owned();
+        let linkpath = match this.resolve_path_at(newdirfd, linkpath)? {
+            Some(linkpath) => linkpath,
+            None => return Ok(-1),
+        };
+
+        // Reject if isolation is enabled.
+        if let IsolatedOp::Reject(reject_with) = this.machine.isolated_op {
+            this.reject_in_isolation(&format!("`{}`", shim), reject_with)?;
+            this.set_last_error_from_io_error(ErrorKind::PermissionDenied)?;
+            return Ok(-1);
+        }
+
+        let result = create_link(&target, &linkpath).map(|_| 0);
+        this.try_unwrap_io_result(result)
+    }
+
+    /// Duplicates `fd`, with the file descriptor flags `fd_flags`. The duplicate refers to the
+    /// same open file description, so it shares the offset and the status flags. Returns `None`
+    /// and sets the last error if `fd` cannot be duplicated.
+    fn duplicate_fd(&mut self, fd: i32, fd_flags: i32) -> InterpResult<'tcx, Option<OpenFd>> {
+        let this = self.eval_context_mut();
+
+        let (dup_result, status_flags) = match this.machine.file_handler.handles.get_mut(&fd) {
+            Some(OpenFd { file_descriptor, status_flags, .. }) =>
+                (file_descriptor.dup(), Rc::clone(status_flags)),
+            None => return this.handle_not_found::<i32>().map(|_| None),
+        };
+        match dup_result {
+            Ok(file_descriptor) => Ok(Some(OpenFd { file_descriptor, status_flags, fd_flags })),
+            Err(e) => {
+                this.set_last_error_from_io_error(e.kind())?;
+                Ok(None)
+            }
+        }
+    }
+
+    /// Implements `dup2` and `dup3`: makes `newfd` a duplicate of `oldfd`, closing what `newfd`
+    /// referred to before.
+    fn duplicate_fd_to(
+        &mut self,
+        oldfd: i32,
+        newfd: i32,
+        fd_flags: i32,
+    ) -> InterpResult<'tcx, i32> {
+        let this = self.eval_context_mut();
+
+        if newfd < 0 {
+            return this.handle_not_found();
+        }
+        let open_fd = match this.duplicate_fd(oldfd, fd_flags)? {
+            Some(open_fd) => open_fd,
+            None => return Ok(-1),
+        };
+        // Errors from closing `newfd` are not reported, like on Linux.
+        let closed = this.machine.file_handler.remove_fd(newfd);
+        this.machine.file_handler.handles.insert(newfd, open_fd);
+        if let Some(OpenFd { file_descriptor, .. }) = closed {
+            file_descriptor.close(this.machine.communicate())?.ok();
+            // Closing a socket makes its peer readable.
+            this.notify_readiness()?;
+        }
+        Ok(newfd)
+    }
+
+    /// Reads the `iovcnt` `struct iovec`s at `iov` that describe the buffers of `readv` and
+    /// `writev`, as pointers and lengths. Returns `None` and sets the last error if there are too
+    /// many of them, or if their lengths add up to more than an `ssize_t` can hold.
+    fn read_iovecs(
+        &mut self,
+        iov: Pointer<Option<Tag>>,
+        iovcnt: i32,
+    ) -> InterpResult<'tcx, Option<Vec<(Pointer<Option<Tag>>, u64)>>> {
+        let this = self.eval_context_mut();
+
+        // `IOV_MAX` is 1024 on all the supported targets.
+        let iovcnt = match u64::try_from(iovcnt) {
+            Ok(iovcnt) if iovcnt <= 1024 => iovcnt,
+            _ => {
+                this.fail_with_errno::<i32>("EINVAL")?;
+                return Ok(None);
+            }
+        };
+        let iovec_layout = this.libc_ty_layout("iovec")?;
+        let mut bufs = vec![];
+        let mut total: u64 = 0;
+        for i in 0..iovcnt {
+            let ptr = iov.offset(iovec_layout.size * i, this)?;
+            let iovec = MPlaceTy::from_aligned_ptr(ptr, iovec_layout);
+            let base = this.mplace_field_named(&iovec, "iov_base")?;
+            let base = this.read_pointer(&base.into())?;
+            let len = this.mplace_field_named(&iovec, "iov_len")?;
+            let len = this.read_scalar(&len.into())?.to_machine_usize(this)?;
+            total = total.saturating_add(len);
+            if total > u64::try_from(this.machine_isize_max()).unwrap() {
+                this.fail_with_errno::<i32>("EINVAL")?;
+                return Ok(None);
+            }
+            bufs.push((base, len));
+        }
+        Ok(Some(bufs))
+    }
+
+    /// Implements `read` and `readv`: reads from `fd` into the buffers `bufs`, given as pointers
+    /// and lengths, filling each of them before the next one, and writes the result to `dest`. If
+    /// there is nothing to read yet and `fd` is in blocking mode, the thread blocks until there is.
+    fn read_to_buffers(
+        &mut self,
+        fd: i32,
+        bufs: Vec<(Pointer<Option<Tag>>, u64)>,
+        dest: &PlaceTy<'tcx, Tag>,
+    ) -> InterpResult<'tcx> {
+        let this = self.eval_context_mut();
+
+        // Isolation check is done via `FileDescriptor` trait.
+
+        // Check that the *entire* buffers are actually valid memory.
+        for &(buf, len) in &bufs {
+            this.check_ptr_access_align(
+                buf,
+                Size::from_bytes(len),
+                Align::ONE,
+                CheckInAllocMsg::MemoryAccessTest,
+            )?;
+        }
+
+        // We cap the number of read bytes to the largest value that we are able to fit in both the
+        // host's and target's `isize`. This saves us from having to handle overflows later.
+        let count = bufs.iter().fold(0u64, |total, &(_, len)| total.saturating_add(len));
+        let count = count.min(this.machine_isize_max() as u64).min(isize::MAX as u64);
+        let communicate = this.machine.communicate();
+        let o_wronly = this.eval_libc_i32("O_WRONLY")?;
+        let o_nonblock = this.eval_libc_i32("O_NONBLOCK")?;
+        let active_thread = this.get_active_thread();
+
+        let result = if let Some(OpenFd { file_descriptor, status_flags, .. }) =
+            this.machine.file_handler.handles.get_mut(&fd)
+        {
+            trace!("read: FD mapped to {:?}", file_descriptor);
+            if status_flags.get() & 0b11 == o_wronly {
+                trace!("read: FD not open for reading");
+                let result = this.handle_not_found()?;
+                return this.write_scalar(Scalar::from_machine_isize(result, this), dest);
+            }
+            let blocking = status_flags.get() & o_nonblock == 0;
+            // We want to read at most `count` bytes. We are sure that `count` is not negative
+            // because it was a target's `usize`. Also we are sure that its smaller than
+            // `usize::MAX` because it is a host's `isize`.
+            let mut bytes = vec![0; count as usize];
+            // `File::read` never returns a value larger than `count`,
+            // so this cannot fail.
+            let result =
+                file_descriptor.read(communicate, &mut bytes)?.map(|c| i64::try_from(c).unwrap());
+
+            match result {
+                Ok(read_bytes) => {
+                    // Reading synchronizes with the writes that made the data available.
+                    if let Some(data_race) = &this.machine.data_race {
+                        file_descriptor.acquire_read(data_race, active_thread);
+                    }
+                    // If reading to `bytes` did not fail, we scatter the bytes that were read
+                    // over the buffers.
+                    let mut rest = &bytes[..usize::try_from(read_bytes).unwrap()];
+                    for &(buf, len) in &bufs {
+                        if rest.is_empty() {
+                            break;
+                        }
+                        let (head, tail) = rest.split_at(rest.len().min(len as usize));
+                        this.write_bytes_ptr(buf, head.iter().copied())?;
+                        rest = tail;
+                    }
+                    read_bytes
+                }
+                Err(e) if e.kind() == ErrorKind::WouldBlock && blocking => {
+                    // The read finishes in `notify_readiness` once there is something to read.
+                    this.block_thread(active_thread);
+                    let op = BlockedOp::Read { bufs };
+                    this.machine.file_handler.block_until_ready(active_thread, fd, op, dest);
+                    return Ok(());
+                }
+                Err(e) => {
+                    this.set_last_error_from_io_error(e.kind())?;
+                    -1
+                }
+            }
+        } else {
+            trace!("read: FD not found");
+            this.handle_not_found()?
+        };
+        this.write_scalar(Scalar::from_machine_isize(result, this), dest)
+    }
+
+    /// Implements `write` and `writev`: writes the contents of the buffers `bufs`, given as
+    /// pointers and lengths, to `fd`, one after the other, and writes the result to `dest`.
+    fn write_from_buffers(
+        &mut self,
+        fd: i32,
+        bufs: &[(Pointer<Option<Tag>>, u64)],
+        dest: &PlaceTy<'tcx, Tag>,
+    ) -> InterpResult<'tcx> {
+        let this = self.eval_context_mut();
+
+        // Isolation check is done via `FileDescriptor` trait.
+
+        // Check that the *entire* buffers are actually valid memory.
+        for &(buf, len) in bufs {
+            this.check_ptr_access_align(
+                buf,
+                Size::from_bytes(len),
+                Align::ONE,
+                CheckInAllocMsg::MemoryAccessTest,
+            )?;
+        }
+
+        // We cap the number of written bytes to the largest value that we are able to fit in both
+        // the host's and target's `isize`. This saves us from having to handle overflows later.
+        let count = bufs.iter().fold(0u64, |total, &(_, len)| total.saturating_add(len));
+        let count = count.min(this.machine_isize_max() as u64).min(isize::MAX as u64);
+
+        // The buffers are gathered into one, so that they are written in one go.
+        let mut bytes = Vec::with_capacity(count as usize);
+        for &(buf, len) in bufs {
+            let len = len.min(count - bytes.len() as u64);
+            bytes.extend_from_slice(this.read_bytes_ptr(buf, Size::from_bytes(len))?);
+        }
+        this.write_to_fd(fd, bytes, dest)
+    }
+
+    /// Writes `bytes` to `fd` and writes the result to `dest`. If `fd` cannot take them yet and is
+    /// in blocking mode, the thread blocks until it can.
+    fn write_to_fd(
+        &mut self,
+        fd: i32,
+        bytes: Vec<u8>,
+        dest: &PlaceTy<'tcx, Tag>,
+    ) -> InterpResult<'tcx> {
+        let this = self.eval_context_mut();
+
+        let communicate = this.machine.communicate();
+        let o_rdonly = this.eval_libc_i32("O_RDONLY")?;
+        let o_nonblock = this.eval_libc_i32("O_NONBLOCK")?;
+        let active_thread = this.get_active_thread();
+
+        let result = if let Some(OpenFd { file_descriptor, status_flags, .. }) =
+            this.machine.file_handler.handles.get(&fd)
+        {
+            if status_flags.get() & 0b11 == o_rdonly {
+                let result = this.handle_not_found()?;
+                return this.write_scalar(Scalar::from_machine_isize(result, this), dest);
+            }
+            let blocking = status_flags.get() & o_nonblock == 0;
+            let result =
+                file_descriptor.write(communicate, &bytes)?.map(|c| i64::try_from(c).unwrap());
+            match &result {
+                Ok(_) => {
+                    // Everything done before the write happens-before reading what it wrote.
+                    if let Some(data_race) = &this.machine.data_race {
+                        file_descriptor.release_write(data_race, active_thread);
+                    }
+                }
+                Err(e) if e.kind() == ErrorKind::WouldBlock && blocking => {
+                    // The write finishes in `notify_readiness` once there is room for it, e.g.
+                    // when an eventfd whose counter would overflow is read.
+                    this.block_thread(active_thread);
+                    let op = BlockedOp::Write { bytes };
+                    this.machine.file_handler.block_until_ready(active_thread, fd, op, dest);
+                    return Ok(());
+                }
+                Err(_) => {}
+            }
+            let result = this.try_unwrap_io_result(result)?;
+            this.notify_readiness()?;
+            result
+        } else {
+            this.handle_not_found()?
+        };
+        this.write_scalar(Scalar::from_machine_isize(result, this), dest)
+    }
+
+    /// Implements `stat`, `lstat` and `fstatat`: writes the metadata of the file at the path
+    /// `path_op`, relative to `dirfd`, to `buf_op`. With `empty_path`, an empty path refers to the
+    /// file `dirfd` itself.
+    fn stat_at(
+        &mut self,
+        shim: &str,
+        dirfd: i32,
+        path_op: &OpTy<'tcx, Tag>,
+        buf_op: &OpTy<'tcx, Tag>,
+        follow_symlink: bool,
+        empty_path: bool,
+    ) -> InterpResult<'tcx, i32> {
+        let this = self.eval_context_mut();
+        let path = this.read_path_from_c_str(this.read_pointer(path_op)?)?.into_owned();
+        let path = if path.as_os_str().is_empty() && empty_path {
+            None
+        } else {
+            match this.resolve_path_at(dirfd, path)? {
+                Some(path) => Some(path),
+                None => return Ok(-1),
+            }
+        };
+
+        // Reject if isolation is enabled.
+        if let IsolatedOp::Reject(reject_with) = this.machine.isolated_op {
+            this.reject_in_isolation(&format!("`{}`", shim), reject_with)?;
+            if path.is_none() {
+                // `EACCES` would be wrong for a file descriptor.
+                return this.handle_not_found();
+            }
+            let eacc = this.eval_libc("EACCES")?;
+            this.set_last_error(eacc)?;
+            return Ok(-1);
+        }
+
+        let metadata = match path {
+            Some(path) => FileMetadata::from_path(this, &path, follow_symlink)?,
+            None => FileMetadata::from_fd(this, dirfd)?,
+        };
+        match metadata {
+            Some(metadata) => this.stat_write_buf(metadata, buf_op),
+            None => Ok(-1),
+        }
+    }
+
+    /// Implements `rename` and `renameat`.
+    fn rename_at(
+        &mut self,
+        shim: &str,
+        olddirfd: i32,
+        oldpath_op: &OpTy<'tcx, Tag>,
+        newdirfd: i32,
+        newpath_op: &OpTy<'tcx, Tag>,
+    ) -> InterpResult<'tcx, i32> {
+        let this = self.eval_context_mut();
+
+        let oldpath_ptr = this.read_pointer(oldpath_op)?;
+        let newpath_ptr = this.read_pointer(newpath_op)?;
+
+        if this.ptr_is_null(oldpath_ptr)? || this.ptr_is_null(newpath_ptr)? {
+            let efault = this.eval_libc("EFAULT")?;
+            this.set_last_error(efault)?;
+            return Ok(-1);
+        }
+
+        let oldpath = this.read_path_from_c_str(oldpath_ptr)?.into_owned();
+        let oldpath = match this.resolve_path_at(olddirfd, oldpath)? {
+            Some(oldpath) => oldpath,
+            None => return Ok(-1),
+        };
+        let newpath = this.read_path_from_c_str(newpath_ptr)?.into_owned();
+        let newpath = match this.resolve_path_at(newdirfd, newpath)? {
+            Some(newpath) => newpath,
+            None => return Ok(-1),
+        };
+
+        // Reject if isolation is enabled.
+        if let IsolatedOp::Reject(reject_with) = this.machine.isolated_op {
+            this.reject_in_isolation(&format!("`{}`", shim), reject_with)?;
+            this.set_last_error_from_io_error(ErrorKind::PermissionDenied)?;
+            return Ok(-1);
+        }
+
+        let result = rename(oldpath, newpath).map(|_| 0);
+
+        this.try_unwrap_io_result(result)
+    }
+
+    /// Implements `mkdir` and `mkdirat`.
+    fn mkdir_at(
+        &mut self,
+        shim: &str,
+        dirfd: i32,
+        path_op: &OpTy<'tcx, Tag>,
+        mode_op: &OpTy<'tcx, Tag>,
+    ) -> InterpResult<'tcx, i32> {
+        let this = self.eval_context_mut();
+
+        #[cfg_attr(not(unix), allow(unused_variables))]
+        let mode = if this.tcx.sess.target.os == "macos" {
+            u32::from(this.read_scalar(mode_op)?.to_u16()?)
+        } else {
+            this.read_scalar(mode_op)?.to_u32()?
+        };
+
+        let path = this.read_path_from_c_str(this.read_pointer(path_op)?)?.into_owned();
+        let path = match this.resolve_path_at(dirfd, path)? {
+            Some(path) => path,
+            None => return Ok(-1),
+        };
+
+        // Reject if isolation is enabled.
+        if let IsolatedOp::Reject(reject_with) = this.machine.isolated_op {
+            this.reject_in_isolation(&format!("`{}`", shim), reject_with)?;
+            this.set_last_error_from_io_error(ErrorKind::PermissionDenied)?;
+            return Ok(-1);
+        }
+
+        #[cfg_attr(not(unix), allow(unused_mut))]
+        let mut builder = DirBuilder::new();
+
+        // If the host supports it, forward on the mode of the directory
+        // (i.e. permission bits and the sticky bit)
+        #[cfg(unix)]
+        {
+            use std::os::unix::fs::DirBuilderExt;
+            builder.mode(mode);
+        }
+
+        let result = builder.create(path).map(|_| 0i32);
+
+        this.try_unwrap_io_result(result)
+    }
+
+    /// Implements `readlink` and `readlinkat`.
+    fn readlink_at(
+        &mut self,
+        shim: &str,
+        dirfd: i32,
+        pathname_op: &OpTy<'tcx, Tag>,
+        buf_op: &OpTy<'tcx, Tag>,
+        bufsize_op: &OpTy<'tcx, Tag>,
+    ) -> InterpResult<'tcx, i64> {
+        let this = self.eval_context_mut();
+
+        let pathname = this.read_path_from_c_str(this.read_pointer(pathname_op)?)?.into_owned();
+        let buf = this.read_pointer(buf_op)?;
+        let bufsize = this.read_scalar(bufsize_op)?.to_machine_usize(this)?;
+        let pathname = match this.resolve_path_at(dirfd, pathname)? {
+            Some(pathname) => pathname,
+            None => return Ok(-1),
+        };
+
+        // Reject if isolation is enabled.
+        if let IsolatedOp::Reject(reject_with) = this.machine.isolated_op {
+            this.reject_in_isolation(&format!("`{}`", shim), reject_with)?;
+            let eacc = this.eval_libc("EACCES")?;
+            this.set_last_error(eacc)?;
+            return Ok(-1);
+        }
+
+        let result = std::fs::read_link(pathname);
+        match result {
+            Ok(resolved) => {
+                let resolved = this.convert_path_separator(
+                    Cow::Borrowed(resolved.as_ref()),
+                    crate::shims::os_str::PathConversion::HostToTarget,
+                );
+                let mut path_bytes = crate::shims::os_str::os_str_to_bytes(resolved.as_ref())?;
+                let bufsize: usize = bufsize.try_into().unwrap();
+                if path_bytes.len() > bufsize {
+                    path_bytes = &path_bytes[..bufsize]
+                }
+                // 'readlink' truncates the resolved path if
+                // the provided buffer is not large enough.
+                this.write_bytes_ptr(buf, path_bytes.iter().copied())?;
+                Ok(path_bytes.len().try_into().unwrap())
+            }
+            Err(e) => {
+                this.set_last_error_from_io_error(e.kind())?;
+                Ok(-1)
+            }
+        }
+    }
+
+    /// Whether `fd` refers to a standard stream that `-Zmiri-pretend-tty` reports as a terminal.
+    /// Returns `None` if `fd` is not open.
+    fn fd_is_tty(&self, fd: i32) -> Option<bool> {
+        let this = self.eval_context_ref();
+        let OpenFd { file_descriptor, .. } = this.machine.file_handler.handles.get(&fd)?;
+        Some(
+            file_descriptor
+                .std_stream()
+                .map_or(false, |stream| this.machine.pretend_tty.contains(&stream)),
+        )
+    }
+
+    /// The file status flags of `fd`, and whether it is a regular file. Returns `None` if `fd` is
+    /// not open.
+    fn fd_status(&self, fd: i32) -> Option<(i32, bool)> {
+        let this = self.eval_context_ref();
+        let OpenFd { file_descriptor, status_flags, .. } =
+            this.machine.file_handler.handles.get(&fd)?;
+        Some((status_flags.get(), file_descriptor.as_file_handle().is_ok()))
+    }
+
+    /// Reads the file offset of type `ty` that `ptr` points to, for the syscalls that take an
+    /// optional offset. Returns the place of the offset, so that it can be updated.
+    fn read_offset_arg(
+        &self,
+        ptr: Pointer<Option<Tag>>,
+        ty: &str,
+    ) -> InterpResult<'tcx, Option<(MPlaceTy<'tcx, Tag>, i64)>> {
+        let this = self.eval_context_ref();
+        if this.ptr_is_null(ptr)? {
+            return Ok(None);
+        }
+        let place = MPlaceTy::from_aligned_ptr(ptr, this.libc_ty_layout(ty)?);
+        let offset = this.read_scalar(&place.into())?.to_bits(place.layout.size)?;
+        let offset = place.layout.size.sign_extend(offset) as i64;
+        Ok(Some((place, offset)))
+    }
+
+    /// Copies up to `len` bytes from the regular file `fd_in` to `fd_out` in chunks, like a loop
+    /// of `read` and `write` would. The files are accessed at the given offsets without changing
+    /// their file offsets, or at their file offsets if no offset is given. `fd_out` only has to be
+    /// a regular file if it has an offset. Returns the number of bytes copied, or the error if
+    /// nothing could be copied.
+    fn copy_between_fds(
+        &mut self,
+        fd_in: i32,
+        in_offset: Option<u64>,
+        fd_out: i32,
+        out_offset: Option<u64>,
+        len: u64,
+    ) -> InterpResult<'tcx, io::Result<u64>> {
+        let this = self.eval_context_mut();
+        let communicate = this.machine.communicate();
+        let active_thread = this.get_active_thread();
+
+        let mut copied = 0;
+        let mut error = None;
+        while copied < len {
+            let chunk_len = (len - copied).min(COPY_CHUNK_SIZE);
+            // The caller checked that both descriptors are open.
+            let FileHandle { file, .. } =
+                this.machine.file_handler.handles[&fd_in].file_descriptor.as_file_handle()?;
+            let bytes = match read_chunk(file, in_offset.map(|offset| offset + copied), chunk_len) {
+                Ok(bytes) if bytes.is_empty() => break,
+                Ok(bytes) => bytes,
+                Err(e) => {
+                    error = Some(e);
+                    break;
+                }
+            };
+            let file_descriptor = &this.machine.file_handler.handles[&fd_out].file_descriptor;
+            let result = match out_offset {
+                Some(offset) => {
+                    let FileHandle { file, .. } = file_descriptor.as_file_handle()?;
+                    write_at(file, offset + copied, &bytes)
+                }
+                None => write_all(&**file_descriptor, communicate, &bytes)?,
+            };
+            if let Err(e) = result {
+                error = Some(e);
+                break;
+            }
+            // Everything done before the write happens-before reading what it wrote.
+            if let Some(data_race) = &this.machine.data_race {
+                file_descriptor.release_write(data_race, active_thread);
+            }
+            copied += u64::try_from(bytes.len()).unwrap();
+        }
+        if copied > 0 {
+            this.notify_readiness()?;
+        }
+        Ok(match error {
+            Some(e) if copied == 0 => Err(e),
+            _ => Ok(copied),
+        })
+    }
+
+    fn file_type_to_d_type(
+        &mut self,
+        file_type: std::io::Result<FileType>,
+    ) -> InterpResult<'tcx, i32> {
+        let this = self.eval_context_mut();
+        match file_type {
+            Ok(file_type) => {
+                if file_type.is_dir() {
+                    Ok(this.eval_libc("DT_DIR")?.to_u8()?.into())
+                } else if file_type.is_file() {
+                    Ok(this.eval_libc("DT_REG")?.to_u8()?.into())
                 } else if file_type.is_symlink() {
                     Ok(this.eval_libc("DT_LNK")?.to_u8()?.into())
                 } else {
@@ -414,12 +1834,15 @@ pub struct OpenDir {
     read_dir: ReadDir,
     /// The most recent entry returned by readdir()
     entry: Pointer<Option<Tag>>,
+    /// The entries to list before those of `read_dir`, in reverse order, with their paths. The
+    /// Windows find functions list `.` and `..`, which `read_dir` skips.
+    dot_entries: Vec<(&'static str, PathBuf)>,
 }
 
 impl OpenDir {
     fn new(read_dir: ReadDir) -> Self {
         // We rely on `free` being a NOP on null pointers.
-        Self { read_dir, entry: Pointer::null() }
+        Self { read_dir, entry: Pointer::null(), dot_entries: Vec::new() }
     }
 }
 
@@ -446,6 +1869,37 @@ impl DirHandler {
         self.streams.try_insert(id, OpenDir::new(read_dir)).unwrap();
         id
     }
+
+    /// Opens a directory stream for listing `dir` with the Windows find functions, which list
+    /// `.` and `..` first.
+    pub fn insert_with_dot_entries(&mut self, read_dir: ReadDir, dir: &Path) -> u64 {
+        let id = self.insert_new(read_dir);
+        let dot_entries = vec![("..", dir.join("..")), (".", dir.to_owned())];
+        self.streams.get_mut(&id).unwrap().dot_entries = dot_entries;
+        id
+    }
+
+    /// The name and metadata of the next entry of the stream `id`, for the Windows find
+    /// functions. Returns `None` if there is no such stream, and `Some(None)` at its end.
+    pub fn next_entry_metadata(
+        &mut self,
+        id: u64,
+    ) -> Option<Option<io::Result<(OsString, Metadata)>>> {
+        let open_dir = self.streams.get_mut(&id)?;
+        if let Some((name, path)) = open_dir.dot_entries.pop() {
+            return Some(Some(path.metadata().map(|metadata| (name.into(), metadata))));
+        }
+        Some(open_dir.read_dir.next().map(|entry| {
+            let entry = entry?;
+            Ok((entry.file_name(), entry.metadata()?))
+        }))
+    }
+
+    /// Closes the stream `id`, which must not have been used by `readdir`. Returns whether the
+    /// stream existed.
+    pub fn remove(&mut self, id: u64) -> bool {
+        self.streams.remove(&id).is_some()
+    }
 }
 
 impl Default for DirHandler {
@@ -486,105 +1940,22 @@ pub trait EvalContextExt<'mir, 'tcx: 'mir>: crate::MiriEvalContextExt<'mir, 'tcx
 
         let this = self.eval_context_mut();
 
-        let path = this.read_pointer(&args[0])?;
-        let flag = this.read_scalar(&args[1])?.to_i32()?;
-
-        let mut options = OpenOptions::new();
-
-        let o_rdonly = this.eval_libc_i32("O_RDONLY")?;
-        let o_wronly = this.eval_libc_i32("O_WRONLY")?;
-        let o_rdwr = this.eval_libc_i32("O_RDWR")?;
-        // The first two bits of the flag correspond to the access mode in linux, macOS and
-        // windows. We need to check that in fact the access mode flags for the current target
-        // only use these two bits, otherwise we are in an unsupported target and should error.
-        if (o_rdonly | o_wronly | o_rdwr) & !0b11 != 0 {
-            throw_unsup_format!("access mode flags on this target are unsupported");
-        }
-        let mut writable = true;
-
-        // Now we check the access mode
-        let access_mode = flag & 0b11;
-
-        if access_mode == o_rdonly {
-            writable = false;
-            options.read(true);
-        } else if access_mode == o_wronly {
-            options.write(true);
-        } else if access_mode == o_rdwr {
-            options.read(true).write(true);
-        } else {
-            throw_unsup_format!("unsupported access mode {:#x}", access_mode);
-        }
-        // We need to check that there aren't unsupported options in `flag`. For this we try to
-        // reproduce the content of `flag` in the `mirror` variable using only the supported
-        // options.
-        let mut mirror = access_mode;
-
-        let o_append = this.eval_libc_i32("O_APPEND")?;
-        if flag & o_append != 0 {
-            options.append(true);
-            mirror |= o_append;
-        }
-        let o_trunc = this.eval_libc_i32("O_TRUNC")?;
-        if flag & o_trunc != 0 {
-            options.truncate(true);
-            mirror |= o_trunc;
-        }
-        let o_creat = this.eval_libc_i32("O_CREAT")?;
-        if flag & o_creat != 0 {
-            // Get the mode.  On macOS, the argument type `mode_t` is actually `u16`, but
-            // C integer promotion rules mean that on the ABI level, it gets passed as `u32`
-            // (see https://github.com/rust-lang/rust/issues/71915).
-            let mode = if let Some(arg) = args.get(2) {
-                this.read_scalar(arg)?.to_u32()?
-            } else {
-                throw_ub_format!(
-                    "incorrect number of arguments for `open` with `O_CREAT`: got {}, expected at least 3",
-                    args.len()
-                );
-            };
-
-            if mode != 0o666 {
-                throw_unsup_format!("non-default mode 0o{:o} is not supported", mode);
-            }
-
-            mirror |= o_creat;
-
-            let o_excl = this.eval_libc_i32("O_EXCL")?;
-            if flag & o_excl != 0 {
-                mirror |= o_excl;
-                options.create_new(true);
-            } else {
-                options.create(true);
-            }
-        }
-        let o_cloexec = this.eval_libc_i32("O_CLOEXEC")?;
-        if flag & o_cloexec != 0 {
-            // We do not need to do anything for this flag because `std` already sets it.
-            // (Technically we do not support *not* setting this flag, but we ignore that.)
-            mirror |= o_cloexec;
-        }
-        // If `flag` is not equal to `mirror`, there is an unsupported option enabled in `flag`,
-        // then we throw an error.
-        if flag != mirror {
-            throw_unsup_format!("unsupported flags {:#x}", flag & !mirror);
-        }
-
-        let path = this.read_path_from_c_str(path)?;
+        let at_fdcwd = this.eval_libc_i32("AT_FDCWD")?;
+        this.open_at("open", at_fdcwd, args, 0)
+    }
 
-        // Reject if isolation is enabled.
-        if let IsolatedOp::Reject(reject_with) = this.machine.isolated_op {
-            this.reject_in_isolation("`open`", reject_with)?;
-            this.set_last_error_from_io_error(ErrorKind::PermissionDenied)?;
-            return Ok(-1);
+    fn openat(&mut self, args: &[OpTy<'tcx, Tag>]) -> InterpResult<'tcx, i32> {
+        if args.len() < 3 {
+            throw_ub_format!(
+                "incorrect number of arguments for `openat`: got {}, expected at least 3",
+                args.len()
+            );
         }
 
-        let fd = options.open(&path).map(|file| {
-            let fh = &mut this.machine.file_handler;
-            fh.insert_fd(Box::new(FileHandle { file, writable }))
-        });
+        let this = self.eval_context_mut();
 
-        this.try_unwrap_io_result(fd)
+        let dirfd = this.read_scalar(&args[0])?.to_i32()?;
+        this.open_at("openat", dirfd, args, 1)
     }
 
     fn fcntl(&mut self, args: &[OpTy<'tcx, Tag>]) -> InterpResult<'tcx, i32> {
@@ -606,24 +1977,37 @@ pub trait EvalContextExt<'mir, 'tcx: 'mir>: crate::MiriEvalContextExt<'mir, 'tcx
             return Ok(-1);
         }
 
-        // We only support getting the flags for a descriptor.
         if cmd == this.eval_libc_i32("F_GETFD")? {
-            // Currently this is the only flag that `F_GETFD` returns. It is OK to just return the
-            // `FD_CLOEXEC` value without checking if the flag is set for the file because `std`
-            // always sets this flag when opening a file. However we still need to check that the
-            // file itself is open.
-            if this.machine.file_handler.handles.contains_key(&fd) {
-                Ok(this.eval_libc_i32("FD_CLOEXEC")?)
-            } else {
-                this.handle_not_found()
+            match this.machine.file_handler.handles.get(&fd) {
+                Some(OpenFd { fd_flags, .. }) => Ok(*fd_flags),
+                None => this.handle_not_found(),
+            }
+        } else if cmd == this.eval_libc_i32("F_SETFD")? {
+            if args.len() < 3 {
+                throw_ub_format!(
+                    "incorrect number of arguments for fcntl with cmd=`F_SETFD`: got {}, expected at least 3",
+                    args.len()
+                );
+            }
+            // `FD_CLOEXEC` is the only file descriptor flag, the other bits are ignored.
+            let flags = this.read_scalar(&args[2])?.to_i32()? & this.eval_libc_i32("FD_CLOEXEC")?;
+            match this.machine.file_handler.handles.get_mut(&fd) {
+                Some(OpenFd { fd_flags, .. }) => {
+                    *fd_flags = flags;
+                    Ok(0)
+                }
+                None => this.handle_not_found(),
             }
         } else if cmd == this.eval_libc_i32("F_DUPFD")?
             || cmd == this.eval_libc_i32("F_DUPFD_CLOEXEC")?
         {
-            // Note that we always assume the FD_CLOEXEC flag is set for every open file, in part
-            // because exec() isn't supported. The F_DUPFD and F_DUPFD_CLOEXEC commands only
-            // differ in whether the FD_CLOEXEC flag is pre-set on the new file descriptor,
-            // thus they can share the same implementation here.
+            // The F_DUPFD and F_DUPFD_CLOEXEC commands only differ in whether the FD_CLOEXEC
+            // flag is set on the new file descriptor.
+            let fd_flags = if cmd == this.eval_libc_i32("F_DUPFD_CLOEXEC")? {
+                this.eval_libc_i32("FD_CLOEXEC")?
+            } else {
+                0
+            };
             if args.len() < 3 {
                 throw_ub_format!(
                     "incorrect number of arguments for fcntl with cmd=`F_DUPFD`/`F_DUPFD_CLOEXEC`: got {}, expected at least 3",
@@ -632,25 +2016,43 @@ pub trait EvalContextExt<'mir, 'tcx: 'mir>: crate::MiriEvalContextExt<'mir, 'tcx
             }
             let start = this.read_scalar(&args[2])?.to_i32()?;
 
-            let fh = &mut this.machine.file_handler;
-
-            match fh.handles.get_mut(&fd) {
-                Some(file_descriptor) => {
-                    let dup_result = file_descriptor.dup();
-                    match dup_result {
-                        Ok(dup_fd) => Ok(fh.insert_fd_with_min_fd(dup_fd, start)),
-                        Err(e) => {
-                            this.set_last_error_from_io_error(e.kind())?;
-                            Ok(-1)
-                        }
-                    }
-                }
+            match this.duplicate_fd(fd, fd_flags)? {
+                Some(open_fd) =>
+                    Ok(this.machine.file_handler.insert_fd_with_min_fd(open_fd, start)),
+                None => Ok(-1),
+            }
+        } else if cmd == this.eval_libc_i32("F_GETFL")? {
+            match this.machine.file_handler.handles.get(&fd) {
+                Some(OpenFd { status_flags, .. }) => Ok(status_flags.get()),
                 None => this.handle_not_found(),
             }
+        } else if cmd == this.eval_libc_i32("F_SETFL")? {
+            if args.len() < 3 {
+                throw_ub_format!(
+                    "incorrect number of arguments for fcntl with cmd=`F_SETFL`: got {}, expected at least 3",
+                    args.len()
+                );
+            }
+            let flags = this.read_scalar(&args[2])?.to_i32()?;
+            let o_append = this.eval_libc_i32("O_APPEND")?;
+            let o_nonblock = this.eval_libc_i32("O_NONBLOCK")?;
+
+            let status_flags = match this.machine.file_handler.handles.get(&fd) {
+                Some(OpenFd { status_flags, .. }) => Rc::clone(status_flags),
+                None => return this.handle_not_found(),
+            };
+            // The access mode cannot be changed, and the file creation flags are ignored.
+            // Appending is done by the host, which we cannot change after the file was opened.
+            if (flags ^ status_flags.get()) & o_append != 0 {
+                throw_unsup_format!("changing `O_APPEND` with `fcntl` is not supported");
+            }
+            status_flags.set((status_flags.get() & !o_nonblock) | (flags & o_nonblock));
+            Ok(0)
         } else if this.tcx.sess.target.os == "macos" && cmd == this.eval_libc_i32("F_FULLFSYNC")? {
-            if let Some(file_descriptor) = this.machine.file_handler.handles.get(&fd) {
+            if let Some(OpenFd { file_descriptor, .. }) = this.machine.file_handler.handles.get(&fd)
+            {
                 // FIXME: Support fullfsync for all FDs
-                let FileHandle { file, writable } = file_descriptor.as_file_handle()?;
+                let FileHandle { file, writable, .. } = file_descriptor.as_file_handle()?;
                 let io_result = maybe_sync_file(file, *writable, File::sync_all);
                 this.try_unwrap_io_result(io_result)
             } else {
@@ -661,26 +2063,254 @@ pub trait EvalContextExt<'mir, 'tcx: 'mir>: crate::MiriEvalContextExt<'mir, 'tcx
         }
     }
 
-    fn close(&mut self, fd_op: &OpTy<'tcx, Tag>) -> InterpResult<'tcx, i32> {
+    fn isatty(&mut self, fd_op: &OpTy<'tcx, Tag>) -> InterpResult<'tcx, i32> {
+        let this = self.eval_context_mut();
+
+        let fd = this.read_scalar(fd_op)?.to_i32()?;
+        // "returns 1 if fd is an open file descriptor referring to a terminal; otherwise 0 is
+        // returned, and errno is set to indicate the error"
+        // Only the standard streams given to `-Zmiri-pretend-tty` are terminals.
+        match this.fd_is_tty(fd) {
+            Some(true) => Ok(1),
+            Some(false) => {
+                let enotty = this.eval_libc("ENOTTY")?;
+                this.set_last_error(enotty)?;
+                Ok(0)
+            }
+            None => {
+                let ebadf = this.eval_libc("EBADF")?;
+                this.set_last_error(ebadf)?;
+                Ok(0)
+            }
+        }
+    }
+
+    fn ioctl(&mut self, args: &[OpTy<'tcx, Tag>]) -> InterpResult<'tcx, i32> {
+        let this = self.eval_context_mut();
+
+        if args.len() < 2 {
+            throw_ub_format!(
+                "incorrect number of arguments for ioctl: got {}, expected at least 2",
+                args.len()
+            );
+        }
+        let fd = this.read_scalar(&args[0])?.to_i32()?;
+        let request = this.read_scalar(&args[1])?.to_machine_usize(this)?;
+
+        if request == this.eval_libc("TIOCGWINSZ")?.to_machine_usize(this)? {
+            if args.len() < 3 {
+                throw_ub_format!(
+                    "incorrect number of arguments for ioctl with request=`TIOCGWINSZ`: got {}, expected at least 3",
+                    args.len()
+                );
+            }
+            match this.fd_is_tty(fd) {
+                Some(true) => {}
+                Some(false) => return this.fail_with_errno("ENOTTY"),
+                None => return this.handle_not_found(),
+            }
+            let winsize_ptr = this.read_pointer(&args[2])?;
+            let winsize_layout = this.libc_ty_layout("winsize")?;
+            let winsize = MPlaceTy::from_aligned_ptr(winsize_ptr, winsize_layout);
+            let (cols, rows) = this.machine.tty_size;
+            this.write_int_fields_named(
+                &[
+                    ("ws_row", rows.into()),
+                    ("ws_col", cols.into()),
+                    ("ws_xpixel", 0),
+                    ("ws_ypixel", 0),
+                ],
+                &winsize,
+            )?;
+            Ok(0)
+        } else if request == this.eval_libc("FIOCLEX")?.to_machine_usize(this)? {
+            // std uses this instead of `fcntl` on some targets, e.g. for sockets on macOS.
+            let fd_cloexec = this.eval_libc_i32("FD_CLOEXEC")?;
+            match this.machine.file_handler.handles.get_mut(&fd) {
+                Some(open_fd) => {
+                    open_fd.fd_flags = fd_cloexec;
+                    Ok(0)
+                }
+                None => this.handle_not_found(),
+            }
+        } else if request == this.eval_libc("FIONBIO")?.to_machine_usize(this)? {
+            // std uses this instead of `fcntl` to make sockets non-blocking on some targets.
+            if args.len() < 3 {
+                throw_ub_format!(
+                    "incorrect number of arguments for ioctl with request=`FIONBIO`: got {}, expected at least 3",
+                    args.len()
+                );
+            }
+            let nonblocking = this.deref_operand(&args[2])?;
+            let nonblocking = this.read_scalar(&nonblocking.into())?.to_i32()? != 0;
+            let o_nonblock = this.eval_libc_i32("O_NONBLOCK")?;
+            match this.machine.file_handler.handles.get(&fd) {
+                Some(open_fd) => {
+                    let status_flags = open_fd.status_flags.get() & !o_nonblock;
+                    open_fd.status_flags.set(if nonblocking {
+                        status_flags | o_nonblock
+                    } else {
+                        status_flags
+                    });
+                    Ok(0)
+                }
+                None => this.handle_not_found(),
+            }
+        } else {
+            throw_unsup_format!("the {:#x} request of `ioctl` is not supported", request);
+        }
+    }
+
+    fn close(&mut self, fd_op: &OpTy<'tcx, Tag>) -> InterpResult<'tcx, i32> {
+        let this = self.eval_context_mut();
+
+        let fd = this.read_scalar(fd_op)?.to_i32()?;
+
+        if let Some(OpenFd { file_descriptor, .. }) = this.machine.file_handler.remove_fd(fd) {
+            let result = file_descriptor.close(this.machine.communicate())?;
+            // Closing a socket makes its peer readable.
+            this.notify_readiness()?;
+            this.try_unwrap_io_result(result)
+        } else {
+            this.handle_not_found()
+        }
+    }
+
+    fn dup(&mut self, oldfd_op: &OpTy<'tcx, Tag>) -> InterpResult<'tcx, i32> {
+        let this = self.eval_context_mut();
+
+        let oldfd = this.read_scalar(oldfd_op)?.to_i32()?;
+        match this.duplicate_fd(oldfd, 0)? {
+            Some(open_fd) => Ok(this.machine.file_handler.insert_fd_with_min_fd(open_fd, 0)),
+            None => Ok(-1),
+        }
+    }
+
+    fn dup2(
+        &mut self,
+        oldfd_op: &OpTy<'tcx, Tag>,
+        newfd_op: &OpTy<'tcx, Tag>,
+    ) -> InterpResult<'tcx, i32> {
+        let this = self.eval_context_mut();
+
+        let oldfd = this.read_scalar(oldfd_op)?.to_i32()?;
+        let newfd = this.read_scalar(newfd_op)?.to_i32()?;
+        // Duplicating a file descriptor onto itself does nothing, if it is open.
+        if oldfd == newfd {
+            return if this.machine.file_handler.handles.contains_key(&oldfd) {
+                Ok(newfd)
+            } else {
+                this.handle_not_found()
+            };
+        }
+        this.duplicate_fd_to(oldfd, newfd, 0)
+    }
+
+    fn dup3(
+        &mut self,
+        oldfd_op: &OpTy<'tcx, Tag>,
+        newfd_op: &OpTy<'tcx, Tag>,
+        flags_op: &OpTy<'tcx, Tag>,
+    ) -> InterpResult<'tcx, i32> {
+        let this = self.eval_context_mut();
+
+        let oldfd = this.read_scalar(oldfd_op)?.to_i32()?;
+        let newfd = this.read_scalar(newfd_op)?.to_i32()?;
+        let flags = this.read_scalar(flags_op)?.to_i32()?;
+
+        // Unlike `dup2`, `dup3` does not allow duplicating a file descriptor onto itself.
+        let o_cloexec = this.eval_libc_i32("O_CLOEXEC")?;
+        if oldfd == newfd || flags & !o_cloexec != 0 {
+            let einval = this.eval_libc("EINVAL")?;
+            this.set_last_error(einval)?;
+            return Ok(-1);
+        }
+        let fd_flags = if flags & o_cloexec != 0 { this.eval_libc_i32("FD_CLOEXEC")? } else { 0 };
+        this.duplicate_fd_to(oldfd, newfd, fd_flags)
+    }
+
+    /// Reads from `fd` and writes the result to `dest`. If there is nothing to read yet and `fd`
+    /// is in blocking mode, the thread blocks until there is.
+    fn read(
+        &mut self,
+        fd: i32,
+        buf: Pointer<Option<Tag>>,
+        count: u64,
+        dest: &PlaceTy<'tcx, Tag>,
+    ) -> InterpResult<'tcx> {
+        let this = self.eval_context_mut();
+
+        trace!("Reading from FD {}, size {}", fd, count);
+        this.read_to_buffers(fd, vec![(buf, count)], dest)
+    }
+
+    /// Writes to `fd` and writes the result to `dest`. If `fd` cannot take what is written yet and
+    /// is in blocking mode, the thread blocks until it can.
+    fn write(
+        &mut self,
+        fd: i32,
+        buf: Pointer<Option<Tag>>,
+        count: u64,
+        dest: &PlaceTy<'tcx, Tag>,
+    ) -> InterpResult<'tcx> {
+        let this = self.eval_context_mut();
+        this.write_from_buffers(fd, &[(buf, count)], dest)
+    }
+
+    /// Implements `readv`: like `read`, but scatters what it reads over the `iovcnt` buffers of
+    /// the `struct iovec` array at `iov_op`.
+    fn readv(
+        &mut self,
+        fd_op: &OpTy<'tcx, Tag>,
+        iov_op: &OpTy<'tcx, Tag>,
+        iovcnt_op: &OpTy<'tcx, Tag>,
+        dest: &PlaceTy<'tcx, Tag>,
+    ) -> InterpResult<'tcx> {
+        let this = self.eval_context_mut();
+
+        let fd = this.read_scalar(fd_op)?.to_i32()?;
+        let iov = this.read_pointer(iov_op)?;
+        let iovcnt = this.read_scalar(iovcnt_op)?.to_i32()?;
+
+        match this.read_iovecs(iov, iovcnt)? {
+            Some(bufs) => this.read_to_buffers(fd, bufs, dest),
+            None => this.write_scalar(Scalar::from_machine_isize(-1, this), dest),
+        }
+    }
+
+    /// Implements `writev`: like `write`, but gathers what it writes from the `iovcnt` buffers of
+    /// the `struct iovec` array at `iov_op`.
+    fn writev(
+        &mut self,
+        fd_op: &OpTy<'tcx, Tag>,
+        iov_op: &OpTy<'tcx, Tag>,
+        iovcnt_op: &OpTy<'tcx, Tag>,
+        dest: &PlaceTy<'tcx, Tag>,
+    ) -> InterpResult<'tcx> {
         let this = self.eval_context_mut();
 
         let fd = this.read_scalar(fd_op)?.to_i32()?;
+        let iov = this.read_pointer(iov_op)?;
+        let iovcnt = this.read_scalar(iovcnt_op)?.to_i32()?;
 
-        if let Some(file_descriptor) = this.machine.file_handler.handles.remove(&fd) {
-            let result = file_descriptor.close(this.machine.communicate())?;
-            this.try_unwrap_io_result(result)
-        } else {
-            this.handle_not_found()
+        match this.read_iovecs(iov, iovcnt)? {
+            Some(bufs) => this.write_from_buffers(fd, &bufs, dest),
+            None => this.write_scalar(Scalar::from_machine_isize(-1, this), dest),
         }
     }
 
-    fn read(&mut self, fd: i32, buf: Pointer<Option<Tag>>, count: u64) -> InterpResult<'tcx, i64> {
+    /// Implements `pread`: like `read`, but at `offset` and without changing the file offset.
+    fn pread(
+        &mut self,
+        fd: i32,
+        buf: Pointer<Option<Tag>>,
+        count: u64,
+        offset: i64,
+    ) -> InterpResult<'tcx, i64> {
         let this = self.eval_context_mut();
 
         // Isolation check is done via `FileDescriptor` trait.
 
-        trace!("Reading from FD {}, size {}", fd, count);
-
         // Check that the *entire* buffer is actually valid memory.
         this.check_ptr_access_align(
             buf,
@@ -689,40 +2319,52 @@ pub trait EvalContextExt<'mir, 'tcx: 'mir>: crate::MiriEvalContextExt<'mir, 'tcx
             CheckInAllocMsg::MemoryAccessTest,
         )?;
 
-        // We cap the number of read bytes to the largest value that we are able to fit in both the
-        // host's and target's `isize`. This saves us from having to handle overflows later.
+        // We cap the number of read bytes like `read` does.
         let count = count.min(this.machine_isize_max() as u64).min(isize::MAX as u64);
+        let offset = match u64::try_from(offset) {
+            Ok(offset) => offset,
+            Err(_) => {
+                let einval = this.eval_libc("EINVAL")?;
+                this.set_last_error(einval)?;
+                return Ok(-1);
+            }
+        };
         let communicate = this.machine.communicate();
+        let o_wronly = this.eval_libc_i32("O_WRONLY")?;
 
-        if let Some(file_descriptor) = this.machine.file_handler.handles.get_mut(&fd) {
-            trace!("read: FD mapped to {:?}", file_descriptor);
-            // We want to read at most `count` bytes. We are sure that `count` is not negative
-            // because it was a target's `usize`. Also we are sure that its smaller than
-            // `usize::MAX` because it is a host's `isize`.
-            let mut bytes = vec![0; count as usize];
-            // `File::read` never returns a value larger than `count`,
-            // so this cannot fail.
-            let result =
-                file_descriptor.read(communicate, &mut bytes)?.map(|c| i64::try_from(c).unwrap());
-
-            match result {
-                Ok(read_bytes) => {
-                    // If reading to `bytes` did not fail, we write those bytes to the buffer.
-                    this.write_bytes_ptr(buf, bytes)?;
-                    Ok(read_bytes)
-                }
-                Err(e) => {
-                    this.set_last_error_from_io_error(e.kind())?;
-                    Ok(-1)
-                }
+        let result = match this.machine.file_handler.handles.get(&fd) {
+            Some(OpenFd { status_flags, .. }) if status_flags.get() & 0b11 == o_wronly =>
+                return this.handle_not_found(),
+            Some(OpenFd { file_descriptor, .. }) => {
+                let mut bytes = vec![0; usize::try_from(count).unwrap()];
+                file_descriptor.pread(communicate, &mut bytes, offset)?.map(|read| {
+                    bytes.truncate(read);
+                    bytes
+                })
+            }
+            None => return this.handle_not_found(),
+        };
+        match result {
+            Ok(bytes) => {
+                let read = i64::try_from(bytes.len()).unwrap();
+                this.write_bytes_ptr(buf, bytes)?;
+                Ok(read)
+            }
+            Err(e) => {
+                this.set_last_error_from_io_error(e.kind())?;
+                Ok(-1)
             }
-        } else {
-            trace!("read: FD not found");
-            this.handle_not_found()
         }
     }
 
-    fn write(&mut self, fd: i32, buf: Pointer<Option<Tag>>, count: u64) -> InterpResult<'tcx, i64> {
+    /// Implements `pwrite`: like `write`, but at `offset` and without changing the file offset.
+    fn pwrite(
+        &mut self,
+        fd: i32,
+        buf: Pointer<Option<Tag>>,
+        count: u64,
+        offset: i64,
+    ) -> InterpResult<'tcx, i64> {
         let this = self.eval_context_mut();
 
         // Isolation check is done via `FileDescriptor` trait.
@@ -735,19 +2377,30 @@ pub trait EvalContextExt<'mir, 'tcx: 'mir>: crate::MiriEvalContextExt<'mir, 'tcx
             CheckInAllocMsg::MemoryAccessTest,
         )?;
 
-        // We cap the number of written bytes to the largest value that we are able to fit in both the
-        // host's and target's `isize`. This saves us from having to handle overflows later.
+        // We cap the number of written bytes like `write` does.
         let count = count.min(this.machine_isize_max() as u64).min(isize::MAX as u64);
+        let offset = match u64::try_from(offset) {
+            Ok(offset) => offset,
+            Err(_) => {
+                let einval = this.eval_libc("EINVAL")?;
+                this.set_last_error(einval)?;
+                return Ok(-1);
+            }
+        };
         let communicate = this.machine.communicate();
+        let o_rdonly = this.eval_libc_i32("O_RDONLY")?;
 
-        if let Some(file_descriptor) = this.machine.file_handler.handles.get(&fd) {
-            let bytes = this.read_bytes_ptr(buf, Size::from_bytes(count))?;
-            let result =
-                file_descriptor.write(communicate, bytes)?.map(|c| i64::try_from(c).unwrap());
-            this.try_unwrap_io_result(result)
-        } else {
-            this.handle_not_found()
-        }
+        let bytes = this.read_bytes_ptr(buf, Size::from_bytes(count))?;
+        let result = match this.machine.file_handler.handles.get(&fd) {
+            Some(OpenFd { status_flags, .. }) if status_flags.get() & 0b11 == o_rdonly =>
+                return this.handle_not_found(),
+            Some(OpenFd { file_descriptor, .. }) =>
+                file_descriptor
+                    .pwrite(communicate, bytes, offset)?
+                    .map(|c| i64::try_from(c).unwrap()),
+            None => return this.handle_not_found(),
+        };
+        this.try_unwrap_io_result(result)
     }
 
     fn lseek64(
@@ -777,7 +2430,8 @@ pub trait EvalContextExt<'mir, 'tcx: 'mir>: crate::MiriEvalContextExt<'mir, 'tcx
         };
 
         let communicate = this.machine.communicate();
-        if let Some(file_descriptor) = this.machine.file_handler.handles.get_mut(&fd) {
+        if let Some(OpenFd { file_descriptor, .. }) = this.machine.file_handler.handles.get_mut(&fd)
+        {
             let result = file_descriptor
                 .seek(communicate, seek_from)?
                 .map(|offset| i64::try_from(offset).unwrap());
@@ -789,18 +2443,27 @@ pub trait EvalContextExt<'mir, 'tcx: 'mir>: crate::MiriEvalContextExt<'mir, 'tcx
 
     fn unlink(&mut self, path_op: &OpTy<'tcx, Tag>) -> InterpResult<'tcx, i32> {
         let this = self.eval_context_mut();
+        let at_fdcwd = this.eval_libc_i32("AT_FDCWD")?;
+        this.unlink_at("unlink", at_fdcwd, path_op, /* dir */ false)
+    }
 
-        let path = this.read_path_from_c_str(this.read_pointer(path_op)?)?;
+    fn unlinkat(
+        &mut self,
+        dirfd_op: &OpTy<'tcx, Tag>,
+        path_op: &OpTy<'tcx, Tag>,
+        flags_op: &OpTy<'tcx, Tag>,
+    ) -> InterpResult<'tcx, i32> {
+        let this = self.eval_context_mut();
 
-        // Reject if isolation is enabled.
-        if let IsolatedOp::Reject(reject_with) = this.machine.isolated_op {
-            this.reject_in_isolation("`unlink`", reject_with)?;
-            this.set_last_error_from_io_error(ErrorKind::PermissionDenied)?;
+        let dirfd = this.read_scalar(dirfd_op)?.to_i32()?;
+        let flags = this.read_scalar(flags_op)?.to_i32()?;
+        let at_removedir = this.eval_libc_i32("AT_REMOVEDIR")?;
+        if flags & !at_removedir != 0 {
+            let einval = this.eval_libc("EINVAL")?;
+            this.set_last_error(einval)?;
             return Ok(-1);
         }
-
-        let result = remove_file(path).map(|_| 0);
-        this.try_unwrap_io_result(result)
+        this.unlink_at("unlinkat", dirfd, path_op, /* dir */ flags == at_removedir)
     }
 
     fn symlink(
@@ -808,97 +2471,51 @@ pub trait EvalContextExt<'mir, 'tcx: 'mir>: crate::MiriEvalContextExt<'mir, 'tcx
         target_op: &OpTy<'tcx, Tag>,
         linkpath_op: &OpTy<'tcx, Tag>,
     ) -> InterpResult<'tcx, i32> {
-        #[cfg(unix)]
-        fn create_link(src: &Path, dst: &Path) -> std::io::Result<()> {
-            std::os::unix::fs::symlink(src, dst)
-        }
-
-        #[cfg(windows)]
-        fn create_link(src: &Path, dst: &Path) -> std::io::Result<()> {
-            use std::os::windows::fs;
-            if src.is_dir() { fs::symlink_dir(src, dst) } else { fs::symlink_file(src, dst) }
-        }
-
         let this = self.eval_context_mut();
-        let target = this.read_path_from_c_str(this.read_pointer(target_op)?)?;
-        let linkpath = this.read_path_from_c_str(this.read_pointer(linkpath_op)?)?;
-
-        // Reject if isolation is enabled.
-        if let IsolatedOp::Reject(reject_with) = this.machine.isolated_op {
-            this.reject_in_isolation("`symlink`", reject_with)?;
-            this.set_last_error_from_io_error(ErrorKind::PermissionDenied)?;
-            return Ok(-1);
-        }
+        let at_fdcwd = this.eval_libc_i32("AT_FDCWD")?;
+        this.symlink_at("symlink", target_op, at_fdcwd, linkpath_op)
+    }
 
-        let result = create_link(&target, &linkpath).map(|_| 0);
-        this.try_unwrap_io_result(result)
+    fn symlinkat(
+        &mut self,
+        target_op: &OpTy<'tcx, Tag>,
+        newdirfd_op: &OpTy<'tcx, Tag>,
+        linkpath_op: &OpTy<'tcx, Tag>,
+    ) -> InterpResult<'tcx, i32> {
+        let this = self.eval_context_mut();
+        let newdirfd = this.read_scalar(newdirfd_op)?.to_i32()?;
+        this.symlink_at("symlinkat", target_op, newdirfd, linkpath_op)
     }
 
-    fn macos_stat(
+    fn stat(
         &mut self,
         path_op: &OpTy<'tcx, Tag>,
         buf_op: &OpTy<'tcx, Tag>,
     ) -> InterpResult<'tcx, i32> {
         let this = self.eval_context_mut();
-        this.assert_target_os("macos", "stat");
-
-        let path_scalar = this.read_pointer(path_op)?;
-        let path = this.read_path_from_c_str(path_scalar)?.into_owned();
-
-        // Reject if isolation is enabled.
-        if let IsolatedOp::Reject(reject_with) = this.machine.isolated_op {
-            this.reject_in_isolation("`stat`", reject_with)?;
-            let eacc = this.eval_libc("EACCES")?;
-            this.set_last_error(eacc)?;
-            return Ok(-1);
-        }
-
+        let at_fdcwd = this.eval_libc_i32("AT_FDCWD")?;
         // `stat` always follows symlinks.
-        let metadata = match FileMetadata::from_path(this, &path, true)? {
-            Some(metadata) => metadata,
-            None => return Ok(-1),
-        };
-
-        this.macos_stat_write_buf(metadata, buf_op)
+        this.stat_at("stat", at_fdcwd, path_op, buf_op, true, false)
     }
 
     // `lstat` is used to get symlink metadata.
-    fn macos_lstat(
+    fn lstat(
         &mut self,
         path_op: &OpTy<'tcx, Tag>,
         buf_op: &OpTy<'tcx, Tag>,
     ) -> InterpResult<'tcx, i32> {
         let this = self.eval_context_mut();
-        this.assert_target_os("macos", "lstat");
-
-        let path_scalar = this.read_pointer(path_op)?;
-        let path = this.read_path_from_c_str(path_scalar)?.into_owned();
-
-        // Reject if isolation is enabled.
-        if let IsolatedOp::Reject(reject_with) = this.machine.isolated_op {
-            this.reject_in_isolation("`lstat`", reject_with)?;
-            let eacc = this.eval_libc("EACCES")?;
-            this.set_last_error(eacc)?;
-            return Ok(-1);
-        }
-
-        let metadata = match FileMetadata::from_path(this, &path, false)? {
-            Some(metadata) => metadata,
-            None => return Ok(-1),
-        };
-
-        this.macos_stat_write_buf(metadata, buf_op)
+        let at_fdcwd = this.eval_libc_i32("AT_FDCWD")?;
+        this.stat_at("lstat", at_fdcwd, path_op, buf_op, false, false)
     }
 
-    fn macos_fstat(
+    fn fstat(
         &mut self,
         fd_op: &OpTy<'tcx, Tag>,
         buf_op: &OpTy<'tcx, Tag>,
     ) -> InterpResult<'tcx, i32> {
         let this = self.eval_context_mut();
 
-        this.assert_target_os("macos", "fstat");
-
         let fd = this.read_scalar(fd_op)?.to_i32()?;
 
         // Reject if isolation is enabled.
@@ -912,7 +2529,36 @@ pub trait EvalContextExt<'mir, 'tcx: 'mir>: crate::MiriEvalContextExt<'mir, 'tcx
             Some(metadata) => metadata,
             None => return Ok(-1),
         };
-        this.macos_stat_write_buf(metadata, buf_op)
+        this.stat_write_buf(metadata, buf_op)
+    }
+
+    fn fstatat(
+        &mut self,
+        dirfd_op: &OpTy<'tcx, Tag>,
+        path_op: &OpTy<'tcx, Tag>,
+        buf_op: &OpTy<'tcx, Tag>,
+        flags_op: &OpTy<'tcx, Tag>,
+    ) -> InterpResult<'tcx, i32> {
+        let this = self.eval_context_mut();
+
+        let dirfd = this.read_scalar(dirfd_op)?.to_i32()?;
+        let flags = this.read_scalar(flags_op)?.to_i32()?;
+
+        let at_symlink_nofollow = this.eval_libc_i32("AT_SYMLINK_NOFOLLOW")?;
+        // `AT_EMPTY_PATH` only exists on Linux.
+        let at_empty_path = if this.tcx.sess.target.os == "linux" {
+            this.eval_libc_i32("AT_EMPTY_PATH")?
+        } else {
+            0
+        };
+        if flags & !(at_symlink_nofollow | at_empty_path) != 0 {
+            let einval = this.eval_libc("EINVAL")?;
+            this.set_last_error(einval)?;
+            return Ok(-1);
+        }
+        let follow_symlink = flags & at_symlink_nofollow == 0;
+        let empty_path = flags & at_empty_path != 0;
+        this.stat_at("fstatat", dirfd, path_op, buf_op, follow_symlink, empty_path)
     }
 
     fn linux_statx(
@@ -930,7 +2576,7 @@ pub trait EvalContextExt<'mir, 'tcx: 'mir>: crate::MiriEvalContextExt<'mir, 'tcx
         let dirfd = this.read_scalar(dirfd_op)?.to_i32()?;
         let pathname_ptr = this.read_pointer(pathname_op)?;
         let flags = this.read_scalar(flags_op)?.to_i32()?;
-        let _mask = this.read_scalar(mask_op)?.to_u32()?;
+        let requested_mask = this.read_scalar(mask_op)?.to_u32()?;
         let statxbuf_ptr = this.read_pointer(statxbuf_op)?;
 
         // If the statxbuf or pathname pointers are null, the function fails with `EFAULT`.
@@ -940,6 +2586,18 @@ pub trait EvalContextExt<'mir, 'tcx: 'mir>: crate::MiriEvalContextExt<'mir, 'tcx
             return Ok(-1);
         }
 
+        // The reserved bit of the mask, and flags with an invalid synchronization type, are
+        // rejected with `EINVAL`. The synchronization types themselves make no difference for
+        // local files.
+        let sync_type = this.eval_libc_i32("AT_STATX_SYNC_TYPE")?;
+        if requested_mask & this.eval_libc("STATX__RESERVED")?.to_u32()? != 0
+            || flags & sync_type == sync_type
+        {
+            let einval = this.eval_libc("EINVAL")?;
+            this.set_last_error(einval)?;
+            return Ok(-1);
+        }
+
         // Under normal circumstances, we would use `deref_operand(statxbuf_op)` to produce a
         // proper `MemPlace` and then write the results of this function to it. However, the
         // `syscall` function is untyped. This means that all the `statx` parameters are provided
@@ -949,7 +2607,7 @@ pub trait EvalContextExt<'mir, 'tcx: 'mir>: crate::MiriEvalContextExt<'mir, 'tcx
             // FIXME: This long path is required because `libc::statx` is an struct and also a
             // function and `resolve_path` is returning the latter.
             let statx_ty = this
-                .resolve_path(&["libc", "unix", "linux_like", "linux", "gnu", "statx"])
+                .resolve_path(&["libc", "unix", "linux_like", "linux", "gnu", "statx"])?
                 .ty(*this.tcx, ty::ParamEnv::reveal_all());
             let statx_layout = this.layout_of(statx_ty)?;
             MPlaceTy::from_aligned_ptr(statxbuf_ptr, statx_layout)
@@ -958,148 +2616,143 @@ pub trait EvalContextExt<'mir, 'tcx: 'mir>: crate::MiriEvalContextExt<'mir, 'tcx
         let path = this.read_path_from_c_str(pathname_ptr)?.into_owned();
         // See <https://github.com/rust-lang/rust/pull/79196> for a discussion of argument sizes.
         let empty_path_flag = flags & this.eval_libc("AT_EMPTY_PATH")?.to_i32()? != 0;
-        // We only support:
-        // * interpreting `path` as an absolute directory,
-        // * interpreting `path` as a path relative to `dirfd` when the latter is `AT_FDCWD`, or
-        // * interpreting `dirfd` as any file descriptor when `path` is empty and AT_EMPTY_PATH is
-        // set.
-        // Other behaviors cannot be tested from `libstd` and thus are not implemented. If you
-        // found this error, please open an issue reporting it.
-        if !(path.is_absolute()
-            || dirfd == this.eval_libc_i32("AT_FDCWD")?
-            || (path.as_os_str().is_empty() && empty_path_flag))
-        {
-            throw_unsup_format!(
-                "using statx is only supported with absolute paths, relative paths with the file \
-                descriptor `AT_FDCWD`, and empty paths with the `AT_EMPTY_PATH` flag set and any \
-                file descriptor"
-            )
-        }
+        // If the path is empty and the `AT_EMPTY_PATH` flag is set, we query the open file
+        // represented by `dirfd`, whether it's a directory or otherwise. Otherwise, the path is
+        // relative to `dirfd`.
+        let path = if path.as_os_str().is_empty() && empty_path_flag {
+            None
+        } else {
+            match this.resolve_path_at(dirfd, path)? {
+                Some(path) => Some(path),
+                None => return Ok(-1),
+            }
+        };
 
         // Reject if isolation is enabled.
         if let IsolatedOp::Reject(reject_with) = this.machine.isolated_op {
             this.reject_in_isolation("`statx`", reject_with)?;
-            let ecode = if path.is_absolute() || dirfd == this.eval_libc_i32("AT_FDCWD")? {
-                // since `path` is provided, either absolute or
-                // relative to CWD, `EACCES` is the most relevant.
+            let ecode = if path.is_some() {
+                // since `path` is provided, `EACCES` is the most relevant.
                 this.eval_libc("EACCES")?
             } else {
-                // `dirfd` is set to target file, and `path` is empty
-                // (or we would have hit the `throw_unsup_format`
-                // above). `EACCES` would violate the spec.
-                assert!(empty_path_flag);
+                // `dirfd` is set to target file, and `path` is empty. `EACCES` would violate the
+                // spec.
                 this.eval_libc("EBADF")?
             };
             this.set_last_error(ecode)?;
             return Ok(-1);
         }
 
-        // the `_mask_op` paramter specifies the file information that the caller requested.
-        // However `statx` is allowed to return information that was not requested or to not
-        // return information that was requested. This `mask` represents the information we can
-        // actually provide for any target.
-        let mut mask =
-            this.eval_libc("STATX_TYPE")?.to_u32()? | this.eval_libc("STATX_SIZE")?.to_u32()?;
-
         // If the `AT_SYMLINK_NOFOLLOW` flag is set, we query the file's metadata without following
         // symbolic links.
         let follow_symlink = flags & this.eval_libc("AT_SYMLINK_NOFOLLOW")?.to_i32()? == 0;
 
-        // If the path is empty, and the AT_EMPTY_PATH flag is set, we query the open file
-        // represented by dirfd, whether it's a directory or otherwise.
-        let metadata = if path.as_os_str().is_empty() && empty_path_flag {
-            FileMetadata::from_fd(this, dirfd)?
-        } else {
-            FileMetadata::from_path(this, &path, follow_symlink)?
+        let metadata = match path {
+            Some(path) => FileMetadata::from_path(this, &path, follow_symlink)?,
+            None => FileMetadata::from_fd(this, dirfd)?,
         };
         let metadata = match metadata {
             Some(metadata) => metadata,
             None => return Ok(-1),
         };
 
+        // The `mask` parameter specifies the file information that the caller requested. However
+        // `statx` is allowed to return information that was not requested or to not return
+        // information that was requested, and the `stx_mask` field tells which fields were filled
+        // in. Like Linux does for most file systems, we always fill in the basic fields we know
+        // and only fill in the creation time if it was requested. The other fields are zero.
+        let mut mask =
+            this.eval_libc("STATX_TYPE")?.to_u32()? | this.eval_libc("STATX_SIZE")?.to_u32()?;
+
         // The `mode` field specifies the type of the file and the permissions over the file for
-        // the owner, its group and other users. Given that we can only provide the file type
-        // without using platform specific methods, we only set the bits corresponding to the file
+        // the owner, its group and other users. Without a Unix host, we can only provide the file
         // type. This should be an `__u16` but `libc` provides its values as `u32`.
-        let mode: u16 = metadata
+        let mut mode: u16 = metadata
             .mode
             .to_u32()?
             .try_into()
             .unwrap_or_else(|_| bug!("libc contains bad value for constant"));
 
+        let unix = match metadata.unix {
+            Some(unix) => {
+                mask |= this.eval_libc("STATX_BASIC_STATS")?.to_u32()?
+                    & !this.eval_libc("STATX_ATIME")?.to_u32()?
+                    & !this.eval_libc("STATX_MTIME")?.to_u32()?;
+                mode |= u16::try_from(unix.permissions).unwrap();
+                unix
+            }
+            None => UnixMetadata::ZERO,
+        };
+
         // We need to set the corresponding bits of `mask` if the access, creation and modification
         // times were available. Otherwise we let them be zero.
-        let (access_sec, access_nsec) = metadata
-            .accessed
-            .map(|tup| {
+        let (access_sec, access_nsec) = match metadata.accessed {
+            Some(time) => {
                 mask |= this.eval_libc("STATX_ATIME")?.to_u32()?;
-                InterpResult::Ok(tup)
-            })
-            .unwrap_or(Ok((0, 0)))?;
-
-        let (created_sec, created_nsec) = metadata
-            .created
-            .map(|tup| {
-                mask |= this.eval_libc("STATX_BTIME")?.to_u32()?;
-                InterpResult::Ok(tup)
-            })
-            .unwrap_or(Ok((0, 0)))?;
-
-        let (modified_sec, modified_nsec) = metadata
-            .modified
-            .map(|tup| {
+                time
+            }
+            None => (0, 0),
+        };
+        let btime = this.eval_libc("STATX_BTIME")?.to_u32()?;
+        let (created_sec, created_nsec) = match metadata.created {
+            Some(time) if requested_mask & btime != 0 => {
+                mask |= btime;
+                time
+            }
+            _ => (0, 0),
+        };
+        let (modified_sec, modified_nsec) = match metadata.modified {
+            Some(time) => {
                 mask |= this.eval_libc("STATX_MTIME")?.to_u32()?;
-                InterpResult::Ok(tup)
-            })
-            .unwrap_or(Ok((0, 0)))?;
-
-        // Now we write everything to `statxbuf`. We write a zero for the unavailable fields.
-        this.write_int_fields_named(
+                time
+            }
+            None => (0, 0),
+        };
+        let (changed_sec, changed_nsec) = unix.changed;
+        let (rdev_major, rdev_minor) = split_dev(unix.rdev);
+        let (dev_major, dev_minor) = split_dev(unix.dev);
+
+        // Now we write everything to `statxbuf`. We write a zero for the unavailable fields. We do
+        // not know any attributes, so `stx_attributes_mask` says that all of them are unset. The
+        // 32-bit fields can be too small for the host's values, then `statx` fails with
+        // `EOVERFLOW` like `stat` does.
+        let fits = this.write_int_fields_named_checked(
             &[
                 ("stx_mask", mask.into()),
-                ("stx_blksize", 0),
+                ("stx_blksize", unix.blksize.into()),
                 ("stx_attributes", 0),
-                ("stx_nlink", 0),
-                ("stx_uid", 0),
-                ("stx_gid", 0),
+                ("stx_nlink", unix.nlink.into()),
+                ("stx_uid", unix.uid.into()),
+                ("stx_gid", unix.gid.into()),
                 ("stx_mode", mode.into()),
-                ("stx_ino", 0),
+                ("stx_ino", unix.ino.into()),
                 ("stx_size", metadata.size.into()),
-                ("stx_blocks", 0),
+                ("stx_blocks", unix.blocks.into()),
                 ("stx_attributes_mask", 0),
-                ("stx_rdev_major", 0),
-                ("stx_rdev_minor", 0),
-                ("stx_dev_major", 0),
-                ("stx_dev_minor", 0),
+                ("stx_rdev_major", rdev_major.into()),
+                ("stx_rdev_minor", rdev_minor.into()),
+                ("stx_dev_major", dev_major.into()),
+                ("stx_dev_minor", dev_minor.into()),
             ],
             &statxbuf,
         )?;
-        this.write_int_fields(
-            &[
-                access_sec.into(),  // stx_atime.tv_sec
-                access_nsec.into(), // stx_atime.tv_nsec
-            ],
+        if !fits {
+            return this.fail_with_errno("EOVERFLOW");
+        }
+        this.write_int_fields_named(
+            &[("tv_sec", access_sec.into()), ("tv_nsec", access_nsec.into())],
             &this.mplace_field_named(&statxbuf, "stx_atime")?,
         )?;
-        this.write_int_fields(
-            &[
-                created_sec.into(),  // stx_btime.tv_sec
-                created_nsec.into(), // stx_btime.tv_nsec
-            ],
+        this.write_int_fields_named(
+            &[("tv_sec", created_sec.into()), ("tv_nsec", created_nsec.into())],
             &this.mplace_field_named(&statxbuf, "stx_btime")?,
         )?;
-        this.write_int_fields(
-            &[
-                0.into(), // stx_ctime.tv_sec
-                0.into(), // stx_ctime.tv_nsec
-            ],
+        this.write_int_fields_named(
+            &[("tv_sec", changed_sec.into()), ("tv_nsec", changed_nsec.into())],
             &this.mplace_field_named(&statxbuf, "stx_ctime")?,
         )?;
-        this.write_int_fields(
-            &[
-                modified_sec.into(),  // stx_mtime.tv_sec
-                modified_nsec.into(), // stx_mtime.tv_nsec
-            ],
+        this.write_int_fields_named(
+            &[("tv_sec", modified_sec.into()), ("tv_nsec", modified_nsec.into())],
             &this.mplace_field_named(&statxbuf, "stx_mtime")?,
         )?;
 
@@ -1112,85 +2765,48 @@ pub trait EvalContextExt<'mir, 'tcx: 'mir>: crate::MiriEvalContextExt<'mir, 'tcx
         newpath_op: &OpTy<'tcx, Tag>,
     ) -> InterpResult<'tcx, i32> {
         let this = self.eval_context_mut();
-
-        let oldpath_ptr = this.read_pointer(oldpath_op)?;
-        let newpath_ptr = this.read_pointer(newpath_op)?;
-
-        if this.ptr_is_null(oldpath_ptr)? || this.ptr_is_null(newpath_ptr)? {
-            let efault = this.eval_libc("EFAULT")?;
-            this.set_last_error(efault)?;
-            return Ok(-1);
-        }
-
-        let oldpath = this.read_path_from_c_str(oldpath_ptr)?;
-        let newpath = this.read_path_from_c_str(newpath_ptr)?;
-
-        // Reject if isolation is enabled.
-        if let IsolatedOp::Reject(reject_with) = this.machine.isolated_op {
-            this.reject_in_isolation("`rename`", reject_with)?;
-            this.set_last_error_from_io_error(ErrorKind::PermissionDenied)?;
-            return Ok(-1);
-        }
-
-        let result = rename(oldpath, newpath).map(|_| 0);
-
-        this.try_unwrap_io_result(result)
-    }
-
-    fn mkdir(
-        &mut self,
-        path_op: &OpTy<'tcx, Tag>,
-        mode_op: &OpTy<'tcx, Tag>,
-    ) -> InterpResult<'tcx, i32> {
-        let this = self.eval_context_mut();
-
-        #[cfg_attr(not(unix), allow(unused_variables))]
-        let mode = if this.tcx.sess.target.os == "macos" {
-            u32::from(this.read_scalar(mode_op)?.to_u16()?)
-        } else {
-            this.read_scalar(mode_op)?.to_u32()?
-        };
-
-        let path = this.read_path_from_c_str(this.read_pointer(path_op)?)?;
-
-        // Reject if isolation is enabled.
-        if let IsolatedOp::Reject(reject_with) = this.machine.isolated_op {
-            this.reject_in_isolation("`mkdir`", reject_with)?;
-            this.set_last_error_from_io_error(ErrorKind::PermissionDenied)?;
-            return Ok(-1);
-        }
-
-        #[cfg_attr(not(unix), allow(unused_mut))]
-        let mut builder = DirBuilder::new();
-
-        // If the host supports it, forward on the mode of the directory
-        // (i.e. permission bits and the sticky bit)
-        #[cfg(unix)]
-        {
-            use std::os::unix::fs::DirBuilderExt;
-            builder.mode(mode);
-        }
-
-        let result = builder.create(path).map(|_| 0i32);
-
-        this.try_unwrap_io_result(result)
+        let at_fdcwd = this.eval_libc_i32("AT_FDCWD")?;
+        this.rename_at("rename", at_fdcwd, oldpath_op, at_fdcwd, newpath_op)
     }
 
-    fn rmdir(&mut self, path_op: &OpTy<'tcx, Tag>) -> InterpResult<'tcx, i32> {
+    fn renameat(
+        &mut self,
+        olddirfd_op: &OpTy<'tcx, Tag>,
+        oldpath_op: &OpTy<'tcx, Tag>,
+        newdirfd_op: &OpTy<'tcx, Tag>,
+        newpath_op: &OpTy<'tcx, Tag>,
+    ) -> InterpResult<'tcx, i32> {
         let this = self.eval_context_mut();
+        let olddirfd = this.read_scalar(olddirfd_op)?.to_i32()?;
+        let newdirfd = this.read_scalar(newdirfd_op)?.to_i32()?;
+        this.rename_at("renameat", olddirfd, oldpath_op, newdirfd, newpath_op)
+    }
 
-        let path = this.read_path_from_c_str(this.read_pointer(path_op)?)?;
-
-        // Reject if isolation is enabled.
-        if let IsolatedOp::Reject(reject_with) = this.machine.isolated_op {
-            this.reject_in_isolation("`rmdir`", reject_with)?;
-            this.set_last_error_from_io_error(ErrorKind::PermissionDenied)?;
-            return Ok(-1);
-        }
+    fn mkdir(
+        &mut self,
+        path_op: &OpTy<'tcx, Tag>,
+        mode_op: &OpTy<'tcx, Tag>,
+    ) -> InterpResult<'tcx, i32> {
+        let this = self.eval_context_mut();
+        let at_fdcwd = this.eval_libc_i32("AT_FDCWD")?;
+        this.mkdir_at("mkdir", at_fdcwd, path_op, mode_op)
+    }
 
-        let result = remove_dir(path).map(|_| 0i32);
+    fn mkdirat(
+        &mut self,
+        dirfd_op: &OpTy<'tcx, Tag>,
+        path_op: &OpTy<'tcx, Tag>,
+        mode_op: &OpTy<'tcx, Tag>,
+    ) -> InterpResult<'tcx, i32> {
+        let this = self.eval_context_mut();
+        let dirfd = this.read_scalar(dirfd_op)?.to_i32()?;
+        this.mkdir_at("mkdirat", dirfd, path_op, mode_op)
+    }
 
-        this.try_unwrap_io_result(result)
+    fn rmdir(&mut self, path_op: &OpTy<'tcx, Tag>) -> InterpResult<'tcx, i32> {
+        let this = self.eval_context_mut();
+        let at_fdcwd = this.eval_libc_i32("AT_FDCWD")?;
+        this.unlink_at("rmdir", at_fdcwd, path_op, /* dir */ true)
     }
 
     fn opendir(&mut self, name_op: &OpTy<'tcx, Tag>) -> InterpResult<'tcx, Scalar<Tag>> {
@@ -1211,6 +2827,13 @@ pub trait EvalContextExt<'mir, 'tcx: 'mir>: crate::MiriEvalContextExt<'mir, 'tcx
         match result {
             Ok(dir_iter) => {
                 let id = this.machine.dir_handler.insert_new(dir_iter);
+                // On targets with small pointers, the IDs can run out.
+                if id > this.machine_usize_max() {
+                    this.machine.dir_handler.streams.remove(&id);
+                    let emfile = this.eval_libc("EMFILE")?;
+                    this.set_last_error(emfile)?;
+                    return Ok(Scalar::null_ptr(this));
+                }
 
                 // The libc API for opendir says that this method returns a pointer to an opaque
                 // structure, but we are returning an ID number. Thus, pass it as a scalar of
@@ -1264,7 +2887,7 @@ pub trait EvalContextExt<'mir, 'tcx: 'mir>: crate::MiriEvalContextExt<'mir, 'tcx
                 let name_len = u64::try_from(name_bytes.len()).unwrap();
 
                 let dirent64_layout = this.libc_ty_layout("dirent64")?;
-                let d_name_offset = dirent64_layout.fields.offset(4 /* d_name */).bytes();
+                let d_name_offset = this.field_offset_named(dirent64_layout, "d_name").bytes();
                 let size = d_name_offset.checked_add(name_len).unwrap();
 
                 let entry =
@@ -1279,12 +2902,12 @@ pub trait EvalContextExt<'mir, 'tcx: 'mir>: crate::MiriEvalContextExt<'mir, 'tcx
 
                 let file_type = this.file_type_to_d_type(dir_entry.file_type())?;
 
-                this.write_int_fields(
+                this.write_int_fields_named(
                     &[
-                        ino.into(),       // d_ino
-                        0,                // d_off
-                        size.into(),      // d_reclen
-                        file_type.into(), // d_type
+                        ("d_ino", ino.into()),
+                        ("d_off", 0),
+                        ("d_reclen", size.into()),
+                        ("d_type", file_type.into()),
                     ],
                     &MPlaceTy::from_aligned_ptr(entry, dirent64_layout),
                 )?;
@@ -1375,13 +2998,13 @@ pub trait EvalContextExt<'mir, 'tcx: 'mir>: crate::MiriEvalContextExt<'mir, 'tcx
 
                 let file_type = this.file_type_to_d_type(dir_entry.file_type())?;
 
-                this.write_int_fields(
+                this.write_int_fields_named(
                     &[
-                        ino.into(),           // d_ino
-                        0,                    // d_seekoff
-                        0,                    // d_reclen
-                        file_name_len.into(), // d_namlen
-                        file_type.into(),     // d_type
+                        ("d_ino", ino.into()),
+                        ("d_seekoff", 0),
+                        ("d_reclen", 0),
+                        ("d_namlen", file_name_len.into()),
+                        ("d_type", file_type.into()),
                     ],
                     &entry_place,
                 )?;
@@ -1448,9 +3071,10 @@ pub trait EvalContextExt<'mir, 'tcx: 'mir>: crate::MiriEvalContextExt<'mir, 'tcx
             return this.handle_not_found();
         }
 
-        if let Some(file_descriptor) = this.machine.file_handler.handles.get_mut(&fd) {
+        if let Some(OpenFd { file_descriptor, .. }) = this.machine.file_handler.handles.get_mut(&fd)
+        {
             // FIXME: Support ftruncate64 for all FDs
-            let FileHandle { file, writable } = file_descriptor.as_file_handle()?;
+            let FileHandle { file, writable, .. } = file_descriptor.as_file_handle()?;
             if *writable {
                 if let Ok(length) = length.try_into() {
                     let result = file.set_len(length);
@@ -1488,9 +3112,9 @@ pub trait EvalContextExt<'mir, 'tcx: 'mir>: crate::MiriEvalContextExt<'mir, 'tcx
             return this.handle_not_found();
         }
 
-        if let Some(file_descriptor) = this.machine.file_handler.handles.get(&fd) {
+        if let Some(OpenFd { file_descriptor, .. }) = this.machine.file_handler.handles.get(&fd) {
             // FIXME: Support fsync for all FDs
-            let FileHandle { file, writable } = file_descriptor.as_file_handle()?;
+            let FileHandle { file, writable, .. } = file_descriptor.as_file_handle()?;
             let io_result = maybe_sync_file(file, *writable, File::sync_all);
             this.try_unwrap_io_result(io_result)
         } else {
@@ -1510,9 +3134,9 @@ pub trait EvalContextExt<'mir, 'tcx: 'mir>: crate::MiriEvalContextExt<'mir, 'tcx
             return this.handle_not_found();
         }
 
-        if let Some(file_descriptor) = this.machine.file_handler.handles.get(&fd) {
+        if let Some(OpenFd { file_descriptor, .. }) = this.machine.file_handler.handles.get(&fd) {
             // FIXME: Support fdatasync for all FDs
-            let FileHandle { file, writable } = file_descriptor.as_file_handle()?;
+            let FileHandle { file, writable, .. } = file_descriptor.as_file_handle()?;
             let io_result = maybe_sync_file(file, *writable, File::sync_data);
             this.try_unwrap_io_result(io_result)
         } else {
@@ -1555,9 +3179,9 @@ pub trait EvalContextExt<'mir, 'tcx: 'mir>: crate::MiriEvalContextExt<'mir, 'tcx
             return this.handle_not_found();
         }
 
-        if let Some(file_descriptor) = this.machine.file_handler.handles.get(&fd) {
+        if let Some(OpenFd { file_descriptor, .. }) = this.machine.file_handler.handles.get(&fd) {
             // FIXME: Support sync_data_range for all FDs
-            let FileHandle { file, writable } = file_descriptor.as_file_handle()?;
+            let FileHandle { file, writable, .. } = file_descriptor.as_file_handle()?;
             let io_result = maybe_sync_file(file, *writable, File::sync_data);
             this.try_unwrap_io_result(io_result)
         } else {
@@ -1565,6 +3189,198 @@ pub trait EvalContextExt<'mir, 'tcx: 'mir>: crate::MiriEvalContextExt<'mir, 'tcx
         }
     }
 
+    fn eventfd(
+        &mut self,
+        initval_op: &OpTy<'tcx, Tag>,
+        flags_op: &OpTy<'tcx, Tag>,
+    ) -> InterpResult<'tcx, i32> {
+        let this = self.eval_context_mut();
+
+        let initval = this.read_scalar(initval_op)?.to_u32()?;
+        let flags = this.read_scalar(flags_op)?.to_i32()?;
+
+        let efd_cloexec = this.eval_libc_i32("EFD_CLOEXEC")?;
+        let efd_nonblock = this.eval_libc_i32("EFD_NONBLOCK")?;
+        let efd_semaphore = this.eval_libc_i32("EFD_SEMAPHORE")?;
+        if flags & !(efd_cloexec | efd_nonblock | efd_semaphore) != 0 {
+            let einval = this.eval_libc("EINVAL")?;
+            this.set_last_error(einval)?;
+            return Ok(-1);
+        }
+        let mut status_flags = this.eval_libc_i32("O_RDWR")?;
+        if flags & efd_nonblock != 0 {
+            status_flags |= this.eval_libc_i32("O_NONBLOCK")?;
+        }
+        let fd_flags = if flags & efd_cloexec != 0 { this.eval_libc_i32("FD_CLOEXEC")? } else { 0 };
+
+        let event_fd = EventFd {
+            counter: Rc::new(Cell::new(initval.into())),
+            semaphore: flags & efd_semaphore != 0,
+            endian: this.tcx.sess.target.endian,
+            clock: Default::default(),
+        };
+        Ok(this.machine.file_handler.insert_fd(Box::new(event_fd), status_flags, fd_flags))
+    }
+
+    fn epoll_create1(&mut self, flags_op: &OpTy<'tcx, Tag>) -> InterpResult<'tcx, i32> {
+        let this = self.eval_context_mut();
+
+        let flags = this.read_scalar(flags_op)?.to_i32()?;
+
+        let epoll_cloexec = this.eval_libc_i32("EPOLL_CLOEXEC")?;
+        if flags & !epoll_cloexec != 0 {
+            let einval = this.eval_libc("EINVAL")?;
+            this.set_last_error(einval)?;
+            return Ok(-1);
+        }
+        let fd_flags =
+            if flags & epoll_cloexec != 0 { this.eval_libc_i32("FD_CLOEXEC")? } else { 0 };
+
+        let epoll = Epoll { interest: Default::default() };
+        let o_rdwr = this.eval_libc_i32("O_RDWR")?;
+        Ok(this.machine.file_handler.insert_fd(Box::new(epoll), o_rdwr, fd_flags))
+    }
+
+    fn epoll_ctl(
+        &mut self,
+        epfd_op: &OpTy<'tcx, Tag>,
+        op_op: &OpTy<'tcx, Tag>,
+        fd_op: &OpTy<'tcx, Tag>,
+        event_op: &OpTy<'tcx, Tag>,
+    ) -> InterpResult<'tcx, i32> {
+        let this = self.eval_context_mut();
+
+        let epfd = this.read_scalar(epfd_op)?.to_i32()?;
+        let op = this.read_scalar(op_op)?.to_i32()?;
+        let fd = this.read_scalar(fd_op)?.to_i32()?;
+
+        let epoll_ctl_add = this.eval_libc_i32("EPOLL_CTL_ADD")?;
+        let epoll_ctl_mod = this.eval_libc_i32("EPOLL_CTL_MOD")?;
+        let epoll_ctl_del = this.eval_libc_i32("EPOLL_CTL_DEL")?;
+
+        let handles = &this.machine.file_handler.handles;
+        let (interest, nested) = match (handles.get(&epfd), handles.get(&fd)) {
+            (Some(epoll), Some(target)) =>
+                (
+                    epoll.file_descriptor.as_epoll().map(|epoll| Rc::clone(&epoll.interest)),
+                    target.file_descriptor.as_epoll().is_some(),
+                ),
+            _ => return this.handle_not_found(),
+        };
+        let interest = match interest {
+            Some(interest) if epfd != fd => interest,
+            _ => {
+                let einval = this.eval_libc("EINVAL")?;
+                this.set_last_error(einval)?;
+                return Ok(-1);
+            }
+        };
+        if nested {
+            throw_unsup_format!("adding an epoll instance to another one is not supported");
+        }
+
+        let registered = interest.borrow().contains_key(&fd);
+        if op == epoll_ctl_add || op == epoll_ctl_mod {
+            if op == epoll_ctl_add && registered {
+                let eexist = this.eval_libc("EEXIST")?;
+                this.set_last_error(eexist)?;
+                return Ok(-1);
+            }
+            if op == epoll_ctl_mod && !registered {
+                let enoent = this.eval_libc("ENOENT")?;
+                this.set_last_error(enoent)?;
+                return Ok(-1);
+            }
+            let event = this.deref_operand(event_op)?;
+            let events = this.mplace_field_named(&event, "events")?;
+            let events = this.read_scalar(&events.into())?.to_u32()?;
+            let data = this.mplace_field_named(&event, "u64")?;
+            let data = this.read_scalar(&data.into())?.to_u64()?;
+            interest.borrow_mut().insert(fd, EpollInterest { events, data });
+        } else if op == epoll_ctl_del {
+            if interest.borrow_mut().remove(&fd).is_none() {
+                let enoent = this.eval_libc("ENOENT")?;
+                this.set_last_error(enoent)?;
+                return Ok(-1);
+            }
+        } else {
+            let einval = this.eval_libc("EINVAL")?;
+            this.set_last_error(einval)?;
+            return Ok(-1);
+        }
+
+        // A file descriptor that was just registered may already be ready.
+        this.notify_readiness()?;
+        Ok(0)
+    }
+
+    fn epoll_wait(
+        &mut self,
+        epfd_op: &OpTy<'tcx, Tag>,
+        events_op: &OpTy<'tcx, Tag>,
+        maxevents_op: &OpTy<'tcx, Tag>,
+        timeout_op: &OpTy<'tcx, Tag>,
+        dest: &PlaceTy<'tcx, Tag>,
+    ) -> InterpResult<'tcx> {
+        let this = self.eval_context_mut();
+
+        let epfd = this.read_scalar(epfd_op)?.to_i32()?;
+        let events = this.read_pointer(events_op)?;
+        let maxevents = this.read_scalar(maxevents_op)?.to_i32()?;
+        let timeout = this.read_scalar(timeout_op)?.to_i32()?;
+
+        let is_epoll = match this.machine.file_handler.handles.get(&epfd) {
+            Some(OpenFd { file_descriptor, .. }) => file_descriptor.as_epoll().is_some(),
+            None => {
+                let result = this.handle_not_found()?;
+                return this.write_scalar(Scalar::from_i32(result), dest);
+            }
+        };
+        if !is_epoll || maxevents <= 0 {
+            let einval = this.eval_libc("EINVAL")?;
+            this.set_last_error(einval)?;
+            return this.write_scalar(Scalar::from_i32(-1), dest);
+        }
+        let maxevents = u32::try_from(maxevents).unwrap();
+
+        let active_thread = this.get_active_thread();
+        let ready = this.epoll_ready_events(epfd, maxevents, active_thread)?;
+        if !ready.is_empty() || timeout == 0 {
+            this.epoll_write_events(events, &ready)?;
+            return this.write_scalar(Scalar::from_i32(ready.len().try_into().unwrap()), dest);
+        }
+
+        // Nothing is ready yet, so the thread blocks until `notify_readiness` finds an event for
+        // it.
+        this.block_thread(active_thread);
+        let dest = *dest;
+        this.machine.file_handler.epoll_waiters.push(EpollWaiter {
+            thread: active_thread,
+            epfd,
+            events,
+            maxevents,
+            dest,
+        });
+        // A negative timeout means waiting forever.
+        if let Ok(timeout) = u64::try_from(timeout) {
+            let timeout_time =
+                Time::Monotonic(this.machine.clock.instant_in(Duration::from_millis(timeout)));
+            this.register_timeout_callback(
+                active_thread,
+                timeout_time,
+                Box::new(move |this| {
+                    this.unblock_thread(active_thread);
+                    let waiters = &mut this.machine.file_handler.epoll_waiters;
+                    waiters.retain(|waiter| waiter.thread != active_thread);
+                    // No events were reported before the timeout.
+                    this.write_scalar(Scalar::from_i32(0), &dest)?;
+                    Ok(())
+                }),
+            );
+        }
+        Ok(())
+    }
+
     fn readlink(
         &mut self,
         pathname_op: &OpTy<'tcx, Tag>,
@@ -1572,35 +3388,140 @@ pub trait EvalContextExt<'mir, 'tcx: 'mir>: crate::MiriEvalContextExt<'mir, 'tcx
         bufsize_op: &OpTy<'tcx, Tag>,
     ) -> InterpResult<'tcx, i64> {
         let this = self.eval_context_mut();
+        let at_fdcwd = this.eval_libc_i32("AT_FDCWD")?;
+        this.readlink_at("readlink", at_fdcwd, pathname_op, buf_op, bufsize_op)
+    }
 
-        let pathname = this.read_path_from_c_str(this.read_pointer(pathname_op)?)?;
-        let buf = this.read_pointer(buf_op)?;
-        let bufsize = this.read_scalar(bufsize_op)?.to_machine_usize(this)?;
+    fn readlinkat(
+        &mut self,
+        dirfd_op: &OpTy<'tcx, Tag>,
+        pathname_op: &OpTy<'tcx, Tag>,
+        buf_op: &OpTy<'tcx, Tag>,
+        bufsize_op: &OpTy<'tcx, Tag>,
+    ) -> InterpResult<'tcx, i64> {
+        let this = self.eval_context_mut();
+        let dirfd = this.read_scalar(dirfd_op)?.to_i32()?;
+        this.readlink_at("readlinkat", dirfd, pathname_op, buf_op, bufsize_op)
+    }
 
-        // Reject if isolation is enabled.
-        if let IsolatedOp::Reject(reject_with) = this.machine.isolated_op {
-            this.reject_in_isolation("`readlink`", reject_with)?;
-            let eacc = this.eval_libc("EACCES")?;
-            this.set_last_error(eacc)?;
-            return Ok(-1);
+    fn copy_file_range(
+        &mut self,
+        fd_in_op: &OpTy<'tcx, Tag>,
+        off_in_op: &OpTy<'tcx, Tag>,
+        fd_out_op: &OpTy<'tcx, Tag>,
+        off_out_op: &OpTy<'tcx, Tag>,
+        len_op: &OpTy<'tcx, Tag>,
+        flags_op: &OpTy<'tcx, Tag>,
+    ) -> InterpResult<'tcx, i64> {
+        let this = self.eval_context_mut();
+
+        // Isolation check is done via `FileDescriptor` trait.
+
+        let fd_in = this.read_scalar(fd_in_op)?.to_i32()?;
+        let off_in = this.read_offset_arg(this.read_pointer(off_in_op)?, "loff_t")?;
+        let fd_out = this.read_scalar(fd_out_op)?.to_i32()?;
+        let off_out = this.read_offset_arg(this.read_pointer(off_out_op)?, "loff_t")?;
+        let len = this.read_scalar(len_op)?.to_machine_usize(this)?;
+        let flags = this.read_scalar(flags_op)?.to_u32()?;
+
+        let o_rdonly = this.eval_libc_i32("O_RDONLY")?;
+        let o_wronly = this.eval_libc_i32("O_WRONLY")?;
+        let o_append = this.eval_libc_i32("O_APPEND")?;
+        let ((in_flags, in_is_file), (out_flags, out_is_file)) =
+            match (this.fd_status(fd_in), this.fd_status(fd_out)) {
+                (Some(in_status), Some(out_status)) => (in_status, out_status),
+                _ => return this.handle_not_found(),
+            };
+        // The access mode is in the first two bits on all supported targets, see `open`.
+        if in_flags & 0b11 == o_wronly || out_flags & 0b11 == o_rdonly || out_flags & o_append != 0
+        {
+            return this.handle_not_found();
+        }
+        let off_in_val = off_in.as_ref().map(|&(_, offset)| offset);
+        let off_out_val = off_out.as_ref().map(|&(_, offset)| offset);
+        if flags != 0
+            || !in_is_file
+            || !out_is_file
+            || off_in_val.map_or(false, |offset| offset < 0)
+            || off_out_val.map_or(false, |offset| offset < 0)
+        {
+            return this.fail_with_errno("EINVAL");
+        }
+        // The ranges must not overlap if a file is copied to itself.
+        if let (true, Some(off_in_val), Some(off_out_val)) =
+            (fd_in == fd_out, off_in_val, off_out_val)
+        {
+            let len = i64::try_from(len).unwrap_or(i64::MAX);
+            if off_in_val < off_out_val.saturating_add(len)
+                && off_out_val < off_in_val.saturating_add(len)
+            {
+                return this.fail_with_errno("EINVAL");
+            }
         }
 
-        let result = std::fs::read_link(pathname);
-        match result {
-            Ok(resolved) => {
-                let resolved = this.convert_path_separator(
-                    Cow::Borrowed(resolved.as_ref()),
-                    crate::shims::os_str::PathConversion::HostToTarget,
-                );
-                let mut path_bytes = crate::shims::os_str::os_str_to_bytes(resolved.as_ref())?;
-                let bufsize: usize = bufsize.try_into().unwrap();
-                if path_bytes.len() > bufsize {
-                    path_bytes = &path_bytes[..bufsize]
+        let len = len.min(this.machine_isize_max() as u64);
+        let off_in_val = off_in_val.map(|offset| u64::try_from(offset).unwrap());
+        let off_out_val = off_out_val.map(|offset| u64::try_from(offset).unwrap());
+        match this.copy_between_fds(fd_in, off_in_val, fd_out, off_out_val, len)? {
+            Ok(copied) => {
+                for (place, offset) in off_in.into_iter().chain(off_out) {
+                    let offset = offset + i64::try_from(copied).unwrap();
+                    this.write_scalar(Scalar::from_i64(offset), &place.into())?;
                 }
-                // 'readlink' truncates the resolved path if
-                // the provided buffer is not large enough.
-                this.write_bytes_ptr(buf, path_bytes.iter().copied())?;
-                Ok(path_bytes.len().try_into().unwrap())
+                Ok(i64::try_from(copied).unwrap())
+            }
+            Err(e) => {
+                this.set_last_error_from_io_error(e.kind())?;
+                Ok(-1)
+            }
+        }
+    }
+
+    fn sendfile(
+        &mut self,
+        out_fd_op: &OpTy<'tcx, Tag>,
+        in_fd_op: &OpTy<'tcx, Tag>,
+        offset_op: &OpTy<'tcx, Tag>,
+        count_op: &OpTy<'tcx, Tag>,
+    ) -> InterpResult<'tcx, i64> {
+        let this = self.eval_context_mut();
+
+        // Isolation check is done via `FileDescriptor` trait.
+
+        let out_fd = this.read_scalar(out_fd_op)?.to_i32()?;
+        let in_fd = this.read_scalar(in_fd_op)?.to_i32()?;
+        let offset = this.read_offset_arg(this.read_pointer(offset_op)?, "off_t")?;
+        let count = this.read_scalar(count_op)?.to_machine_usize(this)?;
+
+        let o_rdonly = this.eval_libc_i32("O_RDONLY")?;
+        let o_wronly = this.eval_libc_i32("O_WRONLY")?;
+        let o_append = this.eval_libc_i32("O_APPEND")?;
+        let ((in_flags, in_is_file), (out_flags, _)) =
+            match (this.fd_status(in_fd), this.fd_status(out_fd)) {
+                (Some(in_status), Some(out_status)) => (in_status, out_status),
+                _ => return this.handle_not_found(),
+            };
+        // The access mode is in the first two bits on all supported targets, see `open`.
+        if in_flags & 0b11 == o_wronly || out_flags & 0b11 == o_rdonly {
+            return this.handle_not_found();
+        }
+        // `in_fd` has to support `mmap`, which only regular files do.
+        let offset_val = offset.as_ref().map(|&(_, offset)| offset);
+        if !in_is_file || out_flags & o_append != 0 || offset_val.map_or(false, |offset| offset < 0)
+        {
+            return this.fail_with_errno("EINVAL");
+        }
+
+        let count = count.min(this.machine_isize_max() as u64);
+        let offset_val = offset_val.map(|offset| u64::try_from(offset).unwrap());
+        match this.copy_between_fds(in_fd, offset_val, out_fd, None, count)? {
+            Ok(copied) => {
+                if let Some((place, offset)) = offset {
+                    let offset = offset + i64::try_from(copied).unwrap();
+                    let offset = Scalar::from_int(offset, place.layout.size);
+                    this.write_scalar(offset, &place.into())?;
+                }
+                Ok(i64::try_from(copied).unwrap())
             }
             Err(e) => {
                 this.set_last_error_from_io_error(e.kind())?;
@@ -1608,6 +3529,173 @@ pub trait EvalContextExt<'mir, 'tcx: 'mir>: crate::MiriEvalContextExt<'mir, 'tcx
             }
         }
     }
+
+    fn fallocate(
+        &mut self,
+        fd_op: &OpTy<'tcx, Tag>,
+        mode_op: &OpTy<'tcx, Tag>,
+        offset_op: &OpTy<'tcx, Tag>,
+        len_op: &OpTy<'tcx, Tag>,
+    ) -> InterpResult<'tcx, i32> {
+        let this = self.eval_context_mut();
+
+        // Isolation check is done via `FileDescriptor` trait.
+
+        let fd = this.read_scalar(fd_op)?.to_i32()?;
+        let mode = this.read_scalar(mode_op)?.to_i32()?;
+        let offset = this.read_scalar(offset_op)?.to_machine_isize(this)?;
+        let len = this.read_scalar(len_op)?.to_machine_isize(this)?;
+
+        let o_rdonly = this.eval_libc_i32("O_RDONLY")?;
+        let (flags, is_file) = match this.fd_status(fd) {
+            Some(status) => status,
+            None => return this.handle_not_found(),
+        };
+        // The access mode is in the first two bits on all supported targets, see `open`.
+        if flags & 0b11 == o_rdonly {
+            return this.handle_not_found();
+        }
+        if offset < 0 || len <= 0 {
+            return this.fail_with_errno("EINVAL");
+        }
+        if !is_file {
+            return this.fail_with_errno("ENODEV");
+        }
+        let (start, end) = match offset.checked_add(len) {
+            Some(end) => (u64::try_from(offset).unwrap(), u64::try_from(end).unwrap()),
+            None => return this.fail_with_errno("EFBIG"),
+        };
+
+        // We do not know which blocks of a file are allocated, so allocating them does nothing.
+        // We still have to emulate the effects of the modes on the contents and size of the file.
+        let keep_size = mode & this.eval_libc_i32("FALLOC_FL_KEEP_SIZE")? != 0;
+        let mode = mode & !this.eval_libc_i32("FALLOC_FL_KEEP_SIZE")?;
+        let (zero_range, extend) = if mode == 0 {
+            (false, !keep_size)
+        } else if mode == this.eval_libc_i32("FALLOC_FL_PUNCH_HOLE")? && keep_size {
+            (true, false)
+        } else if mode == this.eval_libc_i32("FALLOC_FL_ZERO_RANGE")? {
+            (true, !keep_size)
+        } else {
+            // Like a file system that does not support the mode.
+            return this.fail_with_errno("EOPNOTSUPP");
+        };
+
+        let FileHandle { file, .. } =
+            this.machine.file_handler.handles[&fd].file_descriptor.as_file_handle()?;
+        let result = (|| {
+            let size = file.metadata()?.len();
+            if zero_range && start < size {
+                write_zeros(file, start, end.min(size))?;
+            }
+            // The new part of the file is zero.
+            if extend && end > size {
+                file.set_len(end)?;
+            }
+            Ok(0)
+        })();
+        this.try_unwrap_io_result(result)
+    }
+
+    /// Reads up to `len` bytes at `offset` of the file `fd` for a file-backed mapping, without
+    /// changing the file offset. Returns `None` and sets the last error if the file cannot be
+    /// mapped.
+    fn read_file_for_mmap(
+        &mut self,
+        fd: i32,
+        offset: u64,
+        len: u64,
+    ) -> InterpResult<'tcx, Option<Vec<u8>>> {
+        let this = self.eval_context_mut();
+
+        let o_wronly = this.eval_libc_i32("O_WRONLY")?;
+        let result = match this.machine.file_handler.handles.get(&fd) {
+            None => Err("EBADF"),
+            // The access mode is in the first two bits on all supported targets, see `open`.
+            Some(OpenFd { status_flags, .. }) if status_flags.get() & 0b11 == o_wronly =>
+                Err("EACCES"),
+            Some(OpenFd { file_descriptor, .. }) => {
+                let FileHandle { file, .. } = file_descriptor.as_file_handle()?;
+                Ok(read_at(file, offset, len))
+            }
+        };
+        match result {
+            Ok(Ok(bytes)) => Ok(Some(bytes)),
+            Ok(Err(e)) => {
+                this.set_last_error_from_io_error(e.kind())?;
+                Ok(None)
+            }
+            Err(errno) => {
+                let errno = this.eval_libc(errno)?;
+                this.set_last_error(errno)?;
+                Ok(None)
+            }
+        }
+    }
+}
+
+/// The largest chunk that `copy_file_range`, `sendfile`, and `fallocate` read or write at once.
+const COPY_CHUNK_SIZE: u64 = 64 * 1024;
+
+/// Reads up to `len` bytes from `file`: at `offset` without changing the file offset if it is
+/// given, and at the file offset otherwise.
+fn read_chunk(file: &File, offset: Option<u64>, len: u64) -> io::Result<Vec<u8>> {
+    match offset {
+        Some(offset) => read_at(file, offset, len),
+        None => {
+            let mut bytes = Vec::new();
+            file.take(len).read_to_end(&mut bytes)?;
+            Ok(bytes)
+        }
+    }
+}
+
+/// Writes `bytes` to `file` at `offset`, and restores the file offset afterwards.
+fn write_at(mut file: &File, offset: u64, bytes: &[u8]) -> io::Result<()> {
+    let pos = file.stream_position()?;
+    file.seek(SeekFrom::Start(offset))?;
+    let result = file.write_all(bytes);
+    file.seek(SeekFrom::Start(pos))?;
+    result
+}
+
+/// Writes all of `bytes` to `file_descriptor`, which may take several writes.
+fn write_all<'tcx>(
+    file_descriptor: &dyn FileDescriptor,
+    communicate_allowed: bool,
+    mut bytes: &[u8],
+) -> InterpResult<'tcx, io::Result<()>> {
+    while !bytes.is_empty() {
+        match file_descriptor.write(communicate_allowed, bytes)? {
+            Ok(0) => return Ok(Err(ErrorKind::WriteZero.into())),
+            Ok(written) => bytes = &bytes[written..],
+            Err(e) if e.kind() == ErrorKind::Interrupted => {}
+            Err(e) => return Ok(Err(e)),
+        }
+    }
+    Ok(Ok(()))
+}
+
+/// Overwrites the bytes from `start` to `end` of `file` with zeros.
+fn write_zeros(file: &File, start: u64, end: u64) -> io::Result<()> {
+    let zeros = vec![0; usize::try_from(COPY_CHUNK_SIZE.min(end - start)).unwrap()];
+    let mut offset = start;
+    while offset < end {
+        let chunk_len = usize::try_from((end - offset).min(COPY_CHUNK_SIZE)).unwrap();
+        write_at(file, offset, &zeros[..chunk_len])?;
+        offset += u64::try_from(chunk_len).unwrap();
+    }
+    Ok(())
+}
+
+/// Reads up to `len` bytes at `offset` of `file`, and restores the file offset afterwards.
+fn read_at(mut file: &File, offset: u64, len: u64) -> io::Result<Vec<u8>> {
+    let pos = file.stream_position()?;
+    file.seek(SeekFrom::Start(offset))?;
+    let mut bytes = Vec::new();
+    let result = file.take(len).read_to_end(&mut bytes);
+    file.seek(SeekFrom::Start(pos))?;
+    result.map(|_| bytes)
 }
 
 /// Extracts the number of seconds and nanoseconds elapsed between `time` and the unix epoch when
@@ -1627,11 +3715,100 @@ fn extract_sec_and_nsec<'tcx>(
 /// Stores a file's metadata in order to avoid code duplication in the different metadata related
 /// shims.
 struct FileMetadata {
+    /// The file type bits of the mode.
     mode: Scalar<Tag>,
     size: u64,
     created: Option<(u64, u32)>,
     accessed: Option<(u64, u32)>,
     modified: Option<(u64, u32)>,
+    /// The fields that only Unix hosts provide.
+    unix: Option<UnixMetadata>,
+}
+
+/// The metadata of a file on a Unix host, as returned by its `stat`.
+struct UnixMetadata {
+    /// The permission bits of the mode.
+    permissions: u32,
+    dev: u64,
+    ino: u64,
+    nlink: u64,
+    uid: u32,
+    gid: u32,
+    rdev: u64,
+    blksize: u64,
+    blocks: u64,
+    changed: (u64, u32),
+}
+
+impl UnixMetadata {
+    const ZERO: UnixMetadata = UnixMetadata {
+        permissions: 0,
+        dev: 0,
+        ino: 0,
+        nlink: 0,
+        uid: 0,
+        gid: 0,
+        rdev: 0,
+        blksize: 0,
+        blocks: 0,
+        changed: (0, 0),
+    };
+
+    #[cfg(unix)]
+    fn from_meta(metadata: &std::fs::Metadata) -> Option<UnixMetadata> {
+        use std::os::unix::fs::MetadataExt;
+        // The time of the last status change can be before the epoch, like the other times.
+        let changed = match u64::try_from(metadata.ctime()) {
+            Ok(sec) => (sec, u32::try_from(metadata.ctime_nsec()).unwrap()),
+            Err(_) => (0, 0),
+        };
+        Some(UnixMetadata {
+            permissions: metadata.mode() & 0o7777,
+            dev: metadata.dev(),
+            ino: metadata.ino(),
+            nlink: metadata.nlink(),
+            uid: metadata.uid(),
+            gid: metadata.gid(),
+            rdev: metadata.rdev(),
+            blksize: metadata.blksize(),
+            blocks: metadata.blocks(),
+            changed,
+        })
+    }
+
+    #[cfg(not(unix))]
+    fn from_meta(_metadata: &std::fs::Metadata) -> Option<UnixMetadata> {
+        None
+    }
+}
+
+/// The `S_IF*` constant for a file that is neither a regular file, a directory nor a symlink.
+#[cfg(unix)]
+fn special_file_type(file_type: &std::fs::FileType) -> &'static str {
+    use std::os::unix::fs::FileTypeExt;
+    if file_type.is_fifo() {
+        "S_IFIFO"
+    } else if file_type.is_socket() {
+        "S_IFSOCK"
+    } else if file_type.is_block_device() {
+        "S_IFBLK"
+    } else {
+        "S_IFCHR"
+    }
+}
+
+/// Other hosts only have regular files, directories and symlinks.
+#[cfg(not(unix))]
+fn special_file_type(_file_type: &std::fs::FileType) -> &'static str {
+    "S_IFREG"
+}
+
+/// Splits a device number into its major and minor numbers, the way glibc's `major` and `minor`
+/// do.
+fn split_dev(dev: u64) -> (u32, u32) {
+    let major = ((dev >> 8) & 0xfff) | ((dev >> 32) & 0xffff_f000);
+    let minor = (dev & 0xff) | ((dev >> 12) & 0xffff_ff00);
+    (u32::try_from(major).unwrap(), u32::try_from(minor).unwrap())
 }
 
 impl FileMetadata {
@@ -1652,7 +3829,7 @@ impl FileMetadata {
     ) -> InterpResult<'tcx, Option<FileMetadata>> {
         let option = ecx.machine.file_handler.handles.get(&fd);
         let file = match option {
-            Some(file_descriptor) => &file_descriptor.as_file_handle()?.file,
+            Some(OpenFd { file_descriptor, .. }) => &file_descriptor.as_file_handle()?.file,
             None => return ecx.handle_not_found().map(|_: i32| None),
         };
         let metadata = file.metadata();
@@ -1678,8 +3855,10 @@ impl FileMetadata {
             "S_IFREG"
         } else if file_type.is_dir() {
             "S_IFDIR"
-        } else {
+        } else if file_type.is_symlink() {
             "S_IFLNK"
+        } else {
+            special_file_type(&file_type)
         };
 
         let mode = ecx.eval_libc(mode_name)?;
@@ -1690,7 +3869,8 @@ impl FileMetadata {
         let accessed = extract_sec_and_nsec(metadata.accessed())?;
         let modified = extract_sec_and_nsec(metadata.modified())?;
 
-        // FIXME: Provide more fields using platform specific methods.
-        Ok(Some(FileMetadata { mode, size, created, accessed, modified }))
+        let unix = UnixMetadata::from_meta(&metadata);
+
+        Ok(Some(FileMetadata { mode, size, created, accessed, modified, unix }))
     }
 }
@@ -27,7 +27,10 @@ pub trait EvalContextExt<'mir, 'tcx: 'mir>: crate::MiriEvalContextExt<'mir, 'tcx
         ret: Option<(&PlaceTy<'tcx, Tag>, mir::BasicBlock)>,
     ) -> InterpResult<'tcx> {
         let this = self.eval_context_mut();
-        let (_dest, _ret) = ret.expect("we don't support any diverging dlsym");
+        let (_dest, _ret) = match ret {
+            Some(ret) => ret,
+            None => throw_unsup_format!("calling a function obtained with `dlsym` as diverging"),
+        };
         assert!(this.tcx.sess.target.os == "linux");
 
         match dlsym {}
@@ -1,14 +1,36 @@
+use std::ffi::OsStr;
+
 use rustc_middle::mir;
 use rustc_span::Symbol;
 use rustc_target::spec::abi::Abi;
 
 use crate::*;
-use shims::foreign_items::EmulateByNameResult;
+use shims::foreign_items::{EmulateByNameResult, ForeignItemProvider};
 use shims::posix::fs::EvalContextExt as _;
-use shims::posix::linux::sync::futex;
+use shims::posix::linux::sync::{futex, membarrier};
+use shims::posix::mmap::EvalContextExt as _;
+use shims::posix::pipe::EvalContextExt as _;
+use shims::posix::socket::EvalContextExt as _;
 use shims::posix::sync::EvalContextExt as _;
 use shims::posix::thread::EvalContextExt as _;
 
+/// The shims that are available on Linux, in addition to the POSIX ones.
+pub struct LinuxForeignItems;
+
+impl ForeignItemProvider for LinuxForeignItems {
+    fn emulate_foreign_item_by_name<'mir, 'tcx>(
+        &self,
+        ecx: &mut MiriEvalContext<'mir, 'tcx>,
+        link_name: Symbol,
+        abi: Abi,
+        args: &[OpTy<'tcx, Tag>],
+        dest: &PlaceTy<'tcx, Tag>,
+        ret: mir::BasicBlock,
+    ) -> InterpResult<'tcx, EmulateByNameResult<'mir, 'tcx>> {
+        EvalContextExt::emulate_foreign_item_by_name(ecx, link_name, abi, args, dest, ret)
+    }
+}
+
 impl<'mir, 'tcx: 'mir> EvalContextExt<'mir, 'tcx> for crate::MiriEvalContext<'mir, 'tcx> {}
 pub trait EvalContextExt<'mir, 'tcx: 'mir>: crate::MiriEvalContextExt<'mir, 'tcx> {
     fn emulate_foreign_item_by_name(
@@ -29,7 +51,7 @@ pub trait EvalContextExt<'mir, 'tcx: 'mir>: crate::MiriEvalContextExt<'mir, 'tcx
                 this.write_scalar(errno_place.to_ref(this).to_scalar()?, dest)?;
             }
 
-            // File related shims (but also see "syscall" below for statx)
+            // File related shims
             // These symbols have different names on Linux and macOS, which is the only reason they are not
             // in the `posix` module.
             "close" => {
@@ -54,6 +76,12 @@ pub trait EvalContextExt<'mir, 'tcx: 'mir>: crate::MiriEvalContextExt<'mir, 'tcx
                 this.write_scalar(Scalar::from_i32(result), dest)?;
             }
             // Linux-only
+            "statx" => {
+                let [dirfd, pathname, flags, mask, statxbuf] =
+                    this.check_shim(abi, Abi::C { unwind: false }, link_name, args)?;
+                let result = this.linux_statx(dirfd, pathname, flags, mask, statxbuf)?;
+                this.write_scalar(Scalar::from_i32(result), dest)?;
+            }
             "posix_fadvise" => {
                 let [fd, offset, len, advice] =
                     this.check_shim(abi, Abi::C { unwind: false }, link_name, args)?;
@@ -70,6 +98,102 @@ pub trait EvalContextExt<'mir, 'tcx: 'mir>: crate::MiriEvalContextExt<'mir, 'tcx
                 let result = this.sync_file_range(fd, offset, nbytes, flags)?;
                 this.write_scalar(Scalar::from_i32(result), dest)?;
             }
+            "copy_file_range" => {
+                let [fd_in, off_in, fd_out, off_out, len, flags] =
+                    this.check_shim(abi, Abi::C { unwind: false }, link_name, args)?;
+                let result = this.copy_file_range(fd_in, off_in, fd_out, off_out, len, flags)?;
+                this.write_scalar(Scalar::from_machine_isize(result, this), dest)?;
+            }
+            "sendfile" => {
+                let [out_fd, in_fd, offset, count] =
+                    this.check_shim(abi, Abi::C { unwind: false }, link_name, args)?;
+                let result = this.sendfile(out_fd, in_fd, offset, count)?;
+                this.write_scalar(Scalar::from_machine_isize(result, this), dest)?;
+            }
+            "fallocate" => {
+                let [fd, mode, offset, len] =
+                    this.check_shim(abi, Abi::C { unwind: false }, link_name, args)?;
+                let result = this.fallocate(fd, mode, offset, len)?;
+                this.write_scalar(Scalar::from_i32(result), dest)?;
+            }
+            "eventfd" => {
+                let [initval, flags] =
+                    this.check_shim(abi, Abi::C { unwind: false }, link_name, args)?;
+                let result = this.eventfd(initval, flags)?;
+                this.write_scalar(Scalar::from_i32(result), dest)?;
+            }
+            "epoll_create1" => {
+                let [flags] = this.check_shim(abi, Abi::C { unwind: false }, link_name, args)?;
+                let result = this.epoll_create1(flags)?;
+                this.write_scalar(Scalar::from_i32(result), dest)?;
+            }
+            "epoll_ctl" => {
+                let [epfd, op, fd, event] =
+                    this.check_shim(abi, Abi::C { unwind: false }, link_name, args)?;
+                let result = this.epoll_ctl(epfd, op, fd, event)?;
+                this.write_scalar(Scalar::from_i32(result), dest)?;
+            }
+            "epoll_wait" => {
+                let [epfd, events, maxevents, timeout] =
+                    this.check_shim(abi, Abi::C { unwind: false }, link_name, args)?;
+                this.epoll_wait(epfd, events, maxevents, timeout, dest)?;
+            }
+            "pipe2" => {
+                let [fds, flags] =
+                    this.check_shim(abi, Abi::C { unwind: false }, link_name, args)?;
+                let result = this.pipe2(fds, Some(flags))?;
+                this.write_scalar(Scalar::from_i32(result), dest)?;
+            }
+            "dup3" => {
+                let [oldfd, newfd, flags] =
+                    this.check_shim(abi, Abi::C { unwind: false }, link_name, args)?;
+                let result = this.dup3(oldfd, newfd, flags)?;
+                this.write_scalar(Scalar::from_i32(result), dest)?;
+            }
+            "accept4" => {
+                let [fd, addr, addrlen, flags] =
+                    this.check_shim(abi, Abi::C { unwind: false }, link_name, args)?;
+                this.accept4(fd, addr, addrlen, Some(flags), dest)?;
+            }
+
+            // Memory mappings
+            "mmap" => {
+                let [addr, length, prot, flags, fd, offset] =
+                    this.check_shim(abi, Abi::C { unwind: false }, link_name, args)?;
+                let result = this.mmap(addr, length, prot, flags, fd, offset)?;
+                this.write_scalar(result, dest)?;
+            }
+            "munmap" => {
+                let [addr, length] =
+                    this.check_shim(abi, Abi::C { unwind: false }, link_name, args)?;
+                let result = this.munmap(addr, length)?;
+                this.write_scalar(Scalar::from_i32(result), dest)?;
+            }
+            "mremap" => {
+                // `mremap` is variadic. The argument count is checked in `this.mremap()`, so we do
+                // not use `check_shim` here.
+                this.check_abi_and_shim_symbol_clash(abi, Abi::C { unwind: false }, link_name)?;
+                let result = this.mremap(args)?;
+                this.write_scalar(result, dest)?;
+            }
+            "msync" => {
+                let [addr, length, flags] =
+                    this.check_shim(abi, Abi::C { unwind: false }, link_name, args)?;
+                let result = this.msync(addr, length, flags)?;
+                this.write_scalar(Scalar::from_i32(result), dest)?;
+            }
+            "madvise" => {
+                let [addr, length, advice] =
+                    this.check_shim(abi, Abi::C { unwind: false }, link_name, args)?;
+                let result = this.madvise(addr, length, advice)?;
+                this.write_scalar(Scalar::from_i32(result), dest)?;
+            }
+            "mlock" | "munlock" => {
+                let [addr, length] =
+                    this.check_shim(abi, Abi::C { unwind: false }, link_name, args)?;
+                let result = this.mlock(addr, length)?;
+                this.write_scalar(Scalar::from_i32(result), dest)?;
+            }
 
             // Time related shims
             "clock_gettime" => {
@@ -79,6 +203,11 @@ pub trait EvalContextExt<'mir, 'tcx: 'mir>: crate::MiriEvalContextExt<'mir, 'tcx
                 let result = this.clock_gettime(clk_id, tp)?;
                 this.write_scalar(Scalar::from_i32(result), dest)?;
             }
+            "clock_nanosleep" => {
+                let [clk_id, flags, req, rem] =
+                    this.check_shim(abi, Abi::C { unwind: false }, link_name, args)?;
+                this.clock_nanosleep(clk_id, flags, req, rem, dest)?;
+            }
 
             // Querying system information
             "pthread_attr_getstack" => {
@@ -90,11 +219,11 @@ pub trait EvalContextExt<'mir, 'tcx: 'mir>: crate::MiriEvalContextExt<'mir, 'tcx
                 let size_place = this.deref_operand(size_place)?;
 
                 this.write_scalar(
-                    Scalar::from_uint(STACK_ADDR, this.pointer_size()),
+                    Scalar::from_uint(this.machine.stack_addr, this.pointer_size()),
                     &addr_place.into(),
                 )?;
                 this.write_scalar(
-                    Scalar::from_uint(STACK_SIZE, this.pointer_size()),
+                    Scalar::from_uint(this.machine.stack_size, this.pointer_size()),
                     &size_place.into(),
                 )?;
 
@@ -125,60 +254,9 @@ pub trait EvalContextExt<'mir, 'tcx: 'mir>: crate::MiriEvalContextExt<'mir, 'tcx
             // Dynamically invoked syscalls
             "syscall" => {
                 // We do not use `check_shim` here because `syscall` is variadic. The argument
-                // count is checked bellow.
+                // count is checked by `syscall`.
                 this.check_abi_and_shim_symbol_clash(abi, Abi::C { unwind: false }, link_name)?;
-                // The syscall variadic function is legal to call with more arguments than needed,
-                // extra arguments are simply ignored. The important check is that when we use an
-                // argument, we have to also check all arguments *before* it to ensure that they
-                // have the right type.
-
-                let sys_getrandom = this.eval_libc("SYS_getrandom")?.to_machine_usize(this)?;
-
-                let sys_statx = this.eval_libc("SYS_statx")?.to_machine_usize(this)?;
-
-                let sys_futex = this.eval_libc("SYS_futex")?.to_machine_usize(this)?;
-
-                if args.is_empty() {
-                    throw_ub_format!(
-                        "incorrect number of arguments for syscall: got 0, expected at least 1"
-                    );
-                }
-                match this.read_scalar(&args[0])?.to_machine_usize(this)? {
-                    // `libc::syscall(NR_GETRANDOM, buf.as_mut_ptr(), buf.len(), GRND_NONBLOCK)`
-                    // is called if a `HashMap` is created the regular way (e.g. HashMap<K, V>).
-                    id if id == sys_getrandom => {
-                        // The first argument is the syscall id, so skip over it.
-                        if args.len() < 4 {
-                            throw_ub_format!(
-                                "incorrect number of arguments for `getrandom` syscall: got {}, expected at least 4",
-                                args.len()
-                            );
-                        }
-                        getrandom(this, &args[1], &args[2], &args[3], dest)?;
-                    }
-                    // `statx` is used by `libstd` to retrieve metadata information on `linux`
-                    // instead of using `stat`,`lstat` or `fstat` as on `macos`.
-                    id if id == sys_statx => {
-                        // The first argument is the syscall id, so skip over it.
-                        if args.len() < 6 {
-                            throw_ub_format!(
-                                "incorrect number of arguments for `statx` syscall: got {}, expected at least 6",
-                                args.len()
-                            );
-                        }
-                        let result =
-                            this.linux_statx(&args[1], &args[2], &args[3], &args[4], &args[5])?;
-                        this.write_scalar(Scalar::from_machine_isize(result.into(), this), dest)?;
-                    }
-                    // `futex` is used by some synchonization primitives.
-                    id if id == sys_futex => {
-                        futex(this, &args[1..], dest)?;
-                    }
-                    id => {
-                        this.handle_unsupported(format!("can't execute syscall with ID {}", id))?;
-                        return Ok(EmulateByNameResult::AlreadyJumped);
-                    }
-                }
+                return syscall(this, args, dest);
             }
 
             // Miscelanneous
@@ -187,16 +265,52 @@ pub trait EvalContextExt<'mir, 'tcx: 'mir>: crate::MiriEvalContextExt<'mir, 'tcx
                     this.check_shim(abi, Abi::C { unwind: false }, link_name, args)?;
                 getrandom(this, ptr, len, flags, dest)?;
             }
+            "gettid" => {
+                let [] = this.check_shim(abi, Abi::C { unwind: false }, link_name, args)?;
+                let result = this.gettid();
+                this.write_scalar(Scalar::from_i32(result), dest)?;
+            }
             "sched_getaffinity" => {
                 let [pid, cpusetsize, mask] =
                     this.check_shim(abi, Abi::C { unwind: false }, link_name, args)?;
-                this.read_scalar(pid)?.to_i32()?;
-                this.read_scalar(cpusetsize)?.to_machine_usize(this)?;
-                this.deref_operand(mask)?;
-                // FIXME: we just return an error; `num_cpus` then falls back to `sysconf`.
-                let einval = this.eval_libc("EINVAL")?;
-                this.set_last_error(einval)?;
-                this.write_scalar(Scalar::from_i32(-1), dest)?;
+                let result = this.sched_getaffinity(pid, cpusetsize, mask)?;
+                this.write_scalar(Scalar::from_i32(result), dest)?;
+            }
+            "sched_setaffinity" => {
+                let [pid, cpusetsize, mask] =
+                    this.check_shim(abi, Abi::C { unwind: false }, link_name, args)?;
+                let result = this.sched_setaffinity(pid, cpusetsize, mask)?;
+                this.write_scalar(Scalar::from_i32(result), dest)?;
+            }
+
+            // Allocator introspection
+            "malloc_usable_size" => {
+                let [ptr] = this.check_shim(abi, Abi::C { unwind: false }, link_name, args)?;
+                let ptr = this.read_pointer(ptr)?;
+                let size = this.heap_alloc_size(ptr, MiriMemoryKind::C, "malloc_usable_size")?;
+                this.write_scalar(Scalar::from_machine_usize(size, this), dest)?;
+            }
+            "mallinfo" | "mallinfo2" => {
+                let [] = this.check_shim(abi, Abi::C { unwind: false }, link_name, args)?;
+                // There is no free memory kept by the allocator, everything is in use.
+                let in_use = this.machine.c_heap_size.get();
+                // `mallinfo` has `int` fields, which wrap around like in glibc.
+                let in_use = if &*link_name.as_str() == "mallinfo" {
+                    i128::from(in_use as i32)
+                } else {
+                    i128::from(in_use)
+                };
+                let dest = this.force_allocation(dest)?;
+                this.write_bytes_ptr(dest.ptr, vec![0; dest.layout.size.bytes_usize()])?;
+                this.write_int_fields_named(&[("arena", in_use), ("uordblks", in_use)], &dest)?;
+            }
+
+            "gnu_get_libc_version" => {
+                let [] = this.check_shim(abi, Abi::C { unwind: false }, link_name, args)?;
+                // std only uses posix_spawn with a recent enough glibc; otherwise it forks.
+                let version =
+                    this.alloc_os_str_as_c_str(OsStr::new("2.35"), MiriMemoryKind::Machine.into())?;
+                this.write_pointer(version, dest)?;
             }
 
             // Incomplete shims that we "stub out" just to get pre-main initialization code to work.
@@ -214,6 +328,114 @@ pub trait EvalContextExt<'mir, 'tcx: 'mir>: crate::MiriEvalContextExt<'mir, 'tcx
     }
 }
 
+/// The syscalls that `syscall` can execute, with the number of arguments they take (not counting
+/// the syscall number). Calling `syscall` with more arguments than needed is legal, extra arguments
+/// are simply ignored. Some syscalls (like `futex`) take a varying number of arguments; this is
+/// the minimum, and they check the rest themselves.
+const SUPPORTED_SYSCALLS: &[(&str, usize)] = &[
+    ("clock_gettime", 2),
+    ("copy_file_range", 6),
+    ("futex", 3),
+    ("getpid", 0),
+    ("getrandom", 3),
+    ("gettid", 0),
+    ("membarrier", 2),
+    ("statx", 5),
+];
+
+/// Other well-known syscalls. They are not supported, but their name is shown when they are
+/// called.
+#[rustfmt::skip]
+const UNSUPPORTED_SYSCALLS: &[&str] = &[
+    "accept", "bind", "brk", "chdir", "clock_getres", "clock_nanosleep", "clone", "close",
+    "connect", "dup", "dup3", "epoll_create1", "epoll_ctl", "epoll_pwait", "eventfd2", "execve",
+    "exit", "exit_group", "fchdir", "fchmodat", "fchownat", "fcntl", "fdatasync", "flock", "fsync",
+    "ftruncate", "getcwd", "getdents64", "getegid", "geteuid", "getgid", "getrusage", "getuid",
+    "ioctl", "kill", "listen", "lseek", "madvise", "memfd_create", "mkdirat", "mmap", "mprotect",
+    "mremap", "msync", "munmap", "nanosleep", "openat", "pipe2", "ppoll", "prctl", "pread64",
+    "pwrite64", "read", "readlinkat", "readv", "recvfrom", "renameat", "rt_sigaction",
+    "rt_sigprocmask", "sched_getaffinity", "sched_setaffinity", "sched_yield", "sendto",
+    "set_robust_list", "set_tid_address", "socket", "symlinkat", "sysinfo", "tgkill", "truncate",
+    "umask", "uname", "unlinkat", "wait4", "write", "writev",
+];
+
+/// The name of the syscall with number `id`, if it is one of the syscalls we know about.
+fn syscall_name<'tcx>(this: &MiriEvalContext<'_, 'tcx>, id: u64) -> Option<&'static str> {
+    let supported = SUPPORTED_SYSCALLS.iter().map(|&(name, _)| name);
+    supported.chain(UNSUPPORTED_SYSCALLS.iter().copied()).find(|name| {
+        // Not every syscall exists on every architecture.
+        this.eval_libc(&format!("SYS_{}", name))
+            .and_then(|sys| sys.to_machine_usize(this))
+            .map_or(false, |sys| sys == id)
+    })
+}
+
+/// Shims the variadic `syscall` function, by forwarding to the shims of the syscalls.
+fn syscall<'mir, 'tcx>(
+    this: &mut MiriEvalContext<'mir, 'tcx>,
+    args: &[OpTy<'tcx, Tag>],
+    dest: &PlaceTy<'tcx, Tag>,
+) -> InterpResult<'tcx, EmulateByNameResult<'mir, 'tcx>> {
+    if args.is_empty() {
+        throw_ub_format!("incorrect number of arguments for syscall: got 0, expected at least 1");
+    }
+    let id = this.read_scalar(&args[0])?.to_machine_usize(this)?;
+    let name = syscall_name(this, id);
+    let arg_count = name.and_then(|name| {
+        SUPPORTED_SYSCALLS.iter().find(|&&(supported, _)| supported == name).map(|&(_, n)| n)
+    });
+    let (name, arg_count) = match (name, arg_count) {
+        (Some(name), Some(arg_count)) => (name, arg_count),
+        (Some(name), None) => {
+            this.handle_unsupported(format!("can't execute syscall with ID {} (`{}`)", id, name))?;
+            return Ok(EmulateByNameResult::AlreadyJumped);
+        }
+        (None, _) => {
+            this.handle_unsupported(format!("can't execute syscall with ID {}", id))?;
+            return Ok(EmulateByNameResult::AlreadyJumped);
+        }
+    };
+    // The first argument is the syscall id, so skip over it.
+    let args = &args[1..];
+    if args.len() < arg_count {
+        throw_ub_format!(
+            "incorrect number of arguments for `{}` syscall: got {}, expected at least {}",
+            name,
+            args.len() + 1,
+            arg_count + 1
+        );
+    }
+
+    // The syscall wrapper returns a `long`, and sets `errno` on failure just like the shims do.
+    let result: i64 = match name {
+        "clock_gettime" => this.clock_gettime(&args[0], &args[1])?.into(),
+        // `copy_file_range` is used by `std::io::copy` between files.
+        "copy_file_range" =>
+            this.copy_file_range(&args[0], &args[1], &args[2], &args[3], &args[4], &args[5])?,
+        // `futex` is used by some synchonization primitives.
+        "futex" => {
+            futex(this, args, dest)?;
+            return Ok(EmulateByNameResult::NeedsJumping);
+        }
+        "getpid" => PROCESS_ID.into(),
+        // `libc::syscall(NR_GETRANDOM, buf.as_mut_ptr(), buf.len(), GRND_NONBLOCK)`
+        // is called if a `HashMap` is created the regular way (e.g. HashMap<K, V>).
+        "getrandom" => {
+            getrandom(this, &args[0], &args[1], &args[2], dest)?;
+            return Ok(EmulateByNameResult::NeedsJumping);
+        }
+        "gettid" => this.gettid().into(),
+        // `membarrier` is used to implement asymmetric fences.
+        "membarrier" => membarrier(this, args)?,
+        // `statx` is used by `libstd` to retrieve metadata information on `linux`
+        // instead of using `stat`,`lstat` or `fstat` as on `macos`.
+        "statx" => this.linux_statx(&args[0], &args[1], &args[2], &args[3], &args[4])?.into(),
+        _ => unreachable!(),
+    };
+    this.write_scalar(Scalar::from_machine_isize(result, this), dest)?;
+    Ok(EmulateByNameResult::NeedsJumping)
+}
+
 // Shims the linux `getrandom` syscall.
 fn getrandom<'tcx>(
     this: &mut MiriEvalContext<'_, 'tcx>,
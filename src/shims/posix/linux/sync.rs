@@ -1,7 +1,7 @@
 use crate::thread::Time;
 use crate::*;
 use rustc_target::abi::{Align, Size};
-use std::time::{Instant, SystemTime};
+use std::time::SystemTime;
 
 /// Implementation of the SYS_futex syscall.
 /// `args` is the arguments *after* the syscall number.
@@ -89,9 +89,6 @@ pub fn futex<'tcx>(
             let timeout_time = if this.ptr_is_null(timeout.ptr)? {
                 None
             } else {
-                this.check_no_isolation(
-                    "`futex` syscall with `op=FUTEX_WAIT` and non-null timeout",
-                )?;
                 let duration = match this.read_timespec(&timeout)? {
                     Some(duration) => duration,
                     None => {
@@ -101,19 +98,27 @@ pub fn futex<'tcx>(
                         return Ok(());
                     }
                 };
+                // Monotonic timeouts use the virtual clock under isolation; only the system
+                // time needs the host.
+                if op & futex_realtime != 0 {
+                    this.check_no_isolation(
+                        "`futex` syscall with `op=FUTEX_WAIT` and `FUTEX_CLOCK_REALTIME`",
+                    )?;
+                }
                 Some(if wait_bitset {
                     // FUTEX_WAIT_BITSET uses an absolute timestamp.
                     if op & futex_realtime != 0 {
                         Time::RealTime(SystemTime::UNIX_EPOCH.checked_add(duration).unwrap())
                     } else {
-                        Time::Monotonic(this.machine.time_anchor.checked_add(duration).unwrap())
+                        Time::Monotonic(this.machine.clock.instant_at(duration))
                     }
                 } else {
                     // FUTEX_WAIT uses a relative timestamp.
                     if op & futex_realtime != 0 {
-                        Time::RealTime(SystemTime::now().checked_add(duration).unwrap())
+                        let now = this.machine.clock.system_time();
+                        Time::RealTime(now.checked_add(duration).unwrap())
                     } else {
-                        Time::Monotonic(Instant::now().checked_add(duration).unwrap())
+                        Time::Monotonic(this.machine.clock.instant_in(duration))
                     }
                 })
             };
@@ -220,3 +225,58 @@ pub fn futex<'tcx>(
 
     Ok(())
 }
+
+// The commands of `membarrier`. These are the same on all architectures, and not in `libc`.
+const MEMBARRIER_CMD_QUERY: i32 = 0;
+const MEMBARRIER_CMD_GLOBAL: i32 = 1 << 0;
+const MEMBARRIER_CMD_GLOBAL_EXPEDITED: i32 = 1 << 1;
+const MEMBARRIER_CMD_REGISTER_GLOBAL_EXPEDITED: i32 = 1 << 2;
+const MEMBARRIER_CMD_PRIVATE_EXPEDITED: i32 = 1 << 3;
+const MEMBARRIER_CMD_REGISTER_PRIVATE_EXPEDITED: i32 = 1 << 4;
+
+/// Implementation of the SYS_membarrier syscall.
+/// `args` is the arguments *after* the syscall number.
+/// All barriers act like a sequentially consistent fence on every running thread, and
+/// synchronize the calling thread with these threads.
+pub fn membarrier<'tcx>(
+    this: &mut MiriEvalContext<'_, 'tcx>,
+    args: &[OpTy<'tcx, Tag>],
+) -> InterpResult<'tcx, i64> {
+    let cmd = this.read_scalar(&args[0])?.to_i32()?;
+    let flags = this.read_scalar(&args[1])?.to_u32()?;
+
+    let supported = MEMBARRIER_CMD_GLOBAL
+        | MEMBARRIER_CMD_GLOBAL_EXPEDITED
+        | MEMBARRIER_CMD_REGISTER_GLOBAL_EXPEDITED
+        | MEMBARRIER_CMD_PRIVATE_EXPEDITED
+        | MEMBARRIER_CMD_REGISTER_PRIVATE_EXPEDITED;
+    let einval = this.eval_libc("EINVAL")?;
+    if flags != 0 {
+        this.set_last_error(einval)?;
+        return Ok(-1);
+    }
+    match cmd {
+        MEMBARRIER_CMD_QUERY => Ok(supported.into()),
+        MEMBARRIER_CMD_REGISTER_GLOBAL_EXPEDITED => Ok(0),
+        MEMBARRIER_CMD_REGISTER_PRIVATE_EXPEDITED => {
+            this.machine.membarrier_private_expedited = true;
+            Ok(0)
+        }
+        MEMBARRIER_CMD_PRIVATE_EXPEDITED if !this.machine.membarrier_private_expedited => {
+            // The process has to register its intent first.
+            let eperm = this.eval_libc("EPERM")?;
+            this.set_last_error(eperm)?;
+            Ok(-1)
+        }
+        | MEMBARRIER_CMD_GLOBAL
+        | MEMBARRIER_CMD_GLOBAL_EXPEDITED
+        | MEMBARRIER_CMD_PRIVATE_EXPEDITED => {
+            this.validate_membarrier()?;
+            Ok(0)
+        }
+        _ => {
+            this.set_last_error(einval)?;
+            Ok(-1)
+        }
+    }
+}
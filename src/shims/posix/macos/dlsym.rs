@@ -31,7 +31,10 @@ pub trait EvalContextExt<'mir, 'tcx: 'mir>: crate::MiriEvalContextExt<'mir, 'tcx
         ret: Option<(&PlaceTy<'tcx, Tag>, mir::BasicBlock)>,
     ) -> InterpResult<'tcx> {
         let this = self.eval_context_mut();
-        let (dest, ret) = ret.expect("we don't support any diverging dlsym");
+        let (dest, ret) = match ret {
+            Some(ret) => ret,
+            None => throw_unsup_format!("calling a function obtained with `dlsym` as diverging"),
+        };
         assert!(this.tcx.sess.target.os == "macos");
 
         match dlsym {
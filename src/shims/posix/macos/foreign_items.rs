@@ -3,10 +3,28 @@ use rustc_span::Symbol;
 use rustc_target::spec::abi::Abi;
 
 use crate::*;
-use shims::foreign_items::EmulateByNameResult;
+use shims::foreign_items::{EmulateByNameResult, ForeignItemProvider};
 use shims::posix::fs::EvalContextExt as _;
+use shims::posix::mmap::EvalContextExt as _;
 use shims::posix::thread::EvalContextExt as _;
 
+/// The shims that are available on macOS, in addition to the POSIX ones.
+pub struct MacOsForeignItems;
+
+impl ForeignItemProvider for MacOsForeignItems {
+    fn emulate_foreign_item_by_name<'mir, 'tcx>(
+        &self,
+        ecx: &mut MiriEvalContext<'mir, 'tcx>,
+        link_name: Symbol,
+        abi: Abi,
+        args: &[OpTy<'tcx, Tag>],
+        dest: &PlaceTy<'tcx, Tag>,
+        ret: mir::BasicBlock,
+    ) -> InterpResult<'tcx, EmulateByNameResult<'mir, 'tcx>> {
+        EvalContextExt::emulate_foreign_item_by_name(ecx, link_name, abi, args, dest, ret)
+    }
+}
+
 impl<'mir, 'tcx: 'mir> EvalContextExt<'mir, 'tcx> for crate::MiriEvalContext<'mir, 'tcx> {}
 pub trait EvalContextExt<'mir, 'tcx: 'mir>: crate::MiriEvalContextExt<'mir, 'tcx> {
     fn emulate_foreign_item_by_name(
@@ -33,21 +51,27 @@ pub trait EvalContextExt<'mir, 'tcx: 'mir>: crate::MiriEvalContextExt<'mir, 'tcx
                 let result = this.close(result)?;
                 this.write_scalar(Scalar::from_i32(result), dest)?;
             }
-            "stat" | "stat$INODE64" => {
+            "stat$INODE64" => {
                 let [path, buf] =
                     this.check_shim(abi, Abi::C { unwind: false }, link_name, args)?;
-                let result = this.macos_stat(path, buf)?;
+                let result = this.stat(path, buf)?;
                 this.write_scalar(Scalar::from_i32(result), dest)?;
             }
-            "lstat" | "lstat$INODE64" => {
+            "lstat$INODE64" => {
                 let [path, buf] =
                     this.check_shim(abi, Abi::C { unwind: false }, link_name, args)?;
-                let result = this.macos_lstat(path, buf)?;
+                let result = this.lstat(path, buf)?;
                 this.write_scalar(Scalar::from_i32(result), dest)?;
             }
-            "fstat" | "fstat$INODE64" => {
+            "fstat$INODE64" => {
                 let [fd, buf] = this.check_shim(abi, Abi::C { unwind: false }, link_name, args)?;
-                let result = this.macos_fstat(fd, buf)?;
+                let result = this.fstat(fd, buf)?;
+                this.write_scalar(Scalar::from_i32(result), dest)?;
+            }
+            "fstatat$INODE64" => {
+                let [dirfd, path, buf, flags] =
+                    this.check_shim(abi, Abi::C { unwind: false }, link_name, args)?;
+                let result = this.fstatat(dirfd, path, buf, flags)?;
                 this.write_scalar(Scalar::from_i32(result), dest)?;
             }
             "opendir" | "opendir$INODE64" => {
@@ -78,11 +102,6 @@ pub trait EvalContextExt<'mir, 'tcx: 'mir>: crate::MiriEvalContextExt<'mir, 'tcx
             }
 
             // Time related shims
-            "gettimeofday" => {
-                let [tv, tz] = this.check_shim(abi, Abi::C { unwind: false }, link_name, args)?;
-                let result = this.gettimeofday(tv, tz)?;
-                this.write_scalar(Scalar::from_i32(result), dest)?;
-            }
             "mach_absolute_time" => {
                 let [] = this.check_shim(abi, Abi::C { unwind: false }, link_name, args)?;
                 let result = this.mach_absolute_time()?;
@@ -122,17 +141,40 @@ pub trait EvalContextExt<'mir, 'tcx: 'mir>: crate::MiriEvalContextExt<'mir, 'tcx
                 this.machine.tls.set_macos_thread_dtor(active_thread, dtor, data)?;
             }
 
+            // Allocator introspection
+            "malloc_size" => {
+                let [ptr] = this.check_shim(abi, Abi::C { unwind: false }, link_name, args)?;
+                let ptr = this.read_pointer(ptr)?;
+                let size = this.heap_alloc_size(ptr, MiriMemoryKind::C, "malloc_size")?;
+                this.write_scalar(Scalar::from_machine_usize(size, this), dest)?;
+            }
+
+            // Memory mappings
+            // The call that std makes for the guard page of the main thread is stubbed out below.
+            "mmap" if !this.frame_in_std() => {
+                let [addr, length, prot, flags, fd, offset] =
+                    this.check_shim(abi, Abi::C { unwind: false }, link_name, args)?;
+                let result = this.mmap(addr, length, prot, flags, fd, offset)?;
+                this.write_scalar(result, dest)?;
+            }
+            "munmap" => {
+                let [addr, length] =
+                    this.check_shim(abi, Abi::C { unwind: false }, link_name, args)?;
+                let result = this.munmap(addr, length)?;
+                this.write_scalar(Scalar::from_i32(result), dest)?;
+            }
+
             // Querying system information
             "pthread_get_stackaddr_np" => {
                 let [thread] = this.check_shim(abi, Abi::C { unwind: false }, link_name, args)?;
                 this.read_scalar(thread)?.to_machine_usize(this)?;
-                let stack_addr = Scalar::from_uint(STACK_ADDR, this.pointer_size());
+                let stack_addr = Scalar::from_uint(this.machine.stack_addr, this.pointer_size());
                 this.write_scalar(stack_addr, dest)?;
             }
             "pthread_get_stacksize_np" => {
                 let [thread] = this.check_shim(abi, Abi::C { unwind: false }, link_name, args)?;
                 this.read_scalar(thread)?.to_machine_usize(this)?;
-                let stack_size = Scalar::from_uint(STACK_SIZE, this.pointer_size());
+                let stack_size = Scalar::from_uint(this.machine.stack_size, this.pointer_size());
                 this.write_scalar(stack_size, dest)?;
             }
 
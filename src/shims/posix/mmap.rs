@@ -0,0 +1,354 @@
+//! Memory mappings: `mmap`, `munmap`, and `mremap`, and the functions that manage mapped memory
+//! (`msync`, `madvise`, `mlock`, and `munlock`).
+//!
+//! Every mapping is an allocation of its own (see `shims::mapping`). This means that accesses past
+//! the end of a mapping, and accesses through pointers into a mapping that was unmapped or moved,
+//! are caught just like out-of-bounds accesses and use-after-free of heap memory. The downside is
+//! that only whole mappings can be unmapped and remapped.
+//!
+//! Files can only be mapped privately: the mapping gets a copy of the contents of the file when it
+//! is created, and its changes are never written back.
+//!
+//! On macOS, only `mmap` and `munmap` are available so far.
+
+use std::iter;
+
+use rustc_target::abi::Align;
+
+use crate::*;
+use shims::mapping::{EvalContextExt as _, Mapping, Page};
+use shims::posix::fs::EvalContextExt as _;
+
+impl<'mir, 'tcx: 'mir> EvalContextExtPrivate<'mir, 'tcx> for crate::MiriEvalContext<'mir, 'tcx> {}
+trait EvalContextExtPrivate<'mir, 'tcx: 'mir>: crate::MiriEvalContextExt<'mir, 'tcx> {
+    /// Returns the allocation of the mapping that `addr` and `length` describe. Mappings can only
+    /// be unmapped and remapped as a whole, so `addr` has to be the start of a mapping and
+    /// `length` has to be its length (up to rounding to the page size).
+    fn whole_mapping(
+        &self,
+        addr: Pointer<Option<Tag>>,
+        length: u64,
+        op_name: &str,
+    ) -> InterpResult<'tcx, AllocId> {
+        let this = self.eval_context_ref();
+        if let Ok((alloc_id, offset, _)) = this.ptr_try_get_alloc_id(addr) {
+            if let Some(mapping) = this.mapping(alloc_id) {
+                if offset.bytes() == 0 && this.round_to_pages(length) == Some(mapping.size()) {
+                    return Ok(alloc_id);
+                }
+                throw_unsup_format!("`{}` is only supported for whole mappings", op_name);
+            }
+        }
+        throw_unsup_format!("`{}` is only supported for memory returned by `mmap`", op_name);
+    }
+
+    /// Whether the `len` bytes at `ptr` are memory of the program. Mappings extend to the end of
+    /// their last page, other allocations only to their size.
+    fn is_mapped(&self, ptr: Pointer<Option<Tag>>, len: u64) -> bool {
+        let this = self.eval_context_ref();
+        let (alloc_id, offset, _) = match this.ptr_try_get_alloc_id(ptr) {
+            Ok(alloc) => alloc,
+            Err(_) => return false,
+        };
+        let size = match this.mapping(alloc_id) {
+            Some(mapping) => mapping.size(),
+            None =>
+                match this.get_alloc_size_and_align(alloc_id, AllocCheck::Live) {
+                    Ok((size, _)) => size,
+                    Err(_) => return false,
+                },
+        };
+        offset.bytes().checked_add(len).map_or(false, |end| end <= size.bytes())
+    }
+
+    /// Sets the last error to `errno` and returns -1.
+    fn fail(&mut self, errno: &str) -> InterpResult<'tcx, i32> {
+        let this = self.eval_context_mut();
+        let errno = this.eval_libc(errno)?;
+        this.set_last_error(errno)?;
+        Ok(-1)
+    }
+
+    /// Sets the last error to `errno` and returns `MAP_FAILED`.
+    fn map_failed(&mut self, errno: &str) -> InterpResult<'tcx, Scalar<Tag>> {
+        let this = self.eval_context_mut();
+        let errno = this.eval_libc(errno)?;
+        this.set_last_error(errno)?;
+        Ok(Scalar::from_machine_isize(-1, this))
+    }
+}
+
+impl<'mir, 'tcx: 'mir> EvalContextExt<'mir, 'tcx> for crate::MiriEvalContext<'mir, 'tcx> {}
+pub trait EvalContextExt<'mir, 'tcx: 'mir>: crate::MiriEvalContextExt<'mir, 'tcx> {
+    fn mmap(
+        &mut self,
+        addr_op: &OpTy<'tcx, Tag>,
+        length_op: &OpTy<'tcx, Tag>,
+        prot_op: &OpTy<'tcx, Tag>,
+        flags_op: &OpTy<'tcx, Tag>,
+        fd_op: &OpTy<'tcx, Tag>,
+        offset_op: &OpTy<'tcx, Tag>,
+    ) -> InterpResult<'tcx, Scalar<Tag>> {
+        let this = self.eval_context_mut();
+
+        // Without `MAP_FIXED`, the address is only a hint, which we ignore.
+        let _addr = this.read_pointer(addr_op)?;
+        let length = this.read_scalar(length_op)?.to_machine_usize(this)?;
+        let prot = this.read_scalar(prot_op)?.to_i32()?;
+        let flags = this.read_scalar(flags_op)?.to_i32()?;
+        let fd = this.read_scalar(fd_op)?.to_i32()?;
+        let offset = this.read_scalar(offset_op)?.to_machine_isize(this)?;
+
+        let map_anonymous = this.eval_libc_i32("MAP_ANONYMOUS")?;
+        let map_fixed = this.eval_libc_i32("MAP_FIXED")?;
+        let map_shared = this.eval_libc_i32("MAP_SHARED")?;
+        if flags & map_fixed != 0 {
+            throw_unsup_format!("`mmap` with `MAP_FIXED` is not supported");
+        }
+        let anonymous = flags & map_anonymous != 0;
+        // Without `fork`, there is nobody to share an anonymous mapping with, so `MAP_SHARED`
+        // and `MAP_PRIVATE` behave the same. Shared file mappings, however, would have to write
+        // their changes back to the file.
+        if !anonymous && flags & map_shared != 0 {
+            throw_unsup_format!("`mmap` of a file is only supported with `MAP_PRIVATE`");
+        }
+        if prot & this.eval_libc_i32("PROT_READ")? == 0 {
+            throw_unsup_format!("`mmap` is only supported with `PROT_READ`");
+        }
+        let page = if prot & this.eval_libc_i32("PROT_WRITE")? != 0 {
+            Page::ReadWrite
+        } else {
+            Page::ReadOnly
+        };
+
+        if length == 0 || (!anonymous && (offset < 0 || offset as u64 % PAGE_SIZE != 0)) {
+            return this.map_failed("EINVAL");
+        }
+        let size = match this.round_to_pages(length) {
+            Some(size) => size,
+            None => return this.map_failed("ENOMEM"),
+        };
+        let contents = if anonymous {
+            vec![]
+        } else {
+            match this.read_file_for_mmap(fd, offset as u64, size.bytes())? {
+                Some(contents) => contents,
+                None => return Ok(Scalar::from_machine_isize(-1, this)),
+            }
+        };
+
+        let align = Align::from_bytes(PAGE_SIZE).unwrap();
+        let ptr = this.allocate_ptr(size, align, MiriMemoryKind::Mmap.into())?;
+        // Anonymous mappings are zero-initialized, and so is the part of a file mapping past the
+        // end of the file. (Accessing whole pages past the end of the file would raise `SIGBUS`,
+        // which we do not emulate.) We just allocated this, the access is definitely in-bounds.
+        let zeros = iter::repeat(0u8).take(size.bytes_usize() - contents.len());
+        this.write_bytes_ptr(ptr.into(), contents.into_iter().chain(zeros)).unwrap();
+        this.set_mapping(ptr.provenance.alloc_id, Mapping::new(size, page, !anonymous))?;
+        Ok(Scalar::from_maybe_pointer(ptr.into(), this))
+    }
+
+    fn munmap(
+        &mut self,
+        addr_op: &OpTy<'tcx, Tag>,
+        length_op: &OpTy<'tcx, Tag>,
+    ) -> InterpResult<'tcx, i32> {
+        let this = self.eval_context_mut();
+
+        let addr = this.read_pointer(addr_op)?;
+        let length = this.read_scalar(length_op)?.to_machine_usize(this)?;
+        if addr.addr().bytes() % PAGE_SIZE != 0 || length == 0 {
+            return this.fail("EINVAL");
+        }
+
+        let alloc_id = this.whole_mapping(addr, length, "munmap")?;
+        let size = this.mapping(alloc_id).unwrap().size();
+        let align = Align::from_bytes(PAGE_SIZE).unwrap();
+        this.deallocate_ptr(addr, Some((size, align)), MiriMemoryKind::Mmap.into())?;
+        Ok(0)
+    }
+
+    fn mremap(&mut self, args: &[OpTy<'tcx, Tag>]) -> InterpResult<'tcx, Scalar<Tag>> {
+        if args.len() < 4 {
+            throw_ub_format!(
+                "incorrect number of arguments for `mremap`: got {}, expected at least 4",
+                args.len()
+            );
+        }
+
+        let this = self.eval_context_mut();
+
+        let old_address = this.read_pointer(&args[0])?;
+        let old_size = this.read_scalar(&args[1])?.to_machine_usize(this)?;
+        let new_size = this.read_scalar(&args[2])?.to_machine_usize(this)?;
+        let flags = this.read_scalar(&args[3])?.to_i32()?;
+
+        let mremap_maymove = this.eval_libc_i32("MREMAP_MAYMOVE")?;
+        if flags & !mremap_maymove != 0 {
+            // `MREMAP_FIXED` and `MREMAP_DONTUNMAP` take the new address as fifth argument.
+            throw_unsup_format!("`mremap` flags other than `MREMAP_MAYMOVE` are not supported");
+        }
+        if old_address.addr().bytes() % PAGE_SIZE != 0 || new_size == 0 {
+            return this.map_failed("EINVAL");
+        }
+
+        let alloc_id = this.whole_mapping(old_address, old_size, "mremap")?;
+        let mapping = this.mapping(alloc_id).unwrap();
+        let old_size = mapping.size();
+        if mapping.file_backed {
+            throw_unsup_format!("`mremap` is only supported for anonymous mappings");
+        }
+        let new_size = match this.round_to_pages(new_size) {
+            Some(new_size) => new_size,
+            None => return this.map_failed("ENOMEM"),
+        };
+        if new_size == old_size {
+            return Ok(Scalar::from_maybe_pointer(old_address, this));
+        }
+        if flags & mremap_maymove == 0 {
+            // Miri cannot change the size of an allocation without moving it. For growing, this
+            // looks like the pages after the mapping are in use, which is a legitimate reason to
+            // fail; shrinking, however, always works in place.
+            if new_size < old_size {
+                throw_unsup_format!("shrinking a mapping with `mremap` requires `MREMAP_MAYMOVE`");
+            }
+            return this.map_failed("ENOMEM");
+        }
+
+        // Moving the mapping makes every pointer to the old mapping dangling, so using them
+        // afterwards is reported like a use-after-free. The state of the pages moves along.
+        let mut mapping = this.take_mapping(alloc_id)?;
+        let align = Align::from_bytes(PAGE_SIZE).unwrap();
+        let new_ptr = this.reallocate_ptr(
+            old_address,
+            Some((old_size, align)),
+            new_size,
+            align,
+            MiriMemoryKind::Mmap.into(),
+        )?;
+        if new_size > old_size {
+            // The new pages are zero-initialized, like those of a fresh anonymous mapping.
+            let tail = new_ptr.offset(old_size, this)?;
+            let tail_len = (new_size - old_size).bytes_usize();
+            this.write_bytes_ptr(tail.into(), iter::repeat(0u8).take(tail_len)).unwrap();
+        }
+        mapping.resize(new_size);
+        this.set_mapping(new_ptr.provenance.alloc_id, mapping)?;
+        Ok(Scalar::from_maybe_pointer(new_ptr.into(), this))
+    }
+
+    fn msync(
+        &mut self,
+        addr_op: &OpTy<'tcx, Tag>,
+        length_op: &OpTy<'tcx, Tag>,
+        flags_op: &OpTy<'tcx, Tag>,
+    ) -> InterpResult<'tcx, i32> {
+        let this = self.eval_context_mut();
+
+        let addr = this.read_pointer(addr_op)?;
+        let length = this.read_scalar(length_op)?.to_machine_usize(this)?;
+        let flags = this.read_scalar(flags_op)?.to_i32()?;
+
+        let ms_async = this.eval_libc_i32("MS_ASYNC")?;
+        let ms_sync = this.eval_libc_i32("MS_SYNC")?;
+        let ms_invalidate = this.eval_libc_i32("MS_INVALIDATE")?;
+        if flags & !(ms_async | ms_sync | ms_invalidate) != 0
+            || (flags & ms_async != 0 && flags & ms_sync != 0)
+            || addr.addr().bytes() % PAGE_SIZE != 0
+        {
+            return this.fail("EINVAL");
+        }
+        if !this.is_mapped(addr, length) {
+            return this.fail("ENOMEM");
+        }
+        // Files are only mapped privately, so there is nothing to write back.
+        Ok(0)
+    }
+
+    fn madvise(
+        &mut self,
+        addr_op: &OpTy<'tcx, Tag>,
+        length_op: &OpTy<'tcx, Tag>,
+        advice_op: &OpTy<'tcx, Tag>,
+    ) -> InterpResult<'tcx, i32> {
+        let this = self.eval_context_mut();
+
+        let addr = this.read_pointer(addr_op)?;
+        let length = this.read_scalar(length_op)?.to_machine_usize(this)?;
+        let advice = this.read_scalar(advice_op)?.to_i32()?;
+
+        // These are only hints about how the memory will be used, which we can ignore.
+        let hints = &[
+            "MADV_NORMAL",
+            "MADV_RANDOM",
+            "MADV_SEQUENTIAL",
+            "MADV_WILLNEED",
+            "MADV_DONTFORK",
+            "MADV_DOFORK",
+            "MADV_MERGEABLE",
+            "MADV_UNMERGEABLE",
+            "MADV_HUGEPAGE",
+            "MADV_NOHUGEPAGE",
+            "MADV_DONTDUMP",
+            "MADV_DODUMP",
+            // The kernel may free the pages at any time until they are written to, and if it
+            // does not, they keep their contents. We model the latter.
+            "MADV_FREE",
+        ];
+        let mut is_hint = false;
+        for hint in hints {
+            is_hint |= advice == this.eval_libc_i32(hint)?;
+        }
+        let madv_dontneed = this.eval_libc_i32("MADV_DONTNEED")?;
+        if (!is_hint && advice != madv_dontneed) || addr.addr().bytes() % PAGE_SIZE != 0 {
+            return this.fail("EINVAL");
+        }
+        if !this.is_mapped(addr, length) {
+            return this.fail("ENOMEM");
+        }
+
+        if advice == madv_dontneed {
+            // The pages of private anonymous mappings are zero-filled when they are accessed next.
+            let writable = match this.ptr_try_get_alloc_id(addr) {
+                Ok((alloc_id, _, _)) =>
+                    match this.mapping(alloc_id) {
+                        Some(mapping) if !mapping.file_backed =>
+                            Some(mapping.pages[0] == Page::ReadWrite),
+                        _ => None,
+                    },
+                Err(_) => None,
+            };
+            match writable {
+                // Pages that cannot be written to are still zero.
+                Some(false) => {}
+                Some(true) => {
+                    // `is_mapped` checked that the mapping contains these pages.
+                    let length = this.round_to_pages(length).unwrap();
+                    this.write_bytes_ptr(addr, iter::repeat(0u8).take(length.bytes_usize()))?;
+                }
+                None =>
+                    throw_unsup_format!(
+                        "`madvise` with `MADV_DONTNEED` is only supported for anonymous mappings"
+                    ),
+            }
+        }
+        Ok(0)
+    }
+
+    /// Shims `mlock` and `munlock`. Miri has no swap space, so all memory is always locked.
+    fn mlock(
+        &mut self,
+        addr_op: &OpTy<'tcx, Tag>,
+        length_op: &OpTy<'tcx, Tag>,
+    ) -> InterpResult<'tcx, i32> {
+        let this = self.eval_context_mut();
+
+        let addr = this.read_pointer(addr_op)?;
+        let length = this.read_scalar(length_op)?.to_machine_usize(this)?;
+        // The range does not have to be page-aligned: the kernel extends it to whole pages.
+        if !this.is_mapped(addr, length) {
+            return this.fail("ENOMEM");
+        }
+        Ok(0)
+    }
+}
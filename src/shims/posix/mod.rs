@@ -2,10 +2,19 @@ pub mod dlsym;
 pub mod foreign_items;
 
 mod fs;
+mod mmap;
+mod passwd;
+mod pipe;
+mod process;
+mod signal;
+mod socket;
 mod sync;
+mod system;
 mod thread;
 
 mod linux;
 mod macos;
 
 pub use fs::{DirHandler, FileHandler};
+pub use signal::{EvalContextExt as SignalEvalContextExt, Signals};
+pub use system::DEFAULT_HOSTNAME;
@@ -0,0 +1,227 @@
+//! The user database: `getuid`, `geteuid`, `getpwuid_r` and `getpwnam_r`.
+//!
+//! With isolation, there is a single user whose home directory can be set with
+//! `-Zmiri-home-dir`. Without isolation, the user database of the host is used (on Unix hosts).
+
+use rustc_target::abi::Size;
+
+use crate::*;
+
+/// The home directory of the user, unless it is set with `-Zmiri-home-dir`.
+const DEFAULT_HOME_DIR: &str = "/home/miri";
+
+/// The user id of the user under isolation.
+const ISOLATED_UID: u32 = 1000;
+const ISOLATED_NAME: &[u8] = b"miri";
+
+/// A user account, as described by `struct passwd`. The strings do not include the terminator.
+#[derive(Debug)]
+struct PasswdEntry {
+    name: Vec<u8>,
+    uid: u32,
+    gid: u32,
+    gecos: Vec<u8>,
+    dir: Vec<u8>,
+    shell: Vec<u8>,
+}
+
+/// How a user is looked up.
+#[derive(Debug)]
+enum PasswdKey<'a> {
+    Uid(u32),
+    Name(&'a [u8]),
+}
+
+#[cfg(unix)]
+fn host_getuid() -> u32 {
+    unsafe { libc::getuid() }
+}
+
+#[cfg(not(unix))]
+fn host_getuid() -> u32 {
+    ISOLATED_UID
+}
+
+#[cfg(unix)]
+fn host_passwd_entry(key: &PasswdKey<'_>) -> Option<PasswdEntry> {
+    use std::ffi::{CStr, CString};
+
+    let name = match key {
+        PasswdKey::Uid(_) => None,
+        PasswdKey::Name(name) => Some(CString::new(*name).ok()?),
+    };
+    let mut buf = vec![0 as libc::c_char; 1024];
+    loop {
+        let mut pwd: libc::passwd = unsafe { std::mem::zeroed() };
+        let mut result = std::ptr::null_mut();
+        let err = unsafe {
+            match (key, &name) {
+                (PasswdKey::Uid(uid), _) =>
+                    libc::getpwuid_r(*uid, &mut pwd, buf.as_mut_ptr(), buf.len(), &mut result),
+                (PasswdKey::Name(_), Some(name)) =>
+                    libc::getpwnam_r(
+                        name.as_ptr(),
+                        &mut pwd,
+                        buf.as_mut_ptr(),
+                        buf.len(),
+                        &mut result,
+                    ),
+                (PasswdKey::Name(_), None) => unreachable!(),
+            }
+        };
+        if err == libc::ERANGE {
+            let len = buf.len() * 2;
+            buf.resize(len, 0);
+            continue;
+        }
+        if err != 0 || result.is_null() {
+            return None;
+        }
+        let string = |ptr: *const libc::c_char| {
+            if ptr.is_null() {
+                Vec::new()
+            } else {
+                unsafe { CStr::from_ptr(ptr) }.to_bytes().to_vec()
+            }
+        };
+        return Some(PasswdEntry {
+            name: string(pwd.pw_name),
+            uid: pwd.pw_uid,
+            gid: pwd.pw_gid,
+            gecos: string(pwd.pw_gecos),
+            dir: string(pwd.pw_dir),
+            shell: string(pwd.pw_shell),
+        });
+    }
+}
+
+#[cfg(not(unix))]
+fn host_passwd_entry(_key: &PasswdKey<'_>) -> Option<PasswdEntry> {
+    None
+}
+
+impl<'mir, 'tcx: 'mir> EvalContextExtPrivate<'mir, 'tcx> for crate::MiriEvalContext<'mir, 'tcx> {}
+trait EvalContextExtPrivate<'mir, 'tcx: 'mir>: crate::MiriEvalContextExt<'mir, 'tcx> {
+    /// Look up a user, in the host's user database if isolation is disabled.
+    fn passwd_entry(&self, key: &PasswdKey<'_>) -> Option<PasswdEntry> {
+        let this = self.eval_context_ref();
+        if this.machine.communicate() {
+            return host_passwd_entry(key);
+        }
+        let matches = match key {
+            PasswdKey::Uid(uid) => *uid == ISOLATED_UID,
+            PasswdKey::Name(name) => *name == ISOLATED_NAME,
+        };
+        matches.then(|| {
+            PasswdEntry {
+                name: ISOLATED_NAME.to_vec(),
+                uid: ISOLATED_UID,
+                gid: ISOLATED_UID,
+                gecos: ISOLATED_NAME.to_vec(),
+                dir: this
+                    .machine
+                    .home_dir
+                    .as_deref()
+                    .unwrap_or(DEFAULT_HOME_DIR)
+                    .as_bytes()
+                    .to_vec(),
+                shell: b"/bin/sh".to_vec(),
+            }
+        })
+    }
+
+    /// Implements `getpwuid_r` and `getpwnam_r` once the key has been read: fill `pwd` with the
+    /// entry, storing its strings in `buf`, and make `result` point to `pwd`. These functions
+    /// return the error code instead of setting `errno`.
+    fn getpw_r(
+        &mut self,
+        key: PasswdKey<'_>,
+        pwd_op: &OpTy<'tcx, Tag>,
+        buf_op: &OpTy<'tcx, Tag>,
+        buflen_op: &OpTy<'tcx, Tag>,
+        result_op: &OpTy<'tcx, Tag>,
+    ) -> InterpResult<'tcx, i32> {
+        let this = self.eval_context_mut();
+
+        let pwd = this.deref_operand(pwd_op)?;
+        let buf = this.read_pointer(buf_op)?;
+        let buflen = this.read_scalar(buflen_op)?.to_machine_usize(this)?;
+        let result = this.deref_operand(result_op)?;
+
+        let entry = match this.passwd_entry(&key) {
+            Some(entry) => entry,
+            None => {
+                // Not finding the user is not an error.
+                this.write_null(&result.into())?;
+                return Ok(0);
+            }
+        };
+
+        let strings: [(&str, &[u8]); 5] = [
+            ("pw_name", &entry.name),
+            ("pw_passwd", b"x"),
+            ("pw_gecos", &entry.gecos),
+            ("pw_dir", &entry.dir),
+            ("pw_shell", &entry.shell),
+        ];
+        let needed: u64 = strings.iter().map(|(_, s)| u64::try_from(s.len()).unwrap() + 1).sum();
+        if needed > buflen {
+            this.write_null(&result.into())?;
+            return this.eval_libc_i32("ERANGE");
+        }
+
+        // Fields that we do not set (like `pw_change` on macOS) are zero.
+        this.write_bytes_ptr(pwd.ptr, vec![0; pwd.layout.size.bytes_usize()])?;
+        let mut offset = 0;
+        for (field, string) in strings {
+            let ptr = buf.offset(Size::from_bytes(offset), this)?;
+            this.write_bytes_ptr(ptr, string.iter().copied().chain(std::iter::once(0)))?;
+            this.write_pointer(ptr, &this.mplace_field_named(&pwd, field)?.into())?;
+            offset += u64::try_from(string.len()).unwrap() + 1;
+        }
+        this.write_int_fields_named(
+            &[("pw_uid", entry.uid.into()), ("pw_gid", entry.gid.into())],
+            &pwd,
+        )?;
+        this.write_scalar(Scalar::from_maybe_pointer(pwd.ptr, this), &result.into())?;
+        Ok(0)
+    }
+}
+
+impl<'mir, 'tcx: 'mir> EvalContextExt<'mir, 'tcx> for crate::MiriEvalContext<'mir, 'tcx> {}
+pub trait EvalContextExt<'mir, 'tcx: 'mir>: crate::MiriEvalContextExt<'mir, 'tcx> {
+    /// `getuid` and `geteuid`.
+    fn getuid(&self) -> u32 {
+        let this = self.eval_context_ref();
+        if this.machine.communicate() { host_getuid() } else { ISOLATED_UID }
+    }
+
+    fn getpwuid_r(
+        &mut self,
+        uid_op: &OpTy<'tcx, Tag>,
+        pwd_op: &OpTy<'tcx, Tag>,
+        buf_op: &OpTy<'tcx, Tag>,
+        buflen_op: &OpTy<'tcx, Tag>,
+        result_op: &OpTy<'tcx, Tag>,
+    ) -> InterpResult<'tcx, i32> {
+        let this = self.eval_context_mut();
+
+        let uid = this.read_scalar(uid_op)?.to_u32()?;
+        this.getpw_r(PasswdKey::Uid(uid), pwd_op, buf_op, buflen_op, result_op)
+    }
+
+    fn getpwnam_r(
+        &mut self,
+        name_op: &OpTy<'tcx, Tag>,
+        pwd_op: &OpTy<'tcx, Tag>,
+        buf_op: &OpTy<'tcx, Tag>,
+        buflen_op: &OpTy<'tcx, Tag>,
+        result_op: &OpTy<'tcx, Tag>,
+    ) -> InterpResult<'tcx, i32> {
+        let this = self.eval_context_mut();
+
+        let name = this.read_pointer(name_op)?;
+        let name = this.read_c_str(name)?.to_vec();
+        this.getpw_r(PasswdKey::Name(&name), pwd_op, buf_op, buflen_op, result_op)
+    }
+}
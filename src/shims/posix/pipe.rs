@@ -0,0 +1,170 @@
+//! Pipes: `pipe` and `pipe2`.
+//!
+//! A pipe only exists inside the interpreter, as a buffer that its two ends share. The buffer
+//! holds any amount of data, so writing never blocks. Once all descriptors of the write end are
+//! closed, reading returns 0 after the remaining data; once all descriptors of the read end are
+//! closed, writing fails with `EPIPE` without raising `SIGPIPE`.
+
+use std::cell::RefCell;
+use std::collections::VecDeque;
+use std::io::{self, ErrorKind, SeekFrom};
+use std::rc::Rc;
+
+use crate::*;
+use shims::posix::fs::{FileDescriptor, FileHandle};
+
+/// The state of a pipe that its ends share.
+#[derive(Debug)]
+struct PipeBuffer {
+    bytes: VecDeque<u8>,
+    /// The number of open descriptors of the read end.
+    readers: usize,
+    /// The number of open descriptors of the write end.
+    writers: usize,
+}
+
+/// One end of a pipe.
+#[derive(Debug)]
+struct PipeEnd {
+    buffer: Rc<RefCell<PipeBuffer>>,
+    /// Whether this is the write end.
+    write: bool,
+    /// Shared by both ends: what was written happens before reading it.
+    clock: Rc<RefCell<VClock>>,
+}
+
+impl PipeEnd {
+    /// Creates another descriptor of the same end.
+    fn new_descriptor(&self) -> Self {
+        let mut buffer = self.buffer.borrow_mut();
+        if self.write {
+            buffer.writers += 1;
+        } else {
+            buffer.readers += 1;
+        }
+        let (buffer, clock) = (Rc::clone(&self.buffer), Rc::clone(&self.clock));
+        PipeEnd { buffer, write: self.write, clock }
+    }
+}
+
+impl FileDescriptor for PipeEnd {
+    fn as_file_handle<'tcx>(&self) -> InterpResult<'tcx, &FileHandle> {
+        throw_unsup_format!("a pipe cannot be used as FileHandle");
+    }
+
+    fn read<'tcx>(
+        &mut self,
+        _communicate_allowed: bool,
+        bytes: &mut [u8],
+    ) -> InterpResult<'tcx, io::Result<usize>> {
+        // The access mode of the descriptor keeps `read` from calling this for the write end.
+        assert!(!self.write, "reading from the write end of a pipe");
+        let mut buffer = self.buffer.borrow_mut();
+        if buffer.bytes.is_empty() && !bytes.is_empty() {
+            let closed = buffer.writers == 0;
+            return Ok(if closed { Ok(0) } else { Err(ErrorKind::WouldBlock.into()) });
+        }
+        let len = bytes.len().min(buffer.bytes.len());
+        for (byte, received) in bytes.iter_mut().zip(buffer.bytes.drain(..len)) {
+            *byte = received;
+        }
+        Ok(Ok(len))
+    }
+
+    fn write<'tcx>(
+        &self,
+        _communicate_allowed: bool,
+        bytes: &[u8],
+    ) -> InterpResult<'tcx, io::Result<usize>> {
+        assert!(self.write, "writing to the read end of a pipe");
+        let mut buffer = self.buffer.borrow_mut();
+        if buffer.readers == 0 {
+            return Ok(Err(ErrorKind::BrokenPipe.into()));
+        }
+        buffer.bytes.extend(bytes);
+        Ok(Ok(bytes.len()))
+    }
+
+    fn seek<'tcx>(
+        &mut self,
+        _communicate_allowed: bool,
+        _offset: SeekFrom,
+    ) -> InterpResult<'tcx, io::Result<u64>> {
+        throw_unsup_format!("cannot seek on a pipe");
+    }
+
+    fn close<'tcx>(
+        self: Box<Self>,
+        _communicate_allowed: bool,
+    ) -> InterpResult<'tcx, io::Result<i32>> {
+        let mut buffer = self.buffer.borrow_mut();
+        if self.write {
+            buffer.writers -= 1;
+        } else {
+            buffer.readers -= 1;
+        }
+        Ok(Ok(0))
+    }
+
+    fn dup(&mut self) -> io::Result<Box<dyn FileDescriptor>> {
+        Ok(Box::new(self.new_descriptor()))
+    }
+
+    fn readiness(&self) -> (bool, bool) {
+        if self.write {
+            (false, true)
+        } else {
+            let buffer = self.buffer.borrow();
+            (!buffer.bytes.is_empty() || buffer.writers == 0, false)
+        }
+    }
+
+    fn sync_clock(&self) -> Option<&RefCell<VClock>> {
+        Some(&*self.clock)
+    }
+}
+
+impl<'mir, 'tcx: 'mir> EvalContextExt<'mir, 'tcx> for crate::MiriEvalContext<'mir, 'tcx> {}
+pub trait EvalContextExt<'mir, 'tcx: 'mir>: crate::MiriEvalContextExt<'mir, 'tcx> {
+    /// `pipe` and `pipe2`. `flags_op` is `None` for `pipe`.
+    fn pipe2(
+        &mut self,
+        fds_op: &OpTy<'tcx, Tag>,
+        flags_op: Option<&OpTy<'tcx, Tag>>,
+    ) -> InterpResult<'tcx, i32> {
+        let this = self.eval_context_mut();
+
+        let fds = this.read_pointer(fds_op)?;
+        let flags = match flags_op {
+            Some(flags_op) => this.read_scalar(flags_op)?.to_i32()?,
+            None => 0,
+        };
+
+        let o_cloexec = this.eval_libc_i32("O_CLOEXEC")?;
+        let o_nonblock = this.eval_libc_i32("O_NONBLOCK")?;
+        if flags & !(o_cloexec | o_nonblock) != 0 {
+            let einval = this.eval_libc("EINVAL")?;
+            this.set_last_error(einval)?;
+            return Ok(-1);
+        }
+        let fd_flags = if flags & o_cloexec != 0 { this.eval_libc_i32("FD_CLOEXEC")? } else { 0 };
+        // The read end comes first.
+        let o_rdonly = this.eval_libc_i32("O_RDONLY")?;
+        let o_wronly = this.eval_libc_i32("O_WRONLY")?;
+        let ends = [(o_rdonly, false), (o_wronly, true)];
+
+        let buffer = PipeBuffer { bytes: VecDeque::new(), readers: 1, writers: 1 };
+        let buffer = Rc::new(RefCell::new(buffer));
+        let clock: Rc<RefCell<VClock>> = Default::default();
+        let int_layout = this.machine.layouts.i32;
+        for (i, (access_mode, write)) in ends.into_iter().enumerate() {
+            let end = PipeEnd { buffer: Rc::clone(&buffer), write, clock: Rc::clone(&clock) };
+            let status_flags = access_mode | (flags & o_nonblock);
+            let fd = this.machine.file_handler.insert_fd(Box::new(end), status_flags, fd_flags);
+            let ptr = fds.offset(int_layout.size * u64::try_from(i).unwrap(), this)?;
+            let fd_place = MPlaceTy::from_aligned_ptr(ptr, int_layout);
+            this.write_scalar(Scalar::from_i32(fd), &fd_place.into())?;
+        }
+        Ok(0)
+    }
+}
@@ -0,0 +1,121 @@
+//! Process creation (`fork`, `exec*`, `posix_spawn`) and waiting for child processes.
+//!
+//! Miri cannot run another process, so every attempt to create one fails with `ENOSYS`. Many
+//! programs handle that just fine (e.g. by falling back to doing the work themselves), so this is
+//! not an unsupported operation that stops the program. Depending on `-Zmiri-process-spawn`, a
+//! warning is emitted when it happens. The functions that configure a `posix_spawn` call succeed,
+//! so that the failure is reported by the call that would actually create the process.
+
+use crate::*;
+
+impl<'mir, 'tcx: 'mir> EvalContextExtPrivate<'mir, 'tcx> for crate::MiriEvalContext<'mir, 'tcx> {}
+trait EvalContextExtPrivate<'mir, 'tcx: 'mir>: crate::MiriEvalContextExt<'mir, 'tcx> {
+    /// Report that `op_name` was made to fail, as configured by `-Zmiri-process-spawn`, and return
+    /// the error code it fails with.
+    fn reject_process_spawn(&mut self, op_name: &str) -> InterpResult<'tcx, Scalar<Tag>> {
+        let this = self.eval_context_mut();
+        match this.machine.process_spawn {
+            ProcessSpawn::Error =>
+                register_diagnostic(NonHaltingDiagnostic::RejectedProcessSpawn(format!(
+                    "`{}`",
+                    op_name
+                ))),
+            ProcessSpawn::RejectSilent => {}
+        }
+        this.eval_libc("ENOSYS")
+    }
+}
+
+impl<'mir, 'tcx: 'mir> EvalContextExt<'mir, 'tcx> for crate::MiriEvalContext<'mir, 'tcx> {}
+pub trait EvalContextExt<'mir, 'tcx: 'mir>: crate::MiriEvalContextExt<'mir, 'tcx> {
+    /// `fork` and `vfork`.
+    fn fork(&mut self, op_name: &str) -> InterpResult<'tcx, i32> {
+        let this = self.eval_context_mut();
+
+        let enosys = this.reject_process_spawn(op_name)?;
+        this.set_last_error(enosys)?;
+        Ok(-1)
+    }
+
+    /// All the functions of the `exec` family. They only return if they fail.
+    fn execve(
+        &mut self,
+        op_name: &str,
+        path_op: &OpTy<'tcx, Tag>,
+        argv_op: &OpTy<'tcx, Tag>,
+    ) -> InterpResult<'tcx, i32> {
+        let this = self.eval_context_mut();
+
+        this.read_pointer(path_op)?;
+        this.read_pointer(argv_op)?;
+        let enosys = this.reject_process_spawn(op_name)?;
+        this.set_last_error(enosys)?;
+        Ok(-1)
+    }
+
+    /// `posix_spawn` and `posix_spawnp`. Unlike most POSIX functions, these return the error code
+    /// instead of setting `errno`.
+    fn posix_spawn(
+        &mut self,
+        op_name: &str,
+        pid_op: &OpTy<'tcx, Tag>,
+        path_op: &OpTy<'tcx, Tag>,
+        argv_op: &OpTy<'tcx, Tag>,
+    ) -> InterpResult<'tcx, Scalar<Tag>> {
+        let this = self.eval_context_mut();
+
+        // The pid is only written on success, which never happens.
+        this.read_pointer(pid_op)?;
+        this.read_pointer(path_op)?;
+        this.read_pointer(argv_op)?;
+        this.reject_process_spawn(op_name)
+    }
+
+    /// `waitpid`, which always fails since there are no child processes.
+    fn waitpid(
+        &mut self,
+        pid_op: &OpTy<'tcx, Tag>,
+        status_op: &OpTy<'tcx, Tag>,
+        options_op: &OpTy<'tcx, Tag>,
+    ) -> InterpResult<'tcx, i32> {
+        let this = self.eval_context_mut();
+
+        this.read_scalar(pid_op)?.to_i32()?;
+        this.read_pointer(status_op)?;
+        this.read_scalar(options_op)?.to_i32()?;
+        let echild = this.eval_libc("ECHILD")?;
+        this.set_last_error(echild)?;
+        Ok(-1)
+    }
+
+    /// `sigemptyset`. Signal sets are only used to configure processes that are never spawned,
+    /// so `sigaddset` does not need to record anything in them.
+    fn sigemptyset(&mut self, set_op: &OpTy<'tcx, Tag>) -> InterpResult<'tcx, i32> {
+        let this = self.eval_context_mut();
+
+        let set = this.deref_operand(set_op)?;
+        this.write_bytes_ptr(set.ptr, vec![0; set.layout.size.bytes_usize()])?;
+        Ok(0)
+    }
+
+    fn sigaddset(
+        &mut self,
+        set_op: &OpTy<'tcx, Tag>,
+        signum_op: &OpTy<'tcx, Tag>,
+    ) -> InterpResult<'tcx, i32> {
+        let this = self.eval_context_mut();
+
+        let set = this.deref_operand(set_op)?;
+        let signum = this.read_scalar(signum_op)?.to_i32()?;
+        // The set has one bit per signal, starting at signal 1.
+        let max_signum = set.layout.size.bits();
+        if signum < 1 || u64::try_from(signum).unwrap() > max_signum {
+            let einval = this.eval_libc("EINVAL")?;
+            this.set_last_error(einval)?;
+            return Ok(-1);
+        }
+        // Make sure the set is initialized, as it would be read to update it.
+        this.read_bytes_ptr(set.ptr, set.layout.size)?;
+        Ok(0)
+    }
+}
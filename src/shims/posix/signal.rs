@@ -0,0 +1,271 @@
+//! Interval timers (`alarm`, `setitimer`, `getitimer`) and the signal handlers that are called
+//! when they expire.
+//!
+//! Armed timers are recorded, so that programs that arm a watchdog can run, but by default they
+//! never go off. With `-Zmiri-deliver-timer-signals`, once the deadline of the timer has passed,
+//! `SIGALRM` is delivered at the next step of whichever thread is running: the handler that the
+//! program installed with `signal` or `sigaction` is called on top of that thread's stack. Without
+//! a handler, the program is terminated, which is the default action for `SIGALRM`.
+
+use std::time::{Duration, Instant};
+
+use rustc_data_structures::fx::FxHashMap;
+use rustc_target::spec::abi::Abi;
+
+use crate::*;
+
+#[derive(Debug)]
+struct Timer {
+    deadline: Instant,
+    /// If set, the timer is re-armed with this duration when it expires.
+    interval: Option<Duration>,
+}
+
+#[derive(Debug, Default)]
+pub struct Signals {
+    /// The timer that sends `SIGALRM` (`ITIMER_REAL`), if it is armed.
+    real_timer: Option<Timer>,
+    /// The handlers installed by the program, by signal number. Signals that are not in here
+    /// have the default action.
+    handlers: FxHashMap<i32, Scalar<Tag>>,
+}
+
+impl<'mir, 'tcx: 'mir> EvalContextExtPrivate<'mir, 'tcx> for crate::MiriEvalContext<'mir, 'tcx> {}
+trait EvalContextExtPrivate<'mir, 'tcx: 'mir>: crate::MiriEvalContextExt<'mir, 'tcx> {
+    /// Parse a `timeval` struct. Returns `None` if it is invalid.
+    fn read_timeval(&self, tv: &MPlaceTy<'tcx, Tag>) -> InterpResult<'tcx, Option<Duration>> {
+        let this = self.eval_context_ref();
+        let seconds =
+            this.read_scalar(&this.mplace_field(tv, 0)?.into())?.to_machine_isize(this)?;
+        let micros = this.read_scalar(&this.mplace_field(tv, 1)?.into())?.to_machine_isize(this)?;
+        Ok(try {
+            let seconds: u64 = seconds.try_into().ok()?;
+            let micros: u32 = micros.try_into().ok()?;
+            if micros >= 1_000_000 {
+                None?
+            }
+            Duration::new(seconds, micros * 1000)
+        })
+    }
+
+    /// Write the state of the real-time timer to the `itimerval` that `op` points to, unless it
+    /// is null.
+    fn write_itimerval(&mut self, op: &OpTy<'tcx, Tag>) -> InterpResult<'tcx> {
+        let this = self.eval_context_mut();
+        if this.ptr_is_null(this.read_pointer(op)?)? {
+            return Ok(());
+        }
+        let (value, interval) = match &this.machine.signals.real_timer {
+            Some(timer) =>
+                (
+                    timer.deadline.saturating_duration_since(this.machine.clock.now()),
+                    timer.interval.unwrap_or(Duration::ZERO),
+                ),
+            None => (Duration::ZERO, Duration::ZERO),
+        };
+        let itimerval = this.deref_operand(op)?;
+        for (name, duration) in [("it_value", value), ("it_interval", interval)] {
+            let timeval = this.mplace_field_named(&itimerval, name)?;
+            this.write_int_fields_named(
+                &[
+                    ("tv_sec", duration.as_secs().into()),
+                    ("tv_usec", duration.subsec_micros().into()),
+                ],
+                &timeval,
+            )?;
+        }
+        Ok(())
+    }
+
+    /// Arm the real-time timer to expire after `value`, or disarm it if `value` is zero.
+    fn arm_real_timer(&mut self, value: Duration, interval: Duration) {
+        let this = self.eval_context_mut();
+        this.machine.signals.real_timer = (value != Duration::ZERO).then(|| {
+            Timer {
+                deadline: this.machine.clock.instant_in(value),
+                interval: (interval != Duration::ZERO).then(|| interval),
+            }
+        });
+    }
+
+    /// Install `handler` for `signum`, and return the previous one.
+    fn set_signal_handler(
+        &mut self,
+        signum: i32,
+        handler: Scalar<Tag>,
+    ) -> InterpResult<'tcx, Scalar<Tag>> {
+        let this = self.eval_context_mut();
+        let sig_dfl = this.eval_libc("SIG_DFL")?;
+        let old = if handler == sig_dfl {
+            this.machine.signals.handlers.remove(&signum)
+        } else {
+            this.machine.signals.handlers.insert(signum, handler)
+        };
+        Ok(old.unwrap_or(sig_dfl))
+    }
+}
+
+impl<'mir, 'tcx: 'mir> EvalContextExt<'mir, 'tcx> for crate::MiriEvalContext<'mir, 'tcx> {}
+pub trait EvalContextExt<'mir, 'tcx: 'mir>: crate::MiriEvalContextExt<'mir, 'tcx> {
+    fn alarm(&mut self, seconds_op: &OpTy<'tcx, Tag>) -> InterpResult<'tcx, u32> {
+        let this = self.eval_context_mut();
+
+        let seconds = this.read_scalar(seconds_op)?.to_u32()?;
+        // Return the number of seconds that were left, rounded up so that an armed timer does not
+        // look disarmed.
+        let remaining = this.machine.signals.real_timer.as_ref().map_or(0, |timer| {
+            let left = timer.deadline.saturating_duration_since(this.machine.clock.now());
+            left.as_secs() + u64::from(left.subsec_nanos() > 0)
+        });
+        this.arm_real_timer(Duration::from_secs(seconds.into()), Duration::ZERO);
+        Ok(u32::try_from(remaining).unwrap_or(u32::MAX))
+    }
+
+    fn setitimer(
+        &mut self,
+        which_op: &OpTy<'tcx, Tag>,
+        new_op: &OpTy<'tcx, Tag>,
+        old_op: &OpTy<'tcx, Tag>,
+    ) -> InterpResult<'tcx, i32> {
+        let this = self.eval_context_mut();
+
+        let which = this.read_scalar(which_op)?.to_i32()?;
+        if which != this.eval_libc_i32("ITIMER_REAL")? {
+            throw_unsup_format!("`setitimer` is only supported for `ITIMER_REAL`");
+        }
+        let new = this.deref_operand(new_op)?;
+        let value = this.read_timeval(&this.mplace_field_named(&new, "it_value")?)?;
+        let interval = this.read_timeval(&this.mplace_field_named(&new, "it_interval")?)?;
+        let (value, interval) = match (value, interval) {
+            (Some(value), Some(interval)) => (value, interval),
+            _ => {
+                let einval = this.eval_libc("EINVAL")?;
+                this.set_last_error(einval)?;
+                return Ok(-1);
+            }
+        };
+
+        this.write_itimerval(old_op)?;
+        this.arm_real_timer(value, interval);
+        Ok(0)
+    }
+
+    fn getitimer(
+        &mut self,
+        which_op: &OpTy<'tcx, Tag>,
+        curr_op: &OpTy<'tcx, Tag>,
+    ) -> InterpResult<'tcx, i32> {
+        let this = self.eval_context_mut();
+
+        let which = this.read_scalar(which_op)?.to_i32()?;
+        if which != this.eval_libc_i32("ITIMER_REAL")? {
+            throw_unsup_format!("`getitimer` is only supported for `ITIMER_REAL`");
+        }
+        this.write_itimerval(curr_op)?;
+        Ok(0)
+    }
+
+    fn signal(
+        &mut self,
+        signum_op: &OpTy<'tcx, Tag>,
+        handler_op: &OpTy<'tcx, Tag>,
+    ) -> InterpResult<'tcx, Scalar<Tag>> {
+        let this = self.eval_context_mut();
+
+        let signum = this.read_scalar(signum_op)?.to_i32()?;
+        let handler = this.read_scalar(handler_op)?.check_init()?;
+        this.set_signal_handler(signum, handler)
+    }
+
+    fn sigaction(
+        &mut self,
+        signum_op: &OpTy<'tcx, Tag>,
+        act_op: &OpTy<'tcx, Tag>,
+        oldact_op: &OpTy<'tcx, Tag>,
+    ) -> InterpResult<'tcx, i32> {
+        let this = self.eval_context_mut();
+
+        let signum = this.read_scalar(signum_op)?.to_i32()?;
+        let sig_dfl = this.eval_libc("SIG_DFL")?;
+        let old = this.machine.signals.handlers.get(&signum).copied().unwrap_or(sig_dfl);
+
+        let act = this.read_pointer(act_op)?;
+        if !this.ptr_is_null(act)? {
+            let act = this.deref_operand(act_op)?;
+            let flags = this.read_scalar(&this.mplace_field_named(&act, "sa_flags")?.into())?;
+            if flags.to_i32()? & this.eval_libc_i32("SA_SIGINFO")? != 0 {
+                throw_unsup_format!("`sigaction` with `SA_SIGINFO` is not supported");
+            }
+            let handler = this.mplace_field_named(&act, "sa_sigaction")?;
+            let handler = this.read_scalar(&handler.into())?.check_init()?;
+            this.set_signal_handler(signum, handler)?;
+        }
+
+        let oldact = this.read_pointer(oldact_op)?;
+        if !this.ptr_is_null(oldact)? {
+            let oldact = this.deref_operand(oldact_op)?;
+            // The mask and flags of the old action are reported as empty.
+            this.write_bytes_ptr(oldact.ptr, vec![0; oldact.layout.size.bytes_usize()])?;
+            let handler = this.mplace_field_named(&oldact, "sa_sigaction")?;
+            this.write_scalar(old, &handler.into())?;
+        }
+        Ok(0)
+    }
+
+    /// When the real-time timer is going to deliver its signal, if it is armed, signals are
+    /// delivered and `SIGALRM` is not ignored. Sleeps are interrupted then.
+    fn timer_signal_deadline(&self) -> InterpResult<'tcx, Option<Instant>> {
+        let this = self.eval_context_ref();
+        let timer = match &this.machine.signals.real_timer {
+            Some(timer) if this.machine.deliver_timer_signals => timer,
+            _ => return Ok(None),
+        };
+        let sigalrm = this.eval_libc_i32("SIGALRM")?;
+        let sig_ign = this.eval_libc("SIG_IGN")?;
+        let ignored = this.machine.signals.handlers.get(&sigalrm) == Some(&sig_ign);
+        Ok((!ignored).then(|| timer.deadline))
+    }
+
+    /// If timer signals are delivered and the real-time timer expired, deliver `SIGALRM` to the
+    /// active thread. This is called before each step.
+    fn deliver_timer_signals(&mut self) -> InterpResult<'tcx> {
+        let this = self.eval_context_mut();
+        if !this.machine.deliver_timer_signals {
+            return Ok(());
+        }
+        let now = this.machine.clock.now();
+        match &this.machine.signals.real_timer {
+            Some(timer) if timer.deadline <= now => {}
+            _ => return Ok(()),
+        }
+        let timer = this.machine.signals.real_timer.take().unwrap();
+        if let Some(interval) = timer.interval {
+            let deadline = now.checked_add(interval).unwrap();
+            this.machine.signals.real_timer = Some(Timer { deadline, interval: Some(interval) });
+        }
+
+        let sigalrm = this.eval_libc_i32("SIGALRM")?;
+        let handler = match this.machine.signals.handlers.get(&sigalrm) {
+            Some(&handler) => handler,
+            None =>
+                throw_machine_stop!(TerminationInfo::Abort(
+                    "the program was terminated by `SIGALRM`, which it has no handler for"
+                        .to_owned()
+                )),
+        };
+        if handler == this.eval_libc("SIG_IGN")? {
+            return Ok(());
+        }
+        let handler = this.get_ptr_fn(this.scalar_to_ptr(handler)?)?.as_instance()?;
+        // The handler runs on top of the current frame. When it returns, that frame continues
+        // where it was interrupted.
+        let ret_place = MPlaceTy::dangling(this.machine.layouts.unit).into();
+        this.call_function(
+            handler,
+            Abi::C { unwind: false },
+            &[Scalar::from_i32(sigalrm).into()],
+            Some(&ret_place),
+            StackPopCleanup::Root { cleanup: true },
+        )
+    }
+}
@@ -0,0 +1,671 @@
+//! Sockets of the `AF_UNIX` domain and type `SOCK_STREAM`: `socket`, `socketpair`, `bind`,
+//! `listen`, `connect`, `accept`, `shutdown`, `send`, and `recv`.
+//!
+//! The sockets only exist inside the interpreter, so they can only connect to each other. Binding
+//! a socket to a path does not create anything in the file system; the address is merely
+//! remembered until the socket is closed. Connections buffer any amount of data, so writing never
+//! blocks, and `listen` queues any number of connections. Writing to a connection whose peer is
+//! gone fails with `EPIPE` without raising `SIGPIPE`.
+
+use std::cell::RefCell;
+use std::collections::VecDeque;
+use std::io::{self, ErrorKind, SeekFrom};
+use std::rc::Rc;
+
+use rustc_target::abi::Size;
+
+use crate::*;
+use shims::posix::fs::{
+    BlockedOp, EvalContextExt as _, EvalContextExtPrivate as _, FileDescriptor, FileHandle,
+};
+
+/// The data sent in one direction of a connection that was not received yet.
+#[derive(Debug, Default)]
+struct Channel {
+    bytes: VecDeque<u8>,
+    /// What the sending end did before sending happens before the receiving end receives it.
+    /// Each direction has its own clock, so that sending does not synchronize with what the
+    /// receiving end sent.
+    clock: VClock,
+    /// Whether the sending end shut down writing, or was closed. Receiving returns 0 once `bytes`
+    /// is empty.
+    send_closed: bool,
+    /// Whether the receiving end shut down reading, or was closed. Sending fails with `EPIPE`.
+    recv_closed: bool,
+}
+
+/// One end of a connection.
+#[derive(Debug)]
+pub(super) struct Connection {
+    incoming: Rc<RefCell<Channel>>,
+    outgoing: Rc<RefCell<Channel>>,
+}
+
+impl Connection {
+    /// The two ends of a new connection.
+    fn pair() -> (Connection, Connection) {
+        let a = Rc::new(RefCell::new(Channel::default()));
+        let b = Rc::new(RefCell::new(Channel::default()));
+        (
+            Connection { incoming: Rc::clone(&a), outgoing: Rc::clone(&b) },
+            Connection { incoming: b, outgoing: a },
+        )
+    }
+
+    fn shutdown(&self, read: bool, write: bool) {
+        if read {
+            self.incoming.borrow_mut().recv_closed = true;
+        }
+        if write {
+            self.outgoing.borrow_mut().send_closed = true;
+        }
+    }
+}
+
+#[derive(Debug)]
+pub(super) enum SocketState {
+    /// Neither listening nor connected. `address` is set by `bind`.
+    Unconnected {
+        address: Option<Vec<u8>>,
+    },
+    /// Listening for connections. Those that were made but not accepted yet are in `backlog`.
+    Listening {
+        backlog: VecDeque<Connection>,
+    },
+    Connected(Connection),
+}
+
+/// A socket. Duplicated descriptors share the socket.
+#[derive(Debug, Clone)]
+pub(super) struct Socket {
+    state: Rc<RefCell<SocketState>>,
+}
+
+impl Socket {
+    fn new(state: SocketState) -> Self {
+        Socket { state: Rc::new(RefCell::new(state)) }
+    }
+}
+
+impl FileDescriptor for Socket {
+    fn as_file_handle<'tcx>(&self) -> InterpResult<'tcx, &FileHandle> {
+        throw_unsup_format!("a socket cannot be used as FileHandle");
+    }
+
+    fn read<'tcx>(
+        &mut self,
+        _communicate_allowed: bool,
+        bytes: &mut [u8],
+    ) -> InterpResult<'tcx, io::Result<usize>> {
+        let connection = match &*self.state.borrow() {
+            SocketState::Connected(connection) => Rc::clone(&connection.incoming),
+            _ => return Ok(Err(ErrorKind::NotConnected.into())),
+        };
+        let mut incoming = connection.borrow_mut();
+        if incoming.bytes.is_empty() && !bytes.is_empty() {
+            let closed = incoming.send_closed || incoming.recv_closed;
+            return Ok(if closed { Ok(0) } else { Err(ErrorKind::WouldBlock.into()) });
+        }
+        let len = bytes.len().min(incoming.bytes.len());
+        for (byte, received) in bytes.iter_mut().zip(incoming.bytes.drain(..len)) {
+            *byte = received;
+        }
+        Ok(Ok(len))
+    }
+
+    fn write<'tcx>(
+        &self,
+        _communicate_allowed: bool,
+        bytes: &[u8],
+    ) -> InterpResult<'tcx, io::Result<usize>> {
+        match &*self.state.borrow() {
+            SocketState::Connected(connection) => {
+                let mut outgoing = connection.outgoing.borrow_mut();
+                if outgoing.send_closed || outgoing.recv_closed {
+                    return Ok(Err(ErrorKind::BrokenPipe.into()));
+                }
+                outgoing.bytes.extend(bytes);
+                Ok(Ok(bytes.len()))
+            }
+            _ => Ok(Err(ErrorKind::NotConnected.into())),
+        }
+    }
+
+    fn seek<'tcx>(
+        &mut self,
+        _communicate_allowed: bool,
+        _offset: SeekFrom,
+    ) -> InterpResult<'tcx, io::Result<u64>> {
+        throw_unsup_format!("cannot seek on a socket");
+    }
+
+    fn close<'tcx>(
+        self: Box<Self>,
+        _communicate_allowed: bool,
+    ) -> InterpResult<'tcx, io::Result<i32>> {
+        // Closing the last descriptor of a socket ends its connections, including those that
+        // were not accepted yet.
+        if Rc::strong_count(&self.state) == 1 {
+            match &*self.state.borrow() {
+                SocketState::Unconnected { .. } => {}
+                SocketState::Listening { backlog } =>
+                    for connection in backlog {
+                        connection.shutdown(true, true);
+                    },
+                SocketState::Connected(connection) => connection.shutdown(true, true),
+            }
+        }
+        Ok(Ok(0))
+    }
+
+    fn dup(&mut self) -> io::Result<Box<dyn FileDescriptor>> {
+        Ok(Box::new(self.clone()))
+    }
+
+    fn readiness(&self) -> (bool, bool) {
+        match &*self.state.borrow() {
+            SocketState::Unconnected { .. } => (false, false),
+            SocketState::Listening { backlog } => (!backlog.is_empty(), false),
+            SocketState::Connected(connection) => {
+                let incoming = connection.incoming.borrow();
+                let readable =
+                    !incoming.bytes.is_empty() || incoming.send_closed || incoming.recv_closed;
+                (readable, true)
+            }
+        }
+    }
+
+    fn release_write(&self, data_race: &data_race::GlobalState, thread: ThreadId) {
+        if let SocketState::Connected(connection) = &*self.state.borrow() {
+            let mut outgoing = connection.outgoing.borrow_mut();
+            data_race.validate_lock_release_shared(&mut outgoing.clock, thread);
+        }
+    }
+
+    fn acquire_read(&self, data_race: &data_race::GlobalState, thread: ThreadId) {
+        if let SocketState::Connected(connection) = &*self.state.borrow() {
+            data_race.validate_lock_acquire(&connection.incoming.borrow().clock, thread);
+        }
+    }
+
+    fn as_socket(&self) -> Option<&Socket> {
+        Some(self)
+    }
+}
+
+impl<'mir, 'tcx: 'mir> EvalContextExtPrivate<'mir, 'tcx> for crate::MiriEvalContext<'mir, 'tcx> {}
+trait EvalContextExtPrivate<'mir, 'tcx: 'mir>: crate::MiriEvalContextExt<'mir, 'tcx> {
+    /// Looks up the socket that `fd` refers to, and whether it is in blocking mode. Sets the last
+    /// error and returns `None` if `fd` is not open or not a socket.
+    fn socket_of(&mut self, fd: i32) -> InterpResult<'tcx, Option<(Socket, bool)>> {
+        let this = self.eval_context_mut();
+        let o_nonblock = this.eval_libc_i32("O_NONBLOCK")?;
+        let socket = this.machine.file_handler.handles.get(&fd).map(|open_fd| {
+            let blocking = open_fd.status_flags.get() & o_nonblock == 0;
+            open_fd.file_descriptor.as_socket().map(|socket| (socket.clone(), blocking))
+        });
+        match socket {
+            Some(Some(socket)) => Ok(Some(socket)),
+            Some(None) => {
+                this.fail_with_errno::<i32>("ENOTSOCK")?;
+                Ok(None)
+            }
+            None => {
+                this.handle_not_found::<i32>()?;
+                Ok(None)
+            }
+        }
+    }
+
+    /// Splits the flags that Linux accepts together with the socket type off `ty`. Returns the
+    /// remaining type, and the file status and descriptor flags that the flags ask for.
+    fn socket_type_flags(&self, ty: i32) -> InterpResult<'tcx, (i32, i32, i32)> {
+        let this = self.eval_context_ref();
+        let mut status_flags = this.eval_libc_i32("O_RDWR")?;
+        let mut fd_flags = 0;
+        let mut ty = ty;
+        if this.tcx.sess.target.os == "linux" {
+            let sock_cloexec = this.eval_libc_i32("SOCK_CLOEXEC")?;
+            let sock_nonblock = this.eval_libc_i32("SOCK_NONBLOCK")?;
+            if ty & sock_cloexec != 0 {
+                fd_flags = this.eval_libc_i32("FD_CLOEXEC")?;
+            }
+            if ty & sock_nonblock != 0 {
+                status_flags |= this.eval_libc_i32("O_NONBLOCK")?;
+            }
+            ty &= !(sock_cloexec | sock_nonblock);
+        }
+        Ok((ty, status_flags, fd_flags))
+    }
+
+    /// Checks the arguments of `socket` and `socketpair`. Returns the file status and descriptor
+    /// flags of the new sockets, or sets the last error and returns `None`.
+    fn check_socket_args(
+        &mut self,
+        domain: i32,
+        ty: i32,
+        protocol: i32,
+    ) -> InterpResult<'tcx, Option<(i32, i32)>> {
+        let this = self.eval_context_mut();
+        let (ty, status_flags, fd_flags) = this.socket_type_flags(ty)?;
+        if domain != this.eval_libc_i32("AF_UNIX")? {
+            throw_unsup_format!("sockets of domain {} are not supported", domain);
+        }
+        if ty != this.eval_libc_i32("SOCK_STREAM")? {
+            throw_unsup_format!("sockets of type {} are not supported", ty);
+        }
+        if protocol != 0 {
+            this.fail_with_errno::<i32>("EPROTONOSUPPORT")?;
+            return Ok(None);
+        }
+        Ok(Some((status_flags, fd_flags)))
+    }
+
+    /// The offset of the path in a `sockaddr_un`, which is also the length of the address of an
+    /// unnamed socket.
+    fn sun_path_offset(&self) -> InterpResult<'tcx, Size> {
+        let this = self.eval_context_ref();
+        let layout = this.libc_ty_layout("sockaddr_un")?;
+        Ok(this.field_offset_named(layout, "sun_path"))
+    }
+
+    /// Reads the `sockaddr_un` of `addrlen` bytes at `addr`. Returns the path, or sets the last
+    /// error and returns `None` if it is not a valid address.
+    fn read_socket_address(
+        &mut self,
+        addr_op: &OpTy<'tcx, Tag>,
+        addrlen_op: &OpTy<'tcx, Tag>,
+    ) -> InterpResult<'tcx, Option<Vec<u8>>> {
+        let this = self.eval_context_mut();
+
+        let addr = this.read_pointer(addr_op)?;
+        let addrlen = u64::from(this.read_scalar(addrlen_op)?.to_u32()?);
+
+        let layout = this.libc_ty_layout("sockaddr_un")?;
+        let path_offset = this.sun_path_offset()?;
+        if addrlen <= path_offset.bytes() || addrlen > layout.size.bytes() {
+            this.fail_with_errno::<i32>("EINVAL")?;
+            return Ok(None);
+        }
+        let sockaddr = MPlaceTy::from_aligned_ptr(addr, layout);
+        let family = this.mplace_field_named(&sockaddr, "sun_family")?;
+        let family = this.read_scalar(&family.into())?.to_bits(family.layout.size)?;
+        if family != u128::try_from(this.eval_libc_i32("AF_UNIX")?).unwrap() {
+            this.fail_with_errno::<i32>("EINVAL")?;
+            return Ok(None);
+        }
+        let path_len = Size::from_bytes(addrlen - path_offset.bytes());
+        let mut path = this.read_bytes_ptr(addr.offset(path_offset, this)?, path_len)?.to_vec();
+        // A path ends at the first null byte. Abstract addresses, a Linux extension, start with a
+        // null byte and are as long as `addrlen` says.
+        if path[0] != 0 {
+            if let Some(end) = path.iter().position(|&b| b == 0) {
+                path.truncate(end);
+            }
+        }
+        Ok(Some(path))
+    }
+}
+
+impl<'mir, 'tcx: 'mir> EvalContextExt<'mir, 'tcx> for crate::MiriEvalContext<'mir, 'tcx> {}
+pub trait EvalContextExt<'mir, 'tcx: 'mir>: crate::MiriEvalContextExt<'mir, 'tcx> {
+    fn socket(
+        &mut self,
+        domain_op: &OpTy<'tcx, Tag>,
+        type_op: &OpTy<'tcx, Tag>,
+        protocol_op: &OpTy<'tcx, Tag>,
+    ) -> InterpResult<'tcx, i32> {
+        let this = self.eval_context_mut();
+
+        let domain = this.read_scalar(domain_op)?.to_i32()?;
+        let ty = this.read_scalar(type_op)?.to_i32()?;
+        let protocol = this.read_scalar(protocol_op)?.to_i32()?;
+
+        let (status_flags, fd_flags) = match this.check_socket_args(domain, ty, protocol)? {
+            Some(flags) => flags,
+            None => return Ok(-1),
+        };
+        let socket = Socket::new(SocketState::Unconnected { address: None });
+        Ok(this.machine.file_handler.insert_fd(Box::new(socket), status_flags, fd_flags))
+    }
+
+    fn socketpair(
+        &mut self,
+        domain_op: &OpTy<'tcx, Tag>,
+        type_op: &OpTy<'tcx, Tag>,
+        protocol_op: &OpTy<'tcx, Tag>,
+        sv_op: &OpTy<'tcx, Tag>,
+    ) -> InterpResult<'tcx, i32> {
+        let this = self.eval_context_mut();
+
+        let domain = this.read_scalar(domain_op)?.to_i32()?;
+        let ty = this.read_scalar(type_op)?.to_i32()?;
+        let protocol = this.read_scalar(protocol_op)?.to_i32()?;
+        let sv = this.read_pointer(sv_op)?;
+
+        let (status_flags, fd_flags) = match this.check_socket_args(domain, ty, protocol)? {
+            Some(flags) => flags,
+            None => return Ok(-1),
+        };
+        let (a, b) = Connection::pair();
+        let int_layout = this.machine.layouts.i32;
+        for (i, connection) in [a, b].into_iter().enumerate() {
+            let socket = Socket::new(SocketState::Connected(connection));
+            let fd = this.machine.file_handler.insert_fd(Box::new(socket), status_flags, fd_flags);
+            let ptr = sv.offset(int_layout.size * u64::try_from(i).unwrap(), this)?;
+            let fd_place = MPlaceTy::from_aligned_ptr(ptr, int_layout);
+            this.write_scalar(Scalar::from_i32(fd), &fd_place.into())?;
+        }
+        Ok(0)
+    }
+
+    fn bind(
+        &mut self,
+        fd_op: &OpTy<'tcx, Tag>,
+        addr_op: &OpTy<'tcx, Tag>,
+        addrlen_op: &OpTy<'tcx, Tag>,
+    ) -> InterpResult<'tcx, i32> {
+        let this = self.eval_context_mut();
+
+        let fd = this.read_scalar(fd_op)?.to_i32()?;
+        let (socket, _) = match this.socket_of(fd)? {
+            Some(socket) => socket,
+            None => return Ok(-1),
+        };
+        let address = match this.read_socket_address(addr_op, addrlen_op)? {
+            Some(address) => address,
+            None => return Ok(-1),
+        };
+
+        if !matches!(&*socket.state.borrow(), SocketState::Unconnected { address: None }) {
+            return this.fail_with_errno("EINVAL");
+        }
+        let addresses = &mut this.machine.file_handler.socket_addresses;
+        if addresses.get(&address).map_or(false, |bound| bound.strong_count() > 0) {
+            return this.fail_with_errno("EADDRINUSE");
+        }
+        addresses.insert(address.clone(), Rc::downgrade(&socket.state));
+        *socket.state.borrow_mut() = SocketState::Unconnected { address: Some(address) };
+        Ok(0)
+    }
+
+    fn listen(
+        &mut self,
+        fd_op: &OpTy<'tcx, Tag>,
+        _backlog_op: &OpTy<'tcx, Tag>,
+    ) -> InterpResult<'tcx, i32> {
+        let this = self.eval_context_mut();
+
+        let fd = this.read_scalar(fd_op)?.to_i32()?;
+        let (socket, _) = match this.socket_of(fd)? {
+            Some(socket) => socket,
+            None => return Ok(-1),
+        };
+
+        if matches!(&*socket.state.borrow(), SocketState::Connected(_)) {
+            return this.fail_with_errno("EINVAL");
+        }
+        let mut state = socket.state.borrow_mut();
+        if let SocketState::Unconnected { .. } = *state {
+            *state = SocketState::Listening { backlog: VecDeque::new() };
+        }
+        Ok(0)
+    }
+
+    fn connect(
+        &mut self,
+        fd_op: &OpTy<'tcx, Tag>,
+        addr_op: &OpTy<'tcx, Tag>,
+        addrlen_op: &OpTy<'tcx, Tag>,
+    ) -> InterpResult<'tcx, i32> {
+        let this = self.eval_context_mut();
+
+        let fd = this.read_scalar(fd_op)?.to_i32()?;
+        let (socket, _) = match this.socket_of(fd)? {
+            Some(socket) => socket,
+            None => return Ok(-1),
+        };
+        let address = match this.read_socket_address(addr_op, addrlen_op)? {
+            Some(address) => address,
+            None => return Ok(-1),
+        };
+
+        let error = match &*socket.state.borrow() {
+            SocketState::Unconnected { .. } => None,
+            SocketState::Listening { .. } => Some("EINVAL"),
+            SocketState::Connected(_) => Some("EISCONN"),
+        };
+        if let Some(error) = error {
+            return this.fail_with_errno(error);
+        }
+        let listener = match this.machine.file_handler.socket_addresses.get(&address) {
+            Some(bound) => bound.upgrade(),
+            None => return this.fail_with_errno("ENOENT"),
+        };
+        let (client, server) = Connection::pair();
+        // Everything the connecting thread did before happens before the connection is accepted.
+        if let Some(data_race) = &this.machine.data_race {
+            let active_thread = this.get_active_thread();
+            let mut outgoing = client.outgoing.borrow_mut();
+            data_race.validate_lock_release_shared(&mut outgoing.clock, active_thread);
+        }
+        let queued = listener.map_or(false, |listener| {
+            match &mut *listener.borrow_mut() {
+                SocketState::Listening { backlog } => {
+                    backlog.push_back(server);
+                    true
+                }
+                _ => false,
+            }
+        });
+        if !queued {
+            return this.fail_with_errno("ECONNREFUSED");
+        }
+        *socket.state.borrow_mut() = SocketState::Connected(client);
+        this.notify_readiness()?;
+        Ok(0)
+    }
+
+    /// `accept` and `accept4`. `flags_op` is `None` for `accept`.
+    fn accept4(
+        &mut self,
+        fd_op: &OpTy<'tcx, Tag>,
+        addr_op: &OpTy<'tcx, Tag>,
+        addrlen_op: &OpTy<'tcx, Tag>,
+        flags_op: Option<&OpTy<'tcx, Tag>>,
+        dest: &PlaceTy<'tcx, Tag>,
+    ) -> InterpResult<'tcx> {
+        let this = self.eval_context_mut();
+
+        let fd = this.read_scalar(fd_op)?.to_i32()?;
+        let addr = this.read_pointer(addr_op)?;
+        let addrlen = this.read_pointer(addrlen_op)?;
+        let flags = match flags_op {
+            Some(flags_op) => this.read_scalar(flags_op)?.to_i32()?,
+            None => 0,
+        };
+
+        this.accept_connection(fd, addr, addrlen, flags, dest)
+    }
+
+    /// Accepts a connection on the listening socket `fd`, and writes the new file descriptor to
+    /// `dest`. If there is no connection yet and `fd` is in blocking mode, the thread blocks until
+    /// there is.
+    fn accept_connection(
+        &mut self,
+        fd: i32,
+        addr: Pointer<Option<Tag>>,
+        addrlen: Pointer<Option<Tag>>,
+        flags: i32,
+        dest: &PlaceTy<'tcx, Tag>,
+    ) -> InterpResult<'tcx> {
+        let this = self.eval_context_mut();
+
+        let (socket, blocking) = match this.socket_of(fd)? {
+            Some(socket) => socket,
+            None => return this.write_scalar(Scalar::from_i32(-1), dest),
+        };
+        let (other_flags, status_flags, fd_flags) = this.socket_type_flags(flags)?;
+        let pending = match &mut *socket.state.borrow_mut() {
+            SocketState::Listening { backlog } if other_flags == 0 => Some(backlog.pop_front()),
+            _ => None,
+        };
+        let connection = match pending {
+            Some(Some(pending)) => pending,
+            Some(None) if blocking => {
+                // The accept finishes in `notify_readiness` once a connection was made.
+                let active_thread = this.get_active_thread();
+                this.block_thread(active_thread);
+                let op = BlockedOp::Accept { addr, addrlen, flags };
+                this.machine.file_handler.block_until_ready(active_thread, fd, op, dest);
+                return Ok(());
+            }
+            Some(None) => {
+                let result = this.fail_with_errno("EAGAIN")?;
+                return this.write_scalar(Scalar::from_i32(result), dest);
+            }
+            None => {
+                let result = this.fail_with_errno("EINVAL")?;
+                return this.write_scalar(Scalar::from_i32(result), dest);
+            }
+        };
+
+        if let Some(data_race) = &this.machine.data_race {
+            let incoming = connection.incoming.borrow();
+            data_race.validate_lock_acquire(&incoming.clock, this.get_active_thread());
+        }
+        let socket = Socket::new(SocketState::Connected(connection));
+        let new_fd = this.machine.file_handler.insert_fd(Box::new(socket), status_flags, fd_flags);
+
+        // The peer is always unnamed, so its address consists of just the family.
+        if !this.ptr_is_null(addr)? {
+            let addrlen_place =
+                MPlaceTy::from_aligned_ptr(addrlen, this.libc_ty_layout("socklen_t")?);
+            let len = this.read_scalar(&addrlen_place.into())?.to_u32()?;
+            let path_offset = this.sun_path_offset()?;
+            if u64::from(len) >= path_offset.bytes() {
+                let sockaddr =
+                    MPlaceTy::from_aligned_ptr(addr, this.libc_ty_layout("sockaddr_un")?);
+                let af_unix = this.eval_libc_i32("AF_UNIX")?;
+                this.write_int_fields_named(&[("sun_family", af_unix.into())], &sockaddr)?;
+            }
+            let path_offset = u32::try_from(path_offset.bytes()).unwrap();
+            this.write_scalar(Scalar::from_u32(path_offset), &addrlen_place.into())?;
+        }
+        this.write_scalar(Scalar::from_i32(new_fd), dest)
+    }
+
+    fn shutdown(
+        &mut self,
+        fd_op: &OpTy<'tcx, Tag>,
+        how_op: &OpTy<'tcx, Tag>,
+    ) -> InterpResult<'tcx, i32> {
+        let this = self.eval_context_mut();
+
+        let fd = this.read_scalar(fd_op)?.to_i32()?;
+        let how = this.read_scalar(how_op)?.to_i32()?;
+
+        let (socket, _) = match this.socket_of(fd)? {
+            Some(socket) => socket,
+            None => return Ok(-1),
+        };
+        let (read, write) = if how == this.eval_libc_i32("SHUT_RD")? {
+            (true, false)
+        } else if how == this.eval_libc_i32("SHUT_WR")? {
+            (false, true)
+        } else if how == this.eval_libc_i32("SHUT_RDWR")? {
+            (true, true)
+        } else {
+            return this.fail_with_errno("EINVAL");
+        };
+        match &*socket.state.borrow() {
+            SocketState::Connected(connection) => connection.shutdown(read, write),
+            _ => return this.fail_with_errno("ENOTCONN"),
+        }
+        // The peer may now read the end of the data.
+        this.notify_readiness()?;
+        Ok(0)
+    }
+
+    fn send(
+        &mut self,
+        fd_op: &OpTy<'tcx, Tag>,
+        buf_op: &OpTy<'tcx, Tag>,
+        len_op: &OpTy<'tcx, Tag>,
+        flags_op: &OpTy<'tcx, Tag>,
+        dest: &PlaceTy<'tcx, Tag>,
+    ) -> InterpResult<'tcx> {
+        let this = self.eval_context_mut();
+
+        let fd = this.read_scalar(fd_op)?.to_i32()?;
+        let buf = this.read_pointer(buf_op)?;
+        let len = this.read_scalar(len_op)?.to_machine_usize(this)?;
+        let flags = this.read_scalar(flags_op)?.to_i32()?;
+
+        // `SIGPIPE` is never raised, so `MSG_NOSIGNAL` makes no difference.
+        let msg_nosignal = if this.tcx.sess.target.os == "linux" {
+            this.eval_libc_i32("MSG_NOSIGNAL")?
+        } else {
+            0
+        };
+        if flags & !msg_nosignal != 0 {
+            throw_unsup_format!("`send` flags {:#x} are not supported", flags);
+        }
+        if this.socket_of(fd)?.is_none() {
+            return this.write_scalar(Scalar::from_machine_isize(-1, this), dest);
+        }
+        this.write(fd, buf, len, dest)
+    }
+
+    fn recv(
+        &mut self,
+        fd_op: &OpTy<'tcx, Tag>,
+        buf_op: &OpTy<'tcx, Tag>,
+        len_op: &OpTy<'tcx, Tag>,
+        flags_op: &OpTy<'tcx, Tag>,
+        dest: &PlaceTy<'tcx, Tag>,
+    ) -> InterpResult<'tcx> {
+        let this = self.eval_context_mut();
+
+        let fd = this.read_scalar(fd_op)?.to_i32()?;
+        let buf = this.read_pointer(buf_op)?;
+        let len = this.read_scalar(len_op)?.to_machine_usize(this)?;
+        let flags = this.read_scalar(flags_op)?.to_i32()?;
+
+        if flags != 0 {
+            throw_unsup_format!("`recv` flags {:#x} are not supported", flags);
+        }
+        if this.socket_of(fd)?.is_none() {
+            return this.write_scalar(Scalar::from_machine_isize(-1, this), dest);
+        }
+        this.read(fd, buf, len, dest)
+    }
+
+    fn setsockopt(
+        &mut self,
+        fd_op: &OpTy<'tcx, Tag>,
+        level_op: &OpTy<'tcx, Tag>,
+        name_op: &OpTy<'tcx, Tag>,
+        _value_op: &OpTy<'tcx, Tag>,
+        _len_op: &OpTy<'tcx, Tag>,
+    ) -> InterpResult<'tcx, i32> {
+        let this = self.eval_context_mut();
+
+        let fd = this.read_scalar(fd_op)?.to_i32()?;
+        let level = this.read_scalar(level_op)?.to_i32()?;
+        let name = this.read_scalar(name_op)?.to_i32()?;
+
+        // std sets `SO_NOSIGPIPE` on every socket on macOS. `SIGPIPE` is never raised, so there is
+        // nothing to do for it.
+        if this.tcx.sess.target.os != "macos"
+            || level != this.eval_libc_i32("SOL_SOCKET")?
+            || name != this.eval_libc_i32("SO_NOSIGPIPE")?
+        {
+            throw_unsup_format!("`setsockopt` level {} option {} is not supported", level, name);
+        }
+        if this.socket_of(fd)?.is_none() {
+            return Ok(-1);
+        }
+        Ok(0)
+    }
+}
@@ -0,0 +1,105 @@
+//! System identification: `uname` and `gethostname`.
+//!
+//! The values are derived from the target rather than the host, so that they are the same with and
+//! without isolation. The host name and kernel release can be set with `-Zmiri-hostname` and
+//! `-Zmiri-kernel-release` for programs that depend on them.
+
+use std::ffi::OsStr;
+
+use rustc_target::abi::Endian;
+
+use crate::*;
+
+/// The host name, unless it is set with `-Zmiri-hostname`.
+pub const DEFAULT_HOSTNAME: &str = "miri";
+
+impl<'mir, 'tcx: 'mir> EvalContextExtPrivate<'mir, 'tcx> for crate::MiriEvalContext<'mir, 'tcx> {}
+trait EvalContextExtPrivate<'mir, 'tcx: 'mir>: crate::MiriEvalContextExt<'mir, 'tcx> {
+    /// The kernel release, unless it is set with `-Zmiri-kernel-release`.
+    fn default_kernel_release(&self) -> &'static str {
+        let this = self.eval_context_ref();
+        match &*this.tcx.sess.target.os {
+            "linux" => "5.15.0",
+            "macos" => "21.0.0",
+            os => bug!("`uname` is not available on {}", os),
+        }
+    }
+
+    /// The hardware identifier, as reported by `uname -m` on the target OS.
+    fn machine_name(&self) -> String {
+        let this = self.eval_context_ref();
+        let target = &this.tcx.sess.target;
+        match (&*target.os, &*target.arch) {
+            ("macos", "aarch64") => "arm64".to_owned(),
+            (_, "x86") => "i686".to_owned(),
+            (_, "powerpc64") if target.endian == Endian::Little => "ppc64le".to_owned(),
+            (_, "powerpc64") => "ppc64".to_owned(),
+            (_, arch) => arch.to_owned(),
+        }
+    }
+
+    /// Write `value` to the `char` array `field` of the `utsname` struct. The values have been
+    /// checked to fit when they were configured.
+    fn write_utsname_field(
+        &mut self,
+        utsname: &MPlaceTy<'tcx, Tag>,
+        field: &str,
+        value: &str,
+    ) -> InterpResult<'tcx> {
+        let this = self.eval_context_mut();
+        let field = this.mplace_field_named(utsname, field)?;
+        let (complete, _) =
+            this.write_os_str_to_c_str(OsStr::new(value), field.ptr, field.layout.size.bytes())?;
+        assert!(complete, "`{}` does not fit into `utsname`", value);
+        Ok(())
+    }
+}
+
+impl<'mir, 'tcx: 'mir> EvalContextExt<'mir, 'tcx> for crate::MiriEvalContext<'mir, 'tcx> {}
+pub trait EvalContextExt<'mir, 'tcx: 'mir>: crate::MiriEvalContextExt<'mir, 'tcx> {
+    fn uname(&mut self, buf_op: &OpTy<'tcx, Tag>) -> InterpResult<'tcx, i32> {
+        let this = self.eval_context_mut();
+
+        let buf = this.deref_operand(buf_op)?;
+        let release = match &this.machine.kernel_release {
+            Some(release) => release.clone(),
+            None => this.default_kernel_release().to_owned(),
+        };
+        let (sysname, version) = match &*this.tcx.sess.target.os {
+            "linux" => ("Linux", "#1 SMP".to_owned()),
+            "macos" => ("Darwin", format!("Darwin Kernel Version {}", release)),
+            os => bug!("`uname` is not available on {}", os),
+        };
+        let hostname = this.machine.hostname.clone();
+        let machine = this.machine_name();
+
+        this.write_utsname_field(&buf, "sysname", sysname)?;
+        this.write_utsname_field(&buf, "nodename", &hostname)?;
+        this.write_utsname_field(&buf, "release", &release)?;
+        this.write_utsname_field(&buf, "version", &version)?;
+        this.write_utsname_field(&buf, "machine", &machine)?;
+        if this.tcx.sess.target.os == "linux" {
+            this.write_utsname_field(&buf, "domainname", "(none)")?;
+        }
+        Ok(0)
+    }
+
+    fn gethostname(
+        &mut self,
+        name_op: &OpTy<'tcx, Tag>,
+        len_op: &OpTy<'tcx, Tag>,
+    ) -> InterpResult<'tcx, i32> {
+        let this = self.eval_context_mut();
+
+        let name = this.read_pointer(name_op)?;
+        let len = this.read_scalar(len_op)?.to_machine_usize(this)?;
+        let hostname = this.machine.hostname.clone();
+        let (complete, _) = this.write_os_str_to_c_str(OsStr::new(&hostname), name, len)?;
+        if !complete {
+            let enametoolong = this.eval_libc("ENAMETOOLONG")?;
+            this.set_last_error(enametoolong)?;
+            return Ok(-1);
+        }
+        Ok(0)
+    }
+}
@@ -1,6 +1,9 @@
 use crate::*;
 use rustc_middle::ty::layout::LayoutOf;
-use rustc_target::spec::abi::Abi;
+use rustc_target::{
+    abi::{Endian, Size},
+    spec::abi::Abi,
+};
 
 impl<'mir, 'tcx> EvalContextExt<'mir, 'tcx> for crate::MiriEvalContext<'mir, 'tcx> {}
 pub trait EvalContextExt<'mir, 'tcx: 'mir>: crate::MiriEvalContextExt<'mir, 'tcx> {
@@ -18,7 +21,7 @@ pub trait EvalContextExt<'mir, 'tcx: 'mir>: crate::MiriEvalContextExt<'mir, 'tcx
         );
 
         // Create the new thread
-        let new_thread_id = this.create_thread();
+        let new_thread_id = this.create_thread()?;
 
         // Write the current thread-id, switch to the next thread later
         // to treat this write operation as occuring on the current thread.
@@ -95,6 +98,15 @@ pub trait EvalContextExt<'mir, 'tcx: 'mir>: crate::MiriEvalContextExt<'mir, 'tcx
         this.write_scalar(Scalar::from_uint(thread_id.to_u32(), dest.layout.size), dest)
     }
 
+    /// The kernel-level id of the active thread. The main thread has the id of the process.
+    fn gettid(&self) -> i32 {
+        let this = self.eval_context_ref();
+        this.assert_target_os("linux", "gettid");
+
+        let thread_id = this.get_active_thread();
+        i32::try_from(PROCESS_ID + thread_id.to_u32()).unwrap()
+    }
+
     fn prctl(&mut self, args: &[OpTy<'tcx, Tag>]) -> InterpResult<'tcx, i32> {
         let this = self.eval_context_mut();
         this.assert_target_os("linux", "prctl");
@@ -131,9 +143,10 @@ pub trait EvalContextExt<'mir, 'tcx: 'mir>: crate::MiriEvalContextExt<'mir, 'tcx
             }
 
             let address = this.read_pointer(&args[1])?;
+            // Like Linux, return at most 15 bytes of the name, plus the null terminator.
             let mut name = this.get_active_thread_name().to_vec();
+            name.truncate(15);
             name.push(0u8);
-            assert!(name.len() <= 16);
             this.write_bytes_ptr(address, name)?;
         } else {
             throw_unsup_format!("unsupported prctl option {}", option);
@@ -159,4 +172,122 @@ pub trait EvalContextExt<'mir, 'tcx: 'mir>: crate::MiriEvalContextExt<'mir, 'tcx
 
         Ok(0)
     }
+
+    fn sched_getaffinity(
+        &mut self,
+        pid: &OpTy<'tcx, Tag>,
+        cpusetsize: &OpTy<'tcx, Tag>,
+        mask: &OpTy<'tcx, Tag>,
+    ) -> InterpResult<'tcx, i32> {
+        let this = self.eval_context_mut();
+
+        this.assert_target_os("linux", "sched_getaffinity");
+
+        let cpusetsize = match this.check_cpu_affinity_args(pid, cpusetsize)? {
+            Some(cpusetsize) => cpusetsize,
+            None => return Ok(-1),
+        };
+        let mask = this.read_pointer(mask)?;
+
+        // Report the mask the thread set itself, or else all the CPUs the machine has.
+        let mut bytes = match this.get_active_thread_cpu_affinity() {
+            Some(affinity) => affinity.to_vec(),
+            None => default_cpu_affinity(),
+        };
+        let cpusetsize = usize::try_from(cpusetsize).unwrap();
+        bytes.resize(cpusetsize, 0);
+        this.cpu_set_to_target_byte_order(&mut bytes)?;
+        // Only the first `cpusetsize` bytes of a padded `c_ulong` are written.
+        bytes.truncate(cpusetsize);
+        this.write_bytes_ptr(mask, bytes)?;
+
+        Ok(0)
+    }
+
+    fn sched_setaffinity(
+        &mut self,
+        pid: &OpTy<'tcx, Tag>,
+        cpusetsize: &OpTy<'tcx, Tag>,
+        mask: &OpTy<'tcx, Tag>,
+    ) -> InterpResult<'tcx, i32> {
+        let this = self.eval_context_mut();
+
+        this.assert_target_os("linux", "sched_setaffinity");
+
+        let cpusetsize = match this.check_cpu_affinity_args(pid, cpusetsize)? {
+            Some(cpusetsize) => cpusetsize,
+            None => return Ok(-1),
+        };
+        let mask = this.read_pointer(mask)?;
+        let mut bytes = this.read_bytes_ptr(mask, Size::from_bytes(cpusetsize))?.to_vec();
+        // Swapping the bytes of each `c_ulong` is its own inverse.
+        this.cpu_set_to_target_byte_order(&mut bytes)?;
+
+        // Like Linux, ignore the CPUs that do not exist, and reject masks without any CPU left.
+        let default = default_cpu_affinity();
+        bytes.truncate(default.len());
+        bytes.iter_mut().zip(default).for_each(|(byte, existing)| *byte &= existing);
+        if bytes.iter().all(|&byte| byte == 0) {
+            let einval = this.eval_libc("EINVAL")?;
+            this.set_last_error(einval)?;
+            return Ok(-1);
+        }
+
+        // We do not actually pin threads to CPUs, we just remember the mask.
+        this.set_active_thread_cpu_affinity(bytes);
+
+        Ok(0)
+    }
+
+    /// Masks are stored with CPU `n` in bit `n % 8` of byte `n / 8`. A `cpu_set_t` is made of
+    /// `c_ulong`s that have CPU `n` in bit `n % bits` of `c_ulong` number `n / bits`, which is the
+    /// same on little-endian targets, but on big-endian targets the bytes of each `c_ulong` are in
+    /// the opposite order. A set that ends in the middle of a `c_ulong` is padded with zeros to
+    /// a whole `c_ulong` first.
+    fn cpu_set_to_target_byte_order(&self, bytes: &mut Vec<u8>) -> InterpResult<'tcx> {
+        let this = self.eval_context_ref();
+        if this.tcx.sess.target.endian == Endian::Big {
+            let ulong_size = this.libc_ty_layout("c_ulong")?.size.bytes_usize();
+            bytes.resize((bytes.len() + ulong_size - 1) / ulong_size * ulong_size, 0);
+            bytes.chunks_mut(ulong_size).for_each(|ulong| ulong.reverse());
+        }
+        Ok(())
+    }
+
+    /// Checks the `pid` and `cpusetsize` arguments shared by `sched_getaffinity` and
+    /// `sched_setaffinity`. Returns the size of the CPU set, or `None` if an error was
+    /// stored in `errno`.
+    fn check_cpu_affinity_args(
+        &mut self,
+        pid: &OpTy<'tcx, Tag>,
+        cpusetsize: &OpTy<'tcx, Tag>,
+    ) -> InterpResult<'tcx, Option<u64>> {
+        let this = self.eval_context_mut();
+
+        let pid = this.read_scalar(pid)?.to_i32()?;
+        let cpusetsize = this.read_scalar(cpusetsize)?.to_machine_usize(this)?;
+
+        // We only support querying the current thread.
+        if pid != 0 {
+            let esrch = this.eval_libc("ESRCH")?;
+            this.set_last_error(esrch)?;
+            return Ok(None);
+        }
+        // The set has to be large enough to hold all CPUs.
+        if cpusetsize < u64::try_from(default_cpu_affinity().len()).unwrap() {
+            let einval = this.eval_libc("EINVAL")?;
+            this.set_last_error(einval)?;
+            return Ok(None);
+        }
+        Ok(Some(cpusetsize))
+    }
+}
+
+/// The CPU affinity mask of a thread that did not set one: all of the `NUM_CPUS` CPUs.
+fn default_cpu_affinity() -> Vec<u8> {
+    let mut bytes = vec![0u8; usize::try_from((NUM_CPUS + 7) / 8).unwrap()];
+    for cpu in 0..usize::try_from(NUM_CPUS).unwrap() {
+        bytes[cpu / 8] |= 1 << (cpu % 8);
+    }
+    bytes
 }
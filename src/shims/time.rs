@@ -3,6 +3,47 @@ use std::time::{Duration, Instant, SystemTime};
 use crate::*;
 use thread::Time;
 
+const SECS_PER_DAY: i64 = 24 * 60 * 60;
+const ERROR_INVALID_PARAMETER: u32 = 87;
+
+/// The number of days from 1970-01-01 to the given date of the proleptic Gregorian calendar.
+/// See <http://howardhinnant.github.io/date_algorithms.html#days_from_civil>.
+fn days_from_civil(year: i64, month: i64, day: i64) -> i64 {
+    let year = if month <= 2 { year - 1 } else { year };
+    let era = year.div_euclid(400);
+    let year_of_era = year - era * 400;
+    let day_of_year = (153 * (if month > 2 { month - 3 } else { month + 9 }) + 2) / 5 + day - 1;
+    let day_of_era = year_of_era * 365 + year_of_era / 4 - year_of_era / 100 + day_of_year;
+    era * 146097 + day_of_era - 719468
+}
+
+/// The date `(year, month, day)` that is the given number of days after 1970-01-01. This is the
+/// inverse of `days_from_civil`.
+fn civil_from_days(days: i64) -> (i64, i64, i64) {
+    let days = days + 719468;
+    let era = days.div_euclid(146097);
+    let day_of_era = days - era * 146097;
+    let year_of_era =
+        (day_of_era - day_of_era / 1460 + day_of_era / 36524 - day_of_era / 146096) / 365;
+    let day_of_year = day_of_era - (365 * year_of_era + year_of_era / 4 - year_of_era / 100);
+    let mp = (5 * day_of_year + 2) / 153;
+    let day = day_of_year - (153 * mp + 2) / 5 + 1;
+    let month = if mp < 10 { mp + 3 } else { mp - 9 };
+    let year = year_of_era + era * 400 + i64::from(month <= 2);
+    (year, month, day)
+}
+
+/// The abbreviation of the time zone with the given offset from UTC, as used in `tm_zone`.
+fn time_zone_name(utc_offset: i64) -> String {
+    if utc_offset == 0 {
+        "UTC".to_owned()
+    } else {
+        let sign = if utc_offset < 0 { '-' } else { '+' };
+        let minutes = utc_offset.abs() / 60;
+        format!("{}{:02}{:02}", sign, minutes / 60, minutes % 60)
+    }
+}
+
 /// Returns the time elapsed between the provided time and the unix epoch as a `Duration`.
 pub fn system_time_to_duration<'tcx>(time: &SystemTime) -> InterpResult<'tcx, Duration> {
     time.duration_since(SystemTime::UNIX_EPOCH)
@@ -19,16 +60,21 @@ pub trait EvalContextExt<'mir, 'tcx: 'mir>: crate::MiriEvalContextExt<'mir, 'tcx
         let this = self.eval_context_mut();
 
         this.assert_target_os("linux", "clock_gettime");
-        this.check_no_isolation("`clock_gettime`")?;
 
         let clk_id = this.read_scalar(clk_id_op)?.to_i32()?;
 
-        let duration = if clk_id == this.eval_libc_i32("CLOCK_REALTIME")? {
-            system_time_to_duration(&SystemTime::now())?
+        // CPU time is derived from the interpreted steps, so it does not require host access.
+        let duration = if clk_id == this.eval_libc_i32("CLOCK_PROCESS_CPUTIME_ID")? {
+            this.process_cpu_time()
+        } else if clk_id == this.eval_libc_i32("CLOCK_THREAD_CPUTIME_ID")? {
+            this.thread_cpu_time()
+        } else if clk_id == this.eval_libc_i32("CLOCK_REALTIME")? {
+            this.check_no_isolation("`clock_gettime`")?;
+            system_time_to_duration(&this.machine.clock.read_system_time())?
         } else if clk_id == this.eval_libc_i32("CLOCK_MONOTONIC")? {
             // Absolute time does not matter, only relative time does, so we can just
-            // use our own time anchor here.
-            Instant::now().duration_since(this.machine.time_anchor)
+            // use our own time anchor here. With isolation, this is the virtual clock.
+            this.machine.clock.elapsed()
         } else {
             let einval = this.eval_libc("EINVAL")?;
             this.set_last_error(einval)?;
@@ -38,7 +84,16 @@ pub trait EvalContextExt<'mir, 'tcx: 'mir>: crate::MiriEvalContextExt<'mir, 'tcx
         let tv_sec = duration.as_secs();
         let tv_nsec = duration.subsec_nanos();
 
-        this.write_int_fields(&[tv_sec.into(), tv_nsec.into()], &this.deref_operand(tp_op)?)?;
+        // With a 32-bit `time_t`, times after 2038 do not fit.
+        let tp = this.deref_operand(tp_op)?;
+        if !this.write_int_fields_named_checked(
+            &[("tv_sec", tv_sec.into()), ("tv_nsec", tv_nsec.into())],
+            &tp,
+        )? {
+            let eoverflow = this.eval_libc("EOVERFLOW")?;
+            this.set_last_error(eoverflow)?;
+            return Ok(-1);
+        }
 
         Ok(0)
     }
@@ -50,52 +105,363 @@ pub trait EvalContextExt<'mir, 'tcx: 'mir>: crate::MiriEvalContextExt<'mir, 'tcx
     ) -> InterpResult<'tcx, i32> {
         let this = self.eval_context_mut();
 
-        this.assert_target_os("macos", "gettimeofday");
-        this.check_no_isolation("`gettimeofday`")?;
-
-        // Using tz is obsolete and should always be null
+        // Using tz is obsolete, but if it is given, we report the UTC offset and no DST.
         let tz = this.read_pointer(tz_op)?;
         if !this.ptr_is_null(tz)? {
-            let einval = this.eval_libc("EINVAL")?;
-            this.set_last_error(einval)?;
-            return Ok(-1);
+            let minutes_west = -this.machine.utc_offset / 60;
+            this.write_int_fields_named(
+                &[("tz_minuteswest", minutes_west.into()), ("tz_dsttime", 0)],
+                &this.deref_operand(tz_op)?,
+            )?;
         }
 
-        let duration = system_time_to_duration(&SystemTime::now())?;
+        let duration = system_time_to_duration(&this.machine.clock.read_system_time())?;
         let tv_sec = duration.as_secs();
         let tv_usec = duration.subsec_micros();
 
-        this.write_int_fields(&[tv_sec.into(), tv_usec.into()], &this.deref_operand(tv_op)?)?;
+        let tv = this.deref_operand(tv_op)?;
+        if !this.write_int_fields_named_checked(
+            &[("tv_sec", tv_sec.into()), ("tv_usec", tv_usec.into())],
+            &tv,
+        )? {
+            let eoverflow = this.eval_libc("EOVERFLOW")?;
+            this.set_last_error(eoverflow)?;
+            return Ok(-1);
+        }
 
         Ok(0)
     }
 
-    #[allow(non_snake_case)]
-    fn GetSystemTimeAsFileTime(&mut self, LPFILETIME_op: &OpTy<'tcx, Tag>) -> InterpResult<'tcx> {
+    fn times(&mut self, buf_op: &OpTy<'tcx, Tag>) -> InterpResult<'tcx, i64> {
+        let this = self.eval_context_mut();
+
+        // We report clock ticks of 10ms, matching `sysconf(_SC_CLK_TCK)`.
+        let ticks = |duration: Duration| i128::try_from(duration.as_millis() / 10).unwrap();
+        // All CPU time is user time, and there are no child processes.
+        let user = ticks(this.process_cpu_time());
+        // The return value is the elapsed time since an arbitrary point in the past. The clock
+        // does not follow the host clock with isolation enabled, so this needs no host access.
+        let elapsed = ticks(this.machine.clock.elapsed());
+        // A 32-bit `clock_t` overflows after some months.
+        let clock_t = this.libc_ty_layout("clock_t")?;
+        if elapsed > clock_t.size.signed_int_max()
+            || !this.write_int_fields_named_checked(
+                &[("tms_utime", user), ("tms_stime", 0), ("tms_cutime", 0), ("tms_cstime", 0)],
+                &this.deref_operand(buf_op)?,
+            )?
+        {
+            let eoverflow = this.eval_libc("EOVERFLOW")?;
+            this.set_last_error(eoverflow)?;
+            return Ok(-1);
+        }
+        Ok(i64::try_from(elapsed).unwrap())
+    }
+
+    fn getrusage(
+        &mut self,
+        who_op: &OpTy<'tcx, Tag>,
+        usage_op: &OpTy<'tcx, Tag>,
+    ) -> InterpResult<'tcx, i32> {
         let this = self.eval_context_mut();
 
-        this.assert_target_os("windows", "GetSystemTimeAsFileTime");
-        this.check_no_isolation("`GetSystemTimeAsFileTime`")?;
+        let who = this.read_scalar(who_op)?.to_i32()?;
+        let cpu_time = if who == this.eval_libc_i32("RUSAGE_SELF")? {
+            this.process_cpu_time()
+        } else if who == this.eval_libc_i32("RUSAGE_CHILDREN")? {
+            Duration::ZERO
+        } else if this.tcx.sess.target.os == "linux"
+            && who == this.eval_libc_i32("RUSAGE_THREAD")?
+        {
+            this.thread_cpu_time()
+        } else {
+            let einval = this.eval_libc("EINVAL")?;
+            this.set_last_error(einval)?;
+            return Ok(-1);
+        };
+
+        // Everything but the user time is reported as zero.
+        let usage = this.deref_operand(usage_op)?;
+        this.write_bytes_ptr(usage.ptr, vec![0; usage.layout.size.bytes_usize()])?;
+        if !this.write_int_fields_named_checked(
+            &[("tv_sec", cpu_time.as_secs().into()), ("tv_usec", cpu_time.subsec_micros().into())],
+            &this.mplace_field_named(&usage, "ru_utime")?,
+        )? {
+            let eoverflow = this.eval_libc("EOVERFLOW")?;
+            this.set_last_error(eoverflow)?;
+            return Ok(-1);
+        }
+        Ok(0)
+    }
+
+    /// Break the `time_t` that `timep_op` points to down into the `struct tm` that `result_op`
+    /// points to, in the time zone with the given offset from UTC in seconds. This is the common
+    /// part of `gmtime_r` and `localtime_r`; there is no daylight saving time.
+    fn break_down_time(
+        &mut self,
+        timep_op: &OpTy<'tcx, Tag>,
+        result_op: &OpTy<'tcx, Tag>,
+        utc_offset: i64,
+        dest: &PlaceTy<'tcx, Tag>,
+    ) -> InterpResult<'tcx> {
+        let this = self.eval_context_mut();
+
+        let time_t_layout = this.libc_ty_layout("time_t")?;
+        let time = this.read_scalar(&this.deref_operand(timep_op)?.into())?;
+        let time = time.to_int(time_t_layout.size)?;
+        // Both the local time and the year (in `tm_year`) may overflow for huge times.
+        let local_and_year = i64::try_from(time)
+            .ok()
+            .and_then(|time| time.checked_add(utc_offset))
+            .and_then(|local| {
+                let (year, month, day) = civil_from_days(local.div_euclid(SECS_PER_DAY));
+                Some((local, year, month, day, i32::try_from(year - 1900).ok()?))
+            });
+        let (local, year, month, day, year_since_1900) = match local_and_year {
+            Some(local_and_year) => local_and_year,
+            None => {
+                let eoverflow = this.eval_libc("EOVERFLOW")?;
+                this.set_last_error(eoverflow)?;
+                this.write_null(dest)?;
+                return Ok(());
+            }
+        };
+        let days = local.div_euclid(SECS_PER_DAY);
+        let secs_of_day = local.rem_euclid(SECS_PER_DAY);
+
+        let result = this.deref_operand(result_op)?;
+        this.write_int_fields_named(
+            &[
+                ("tm_sec", (secs_of_day % 60).into()),
+                ("tm_min", (secs_of_day / 60 % 60).into()),
+                ("tm_hour", (secs_of_day / 3600).into()),
+                ("tm_mday", day.into()),
+                ("tm_mon", (month - 1).into()),
+                ("tm_year", year_since_1900.into()),
+                // 1970-01-01 was a Thursday.
+                ("tm_wday", (days + 4).rem_euclid(7).into()),
+                ("tm_yday", (days - days_from_civil(year, 1, 1)).into()),
+                ("tm_isdst", 0),
+                ("tm_gmtoff", utc_offset.into()),
+            ],
+            &result,
+        )?;
+        let zone = match this.machine.time_zone_names.get(&utc_offset) {
+            Some(&zone) => zone,
+            None => {
+                let name = time_zone_name(utc_offset);
+                let zone =
+                    this.alloc_os_str_as_c_str(name.as_ref(), MiriMemoryKind::Machine.into())?;
+                this.machine.time_zone_names.insert(utc_offset, zone);
+                zone
+            }
+        };
+        this.write_pointer(zone, &this.mplace_field_named(&result, "tm_zone")?.into())?;
+
+        this.write_scalar(Scalar::from_maybe_pointer(result.ptr, this), dest)
+    }
+
+    fn gmtime_r(
+        &mut self,
+        timep_op: &OpTy<'tcx, Tag>,
+        result_op: &OpTy<'tcx, Tag>,
+        dest: &PlaceTy<'tcx, Tag>,
+    ) -> InterpResult<'tcx> {
+        self.break_down_time(timep_op, result_op, 0, dest)
+    }
+
+    fn localtime_r(
+        &mut self,
+        timep_op: &OpTy<'tcx, Tag>,
+        result_op: &OpTy<'tcx, Tag>,
+        dest: &PlaceTy<'tcx, Tag>,
+    ) -> InterpResult<'tcx> {
+        let utc_offset = self.eval_context_ref().machine.utc_offset;
+        self.break_down_time(timep_op, result_op, utc_offset, dest)
+    }
+
+    /// Converts a number of Windows ticks, the 100ns intervals that `FILETIME`s count, to a
+    /// `Duration`.
+    #[allow(non_snake_case)]
+    fn file_time_ticks_to_duration(&self, ticks: u64) -> InterpResult<'tcx, Duration> {
+        let this = self.eval_context_ref();
+
+        let NANOS_PER_SEC = this.eval_windows_u64("time", "NANOS_PER_SEC")?;
+        let INTERVALS_PER_SEC = this.eval_windows_u64("time", "INTERVALS_PER_SEC")?;
+        let NANOS_PER_INTERVAL = NANOS_PER_SEC / INTERVALS_PER_SEC;
+
+        let nanos = ticks % INTERVALS_PER_SEC * NANOS_PER_INTERVAL;
+        Ok(Duration::new(ticks / INTERVALS_PER_SEC, u32::try_from(nanos).unwrap()))
+    }
+
+    /// Converts `time` to a Windows `FILETIME`, the number of 100ns intervals since 1601-01-01.
+    /// The time is rounded down to a multiple of 100ns.
+    #[allow(non_snake_case)]
+    fn system_time_to_file_time(&self, time: &SystemTime) -> InterpResult<'tcx, u64> {
+        let this = self.eval_context_ref();
 
         let NANOS_PER_SEC = this.eval_windows_u64("time", "NANOS_PER_SEC")?;
         let INTERVALS_PER_SEC = this.eval_windows_u64("time", "INTERVALS_PER_SEC")?;
         let INTERVALS_TO_UNIX_EPOCH = this.eval_windows_u64("time", "INTERVALS_TO_UNIX_EPOCH")?;
         let NANOS_PER_INTERVAL = NANOS_PER_SEC / INTERVALS_PER_SEC;
-        let SECONDS_TO_UNIX_EPOCH = INTERVALS_TO_UNIX_EPOCH / INTERVALS_PER_SEC;
 
-        let duration = system_time_to_duration(&SystemTime::now())?
-            + Duration::from_secs(SECONDS_TO_UNIX_EPOCH);
-        let duration_ticks = u64::try_from(duration.as_nanos() / u128::from(NANOS_PER_INTERVAL))
-            .map_err(|_| err_unsup_format!("programs running more than 2^64 Windows ticks after the Windows epoch are not supported"))?;
+        let to_unix_epoch = this.file_time_ticks_to_duration(INTERVALS_TO_UNIX_EPOCH)?;
+        let duration = match time.duration_since(SystemTime::UNIX_EPOCH) {
+            Ok(after_unix_epoch) => to_unix_epoch + after_unix_epoch,
+            Err(before_unix_epoch) =>
+                to_unix_epoch.checked_sub(before_unix_epoch.duration()).ok_or_else(|| {
+                    err_unsup_format!("times before the Windows epoch are not supported")
+                })?,
+        };
+        u64::try_from(duration.as_nanos() / u128::from(NANOS_PER_INTERVAL)).map_err(|_| {
+            err_unsup_format!(
+                "times more than 2^64 Windows ticks after the epoch are not supported"
+            )
+            .into()
+        })
+    }
+
+    /// Converts the Windows `FILETIME` `file_time` to a `SystemTime`. This is the inverse of
+    /// `system_time_to_file_time`.
+    fn file_time_to_system_time(&self, file_time: u64) -> InterpResult<'tcx, SystemTime> {
+        let this = self.eval_context_ref();
+
+        let intervals_to_unix_epoch = this.eval_windows_u64("time", "INTERVALS_TO_UNIX_EPOCH")?;
+        let to_unix_epoch = this.file_time_ticks_to_duration(intervals_to_unix_epoch)?;
+        let since_windows_epoch = this.file_time_ticks_to_duration(file_time)?;
+        let time = if since_windows_epoch >= to_unix_epoch {
+            SystemTime::UNIX_EPOCH.checked_add(since_windows_epoch - to_unix_epoch)
+        } else {
+            SystemTime::UNIX_EPOCH.checked_sub(to_unix_epoch - since_windows_epoch)
+        };
+        time.ok_or_else(|| {
+            err_unsup_format!("`FILETIME` {} cannot be represented on the host", file_time).into()
+        })
+    }
+
+    /// Reads the `FILETIME` at `place`.
+    fn read_file_time(&self, place: &MPlaceTy<'tcx, Tag>) -> InterpResult<'tcx, u64> {
+        let this = self.eval_context_ref();
+
+        let low = this.read_scalar(&this.mplace_field(place, 0)?.into())?.to_u32()?;
+        let high = this.read_scalar(&this.mplace_field(place, 1)?.into())?.to_u32()?;
+        Ok(u64::from(high) << 32 | u64::from(low))
+    }
+
+    /// Writes `file_time` to the `FILETIME` at `place`.
+    fn write_file_time(
+        &mut self,
+        file_time: u64,
+        place: &MPlaceTy<'tcx, Tag>,
+    ) -> InterpResult<'tcx> {
+        let this = self.eval_context_mut();
+
+        // `dwLowDateTime` and `dwHighDateTime`.
+        this.write_int_fields(&[(file_time & 0xFFFFFFFF).into(), (file_time >> 32).into()], place)
+    }
+
+    /// `GetSystemTimeAsFileTime` and `GetSystemTimePreciseAsFileTime`, which are the same since
+    /// the clock of Miri is as precise as a `FILETIME` anyway.
+    #[allow(non_snake_case)]
+    fn GetSystemTimeAsFileTime(
+        &mut self,
+        shim: &str,
+        LPFILETIME_op: &OpTy<'tcx, Tag>,
+    ) -> InterpResult<'tcx> {
+        let this = self.eval_context_mut();
+
+        this.assert_target_os("windows", shim);
+        this.check_no_isolation(&format!("`{}`", shim))?;
+
+        let file_time = this.system_time_to_file_time(&this.machine.clock.read_system_time())?;
+        this.write_file_time(file_time, &this.deref_operand(LPFILETIME_op)?)
+    }
+
+    /// Converts the `SYSTEMTIME` `lpSystemTime_op` to the `FILETIME` `lpFileTime_op`.
+    #[allow(non_snake_case)]
+    fn SystemTimeToFileTime(
+        &mut self,
+        lpSystemTime_op: &OpTy<'tcx, Tag>,
+        lpFileTime_op: &OpTy<'tcx, Tag>,
+    ) -> InterpResult<'tcx, i32> {
+        let this = self.eval_context_mut();
+
+        let INTERVALS_PER_SEC = this.eval_windows_u64("time", "INTERVALS_PER_SEC")?;
+
+        let system_time = this.deref_operand(lpSystemTime_op)?;
+        // `wYear`, `wMonth`, `wDayOfWeek`, `wDay`, `wHour`, `wMinute`, `wSecond`, and
+        // `wMilliseconds`. The day of the week is ignored.
+        let mut fields = [0; 8];
+        for (i, field) in fields.iter_mut().enumerate() {
+            let place = this.mplace_field(&system_time, i)?;
+            *field = i64::from(this.read_scalar(&place.into())?.to_u16()?);
+        }
+        let [year, month, _, day, hour, minute, second, millis] = fields;
+
+        // Invalid dates are those that do not survive a round trip through the day count.
+        let days = days_from_civil(year, month, day);
+        if !(1601..=30827).contains(&year)
+            || !(1..=12).contains(&month)
+            || civil_from_days(days) != (year, month, day)
+            || hour >= 24
+            || minute >= 60
+            || second >= 60
+            || millis >= 1000
+        {
+            this.set_last_error(Scalar::from_u32(ERROR_INVALID_PARAMETER))?;
+            return Ok(0);
+        }
+        let days = days - days_from_civil(1601, 1, 1);
+        let secs = days * SECS_PER_DAY + hour * 60 * 60 + minute * 60 + second;
+        let file_time = u64::try_from(secs).unwrap() * INTERVALS_PER_SEC
+            + u64::try_from(millis).unwrap() * (INTERVALS_PER_SEC / 1000);
+        this.write_file_time(file_time, &this.deref_operand(lpFileTime_op)?)?;
+        Ok(1)
+    }
+
+    /// Converts the `FILETIME` `lpFileTime_op` to the `SYSTEMTIME` `lpSystemTime_op`. This is
+    /// the inverse of `SystemTimeToFileTime`, except that it rounds down to milliseconds.
+    #[allow(non_snake_case)]
+    fn FileTimeToSystemTime(
+        &mut self,
+        lpFileTime_op: &OpTy<'tcx, Tag>,
+        lpSystemTime_op: &OpTy<'tcx, Tag>,
+    ) -> InterpResult<'tcx, i32> {
+        let this = self.eval_context_mut();
+
+        let INTERVALS_PER_SEC = this.eval_windows_u64("time", "INTERVALS_PER_SEC")?;
+
+        let file_time = this.read_file_time(&this.deref_operand(lpFileTime_op)?)?;
+        // Like on Windows, a `FILETIME` must fit in an `i64`.
+        let file_time = match i64::try_from(file_time) {
+            Ok(file_time) => file_time,
+            Err(_) => {
+                this.set_last_error(Scalar::from_u32(ERROR_INVALID_PARAMETER))?;
+                return Ok(0);
+            }
+        };
+        let intervals_per_sec = i64::try_from(INTERVALS_PER_SEC).unwrap();
+        let secs = file_time / intervals_per_sec;
+        let millis = file_time % intervals_per_sec / (intervals_per_sec / 1000);
+        let days = secs / SECS_PER_DAY + days_from_civil(1601, 1, 1);
+        let secs_of_day = secs % SECS_PER_DAY;
+        let (year, month, day) = civil_from_days(days);
+        // 1970-01-01 was a Thursday, and Sunday is day 0.
+        let day_of_week = (days + 4).rem_euclid(7);
 
-        let dwLowDateTime = u32::try_from(duration_ticks & 0x00000000FFFFFFFF).unwrap();
-        let dwHighDateTime = u32::try_from((duration_ticks & 0xFFFFFFFF00000000) >> 32).unwrap();
         this.write_int_fields(
-            &[dwLowDateTime.into(), dwHighDateTime.into()],
-            &this.deref_operand(LPFILETIME_op)?,
+            &[
+                year.into(),
+                month.into(),
+                day_of_week.into(),
+                day.into(),
+                (secs_of_day / (60 * 60)).into(),
+                (secs_of_day / 60 % 60).into(),
+                (secs_of_day % 60).into(),
+                millis.into(),
+            ],
+            &this.deref_operand(lpSystemTime_op)?,
         )?;
-
-        Ok(())
+        Ok(1)
     }
 
     #[allow(non_snake_case)]
@@ -106,11 +472,10 @@ pub trait EvalContextExt<'mir, 'tcx: 'mir>: crate::MiriEvalContextExt<'mir, 'tcx
         let this = self.eval_context_mut();
 
         this.assert_target_os("windows", "QueryPerformanceCounter");
-        this.check_no_isolation("`QueryPerformanceCounter`")?;
 
         // QueryPerformanceCounter uses a hardware counter as its basis.
         // Miri will emulate a counter with a resolution of 1 nanosecond.
-        let duration = Instant::now().duration_since(this.machine.time_anchor);
+        let duration = this.machine.clock.elapsed();
         let qpc = i64::try_from(duration.as_nanos()).map_err(|_| {
             err_unsup_format!("programs running longer than 2^63 nanoseconds are not supported")
         })?;
@@ -129,7 +494,6 @@ pub trait EvalContextExt<'mir, 'tcx: 'mir>: crate::MiriEvalContextExt<'mir, 'tcx
         let this = self.eval_context_mut();
 
         this.assert_target_os("windows", "QueryPerformanceFrequency");
-        this.check_no_isolation("`QueryPerformanceFrequency`")?;
 
         // Retrieves the frequency of the hardware performance counter.
         // The frequency of the performance counter is fixed at system boot and
@@ -147,11 +511,10 @@ pub trait EvalContextExt<'mir, 'tcx: 'mir>: crate::MiriEvalContextExt<'mir, 'tcx
         let this = self.eval_context_ref();
 
         this.assert_target_os("macos", "mach_absolute_time");
-        this.check_no_isolation("`mach_absolute_time`")?;
 
         // This returns a u64, with time units determined dynamically by `mach_timebase_info`.
         // We return plain nanoseconds.
-        let duration = Instant::now().duration_since(this.machine.time_anchor);
+        let duration = this.machine.clock.elapsed();
         u64::try_from(duration.as_nanos()).map_err(|_| {
             err_unsup_format!("programs running longer than 2^64 nanoseconds are not supported")
                 .into()
@@ -162,51 +525,170 @@ pub trait EvalContextExt<'mir, 'tcx: 'mir>: crate::MiriEvalContextExt<'mir, 'tcx
         let this = self.eval_context_mut();
 
         this.assert_target_os("macos", "mach_timebase_info");
-        this.check_no_isolation("`mach_timebase_info`")?;
 
         let info = this.deref_operand(info_op)?;
 
         // Since our emulated ticks in `mach_absolute_time` *are* nanoseconds,
         // no scaling needs to happen.
         let (numer, denom) = (1, 1);
-        this.write_int_fields(&[numer.into(), denom.into()], &info)?;
+        this.write_int_fields_named(&[("numer", numer.into()), ("denom", denom.into())], &info)?;
 
         Ok(0) // KERN_SUCCESS
     }
 
+    /// Block the active thread until the monotonic time `deadline`. If a timer signal is delivered
+    /// before then, the sleep is interrupted when the timer expires instead, and `interrupted` is
+    /// called with the time that was left.
+    fn sleep_until(
+        &mut self,
+        deadline: Instant,
+        interrupted: Box<
+            dyn FnOnce(&mut MiriEvalContext<'mir, 'tcx>, Duration) -> InterpResult<'tcx> + 'tcx,
+        >,
+    ) -> InterpResult<'tcx> {
+        let this = self.eval_context_mut();
+
+        let interruption = this.timer_signal_deadline()?.filter(|&at| at < deadline);
+        let active_thread = this.get_active_thread();
+        this.block_thread(active_thread);
+
+        this.register_timeout_callback(
+            active_thread,
+            Time::Monotonic(interruption.unwrap_or(deadline)),
+            Box::new(move |ecx| {
+                ecx.unblock_thread(active_thread);
+                match interruption {
+                    Some(at) => interrupted(ecx, deadline.duration_since(at)),
+                    None => Ok(()),
+                }
+            }),
+        );
+        Ok(())
+    }
+
     fn nanosleep(
         &mut self,
         req_op: &OpTy<'tcx, Tag>,
-        _rem: &OpTy<'tcx, Tag>,
-    ) -> InterpResult<'tcx, i32> {
-        // Signal handlers are not supported, so rem will never be written to.
-
+        rem_op: &OpTy<'tcx, Tag>,
+        dest: &PlaceTy<'tcx, Tag>,
+    ) -> InterpResult<'tcx> {
         let this = self.eval_context_mut();
 
-        this.check_no_isolation("`nanosleep`")?;
+        // No isolation check: with isolation enabled, the clock does not follow the host clock, so
+        // sleeping is deterministic.
 
         let duration = match this.read_timespec(&this.deref_operand(req_op)?)? {
             Some(duration) => duration,
             None => {
                 let einval = this.eval_libc("EINVAL")?;
                 this.set_last_error(einval)?;
-                return Ok(-1);
+                this.write_scalar(Scalar::from_i32(-1), dest)?;
+                return Ok(());
             }
         };
-        let timeout_time = Time::Monotonic(Instant::now().checked_add(duration).unwrap());
-
-        let active_thread = this.get_active_thread();
-        this.block_thread(active_thread);
+        let rem = if this.ptr_is_null(this.read_pointer(rem_op)?)? {
+            None
+        } else {
+            Some(this.deref_operand(rem_op)?)
+        };
 
-        this.register_timeout_callback(
-            active_thread,
-            timeout_time,
-            Box::new(move |ecx| {
-                ecx.unblock_thread(active_thread);
+        // We return success for now and override it if the sleep gets interrupted.
+        this.write_scalar(Scalar::from_i32(0), dest)?;
+
+        let dest = *dest;
+        this.sleep_until(
+            this.machine.clock.instant_in(duration),
+            Box::new(move |ecx, left| {
+                let eintr = ecx.eval_libc("EINTR")?;
+                ecx.set_last_error(eintr)?;
+                ecx.write_scalar(Scalar::from_i32(-1), &dest)?;
+                if let Some(rem) = rem {
+                    let left = [
+                        ("tv_sec", left.as_secs().into()),
+                        ("tv_nsec", left.subsec_nanos().into()),
+                    ];
+                    ecx.write_int_fields_named(&left, &rem)?;
+                }
                 Ok(())
             }),
-        );
+        )
+    }
 
-        Ok(0)
+    fn clock_nanosleep(
+        &mut self,
+        clk_id_op: &OpTy<'tcx, Tag>,
+        flags_op: &OpTy<'tcx, Tag>,
+        req_op: &OpTy<'tcx, Tag>,
+        rem_op: &OpTy<'tcx, Tag>,
+        dest: &PlaceTy<'tcx, Tag>,
+    ) -> InterpResult<'tcx> {
+        let this = self.eval_context_mut();
+
+        this.assert_target_os("linux", "clock_nanosleep");
+
+        let clk_id = this.read_scalar(clk_id_op)?.to_i32()?;
+        let flags = this.read_scalar(flags_op)?.to_i32()?;
+        let timer_abstime = this.eval_libc_i32("TIMER_ABSTIME")?;
+        if flags & !timer_abstime != 0 {
+            throw_unsup_format!("unsupported flags {:#x} for `clock_nanosleep`", flags);
+        }
+        let absolute = flags & timer_abstime != 0;
+
+        // This function returns the error number instead of setting `errno`.
+        let duration = match this.read_timespec(&this.deref_operand(req_op)?)? {
+            Some(duration) => duration,
+            None => {
+                let einval = this.eval_libc("EINVAL")?;
+                this.write_scalar(einval, dest)?;
+                return Ok(());
+            }
+        };
+        let deadline = if clk_id == this.eval_libc_i32("CLOCK_MONOTONIC")? {
+            if absolute {
+                this.machine.clock.instant_at(duration)
+            } else {
+                this.machine.clock.instant_in(duration)
+            }
+        } else if clk_id == this.eval_libc_i32("CLOCK_REALTIME")? {
+            let duration = if absolute {
+                // Time that passes on the system clock passes on the monotonic clock as well (at
+                // least while we sleep), so convert the deadline once.
+                let deadline = SystemTime::UNIX_EPOCH.checked_add(duration).unwrap();
+                deadline.duration_since(this.machine.clock.system_time()).unwrap_or(Duration::ZERO)
+            } else {
+                duration
+            };
+            this.machine.clock.instant_in(duration)
+        } else {
+            let einval = this.eval_libc("EINVAL")?;
+            this.write_scalar(einval, dest)?;
+            return Ok(());
+        };
+        // The remaining time is only reported for relative sleeps.
+        let rem = if absolute || this.ptr_is_null(this.read_pointer(rem_op)?)? {
+            None
+        } else {
+            Some(this.deref_operand(rem_op)?)
+        };
+
+        // We return success for now and override it if the sleep gets interrupted.
+        this.write_scalar(Scalar::from_i32(0), dest)?;
+
+        let dest = *dest;
+        this.sleep_until(
+            deadline,
+            Box::new(move |ecx, left| {
+                let eintr = ecx.eval_libc("EINTR")?;
+                ecx.write_scalar(eintr, &dest)?;
+                if let Some(rem) = rem {
+                    let left = [
+                        ("tv_sec", left.as_secs().into()),
+                        ("tv_nsec", left.subsec_nanos().into()),
+                    ];
+                    ecx.write_int_fields_named(&left, &rem)?;
+                }
+                Ok(())
+            }),
+        )
     }
 }
@@ -357,7 +357,14 @@ pub trait EvalContextExt<'mir, 'tcx: 'mir>: crate::MiriEvalContextExt<'mir, 'tcx
             // This is the first time we got asked to schedule a destructor. The
             // Windows schedule destructor function must be called exactly once,
             // this is why it is in this block.
-            if this.tcx.sess.target.os == "windows" {
+            // Programs without `std` have no TLS destructors on Windows.
+            if this.tcx.sess.target.os == "windows"
+                && helpers::try_resolve_did(
+                    this.tcx.tcx,
+                    &["std", "sys", "windows", "thread_local_key", "p_thread_callback"],
+                )
+                .is_some()
+            {
                 // On Windows, we signal that the thread quit by starting the
                 // relevant function, reenabling the thread, and going back to
                 // the scheduler.
@@ -0,0 +1,237 @@
+//! The directories of the user: `GetTempPathW`, `GetUserProfileDirectoryW`, and
+//! `SHGetKnownFolderPath`, as well as the path of the executable: `GetModuleFileNameW`.
+//!
+//! With isolation, the home directory is the one set with `-Zmiri-home-dir`, and the directory for
+//! temporary files the one set with `-Zmiri-temp-dir`. Without isolation, those of the host are
+//! reported. The program is not actually an executable file, so its path is always the one set
+//! with `-Zmiri-exe-path`.
+
+use std::borrow::Cow;
+use std::env;
+use std::ffi::{OsStr, OsString};
+use std::path::PathBuf;
+
+use rustc_target::abi::Size;
+
+use crate::*;
+use shims::env::windows_check_buffer_size;
+use shims::os_str::PathConversion;
+use shims::windows::handle::{EvalContextExt as _, Object};
+
+/// The home directory of the user with isolation, unless it is set with `-Zmiri-home-dir`.
+const DEFAULT_HOME_DIR: &str = r"C:\Users\miri";
+/// The directory for temporary files with isolation, unless it is set with `-Zmiri-temp-dir`.
+const DEFAULT_TEMP_DIR: &str = r"C:\Temp";
+
+const ERROR_INVALID_HANDLE: u32 = 6;
+const ERROR_INSUFFICIENT_BUFFER: u32 = 122;
+
+const S_OK: u32 = 0;
+const E_INVALIDARG: u32 = 0x80070057;
+
+/// The bytes of a `GUID`, which stores its first three fields in little endian.
+fn guid(data1: u32, data2: u16, data3: u16, data4: [u8; 8]) -> Vec<u8> {
+    [&data1.to_le_bytes()[..], &data2.to_le_bytes(), &data3.to_le_bytes(), &data4].concat()
+}
+
+/// The path of the known folder with the `KNOWNFOLDERID` `id`, relative to the home directory.
+fn known_folder(id: &[u8]) -> Option<&'static str> {
+    let known_folders = [
+        // `FOLDERID_Profile`
+        (guid(0x5E6C858F, 0x0E22, 0x4760, [0x9A, 0xFE, 0xEA, 0x33, 0x17, 0xB6, 0x71, 0x73]), ""),
+        // `FOLDERID_RoamingAppData`
+        (
+            guid(0x3EB685DB, 0x65F9, 0x4CF6, [0xA0, 0x3A, 0xE3, 0xEF, 0x65, 0x72, 0x9F, 0x3D]),
+            r"AppData\Roaming",
+        ),
+        // `FOLDERID_LocalAppData`
+        (
+            guid(0xF1B32785, 0x6FBA, 0x4FCF, [0x9D, 0x55, 0x7B, 0x8E, 0x7F, 0x15, 0x70, 0x91]),
+            r"AppData\Local",
+        ),
+    ];
+    known_folders.iter().find(|(known_id, _)| known_id == id).map(|&(_, path)| path)
+}
+
+impl<'mir, 'tcx: 'mir> EvalContextExtPrivate<'mir, 'tcx> for crate::MiriEvalContext<'mir, 'tcx> {}
+trait EvalContextExtPrivate<'mir, 'tcx: 'mir>: crate::MiriEvalContextExt<'mir, 'tcx> {
+    /// Converts a directory of the host to a path of the target.
+    fn host_dir_to_target(&self, dir: PathBuf) -> OsString {
+        let this = self.eval_context_ref();
+        this.convert_path_separator(Cow::Owned(dir.into_os_string()), PathConversion::HostToTarget)
+            .into_owned()
+    }
+
+    /// The home directory of the user, as a path of the target.
+    fn home_dir(&self) -> InterpResult<'tcx, OsString> {
+        let this = self.eval_context_ref();
+        if !this.machine.communicate() {
+            return Ok(this.machine.home_dir.as_deref().unwrap_or(DEFAULT_HOME_DIR).into());
+        }
+        // `home_dir` is deprecated for how it treats `HOME` on Windows hosts, which does not matter
+        // here.
+        #[allow(deprecated)]
+        let home_dir = env::home_dir();
+        match home_dir {
+            Some(dir) => Ok(this.host_dir_to_target(dir)),
+            None => throw_unsup_format!("the host does not have a home directory"),
+        }
+    }
+}
+
+impl<'mir, 'tcx: 'mir> EvalContextExt<'mir, 'tcx> for crate::MiriEvalContext<'mir, 'tcx> {}
+pub trait EvalContextExt<'mir, 'tcx: 'mir>: crate::MiriEvalContextExt<'mir, 'tcx> {
+    #[allow(non_snake_case)]
+    fn GetTempPathW(
+        &mut self,
+        size_op: &OpTy<'tcx, Tag>,
+        buf_op: &OpTy<'tcx, Tag>,
+    ) -> InterpResult<'tcx, u32> {
+        let this = self.eval_context_mut();
+
+        let size = u64::from(this.read_scalar(size_op)?.to_u32()?);
+        let buf = this.read_pointer(buf_op)?;
+
+        let mut temp_dir = if this.machine.communicate() {
+            this.host_dir_to_target(env::temp_dir())
+        } else {
+            this.machine.temp_dir.as_deref().unwrap_or(DEFAULT_TEMP_DIR).into()
+        };
+        // The path always ends with a backslash.
+        if !temp_dir.to_string_lossy().ends_with('\\') {
+            temp_dir.push(r"\");
+        }
+        Ok(windows_check_buffer_size(this.write_os_str_to_wide_str(&temp_dir, buf, size)?))
+    }
+
+    #[allow(non_snake_case)]
+    fn OpenProcessToken(
+        &mut self,
+        process_op: &OpTy<'tcx, Tag>,
+        _desired_access_op: &OpTy<'tcx, Tag>,
+        token_op: &OpTy<'tcx, Tag>,
+    ) -> InterpResult<'tcx, i32> {
+        let this = self.eval_context_mut();
+
+        let token_place = this.deref_operand(token_op)?;
+
+        if !this.is_current_process(process_op)? {
+            this.set_last_error(Scalar::from_u32(ERROR_INVALID_HANDLE))?;
+            return Ok(0);
+        }
+        let token = this.machine.windows_handles.insert(Object::Token);
+        this.write_scalar(Scalar::from_machine_usize(token, this), &token_place.into())?;
+        Ok(1)
+    }
+
+    #[allow(non_snake_case)]
+    fn GetUserProfileDirectoryW(
+        &mut self,
+        token_op: &OpTy<'tcx, Tag>,
+        buf_op: &OpTy<'tcx, Tag>,
+        size_op: &OpTy<'tcx, Tag>,
+    ) -> InterpResult<'tcx, i32> {
+        let this = self.eval_context_mut();
+
+        let token = this.read_scalar(token_op)?.to_machine_usize(this)?;
+        let buf = this.read_pointer(buf_op)?;
+        let size_place = this.deref_operand(size_op)?;
+        let size = this.read_scalar(&size_place.into())?.to_u32()?;
+
+        if !matches!(this.machine.windows_handles.get(token), Some(Object::Token)) {
+            this.set_last_error(Scalar::from_u32(ERROR_INVALID_HANDLE))?;
+            return Ok(0);
+        }
+        let home_dir = this.home_dir()?;
+        let (written, len) = this.write_os_str_to_wide_str(&home_dir, buf, size.into())?;
+        // Either way, the size is that of the path with its terminator.
+        let len = u32::try_from(len.checked_add(1).unwrap()).unwrap();
+        this.write_scalar(Scalar::from_u32(len), &size_place.into())?;
+        if !written {
+            this.set_last_error(Scalar::from_u32(ERROR_INSUFFICIENT_BUFFER))?;
+            return Ok(0);
+        }
+        Ok(1)
+    }
+
+    #[allow(non_snake_case)]
+    fn GetModuleFileNameW(
+        &mut self,
+        module_op: &OpTy<'tcx, Tag>,
+        filename_op: &OpTy<'tcx, Tag>,
+        size_op: &OpTy<'tcx, Tag>,
+    ) -> InterpResult<'tcx, u32> {
+        let this = self.eval_context_mut();
+
+        let module = this.read_pointer(module_op)?;
+        let filename = this.read_pointer(filename_op)?;
+        let size = this.read_scalar(size_op)?.to_u32()?;
+
+        if !this.ptr_is_null(module)? {
+            throw_unsup_format!("`GetModuleFileNameW` is only supported for the executable");
+        }
+        let exe_path = this.machine.exe_path.clone();
+        let (written, len) =
+            this.write_os_str_to_wide_str(OsStr::new(&exe_path), filename, size.into())?;
+        if written {
+            return Ok(u32::try_from(len).unwrap());
+        }
+        // If the buffer is too small, as much of the path as fits is written, still with the
+        // terminator, and the size of the buffer is returned.
+        if size > 0 {
+            let mut len = 0;
+            let truncated: String = exe_path
+                .chars()
+                .take_while(|c| {
+                    len += c.len_utf16();
+                    len < usize::try_from(size).unwrap()
+                })
+                .collect();
+            this.write_os_str_to_wide_str(OsStr::new(&truncated), filename, size.into())?;
+        }
+        this.set_last_error(Scalar::from_u32(ERROR_INSUFFICIENT_BUFFER))?;
+        Ok(size)
+    }
+
+    /// Returns the `HRESULT`.
+    #[allow(non_snake_case)]
+    fn SHGetKnownFolderPath(
+        &mut self,
+        id_op: &OpTy<'tcx, Tag>,
+        flags_op: &OpTy<'tcx, Tag>,
+        token_op: &OpTy<'tcx, Tag>,
+        path_op: &OpTy<'tcx, Tag>,
+    ) -> InterpResult<'tcx, u32> {
+        let this = self.eval_context_mut();
+
+        let id = this.read_pointer(id_op)?;
+        let flags = this.read_scalar(flags_op)?.to_u32()?;
+        let token = this.read_scalar(token_op)?.to_machine_usize(this)?;
+        let path_place = this.deref_operand(path_op)?;
+
+        if flags != 0 {
+            throw_unsup_format!("`SHGetKnownFolderPath` flags are not supported");
+        }
+        if token != 0 && !matches!(this.machine.windows_handles.get(token), Some(Object::Token)) {
+            return Ok(E_INVALIDARG);
+        }
+        let id = this.read_bytes_ptr(id, Size::from_bytes(16))?.to_vec();
+        let relative_path = match known_folder(&id) {
+            Some(relative_path) => relative_path,
+            None =>
+                throw_unsup_format!(
+                    "`SHGetKnownFolderPath` is not supported for folder {:02x?}",
+                    id
+                ),
+        };
+        let mut path = this.home_dir()?;
+        if !relative_path.is_empty() {
+            path.push(r"\");
+            path.push(relative_path);
+        }
+        // The program frees the path with `CoTaskMemFree`.
+        let path = this.alloc_os_str_as_wide_str(&path, MiriMemoryKind::WinHeap.into())?;
+        this.write_pointer(path, &path_place.into())?;
+        Ok(S_OK)
+    }
+}
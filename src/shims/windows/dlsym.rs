@@ -1,15 +1,24 @@
 use rustc_middle::mir;
-use rustc_target::abi::Size;
 use rustc_target::spec::abi::Abi;
 
 use log::trace;
 
 use crate::helpers::check_arg_count;
 use crate::*;
+use shims::windows::fs::EvalContextExt as _;
+use shims::windows::sync::EvalContextExt as _;
 
 #[derive(Debug, Copy, Clone)]
 pub enum Dlsym {
+    GetSystemTimePreciseAsFileTime,
     NtWriteFile,
+    NtReadFile,
+    WaitOnAddress,
+    WakeByAddressSingle,
+    WakeByAddressAll,
+    NtCreateKeyedEvent,
+    NtWaitForKeyedEvent,
+    NtReleaseKeyedEvent,
 }
 
 impl Dlsym {
@@ -17,8 +26,15 @@ impl Dlsym {
     // should become a NULL pointer (pretend it does not exist).
     pub fn from_str(name: &str) -> InterpResult<'static, Option<Dlsym>> {
         Ok(match name {
-            "GetSystemTimePreciseAsFileTime" => None,
+            "GetSystemTimePreciseAsFileTime" => Some(Dlsym::GetSystemTimePreciseAsFileTime),
             "NtWriteFile" => Some(Dlsym::NtWriteFile),
+            "NtReadFile" => Some(Dlsym::NtReadFile),
+            "WaitOnAddress" => Some(Dlsym::WaitOnAddress),
+            "WakeByAddressSingle" => Some(Dlsym::WakeByAddressSingle),
+            "WakeByAddressAll" => Some(Dlsym::WakeByAddressAll),
+            "NtCreateKeyedEvent" => Some(Dlsym::NtCreateKeyedEvent),
+            "NtWaitForKeyedEvent" => Some(Dlsym::NtWaitForKeyedEvent),
+            "NtReleaseKeyedEvent" => Some(Dlsym::NtReleaseKeyedEvent),
             _ => throw_unsup_format!("unsupported Windows dlsym: {}", name),
         })
     }
@@ -34,19 +50,20 @@ pub trait EvalContextExt<'mir, 'tcx: 'mir>: crate::MiriEvalContextExt<'mir, 'tcx
         ret: Option<(&PlaceTy<'tcx, Tag>, mir::BasicBlock)>,
     ) -> InterpResult<'tcx> {
         let this = self.eval_context_mut();
-        let (dest, ret) = ret.expect("we don't support any diverging dlsym");
+        let (dest, ret) = match ret {
+            Some(ret) => ret,
+            None => throw_unsup_format!("calling a function obtained with `dlsym` as diverging"),
+        };
         assert!(this.tcx.sess.target.os == "windows");
 
         this.check_abi(abi, Abi::System { unwind: false })?;
 
         match dlsym {
+            Dlsym::GetSystemTimePreciseAsFileTime => {
+                let [file_time] = check_arg_count(args)?;
+                this.GetSystemTimeAsFileTime("GetSystemTimePreciseAsFileTime", file_time)?;
+            }
             Dlsym::NtWriteFile => {
-                if !this.frame_in_std() {
-                    throw_unsup_format!(
-                        "NtWriteFile support is crude and just enough for stdout to work"
-                    );
-                }
-
                 let [
                     handle,
                     _event,
@@ -58,49 +75,48 @@ pub trait EvalContextExt<'mir, 'tcx: 'mir>: crate::MiriEvalContextExt<'mir, 'tcx
                     byte_offset,
                     _key,
                 ] = check_arg_count(args)?;
-                let handle = this.read_scalar(handle)?.to_machine_isize(this)?;
-                let buf = this.read_pointer(buf)?;
-                let n = this.read_scalar(n)?.to_u32()?;
-                let byte_offset = this.read_scalar(byte_offset)?.to_machine_usize(this)?; // is actually a pointer
-                let io_status_block = this.deref_operand(io_status_block)?;
-
-                if byte_offset != 0 {
-                    throw_unsup_format!(
-                        "NtWriteFile ByteOffset paremeter is non-null, which is unsupported"
-                    );
-                }
-
-                let written = if handle == -11 || handle == -12 {
-                    // stdout/stderr
-                    use std::io::{self, Write};
-
-                    let buf_cont = this.read_bytes_ptr(buf, Size::from_bytes(u64::from(n)))?;
-                    let res = if handle == -11 {
-                        io::stdout().write(buf_cont)
-                    } else {
-                        io::stderr().write(buf_cont)
-                    };
-                    res.ok().map(|n| n as u32)
-                } else {
-                    throw_unsup_format!(
-                        "on Windows, writing to anything except stdout/stderr is not supported"
-                    )
-                };
-                // We have to put the result into io_status_block.
-                if let Some(n) = written {
-                    let io_status_information =
-                        this.mplace_field_named(&io_status_block, "Information")?;
-                    this.write_scalar(
-                        Scalar::from_machine_usize(n.into(), this),
-                        &io_status_information.into(),
-                    )?;
-                }
-                // Return whether this was a success. >= 0 is success.
-                // For the error code we arbitrarily pick 0xC0000185, STATUS_IO_DEVICE_ERROR.
-                this.write_scalar(
-                    Scalar::from_i32(if written.is_some() { 0 } else { 0xC0000185u32 as i32 }),
-                    dest,
-                )?;
+                let status = this.NtWriteFile(handle, io_status_block, buf, n, byte_offset)?;
+                this.write_scalar(Scalar::from_u32(status), dest)?;
+            }
+            Dlsym::NtReadFile => {
+                let [
+                    handle,
+                    _event,
+                    _apc_routine,
+                    _apc_context,
+                    io_status_block,
+                    buf,
+                    n,
+                    byte_offset,
+                    _key,
+                ] = check_arg_count(args)?;
+                let status = this.NtReadFile(handle, io_status_block, buf, n, byte_offset)?;
+                this.write_scalar(Scalar::from_u32(status), dest)?;
+            }
+            Dlsym::WaitOnAddress => {
+                let [addr, compare, size, milliseconds] = check_arg_count(args)?;
+                this.WaitOnAddress(addr, compare, size, milliseconds, dest)?;
+            }
+            Dlsym::WakeByAddressSingle => {
+                let [addr] = check_arg_count(args)?;
+                this.wake_by_address(addr, false)?;
+            }
+            Dlsym::WakeByAddressAll => {
+                let [addr] = check_arg_count(args)?;
+                this.wake_by_address(addr, true)?;
+            }
+            Dlsym::NtCreateKeyedEvent => {
+                let [handle, access, attributes, flags] = check_arg_count(args)?;
+                let status = this.NtCreateKeyedEvent(handle, access, attributes, flags)?;
+                this.write_scalar(Scalar::from_u32(status), dest)?;
+            }
+            Dlsym::NtWaitForKeyedEvent => {
+                let [handle, key, alertable, timeout] = check_arg_count(args)?;
+                this.keyed_event_op(handle, key, alertable, timeout, false, dest)?;
+            }
+            Dlsym::NtReleaseKeyedEvent => {
+                let [handle, key, alertable, timeout] = check_arg_count(args)?;
+                this.keyed_event_op(handle, key, alertable, timeout, true, dest)?;
             }
         }
 
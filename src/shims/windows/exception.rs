@@ -0,0 +1,116 @@
+//! Structured exception handling (SEH): `RaiseException`, and the functions that unwind via SEH.
+//!
+//! Miri does not run exception handlers, so a raised exception is never handled: like an
+//! unhandled exception, it terminates the process. Unwinding via SEH (which C++ `throw` and
+//! exception handlers do) is not supported either. Rust panics do not need any of this, since
+//! Miri implements unwinding itself.
+
+use rustc_span::Symbol;
+use rustc_target::spec::abi::Abi;
+
+use crate::*;
+
+/// The code of the exception that MSVC raises for a C++ `throw`.
+const EXCEPTION_MSVC_CPP: u32 = 0xE06D7363;
+
+/// Describes the exception with code `code`, e.g. `0xc0000005 (EXCEPTION_ACCESS_VIOLATION)`.
+fn describe_exception(code: u32) -> String {
+    let name = match code {
+        0x80000003 => "EXCEPTION_BREAKPOINT",
+        0xC0000005 => "EXCEPTION_ACCESS_VIOLATION",
+        0xC000001D => "EXCEPTION_ILLEGAL_INSTRUCTION",
+        0xC0000094 => "EXCEPTION_INT_DIVIDE_BY_ZERO",
+        0xC00000FD => "EXCEPTION_STACK_OVERFLOW",
+        0xC0000409 => "STATUS_STACK_BUFFER_OVERRUN",
+        EXCEPTION_MSVC_CPP => "C++ exception",
+        _ => return format!("{:#010x}", code),
+    };
+    format!("{:#010x} ({})", code, name)
+}
+
+impl<'mir, 'tcx: 'mir> EvalContextExtPrivate<'mir, 'tcx> for crate::MiriEvalContext<'mir, 'tcx> {}
+trait EvalContextExtPrivate<'mir, 'tcx: 'mir>: crate::MiriEvalContextExt<'mir, 'tcx> {
+    /// The path of the function that called the shim.
+    fn calling_function(&self) -> String {
+        let this = self.eval_context_ref();
+        this.tcx.def_path_str(this.frame().instance.def_id())
+    }
+
+    /// Reports that `link_name` tried to unwind via SEH, for the exception with code `code`.
+    fn seh_unwind(&mut self, link_name: Symbol, code: Option<u32>) -> InterpResult<'tcx, !> {
+        let this = self.eval_context_mut();
+        let exception = match code {
+            Some(code) => format!("exception {}", describe_exception(code)),
+            None => "an unknown exception".to_owned(),
+        };
+        throw_unsup_format!(
+            "unwinding via SEH is not supported: `{}` called `{}` for {}",
+            this.calling_function(),
+            link_name,
+            exception
+        )
+    }
+
+    /// The code of the `EXCEPTION_RECORD` that `record_op` points to, if it is not null.
+    fn exception_record_code(
+        &mut self,
+        record_op: &OpTy<'tcx, Tag>,
+    ) -> InterpResult<'tcx, Option<u32>> {
+        let this = self.eval_context_mut();
+        if this.ptr_is_null(this.read_pointer(record_op)?)? {
+            return Ok(None);
+        }
+        // `ExceptionCode` is the first field.
+        let code = this.read_scalar_at_offset(record_op, 0, this.machine.layouts.u32)?.to_u32()?;
+        Ok(Some(code))
+    }
+}
+
+impl<'mir, 'tcx: 'mir> EvalContextExt<'mir, 'tcx> for crate::MiriEvalContext<'mir, 'tcx> {}
+pub trait EvalContextExt<'mir, 'tcx: 'mir>: crate::MiriEvalContextExt<'mir, 'tcx> {
+    /// Emulates `RaiseException`, `RtlUnwind`, `RtlUnwindEx`, and `_CxxThrowException`, none of
+    /// which return. Programs may declare them as diverging or not.
+    fn emulate_seh_shim(
+        &mut self,
+        link_name: Symbol,
+        abi: Abi,
+        args: &[OpTy<'tcx, Tag>],
+    ) -> InterpResult<'tcx, !> {
+        let this = self.eval_context_mut();
+        match link_name.as_str() {
+            "RaiseException" => {
+                let [code, _flags, _num_args, _args] =
+                    this.check_shim(abi, Abi::System { unwind: false }, link_name, args)?;
+                let code = this.read_scalar(code)?.to_u32()?;
+                // Whether the exception is continuable does not matter, since there is no handler
+                // that could continue it.
+                throw_machine_stop!(TerminationInfo::Abort(format!(
+                    "`{}` raised exception {}, which terminates the process since Miri does not \
+                    support exception handlers",
+                    this.calling_function(),
+                    describe_exception(code)
+                )))
+            }
+            "RtlUnwind" => {
+                let [_target_frame, _target_ip, exception_record, _return_value] =
+                    this.check_shim(abi, Abi::System { unwind: false }, link_name, args)?;
+                let code = this.exception_record_code(exception_record)?;
+                this.seh_unwind(link_name, code)
+            }
+            "RtlUnwindEx" => {
+                let [_frame, _ip, exception_record, _return_value, _context, _history_table] =
+                    this.check_shim(abi, Abi::System { unwind: false }, link_name, args)?;
+                let code = this.exception_record_code(exception_record)?;
+                this.seh_unwind(link_name, code)
+            }
+            "_CxxThrowException" => {
+                // This is meant to unwind, so it may be declared with `system-unwind`.
+                let unwind = matches!(abi, Abi::System { unwind: true });
+                let [_exception_object, _throw_info] =
+                    this.check_shim(abi, Abi::System { unwind }, link_name, args)?;
+                this.seh_unwind(link_name, Some(EXCEPTION_MSVC_CPP))
+            }
+            _ => bug!("`{}` is not an SEH shim", link_name),
+        }
+    }
+}
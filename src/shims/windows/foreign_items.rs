@@ -6,8 +6,50 @@ use rustc_target::abi::Size;
 use rustc_target::spec::abi::Abi;
 
 use crate::*;
-use shims::foreign_items::EmulateByNameResult;
+use shims::foreign_items::{EmulateByNameResult, ForeignItemProvider};
+use shims::windows::dirs::EvalContextExt as _;
+use shims::windows::exception::EvalContextExt as _;
+use shims::windows::fs::EvalContextExt as _;
+use shims::windows::handle::{
+    std_handle, EvalContextExt as _, Object, CURRENT_PROCESS_HANDLE, CURRENT_THREAD_HANDLE,
+};
 use shims::windows::sync::EvalContextExt as _;
+use shims::windows::virtual_memory::EvalContextExt as _;
+
+const ERROR_INVALID_HANDLE: u32 = 6;
+
+/// The argument of `GetStdHandle` that asks for stdin. Those for stdout and stderr follow it
+/// downwards.
+const STD_INPUT_HANDLE: i32 = -10;
+
+/// The handle that `GetProcessHeap` returns. Miri does not support private heaps, so this is the
+/// only valid heap handle.
+const PROCESS_HEAP: i64 = 1;
+
+/// The standard stream that `handle` refers to, if `-Zmiri-pretend-tty` reports it as a console.
+fn pretended_console(ecx: &MiriEvalContext<'_, '_>, handle: u64) -> Option<i32> {
+    match ecx.machine.windows_handles.get(handle) {
+        Some(&Object::Stdio { fd }) if ecx.machine.pretend_tty.contains(&fd) => Some(fd),
+        _ => None,
+    }
+}
+
+/// The shims that are available on Windows.
+pub struct WindowsForeignItems;
+
+impl ForeignItemProvider for WindowsForeignItems {
+    fn emulate_foreign_item_by_name<'mir, 'tcx>(
+        &self,
+        ecx: &mut MiriEvalContext<'mir, 'tcx>,
+        link_name: Symbol,
+        abi: Abi,
+        args: &[OpTy<'tcx, Tag>],
+        dest: &PlaceTy<'tcx, Tag>,
+        ret: mir::BasicBlock,
+    ) -> InterpResult<'tcx, EmulateByNameResult<'mir, 'tcx>> {
+        EvalContextExt::emulate_foreign_item_by_name(ecx, link_name, abi, args, dest, ret)
+    }
+}
 
 impl<'mir, 'tcx: 'mir> EvalContextExt<'mir, 'tcx> for crate::MiriEvalContext<'mir, 'tcx> {}
 pub trait EvalContextExt<'mir, 'tcx: 'mir>: crate::MiriEvalContextExt<'mir, 'tcx> {
@@ -64,12 +106,113 @@ pub trait EvalContextExt<'mir, 'tcx: 'mir>: crate::MiriEvalContextExt<'mir, 'tcx
                 let result = this.SetCurrentDirectoryW(path)?;
                 this.write_scalar(Scalar::from_i32(result), dest)?;
             }
+            "GetTempPathW" => {
+                let [size, buf] =
+                    this.check_shim(abi, Abi::System { unwind: false }, link_name, args)?;
+                let result = this.GetTempPathW(size, buf)?;
+                this.write_scalar(Scalar::from_u32(result), dest)?;
+            }
+            "GetUserProfileDirectoryW" => {
+                let [token, buf, size] =
+                    this.check_shim(abi, Abi::System { unwind: false }, link_name, args)?;
+                let result = this.GetUserProfileDirectoryW(token, buf, size)?;
+                this.write_scalar(Scalar::from_i32(result), dest)?;
+            }
+            "SHGetKnownFolderPath" => {
+                let [id, flags, token, path] =
+                    this.check_shim(abi, Abi::System { unwind: false }, link_name, args)?;
+                let result = this.SHGetKnownFolderPath(id, flags, token, path)?;
+                this.write_scalar(Scalar::from_u32(result), dest)?;
+            }
+            "GetModuleFileNameW" => {
+                let [module, filename, size] =
+                    this.check_shim(abi, Abi::System { unwind: false }, link_name, args)?;
+                let result = this.GetModuleFileNameW(module, filename, size)?;
+                this.write_scalar(Scalar::from_u32(result), dest)?;
+            }
+            "OpenProcessToken" => {
+                let [process, desired_access, token] =
+                    this.check_shim(abi, Abi::System { unwind: false }, link_name, args)?;
+                let result = this.OpenProcessToken(process, desired_access, token)?;
+                this.write_scalar(Scalar::from_i32(result), dest)?;
+            }
+
+            // File related shims
+            "CreateFileW" => {
+                let [
+                    file_name,
+                    desired_access,
+                    share_mode,
+                    security_attributes,
+                    creation_disposition,
+                    flags_and_attributes,
+                    template_file,
+                ] = this.check_shim(abi, Abi::System { unwind: false }, link_name, args)?;
+                let handle = this.CreateFileW(
+                    file_name,
+                    desired_access,
+                    share_mode,
+                    security_attributes,
+                    creation_disposition,
+                    flags_and_attributes,
+                    template_file,
+                )?;
+                this.write_scalar(Scalar::from_machine_isize(handle, this), dest)?;
+            }
+            "GetFinalPathNameByHandleW" => {
+                let [file, buf, size, flags] =
+                    this.check_shim(abi, Abi::System { unwind: false }, link_name, args)?;
+                let result = this.GetFinalPathNameByHandleW(file, buf, size, flags)?;
+                this.write_scalar(Scalar::from_u32(result), dest)?;
+            }
+            "FindFirstFileW" => {
+                let [file_name, find_data] =
+                    this.check_shim(abi, Abi::System { unwind: false }, link_name, args)?;
+                let handle = this.FindFirstFileW(file_name, find_data)?;
+                this.write_scalar(Scalar::from_machine_isize(handle, this), dest)?;
+            }
+            "FindNextFileW" => {
+                let [find_file, find_data] =
+                    this.check_shim(abi, Abi::System { unwind: false }, link_name, args)?;
+                let result = this.FindNextFileW(find_file, find_data)?;
+                this.write_scalar(Scalar::from_i32(result), dest)?;
+            }
+            "FindClose" => {
+                let [find_file] =
+                    this.check_shim(abi, Abi::System { unwind: false }, link_name, args)?;
+                let result = this.FindClose(find_file)?;
+                this.write_scalar(Scalar::from_i32(result), dest)?;
+            }
+            "DeleteFileW" => {
+                let [file_name] =
+                    this.check_shim(abi, Abi::System { unwind: false }, link_name, args)?;
+                let result = this.DeleteFileW(file_name)?;
+                this.write_scalar(Scalar::from_i32(result), dest)?;
+            }
+            "CreateDirectoryW" => {
+                let [path_name, security_attributes] =
+                    this.check_shim(abi, Abi::System { unwind: false }, link_name, args)?;
+                let result = this.CreateDirectoryW(path_name, security_attributes)?;
+                this.write_scalar(Scalar::from_i32(result), dest)?;
+            }
+            "RemoveDirectoryW" => {
+                let [path_name] =
+                    this.check_shim(abi, Abi::System { unwind: false }, link_name, args)?;
+                let result = this.RemoveDirectoryW(path_name)?;
+                this.write_scalar(Scalar::from_i32(result), dest)?;
+            }
+            "MoveFileExW" => {
+                let [existing_file_name, new_file_name, flags] =
+                    this.check_shim(abi, Abi::System { unwind: false }, link_name, args)?;
+                let result = this.MoveFileExW(existing_file_name, new_file_name, flags)?;
+                this.write_scalar(Scalar::from_i32(result), dest)?;
+            }
 
             // Allocation
             "HeapAlloc" => {
                 let [handle, flags, size] =
                     this.check_shim(abi, Abi::System { unwind: false }, link_name, args)?;
-                this.read_scalar(handle)?.to_machine_isize(this)?;
+                this.check_heap_handle(handle, link_name)?;
                 let flags = this.read_scalar(flags)?.to_u32()?;
                 let size = this.read_scalar(size)?.to_machine_usize(this)?;
                 let zero_init = (flags & 0x00000008) != 0; // HEAP_ZERO_MEMORY
@@ -79,22 +222,90 @@ pub trait EvalContextExt<'mir, 'tcx: 'mir>: crate::MiriEvalContextExt<'mir, 'tcx
             "HeapFree" => {
                 let [handle, flags, ptr] =
                     this.check_shim(abi, Abi::System { unwind: false }, link_name, args)?;
-                this.read_scalar(handle)?.to_machine_isize(this)?;
+                this.check_heap_handle(handle, link_name)?;
                 this.read_scalar(flags)?.to_u32()?;
                 let ptr = this.read_pointer(ptr)?;
                 this.free(ptr, MiriMemoryKind::WinHeap)?;
                 this.write_scalar(Scalar::from_i32(1), dest)?;
             }
+            "CoTaskMemFree" => {
+                // Frees the strings that `SHGetKnownFolderPath` returns.
+                let [ptr] = this.check_shim(abi, Abi::System { unwind: false }, link_name, args)?;
+                let ptr = this.read_pointer(ptr)?;
+                this.free(ptr, MiriMemoryKind::WinHeap)?;
+            }
             "HeapReAlloc" => {
                 let [handle, flags, ptr, size] =
                     this.check_shim(abi, Abi::System { unwind: false }, link_name, args)?;
-                this.read_scalar(handle)?.to_machine_isize(this)?;
+                this.check_heap_handle(handle, link_name)?;
                 this.read_scalar(flags)?.to_u32()?;
                 let ptr = this.read_pointer(ptr)?;
                 let size = this.read_scalar(size)?.to_machine_usize(this)?;
                 let res = this.realloc(ptr, size, MiriMemoryKind::WinHeap)?;
                 this.write_pointer(res, dest)?;
             }
+            "HeapSize" => {
+                let [handle, flags, ptr] =
+                    this.check_shim(abi, Abi::System { unwind: false }, link_name, args)?;
+                this.check_heap_handle(handle, link_name)?;
+                this.read_scalar(flags)?.to_u32()?;
+                let ptr = this.read_pointer(ptr)?;
+                if this.ptr_is_null(ptr)? {
+                    throw_ub_format!("`HeapSize` called on a null pointer");
+                }
+                let size = this.heap_alloc_size(ptr, MiriMemoryKind::WinHeap, "HeapSize")?;
+                this.write_scalar(Scalar::from_machine_usize(size, this), dest)?;
+            }
+            "HeapValidate" => {
+                let [handle, flags, ptr] =
+                    this.check_shim(abi, Abi::System { unwind: false }, link_name, args)?;
+                this.check_heap_handle(handle, link_name)?;
+                this.read_scalar(flags)?.to_u32()?;
+                let ptr = this.read_pointer(ptr)?;
+                // Miri checks every heap access, so the heap as a whole is always valid. A block
+                // is valid if it is the start of a live allocation of this heap.
+                let valid = this.ptr_is_null(ptr)?
+                    || match this.ptr_try_get_alloc_id(ptr) {
+                        Ok((alloc_id, offset, _)) =>
+                            offset.bytes() == 0
+                                && this.get_alloc_extra(alloc_id).map_or(false, |extra| {
+                                    extra.heap == Some(MiriMemoryKind::WinHeap)
+                                }),
+                        Err(_) => false,
+                    };
+                this.write_scalar(Scalar::from_i32(valid.into()), dest)?;
+            }
+            "GetProcessHeap" => {
+                let [] = this.check_shim(abi, Abi::System { unwind: false }, link_name, args)?;
+                this.write_scalar(Scalar::from_machine_isize(PROCESS_HEAP, this), dest)?;
+            }
+            "VirtualAlloc" => {
+                let [address, size, allocation_type, protect] =
+                    this.check_shim(abi, Abi::System { unwind: false }, link_name, args)?;
+                let ptr = this.VirtualAlloc(address, size, allocation_type, protect)?;
+                this.write_pointer(ptr, dest)?;
+            }
+            "VirtualFree" => {
+                let [address, size, free_type] =
+                    this.check_shim(abi, Abi::System { unwind: false }, link_name, args)?;
+                let result = this.VirtualFree(address, size, free_type)?;
+                this.write_scalar(Scalar::from_i32(result), dest)?;
+            }
+            "VirtualProtect" => {
+                let [address, size, new_protect, old_protect] =
+                    this.check_shim(abi, Abi::System { unwind: false }, link_name, args)?;
+                let result = this.VirtualProtect(address, size, new_protect, old_protect)?;
+                this.write_scalar(Scalar::from_i32(result), dest)?;
+            }
+            "_msize" => {
+                let [ptr] = this.check_shim(abi, Abi::C { unwind: false }, link_name, args)?;
+                let ptr = this.read_pointer(ptr)?;
+                if this.ptr_is_null(ptr)? {
+                    throw_ub_format!("`_msize` called on a null pointer");
+                }
+                let size = this.heap_alloc_size(ptr, MiriMemoryKind::C, "_msize")?;
+                this.write_scalar(Scalar::from_machine_usize(size, this), dest)?;
+            }
 
             // errno
             "SetLastError" => {
@@ -163,11 +374,25 @@ pub trait EvalContextExt<'mir, 'tcx: 'mir>: crate::MiriEvalContextExt<'mir, 'tcx
             }
 
             // Time related shims
-            "GetSystemTimeAsFileTime" => {
+            "GetSystemTimeAsFileTime" | "GetSystemTimePreciseAsFileTime" => {
                 #[allow(non_snake_case)]
                 let [LPFILETIME] =
                     this.check_shim(abi, Abi::System { unwind: false }, link_name, args)?;
-                this.GetSystemTimeAsFileTime(LPFILETIME)?;
+                this.GetSystemTimeAsFileTime(link_name.as_str(), LPFILETIME)?;
+            }
+            "SystemTimeToFileTime" => {
+                #[allow(non_snake_case)]
+                let [lpSystemTime, lpFileTime] =
+                    this.check_shim(abi, Abi::System { unwind: false }, link_name, args)?;
+                let result = this.SystemTimeToFileTime(lpSystemTime, lpFileTime)?;
+                this.write_scalar(Scalar::from_i32(result), dest)?;
+            }
+            "FileTimeToSystemTime" => {
+                #[allow(non_snake_case)]
+                let [lpFileTime, lpSystemTime] =
+                    this.check_shim(abi, Abi::System { unwind: false }, link_name, args)?;
+                let result = this.FileTimeToSystemTime(lpFileTime, lpSystemTime)?;
+                this.write_scalar(Scalar::from_i32(result), dest)?;
             }
             "QueryPerformanceCounter" => {
                 #[allow(non_snake_case)]
@@ -211,6 +436,128 @@ pub trait EvalContextExt<'mir, 'tcx: 'mir>: crate::MiriEvalContextExt<'mir, 'tcx
                 let ret = this.TryAcquireSRWLockShared(ptr)?;
                 this.write_scalar(Scalar::from_u8(ret), dest)?;
             }
+            "CreateEventW" => {
+                #[allow(non_snake_case)]
+                let [lpEventAttributes, bManualReset, bInitialState, lpName] =
+                    this.check_shim(abi, Abi::System { unwind: false }, link_name, args)?;
+                let handle =
+                    this.CreateEventW(lpEventAttributes, bManualReset, bInitialState, lpName)?;
+                this.write_scalar(Scalar::from_machine_usize(handle, this), dest)?;
+            }
+            "SetEvent" => {
+                #[allow(non_snake_case)]
+                let [hEvent] =
+                    this.check_shim(abi, Abi::System { unwind: false }, link_name, args)?;
+                let ret = this.set_event_state(hEvent, true)?;
+                this.write_scalar(Scalar::from_i32(ret), dest)?;
+            }
+            "ResetEvent" => {
+                #[allow(non_snake_case)]
+                let [hEvent] =
+                    this.check_shim(abi, Abi::System { unwind: false }, link_name, args)?;
+                let ret = this.set_event_state(hEvent, false)?;
+                this.write_scalar(Scalar::from_i32(ret), dest)?;
+            }
+            "CreateMutexW" => {
+                #[allow(non_snake_case)]
+                let [lpMutexAttributes, bInitialOwner, lpName] =
+                    this.check_shim(abi, Abi::System { unwind: false }, link_name, args)?;
+                let handle = this.CreateMutexW(lpMutexAttributes, bInitialOwner, lpName)?;
+                this.write_scalar(Scalar::from_machine_usize(handle, this), dest)?;
+            }
+            "ReleaseMutex" => {
+                #[allow(non_snake_case)]
+                let [hMutex] =
+                    this.check_shim(abi, Abi::System { unwind: false }, link_name, args)?;
+                let ret = this.ReleaseMutex(hMutex)?;
+                this.write_scalar(Scalar::from_i32(ret), dest)?;
+            }
+            "WaitForSingleObject" => {
+                #[allow(non_snake_case)]
+                let [hHandle, dwMilliseconds] =
+                    this.check_shim(abi, Abi::System { unwind: false }, link_name, args)?;
+                this.WaitForSingleObject(hHandle, dwMilliseconds, dest)?;
+            }
+            "WaitForMultipleObjects" => {
+                #[allow(non_snake_case)]
+                let [nCount, lpHandles, bWaitAll, dwMilliseconds] =
+                    this.check_shim(abi, Abi::System { unwind: false }, link_name, args)?;
+                this.WaitForMultipleObjects(nCount, lpHandles, bWaitAll, dwMilliseconds, dest)?;
+            }
+            "CloseHandle" => {
+                #[allow(non_snake_case)]
+                let [hObject] =
+                    this.check_shim(abi, Abi::System { unwind: false }, link_name, args)?;
+                let ret = this.CloseHandle(hObject)?;
+                this.write_scalar(Scalar::from_i32(ret), dest)?;
+            }
+            "DuplicateHandle" => {
+                #[allow(non_snake_case)]
+                let [
+                    hSourceProcessHandle,
+                    hSourceHandle,
+                    hTargetProcessHandle,
+                    lpTargetHandle,
+                    dwDesiredAccess,
+                    bInheritHandle,
+                    dwOptions,
+                ] = this.check_shim(abi, Abi::System { unwind: false }, link_name, args)?;
+                let ret = this.DuplicateHandle(
+                    hSourceProcessHandle,
+                    hSourceHandle,
+                    hTargetProcessHandle,
+                    lpTargetHandle,
+                    dwDesiredAccess,
+                    bInheritHandle,
+                    dwOptions,
+                )?;
+                this.write_scalar(Scalar::from_i32(ret), dest)?;
+            }
+            "GetCurrentProcess" => {
+                let [] = this.check_shim(abi, Abi::System { unwind: false }, link_name, args)?;
+                this.write_scalar(Scalar::from_machine_isize(CURRENT_PROCESS_HANDLE, this), dest)?;
+            }
+            "GetCurrentThread" => {
+                let [] = this.check_shim(abi, Abi::System { unwind: false }, link_name, args)?;
+                this.write_scalar(Scalar::from_machine_isize(CURRENT_THREAD_HANDLE, this), dest)?;
+            }
+            "WaitOnAddress" => {
+                #[allow(non_snake_case)]
+                let [Address, CompareAddress, AddressSize, dwMilliseconds] =
+                    this.check_shim(abi, Abi::System { unwind: false }, link_name, args)?;
+                this.WaitOnAddress(Address, CompareAddress, AddressSize, dwMilliseconds, dest)?;
+            }
+            "WakeByAddressSingle" => {
+                #[allow(non_snake_case)]
+                let [Address] =
+                    this.check_shim(abi, Abi::System { unwind: false }, link_name, args)?;
+                this.wake_by_address(Address, false)?;
+            }
+            "WakeByAddressAll" => {
+                #[allow(non_snake_case)]
+                let [Address] =
+                    this.check_shim(abi, Abi::System { unwind: false }, link_name, args)?;
+                this.wake_by_address(Address, true)?;
+            }
+            "NtCreateKeyedEvent" => {
+                #[allow(non_snake_case)]
+                let [KeyedEventHandle, DesiredAccess, ObjectAttributes, Flags] =
+                    this.check_shim(abi, Abi::System { unwind: false }, link_name, args)?;
+                let status = this.NtCreateKeyedEvent(
+                    KeyedEventHandle,
+                    DesiredAccess,
+                    ObjectAttributes,
+                    Flags,
+                )?;
+                this.write_scalar(Scalar::from_u32(status), dest)?;
+            }
+            "NtWaitForKeyedEvent" | "NtReleaseKeyedEvent" => {
+                #[allow(non_snake_case)]
+                let [KeyedEventHandle, Key, Alertable, Timeout] =
+                    this.check_shim(abi, Abi::System { unwind: false }, link_name, args)?;
+                let release = link_name.as_str() == "NtReleaseKeyedEvent";
+                this.keyed_event_op(KeyedEventHandle, Key, Alertable, Timeout, release, dest)?;
+            }
 
             // Dynamic symbol loading
             "GetProcAddress" => {
@@ -227,6 +574,11 @@ pub trait EvalContextExt<'mir, 'tcx: 'mir>: crate::MiriEvalContextExt<'mir, 'tcx
                 }
             }
 
+            // Structured exception handling
+            "RaiseException" | "RtlUnwind" | "RtlUnwindEx" | "_CxxThrowException" => {
+                this.emulate_seh_shim(link_name, abi, args)?;
+            }
+
             // Miscellaneous
             "SystemFunction036" => {
                 // This is really 'RtlGenRandom'.
@@ -259,24 +611,51 @@ pub trait EvalContextExt<'mir, 'tcx: 'mir>: crate::MiriEvalContextExt<'mir, 'tcx
                 this.write_null(dest)?; // STATUS_SUCCESS
             }
             "GetConsoleScreenBufferInfo" => {
-                // `term` needs this, so we fake it.
+                // `term` needs this. Only the streams given to `-Zmiri-pretend-tty` are consoles.
                 let [console, buffer_info] =
                     this.check_shim(abi, Abi::System { unwind: false }, link_name, args)?;
-                this.read_scalar(console)?.to_machine_isize(this)?;
-                this.deref_operand(buffer_info)?;
-                // Indicate an error.
-                // FIXME: we should set last_error, but to what?
-                this.write_null(dest)?;
+                let console = this.read_scalar(console)?.to_machine_usize(this)?;
+                let buffer_info = this.deref_operand(buffer_info)?;
+                if pretended_console(this, console).is_some() {
+                    let (cols, rows) = this.machine.tty_size;
+                    let (cols, rows) = (i128::from(cols), i128::from(rows));
+                    // `dwSize`, `dwCursorPosition`, `wAttributes`, `srWindow` and
+                    // `dwMaximumWindowSize`.
+                    let size = this.mplace_field(&buffer_info, 0)?;
+                    this.write_int_fields(&[cols, rows], &size)?;
+                    let cursor = this.mplace_field(&buffer_info, 1)?;
+                    this.write_int_fields(&[0, 0], &cursor)?;
+                    let attributes = this.mplace_field(&buffer_info, 2)?;
+                    // White text on a black background.
+                    this.write_int(7, &attributes.into())?;
+                    let window = this.mplace_field(&buffer_info, 3)?;
+                    this.write_int_fields(&[0, 0, cols - 1, rows - 1], &window)?;
+                    let max_size = this.mplace_field(&buffer_info, 4)?;
+                    this.write_int_fields(&[cols, rows], &max_size)?;
+                    this.write_scalar(Scalar::from_i32(1), dest)?;
+                } else {
+                    this.set_last_error(Scalar::from_u32(ERROR_INVALID_HANDLE))?;
+                    this.write_null(dest)?;
+                }
             }
             "GetConsoleMode" => {
-                // Windows "isatty" (in libtest) needs this, so we fake it.
+                // Windows "isatty" (in libtest) needs this. Only the streams given to
+                // `-Zmiri-pretend-tty` are consoles.
                 let [console, mode] =
                     this.check_shim(abi, Abi::System { unwind: false }, link_name, args)?;
-                this.read_scalar(console)?.to_machine_isize(this)?;
-                this.deref_operand(mode)?;
-                // Indicate an error.
-                // FIXME: we should set last_error, but to what?
-                this.write_null(dest)?;
+                let console = this.read_scalar(console)?.to_machine_usize(this)?;
+                let mode = this.deref_operand(mode)?;
+                if let Some(fd) = pretended_console(this, console) {
+                    // The default modes of a console: `ENABLE_PROCESSED_INPUT |
+                    // ENABLE_LINE_INPUT | ENABLE_ECHO_INPUT` for input, and
+                    // `ENABLE_PROCESSED_OUTPUT | ENABLE_WRAP_AT_EOL_OUTPUT` for output.
+                    let console_mode = if fd == 0 { 0x7 } else { 0x3 };
+                    this.write_scalar(Scalar::from_u32(console_mode), &mode.into())?;
+                    this.write_scalar(Scalar::from_i32(1), dest)?;
+                } else {
+                    this.set_last_error(Scalar::from_u32(ERROR_INVALID_HANDLE))?;
+                    this.write_null(dest)?;
+                }
             }
             "SwitchToThread" => {
                 let [] = this.check_shim(abi, Abi::System { unwind: false }, link_name, args)?;
@@ -288,10 +667,18 @@ pub trait EvalContextExt<'mir, 'tcx: 'mir>: crate::MiriEvalContextExt<'mir, 'tcx
                 let [which] =
                     this.check_shim(abi, Abi::System { unwind: false }, link_name, args)?;
                 let which = this.read_scalar(which)?.to_i32()?;
-                // We just make this the identity function, so we know later in `NtWriteFile` which
-                // one it is. This is very fake, but libtest needs it so we cannot make it a
-                // std-only shim.
-                this.write_scalar(Scalar::from_machine_isize(which.into(), this), dest)?;
+                // The standard streams have handles of their own in the handle table.
+                match STD_INPUT_HANDLE - which {
+                    fd @ 0..=2 => {
+                        let handle = std_handle(fd);
+                        this.write_scalar(Scalar::from_machine_usize(handle, this), dest)?;
+                    }
+                    _ => {
+                        this.set_last_error(Scalar::from_u32(ERROR_INVALID_HANDLE))?;
+                        // `INVALID_HANDLE_VALUE`
+                        this.write_scalar(Scalar::from_machine_isize(-1, this), dest)?;
+                    }
+                }
             }
 
             // Better error for attempts to create a thread
@@ -305,11 +692,6 @@ pub trait EvalContextExt<'mir, 'tcx: 'mir>: crate::MiriEvalContextExt<'mir, 'tcx
 
             // Incomplete shims that we "stub out" just to get pre-main initialization code to work.
             // These shims are enabled only when the caller is in the standard library.
-            "GetProcessHeap" if this.frame_in_std() => {
-                let [] = this.check_shim(abi, Abi::System { unwind: false }, link_name, args)?;
-                // Just fake a HANDLE
-                this.write_scalar(Scalar::from_machine_isize(1, this), dest)?;
-            }
             "GetModuleHandleA" if this.frame_in_std() => {
                 #[allow(non_snake_case)]
                 let [_lpModuleName] =
@@ -347,11 +729,9 @@ pub trait EvalContextExt<'mir, 'tcx: 'mir>: crate::MiriEvalContextExt<'mir, 'tcx
                 #[allow(non_snake_case)]
                 let [_lpCriticalSection] =
                     this.check_shim(abi, Abi::System { unwind: false }, link_name, args)?;
-                assert_eq!(
-                    this.get_total_thread_count(),
-                    1,
-                    "concurrency on Windows is not supported"
-                );
+                if this.get_total_thread_count() != 1 {
+                    throw_unsup_format!("concurrency on Windows is not supported");
+                }
                 // Nothing to do, not even a return value.
                 // (Windows locks are reentrant, and we have only 1 thread,
                 // so not doing any futher checks here is at least not incorrect.)
@@ -360,11 +740,9 @@ pub trait EvalContextExt<'mir, 'tcx: 'mir>: crate::MiriEvalContextExt<'mir, 'tcx
                 #[allow(non_snake_case)]
                 let [_lpCriticalSection] =
                     this.check_shim(abi, Abi::System { unwind: false }, link_name, args)?;
-                assert_eq!(
-                    this.get_total_thread_count(),
-                    1,
-                    "concurrency on Windows is not supported"
-                );
+                if this.get_total_thread_count() != 1 {
+                    throw_unsup_format!("concurrency on Windows is not supported");
+                }
                 // There is only one thread, so this always succeeds and returns TRUE.
                 this.write_scalar(Scalar::from_i32(1), dest)?;
             }
@@ -374,4 +752,19 @@ pub trait EvalContextExt<'mir, 'tcx: 'mir>: crate::MiriEvalContextExt<'mir, 'tcx
 
         Ok(EmulateByNameResult::NeedsJumping)
     }
+
+    /// Heap functions must be called with the handle returned by `GetProcessHeap`; anything else
+    /// would be a private heap, which Miri does not support, or a handle that is not a heap at all.
+    fn check_heap_handle(&self, handle: &OpTy<'tcx, Tag>, link_name: Symbol) -> InterpResult<'tcx> {
+        let this = self.eval_context_ref();
+        let handle = this.read_scalar(handle)?.to_machine_isize(this)?;
+        if handle != PROCESS_HEAP {
+            throw_ub_format!(
+                "`{}` called with heap handle {:#x}, which was not returned by `GetProcessHeap`",
+                link_name,
+                handle,
+            );
+        }
+        Ok(())
+    }
 }
@@ -0,0 +1,648 @@
+//! Files on Windows. Files are opened on the host, like on POSIX targets, and handed to the
+//! program as `HANDLE`s (see `handle.rs`).
+
+use std::borrow::Cow;
+use std::ffi::{OsStr, OsString};
+use std::fs::{read_dir, remove_dir, remove_file, rename, DirBuilder, Metadata, OpenOptions};
+use std::io::{self, ErrorKind, Read, Write};
+use std::path::{Path, PathBuf};
+use std::time::SystemTime;
+
+use rustc_target::abi::Size;
+
+use crate::*;
+use shims::os_str::PathConversion;
+use shims::windows::handle::Object;
+
+const GENERIC_ALL: u32 = 0x10000000;
+const GENERIC_WRITE: u32 = 0x40000000;
+const GENERIC_READ: u32 = 0x80000000;
+const FILE_READ_DATA: u32 = 0x1;
+const FILE_WRITE_DATA: u32 = 0x2;
+const FILE_APPEND_DATA: u32 = 0x4;
+
+const CREATE_NEW: u32 = 1;
+const CREATE_ALWAYS: u32 = 2;
+const OPEN_EXISTING: u32 = 3;
+const OPEN_ALWAYS: u32 = 4;
+const TRUNCATE_EXISTING: u32 = 5;
+
+const FILE_FLAG_SEQUENTIAL_SCAN: u32 = 0x08000000;
+const FILE_FLAG_RANDOM_ACCESS: u32 = 0x10000000;
+const FILE_FLAG_BACKUP_SEMANTICS: u32 = 0x02000000;
+
+const MOVEFILE_REPLACE_EXISTING: u32 = 0x1;
+const MOVEFILE_COPY_ALLOWED: u32 = 0x2;
+const MOVEFILE_WRITE_THROUGH: u32 = 0x8;
+
+const VOLUME_NAME_DOS: u32 = 0x0;
+const VOLUME_NAME_NONE: u32 = 0x4;
+const FILE_NAME_OPENED: u32 = 0x8;
+
+const FILE_ATTRIBUTE_READONLY: u32 = 0x1;
+const FILE_ATTRIBUTE_DIRECTORY: u32 = 0x10;
+const FILE_ATTRIBUTE_NORMAL: u32 = 0x80;
+const FILE_ATTRIBUTE_REPARSE_POINT: u32 = 0x400;
+const IO_REPARSE_TAG_SYMLINK: u32 = 0xA000000C;
+
+/// The length of `WIN32_FIND_DATAW::cFileName`.
+const MAX_PATH: u64 = 260;
+
+const ERROR_ACCESS_DENIED: u32 = 5;
+const ERROR_INVALID_HANDLE: u32 = 6;
+const ERROR_NO_MORE_FILES: u32 = 18;
+const ERROR_INVALID_PARAMETER: u32 = 87;
+const ERROR_ALREADY_EXISTS: u32 = 183;
+
+/// The prefix of the paths that `GetFinalPathNameByHandleW` returns, which tells Windows not to
+/// normalize the rest of the path.
+const VERBATIM_PREFIX: &str = r"\\?\";
+
+const STATUS_INVALID_HANDLE: u32 = 0xC0000008;
+const STATUS_END_OF_FILE: u32 = 0xC0000011;
+/// The status of failed reads and writes. Miri does not tell I/O errors apart.
+const STATUS_IO_DEVICE_ERROR: u32 = 0xC0000185;
+
+/// The value of an invalid `HANDLE`, which functions that open a handle return on failure.
+const INVALID_HANDLE_VALUE: i64 = -1;
+
+impl<'mir, 'tcx: 'mir> EvalContextExtPrivate<'mir, 'tcx> for crate::MiriEvalContext<'mir, 'tcx> {}
+trait EvalContextExtPrivate<'mir, 'tcx: 'mir>: crate::MiriEvalContextExt<'mir, 'tcx> {
+    /// Reads a path that the program passed to a file function. Paths with the verbatim prefix
+    /// are accepted as well, so that the paths returned by `GetFinalPathNameByHandleW` can be
+    /// opened again.
+    fn read_windows_path(&self, ptr: Pointer<Option<Tag>>) -> InterpResult<'tcx, PathBuf> {
+        let this = self.eval_context_ref();
+        let os_str = this.read_os_str_from_wide_str(ptr)?;
+        let os_str = match os_str.to_str().and_then(|path| path.strip_prefix(VERBATIM_PREFIX)) {
+            // Without drive letters, the absolute paths of Unix hosts lose their root in
+            // `GetFinalPathNameByHandleW`.
+            Some(path) if cfg!(unix) => OsString::from(format!(r"\{}", path)),
+            Some(path) => OsString::from(path),
+            None => os_str,
+        };
+        Ok(this
+            .convert_path_separator(Cow::Owned(os_str), PathConversion::TargetToHost)
+            .into_owned()
+            .into())
+    }
+
+    /// Returns `TRUE` if `result` is `Ok`, and sets the last error and returns `FALSE` otherwise.
+    fn try_unwrap_io_result_bool(&mut self, result: io::Result<()>) -> InterpResult<'tcx, i32> {
+        let this = self.eval_context_mut();
+        match result {
+            Ok(()) => Ok(1),
+            Err(e) => {
+                this.set_last_error_from_io_error(e.kind())?;
+                Ok(0)
+            }
+        }
+    }
+
+    /// Fills the `WIN32_FIND_DATAW` that `find_data_op` points to with the entry `name`.
+    fn write_find_data(
+        &mut self,
+        name: &OsStr,
+        metadata: &Metadata,
+        find_data_op: &OpTy<'tcx, Tag>,
+    ) -> InterpResult<'tcx> {
+        let this = self.eval_context_mut();
+        let find_data = this.deref_operand(find_data_op)?;
+
+        let is_symlink = metadata.file_type().is_symlink();
+        let mut attributes = 0;
+        if metadata.is_dir() {
+            attributes |= FILE_ATTRIBUTE_DIRECTORY;
+        }
+        if is_symlink {
+            attributes |= FILE_ATTRIBUTE_REPARSE_POINT;
+        }
+        if metadata.permissions().readonly() {
+            attributes |= FILE_ATTRIBUTE_READONLY;
+        }
+        if attributes == 0 {
+            attributes = FILE_ATTRIBUTE_NORMAL;
+        }
+        // Hosts that do not know a time report it as the Windows epoch.
+        let file_time = |time: io::Result<SystemTime>| {
+            match time {
+                Ok(time) => this.system_time_to_file_time(&time),
+                Err(_) => Ok(0),
+            }
+        };
+        let times = [
+            file_time(metadata.created())?,
+            file_time(metadata.accessed())?,
+            file_time(metadata.modified())?,
+        ];
+        let size = metadata.len();
+        // The reparse tag tells symbolic links apart from other reparse points.
+        let reparse_tag = if is_symlink { IO_REPARSE_TAG_SYMLINK } else { 0 };
+
+        // `dwFileAttributes`, the three times, `nFileSizeHigh`, `nFileSizeLow`, `dwReserved0`,
+        // `dwReserved1`, `cFileName`, and `cAlternateFileName`.
+        let attributes_place = this.mplace_field(&find_data, 0)?;
+        this.write_scalar(Scalar::from_u32(attributes), &attributes_place.into())?;
+        for (i, time) in times.iter().enumerate() {
+            let time_place = this.mplace_field(&find_data, i + 1)?;
+            this.write_file_time(*time, &time_place)?;
+        }
+        let size_high = this.mplace_field(&find_data, 4)?;
+        this.write_scalar(Scalar::from_u32((size >> 32) as u32), &size_high.into())?;
+        let size_low = this.mplace_field(&find_data, 5)?;
+        this.write_scalar(Scalar::from_u32(size as u32), &size_low.into())?;
+        let reserved0 = this.mplace_field(&find_data, 6)?;
+        this.write_scalar(Scalar::from_u32(reparse_tag), &reserved0.into())?;
+        let reserved1 = this.mplace_field(&find_data, 7)?;
+        this.write_scalar(Scalar::from_u32(0), &reserved1.into())?;
+        let file_name = this.mplace_field(&find_data, 8)?;
+        let (written, _) = this.write_os_str_to_wide_str(name, file_name.ptr, MAX_PATH)?;
+        if !written {
+            throw_unsup_format!("file name {:?} is too long for `WIN32_FIND_DATAW`", name);
+        }
+        // There are no short (8.3) names.
+        let alternate_file_name = this.mplace_field(&find_data, 9)?;
+        this.write_os_str_to_wide_str(OsStr::new(""), alternate_file_name.ptr, 14)?;
+        Ok(())
+    }
+
+    /// Lists the next entry of the find handle `find_file` into `find_data_op`. Returns `FALSE`
+    /// (and sets the last error) if there is none.
+    fn find_next(
+        &mut self,
+        find_file: u64,
+        find_data_op: &OpTy<'tcx, Tag>,
+    ) -> InterpResult<'tcx, i32> {
+        let this = self.eval_context_mut();
+        let error = match this.machine.dir_handler.next_entry_metadata(find_file) {
+            Some(Some(Ok((name, metadata)))) => {
+                this.write_find_data(&name, &metadata, find_data_op)?;
+                return Ok(1);
+            }
+            Some(Some(Err(e))) => this.io_error_to_errnum(e.kind())?,
+            Some(None) => Scalar::from_u32(ERROR_NO_MORE_FILES),
+            None => Scalar::from_u32(ERROR_INVALID_HANDLE),
+        };
+        this.set_last_error(error)?;
+        Ok(0)
+    }
+
+    /// Checks the arguments that `NtReadFile` and `NtWriteFile` have in common, and returns the
+    /// handle and the `IO_STATUS_BLOCK` place.
+    fn nt_io_args(
+        &mut self,
+        name: &str,
+        handle_op: &OpTy<'tcx, Tag>,
+        io_status_block_op: &OpTy<'tcx, Tag>,
+        byte_offset_op: &OpTy<'tcx, Tag>,
+    ) -> InterpResult<'tcx, (u64, MPlaceTy<'tcx, Tag>)> {
+        let this = self.eval_context_mut();
+        let handle = this.read_scalar(handle_op)?.to_machine_usize(this)?;
+        let io_status_block = this.deref_operand(io_status_block_op)?;
+        // Without an offset, the I/O happens at the current position of the file.
+        if !this.ptr_is_null(this.read_pointer(byte_offset_op)?)? {
+            throw_unsup_format!("`{}` with a `ByteOffset` is not supported", name);
+        }
+        Ok((handle, io_status_block))
+    }
+
+    /// Finishes `NtReadFile` or `NtWriteFile`: stores how many bytes were transferred in the
+    /// `IO_STATUS_BLOCK` and returns the `NTSTATUS`.
+    fn finish_nt_io(
+        &mut self,
+        io_status_block: &MPlaceTy<'tcx, Tag>,
+        result: io::Result<usize>,
+    ) -> InterpResult<'tcx, u32> {
+        let this = self.eval_context_mut();
+        let n = match result {
+            Ok(n) => n,
+            Err(_) => return Ok(STATUS_IO_DEVICE_ERROR),
+        };
+        let information = this.mplace_field_named(io_status_block, "Information")?;
+        let n = u64::try_from(n).unwrap();
+        this.write_scalar(Scalar::from_machine_usize(n, this), &information.into())?;
+        Ok(0) // STATUS_SUCCESS
+    }
+
+    /// The path of the file that `handle` refers to, or `None` if `handle` is not a file.
+    fn file_path(&self, handle: u64) -> Option<&Path> {
+        let this = self.eval_context_ref();
+        match this.machine.windows_handles.get(handle) {
+            Some(Object::File { path, .. }) => Some(path),
+            _ => None,
+        }
+    }
+}
+
+impl<'mir, 'tcx: 'mir> EvalContextExt<'mir, 'tcx> for crate::MiriEvalContext<'mir, 'tcx> {}
+pub trait EvalContextExt<'mir, 'tcx: 'mir>: crate::MiriEvalContextExt<'mir, 'tcx> {
+    #[allow(non_snake_case)]
+    fn CreateFileW(
+        &mut self,
+        file_name_op: &OpTy<'tcx, Tag>,
+        desired_access_op: &OpTy<'tcx, Tag>,
+        _share_mode_op: &OpTy<'tcx, Tag>,
+        security_attributes_op: &OpTy<'tcx, Tag>,
+        creation_disposition_op: &OpTy<'tcx, Tag>,
+        flags_and_attributes_op: &OpTy<'tcx, Tag>,
+        template_file_op: &OpTy<'tcx, Tag>,
+    ) -> InterpResult<'tcx, i64> {
+        let this = self.eval_context_mut();
+
+        let file_name = this.read_pointer(file_name_op)?;
+        let desired_access = this.read_scalar(desired_access_op)?.to_u32()?;
+        let security_attributes = this.read_pointer(security_attributes_op)?;
+        let creation_disposition = this.read_scalar(creation_disposition_op)?.to_u32()?;
+        let flags_and_attributes = this.read_scalar(flags_and_attributes_op)?.to_u32()?;
+        let template_file = this.read_scalar(template_file_op)?.to_machine_isize(this)?;
+
+        if !this.ptr_is_null(security_attributes)? {
+            throw_unsup_format!("`CreateFileW` with security attributes is not supported");
+        }
+        if template_file != 0 {
+            throw_unsup_format!("`CreateFileW` with a template file is not supported");
+        }
+        // Other flags (like `FILE_FLAG_DELETE_ON_CLOSE`, `FILE_FLAG_OPEN_REPARSE_POINT` or
+        // `FILE_FLAG_OVERLAPPED`) and attributes would change what the file is or how it is
+        // accessed, which we cannot do with the host file. The access pattern hints do not matter.
+        let unsupported = flags_and_attributes
+            & !(FILE_ATTRIBUTE_NORMAL
+                | FILE_FLAG_SEQUENTIAL_SCAN
+                | FILE_FLAG_RANDOM_ACCESS
+                | FILE_FLAG_BACKUP_SEMANTICS);
+        if unsupported != 0 {
+            throw_unsup_format!(
+                "`CreateFileW` with flags and attributes {:#x} is not supported",
+                unsupported
+            );
+        }
+
+        // Sharing is not emulated: Miri's host does not restrict it, and there are no other
+        // processes that could observe it.
+        let mut options = OpenOptions::new();
+        let read = desired_access & (GENERIC_ALL | GENERIC_READ | FILE_READ_DATA) != 0;
+        let write = desired_access & (GENERIC_ALL | GENERIC_WRITE | FILE_WRITE_DATA) != 0;
+        let append = !write && desired_access & FILE_APPEND_DATA != 0;
+        // Opening a file without access to its data is how Windows queries the metadata of a
+        // file; reading works for that on every host.
+        options.read(read || !(write || append)).write(write).append(append);
+        match creation_disposition {
+            CREATE_NEW => {
+                options.create_new(true);
+            }
+            CREATE_ALWAYS => {
+                options.create(true).truncate(true);
+            }
+            OPEN_EXISTING => {}
+            OPEN_ALWAYS => {
+                options.create(true);
+            }
+            TRUNCATE_EXISTING => {
+                options.truncate(true);
+            }
+            _ => {
+                this.set_last_error(Scalar::from_u32(ERROR_INVALID_PARAMETER))?;
+                return Ok(INVALID_HANDLE_VALUE);
+            }
+        }
+        if (creation_disposition != OPEN_EXISTING && !(write || append))
+            || (creation_disposition == TRUNCATE_EXISTING && !write)
+        {
+            // The host requires write access to create or truncate a file.
+            throw_unsup_format!(
+                "`CreateFileW` can only create or truncate files that are opened for writing"
+            );
+        }
+
+        let path = this.read_windows_path(file_name)?;
+
+        // Reject if isolation is enabled.
+        if let IsolatedOp::Reject(reject_with) = this.machine.isolated_op {
+            this.reject_in_isolation("`CreateFileW`", reject_with)?;
+            this.set_last_error_from_io_error(ErrorKind::PermissionDenied)?;
+            return Ok(INVALID_HANDLE_VALUE);
+        }
+
+        let existed = path.exists();
+        let file = match options.open(&path) {
+            Ok(file) => file,
+            Err(e) => {
+                this.set_last_error_from_io_error(e.kind())?;
+                return Ok(INVALID_HANDLE_VALUE);
+            }
+        };
+        // Directories can only be opened with backup semantics.
+        if file.metadata().map_or(false, |metadata| metadata.is_dir())
+            && flags_and_attributes & FILE_FLAG_BACKUP_SEMANTICS == 0
+        {
+            this.set_last_error(Scalar::from_u32(ERROR_ACCESS_DENIED))?;
+            return Ok(INVALID_HANDLE_VALUE);
+        }
+        // These succeed either way, but tell the program whether the file was there already.
+        if matches!(creation_disposition, CREATE_ALWAYS | OPEN_ALWAYS) {
+            let error = if existed { ERROR_ALREADY_EXISTS } else { 0 };
+            this.set_last_error(Scalar::from_u32(error))?;
+        }
+        // Relative paths are relative to the current working directory, which can change later.
+        let path = match std::env::current_dir() {
+            Ok(cwd) => cwd.join(path),
+            Err(_) => path,
+        };
+        let handle = this.machine.windows_handles.insert(Object::File { file, path });
+        Ok(i64::try_from(handle).unwrap())
+    }
+
+    #[allow(non_snake_case)]
+    fn GetFinalPathNameByHandleW(
+        &mut self,
+        file_op: &OpTy<'tcx, Tag>,
+        buf_op: &OpTy<'tcx, Tag>,
+        size_op: &OpTy<'tcx, Tag>,
+        flags_op: &OpTy<'tcx, Tag>,
+    ) -> InterpResult<'tcx, u32> {
+        let this = self.eval_context_mut();
+
+        let file = this.read_scalar(file_op)?.to_machine_usize(this)?;
+        let buf = this.read_pointer(buf_op)?;
+        let size = this.read_scalar(size_op)?.to_u32()?;
+        let flags = this.read_scalar(flags_op)?.to_u32()?;
+
+        let volume_name = flags & !FILE_NAME_OPENED;
+        if volume_name != VOLUME_NAME_DOS && volume_name != VOLUME_NAME_NONE {
+            throw_unsup_format!(
+                "`GetFinalPathNameByHandleW` only supports `VOLUME_NAME_DOS` and `VOLUME_NAME_NONE`"
+            );
+        }
+        let path = match this.file_path(file) {
+            Some(path) => path.to_owned(),
+            None => {
+                this.set_last_error(Scalar::from_u32(ERROR_INVALID_HANDLE))?;
+                return Ok(0);
+            }
+        };
+        // The final path has all symbolic links resolved. Files cannot be moved without another
+        // process, so the path the file was opened with still leads to it, unless the program
+        // renamed the file itself; then the opened path is the best we know.
+        let path = if flags & FILE_NAME_OPENED == 0 {
+            std::fs::canonicalize(&path).unwrap_or(path)
+        } else {
+            path
+        };
+        let target_path = this
+            .convert_path_separator(Cow::Owned(path.into_os_string()), PathConversion::HostToTarget)
+            .into_owned();
+        let target_path = target_path
+            .into_string()
+            .map_err(|path| err_unsup_format!("{:?} is not a valid utf-8 string", path))?;
+        // Canonical paths of Windows hosts have the prefix already.
+        let target_path = target_path.strip_prefix(VERBATIM_PREFIX).unwrap_or(&target_path);
+        let final_path = if volume_name == VOLUME_NAME_DOS {
+            format!("{}{}", VERBATIM_PREFIX, target_path.trim_start_matches('\\'))
+        } else {
+            // Without the volume, i.e. the drive letter (`C:`) of Windows hosts. The paths of
+            // Unix hosts have none.
+            match target_path.find('\\') {
+                Some(index) if cfg!(windows) => target_path[index..].to_owned(),
+                _ => target_path.to_owned(),
+            }
+        };
+
+        // If the buffer is too small, the return value is the size the buffer needs, including
+        // the null terminator. Otherwise it is the length of the path.
+        let (_, len) = this.write_os_str_to_wide_str(final_path.as_ref(), buf, size.into())?;
+        Ok(u32::try_from(len).unwrap())
+    }
+
+    #[allow(non_snake_case)]
+    fn DeleteFileW(&mut self, file_name_op: &OpTy<'tcx, Tag>) -> InterpResult<'tcx, i32> {
+        let this = self.eval_context_mut();
+
+        let path = this.read_windows_path(this.read_pointer(file_name_op)?)?;
+
+        // Reject if isolation is enabled.
+        if let IsolatedOp::Reject(reject_with) = this.machine.isolated_op {
+            this.reject_in_isolation("`DeleteFileW`", reject_with)?;
+            this.set_last_error_from_io_error(ErrorKind::PermissionDenied)?;
+            return Ok(0);
+        }
+
+        this.try_unwrap_io_result_bool(remove_file(path))
+    }
+
+    #[allow(non_snake_case)]
+    fn CreateDirectoryW(
+        &mut self,
+        path_name_op: &OpTy<'tcx, Tag>,
+        security_attributes_op: &OpTy<'tcx, Tag>,
+    ) -> InterpResult<'tcx, i32> {
+        let this = self.eval_context_mut();
+
+        let path = this.read_windows_path(this.read_pointer(path_name_op)?)?;
+        if !this.ptr_is_null(this.read_pointer(security_attributes_op)?)? {
+            throw_unsup_format!("`CreateDirectoryW` with security attributes is not supported");
+        }
+
+        // Reject if isolation is enabled.
+        if let IsolatedOp::Reject(reject_with) = this.machine.isolated_op {
+            this.reject_in_isolation("`CreateDirectoryW`", reject_with)?;
+            this.set_last_error_from_io_error(ErrorKind::PermissionDenied)?;
+            return Ok(0);
+        }
+
+        this.try_unwrap_io_result_bool(DirBuilder::new().create(path))
+    }
+
+    #[allow(non_snake_case)]
+    fn RemoveDirectoryW(&mut self, path_name_op: &OpTy<'tcx, Tag>) -> InterpResult<'tcx, i32> {
+        let this = self.eval_context_mut();
+
+        let path = this.read_windows_path(this.read_pointer(path_name_op)?)?;
+
+        // Reject if isolation is enabled.
+        if let IsolatedOp::Reject(reject_with) = this.machine.isolated_op {
+            this.reject_in_isolation("`RemoveDirectoryW`", reject_with)?;
+            this.set_last_error_from_io_error(ErrorKind::PermissionDenied)?;
+            return Ok(0);
+        }
+
+        this.try_unwrap_io_result_bool(remove_dir(path))
+    }
+
+    #[allow(non_snake_case)]
+    fn MoveFileExW(
+        &mut self,
+        existing_file_name_op: &OpTy<'tcx, Tag>,
+        new_file_name_op: &OpTy<'tcx, Tag>,
+        flags_op: &OpTy<'tcx, Tag>,
+    ) -> InterpResult<'tcx, i32> {
+        let this = self.eval_context_mut();
+
+        let flags = this.read_scalar(flags_op)?.to_u32()?;
+        // Miri writes to the host right away, and moves that need to copy across volumes are
+        // done by the host anyway (or fail there).
+        let supported = MOVEFILE_REPLACE_EXISTING | MOVEFILE_COPY_ALLOWED | MOVEFILE_WRITE_THROUGH;
+        if flags & !supported != 0 {
+            throw_unsup_format!("`MoveFileExW` flags {:#x} are not supported", flags & !supported);
+        }
+        let existing_path = this.read_windows_path(this.read_pointer(existing_file_name_op)?)?;
+        let new_path = this.read_windows_path(this.read_pointer(new_file_name_op)?)?;
+
+        // Reject if isolation is enabled.
+        if let IsolatedOp::Reject(reject_with) = this.machine.isolated_op {
+            this.reject_in_isolation("`MoveFileExW`", reject_with)?;
+            this.set_last_error_from_io_error(ErrorKind::PermissionDenied)?;
+            return Ok(0);
+        }
+
+        // Unlike `rename` on the host, this only replaces an existing file if asked to.
+        if flags & MOVEFILE_REPLACE_EXISTING == 0 && new_path.symlink_metadata().is_ok() {
+            this.set_last_error(Scalar::from_u32(ERROR_ALREADY_EXISTS))?;
+            return Ok(0);
+        }
+        this.try_unwrap_io_result_bool(rename(existing_path, new_path))
+    }
+
+    #[allow(non_snake_case)]
+    fn FindFirstFileW(
+        &mut self,
+        file_name_op: &OpTy<'tcx, Tag>,
+        find_data_op: &OpTy<'tcx, Tag>,
+    ) -> InterpResult<'tcx, i64> {
+        let this = self.eval_context_mut();
+
+        let pattern = this.read_windows_path(this.read_pointer(file_name_op)?)?;
+        // `read_dir` lists directories with the pattern `*`.
+        let dir = match pattern.file_name().and_then(OsStr::to_str) {
+            Some("*" | "*.*") => pattern.parent().unwrap(),
+            _ =>
+                throw_unsup_format!(
+                    "`FindFirstFileW` is only supported with the pattern `*`, not {:?}",
+                    pattern
+                ),
+        };
+
+        // Reject if isolation is enabled.
+        if let IsolatedOp::Reject(reject_with) = this.machine.isolated_op {
+            this.reject_in_isolation("`FindFirstFileW`", reject_with)?;
+            this.set_last_error_from_io_error(ErrorKind::PermissionDenied)?;
+            return Ok(INVALID_HANDLE_VALUE);
+        }
+
+        let find_file = match read_dir(dir) {
+            Ok(read_dir) => this.machine.dir_handler.insert_with_dot_entries(read_dir, dir),
+            Err(e) => {
+                this.set_last_error_from_io_error(e.kind())?;
+                return Ok(INVALID_HANDLE_VALUE);
+            }
+        };
+        // The first entry is `.`, so there always is one.
+        if this.find_next(find_file, find_data_op)? == 0 {
+            this.machine.dir_handler.remove(find_file);
+            return Ok(INVALID_HANDLE_VALUE);
+        }
+        Ok(i64::try_from(find_file).unwrap())
+    }
+
+    #[allow(non_snake_case)]
+    fn FindNextFileW(
+        &mut self,
+        find_file_op: &OpTy<'tcx, Tag>,
+        find_data_op: &OpTy<'tcx, Tag>,
+    ) -> InterpResult<'tcx, i32> {
+        let this = self.eval_context_mut();
+
+        let find_file = this.read_scalar(find_file_op)?.to_machine_usize(this)?;
+        this.find_next(find_file, find_data_op)
+    }
+
+    #[allow(non_snake_case)]
+    fn FindClose(&mut self, find_file_op: &OpTy<'tcx, Tag>) -> InterpResult<'tcx, i32> {
+        let this = self.eval_context_mut();
+
+        let find_file = this.read_scalar(find_file_op)?.to_machine_usize(this)?;
+        if !this.machine.dir_handler.remove(find_file) {
+            this.set_last_error(Scalar::from_u32(ERROR_INVALID_HANDLE))?;
+            return Ok(0);
+        }
+        Ok(1)
+    }
+
+    /// The `NtWriteFile` of `ntdll.dll`, which std uses to write to handles. Returns the
+    /// `NTSTATUS`.
+    #[allow(non_snake_case)]
+    fn NtWriteFile(
+        &mut self,
+        handle_op: &OpTy<'tcx, Tag>,
+        io_status_block_op: &OpTy<'tcx, Tag>,
+        buf_op: &OpTy<'tcx, Tag>,
+        n_op: &OpTy<'tcx, Tag>,
+        byte_offset_op: &OpTy<'tcx, Tag>,
+    ) -> InterpResult<'tcx, u32> {
+        let this = self.eval_context_mut();
+
+        let (handle, io_status_block) =
+            this.nt_io_args("NtWriteFile", handle_op, io_status_block_op, byte_offset_op)?;
+        let buf = this.read_pointer(buf_op)?;
+        let n = this.read_scalar(n_op)?.to_u32()?;
+
+        let bytes = this.read_bytes_ptr(buf, Size::from_bytes(n))?;
+        let result = match this.machine.windows_handles.get(handle) {
+            Some(Object::File { file, .. }) => (&mut &*file).write(bytes),
+            // We allow writing to stdout and stderr even with isolation enabled.
+            Some(Object::Stdio { fd: 1 }) => {
+                let result = io::stdout().write(bytes);
+                // Like `write` on stdout, this corresponds to a write on the host, so we do not
+                // buffer anything.
+                io::stdout().flush().unwrap();
+                result
+            }
+            Some(Object::Stdio { fd: 2 }) => io::stderr().write(bytes),
+            Some(Object::Stdio { .. }) => throw_unsup_format!("cannot write to stdin"),
+            _ => return Ok(STATUS_INVALID_HANDLE),
+        };
+        this.finish_nt_io(&io_status_block, result)
+    }
+
+    /// The `NtReadFile` of `ntdll.dll`, which std uses to read from handles. Returns the
+    /// `NTSTATUS`.
+    #[allow(non_snake_case)]
+    fn NtReadFile(
+        &mut self,
+        handle_op: &OpTy<'tcx, Tag>,
+        io_status_block_op: &OpTy<'tcx, Tag>,
+        buf_op: &OpTy<'tcx, Tag>,
+        n_op: &OpTy<'tcx, Tag>,
+        byte_offset_op: &OpTy<'tcx, Tag>,
+    ) -> InterpResult<'tcx, u32> {
+        let this = self.eval_context_mut();
+
+        let (handle, io_status_block) =
+            this.nt_io_args("NtReadFile", handle_op, io_status_block_op, byte_offset_op)?;
+        let buf = this.read_pointer(buf_op)?;
+        let n = this.read_scalar(n_op)?.to_u32()?;
+
+        let mut bytes = vec![0; usize::try_from(n).unwrap()];
+        let result = match this.machine.windows_handles.get(handle) {
+            Some(Object::File { file, .. }) => (&mut &*file).read(&mut bytes),
+            Some(Object::Stdio { fd: 0 }) => {
+                if !this.machine.communicate() {
+                    // We want isolation mode to be deterministic, so we have to disallow all
+                    // reads, even stdin.
+                    helpers::isolation_abort_error("`NtReadFile` from stdin")?;
+                }
+                io::stdin().read(&mut bytes)
+            }
+            Some(Object::Stdio { .. }) => throw_unsup_format!("cannot read from stdout/stderr"),
+            _ => return Ok(STATUS_INVALID_HANDLE),
+        };
+        // Reading nothing is how the end of the file shows.
+        if let Ok(read) = result {
+            this.write_bytes_ptr(buf, bytes[..read].iter().copied())?;
+            if read == 0 && n != 0 {
+                this.finish_nt_io(&io_status_block, Ok(0))?;
+                return Ok(STATUS_END_OF_FILE);
+            }
+        }
+        this.finish_nt_io(&io_status_block, result)
+    }
+}
@@ -0,0 +1,321 @@
+//! The kernel objects that a Windows program refers to by `HANDLE`.
+
+use std::collections::BTreeMap;
+use std::fs::File;
+use std::path::PathBuf;
+
+use crate::*;
+
+/// The first handle value that is handed out. Like on Windows, handles are multiples of 4.
+const FIRST_HANDLE: u64 = 0x100;
+
+/// The pseudo-handle that `GetCurrentProcess` returns, which always refers to the current process.
+pub const CURRENT_PROCESS_HANDLE: i64 = -1;
+/// The pseudo-handle that `GetCurrentThread` returns, which always refers to the calling thread.
+pub const CURRENT_THREAD_HANDLE: i64 = -2;
+
+const DUPLICATE_CLOSE_SOURCE: u32 = 1;
+const DUPLICATE_SAME_ACCESS: u32 = 2;
+
+const ERROR_INVALID_HANDLE: u32 = 6;
+const ERROR_INVALID_PARAMETER: u32 = 87;
+
+/// The handle of the standard stream `fd` (0 for stdin, 1 for stdout, 2 for stderr). The first
+/// handles are those of the standard streams.
+pub fn std_handle(fd: i32) -> u64 {
+    FIRST_HANDLE + 4 * u64::try_from(fd).unwrap()
+}
+
+#[derive(Debug)]
+pub enum Object {
+    Event {
+        /// Whether the event stays signaled when a wait on it is satisfied.
+        manual_reset: bool,
+        signaled: bool,
+    },
+    Mutex {
+        owner: Option<ThreadId>,
+        /// How many times the owner acquired the mutex. Windows mutexes are recursive.
+        recursion: u32,
+    },
+    File {
+        file: File,
+        /// The absolute path the file was opened with. Renaming the file does not change it.
+        path: PathBuf,
+    },
+    /// A standard stream of the host: 0 for stdin, 1 for stdout, 2 for stderr.
+    Stdio { fd: i32 },
+    /// The access token of the process, returned by `OpenProcessToken`.
+    Token,
+    /// A keyed event, created by `NtCreateKeyedEvent`. The threads that wait for it or release it
+    /// are queued by key, so it has no state of its own.
+    KeyedEvent,
+    /// The current process, from duplicating `CURRENT_PROCESS_HANDLE`.
+    Process,
+    /// A thread, from duplicating `CURRENT_THREAD_HANDLE`.
+    Thread(ThreadId),
+}
+
+/// A thread that is blocked in `WaitForSingleObject` or `WaitForMultipleObjects`.
+#[derive(Debug)]
+pub struct Waiter<'tcx> {
+    pub thread: ThreadId,
+    pub handles: Vec<u64>,
+    /// Whether all objects must be signaled at once, instead of any of them.
+    pub wait_all: bool,
+    /// Where the result of the wait is written.
+    pub dest: PlaceTy<'tcx, Tag>,
+}
+
+/// An object, together with the number of handles that refer to it.
+#[derive(Debug)]
+struct Entry {
+    object: Object,
+    handles: usize,
+}
+
+/// The handle table of the process. Duplicated handles refer to the same object, which is
+/// destroyed when its last handle is closed. Handle values are never reused, so using a handle
+/// after it was closed can be told apart from using a handle that never existed.
+#[derive(Debug)]
+pub struct Handles<'tcx> {
+    /// The object that each open handle refers to, by the first handle that referred to it.
+    handles: BTreeMap<u64, u64>,
+    objects: BTreeMap<u64, Entry>,
+    next: u64,
+    /// The blocked waits, in the order they started.
+    pub waiters: Vec<Waiter<'tcx>>,
+}
+
+impl<'tcx> Default for Handles<'tcx> {
+    fn default() -> Self {
+        let mut handles = Handles {
+            handles: BTreeMap::new(),
+            objects: BTreeMap::new(),
+            next: FIRST_HANDLE,
+            waiters: Vec::new(),
+        };
+        for fd in 0..3 {
+            let handle = handles.insert(Object::Stdio { fd });
+            assert_eq!(handle, std_handle(fd));
+        }
+        handles
+    }
+}
+
+impl<'tcx> Handles<'tcx> {
+    /// Returns a new handle that refers to `object`.
+    pub fn insert(&mut self, object: Object) -> u64 {
+        let handle = self.next;
+        self.next += 4;
+        self.handles.insert(handle, handle);
+        self.objects.insert(handle, Entry { object, handles: 1 });
+        handle
+    }
+
+    /// Returns a new handle that refers to the same object as `handle`, if it is open.
+    pub fn duplicate(&mut self, handle: u64) -> Option<u64> {
+        let object = *self.handles.get(&handle)?;
+        self.objects.get_mut(&object).unwrap().handles += 1;
+        let duplicate = self.next;
+        self.next += 4;
+        self.handles.insert(duplicate, object);
+        Some(duplicate)
+    }
+
+    pub fn get(&self, handle: u64) -> Option<&Object> {
+        let object = self.handles.get(&handle)?;
+        Some(&self.objects[object].object)
+    }
+
+    pub fn get_mut(&mut self, handle: u64) -> Option<&mut Object> {
+        let object = self.handles.get(&handle)?;
+        Some(&mut self.objects.get_mut(object).unwrap().object)
+    }
+
+    pub fn contains(&self, handle: u64) -> bool {
+        self.handles.contains_key(&handle)
+    }
+
+    /// Closes `handle`, and destroys its object if no other handle refers to it. Returns whether
+    /// `handle` was open.
+    pub fn close(&mut self, handle: u64) -> bool {
+        let object = match self.handles.remove(&handle) {
+            Some(object) => object,
+            None => return false,
+        };
+        let entry = self.objects.get_mut(&object).unwrap();
+        entry.handles -= 1;
+        if entry.handles == 0 {
+            self.objects.remove(&object);
+        }
+        true
+    }
+
+    /// Whether `handle` was handed out, but has been closed since.
+    pub fn was_closed(&self, handle: u64) -> bool {
+        (FIRST_HANDLE..self.next).contains(&handle)
+            && handle % 4 == 0
+            && !self.handles.contains_key(&handle)
+    }
+
+    /// Whether a wait by `thread` on `handle` would be satisfied now.
+    fn is_signaled(&self, handle: u64, thread: ThreadId) -> bool {
+        // A handle that was closed during the wait is never signaled.
+        let object = match self.get(handle) {
+            Some(object) => object,
+            None => return false,
+        };
+        match object {
+            Object::Event { signaled, .. } => *signaled,
+            Object::Mutex { owner, .. } => owner.map_or(true, |owner| owner == thread),
+            // A file is signaled when it has no I/O in progress, and all I/O is synchronous.
+            Object::File { .. } | Object::Stdio { .. } => true,
+            // Windows does not allow waiting on these, but nothing waits on them anyway.
+            Object::Token | Object::KeyedEvent => true,
+            // These are signaled when they terminate, but the waiting thread is still running,
+            // and Miri does not support creating other threads on Windows.
+            Object::Process | Object::Thread(_) => false,
+        }
+    }
+
+    /// Satisfy a wait by `thread` on `handle`: reset an auto-reset event, or acquire a mutex.
+    fn acquire(&mut self, handle: u64, thread: ThreadId) {
+        match self.get_mut(handle).unwrap() {
+            Object::Event { manual_reset, signaled } =>
+                if !*manual_reset {
+                    *signaled = false;
+                },
+            Object::Mutex { owner, recursion } => {
+                *owner = Some(thread);
+                *recursion += 1;
+            }
+            Object::File { .. }
+            | Object::Stdio { .. }
+            | Object::Token
+            | Object::KeyedEvent
+            | Object::Process
+            | Object::Thread(_) => {}
+        }
+    }
+
+    /// Try to satisfy a wait by `thread` on `handles`. Returns the index of the object that
+    /// satisfied it (0 if all objects had to be signaled), or `None` if the thread has to wait.
+    pub fn try_wait(&mut self, thread: ThreadId, handles: &[u64], wait_all: bool) -> Option<u32> {
+        if wait_all {
+            if !handles.iter().all(|&handle| self.is_signaled(handle, thread)) {
+                return None;
+            }
+            for &handle in handles {
+                self.acquire(handle, thread);
+            }
+            Some(0)
+        } else {
+            let index = handles.iter().position(|&handle| self.is_signaled(handle, thread))?;
+            self.acquire(handles[index], thread);
+            Some(u32::try_from(index).unwrap())
+        }
+    }
+}
+
+impl<'mir, 'tcx: 'mir> EvalContextExt<'mir, 'tcx> for crate::MiriEvalContext<'mir, 'tcx> {}
+pub trait EvalContextExt<'mir, 'tcx: 'mir>: crate::MiriEvalContextExt<'mir, 'tcx> {
+    /// Whether `handle_op` refers to the current process, which is the only process there is.
+    fn is_current_process(&self, handle_op: &OpTy<'tcx, Tag>) -> InterpResult<'tcx, bool> {
+        let this = self.eval_context_ref();
+
+        let handle = this.read_scalar(handle_op)?;
+        if handle.to_machine_isize(this)? == CURRENT_PROCESS_HANDLE {
+            return Ok(true);
+        }
+        let object = this.machine.windows_handles.get(handle.to_machine_usize(this)?);
+        Ok(matches!(object, Some(Object::Process)))
+    }
+
+    #[allow(non_snake_case)]
+    fn CloseHandle(&mut self, handle_op: &OpTy<'tcx, Tag>) -> InterpResult<'tcx, i32> {
+        let this = self.eval_context_mut();
+
+        let handle = this.read_scalar(handle_op)?;
+        // Closing a pseudo-handle does nothing.
+        let pseudo_handles = [CURRENT_PROCESS_HANDLE, CURRENT_THREAD_HANDLE];
+        if pseudo_handles.contains(&handle.to_machine_isize(this)?) {
+            return Ok(1);
+        }
+        let handle = handle.to_machine_usize(this)?;
+        if this.machine.windows_handles.close(handle) {
+            return Ok(1);
+        }
+        // On Windows, the handle may have been reused by then, so this closes an unrelated
+        // object.
+        if this.machine.windows_handles.was_closed(handle) {
+            throw_ub_format!(
+                "`CloseHandle` called on handle {:#x}, which was already closed",
+                handle
+            );
+        }
+        this.set_last_error(Scalar::from_u32(ERROR_INVALID_HANDLE))?;
+        Ok(0)
+    }
+
+    #[allow(non_snake_case)]
+    fn DuplicateHandle(
+        &mut self,
+        source_process_op: &OpTy<'tcx, Tag>,
+        source_handle_op: &OpTy<'tcx, Tag>,
+        target_process_op: &OpTy<'tcx, Tag>,
+        target_handle_op: &OpTy<'tcx, Tag>,
+        _desired_access_op: &OpTy<'tcx, Tag>,
+        _inherit_handle_op: &OpTy<'tcx, Tag>,
+        options_op: &OpTy<'tcx, Tag>,
+    ) -> InterpResult<'tcx, i32> {
+        let this = self.eval_context_mut();
+
+        let source = this.read_scalar(source_handle_op)?;
+        let target_ptr = this.read_pointer(target_handle_op)?;
+        let options = this.read_scalar(options_op)?.to_u32()?;
+
+        if options & !(DUPLICATE_CLOSE_SOURCE | DUPLICATE_SAME_ACCESS) != 0 {
+            this.set_last_error(Scalar::from_u32(ERROR_INVALID_PARAMETER))?;
+            return Ok(0);
+        }
+        // There are no other processes to duplicate handles from or to. Access rights are not
+        // checked, so the duplicate has the same access as the source anyway.
+        if !this.is_current_process(source_process_op)?
+            || !this.is_current_process(target_process_op)?
+        {
+            this.set_last_error(Scalar::from_u32(ERROR_INVALID_HANDLE))?;
+            return Ok(0);
+        }
+
+        let active_thread = this.get_active_thread();
+        let pseudo_source = source.to_machine_isize(this)?;
+        let source = source.to_machine_usize(this)?;
+        let handles = &mut this.machine.windows_handles;
+        // Duplicating a pseudo-handle gives a real handle to the object it refers to.
+        let duplicate = match pseudo_source {
+            CURRENT_PROCESS_HANDLE => Some(handles.insert(Object::Process)),
+            CURRENT_THREAD_HANDLE => Some(handles.insert(Object::Thread(active_thread))),
+            _ => handles.duplicate(source),
+        };
+        let duplicate = match duplicate {
+            Some(duplicate) => duplicate,
+            None => {
+                this.set_last_error(Scalar::from_u32(ERROR_INVALID_HANDLE))?;
+                return Ok(0);
+            }
+        };
+        if options & DUPLICATE_CLOSE_SOURCE != 0 {
+            this.CloseHandle(source_handle_op)?;
+        }
+        // Without a target handle, the duplicate is not needed. This is how a handle of another
+        // process is closed, and here it just closes the source.
+        if this.ptr_is_null(target_ptr)? {
+            this.machine.windows_handles.close(duplicate);
+        } else {
+            let target_place = this.deref_operand(target_handle_op)?;
+            this.write_scalar(Scalar::from_machine_usize(duplicate, this), &target_place.into())?;
+        }
+        Ok(1)
+    }
+}
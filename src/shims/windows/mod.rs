@@ -1,4 +1,11 @@
 pub mod dlsym;
+pub mod exception;
 pub mod foreign_items;
 
+mod dirs;
+mod fs;
+mod handle;
 mod sync;
+mod virtual_memory;
+
+pub use handle::Handles;
@@ -1,4 +1,30 @@
+use std::time::Duration;
+
+use rustc_target::abi::Size;
+
 use crate::*;
+use shims::windows::handle::{Object, Waiter};
+use thread::Time;
+
+const INFINITE: u32 = u32::MAX;
+const WAIT_OBJECT_0: u32 = 0;
+const WAIT_TIMEOUT: u32 = 0x102;
+const WAIT_FAILED: u32 = u32::MAX;
+const MAXIMUM_WAIT_OBJECTS: u32 = 64;
+const ERROR_INVALID_HANDLE: u32 = 6;
+const ERROR_INVALID_PARAMETER: u32 = 87;
+const ERROR_NOT_OWNER: u32 = 288;
+const ERROR_TIMEOUT: u32 = 1460;
+const STATUS_SUCCESS: u32 = 0;
+const STATUS_TIMEOUT: u32 = 0x102;
+const STATUS_INVALID_HANDLE: u32 = 0xC0000008;
+const STATUS_INVALID_PARAMETER: u32 = 0xC000000D;
+
+// `WaitOnAddress` and keyed events queue their threads by address, in the futex queues. The
+// bitsets keep the queues of the different kinds of waits on the same address apart.
+const WAIT_ON_ADDRESS_BITSET: u32 = 1 << 0;
+const KEYED_EVENT_WAIT_BITSET: u32 = 1 << 1;
+const KEYED_EVENT_RELEASE_BITSET: u32 = 1 << 2;
 
 // Locks are pointer-sized pieces of data, initialized to 0.
 // We use the first 4 bytes to store the RwLockId.
@@ -118,4 +144,429 @@ pub trait EvalContextExt<'mir, 'tcx: 'mir>: crate::MiriEvalContextExt<'mir, 'tcx
 
         Ok(())
     }
+
+    #[allow(non_snake_case)]
+    fn CreateEventW(
+        &mut self,
+        attributes_op: &OpTy<'tcx, Tag>,
+        manual_reset_op: &OpTy<'tcx, Tag>,
+        initial_state_op: &OpTy<'tcx, Tag>,
+        name_op: &OpTy<'tcx, Tag>,
+    ) -> InterpResult<'tcx, u64> {
+        let this = self.eval_context_mut();
+
+        if !this.ptr_is_null(this.read_pointer(attributes_op)?)? {
+            throw_unsup_format!("`CreateEventW` with security attributes is not supported");
+        }
+        if !this.ptr_is_null(this.read_pointer(name_op)?)? {
+            throw_unsup_format!("named events are not supported");
+        }
+        let manual_reset = this.read_scalar(manual_reset_op)?.to_i32()? != 0;
+        let signaled = this.read_scalar(initial_state_op)?.to_i32()? != 0;
+        Ok(this.machine.windows_handles.insert(Object::Event { manual_reset, signaled }))
+    }
+
+    /// Set the state of the event `handle_op`, and wake up the threads that this allows to
+    /// continue.
+    fn set_event_state(
+        &mut self,
+        handle_op: &OpTy<'tcx, Tag>,
+        new_state: bool,
+    ) -> InterpResult<'tcx, i32> {
+        let this = self.eval_context_mut();
+
+        let handle = this.read_scalar(handle_op)?.to_machine_usize(this)?;
+        match this.machine.windows_handles.get_mut(handle) {
+            Some(Object::Event { signaled, .. }) => *signaled = new_state,
+            _ => {
+                this.set_last_error(Scalar::from_u32(ERROR_INVALID_HANDLE))?;
+                return Ok(0);
+            }
+        }
+        this.wake_object_waiters()?;
+        Ok(1)
+    }
+
+    #[allow(non_snake_case)]
+    fn CreateMutexW(
+        &mut self,
+        attributes_op: &OpTy<'tcx, Tag>,
+        initial_owner_op: &OpTy<'tcx, Tag>,
+        name_op: &OpTy<'tcx, Tag>,
+    ) -> InterpResult<'tcx, u64> {
+        let this = self.eval_context_mut();
+
+        if !this.ptr_is_null(this.read_pointer(attributes_op)?)? {
+            throw_unsup_format!("`CreateMutexW` with security attributes is not supported");
+        }
+        if !this.ptr_is_null(this.read_pointer(name_op)?)? {
+            throw_unsup_format!("named mutexes are not supported");
+        }
+        let object = if this.read_scalar(initial_owner_op)?.to_i32()? != 0 {
+            Object::Mutex { owner: Some(this.get_active_thread()), recursion: 1 }
+        } else {
+            Object::Mutex { owner: None, recursion: 0 }
+        };
+        Ok(this.machine.windows_handles.insert(object))
+    }
+
+    #[allow(non_snake_case)]
+    fn ReleaseMutex(&mut self, handle_op: &OpTy<'tcx, Tag>) -> InterpResult<'tcx, i32> {
+        let this = self.eval_context_mut();
+
+        let handle = this.read_scalar(handle_op)?.to_machine_usize(this)?;
+        let active_thread = this.get_active_thread();
+        match this.machine.windows_handles.get_mut(handle) {
+            Some(Object::Mutex { owner, recursion }) if *owner == Some(active_thread) => {
+                *recursion -= 1;
+                if *recursion == 0 {
+                    *owner = None;
+                }
+            }
+            Some(Object::Mutex { .. }) => {
+                this.set_last_error(Scalar::from_u32(ERROR_NOT_OWNER))?;
+                return Ok(0);
+            }
+            _ => {
+                this.set_last_error(Scalar::from_u32(ERROR_INVALID_HANDLE))?;
+                return Ok(0);
+            }
+        }
+        this.wake_object_waiters()?;
+        Ok(1)
+    }
+
+    #[allow(non_snake_case)]
+    fn WaitForSingleObject(
+        &mut self,
+        handle_op: &OpTy<'tcx, Tag>,
+        milliseconds_op: &OpTy<'tcx, Tag>,
+        dest: &PlaceTy<'tcx, Tag>,
+    ) -> InterpResult<'tcx> {
+        let this = self.eval_context_mut();
+
+        let handle = this.read_scalar(handle_op)?.to_machine_usize(this)?;
+        let milliseconds = this.read_scalar(milliseconds_op)?.to_u32()?;
+        this.wait_for_objects(vec![handle], false, milliseconds, dest)
+    }
+
+    #[allow(non_snake_case)]
+    fn WaitForMultipleObjects(
+        &mut self,
+        count_op: &OpTy<'tcx, Tag>,
+        handles_op: &OpTy<'tcx, Tag>,
+        wait_all_op: &OpTy<'tcx, Tag>,
+        milliseconds_op: &OpTy<'tcx, Tag>,
+        dest: &PlaceTy<'tcx, Tag>,
+    ) -> InterpResult<'tcx> {
+        let this = self.eval_context_mut();
+
+        let count = this.read_scalar(count_op)?.to_u32()?;
+        let wait_all = this.read_scalar(wait_all_op)?.to_i32()? != 0;
+        let milliseconds = this.read_scalar(milliseconds_op)?.to_u32()?;
+        if count == 0 || count > MAXIMUM_WAIT_OBJECTS {
+            this.set_last_error(Scalar::from_u32(ERROR_INVALID_PARAMETER))?;
+            this.write_scalar(Scalar::from_u32(WAIT_FAILED), dest)?;
+            return Ok(());
+        }
+        let array = this.read_pointer(handles_op)?;
+        let handle_size = this.pointer_size();
+        let mut handles = Vec::new();
+        for i in 0..u64::from(count) {
+            let ptr = array.offset(handle_size * i, this)?;
+            let place = MPlaceTy::from_aligned_ptr(ptr, this.machine.layouts.usize);
+            handles.push(this.read_scalar(&place.into())?.to_machine_usize(this)?);
+        }
+        this.wait_for_objects(handles, wait_all, milliseconds, dest)
+    }
+
+    /// Wait until the objects `handles` are signaled, and write the result to `dest`.
+    fn wait_for_objects(
+        &mut self,
+        handles: Vec<u64>,
+        wait_all: bool,
+        milliseconds: u32,
+        dest: &PlaceTy<'tcx, Tag>,
+    ) -> InterpResult<'tcx> {
+        let this = self.eval_context_mut();
+
+        if !handles.iter().all(|&handle| this.machine.windows_handles.contains(handle)) {
+            this.set_last_error(Scalar::from_u32(ERROR_INVALID_HANDLE))?;
+            this.write_scalar(Scalar::from_u32(WAIT_FAILED), dest)?;
+            return Ok(());
+        }
+        let active_thread = this.get_active_thread();
+        if let Some(index) =
+            this.machine.windows_handles.try_wait(active_thread, &handles, wait_all)
+        {
+            this.write_scalar(Scalar::from_u32(WAIT_OBJECT_0 + index), dest)?;
+            return Ok(());
+        }
+        if milliseconds == 0 {
+            this.write_scalar(Scalar::from_u32(WAIT_TIMEOUT), dest)?;
+            return Ok(());
+        }
+
+        this.block_thread(active_thread);
+        this.machine.windows_handles.waiters.push(Waiter {
+            thread: active_thread,
+            handles,
+            wait_all,
+            dest: *dest,
+        });
+        if milliseconds != INFINITE {
+            let duration = Duration::from_millis(milliseconds.into());
+            let dest = *dest;
+            this.register_timeout_callback(
+                active_thread,
+                Time::Monotonic(this.machine.clock.instant_in(duration)),
+                Box::new(move |ecx| {
+                    ecx.machine.windows_handles.waiters.retain(|w| w.thread != active_thread);
+                    ecx.unblock_thread(active_thread);
+                    ecx.write_scalar(Scalar::from_u32(WAIT_TIMEOUT), &dest)
+                }),
+            );
+        }
+        Ok(())
+    }
+
+    /// Satisfy the waits that can be satisfied now, in the order in which they started.
+    fn wake_object_waiters(&mut self) -> InterpResult<'tcx> {
+        let this = self.eval_context_mut();
+
+        let mut i = 0;
+        while i < this.machine.windows_handles.waiters.len() {
+            let handles = &mut this.machine.windows_handles;
+            let Waiter { thread, wait_all, .. } = handles.waiters[i];
+            let waiter_handles = std::mem::take(&mut handles.waiters[i].handles);
+            match handles.try_wait(thread, &waiter_handles, wait_all) {
+                Some(index) => {
+                    let waiter = handles.waiters.remove(i);
+                    this.unregister_timeout_callback_if_exists(thread);
+                    this.unblock_thread(thread);
+                    this.write_scalar(Scalar::from_u32(WAIT_OBJECT_0 + index), &waiter.dest)?;
+                }
+                None => {
+                    handles.waiters[i].handles = waiter_handles;
+                    i += 1;
+                }
+            }
+        }
+        Ok(())
+    }
+
+    /// Block the active thread in the queue of `addr` that `bitset` selects, until another thread
+    /// wakes it with `wake_address_waiter`, or until `timeout`. Then `on_timeout` is called.
+    fn wait_on_address_queue(
+        &mut self,
+        addr: u64,
+        bitset: u32,
+        timeout: Option<Time>,
+        on_timeout: impl FnOnce(&mut MiriEvalContext<'mir, 'tcx>) -> InterpResult<'tcx> + 'tcx,
+    ) {
+        let this = self.eval_context_mut();
+
+        let thread = this.get_active_thread();
+        this.block_thread(thread);
+        this.futex_wait(addr, thread, bitset);
+        if let Some(timeout) = timeout {
+            this.register_timeout_callback(
+                thread,
+                timeout,
+                Box::new(move |ecx| {
+                    ecx.futex_remove_waiter(addr, thread);
+                    ecx.unblock_thread(thread);
+                    on_timeout(ecx)
+                }),
+            );
+        }
+    }
+
+    /// Wake the first thread in the queue of `addr` that `bitset` selects, if there is one.
+    fn wake_address_waiter(&mut self, addr: u64, bitset: u32) -> bool {
+        let this = self.eval_context_mut();
+
+        match this.futex_wake(addr, bitset) {
+            Some(thread) => {
+                this.unregister_timeout_callback_if_exists(thread);
+                this.unblock_thread(thread);
+                true
+            }
+            None => false,
+        }
+    }
+
+    #[allow(non_snake_case)]
+    fn WaitOnAddress(
+        &mut self,
+        addr_op: &OpTy<'tcx, Tag>,
+        compare_op: &OpTy<'tcx, Tag>,
+        size_op: &OpTy<'tcx, Tag>,
+        milliseconds_op: &OpTy<'tcx, Tag>,
+        dest: &PlaceTy<'tcx, Tag>,
+    ) -> InterpResult<'tcx> {
+        let this = self.eval_context_mut();
+
+        let ptr = this.read_pointer(addr_op)?;
+        let addr = this.read_scalar(addr_op)?.to_machine_usize(this)?;
+        let compare = this.read_pointer(compare_op)?;
+        let size = this.read_scalar(size_op)?.to_machine_usize(this)?;
+        let milliseconds = this.read_scalar(milliseconds_op)?.to_u32()?;
+
+        let ty = match size {
+            1 => this.tcx.types.u8,
+            2 => this.tcx.types.u16,
+            4 => this.tcx.types.u32,
+            8 => this.tcx.types.u64,
+            _ => {
+                this.set_last_error(Scalar::from_u32(ERROR_INVALID_PARAMETER))?;
+                this.write_scalar(Scalar::from_i32(0), dest)?;
+                return Ok(());
+            }
+        };
+        let layout = this.layout_of(ty)?;
+        // Like the futex word, the value at `addr` is read atomically.
+        let place = MPlaceTy::from_aligned_ptr(ptr, layout);
+        let value = this.read_scalar_atomic(&place, AtomicReadOp::SeqCst)?.check_init()?;
+        let compare = this.read_scalar(&MPlaceTy::from_aligned_ptr(compare, layout).into())?;
+        // Waking up always returns `TRUE`, so that is written now. A timeout overwrites it.
+        this.write_scalar(Scalar::from_i32(1), dest)?;
+        if value != compare.check_init()? {
+            return Ok(());
+        }
+        if milliseconds == 0 {
+            this.set_last_error(Scalar::from_u32(ERROR_TIMEOUT))?;
+            this.write_scalar(Scalar::from_i32(0), dest)?;
+            return Ok(());
+        }
+
+        let timeout = (milliseconds != INFINITE).then(|| {
+            let duration = Duration::from_millis(milliseconds.into());
+            Time::Monotonic(this.machine.clock.instant_in(duration))
+        });
+        let dest = *dest;
+        this.wait_on_address_queue(addr, WAIT_ON_ADDRESS_BITSET, timeout, move |ecx| {
+            ecx.set_last_error(Scalar::from_u32(ERROR_TIMEOUT))?;
+            ecx.write_scalar(Scalar::from_i32(0), &dest)
+        });
+        Ok(())
+    }
+
+    /// `WakeByAddressSingle` and `WakeByAddressAll`.
+    fn wake_by_address(&mut self, addr_op: &OpTy<'tcx, Tag>, all: bool) -> InterpResult<'tcx> {
+        let this = self.eval_context_mut();
+
+        let addr = this.read_scalar(addr_op)?.to_machine_usize(this)?;
+        while this.wake_address_waiter(addr, WAIT_ON_ADDRESS_BITSET) && all {}
+        Ok(())
+    }
+
+    #[allow(non_snake_case)]
+    fn NtCreateKeyedEvent(
+        &mut self,
+        handle_op: &OpTy<'tcx, Tag>,
+        _access_op: &OpTy<'tcx, Tag>,
+        attributes_op: &OpTy<'tcx, Tag>,
+        flags_op: &OpTy<'tcx, Tag>,
+    ) -> InterpResult<'tcx, u32> {
+        let this = self.eval_context_mut();
+
+        if !this.ptr_is_null(this.read_pointer(attributes_op)?)? {
+            throw_unsup_format!("`NtCreateKeyedEvent` with object attributes is not supported");
+        }
+        if this.read_scalar(flags_op)?.to_u32()? != 0 {
+            return Ok(STATUS_INVALID_PARAMETER);
+        }
+        let handle = this.machine.windows_handles.insert(Object::KeyedEvent);
+        let handle_place = this.deref_operand(handle_op)?;
+        this.write_scalar(Scalar::from_machine_usize(handle, this), &handle_place.into())?;
+        Ok(STATUS_SUCCESS)
+    }
+
+    /// `NtWaitForKeyedEvent` and `NtReleaseKeyedEvent`. Each release of a key is paired with
+    /// one wait for it: whichever comes first blocks until the other one happens.
+    fn keyed_event_op(
+        &mut self,
+        handle_op: &OpTy<'tcx, Tag>,
+        key_op: &OpTy<'tcx, Tag>,
+        _alertable_op: &OpTy<'tcx, Tag>,
+        timeout_op: &OpTy<'tcx, Tag>,
+        release: bool,
+        dest: &PlaceTy<'tcx, Tag>,
+    ) -> InterpResult<'tcx> {
+        let this = self.eval_context_mut();
+
+        // A null handle is the keyed event that Windows creates for every process.
+        let handle = this.read_scalar(handle_op)?.to_machine_usize(this)?;
+        let keyed_event = this.machine.windows_handles.get(handle);
+        if handle != 0 && !matches!(keyed_event, Some(Object::KeyedEvent)) {
+            this.write_scalar(Scalar::from_u32(STATUS_INVALID_HANDLE), dest)?;
+            return Ok(());
+        }
+        // Keys are addresses, so that is what the threads queue on. Programs use a single keyed
+        // event, so the queues are not kept apart by handle.
+        let key = this.read_scalar(key_op)?.to_machine_usize(this)?;
+        if key % 2 != 0 {
+            this.write_scalar(Scalar::from_u32(STATUS_INVALID_PARAMETER), dest)?;
+            return Ok(());
+        }
+        // Miri never queues APCs, so an alertable wait is never interrupted.
+        let (own_bitset, other_bitset) = if release {
+            (KEYED_EVENT_RELEASE_BITSET, KEYED_EVENT_WAIT_BITSET)
+        } else {
+            (KEYED_EVENT_WAIT_BITSET, KEYED_EVENT_RELEASE_BITSET)
+        };
+
+        // Waking up always returns `STATUS_SUCCESS`, so that is written now. A timeout
+        // overwrites it.
+        this.write_scalar(Scalar::from_u32(STATUS_SUCCESS), dest)?;
+        if this.wake_address_waiter(key, other_bitset) {
+            return Ok(());
+        }
+        let timeout = match this.read_timeout(timeout_op)? {
+            Some(Some(timeout)) => Some(timeout),
+            // A timeout of zero does not wait at all.
+            Some(None) => {
+                this.write_scalar(Scalar::from_u32(STATUS_TIMEOUT), dest)?;
+                return Ok(());
+            }
+            None => None,
+        };
+        let dest = *dest;
+        this.wait_on_address_queue(key, own_bitset, timeout, move |ecx| {
+            ecx.write_scalar(Scalar::from_u32(STATUS_TIMEOUT), &dest)
+        });
+        Ok(())
+    }
+
+    /// Reads the `LARGE_INTEGER` timeout of an `Nt*` function that `timeout_op` points to. Returns
+    /// `None` if it is null (which means to wait forever), and `Some(None)` for a zero timeout.
+    /// Negative timeouts are relative, in 100ns intervals; positive ones are absolute `FILETIME`s.
+    fn read_timeout(
+        &mut self,
+        timeout_op: &OpTy<'tcx, Tag>,
+    ) -> InterpResult<'tcx, Option<Option<Time>>> {
+        let this = self.eval_context_mut();
+
+        if this.ptr_is_null(this.read_pointer(timeout_op)?)? {
+            return Ok(None);
+        }
+        let timeout_place = this.deref_operand(timeout_op)?;
+        let i64_layout = this.layout_of(this.tcx.types.i64)?;
+        let timeout_place =
+            timeout_place.offset(Size::ZERO, MemPlaceMeta::None, i64_layout, this)?;
+        let timeout = this.read_scalar(&timeout_place.into())?.to_i64()?;
+
+        Ok(Some(match timeout {
+            0 => None,
+            _ if timeout < 0 => {
+                let duration = this.file_time_ticks_to_duration(timeout.unsigned_abs())?;
+                Some(Time::Monotonic(this.machine.clock.instant_in(duration)))
+            }
+            _ => {
+                let time = this.file_time_to_system_time(u64::try_from(timeout).unwrap())?;
+                Some(Time::RealTime(time))
+            }
+        }))
+    }
 }
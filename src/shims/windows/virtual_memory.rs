@@ -0,0 +1,252 @@
+//! Virtual memory: `VirtualAlloc`, `VirtualFree`, and `VirtualProtect`.
+//!
+//! Like the mappings of `mmap`, every region reserved by `VirtualAlloc` is an allocation of its
+//! own (see `shims::mapping`), so only whole regions can be released. Committing, decommitting,
+//! and changing the protection works on individual pages, though: accessing a page that is only
+//! reserved, or that the protection does not allow to access, is reported as UB.
+
+use std::iter;
+
+use rustc_target::abi::{Align, Size};
+
+use crate::*;
+use shims::mapping::{EvalContextExt as _, Mapping, Page};
+
+const MEM_COMMIT: u32 = 0x1000;
+const MEM_RESERVE: u32 = 0x2000;
+const MEM_DECOMMIT: u32 = 0x4000;
+const MEM_RELEASE: u32 = 0x8000;
+
+const PAGE_NOACCESS: u32 = 0x01;
+const PAGE_READONLY: u32 = 0x02;
+const PAGE_READWRITE: u32 = 0x04;
+/// `PAGE_EXECUTE` and its variants.
+const PAGE_EXECUTE_ANY: u32 = 0xf0;
+/// `PAGE_GUARD`, `PAGE_NOCACHE`, and `PAGE_WRITECOMBINE`.
+const PAGE_MODIFIERS: u32 = 0x700;
+
+const ERROR_NOT_ENOUGH_MEMORY: u32 = 8;
+const ERROR_INVALID_PARAMETER: u32 = 87;
+const ERROR_INVALID_ADDRESS: u32 = 487;
+
+/// Regions are reserved at multiples of the allocation granularity.
+const ALLOCATION_GRANULARITY: u64 = 16 * PAGE_SIZE;
+
+/// The state that pages committed with protection `protect` get. Returns `None` if `protect` is
+/// not a valid protection.
+fn page_with_protection<'tcx>(protect: u32) -> InterpResult<'tcx, Option<Page>> {
+    Ok(match protect {
+        PAGE_NOACCESS => Some(Page::NoAccess),
+        PAGE_READONLY => Some(Page::ReadOnly),
+        PAGE_READWRITE => Some(Page::ReadWrite),
+        _ if protect & PAGE_EXECUTE_ANY != 0 =>
+            throw_unsup_format!("executable memory is not supported"),
+        _ if protect & PAGE_MODIFIERS != 0 =>
+            throw_unsup_format!("page protection {:#x} is not supported", protect),
+        _ => None,
+    })
+}
+
+/// The protection of a committed page.
+fn protection_of(page: Page) -> u32 {
+    match page {
+        Page::Reserved => bug!("reserved pages have no protection"),
+        Page::NoAccess => PAGE_NOACCESS,
+        Page::ReadOnly => PAGE_READONLY,
+        Page::ReadWrite => PAGE_READWRITE,
+    }
+}
+
+impl<'mir, 'tcx: 'mir> EvalContextExtPrivate<'mir, 'tcx> for crate::MiriEvalContext<'mir, 'tcx> {}
+trait EvalContextExtPrivate<'mir, 'tcx: 'mir>: crate::MiriEvalContextExt<'mir, 'tcx> {
+    /// Finds the pages that the `size` bytes at `addr` overlap. Returns the region they belong to,
+    /// a pointer to its start, and the page-aligned range of offsets into the region, or `None`
+    /// if the bytes are not all part of the same region.
+    fn region_pages(
+        &self,
+        addr: Pointer<Option<Tag>>,
+        size: u64,
+    ) -> Option<(AllocId, Pointer<Option<Tag>>, u64, u64)> {
+        let this = self.eval_context_ref();
+        let (alloc_id, offset, _) = this.ptr_try_get_alloc_id(addr).ok()?;
+        let mapping = this.mapping(alloc_id)?;
+        let start = offset.bytes() / PAGE_SIZE * PAGE_SIZE;
+        let end = offset.bytes().checked_add(size)?;
+        let end = this.round_to_pages(end)?.bytes();
+        if end > mapping.size().bytes() {
+            return None;
+        }
+        let base = Pointer::new(addr.provenance, addr.addr() - offset);
+        Some((alloc_id, base, start, end))
+    }
+
+    /// Sets the last error to `error` and returns `FALSE`.
+    fn fail(&mut self, error: u32) -> InterpResult<'tcx, i32> {
+        let this = self.eval_context_mut();
+        this.set_last_error(Scalar::from_u32(error))?;
+        Ok(0)
+    }
+}
+
+impl<'mir, 'tcx: 'mir> EvalContextExt<'mir, 'tcx> for crate::MiriEvalContext<'mir, 'tcx> {}
+pub trait EvalContextExt<'mir, 'tcx: 'mir>: crate::MiriEvalContextExt<'mir, 'tcx> {
+    #[allow(non_snake_case)]
+    fn VirtualAlloc(
+        &mut self,
+        address_op: &OpTy<'tcx, Tag>,
+        size_op: &OpTy<'tcx, Tag>,
+        allocation_type_op: &OpTy<'tcx, Tag>,
+        protect_op: &OpTy<'tcx, Tag>,
+    ) -> InterpResult<'tcx, Pointer<Option<Tag>>> {
+        let this = self.eval_context_mut();
+
+        let address = this.read_pointer(address_op)?;
+        let size = this.read_scalar(size_op)?.to_machine_usize(this)?;
+        let allocation_type = this.read_scalar(allocation_type_op)?.to_u32()?;
+        let protect = this.read_scalar(protect_op)?.to_u32()?;
+
+        if allocation_type & !(MEM_COMMIT | MEM_RESERVE) != 0 {
+            throw_unsup_format!(
+                "`VirtualAlloc` flags other than `MEM_COMMIT` and `MEM_RESERVE` are not supported"
+            );
+        }
+        let page = match page_with_protection(protect)? {
+            Some(page) if allocation_type != 0 && size != 0 => page,
+            _ => {
+                this.fail(ERROR_INVALID_PARAMETER)?;
+                return Ok(Pointer::null());
+            }
+        };
+
+        if this.ptr_is_null(address)? {
+            // Reserve a new region, and commit all of it if asked to.
+            let size = match this.round_to_pages(size) {
+                Some(size) => size,
+                None => {
+                    this.fail(ERROR_NOT_ENOUGH_MEMORY)?;
+                    return Ok(Pointer::null());
+                }
+            };
+            let align = Align::from_bytes(ALLOCATION_GRANULARITY).unwrap();
+            let ptr = this.allocate_ptr(size, align, MiriMemoryKind::Mmap.into())?;
+            // Committed pages are zero-initialized. We just allocated this, the access is
+            // definitely in-bounds.
+            this.write_bytes_ptr(ptr.into(), iter::repeat(0u8).take(size.bytes_usize())).unwrap();
+            let page = if allocation_type & MEM_COMMIT != 0 { page } else { Page::Reserved };
+            this.set_mapping(ptr.provenance.alloc_id, Mapping::new(size, page, false))?;
+            return Ok(ptr.into());
+        }
+
+        if allocation_type & MEM_RESERVE != 0 {
+            throw_unsup_format!(
+                "`VirtualAlloc` with `MEM_RESERVE` at a given address is not supported"
+            );
+        }
+        // Commit pages of a region that was reserved before.
+        let (alloc_id, base, start, end) = match this.region_pages(address, size) {
+            Some(pages) => pages,
+            None => {
+                this.fail(ERROR_INVALID_ADDRESS)?;
+                return Ok(Pointer::null());
+            }
+        };
+        // Pages that were not committed yet are zero-filled, and pages that were keep their
+        // contents. Without the mapping, the pages can be written to while we do that.
+        let mut mapping = this.take_mapping(alloc_id)?;
+        for (i, state) in mapping.pages_mut(start, end - start).unwrap().iter_mut().enumerate() {
+            if *state == Page::Reserved {
+                let offset = Size::from_bytes(start + u64::try_from(i).unwrap() * PAGE_SIZE);
+                let zeros = iter::repeat(0u8).take(usize::try_from(PAGE_SIZE).unwrap());
+                this.write_bytes_ptr(base.offset(offset, this)?, zeros)?;
+            }
+            *state = page;
+        }
+        this.set_mapping(alloc_id, mapping)?;
+        Ok(base.offset(Size::from_bytes(start), this)?)
+    }
+
+    #[allow(non_snake_case)]
+    fn VirtualFree(
+        &mut self,
+        address_op: &OpTy<'tcx, Tag>,
+        size_op: &OpTy<'tcx, Tag>,
+        free_type_op: &OpTy<'tcx, Tag>,
+    ) -> InterpResult<'tcx, i32> {
+        let this = self.eval_context_mut();
+
+        let address = this.read_pointer(address_op)?;
+        let size = this.read_scalar(size_op)?.to_machine_usize(this)?;
+        let free_type = this.read_scalar(free_type_op)?.to_u32()?;
+
+        // Releasing, and decommitting with a size of 0, apply to a whole region, which `address`
+        // has to be the start of.
+        let region = match this.ptr_try_get_alloc_id(address) {
+            Ok((alloc_id, offset, _)) if offset.bytes() == 0 =>
+                this.mapping(alloc_id).map(|mapping| (alloc_id, mapping.size())),
+            _ => None,
+        };
+        match free_type {
+            MEM_RELEASE => {
+                if size != 0 {
+                    return this.fail(ERROR_INVALID_PARAMETER);
+                }
+                let (_, size) = match region {
+                    Some(region) => region,
+                    None => return this.fail(ERROR_INVALID_ADDRESS),
+                };
+                let align = Align::from_bytes(ALLOCATION_GRANULARITY).unwrap();
+                this.deallocate_ptr(address, Some((size, align)), MiriMemoryKind::Mmap.into())?;
+            }
+            MEM_DECOMMIT => {
+                let size = match (size, region) {
+                    (0, Some((_, region_size))) => region_size.bytes(),
+                    (0, None) => return this.fail(ERROR_INVALID_ADDRESS),
+                    (size, _) => size,
+                };
+                let (alloc_id, _, start, end) = match this.region_pages(address, size) {
+                    Some(pages) => pages,
+                    None => return this.fail(ERROR_INVALID_ADDRESS),
+                };
+                let mapping = this.mapping_mut(alloc_id)?;
+                mapping.pages_mut(start, end - start).unwrap().fill(Page::Reserved);
+            }
+            _ => return this.fail(ERROR_INVALID_PARAMETER),
+        }
+        Ok(1)
+    }
+
+    #[allow(non_snake_case)]
+    fn VirtualProtect(
+        &mut self,
+        address_op: &OpTy<'tcx, Tag>,
+        size_op: &OpTy<'tcx, Tag>,
+        new_protect_op: &OpTy<'tcx, Tag>,
+        old_protect_op: &OpTy<'tcx, Tag>,
+    ) -> InterpResult<'tcx, i32> {
+        let this = self.eval_context_mut();
+
+        let address = this.read_pointer(address_op)?;
+        let size = this.read_scalar(size_op)?.to_machine_usize(this)?;
+        let new_protect = this.read_scalar(new_protect_op)?.to_u32()?;
+        let old_protect_place = this.deref_operand(old_protect_op)?;
+
+        let page = match page_with_protection(new_protect)? {
+            Some(page) if size != 0 => page,
+            _ => return this.fail(ERROR_INVALID_PARAMETER),
+        };
+        let (alloc_id, _, start, end) = match this.region_pages(address, size) {
+            Some(pages) => pages,
+            None => return this.fail(ERROR_INVALID_ADDRESS),
+        };
+        let mapping = this.mapping_mut(alloc_id)?;
+        let pages = mapping.pages_mut(start, end - start).unwrap();
+        // Only committed pages have a protection.
+        if pages.contains(&Page::Reserved) {
+            return this.fail(ERROR_INVALID_ADDRESS);
+        }
+        let old_protect = protection_of(pages[0]);
+        pages.fill(page);
+        this.write_scalar(Scalar::from_u32(old_protect), &old_protect_place.into())?;
+        Ok(1)
+    }
+}
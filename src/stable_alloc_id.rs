@@ -0,0 +1,98 @@
+//! Allocation IDs that only depend on the execution of the interpreted program.
+//!
+//! The `AllocId`s are handed out by rustc, which also uses them for constants and other
+//! allocations that the program never sees, so they shift whenever the interpreter changes
+//! slightly. A `StableAllocId` instead names an allocation by the thread that created it and how
+//! many allocations that thread had created before, so it is the same whenever the same program
+//! is run with the same seed.
+
+use std::fmt;
+use std::str::FromStr;
+
+use rustc_data_structures::fx::FxHashMap;
+
+use crate::*;
+
+/// The `ordinal`-th allocation (counting from 0) created by `thread`.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash, PartialOrd, Ord)]
+pub struct StableAllocId {
+    pub thread: ThreadId,
+    pub ordinal: u64,
+}
+
+impl fmt::Display for StableAllocId {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}:{}", self.thread.to_u32(), self.ordinal)
+    }
+}
+
+impl FromStr for StableAllocId {
+    type Err = String;
+
+    /// Parses the `<thread>:<ordinal>` form used by `Display`.
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let (thread, ordinal) = s
+            .split_once(':')
+            .ok_or_else(|| format!("`{}` is not of the form `<thread>:<n>`", s))?;
+        let thread = thread.parse::<u32>().map_err(|err| err.to_string())?;
+        let ordinal = ordinal.parse::<u64>().map_err(|err| err.to_string())?;
+        Ok(StableAllocId { thread: ThreadId::from(thread), ordinal })
+    }
+}
+
+/// Assigns the stable IDs, and remembers them for the live allocations (or all allocations, if
+/// the mapping is printed at the end).
+#[derive(Debug, Default)]
+pub struct StableAllocIds {
+    /// The ordinal of the next allocation of each thread.
+    next_ordinal: FxHashMap<ThreadId, u64>,
+    ids: FxHashMap<AllocId, StableAllocId>,
+    /// Whether to keep the IDs of deallocated allocations.
+    keep_all: bool,
+}
+
+impl StableAllocIds {
+    pub fn new(keep_all: bool) -> Self {
+        StableAllocIds { keep_all, ..Default::default() }
+    }
+
+    /// Assigns the stable ID of a new allocation created by `thread`.
+    pub fn assign(&mut self, alloc_id: AllocId, thread: ThreadId) -> StableAllocId {
+        let next_ordinal = self.next_ordinal.entry(thread).or_default();
+        let id = StableAllocId { thread, ordinal: *next_ordinal };
+        *next_ordinal += 1;
+        self.ids.insert(alloc_id, id);
+        id
+    }
+
+    pub fn get(&self, alloc_id: AllocId) -> Option<StableAllocId> {
+        self.ids.get(&alloc_id).copied()
+    }
+
+    pub fn deallocated(&mut self, alloc_id: AllocId) {
+        if !self.keep_all {
+            self.ids.remove(&alloc_id);
+        }
+    }
+
+    /// The mapping from `AllocId`s to stable IDs, ordered by `AllocId`.
+    pub fn table(&self) -> Vec<(AllocId, StableAllocId)> {
+        let mut table: Vec<_> = self.ids.iter().map(|(&alloc_id, &id)| (alloc_id, id)).collect();
+        table.sort();
+        table
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_stable_alloc_id() {
+        let id: StableAllocId = "1:42".parse().unwrap();
+        assert_eq!(id, StableAllocId { thread: ThreadId::from(1), ordinal: 42 });
+        assert_eq!(id.to_string(), "1:42");
+        assert!("42".parse::<StableAllocId>().is_err());
+        assert!("1:-1".parse::<StableAllocId>().is_err());
+    }
+}
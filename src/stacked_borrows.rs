@@ -3,8 +3,9 @@
 
 use log::trace;
 use std::cell::RefCell;
-use std::fmt;
+use std::fmt::{self, Write};
 use std::num::NonZeroU64;
+use std::rc::Rc;
 
 use rustc_data_structures::fx::{FxHashMap, FxHashSet};
 use rustc_hir::Mutability;
@@ -12,6 +13,7 @@ use rustc_middle::mir::RetagKind;
 use rustc_middle::ty::{
     self,
     layout::{HasParamEnv, LayoutOf},
+    TyCtxt,
 };
 use rustc_span::DUMMY_SP;
 use rustc_target::abi::Size;
@@ -19,6 +21,10 @@ use std::collections::HashSet;
 
 use crate::*;
 
+mod trace;
+
+use trace::{SbTrace, TracedOpKind};
+
 pub type PtrId = NonZeroU64;
 pub type CallId = NonZeroU64;
 pub type AllocExtra = Stacks;
@@ -82,7 +88,11 @@ pub struct Stack {
     /// Invariants:
     /// * Above a `SharedReadOnly` there can only be more `SharedReadOnly`.
     /// * Except for `Untagged`, no tag occurs in the stack more than once.
-    borrows: Vec<Item>,
+    ///
+    /// The items are reference-counted and copied on write, so that when the `RangeMap` splits
+    /// a range of locations, the resulting stacks share their items until one of them actually
+    /// changes. This keeps allocations with many identical stacks cheap.
+    borrows: Rc<Vec<Item>>,
 }
 
 /// Extra per-allocation state.
@@ -111,6 +121,8 @@ pub struct GlobalStateInner {
     tracked_call_ids: HashSet<CallId>,
     /// Whether to track raw pointers.
     tag_raw: bool,
+    /// The operations on the allocation traced with `-Zmiri-sb-trace`.
+    trace: Option<RefCell<SbTrace>>,
 }
 /// We need interior mutable access to the global state.
 pub type GlobalState = RefCell<GlobalStateInner>;
@@ -162,6 +174,7 @@ impl GlobalStateInner {
         tracked_pointer_tags: HashSet<PtrId>,
         tracked_call_ids: HashSet<CallId>,
         tag_raw: bool,
+        trace: Option<(AllocId, String)>,
     ) -> Self {
         GlobalStateInner {
             next_ptr_id: NonZeroU64::new(1).unwrap(),
@@ -171,9 +184,15 @@ impl GlobalStateInner {
             tracked_pointer_tags,
             tracked_call_ids,
             tag_raw,
+            trace: trace.map(|(alloc_id, path)| RefCell::new(SbTrace::new(alloc_id, path))),
         }
     }
 
+    /// Whether raw pointers get their own tags.
+    pub fn tag_raw(&self) -> bool {
+        self.tag_raw
+    }
+
     fn new_ptr(&mut self) -> PtrId {
         let id = self.next_ptr_id;
         if self.tracked_pointer_tags.contains(&id) {
@@ -211,6 +230,41 @@ impl GlobalStateInner {
         })
     }
 
+    /// Starts recording an operation on `alloc_id`, if it is traced.
+    #[inline(always)]
+    fn trace_begin(
+        &self,
+        alloc_id: AllocId,
+        range: AllocRange,
+        kind: impl FnOnce() -> TracedOpKind,
+    ) {
+        if let Some(trace) = &self.trace {
+            trace.borrow_mut().begin(alloc_id, range, kind);
+        }
+    }
+
+    #[inline(always)]
+    fn trace_popped(&self, item: &Item, disabled: bool) {
+        if let Some(trace) = &self.trace {
+            trace.borrow_mut().popped(item, disabled);
+        }
+    }
+
+    #[inline(always)]
+    fn trace_end<T>(&self, result: &InterpResult<'_, T>) {
+        if let Some(trace) = &self.trace {
+            trace.borrow_mut().end(result.is_err());
+        }
+    }
+
+    /// Writes the trace of `-Zmiri-sb-trace`, if it is enabled.
+    pub fn write_trace(&self, tcx: TyCtxt<'_>) -> std::io::Result<()> {
+        match &self.trace {
+            Some(trace) => trace.borrow().write(tcx),
+            None => Ok(()),
+        }
+    }
+
     pub fn base_tag_untagged(&mut self, id: AllocId) -> SbTag {
         trace!("New allocation {:?} has no base tag (untagged)", id);
         let tag = SbTag::Untagged;
@@ -221,13 +275,20 @@ impl GlobalStateInner {
 }
 
 /// Error reporting
-fn err_sb_ub(msg: String, help: Option<String>) -> InterpError<'static> {
+fn err_sb_ub(
+    msg: String,
+    help: Option<String>,
+    alloc_id: Option<AllocId>,
+    untagged_access: bool,
+) -> InterpError<'static> {
     err_machine_stop!(TerminationInfo::ExperimentalUb {
         msg,
         help,
         url: format!(
             "https://github.com/rust-lang/unsafe-code-guidelines/blob/master/wip/stacked-borrows.md"
         ),
+        alloc_id,
+        untagged_access,
     })
 }
 
@@ -328,11 +389,15 @@ impl<'tcx> Stack {
                             tag, item
                         ),
                         None,
+                        None,
+                        false,
                     ))?
                 } else {
                     Err(err_sb_ub(
                         format!("deallocating while item is protected: {:?}", item),
                         None,
+                        None,
+                        false,
                     ))?
                 }
             }
@@ -364,9 +429,13 @@ impl<'tcx> Stack {
             // Remove everything above the write-compatible items, like a proper stack. This makes sure read-only and unique
             // pointers become invalid on write accesses (ensures F2a, and ensures U2 for write accesses).
             let first_incompatible_idx = self.find_first_write_incompatible(granting_idx);
-            for item in self.borrows.drain(first_incompatible_idx..).rev() {
-                trace!("access: popping item {:?}", item);
-                Stack::check_protector(&item, Some((tag, access)), global)?;
+            if first_incompatible_idx < self.borrows.len() {
+                let borrows = Rc::make_mut(&mut self.borrows);
+                for item in borrows.drain(first_incompatible_idx..).rev() {
+                    trace!("access: popping item {:?}", item);
+                    global.trace_popped(&item, false);
+                    Stack::check_protector(&item, Some((tag, access)), global)?;
+                }
             }
         } else {
             // On a read, *disable* all `Unique` above the granting item.  This ensures U2 for read accesses.
@@ -377,12 +446,17 @@ impl<'tcx> Stack {
             // This pattern occurs a lot in the standard library: create a raw pointer, then also create a shared
             // reference and use that.
             // We *disable* instead of removing `Unique` to avoid "connecting" two neighbouring blocks of SRWs.
-            for idx in ((granting_idx + 1)..self.borrows.len()).rev() {
-                let item = &mut self.borrows[idx];
-                if item.perm == Permission::Unique {
-                    trace!("access: disabling item {:?}", item);
-                    Stack::check_protector(item, Some((tag, access)), global)?;
-                    item.perm = Permission::Disabled;
+            // Only un-share the items if there actually is something to disable.
+            if self.borrows[granting_idx + 1..].iter().any(|item| item.perm == Permission::Unique) {
+                let borrows = Rc::make_mut(&mut self.borrows);
+                for idx in ((granting_idx + 1)..borrows.len()).rev() {
+                    let item = &mut borrows[idx];
+                    if item.perm == Permission::Unique {
+                        trace!("access: disabling item {:?}", item);
+                        global.trace_popped(item, true);
+                        Stack::check_protector(item, Some((tag, access)), global)?;
+                        item.perm = Permission::Disabled;
+                    }
                 }
             }
         }
@@ -401,16 +475,23 @@ impl<'tcx> Stack {
     ) -> InterpResult<'tcx> {
         // Step 1: Find granting item.
         self.find_granting(AccessKind::Write, tag).ok_or_else(|| {
-            err_sb_ub(format!(
-                "no item granting write access for deallocation to tag {:?} at {:?} found in borrow stack",
-                tag, dbg_ptr,
-            ), None)
+            err_sb_ub(
+                format!(
+                    "no item granting write access for deallocation to tag {:?} at {:?} found in borrow stack",
+                    tag, dbg_ptr,
+                ),
+                None,
+                Some(dbg_ptr.provenance),
+                tag == SbTag::Untagged,
+            )
         })?;
 
         // Step 2: Remove all items.  Also checks for protectors.
-        for item in self.borrows.drain(..).rev() {
-            Stack::check_protector(&item, None, global)?;
+        for item in self.borrows.iter().rev() {
+            global.trace_popped(item, false);
+            Stack::check_protector(item, None, global)?;
         }
+        self.borrows = Rc::new(Vec::new());
 
         Ok(())
     }
@@ -469,7 +550,7 @@ impl<'tcx> Stack {
             trace!("reborrow: avoiding adding redundant item {:?}", new);
         } else {
             trace!("reborrow: adding item {:?}", new);
-            self.borrows.insert(new_idx, new);
+            Rc::make_mut(&mut self.borrows).insert(new_idx, new);
         }
 
         Ok(())
@@ -494,6 +575,8 @@ impl<'tcx> Stack {
         err_sb_ub(
             format!("{}{}", action, self.error_cause(derived_from)),
             Some(Self::operation_summary("a reborrow", alloc_id, alloc_range)),
+            Some(alloc_id),
+            derived_from == SbTag::Untagged,
         )
     }
 
@@ -516,6 +599,8 @@ impl<'tcx> Stack {
         err_sb_ub(
             format!("{}{}", action, self.error_cause(tag)),
             Some(Self::operation_summary("an access", alloc_id, alloc_range)),
+            Some(alloc_id),
+            tag == SbTag::Untagged,
         )
     }
 
@@ -540,6 +625,12 @@ impl<'tcx> Stack {
             ", but that tag does not exist in the borrow stack for this location"
         }
     }
+
+    /// Returns whether both stacks share the same items, i.e., neither of them
+    /// was changed since they were split from a common range.
+    fn shares_items_with(&self, other: &Stack) -> bool {
+        Rc::ptr_eq(&self.borrows, &other.borrows)
+    }
 }
 // # Stacked Borrows Core End
 
@@ -548,7 +639,7 @@ impl<'tcx> Stacks {
     /// Creates new stack with initial tag.
     fn new(size: Size, perm: Permission, tag: SbTag) -> Self {
         let item = Item { perm, tag, protector: None };
-        let stack = Stack { borrows: vec![item] };
+        let stack = Stack { borrows: Rc::new(vec![item]) };
 
         Stacks { stacks: RefCell::new(RangeMap::new(size, stack)) }
     }
@@ -560,10 +651,7 @@ impl<'tcx> Stacks {
         f: impl Fn(Size, &mut Stack) -> InterpResult<'tcx>,
     ) -> InterpResult<'tcx> {
         let mut stacks = self.stacks.borrow_mut();
-        for (offset, stack) in stacks.iter_mut(range.start, range.size) {
-            f(offset, stack)?;
-        }
-        Ok(())
+        Self::for_each_in(&mut stacks, range, f)
     }
 
     /// Call `f` on every stack in the range.
@@ -573,15 +661,81 @@ impl<'tcx> Stacks {
         f: impl Fn(Size, &mut Stack) -> InterpResult<'tcx>,
     ) -> InterpResult<'tcx> {
         let stacks = self.stacks.get_mut();
+        Self::for_each_in(stacks, range, f)
+    }
+
+    fn for_each_in(
+        stacks: &mut RangeMap<Stack>,
+        range: AllocRange,
+        f: impl Fn(Size, &mut Stack) -> InterpResult<'tcx>,
+    ) -> InterpResult<'tcx> {
+        // Neighbouring ranges often still share their items (e.g. after a range was split
+        // and only part of it was accessed since). For such a run of shared stacks we only
+        // apply `f` to the first one and share the result with the others. This relies on `f`
+        // behaving the same on equal stacks: the offset only goes into error messages, and the
+        // rest of the global state does not change while we iterate. So if `f` fails on a run, it
+        // fails on its first stack, and the error reports the right offset. The only difference is
+        // that a tracked tag that gets popped is reported once per run instead of per location.
+        // We only remember the address of the items before `f`: holding on to a copy of the stack
+        // would make `f` copy the items whenever it changes them. If `f` does copy them, the old
+        // items stay alive in the stacks that still share them, so their address cannot be
+        // reused by any stack we have yet to visit.
+        let mut last: Option<(*const Vec<Item>, Stack)> = None; // (items before `f`, after `f`)
         for (offset, stack) in stacks.iter_mut(range.start, range.size) {
+            if let Some((before, after)) = &last {
+                if Rc::as_ptr(&stack.borrows) == *before {
+                    *stack = after.clone();
+                    continue;
+                }
+            }
+            let before = Rc::as_ptr(&stack.borrows);
             f(offset, stack)?;
+            last = Some((before, stack.clone()));
         }
+        // Runs of shared stacks are identical now, so store each of them as a single range.
+        stacks.merge_adjacent(range.start, range.size, Stack::shares_items_with);
         Ok(())
     }
 }
 
 /// Glue code to connect with Miri Machine Hooks
 impl Stacks {
+    /// Whether `tag` currently has unique access to all of `range`: on every location, the top
+    /// item of the stack must belong to `tag` and grant write access, so that no other pointer
+    /// derived from it is still usable.
+    pub fn has_unique_access(&self, tag: SbTag, range: AllocRange) -> bool {
+        self.stacks.borrow().iter(range.start, range.size).all(|(_, stack)| {
+            let top = stack.borrows.last().unwrap();
+            top.tag == tag && matches!(top.perm, Permission::Unique | Permission::SharedReadWrite)
+        })
+    }
+
+    /// Print the stacks of `range` to stderr, one line per run of locations with the same stack.
+    pub fn print_stacks(&self, alloc_id: AllocId, range: AllocRange) {
+        eprint!("{}", self.describe_stacks(alloc_id, range));
+    }
+
+    /// The stacks of `range` as printed by `print_stacks`.
+    pub fn describe_stacks(&self, alloc_id: AllocId, range: AllocRange) -> String {
+        let stacks = self.stacks.borrow();
+        let elems: Vec<(Size, &Stack)> = stacks.iter(range.start, range.size).collect();
+        let mut out = format!(
+            "borrow stacks of {:?}[{:#x}..{:#x}], from bottom to top:\n",
+            alloc_id,
+            range.start.bytes(),
+            range.end().bytes()
+        );
+        for (i, &(start, stack)) in elems.iter().enumerate() {
+            let start = start.max(range.start);
+            let end = elems.get(i + 1).map_or(range.end(), |&(next, _)| next);
+            let items: Vec<String> =
+                stack.borrows.iter().map(|item| format!("{:?}", item)).collect();
+            writeln!(out, "  {:#x}..{:#x}: {}", start.bytes(), end.bytes(), items.join(" "))
+                .unwrap();
+        }
+        out
+    }
+
     pub fn new_allocation(
         id: AllocId,
         size: Size,
@@ -613,13 +767,20 @@ impl Stacks {
             ) => (extra.base_tag(id), Permission::SharedReadWrite),
             // Heap allocations we only track precisely when raw pointers are tagged, for now.
             MemoryKind::Machine(
-                MiriMemoryKind::Rust | MiriMemoryKind::C | MiriMemoryKind::WinHeap,
+                MiriMemoryKind::Rust
+                | MiriMemoryKind::C
+                | MiriMemoryKind::WinHeap
+                | MiriMemoryKind::Mmap,
             ) => {
                 let tag =
                     if extra.tag_raw { extra.base_tag(id) } else { extra.base_tag_untagged(id) };
                 (tag, Permission::SharedReadWrite)
             }
         };
+        extra.trace_begin(id, alloc_range(Size::ZERO, size), || {
+            TracedOpKind::Allocate { tag: base_tag, perm }
+        });
+        extra.trace_end(&Ok(()));
         Stacks::new(size, perm, base_tag)
     }
 
@@ -638,9 +799,13 @@ impl Stacks {
             range.size.bytes()
         );
         let global = &*state.borrow();
-        self.for_each(range, move |offset, stack| {
+        global
+            .trace_begin(alloc_id, range, || TracedOpKind::Access { tag, kind: AccessKind::Read });
+        let result = self.for_each(range, move |offset, stack| {
             stack.access(AccessKind::Read, tag, (alloc_id, range, offset), global)
-        })
+        });
+        global.trace_end(&result);
+        result
     }
 
     #[inline(always)]
@@ -657,10 +822,14 @@ impl Stacks {
             Pointer::new(alloc_id, range.start),
             range.size.bytes()
         );
-        let global = state.get_mut();
-        self.for_each_mut(range, move |offset, stack| {
+        let global = &*state.get_mut();
+        global
+            .trace_begin(alloc_id, range, || TracedOpKind::Access { tag, kind: AccessKind::Write });
+        let result = self.for_each_mut(range, move |offset, stack| {
             stack.access(AccessKind::Write, tag, (alloc_id, range, offset), global)
-        })
+        });
+        global.trace_end(&result);
+        result
     }
 
     #[inline(always)]
@@ -672,10 +841,13 @@ impl Stacks {
         state: &mut GlobalState,
     ) -> InterpResult<'tcx> {
         trace!("deallocation with tag {:?}: {:?}, size {}", tag, alloc_id, range.size.bytes());
-        let global = state.get_mut();
-        self.for_each_mut(range, move |offset, stack| {
+        let global = &*state.get_mut();
+        global.trace_begin(alloc_id, range, || TracedOpKind::Dealloc { tag });
+        let result = self.for_each_mut(range, move |offset, stack| {
             stack.dealloc(tag, Pointer::new(alloc_id, offset), global)
-        })
+        });
+        global.trace_end(&result);
+        result
     }
 }
 
@@ -764,9 +936,15 @@ trait EvalContextPrivExt<'mir, 'tcx: 'mir>: crate::MiriEvalContextExt<'mir, 'tcx
                         Permission::SharedReadWrite
                     };
                     let item = Item { perm, tag: new_tag, protector };
-                    stacked_borrows.for_each(range, |offset, stack| {
+                    global.trace_begin(alloc_id, range, || {
+                        let protected = protector.is_some();
+                        TracedOpKind::Retag { from: orig_tag, tag: new_tag, perm, protected }
+                    });
+                    let result = stacked_borrows.for_each(range, |offset, stack| {
                         stack.grant(orig_tag, item, (alloc_id, range, offset), &*global)
-                    })
+                    });
+                    global.trace_end(&result);
+                    result
                 })?;
                 return Ok(());
             }
@@ -777,13 +955,18 @@ trait EvalContextPrivExt<'mir, 'tcx: 'mir>: crate::MiriEvalContextExt<'mir, 'tcx
         let (alloc_extra, memory_extra) = this.get_alloc_extra_mut(alloc_id)?;
         let stacked_borrows =
             alloc_extra.stacked_borrows.as_mut().expect("we should have Stacked Borrows data");
-        let global = memory_extra.stacked_borrows.as_mut().unwrap().get_mut();
+        let global = &*memory_extra.stacked_borrows.as_mut().unwrap().get_mut();
         let item = Item { perm, tag: new_tag, protector };
         let range = alloc_range(base_offset, size);
-        stacked_borrows.for_each_mut(alloc_range(base_offset, size), |offset, stack| {
+        global.trace_begin(alloc_id, range, || {
+            let protected = protector.is_some();
+            TracedOpKind::Retag { from: orig_tag, tag: new_tag, perm, protected }
+        });
+        let result = stacked_borrows.for_each_mut(range, |offset, stack| {
             stack.grant(orig_tag, item, (alloc_id, range, offset), global)
-        })?;
-        Ok(())
+        });
+        global.trace_end(&result);
+        result
     }
 
     /// Retags an indidual pointer, returning the retagged version.
@@ -868,6 +1051,24 @@ pub trait EvalContextExt<'mir, 'tcx: 'mir>: crate::MiriEvalContextExt<'mir, 'tcx
         Ok(())
     }
 
+    /// Remembers the statement that is about to be executed, to attribute the operations traced
+    /// with `-Zmiri-sb-trace` to it.
+    fn start_sb_trace_step(&self) {
+        let this = self.eval_context_ref();
+        let traced = this
+            .machine
+            .stacked_borrows
+            .as_ref()
+            .map_or(false, |global| global.borrow().trace.is_some());
+        if !traced {
+            // Looking up the span of the current statement is not free, so don't do it for nothing.
+            return;
+        }
+        let span = this.active_thread_stack().last().map(|frame| frame.current_span());
+        let global = this.machine.stacked_borrows.as_ref().unwrap().borrow();
+        global.trace.as_ref().unwrap().borrow_mut().current_span = span;
+    }
+
     /// After a stack frame got pushed, retag the return place so that we are sure
     /// it does not alias with anything.
     ///
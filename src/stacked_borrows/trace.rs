@@ -0,0 +1,294 @@
+//! Tracing the Stacked Borrows operations on one allocation, enabled with
+//! `-Zmiri-sb-trace=<alloc-id>:<file>`. We record every retag of a pointer into the allocation,
+//! every access that pops or disables items of its borrow stacks, and the operation that failed,
+//! if any. At the end of the execution the trace is written to `file`: as a JSON list of the
+//! operations if the name ends in `.json`, and otherwise as a graph in the DOT format of Graphviz.
+//! The graph has a node per tag and an edge from each tag to the tags derived from it, and the
+//! nodes of tags that were popped name the operation that popped them, which shows which reborrow
+//! or access invalidated a pointer.
+
+use std::fmt::Write;
+use std::io;
+
+use rustc_middle::ty::TyCtxt;
+use rustc_span::Span;
+
+use super::{AccessKind, Item, Permission, SbTag};
+use crate::*;
+
+/// What a traced operation did.
+#[derive(Debug)]
+pub enum TracedOpKind {
+    /// The allocation was created, with `tag` as the tag of its base pointer.
+    Allocate {
+        tag: SbTag,
+        perm: Permission,
+    },
+    /// A pointer with tag `tag` was derived from one with tag `from`.
+    Retag {
+        from: SbTag,
+        tag: SbTag,
+        perm: Permission,
+        protected: bool,
+    },
+    Access {
+        tag: SbTag,
+        kind: AccessKind,
+    },
+    Dealloc {
+        tag: SbTag,
+    },
+}
+
+impl TracedOpKind {
+    /// The tag of the pointer that performed the operation.
+    fn tag(&self) -> SbTag {
+        match *self {
+            TracedOpKind::Allocate { tag, .. }
+            | TracedOpKind::Retag { tag, .. }
+            | TracedOpKind::Access { tag, .. }
+            | TracedOpKind::Dealloc { tag } => tag,
+        }
+    }
+
+    fn describe(&self) -> String {
+        match self {
+            TracedOpKind::Allocate { tag, perm } => format!("allocation of {:?} ({:?})", tag, perm),
+            TracedOpKind::Retag { from, tag, perm, protected } =>
+                format!(
+                    "retag of {:?} to {:?} ({:?}{})",
+                    from,
+                    tag,
+                    perm,
+                    if *protected { ", protected" } else { "" }
+                ),
+            TracedOpKind::Access { tag, kind } => format!("{} with {:?}", kind, tag),
+            TracedOpKind::Dealloc { tag } => format!("deallocation with {:?}", tag),
+        }
+    }
+}
+
+#[derive(Debug)]
+struct TracedOp {
+    kind: TracedOpKind,
+    range: AllocRange,
+    /// The statement that performed the operation.
+    span: Option<Span>,
+    /// The items the operation removed from the borrow stacks, and whether each of them was only
+    /// disabled.
+    popped: Vec<(Item, bool)>,
+    /// Whether the operation was Undefined Behavior.
+    failed: bool,
+}
+
+#[derive(Debug)]
+pub struct SbTrace {
+    alloc_id: AllocId,
+    path: String,
+    /// The statement that is about to be executed, set before every step.
+    pub current_span: Option<Span>,
+    ops: Vec<TracedOp>,
+    /// The operation in progress, if it is on the traced allocation.
+    pending: Option<TracedOp>,
+}
+
+impl SbTrace {
+    pub fn new(alloc_id: AllocId, path: String) -> Self {
+        SbTrace { alloc_id, path, current_span: None, ops: Vec::new(), pending: None }
+    }
+
+    /// Starts recording an operation on `range` of `alloc_id`, if that is the traced allocation.
+    pub fn begin(
+        &mut self,
+        alloc_id: AllocId,
+        range: AllocRange,
+        kind: impl FnOnce() -> TracedOpKind,
+    ) {
+        if alloc_id == self.alloc_id {
+            let span = self.current_span;
+            let op = TracedOp { kind: kind(), range, span, popped: vec![], failed: false };
+            self.pending = Some(op);
+        }
+    }
+
+    /// Records that the operation in progress popped `item`, or only disabled it.
+    pub fn popped(&mut self, item: &Item, disabled: bool) {
+        if let Some(op) = &mut self.pending {
+            // The same item is usually popped from the stacks of many locations.
+            if !op.popped.contains(&(*item, disabled)) {
+                op.popped.push((*item, disabled));
+            }
+        }
+    }
+
+    /// Finishes the operation in progress.
+    pub fn end(&mut self, failed: bool) {
+        if let Some(mut op) = self.pending.take() {
+            op.failed = failed;
+            // Accesses are only interesting if they changed the stacks.
+            if failed || !op.popped.is_empty() || !matches!(op.kind, TracedOpKind::Access { .. }) {
+                self.ops.push(op);
+            }
+        }
+    }
+
+    /// Writes the trace to its file.
+    pub fn write(&self, tcx: TyCtxt<'_>) -> io::Result<()> {
+        let out = if self.path.ends_with(".json") { self.to_json(tcx) } else { self.to_dot(tcx) };
+        std::fs::write(&self.path, out)
+    }
+
+    fn to_json(&self, tcx: TyCtxt<'_>) -> String {
+        let ops: Vec<String> = self
+            .ops
+            .iter()
+            .enumerate()
+            .map(|(i, op)| {
+                let mut out = format!(r#"{{"id":{},"#, i + 1);
+                match op.kind {
+                    TracedOpKind::Allocate { tag, perm } =>
+                        write!(out, r#""kind":"allocate","tag":"{:?}","perm":"{:?}""#, tag, perm),
+                    TracedOpKind::Retag { from, tag, perm, protected } =>
+                        write!(
+                            out,
+                            concat!(
+                                r#""kind":"retag","from":"{:?}","tag":"{:?}","perm":"{:?}","#,
+                                r#""protected":{}"#
+                            ),
+                            from, tag, perm, protected
+                        ),
+                    TracedOpKind::Access { tag, kind } => {
+                        let kind = match kind {
+                            AccessKind::Read => "read",
+                            AccessKind::Write => "write",
+                        };
+                        write!(out, r#""kind":"{}","tag":"{:?}""#, kind, tag)
+                    }
+                    TracedOpKind::Dealloc { tag } =>
+                        write!(out, r#""kind":"deallocate","tag":"{:?}""#, tag),
+                }
+                .unwrap();
+                let popped: Vec<String> = op
+                    .popped
+                    .iter()
+                    .map(|(item, disabled)| {
+                        format!(
+                            r#"{{"tag":"{:?}","perm":"{:?}","disabled":{}}}"#,
+                            item.tag, item.perm, disabled
+                        )
+                    })
+                    .collect();
+                let loc = op
+                    .span
+                    .and_then(|span| location(tcx, span))
+                    .map_or("null".to_string(), |loc| crate::sarif::json_str(&loc));
+                write!(
+                    out,
+                    r#","range":[{},{}],"location":{},"popped":[{}],"failed":{}}}"#,
+                    op.range.start.bytes(),
+                    op.range.end().bytes(),
+                    loc,
+                    popped.join(","),
+                    op.failed
+                )
+                .unwrap();
+                out
+            })
+            .collect();
+        format!("{{\"alloc\":\"{:?}\",\"operations\":[\n{}\n]}}\n", self.alloc_id, ops.join(",\n"))
+    }
+
+    fn to_dot(&self, tcx: TyCtxt<'_>) -> String {
+        // The nodes of the tags, in the order in which they appear, with the lines of their label
+        // and whether they were popped.
+        let mut nodes: Vec<(SbTag, Vec<String>, bool)> = Vec::new();
+        fn node(nodes: &mut Vec<(SbTag, Vec<String>, bool)>, tag: SbTag) -> usize {
+            nodes.iter().position(|(t, ..)| *t == tag).unwrap_or_else(|| {
+                nodes.push((tag, vec![format!("{:?}", tag)], false));
+                nodes.len() - 1
+            })
+        }
+        let mut edges = String::new();
+        for (i, op) in self.ops.iter().enumerate() {
+            let id = i + 1;
+            let at = op
+                .span
+                .and_then(|span| location(tcx, span))
+                .map_or(String::new(), |loc| format!(" at {}", loc));
+            let range = format!("[{:#x}..{:#x}]", op.range.start.bytes(), op.range.end().bytes());
+            match op.kind {
+                TracedOpKind::Allocate { tag, perm } => {
+                    let n = node(&mut nodes, tag);
+                    nodes[n].1.push(format!("#{}: allocated ({:?}) {}{}", id, perm, range, at));
+                }
+                TracedOpKind::Retag { from, tag, perm, protected } => {
+                    node(&mut nodes, from);
+                    let n = node(&mut nodes, tag);
+                    nodes[n].1.push(format!("#{}: created{}", id, at));
+                    let protected = if protected { ", protected" } else { "" };
+                    let label = format!("#{}: {:?}{} {}", id, perm, protected, range);
+                    writeln!(
+                        edges,
+                        "  {} -> {} [label={}];",
+                        dot_str(&format!("{:?}", from)),
+                        dot_str(&format!("{:?}", tag)),
+                        dot_str(&label)
+                    )
+                    .unwrap();
+                }
+                TracedOpKind::Access { .. } | TracedOpKind::Dealloc { .. } => {}
+            }
+            for (item, disabled) in &op.popped {
+                let n = node(&mut nodes, item.tag);
+                let what = if *disabled { "disabled" } else { "popped" };
+                nodes[n].1.push(format!("{} by #{}: {}{}", what, id, op.kind.describe(), at));
+                nodes[n].2 = true;
+            }
+            if op.failed {
+                let n = node(&mut nodes, op.kind.tag());
+                nodes[n].1.push(format!("#{} failed: {} {}{}", id, op.kind.describe(), range, at));
+                nodes[n].2 = true;
+            }
+        }
+        let mut out = format!(
+            "digraph {} {{\n  node [shape=box];\n",
+            dot_str(&format!("Stacked Borrows trace of {:?}", self.alloc_id))
+        );
+        for (tag, lines, popped) in &nodes {
+            writeln!(
+                out,
+                "  {} [label={}{}];",
+                dot_str(&format!("{:?}", tag)),
+                dot_str(&(lines.join("\n") + "\n")),
+                if *popped { ", color=red" } else { "" }
+            )
+            .unwrap();
+        }
+        out.push_str(&edges);
+        out.push_str("}\n");
+        out
+    }
+}
+
+/// The location of `span` as `file:line:column`.
+fn location(tcx: TyCtxt<'_>, span: Span) -> Option<String> {
+    let loc = Location::from_span(tcx, span)?;
+    Some(format!("{}:{}:{}", loc.file, loc.line, loc.column + 1))
+}
+
+/// `s` as a quoted string in the DOT format. Every line, including the last one, must end in a
+/// newline, which left-aligns it.
+fn dot_str(s: &str) -> String {
+    let mut out = String::with_capacity(s.len() + 2);
+    out.push('"');
+    for c in s.chars() {
+        match c {
+            '"' => out.push_str("\\\""),
+            '\\' => out.push_str("\\\\"),
+            '\n' => out.push_str("\\l"),
+            c => out.push(c),
+        }
+    }
+    out.push('"');
+    out
+}
@@ -0,0 +1,150 @@
+//! Statistics about an execution, printed at exit with `-Zmiri-stats`. They are meant to help
+//! with deciding which checks are worth their cost for a large test suite: besides the number of
+//! steps and allocations, we measure how much time the memory hooks of each detector take.
+//!
+//! Everything here is only collected when `-Zmiri-stats` is set, so that the hooks do not have to
+//! query the clock otherwise.
+
+use std::cell::Cell;
+use std::fmt::Write;
+use std::time::{Duration, Instant};
+
+use rustc_target::abi::Size;
+
+/// The detectors whose cost is measured.
+#[derive(Debug, Clone, Copy)]
+pub enum Detector {
+    StackedBorrows,
+    DataRace,
+}
+
+impl Detector {
+    const ALL: [Detector; 2] = [Detector::StackedBorrows, Detector::DataRace];
+
+    fn name(self) -> &'static str {
+        match self {
+            Detector::StackedBorrows => "Stacked Borrows",
+            Detector::DataRace => "the data race detector",
+        }
+    }
+}
+
+#[derive(Debug, Default)]
+struct DetectorCost {
+    /// How many times the hooks of the detector were called.
+    calls: Cell<u64>,
+    /// The time spent in those calls.
+    time: Cell<Duration>,
+}
+
+#[derive(Debug, Default)]
+pub struct Stats {
+    allocations: Cell<u64>,
+    deallocations: Cell<u64>,
+    /// The size of all allocations that are currently live.
+    live_bytes: Cell<u64>,
+    /// The largest value `live_bytes` had.
+    peak_live_bytes: Cell<u64>,
+    detectors: [DetectorCost; 2],
+}
+
+/// Adds the time until it is dropped to the cost of a detector.
+pub struct DetectorTimer<'a> {
+    cost: &'a DetectorCost,
+    start: Instant,
+}
+
+impl Drop for DetectorTimer<'_> {
+    fn drop(&mut self) {
+        self.cost.add(self.start.elapsed());
+    }
+}
+
+impl DetectorCost {
+    fn add(&self, time: Duration) {
+        self.calls.set(self.calls.get() + 1);
+        self.time.set(self.time.get() + time);
+    }
+}
+
+impl Stats {
+    pub fn allocated(&self, size: Size) {
+        self.allocations.set(self.allocations.get() + 1);
+        let live_bytes = self.live_bytes.get() + size.bytes();
+        self.live_bytes.set(live_bytes);
+        self.peak_live_bytes.set(self.peak_live_bytes.get().max(live_bytes));
+    }
+
+    pub fn deallocated(&self, size: Size) {
+        self.deallocations.set(self.deallocations.get() + 1);
+        self.live_bytes.set(self.live_bytes.get() - size.bytes());
+    }
+
+    /// Starts measuring a call to `detector`, until the returned timer is dropped.
+    pub fn time(&self, detector: Detector) -> DetectorTimer<'_> {
+        DetectorTimer { cost: &self.detectors[detector as usize], start: Instant::now() }
+    }
+
+    /// Adds a call to `detector` that took `time`.
+    pub fn add_time(&self, detector: Detector, time: Duration) {
+        self.detectors[detector as usize].add(time);
+    }
+
+    pub fn allocations(&self) -> u64 {
+        self.allocations.get()
+    }
+
+    pub fn deallocations(&self) -> u64 {
+        self.deallocations.get()
+    }
+
+    pub fn peak_live_bytes(&self) -> u64 {
+        self.peak_live_bytes.get()
+    }
+
+    /// The report printed at exit.
+    pub fn report(&self, steps: u64) -> String {
+        let mut report = String::new();
+        writeln!(report, "statistics of the execution:").unwrap();
+        writeln!(report, "  interpreted steps: {}", steps).unwrap();
+        writeln!(
+            report,
+            "  allocations: {} ({} deallocated)",
+            self.allocations.get(),
+            self.deallocations.get()
+        )
+        .unwrap();
+        writeln!(report, "  peak memory of the program: {} bytes", self.peak_live_bytes.get())
+            .unwrap();
+        // The rest of the interpreter's memory is mostly the metadata of the detectors.
+        if let Some(peak_rss) = host_peak_rss() {
+            writeln!(
+                report,
+                "  peak memory of the interpreter: {} bytes ({} bytes besides the program's memory)",
+                peak_rss,
+                peak_rss.saturating_sub(self.peak_live_bytes.get())
+            )
+            .unwrap();
+        }
+        for detector in Detector::ALL {
+            let cost = &self.detectors[detector as usize];
+            writeln!(
+                report,
+                "  time in {}: {:.3}s ({} calls)",
+                detector.name(),
+                cost.time.get().as_secs_f64(),
+                cost.calls.get()
+            )
+            .unwrap();
+        }
+        report
+    }
+}
+
+/// The peak resident set size of the Miri process, if the host tells us.
+fn host_peak_rss() -> Option<u64> {
+    let status = std::fs::read_to_string("/proc/self/status").ok()?;
+    let line = status.lines().find(|line| line.starts_with("VmHWM:"))?;
+    let kb: u64 = line.strip_prefix("VmHWM:")?.trim().strip_suffix("kB")?.trim().parse().ok()?;
+    Some(kb * 1024)
+}
@@ -0,0 +1,120 @@
+//! A simple profiler that counts how many interpreter steps are spent in each interpreted
+//! function. This is meant to answer the question "where is the time going" without having to
+//! take Miri's own overhead into account: every step costs roughly the same, so a function that
+//! takes many steps is a function that executes a lot of code.
+//!
+//! For each function we track the *exclusive* count (steps executed while that function was the
+//! topmost frame) and the *inclusive* count (steps executed while that function was anywhere on
+//! the stack of the thread that called it).
+
+use std::fmt::Write;
+
+use rustc_data_structures::fx::FxHashMap;
+use rustc_middle::ty::Instance;
+
+use crate::*;
+
+/// How many functions to list in the report.
+const REPORT_TOP_N: usize = 50;
+
+#[derive(Default, Debug, Clone, Copy)]
+struct FunctionSteps {
+    exclusive: u64,
+    inclusive: u64,
+}
+
+#[derive(Debug)]
+pub struct StepProfiler<'tcx> {
+    /// The file the report is written to.
+    out: String,
+    /// The number of steps executed so far by each thread.
+    thread_steps: FxHashMap<ThreadId, u64>,
+    functions: FxHashMap<Instance<'tcx>, FunctionSteps>,
+}
+
+impl<'tcx> StepProfiler<'tcx> {
+    pub fn new(out: String) -> Self {
+        StepProfiler { out, thread_steps: FxHashMap::default(), functions: FxHashMap::default() }
+    }
+
+    /// The number of steps executed so far by the given thread. Frames record this when they are
+    /// pushed, so that the inclusive count can be computed when they are popped.
+    pub fn current_steps(&self, thread: ThreadId) -> u64 {
+        self.thread_steps.get(&thread).copied().unwrap_or(0)
+    }
+
+    /// Records a step executed by `thread` with `instance` as the topmost frame.
+    pub fn step(&mut self, thread: ThreadId, instance: Instance<'tcx>) {
+        *self.thread_steps.entry(thread).or_insert(0) += 1;
+        self.functions.entry(instance).or_default().exclusive += 1;
+    }
+
+    /// Records that a frame of `instance` that was pushed when `thread` had executed
+    /// `steps_at_push` steps has been popped.
+    /// `recursive` must be set if another frame of the same instance is still on the stack,
+    /// in which case the steps will be accounted for when that outer frame is popped.
+    pub fn frame_popped(
+        &mut self,
+        thread: ThreadId,
+        instance: Instance<'tcx>,
+        steps_at_push: u64,
+        recursive: bool,
+    ) {
+        if !recursive {
+            let steps = self.current_steps(thread) - steps_at_push;
+            self.functions.entry(instance).or_default().inclusive += steps;
+        }
+    }
+
+    /// Writes the functions with the highest exclusive step counts to the output file. Frames
+    /// that are still on the stack of some thread, because the program stopped with an error,
+    /// called `exit`, or left threads running, count as if they were popped now.
+    pub fn write_report(&self, threads: &ThreadManager<'_, 'tcx>) -> std::io::Result<()> {
+        let mut functions = self.functions.clone();
+        for (thread, stack) in threads.thread_stacks() {
+            for (i, frame) in stack.iter().enumerate() {
+                let steps_at_push = match frame.extra.profile_steps {
+                    Some(steps_at_push) => steps_at_push,
+                    None => continue,
+                };
+                // Like in `frame_popped`, only the outermost frame of an instance counts.
+                if stack[..i].iter().any(|outer| outer.instance == frame.instance) {
+                    continue;
+                }
+                let steps = self.current_steps(thread) - steps_at_push;
+                functions.entry(frame.instance).or_default().inclusive += steps;
+            }
+        }
+
+        let total: u64 = self.thread_steps.values().sum();
+        let mut functions: Vec<_> = functions.iter().collect();
+        functions.sort_by(|(_, a), (_, b)| b.exclusive.cmp(&a.exclusive));
+
+        let mut report = String::new();
+        writeln!(report, "total steps: {}", total).unwrap();
+        writeln!(report, "{:>12} {:>12}  function", "exclusive", "inclusive").unwrap();
+        for (instance, steps) in functions.into_iter().take(REPORT_TOP_N) {
+            writeln!(report, "{:>12} {:>12}  {}", steps.exclusive, steps.inclusive, instance)
+                .unwrap();
+        }
+        std::fs::write(&self.out, report)
+    }
+}
+
+impl<'mir, 'tcx: 'mir> EvalContextExt<'mir, 'tcx> for crate::MiriEvalContext<'mir, 'tcx> {}
+pub trait EvalContextExt<'mir, 'tcx: 'mir>: crate::MiriEvalContextExt<'mir, 'tcx> {
+    /// Attributes the step that is about to be executed to the topmost frame of the active
+    /// thread. Does nothing if step profiling is disabled.
+    fn profile_step(&mut self) {
+        let this = self.eval_context_mut();
+        if this.machine.step_profiler.is_none() {
+            return;
+        }
+        let thread = this.get_active_thread();
+        let instance = match this.active_thread_stack().last() {
+            Some(frame) => frame.instance,
+            None => return,
+        };
+        this.machine.step_profiler.as_mut().unwrap().step(thread, instance);
+    }
+}
@@ -154,6 +154,9 @@ pub(super) struct SynchronizationState {
     rwlocks: IndexVec<RwLockId, RwLock>,
     condvars: IndexVec<CondvarId, Condvar>,
     futexes: HashMap<u64, Futex>,
+    /// The clocks released with `miri_release`, by address. Contains the join of the clocks of
+    /// all threads that released the address.
+    annotations: HashMap<u64, VClock>,
 }
 
 // Private extension trait for local helper methods
@@ -232,6 +235,7 @@ pub trait EvalContextExt<'mir, 'tcx: 'mir>: crate::MiriEvalContextExt<'mir, 'tcx
     /// Lock by setting the mutex owner and increasing the lock count.
     fn mutex_lock(&mut self, id: MutexId, thread: ThreadId) {
         let this = self.eval_context_mut();
+        this.note_sync_point();
         let mutex = &mut this.machine.threads.sync.mutexes[id];
         if let Some(current_owner) = mutex.owner {
             assert_eq!(thread, current_owner, "mutex already locked by another thread");
@@ -254,6 +258,7 @@ pub trait EvalContextExt<'mir, 'tcx: 'mir>: crate::MiriEvalContextExt<'mir, 'tcx
     /// return `None`.
     fn mutex_unlock(&mut self, id: MutexId, expected_owner: ThreadId) -> Option<usize> {
         let this = self.eval_context_mut();
+        this.note_sync_point();
         let mutex = &mut this.machine.threads.sync.mutexes[id];
         if let Some(current_owner) = mutex.owner {
             // Mutex is locked.
@@ -522,4 +527,27 @@ pub trait EvalContextExt<'mir, 'tcx: 'mir>: crate::MiriEvalContextExt<'mir, 'tcx
             futex.waiters.retain(|waiter| waiter.thread != thread);
         }
     }
+
+    /// Implements `miri_release`: everything the current thread did so far happens-before any
+    /// later `miri_acquire` of `addr`.
+    fn annotate_release(&mut self, addr: u64) {
+        let this = self.eval_context_mut();
+        let current_thread = this.get_active_thread();
+        if let Some(data_race) = &this.machine.data_race {
+            let clock = this.machine.threads.sync.annotations.entry(addr).or_default();
+            data_race.validate_lock_release_shared(clock, current_thread);
+        }
+    }
+
+    /// Implements `miri_acquire`: every earlier `miri_release` of `addr` happens-before
+    /// everything the current thread does from now on.
+    fn annotate_acquire(&mut self, addr: u64) {
+        let this = self.eval_context_mut();
+        let current_thread = this.get_active_thread();
+        if let Some(data_race) = &this.machine.data_race {
+            if let Some(clock) = this.machine.threads.sync.annotations.get(&addr) {
+                data_race.validate_lock_acquire(clock, current_thread);
+            }
+        }
+    }
 }
@@ -1,7 +1,7 @@
 //! Implements threads.
 
-use std::cell::RefCell;
-use std::collections::hash_map::Entry;
+use std::borrow::Cow;
+use std::cell::{Cell, RefCell};
 use std::num::TryFromIntError;
 use std::time::{Duration, Instant, SystemTime};
 
@@ -11,6 +11,7 @@ use rustc_data_structures::fx::FxHashMap;
 use rustc_hir::def_id::DefId;
 use rustc_index::vec::{Idx, IndexVec};
 use rustc_middle::mir::Mutability;
+use rustc_span::{SpanData, Symbol};
 
 use crate::sync::SynchronizationState;
 use crate::*;
@@ -32,6 +33,12 @@ pub enum SchedulingAction {
 type TimeoutCallback<'mir, 'tcx> =
     Box<dyn FnOnce(&mut InterpCx<'mir, 'tcx, Evaluator<'mir, 'tcx>>) -> InterpResult<'tcx> + 'tcx>;
 
+/// The threads that read from an allocation since it was last written, as a bitmask of their
+/// ids modulo 64, to tell whether a write can be observed by another thread. Only maintained
+/// when spin loops are detected.
+#[derive(Debug, Clone, Default)]
+pub struct Readers(Cell<u64>);
+
 /// A thread identifier.
 #[derive(Clone, Copy, Debug, PartialOrd, Ord, PartialEq, Eq, Hash)]
 pub struct ThreadId(u32);
@@ -103,6 +110,17 @@ enum ThreadJoinStatus {
     Joined,
 }
 
+/// Why a thread is reported by the thread leak check.
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum LeakedThread {
+    /// The thread was still running when the main thread terminated.
+    Running,
+    /// The thread terminated, but was neither joined nor detached.
+    NotJoined,
+    /// The thread terminated after being detached; only reported if joining is required.
+    Detached,
+}
+
 /// A thread.
 pub struct Thread<'mir, 'tcx> {
     state: ThreadState,
@@ -123,6 +141,24 @@ pub struct Thread<'mir, 'tcx> {
 
     /// Last OS error location in memory. It is a 32-bit integer.
     pub(crate) last_error: Option<MPlaceTy<'tcx, Tag>>,
+
+    /// The shim that last set the OS error, if it was set during a shim call.
+    pub(crate) last_error_setter: Option<Symbol>,
+
+    /// If the OS error was made uninitialized by `-Zmiri-strict-errno`, the shim after whose
+    /// successful call that happened.
+    pub(crate) last_error_poisoned_after: Option<Symbol>,
+
+    /// Where this thread was spawned. Empty for the main thread.
+    spawn_stacktrace: Vec<FrameInfo<'tcx>>,
+
+    /// The CPU affinity mask set by the program, if any, either for this thread or for the thread
+    /// that spawned it. Miri does not actually pin threads to CPUs, this is only recorded so that
+    /// it can be reported back and shows up in debug output.
+    cpu_affinity: Option<Vec<u8>>,
+
+    /// The number of interpreter steps the thread executed. Its CPU time is derived from this.
+    cpu_steps: u64,
 }
 
 impl<'mir, 'tcx> Thread<'mir, 'tcx> {
@@ -152,7 +188,11 @@ impl<'mir, 'tcx> std::fmt::Debug for Thread<'mir, 'tcx> {
             String::from_utf8_lossy(self.thread_name()),
             self.state,
             self.join_status
-        )
+        )?;
+        if let Some(cpu_affinity) = &self.cpu_affinity {
+            write!(f, " with CPU affinity {:02x?}", cpu_affinity)?;
+        }
+        Ok(())
     }
 }
 
@@ -165,6 +205,11 @@ impl<'mir, 'tcx> Default for Thread<'mir, 'tcx> {
             join_status: ThreadJoinStatus::Joinable,
             panic_payload: None,
             last_error: None,
+            last_error_setter: None,
+            last_error_poisoned_after: None,
+            spawn_stacktrace: Vec::new(),
+            cpu_affinity: None,
+            cpu_steps: 0,
         }
     }
 }
@@ -178,11 +223,19 @@ pub enum Time {
 
 impl Time {
     /// How long do we have to wait from now until the specified time?
-    fn get_wait_time(&self) -> Duration {
+    fn get_wait_time(&self, clock: &Clock) -> Duration {
         match self {
-            Time::Monotonic(instant) => instant.saturating_duration_since(Instant::now()),
+            Time::Monotonic(instant) => instant.saturating_duration_since(clock.now()),
             Time::RealTime(time) =>
-                time.duration_since(SystemTime::now()).unwrap_or(Duration::new(0, 0)),
+                time.duration_since(clock.system_time()).unwrap_or(Duration::new(0, 0)),
+        }
+    }
+
+    /// How long ago did the specified time pass? Returns `None` if it is still in the future.
+    fn get_overdue_time(&self, clock: &Clock) -> Option<Duration> {
+        match self {
+            Time::Monotonic(instant) => clock.now().checked_duration_since(*instant),
+            Time::RealTime(time) => clock.system_time().duration_since(*time).ok(),
         }
     }
 }
@@ -221,12 +274,28 @@ pub struct ThreadManager<'mir, 'tcx> {
     thread_local_alloc_ids: RefCell<FxHashMap<(DefId, ThreadId), Pointer<Tag>>>,
     /// A flag that indicates that we should change the active thread.
     yield_active_thread: bool,
+    /// The number of times threads yielded since any thread last made observable progress
+    /// (see `note_progress`). Used to detect threads spinning on each other forever.
+    spins_without_progress: u64,
     /// Callbacks that are called once the specified time passes.
     timeout_callbacks: FxHashMap<ThreadId, TimeoutCallbackInfo<'mir, 'tcx>>,
+    /// Set when the active thread performs an atomic or synchronization operation, i.e., at the
+    /// points where a preemption can affect the outcome of the program.
+    sync_point: Cell<bool>,
+    /// Decides which thread runs next.
+    policy: Box<dyn SchedulingPolicy>,
+    /// Records the scheduling decisions, if requested.
+    recorder: Option<ScheduleRecorder>,
 }
 
 impl<'mir, 'tcx> Default for ThreadManager<'mir, 'tcx> {
     fn default() -> Self {
+        Self::new(new_scheduling_policy(SchedulingPolicyKind::Cooperative, 0), false)
+    }
+}
+
+impl<'mir, 'tcx> ThreadManager<'mir, 'tcx> {
+    pub fn new(policy: Box<dyn SchedulingPolicy>, record: bool) -> Self {
         let mut threads = IndexVec::new();
         // Create the main thread and add it to the list of threads.
         let mut main_thread = Thread::default();
@@ -239,7 +308,11 @@ impl<'mir, 'tcx> Default for ThreadManager<'mir, 'tcx> {
             sync: SynchronizationState::default(),
             thread_local_alloc_ids: Default::default(),
             yield_active_thread: false,
+            spins_without_progress: 0,
             timeout_callbacks: FxHashMap::default(),
+            sync_point: Cell::new(false),
+            policy,
+            recorder: if record { Some(ScheduleRecorder::default()) } else { None },
         }
     }
 }
@@ -273,9 +346,12 @@ impl<'mir, 'tcx: 'mir> ThreadManager<'mir, 'tcx> {
     }
 
     /// Create a new thread and returns its id.
-    fn create_thread(&mut self) -> ThreadId {
+    fn create_thread(&mut self, spawn_stacktrace: Vec<FrameInfo<'tcx>>) -> ThreadId {
         let new_thread_id = ThreadId::new(self.threads.len());
-        self.threads.push(Default::default());
+        // Like on Linux, a new thread inherits the CPU affinity mask of the thread spawning it.
+        let cpu_affinity = self.active_thread_ref().cpu_affinity.clone();
+        self.threads.push(Thread { spawn_stacktrace, cpu_affinity, ..Default::default() });
+        self.note_progress();
         new_thread_id
     }
 
@@ -381,11 +457,37 @@ impl<'mir, 'tcx: 'mir> ThreadManager<'mir, 'tcx> {
         self.active_thread_ref().thread_name()
     }
 
+    /// Set the CPU affinity mask of the active thread.
+    fn set_cpu_affinity(&mut self, mask: Vec<u8>) {
+        self.active_thread_mut().cpu_affinity = Some(mask);
+    }
+
+    /// Get the CPU affinity mask of the active thread, if it was set.
+    fn get_cpu_affinity(&self) -> Option<&[u8]> {
+        self.active_thread_ref().cpu_affinity.as_deref()
+    }
+
+    /// Record that the active thread executed an interpreter step.
+    fn count_cpu_step(&mut self) {
+        self.active_thread_mut().cpu_steps += 1;
+    }
+
+    /// The number of interpreter steps executed by the active thread.
+    fn thread_cpu_steps(&self) -> u64 {
+        self.active_thread_ref().cpu_steps
+    }
+
+    /// The number of interpreter steps executed by all threads, including terminated ones.
+    fn process_cpu_steps(&self) -> u64 {
+        self.threads.iter().map(|thread| thread.cpu_steps).sum()
+    }
+
     /// Put the thread into the blocked state.
     fn block_thread(&mut self, thread: ThreadId) {
         let state = &mut self.threads[thread].state;
         assert_eq!(*state, ThreadState::Enabled);
         *state = ThreadState::BlockedOnSync;
+        self.note_progress();
     }
 
     /// Put the blocked thread into the enabled state.
@@ -393,6 +495,7 @@ impl<'mir, 'tcx: 'mir> ThreadManager<'mir, 'tcx> {
         let state = &mut self.threads[thread].state;
         assert_eq!(*state, ThreadState::BlockedOnSync);
         *state = ThreadState::Enabled;
+        self.note_progress();
     }
 
     /// Change the active thread to some enabled thread.
@@ -401,6 +504,178 @@ impl<'mir, 'tcx: 'mir> ThreadManager<'mir, 'tcx> {
         // could lead to all sorts of confusion.
         // We should only switch stacks between steps.
         self.yield_active_thread = true;
+        // Waiting for a timeout to pass is progress, even if it takes many spins. Yielding when
+        // no other thread can run is not spinning on another thread, e.g. a backoff loop in a
+        // single-threaded program.
+        let others_enabled = self
+            .threads
+            .iter_enumerated()
+            .any(|(id, thread)| id != self.active_thread && thread.state == ThreadState::Enabled);
+        if self.timeout_callbacks.is_empty() && others_enabled {
+            self.spins_without_progress += 1;
+        }
+    }
+
+    /// The stacks of all threads. Threads that terminated have an empty stack.
+    pub fn thread_stacks(
+        &self,
+    ) -> impl Iterator<Item = (ThreadId, &[Frame<'mir, 'tcx, Tag, FrameData<'tcx>>])> {
+        self.threads.iter_enumerated().map(|(id, thread)| (id, &thread.stack[..]))
+    }
+
+    /// Get the threads that are still running, or that terminated without anyone joining or
+    /// detaching them, together with their names and where they were spawned. If
+    /// `require_join` is set, threads that were detached are included as well.
+    pub fn leaked_threads(
+        &self,
+        require_join: bool,
+    ) -> Vec<(String, LeakedThread, Vec<FrameInfo<'tcx>>)> {
+        let mut leaked = Vec::new();
+        for (id, thread) in self.threads.iter_enumerated() {
+            if id == MAIN_THREAD {
+                continue;
+            }
+            let kind = match (thread.state, thread.join_status) {
+                (ThreadState::Terminated, ThreadJoinStatus::Joinable) => LeakedThread::NotJoined,
+                (ThreadState::Terminated, ThreadJoinStatus::Detached) if require_join =>
+                    LeakedThread::Detached,
+                (ThreadState::Terminated, _) => continue,
+                _ => LeakedThread::Running,
+            };
+            let name = String::from_utf8_lossy(thread.thread_name()).into_owned();
+            leaked.push((name, kind, thread.spawn_stacktrace.clone()));
+        }
+        leaked
+    }
+
+    /// Get the allocations that the stack frames of threads which are still running refer to
+    /// directly. When the main thread terminates, the process exits and these threads are killed
+    /// without unwinding or running their TLS destructors, so the memory they are still using
+    /// was not leaked by the program.
+    pub fn remaining_thread_roots(&self) -> Vec<AllocId> {
+        let mut roots = Vec::new();
+        for thread in self.threads.iter().filter(|thread| thread.state != ThreadState::Terminated) {
+            for local in thread.stack.iter().flat_map(|frame| frame.locals.iter()) {
+                match local.value {
+                    LocalValue::Live(Operand::Indirect(mplace)) =>
+                        roots.extend(mplace.ptr.provenance.map(|tag| tag.alloc_id)),
+                    LocalValue::Live(Operand::Immediate(imm)) => {
+                        let scalars = match imm {
+                            Immediate::Scalar(a) => [Some(a), None],
+                            Immediate::ScalarPair(a, b) => [Some(a), Some(b)],
+                        };
+                        for scalar in scalars.into_iter().flatten() {
+                            if let ScalarMaybeUninit::Scalar(Scalar::Ptr(ptr, _)) = scalar {
+                                roots.push(ptr.provenance.alloc_id);
+                            }
+                        }
+                    }
+                    _ => {}
+                }
+            }
+        }
+        roots
+    }
+
+    /// Get the number of threads that have not terminated yet.
+    fn live_thread_count(&self) -> usize {
+        self.threads.iter().filter(|thread| thread.state != ThreadState::Terminated).count()
+    }
+
+    /// Describe where each thread that has not terminated yet currently is.
+    fn live_thread_locations(&self) -> Vec<(Option<SpanData>, String)> {
+        self.threads
+            .iter()
+            .filter(|thread| thread.state != ThreadState::Terminated)
+            .map(|thread| {
+                let name = String::from_utf8_lossy(thread.thread_name());
+                match thread.stack.last() {
+                    Some(frame) =>
+                        (
+                            Some(frame.current_span().data()),
+                            format!("thread `{}` is alive inside `{}`", name, frame.instance),
+                        ),
+                    None => (None, format!("thread `{}` is alive", name)),
+                }
+            })
+            .collect()
+    }
+
+    /// The id, name, and stack of each thread that has not terminated yet.
+    pub fn live_threads(
+        &self,
+    ) -> impl Iterator<Item = (ThreadId, Cow<'_, str>, &[Frame<'mir, 'tcx, Tag, FrameData<'tcx>>])>
+    {
+        self.threads
+            .iter_enumerated()
+            .filter(|(_, thread)| thread.state != ThreadState::Terminated)
+            .map(|(id, thread)| {
+                (id, String::from_utf8_lossy(thread.thread_name()), &thread.stack[..])
+            })
+    }
+
+    /// Replace the scheduling policy. Must be called before the program starts running.
+    pub fn set_policy(&mut self, policy: Box<dyn SchedulingPolicy>) {
+        self.policy = policy;
+    }
+
+    /// Write the recorded schedule to `path`. Does nothing if the schedule was not recorded.
+    pub fn write_schedule(&self, path: &str) -> std::io::Result<()> {
+        match &self.recorder {
+            Some(recorder) => recorder.write(path),
+            None => Ok(()),
+        }
+    }
+
+    /// Record that some thread did something that another thread could be waiting for, such
+    /// as a change of thread state.
+    pub fn note_progress(&mut self) {
+        self.spins_without_progress = 0;
+    }
+
+    /// Record that the active thread read from memory with the given `readers`.
+    #[inline(always)]
+    pub fn note_read(&self, readers: &Readers) {
+        readers.0.set(readers.0.get() | self.active_thread_bit());
+    }
+
+    /// Record that the active thread wrote to memory with the given `readers`. Another thread
+    /// that read the memory before might be spinning until this write happens, so that is
+    /// progress; a write to memory that only the writing thread reads, such as a counter in a
+    /// spin loop, is not.
+    #[inline(always)]
+    pub fn note_write(&mut self, readers: &Readers) {
+        if readers.0.replace(0) & !self.active_thread_bit() != 0 {
+            self.note_progress();
+        }
+    }
+
+    fn active_thread_bit(&self) -> u64 {
+        1 << (self.active_thread.to_u32() % 64)
+    }
+
+    /// Describe where all other threads that have not terminated yet currently are, as a list
+    /// of messages pointing to each of their frames.
+    fn other_thread_locations(&self) -> Vec<(Option<SpanData>, String)> {
+        let mut locations = Vec::new();
+        for (id, thread) in self.threads.iter_enumerated() {
+            if id == self.active_thread || thread.state == ThreadState::Terminated {
+                continue;
+            }
+            for (idx, frame) in thread.stack.iter().rev().enumerate() {
+                let msg = if idx == 0 {
+                    format!(
+                        "thread `{}` is inside `{}`",
+                        String::from_utf8_lossy(thread.thread_name()),
+                        frame.instance
+                    )
+                } else {
+                    format!("called from `{}`", frame.instance)
+                };
+                locations.push((Some(frame.current_span().data()), msg));
+            }
+        }
+        locations
     }
 
     /// Register the given `callback` to be called once the `call_time` passes.
@@ -424,19 +699,35 @@ impl<'mir, 'tcx: 'mir> ThreadManager<'mir, 'tcx> {
     }
 
     /// Get a callback that is ready to be called.
-    fn get_ready_callback(&mut self) -> Option<(ThreadId, TimeoutCallback<'mir, 'tcx>)> {
-        // We iterate over all threads in the order of their indices because
-        // this allows us to have a deterministic scheduler.
+    ///
+    /// If several timeouts have passed, the one that passed first is returned, so that timeouts
+    /// fire in the same order as their deadlines. Ties are broken by picking the thread with
+    /// the lowest index, which keeps the scheduler deterministic.
+    fn get_ready_callback(
+        &mut self,
+        clock: &Clock,
+    ) -> Option<(ThreadId, TimeoutCallback<'mir, 'tcx>)> {
+        let mut ready: Option<(ThreadId, Duration)> = None;
         for thread in self.threads.indices() {
-            match self.timeout_callbacks.entry(thread) {
-                Entry::Occupied(entry) =>
-                    if entry.get().call_time.get_wait_time() == Duration::new(0, 0) {
-                        return Some((thread, entry.remove().callback));
-                    },
-                Entry::Vacant(_) => {}
+            if let Some(info) = self.timeout_callbacks.get(&thread) {
+                if let Some(overdue) = info.call_time.get_overdue_time(clock) {
+                    if ready.map_or(true, |(_, most_overdue)| overdue > most_overdue) {
+                        ready = Some((thread, overdue));
+                    }
+                }
             }
         }
-        None
+        if ready.is_none() && clock.is_frozen() {
+            // Time does not pass, so we only get here because every thread is blocked: let the
+            // earliest deadline expire.
+            ready = self
+                .timeout_callbacks
+                .iter()
+                .min_by_key(|(thread, info)| (info.call_time.get_wait_time(clock), **thread))
+                .map(|(thread, _)| (*thread, Duration::ZERO));
+        }
+        let (thread, _) = ready?;
+        Some((thread, self.timeout_callbacks.remove(&thread).unwrap().callback))
     }
 
     /// Wakes up threads joining on the active one and deallocates thread-local statics.
@@ -474,18 +765,18 @@ impl<'mir, 'tcx: 'mir> ThreadManager<'mir, 'tcx> {
                 thread.state = ThreadState::Enabled;
             }
         }
+        self.note_progress();
         free_tls_statics
     }
 
     /// Decide which action to take next and on which thread.
     ///
-    /// The currently implemented scheduling policy is the one that is commonly
-    /// used in stateless model checkers such as Loom: run the active thread as
-    /// long as we can and switch only when we have to (the active thread was
-    /// blocked, terminated, or has explicitly asked to be preempted).
+    /// Which thread runs next, and whether the active thread gets preempted, is decided by the
+    /// `SchedulingPolicy`.
     fn schedule(
         &mut self,
         data_race: &Option<data_race::GlobalState>,
+        clock: &Clock,
     ) -> InterpResult<'tcx, SchedulingAction> {
         // Check whether the thread has **just** terminated (`check_terminated`
         // checks whether the thread has popped all its stack and if yes, sets
@@ -495,16 +786,30 @@ impl<'mir, 'tcx: 'mir> ThreadManager<'mir, 'tcx> {
         }
         // If we get here again and the thread is *still* terminated, there are no more dtors to run.
         if self.threads[MAIN_THREAD].state == ThreadState::Terminated {
-            // The main thread terminated; stop the program.
-            // We do *not* run TLS dtors of remaining threads, which seems to match rustc behavior.
+            // The main thread terminated (after running its TLS dtors); stop the program. Like a
+            // real process exit, this kills the remaining threads: we do *not* unwind them or run
+            // their TLS dtors.
             return Ok(SchedulingAction::Stop);
         }
         // This thread and the program can keep going.
         if self.threads[self.active_thread].state == ThreadState::Enabled
             && !self.yield_active_thread
         {
-            // The currently active thread is still enabled, just continue with it.
-            return Ok(SchedulingAction::ExecuteStep);
+            let at_sync_point = self.sync_point.replace(false)
+                && self.threads.iter_enumerated().any(|(id, thread)| {
+                    id != self.active_thread && thread.state == ThreadState::Enabled
+                });
+            let preempt = self.policy.preempt(self.active_thread, at_sync_point);
+            if let Some(recorder) = &mut self.recorder {
+                recorder.step(preempt);
+            }
+            if preempt {
+                trace!("preempting {:?}", self.active_thread);
+                self.yield_active_thread = true;
+            } else {
+                // The currently active thread is still enabled, just continue with it.
+                return Ok(SchedulingAction::ExecuteStep);
+            }
         }
         // The active thread yielded. Let's see if there are any timeouts to take care of. We do
         // this *before* running any other thread, to ensure that timeouts "in the past" fire before
@@ -513,21 +818,34 @@ impl<'mir, 'tcx: 'mir> ThreadManager<'mir, 'tcx> {
         // at the time of the call".
         // <https://pubs.opengroup.org/onlinepubs/9699919799/functions/pthread_cond_timedwait.html>
         let potential_sleep_time =
-            self.timeout_callbacks.values().map(|info| info.call_time.get_wait_time()).min();
+            self.timeout_callbacks.values().map(|info| info.call_time.get_wait_time(clock)).min();
         if potential_sleep_time == Some(Duration::new(0, 0)) {
             return Ok(SchedulingAction::ExecuteTimeoutCallback);
         }
         // No callbacks scheduled, pick a regular thread to execute.
-        // We need to pick a new thread for execution.
-        for (id, thread) in self.threads.iter_enumerated() {
-            if thread.state == ThreadState::Enabled {
-                if !self.yield_active_thread || id != self.active_thread {
-                    self.active_thread = id;
-                    if let Some(data_race) = data_race {
-                        data_race.thread_set_active(self.active_thread);
-                    }
-                    break;
-                }
+        // We need to pick a new thread for execution. A thread that yielded only gets to
+        // continue if there is no other thread.
+        let mut enabled: Vec<ThreadId> = self
+            .threads
+            .iter_enumerated()
+            .filter(|(id, thread)| {
+                thread.state == ThreadState::Enabled
+                    && (!self.yield_active_thread || *id != self.active_thread)
+            })
+            .map(|(id, _)| id)
+            .collect();
+        if enabled.is_empty() && self.threads[self.active_thread].state == ThreadState::Enabled {
+            enabled.push(self.active_thread);
+        }
+        if !enabled.is_empty() {
+            let id = self.policy.pick_next(self.active_thread, self.yield_active_thread, &enabled);
+            if let Some(recorder) = &mut self.recorder {
+                recorder.switch(id);
+            }
+            trace!("scheduling {:?}: {:?}", id, self.threads[id]);
+            self.active_thread = id;
+            if let Some(data_race) = data_race {
+                data_race.thread_set_active(self.active_thread);
             }
         }
         self.yield_active_thread = false;
@@ -540,8 +858,8 @@ impl<'mir, 'tcx: 'mir> ThreadManager<'mir, 'tcx> {
         } else if let Some(sleep_time) = potential_sleep_time {
             // All threads are currently blocked, but we have unexecuted
             // timeout_callbacks, which may unblock some of the threads. Hence,
-            // sleep until the first callback.
-            std::thread::sleep(sleep_time);
+            // skip ahead to the first callback instead of waiting for it.
+            clock.skip(sleep_time);
             Ok(SchedulingAction::ExecuteTimeoutCallback)
         } else {
             throw_machine_stop!(TerminationInfo::Deadlock);
@@ -582,14 +900,24 @@ pub trait EvalContextExt<'mir, 'tcx: 'mir>: crate::MiriEvalContextExt<'mir, 'tcx
         }
     }
 
+    /// Create a new thread. Fails if the `-Zmiri-max-threads` limit would be exceeded.
     #[inline]
-    fn create_thread(&mut self) -> ThreadId {
+    fn create_thread(&mut self) -> InterpResult<'tcx, ThreadId> {
         let this = self.eval_context_mut();
-        let id = this.machine.threads.create_thread();
+        if let Some(limit) = this.machine.max_threads {
+            if this.machine.threads.live_thread_count() as u64 >= limit {
+                throw_machine_stop!(TerminationInfo::TooManyThreads {
+                    limit,
+                    live_threads: this.machine.threads.live_thread_locations(),
+                });
+            }
+        }
+        let spawn_stacktrace = this.generate_stacktrace();
+        let id = this.machine.threads.create_thread(spawn_stacktrace);
         if let Some(data_race) = &mut this.machine.data_race {
             data_race.thread_created(id);
         }
-        id
+        Ok(id)
     }
 
     #[inline]
@@ -688,6 +1016,44 @@ pub trait EvalContextExt<'mir, 'tcx: 'mir>: crate::MiriEvalContextExt<'mir, 'tcx
         this.machine.threads.get_thread_name()
     }
 
+    #[inline]
+    fn set_active_thread_cpu_affinity(&mut self, mask: Vec<u8>) {
+        let this = self.eval_context_mut();
+        this.machine.threads.set_cpu_affinity(mask);
+    }
+
+    #[inline]
+    fn get_active_thread_cpu_affinity(&self) -> Option<&[u8]> {
+        let this = self.eval_context_ref();
+        this.machine.threads.get_cpu_affinity()
+    }
+
+    #[inline]
+    fn count_cpu_step(&mut self) {
+        let this = self.eval_context_mut();
+        this.machine.threads.count_cpu_step();
+    }
+
+    /// The CPU time used by the active thread.
+    fn thread_cpu_time(&self) -> Duration {
+        let this = self.eval_context_ref();
+        clock::cpu_time(this.machine.threads.thread_cpu_steps())
+    }
+
+    /// The CPU time used by all threads of the program.
+    fn process_cpu_time(&self) -> Duration {
+        let this = self.eval_context_ref();
+        clock::cpu_time(this.machine.threads.process_cpu_steps())
+    }
+
+    /// Record that the active thread performed an atomic or synchronization operation, after
+    /// which the scheduler may consider switching threads.
+    #[inline]
+    fn note_sync_point(&self) {
+        let this = self.eval_context_ref();
+        this.machine.threads.sync_point.set(true);
+    }
+
     #[inline]
     fn block_thread(&mut self, thread: ThreadId) {
         let this = self.eval_context_mut();
@@ -727,18 +1093,19 @@ pub trait EvalContextExt<'mir, 'tcx: 'mir>: crate::MiriEvalContextExt<'mir, 'tcx
     #[inline]
     fn run_timeout_callback(&mut self) -> InterpResult<'tcx> {
         let this = self.eval_context_mut();
-        let (thread, callback) =
-            if let Some((thread, callback)) = this.machine.threads.get_ready_callback() {
-                (thread, callback)
-            } else {
-                // get_ready_callback can return None if the computer's clock
-                // was shifted after calling the scheduler and before the call
-                // to get_ready_callback (see issue
-                // https://github.com/rust-lang/miri/issues/1763). In this case,
-                // just do nothing, which effectively just returns to the
-                // scheduler.
-                return Ok(());
-            };
+        let (thread, callback) = if let Some((thread, callback)) =
+            this.machine.threads.get_ready_callback(&this.machine.clock)
+        {
+            (thread, callback)
+        } else {
+            // get_ready_callback can return None if the computer's clock
+            // was shifted after calling the scheduler and before the call
+            // to get_ready_callback (see issue
+            // https://github.com/rust-lang/miri/issues/1763). In this case,
+            // just do nothing, which effectively just returns to the
+            // scheduler.
+            return Ok(());
+        };
         // This back-and-forth with `set_active_thread` is here because of two
         // design decisions:
         // 1. Make the caller and not the callback responsible for changing
@@ -755,8 +1122,15 @@ pub trait EvalContextExt<'mir, 'tcx: 'mir>: crate::MiriEvalContextExt<'mir, 'tcx
     #[inline]
     fn schedule(&mut self) -> InterpResult<'tcx, SchedulingAction> {
         let this = self.eval_context_mut();
+        if let Some(limit) = this.machine.spin_loop_limit {
+            if this.machine.threads.spins_without_progress > limit {
+                throw_machine_stop!(TerminationInfo::SpinDeadlock {
+                    other_threads: this.machine.threads.other_thread_locations(),
+                });
+            }
+        }
         let data_race = &this.machine.data_race;
-        this.machine.threads.schedule(data_race)
+        this.machine.threads.schedule(data_race, &this.machine.clock)
     }
 
     /// Handles thread termination of the active thread: wakes up threads joining on this one,
@@ -0,0 +1,229 @@
+//! Tracking where the values in memory come from, so that an error about an invalid value (like
+//! a dangling pointer or a bad enum discriminant) can point to where that value was computed, and
+//! not only to where it was used.
+//!
+//! For each byte of memory we remember the write that stored it. When a write stores a value that
+//! was read from memory in the same step (e.g. a copy, or a load followed by a store through a
+//! pointer), the origin of the read value is kept as well, which gives a chain back to where the
+//! value was first stored. Values in locals that do not live in memory are not tracked, so a chain
+//! starts at the first write of the value to memory.
+
+use std::cell::RefCell;
+use std::rc::Rc;
+
+use rustc_span::SpanData;
+use rustc_target::abi::Size;
+
+use crate::*;
+
+/// How many writes the chain of an origin contains at most. Longer chains lose the copies right
+/// after the first write, so that both where the value was computed and how it got to where it was
+/// read are kept.
+const MAX_CHAIN_LEN: usize = 8;
+
+/// A write that stored a value in memory.
+#[derive(Clone, Debug)]
+pub struct ValueOrigin {
+    /// Where the write happened.
+    pub span: SpanData,
+    /// The thread that performed the write.
+    pub thread: ThreadId,
+    /// The location that was written.
+    pub alloc_id: AllocId,
+    pub offset: Size,
+    /// Where the stored value was read from, if it was read from memory in the same step.
+    pub copied_from: Option<Origin>,
+    /// Whether some copies between `copied_from` and this write were dropped from the chain.
+    pub copies_omitted: bool,
+}
+
+/// A shared reference to a `ValueOrigin`. Two `Origin`s are only equal if they refer to the same
+/// write, so that the bytes stored by one write are kept together in the `RangeMap`.
+#[derive(Clone, Debug)]
+pub struct Origin(Rc<ValueOrigin>);
+
+impl PartialEq for Origin {
+    fn eq(&self, other: &Self) -> bool {
+        Rc::ptr_eq(&self.0, &other.0)
+    }
+}
+
+impl Origin {
+    /// This origin and the origins it was copied from, most recent first.
+    pub fn chain(&self) -> impl Iterator<Item = &ValueOrigin> {
+        std::iter::successors(Some(&*self.0), |origin| {
+            origin.copied_from.as_ref().map(|from| &*from.0)
+        })
+    }
+
+    /// Record a write of the value with this origin, shortening the chain if it gets too long.
+    fn copied_to(&self, mut write: ValueOrigin) -> Origin {
+        let mut chain: Vec<ValueOrigin> = self.chain().cloned().collect();
+        if chain.len() + 1 > MAX_CHAIN_LEN {
+            // Drop the oldest copy, and mark the write that now follows the first write.
+            chain.remove(chain.len() - 2);
+            let after_first = chain.len() - 2;
+            chain[after_first].copies_omitted = true;
+        }
+        let mut copied_from = None;
+        for mut origin in chain.into_iter().rev() {
+            origin.copied_from = copied_from;
+            copied_from = Some(Origin(Rc::new(origin)));
+        }
+        write.copied_from = copied_from;
+        Origin(Rc::new(write))
+    }
+}
+
+/// A read from memory in the current step.
+#[derive(Debug)]
+pub struct OriginRead {
+    pub alloc_id: AllocId,
+    pub range: AllocRange,
+    /// The origin of the first byte that was read, if it was written since tracking began.
+    pub origin: Option<Origin>,
+}
+
+/// The origins of the bytes of an allocation.
+#[derive(Clone, Debug)]
+pub struct AllocExtra {
+    origins: RangeMap<Option<Origin>>,
+}
+
+impl AllocExtra {
+    pub fn new_allocation(size: Size) -> Self {
+        AllocExtra { origins: RangeMap::new(size, None) }
+    }
+
+    /// Remember the origin of the value that is read from `range`.
+    pub fn read(&self, alloc_id: AllocId, range: AllocRange, global: &GlobalState) {
+        let origin = self
+            .origins
+            .iter(range.start, range.size)
+            .next()
+            .and_then(|(_, origin)| origin.clone());
+        global.borrow_mut().reads.push(OriginRead { alloc_id, range, origin });
+    }
+
+    /// Record that `range` was written in the current step.
+    pub fn write(&mut self, alloc_id: AllocId, range: AllocRange, global: &GlobalState) {
+        let global = global.borrow();
+        let (span, thread) = match global.current {
+            Some(current) => current,
+            // Writes outside of a step (like the ones setting up the program) have no origin.
+            None => return,
+        };
+        // A write of the same size as a read in this step most likely stores the value that was
+        // read.
+        let copied_from = global
+            .reads
+            .iter()
+            .rev()
+            .find(|read| read.range.size == range.size)
+            .and_then(|read| read.origin.as_ref());
+        let write = ValueOrigin {
+            span,
+            thread,
+            alloc_id,
+            offset: range.start,
+            copied_from: None,
+            copies_omitted: false,
+        };
+        let origin = match copied_from {
+            Some(copied_from) => copied_from.copied_to(write),
+            None => Origin(Rc::new(write)),
+        };
+        for (_, byte_origin) in self.origins.iter_mut(range.start, range.size) {
+            *byte_origin = Some(origin.clone());
+        }
+    }
+}
+
+/// The state of the current step.
+#[derive(Debug, Default)]
+pub struct GlobalStateInner {
+    /// The span and thread of the current step.
+    current: Option<(SpanData, ThreadId)>,
+    /// The reads from memory in the current step, in order.
+    reads: Vec<OriginRead>,
+}
+
+pub type GlobalState = RefCell<GlobalStateInner>;
+
+impl GlobalStateInner {
+    /// The reads from memory in the current step (which is the step that failed, when reporting
+    /// an error).
+    pub fn reads(&self) -> &[OriginRead] {
+        &self.reads
+    }
+}
+
+impl<'mir, 'tcx: 'mir> EvalContextExt<'mir, 'tcx> for crate::MiriEvalContext<'mir, 'tcx> {}
+pub trait EvalContextExt<'mir, 'tcx: 'mir>: crate::MiriEvalContextExt<'mir, 'tcx> {
+    /// Called before each step: writes in this step are attributed to the statement that is about
+    /// to be executed.
+    fn start_value_origin_step(&mut self) {
+        let this = self.eval_context_mut();
+        if this.machine.value_origins.is_none() {
+            // Looking up the span of the current statement is not free, so don't do it for nothing.
+            return;
+        }
+        let current = this
+            .active_thread_stack()
+            .last()
+            .map(|frame| (frame.current_span().data(), this.get_active_thread()));
+        if let Some(value_origins) = &this.machine.value_origins {
+            let mut value_origins = value_origins.borrow_mut();
+            value_origins.current = current;
+            value_origins.reads.clear();
+        }
+    }
+}
+
+/// Explains where the values read in the failing step were written, for an error about an
+/// invalid value.
+pub fn origin_helps(global: &GlobalStateInner) -> Vec<(Option<SpanData>, String)> {
+    let read = match global.reads().last() {
+        Some(read) => read,
+        None => return vec![],
+    };
+    let origin = match &read.origin {
+        Some(origin) => origin,
+        None =>
+            return vec![(
+                None,
+                format!(
+                    "the value was read from {:?}[{:#x}..{:#x}], which was not written since it was created",
+                    read.alloc_id,
+                    read.range.start.bytes(),
+                    read.range.end().bytes(),
+                ),
+            )],
+    };
+    let mut helps = vec![(
+        None,
+        format!(
+            "the value was read from {:?}[{:#x}..{:#x}]; this is where it came from:",
+            read.alloc_id,
+            read.range.start.bytes(),
+            read.range.end().bytes(),
+        ),
+    )];
+    for (i, origin) in origin.chain().enumerate() {
+        let action = if i == 0 { "it was written to" } else { "copying the value written to" };
+        helps.push((
+            Some(origin.span),
+            format!(
+                "{} {:?}[{:#x}] by thread {} here",
+                action,
+                origin.alloc_id,
+                origin.offset.bytes(),
+                origin.thread.to_u32(),
+            ),
+        ));
+        if origin.copies_omitted {
+            helps.push((None, format!("(some copies of the value are omitted here)")));
+        }
+    }
+    helps
+}
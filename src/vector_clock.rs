@@ -319,13 +319,91 @@ impl Index<VectorIdx> for VClock {
     }
 }
 
+/// The number of entries of a sparse vector-clock to store inline,
+/// sparse clocks with more entries will be stored on the heap.
+const SMALL_SPARSE_VECTOR: usize = 2;
+
+/// A sparse vector clock, used for the per-location clocks that are only
+/// ever updated one vector index at a time (such as the set of reads since
+/// the last write). Only the non-zero entries are stored, as a list of
+/// `(index, timestamp)` pairs sorted by index, so in the common case of
+/// a single accessing thread this is just an epoch and the cost of
+/// comparing it against a thread clock is proportional to the number
+/// of threads that actually accessed the location, not the total number
+/// of threads that have ever existed.
+#[derive(Clone, PartialEq, Eq, Default, Debug)]
+pub struct SparseVClock(SmallVec<[(VectorIdx, VTimestamp); SMALL_SPARSE_VECTOR]>);
+
+impl SparseVClock {
+    /// Load the internal `(index, timestamp)` pairs of the sparse clock.
+    #[inline]
+    pub fn as_slice(&self) -> &[(VectorIdx, VTimestamp)] {
+        self.0.as_slice()
+    }
+
+    /// Set the element at the given index to the value of that
+    /// index in the dense vector clock `other`.
+    pub fn set_at_index(&mut self, other: &VClock, idx: VectorIdx) {
+        let timestamp = other[idx];
+        match self.0.binary_search_by_key(&idx, |&(i, _)| i) {
+            Ok(pos) =>
+                if timestamp == 0 {
+                    self.0.remove(pos);
+                } else {
+                    self.0[pos].1 = timestamp;
+                },
+            Err(pos) =>
+                if timestamp != 0 {
+                    self.0.insert(pos, (idx, timestamp));
+                },
+        }
+    }
+
+    /// Set the vector to the all-zero vector
+    #[inline]
+    pub fn set_zero_vector(&mut self) {
+        self.0.clear();
+    }
+
+    /// Return if this vector is the all-zero vector
+    #[inline]
+    pub fn is_zero_vector(&self) -> bool {
+        self.0.is_empty()
+    }
+
+    /// Returns true if every timestamp in this sparse clock is less
+    /// than or equal to the timestamp at the same index in `other`,
+    /// i.e. if `self <= other` when viewed as a dense vector clock.
+    #[inline]
+    pub fn le_clock(&self, other: &VClock) -> bool {
+        self.0.iter().all(|&(idx, timestamp)| timestamp <= other[idx])
+    }
+
+    /// Find the first index, if one exists, where the value in this
+    /// sparse clock is greater than the value in `other`.
+    pub fn find_gt_index(&self, other: &VClock) -> Option<VectorIdx> {
+        self.0.iter().find_map(|&(idx, timestamp)| (timestamp > other[idx]).then_some(idx))
+    }
+
+    /// Expand this sparse clock into the equivalent dense vector clock.
+    pub fn to_vclock(&self) -> VClock {
+        let mut clock = VClock::default();
+        // The entries are sorted and non-zero, so the last element of the
+        // resulting vector is non-zero and the `VClock` invariant holds.
+        for &(idx, timestamp) in self.0.iter() {
+            clock.get_mut_with_min_len(idx.index() + 1)[idx.index()] = timestamp;
+        }
+        clock
+    }
+}
+
 /// Test vector clock ordering operations
 ///  data-race detection is tested in the external
 ///  test suite
 #[cfg(test)]
 mod tests {
 
-    use super::{VClock, VTimestamp, VectorIdx};
+    use super::{SparseVClock, VClock, VTimestamp, VectorIdx};
     use std::cmp::Ordering;
 
     #[test]
@@ -388,6 +466,31 @@ mod tests {
         );
     }
 
+    #[test]
+    fn test_sparse() {
+        let mut sparse = SparseVClock::default();
+        assert!(sparse.is_zero_vector());
+        assert!(sparse.le_clock(&VClock::default()));
+
+        sparse.set_at_index(&from_slice(&[0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 3]), VectorIdx(10));
+        sparse.set_at_index(&from_slice(&[0, 2]), VectorIdx(1));
+        assert_eq!(sparse.as_slice(), &[(VectorIdx(1), 2), (VectorIdx(10), 3)]);
+        assert_eq!(sparse.to_vclock(), from_slice(&[0, 2, 0, 0, 0, 0, 0, 0, 0, 0, 3]));
+
+        let clock = from_slice(&[5, 2, 0, 0, 0, 0, 0, 0, 0, 0, 2]);
+        assert!(!sparse.le_clock(&clock));
+        assert_eq!(sparse.find_gt_index(&clock), Some(VectorIdx(10)));
+        let clock = from_slice(&[0, 2, 0, 0, 0, 0, 0, 0, 0, 0, 3]);
+        assert!(sparse.le_clock(&clock));
+        assert_eq!(sparse.find_gt_index(&clock), None);
+
+        // Setting an entry back to zero removes it.
+        sparse.set_at_index(&VClock::default(), VectorIdx(10));
+        assert_eq!(sparse.as_slice(), &[(VectorIdx(1), 2)]);
+        sparse.set_zero_vector();
+        assert!(sparse.is_zero_vector());
+    }
+
     fn from_slice(mut slice: &[VTimestamp]) -> VClock {
         while let Some(0) = slice.last() {
             slice = &slice[..slice.len() - 1]
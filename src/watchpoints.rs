@@ -0,0 +1,152 @@
+//! Watchpoints: every access to the watched bytes of memory is reported, together with the values
+//! before and after a write, to find out which code reads or clobbers some memory. They are set
+//! with `-Zmiri-watch=<alloc-id>:<offset>:<len>`, or by the program with `miri_watch(ptr, len)`.
+//!
+//! The memory access hooks only record that a watchpoint was hit, since they cannot see the
+//! contents of the memory; the accesses are reported after the step, when the new value can be
+//! read.
+
+use std::cell::RefCell;
+
+use rustc_data_structures::fx::FxHashMap;
+
+use crate::*;
+
+#[derive(Debug)]
+struct Watchpoint {
+    range: AllocRange,
+    /// The contents of the watched bytes when they were last reported, or `None` if the
+    /// allocation did not exist yet when the watchpoint was set.
+    value: Option<Vec<u8>>,
+    /// How the bytes were accessed in the current step.
+    read: bool,
+    written: bool,
+}
+
+/// An access to watched memory, to be reported as a `NonHaltingDiagnostic::WatchedAccess`.
+#[derive(Debug)]
+pub struct WatchedAccess {
+    pub alloc_id: AllocId,
+    pub range: AllocRange,
+    pub thread: ThreadId,
+    pub read: bool,
+    /// For a write, the values before and after it.
+    pub write: Option<(Option<Vec<u8>>, Vec<u8>)>,
+}
+
+#[derive(Debug, Default)]
+pub struct GlobalStateInner {
+    watchpoints: FxHashMap<AllocId, Vec<Watchpoint>>,
+    /// Whether a watchpoint was hit in the current step.
+    hit: bool,
+}
+
+pub type GlobalState = RefCell<GlobalStateInner>;
+
+impl GlobalStateInner {
+    pub fn new(watchpoints: &[(AllocId, AllocRange)]) -> Self {
+        let mut this = GlobalStateInner::default();
+        for &(alloc_id, range) in watchpoints {
+            this.add(alloc_id, range, None);
+        }
+        this
+    }
+
+    fn add(&mut self, alloc_id: AllocId, range: AllocRange, value: Option<Vec<u8>>) {
+        let watchpoint = Watchpoint { range, value, read: false, written: false };
+        self.watchpoints.entry(alloc_id).or_default().push(watchpoint);
+    }
+
+    /// Whether some memory of the allocation is watched.
+    #[inline(always)]
+    pub fn is_watched(&self, alloc_id: AllocId) -> bool {
+        self.watchpoints.contains_key(&alloc_id)
+    }
+
+    /// Remember the initial contents of a watched allocation that was just created.
+    pub fn created<Tag, Extra>(&mut self, alloc_id: AllocId, alloc: &Allocation<Tag, Extra>) {
+        for watchpoint in self.watchpoints.get_mut(&alloc_id).into_iter().flatten() {
+            watchpoint.value = Some(watched_bytes(alloc, watchpoint.range));
+        }
+    }
+
+    /// Record an access to `range`, if it overlaps a watchpoint.
+    pub fn access(&mut self, alloc_id: AllocId, range: AllocRange, write: bool) {
+        for watchpoint in self.watchpoints.get_mut(&alloc_id).into_iter().flatten() {
+            let watched = watchpoint.range;
+            if watched.start < range.end() && range.start < watched.end() {
+                if write {
+                    watchpoint.written = true;
+                } else {
+                    watchpoint.read = true;
+                }
+                self.hit = true;
+            }
+        }
+    }
+
+    /// The watchpoints of deallocated memory can never be hit again.
+    pub fn deallocated(&mut self, alloc_id: AllocId) {
+        self.watchpoints.remove(&alloc_id);
+    }
+}
+
+fn watched_bytes<Tag, Extra>(alloc: &Allocation<Tag, Extra>, range: AllocRange) -> Vec<u8> {
+    let range = range.start.bytes_usize()..range.end().bytes_usize();
+    alloc.inspect_with_uninit_and_ptr_outside_interpreter(range).to_vec()
+}
+
+impl<'mir, 'tcx: 'mir> EvalContextExt<'mir, 'tcx> for crate::MiriEvalContext<'mir, 'tcx> {}
+pub trait EvalContextExt<'mir, 'tcx: 'mir>: crate::MiriEvalContextExt<'mir, 'tcx> {
+    /// Implements `miri_watch`: watch the `size` bytes at `ptr` from now on.
+    fn watch_memory(&mut self, ptr: Pointer<Option<Tag>>, size: Size) -> InterpResult<'tcx> {
+        let this = self.eval_context_mut();
+        // Check that the range is in bounds.
+        if this.get_ptr_alloc(ptr, size, Align::ONE)?.is_none() {
+            return Ok(());
+        }
+        let (alloc_id, offset, _) = this.ptr_get_alloc_id(ptr)?;
+        let range = alloc_range(offset, size);
+        let value = watched_bytes(this.get_alloc_raw(alloc_id)?, range);
+        this.machine.watchpoints.borrow_mut().add(alloc_id, range, Some(value));
+        Ok(())
+    }
+
+    /// Called after each step: report the accesses to watched memory in that step.
+    fn report_watched_accesses(&mut self) -> InterpResult<'tcx> {
+        let this = self.eval_context_mut();
+        let mut watchpoints = this.machine.watchpoints.borrow_mut();
+        if !watchpoints.hit {
+            return Ok(());
+        }
+        watchpoints.hit = false;
+        let thread = this.get_active_thread();
+        for (&alloc_id, watchpoints) in watchpoints.watchpoints.iter_mut() {
+            for watchpoint in watchpoints {
+                if !watchpoint.read && !watchpoint.written {
+                    continue;
+                }
+                let write = if watchpoint.written {
+                    let new = watched_bytes(this.get_alloc_raw(alloc_id)?, watchpoint.range);
+                    Some((watchpoint.value.replace(new.clone()), new))
+                } else {
+                    None
+                };
+                register_diagnostic(NonHaltingDiagnostic::WatchedAccess(WatchedAccess {
+                    alloc_id,
+                    range: watchpoint.range,
+                    thread,
+                    read: watchpoint.read,
+                    write,
+                }));
+                watchpoint.read = false;
+                watchpoint.written = false;
+            }
+        }
+        drop(watchpoints);
+        if let Some(debugger) = &mut this.machine.debugger {
+            debugger.stop_at_next_step();
+        }
+        Ok(())
+    }
+}
@@ -5,7 +5,7 @@ Assumes the `MIRI_SYSROOT` env var to be set appropriately,
 and the working directory to contain the cargo-miri-test project.
 '''
 
-import sys, subprocess, os, re
+import sys, subprocess, os, re, json
 
 CGREEN  = '\33[32m'
 CBOLD   = '\33[1m'
@@ -108,6 +108,36 @@ def test_cargo_miri_run():
         "run.args.stdout.ref", "run.custom-target-dir.stderr.ref",
     )
 
+def test_cargo_miri_unsupported_shims():
+    print("Testing `cargo miri run` (unsupported shims summary)...")
+    summary_path = "target/miri/unsupported-shims.json"
+    env = os.environ.copy()
+    env['MIRIFLAGS'] = "-Zmiri-panic-on-unsupported"
+    def run(quiet):
+        p = subprocess.run(
+            cargo_miri("run", quiet=quiet) + ["--", "unsupported"],
+            stdout=subprocess.PIPE,
+            stderr=subprocess.PIPE,
+            env=env,
+        )
+        stderr = p.stderr.decode("UTF-8")
+        if p.returncode != 0:
+            print(stderr, end="")
+            fail("exit code was {}".format(p.returncode))
+        return stderr
+    stderr = run(quiet=False)
+    if "`miri_test_unsupported`: 2 calls in 1 binary" not in stderr:
+        print(stderr, end="")
+        fail("the unsupported foreign functions were not summarized")
+    summary = json.load(open(summary_path))
+    if summary != {"miri_test_unsupported": {"calls": 2, "binaries": 1}}:
+        fail("unexpected summary in `{}`: {}".format(summary_path, summary))
+    # With `-q`, there is no summary, and the one of the previous run is removed.
+    stderr = run(quiet=True)
+    if "Miri does not support these foreign functions" in stderr or os.path.exists(summary_path):
+        print(stderr, end="")
+        fail("`-q` did not suppress the summary")
+
 def test_cargo_miri_test():
     # rustdoc is not run on foreign targets
     is_foreign = 'MIRI_TEST_TARGET' in os.environ
@@ -154,6 +184,13 @@ def test_cargo_miri_test():
         cargo_miri("test") + ["--target-dir=custom-test"],
         default_ref, "test.stderr-empty.ref",
     )
+    test("`cargo miri test` (HTML report)",
+        cargo_miri("test") + ["--html-report=target/miri/report.html"],
+        default_ref, "test.stderr-empty.ref",
+    )
+    report = open("target/miri/report.html").read()
+    if "cargo_miri_test" not in report or "6 passed, 0 failed, 1 ignored" not in report:
+        fail("the HTML report does not contain the expected results")
     del os.environ["CARGO_TARGET_DIR"] # this overrides `build.target-dir` passed by `--config`, so unset it
     test("`cargo miri test` (config-cli)",
         cargo_miri("test") + ["--config=build.target-dir=\"config-cli\"", "-Zunstable-options"],
@@ -173,6 +210,7 @@ if not 'MIRI_SYSROOT' in os.environ:
     # (If the sysroot gets built later when output is compared, that leads to test failures.)
     subprocess.run(cargo_miri("setup"), check=True)
 test_cargo_miri_run()
+test_cargo_miri_unsupported_shims()
 test_cargo_miri_test()
 # Ensure we did not create anything outside the expected target dir.
 for target_dir in ["target", "custom-run", "custom-test", "config-cli"]:
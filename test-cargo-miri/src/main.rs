@@ -18,6 +18,18 @@ fn main() {
         eprintln!("{}", arg);
     }
 
+    // Call a foreign function that Miri does not support, for the summary at the end of `cargo
+    // miri`. (We rely on the test runner to pass `-Zmiri-panic-on-unsupported` in that case.)
+    if std::env::args().nth(1).as_deref() == Some("unsupported") {
+        extern "Rust" {
+            fn miri_test_unsupported();
+        }
+        for _ in 0..2 {
+            assert!(std::panic::catch_unwind(|| unsafe { miri_test_unsupported() }).is_err());
+        }
+        return;
+    }
+
     // If there were no arguments, access stdin and test working dir.
     // (We rely on the test runner to always disable isolation when passing no arguments.)
     if std::env::args().len() <= 1 {
@@ -0,0 +1,12 @@
+// ignore-macos: `malloc_usable_size` is Linux-only
+// ignore-windows: No libc on Windows
+#![feature(rustc_private)]
+extern crate libc;
+
+fn main() {
+    let b = Box::new(0u64);
+    let ptr = Box::into_raw(b);
+    unsafe {
+        libc::malloc_usable_size(ptr.cast()); //~ ERROR: does not point to the start of a live C heap allocation
+    }
+}
@@ -0,0 +1,23 @@
+// ignore-windows: Concurrency on Windows is not supported yet.
+// compile-flags: -Zmiri-scheduler=exhaustive=1
+
+// The default schedule never runs the other thread between the two loads, but exploring all
+// schedules with one preemption finds the execution that does.
+
+use std::ptr;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::thread;
+
+static FLAG: AtomicUsize = AtomicUsize::new(0);
+
+fn main() {
+    let handle = thread::spawn(|| {
+        FLAG.store(1, Ordering::SeqCst);
+    });
+    let a = FLAG.load(Ordering::SeqCst);
+    let b = FLAG.load(Ordering::SeqCst);
+    if a != b {
+        let _x: i32 = unsafe { *ptr::null() }; //~ ERROR null pointer
+    }
+    handle.join().unwrap();
+}
@@ -0,0 +1,26 @@
+// ignore-windows: Concurrency on Windows is not supported yet.
+// compile-flags: -Zmiri-spin-loop-limit=1000
+// error-pattern: the evaluated program seems to be stuck in a spin loop
+
+// Both threads spin waiting for the other one to set its flag first.
+
+use std::hint;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::thread;
+
+static A: AtomicBool = AtomicBool::new(false);
+static B: AtomicBool = AtomicBool::new(false);
+
+fn main() {
+    let handle = thread::spawn(|| {
+        while !A.load(Ordering::Acquire) {
+            hint::spin_loop();
+        }
+        B.store(true, Ordering::Release);
+    });
+    while !B.load(Ordering::Acquire) {
+        hint::spin_loop();
+    }
+    A.store(true, Ordering::Release);
+    handle.join().unwrap();
+}
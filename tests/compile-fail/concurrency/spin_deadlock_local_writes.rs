@@ -0,0 +1,35 @@
+// ignore-windows: Concurrency on Windows is not supported yet.
+// compile-flags: -Zmiri-spin-loop-limit=1000
+// error-pattern: the evaluated program seems to be stuck in a spin loop
+
+// Both threads spin waiting for the other one to set its flag first. Writing to a local variable
+// while spinning is not progress that the other thread could be waiting for.
+
+use std::hint;
+use std::sync::atomic::{AtomicBool, AtomicUsize, Ordering};
+use std::thread;
+
+static A: AtomicBool = AtomicBool::new(false);
+static B: AtomicBool = AtomicBool::new(false);
+
+fn spin_until(flag: &AtomicBool) -> usize {
+    let mut spins = 0;
+    // An atomic counter only this thread uses, as in a CAS retry loop.
+    let retries = AtomicUsize::new(0);
+    while !flag.load(Ordering::Acquire) {
+        spins += 1;
+        retries.fetch_add(1, Ordering::Relaxed);
+        hint::spin_loop();
+    }
+    spins + retries.load(Ordering::Relaxed)
+}
+
+fn main() {
+    let handle = thread::spawn(|| {
+        spin_until(&A);
+        B.store(true, Ordering::Release);
+    });
+    spin_until(&B);
+    A.store(true, Ordering::Release);
+    handle.join().unwrap();
+}
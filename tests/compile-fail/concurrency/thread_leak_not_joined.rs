@@ -0,0 +1,30 @@
+// ignore-windows: No libc on Windows
+// error-pattern: was never joined or detached
+
+// A thread that terminated before the main thread, but was neither joined nor detached, is a
+// leak as well: its return value and the resources of the thread are never released.
+
+#![feature(rustc_private)]
+
+extern crate libc;
+
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::{mem, ptr};
+
+static DONE: AtomicBool = AtomicBool::new(false);
+
+extern "C" fn thread_start(_null: *mut libc::c_void) -> *mut libc::c_void {
+    DONE.store(true, Ordering::Release);
+    ptr::null_mut()
+}
+
+fn main() {
+    unsafe {
+        let mut native: libc::pthread_t = mem::zeroed();
+        let attr: libc::pthread_attr_t = mem::zeroed();
+        assert_eq!(libc::pthread_create(&mut native, &attr, thread_start, ptr::null_mut()), 0);
+    }
+    while !DONE.load(Ordering::Acquire) {
+        std::thread::yield_now();
+    }
+}
@@ -0,0 +1,16 @@
+// ignore-windows: Concurrency on Windows is not supported yet.
+// compile-flags: -Zmiri-thread-leaks=require-join
+// error-pattern: the main thread terminated without waiting for all remaining threads
+
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::thread;
+
+static DONE: AtomicBool = AtomicBool::new(false);
+
+fn main() {
+    // The thread is detached, and finishes before `main` returns, but it is never joined.
+    drop(thread::spawn(|| DONE.store(true, Ordering::Release)));
+    while !DONE.load(Ordering::Acquire) {
+        thread::yield_now();
+    }
+}
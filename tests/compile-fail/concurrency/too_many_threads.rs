@@ -0,0 +1,13 @@
+// ignore-windows: Concurrency on Windows is not supported yet.
+// compile-flags: -Zmiri-max-threads=3
+// error-pattern: the limit of 3 live threads was reached
+
+use std::thread;
+
+fn main() {
+    let handles: Vec<_> = (0..3).map(|_| thread::spawn(|| thread::park())).collect();
+    for handle in handles {
+        handle.thread().unpark();
+        handle.join().unwrap();
+    }
+}
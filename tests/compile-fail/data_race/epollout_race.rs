@@ -0,0 +1,45 @@
+// ignore-macos: Uses Linux-only APIs
+// ignore-windows: Uses Linux-only APIs
+// compile-flags: -Zmiri-disable-isolation
+
+// An eventfd is writable without anyone writing to it, so finding it writable does not
+// synchronize with the last write to it, unlike finding it readable.
+
+#![feature(rustc_private)]
+extern crate libc;
+
+use std::thread::{sleep, spawn};
+use std::time::Duration;
+
+#[derive(Copy, Clone)]
+struct EvilSend<T>(pub T);
+
+unsafe impl<T> Send for EvilSend<T> {}
+unsafe impl<T> Sync for EvilSend<T> {}
+
+pub fn main() {
+    let mut a = 0u32;
+    let c = EvilSend(&mut a as *mut u32);
+    unsafe {
+        let fd = libc::eventfd(0, libc::EFD_NONBLOCK);
+        let epfd = libc::epoll_create1(0);
+        let mut event = libc::epoll_event { events: libc::EPOLLOUT as u32, u64: 0 };
+        assert_eq!(libc::epoll_ctl(epfd, libc::EPOLL_CTL_ADD, fd, &mut event), 0);
+
+        let j1 = spawn(move || {
+            *c.0 = 1;
+            let value = 1u64;
+            libc::write(fd, &value as *const u64 as *const libc::c_void, 8);
+        });
+
+        // Let the thread run first.
+        sleep(Duration::from_millis(10));
+        let mut events = [libc::epoll_event { events: 0, u64: 0 }; 1];
+        assert_eq!(libc::epoll_wait(epfd, events.as_mut_ptr(), 1, -1), 1);
+        assert_eq!(events[0].events, libc::EPOLLOUT as u32);
+        let value = *c.0; //~ ERROR Data race detected between Read on Thread(id = 0, name = "main") and Write on Thread(id = 1)
+        assert_eq!(value, 1);
+
+        j1.join().unwrap();
+    }
+}
@@ -0,0 +1,7 @@
+// Errors inside the standard library point at the local code that called it.
+
+fn main() {
+    let mut v: Vec<u8> = Vec::with_capacity(1);
+    unsafe { v.set_len(1) };
+    let _found = v.contains(&0); //~ ERROR uninitialized
+}
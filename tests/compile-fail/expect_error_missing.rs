@@ -0,0 +1,7 @@
+// compile-flags: -Zmiri-expect-error=Undefined
+// error-pattern: the evaluated program terminated, but it was expected to stop with an error
+
+fn main() {
+    let b = Box::new(42);
+    assert_eq!(*b, 42);
+}
@@ -0,0 +1,14 @@
+extern "Rust" {
+    fn miri_freeze(ptr: *const u8, size: usize);
+}
+
+fn main() {
+    let mut buf = [0u8; 8];
+    buf[5] = 1;
+    unsafe { miri_freeze(buf.as_ptr().add(4), 4) };
+    // The frozen memory can still be read, and the rest can still be written.
+    assert_eq!(buf[5], 1);
+    buf[3] = 1;
+    let ptr = buf.as_mut_ptr();
+    unsafe { *ptr.add(6) = 2 }; //~ ERROR which was frozen by `miri_freeze`
+}
@@ -0,0 +1,11 @@
+// compile-flags: -Zmiri-leak-report=summary
+// error-pattern: make_leak (Rust heap)
+
+fn make_leak() {
+    std::mem::forget(Box::new(42u32));
+}
+
+fn main() {
+    make_leak();
+    make_leak();
+}
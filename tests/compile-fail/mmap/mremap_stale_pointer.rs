@@ -0,0 +1,18 @@
+// ignore-macos: `mremap` is Linux-only
+// ignore-windows: No libc on Windows
+#![feature(rustc_private)]
+extern crate libc;
+
+use std::ptr;
+
+fn main() {
+    unsafe {
+        let prot = libc::PROT_READ | libc::PROT_WRITE;
+        let flags = libc::MAP_PRIVATE | libc::MAP_ANONYMOUS;
+        let old = libc::mmap(ptr::null_mut(), 4096, prot, flags, -1, 0);
+        let new = libc::mremap(old, 4096, 8192, libc::MREMAP_MAYMOVE);
+        assert_ne!(new, libc::MAP_FAILED);
+        // The mapping moved, so the old pointer is dangling.
+        let _val = *old.cast::<u8>(); //~ ERROR: dereferenced after this allocation got freed
+    }
+}
@@ -0,0 +1,16 @@
+// ignore-macos: the `mmap` shim is Linux-only
+// ignore-windows: No libc on Windows
+#![feature(rustc_private)]
+extern crate libc;
+
+use std::ptr;
+
+fn main() {
+    unsafe {
+        let flags = libc::MAP_PRIVATE | libc::MAP_ANONYMOUS;
+        let ptr = libc::mmap(ptr::null_mut(), 4096, libc::PROT_READ, flags, -1, 0);
+        assert_ne!(ptr, libc::MAP_FAILED);
+        assert_eq!(*ptr.cast::<u8>(), 0);
+        *ptr.cast::<u8>() = 1; //~ ERROR: which is mapped read-only
+    }
+}
@@ -0,0 +1,11 @@
+extern "Rust" {
+    fn miri_poison_memory(ptr: *const u8, size: usize);
+}
+
+fn main() {
+    let mut buf = [0u8; 8];
+    unsafe { miri_poison_memory(buf.as_ptr().add(4), 4) };
+    // The part before the poisoned range can still be used.
+    buf[3] = 1;
+    let _val = buf[6]; //~ ERROR which was poisoned by `miri_poison_memory`
+}
@@ -0,0 +1,14 @@
+// compile-flags: -Zmiri-realloc=in-place
+use std::alloc::{alloc, dealloc, realloc, Layout};
+
+fn main() {
+    unsafe {
+        let layout = Layout::from_size_align(16, 8).unwrap();
+        let p1 = alloc(layout);
+        let p2 = realloc(p1, layout, 8);
+        // Same address, but `p1` still points to the freed allocation.
+        assert_eq!(p1 as usize, p2 as usize);
+        let _val = *p1; //~ ERROR dereferenced after this allocation got freed
+        dealloc(p2, Layout::from_size_align(8, 8).unwrap());
+    }
+}
@@ -0,0 +1,15 @@
+// compile-flags: -Zmiri-tag-raw-pointers
+
+extern "Rust" {
+    fn miri_assert_unique(ptr: *const u8, size: usize);
+}
+
+fn main() {
+    let mut x = 0u8;
+    let raw = &mut x as *mut u8;
+    unsafe { miri_assert_unique(raw, 1) };
+    let shared = unsafe { &*raw };
+    // `raw` is still usable, but it is not unique any more.
+    unsafe { miri_assert_unique(raw, 1) }; //~ ERROR `miri_assert_unique` failed
+    let _val = *shared;
+}
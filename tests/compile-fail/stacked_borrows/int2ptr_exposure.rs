@@ -0,0 +1,11 @@
+// compile-flags: -Zmiri-tag-raw-pointers -Zmiri-track-exposures
+//! With raw pointer tracking, a pointer cast from an integer cannot be used to access memory. The
+//! error points at the cast of `raw` to an integer, which the program expected to justify it.
+
+fn main() {
+    let mut x = 0u32;
+    let raw = &mut x as *mut u32;
+    let addr = raw as usize;
+    let ptr = addr as *mut u32;
+    unsafe { *ptr = 1; } //~ ERROR does not exist in the borrow stack
+}
@@ -0,0 +1,14 @@
+// The locations 4..8 are split into ranges whose borrow stacks share their items. Writing
+// through `ptr` is fine on 0..4, so the error has to point at the first of the shared locations.
+fn main() {
+    let mut arr = [0u8; 8];
+    let base = arr.as_mut_ptr();
+    let whole = unsafe { &mut *base.cast::<[u8; 8]>() };
+    let ptr = whole.as_mut_ptr();
+    // Creating `tail` from `base` removes `whole` and `ptr` from the stacks of 4..8.
+    let tail = unsafe { &mut *base.add(4).cast::<[u8; 4]>() };
+    // Reading just one location splits 4..8 without changing the stacks.
+    let _val = tail[0];
+    unsafe { *ptr.cast::<[u8; 8]>() = [1; 8] };
+    //~^ ERROR [0x4], but that tag does not exist in the borrow stack for this location
+}
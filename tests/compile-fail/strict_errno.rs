@@ -0,0 +1,16 @@
+// Unfortunately, the test framework does not support 'only-linux',
+// so we need to ignore Windows and macOS instead.
+// ignore-macos: Uses Linux-only APIs
+// ignore-windows: Uses Linux-only APIs
+// compile-flags: -Zmiri-strict-errno
+#![feature(rustc_private)]
+extern crate libc;
+
+fn main() {
+    unsafe {
+        let mut ts = std::mem::MaybeUninit::<libc::timespec>::uninit();
+        assert_eq!(libc::clock_gettime(libc::CLOCK_MONOTONIC, ts.as_mut_ptr()), 0);
+        // The call succeeded, so `errno` has no meaningful value.
+        let _errno = *libc::__errno_location(); //~ ERROR uninitialized
+    }
+}
@@ -0,0 +1,19 @@
+// only-x86_64
+#![feature(abi_x86_interrupt)]
+
+#[allow(dead_code)]
+#[repr(C)]
+struct InterruptStackFrame {
+    ip: u64,
+    cs: u64,
+    flags: u64,
+    sp: u64,
+    ss: u64,
+}
+
+extern "x86-interrupt" fn handler(_frame: InterruptStackFrame) {}
+
+fn main() {
+    let frame = InterruptStackFrame { ip: 0, cs: 0, flags: 0, sp: 0, ss: 0 };
+    handler(frame); //~ ERROR unsupported operation: calling `handler` with the `x86-interrupt` ABI is not supported
+}
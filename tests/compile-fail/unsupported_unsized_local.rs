@@ -0,0 +1,11 @@
+#![feature(unsized_locals)]
+#![allow(incomplete_features)]
+
+fn sum(b: Box<[u8]>) -> u8 {
+    let s: [u8] = *b;
+    s.iter().sum()
+}
+
+fn main() {
+    sum(Box::new([1, 2, 3])); //~ ERROR unsupported operation: unsized local
+}
@@ -0,0 +1,11 @@
+// compile-flags: -Zmiri-track-value-origins
+//! With value origin tracking, the error points to where the invalid byte was written, and to the
+//! copy of the array that it was read from.
+
+fn main() {
+    let mut bytes = [0u8; 2];
+    bytes[1] = 2;
+    let copy = bytes;
+    let ptr = &copy[1] as *const u8 as *const bool;
+    let _b = unsafe { *ptr }; //~ ERROR encountered 0x02, but expected a boolean
+}
@@ -0,0 +1,21 @@
+// ignore-linux: Uses Windows-only APIs
+// ignore-macos: Uses Windows-only APIs
+use std::ffi::c_void;
+use std::ptr;
+
+const MEM_COMMIT: u32 = 0x1000;
+const MEM_RESERVE: u32 = 0x2000;
+const PAGE_READWRITE: u32 = 0x04;
+
+extern "system" {
+    fn VirtualAlloc(address: *mut c_void, size: usize, ty: u32, protect: u32) -> *mut c_void;
+}
+
+fn main() {
+    unsafe {
+        let region = VirtualAlloc(ptr::null_mut(), 2 * 4096, MEM_RESERVE, PAGE_READWRITE);
+        VirtualAlloc(region, 4096, MEM_COMMIT, PAGE_READWRITE);
+        let second_page = (region as *mut u8).add(4096);
+        *second_page = 1; //~ ERROR reserved but not committed
+    }
+}
@@ -0,0 +1,12 @@
+// ignore-linux: Uses Windows-only APIs
+// ignore-macos: Uses Windows-only APIs
+use std::fs::OpenOptions;
+use std::os::windows::fs::OpenOptionsExt;
+
+const FILE_FLAG_DELETE_ON_CLOSE: u32 = 0x04000000;
+
+fn main() {
+    let mut options = OpenOptions::new();
+    options.write(true).create(true).custom_flags(FILE_FLAG_DELETE_ON_CLOSE);
+    let _file = options.open("temp.txt"); //~ ERROR `CreateFileW` with flags and attributes 0x4000000 is not supported
+}
@@ -0,0 +1,18 @@
+// ignore-linux: Uses Windows-only APIs
+// ignore-macos: Uses Windows-only APIs
+use std::ffi::c_void;
+
+extern "system" {
+    fn _CxxThrowException(exception_object: *mut c_void, throw_info: *mut u8) -> !;
+}
+
+fn throw() {
+    unsafe {
+        _CxxThrowException(std::ptr::null_mut(), std::ptr::null_mut());
+        //~^ ERROR unwinding via SEH is not supported: `throw` called `_CxxThrowException`
+    }
+}
+
+fn main() {
+    throw();
+}
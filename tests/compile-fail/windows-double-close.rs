@@ -0,0 +1,19 @@
+// ignore-linux: Uses Windows-only APIs
+// ignore-macos: Uses Windows-only APIs
+use std::ffi::c_void;
+use std::ptr;
+
+type HANDLE = *mut c_void;
+
+extern "system" {
+    fn CreateEventW(attrs: *mut c_void, manual: i32, initial: i32, name: *const u16) -> HANDLE;
+    fn CloseHandle(handle: HANDLE) -> i32;
+}
+
+fn main() {
+    unsafe {
+        let event = CreateEventW(ptr::null_mut(), 0, 0, ptr::null());
+        assert_eq!(CloseHandle(event), 1);
+        CloseHandle(event); //~ ERROR which was already closed
+    }
+}
@@ -0,0 +1,20 @@
+// ignore-linux: Uses Windows-only APIs
+// ignore-macos: Uses Windows-only APIs
+use std::ffi::c_void;
+
+type HANDLE = *mut c_void;
+
+extern "system" {
+    fn GetProcessHeap() -> HANDLE;
+    fn HeapAlloc(heap: HANDLE, flags: u32, size: usize) -> *mut c_void;
+    fn HeapFree(heap: HANDLE, flags: u32, mem: *mut c_void) -> i32;
+}
+
+fn main() {
+    unsafe {
+        let p = HeapAlloc(GetProcessHeap(), 0, 16);
+        // A made-up private heap.
+        let private_heap = 0x1000 as HANDLE;
+        HeapFree(private_heap, 0, p); //~ ERROR was not returned by `GetProcessHeap`
+    }
+}
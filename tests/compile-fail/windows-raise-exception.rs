@@ -0,0 +1,16 @@
+// ignore-linux: Uses Windows-only APIs
+// ignore-macos: Uses Windows-only APIs
+
+extern "system" {
+    fn RaiseException(code: u32, flags: u32, num_args: u32, args: *const usize);
+}
+
+const STATUS_STACK_BUFFER_OVERRUN: u32 = 0xC0000409;
+const EXCEPTION_NONCONTINUABLE: u32 = 0x1;
+
+fn main() {
+    unsafe {
+        RaiseException(STATUS_STACK_BUFFER_OVERRUN, EXCEPTION_NONCONTINUABLE, 0, std::ptr::null());
+        //~^ ERROR `main` raised exception 0xc0000409 (STATUS_STACK_BUFFER_OVERRUN)
+    }
+}
@@ -1,5 +1,8 @@
 use std::env;
-use std::path::PathBuf;
+use std::fs;
+use std::io::{BufRead, BufReader, Read, Write};
+use std::path::{Path, PathBuf};
+use std::process::{Command, Output, Stdio};
 
 use colored::*;
 use compiletest_rs as compiletest;
@@ -68,6 +71,421 @@ fn miri_pass(path: &str, target: &str) {
     run_tests("ui", path, target);
 }
 
+/// Whether the tests that are not run by compiletest and are called `name` should be skipped,
+/// because a filter was given that does not match them.
+fn filtered_out(name: &str) -> bool {
+    env::args().nth(1).map_or(false, |filter| !name.contains(&filter))
+}
+
+/// A command that runs `program` in Miri, for the tests that are not run by compiletest.
+fn miri_command(program: &str, target: &str) -> Command {
+    let mut cmd = Command::new(miri_path());
+    cmd.args(["--edition", "2018", "--target", target]);
+    if let Ok(sysroot) = env::var("MIRI_SYSROOT") {
+        cmd.arg("--sysroot").arg(sysroot);
+    }
+    cmd.arg(program);
+    cmd
+}
+
+/// Records the schedule of a failing concurrent program and checks that replaying it reproduces
+/// the same execution. This needs two runs of the same program, so it is not a compiletest test.
+fn schedule_replay(target: &str) {
+    let program = "tests/run-pass/concurrency/schedule_replay.rs";
+    if filtered_out(program) {
+        return;
+    }
+    if target.contains("windows") {
+        // Concurrency on Windows is not supported yet.
+        return;
+    }
+    eprintln!(
+        "{}",
+        format!("## Running schedule record/replay test against miri for target {}", target)
+            .green()
+            .bold()
+    );
+
+    let schedule = env::temp_dir().join("miri_schedule_replay.txt");
+    fs::remove_file(&schedule).ok();
+    let run = |schedule_flags: &[&str]| -> Output {
+        let mut cmd = miri_command(program, target);
+        cmd.arg("-Zmiri-seed=5ca1ab1e").args(schedule_flags);
+        cmd.args(["--", "abort"]);
+        cmd.output().expect("failed to run miri")
+    };
+
+    let record_flag = format!("-Zmiri-record-schedule={}", schedule.display());
+    let recorded = run(&["-Zmiri-scheduler=random", record_flag.as_str()]);
+    assert!(!recorded.status.success(), "the program was supposed to abort");
+    assert!(schedule.exists(), "no schedule was recorded");
+    // The replay does not use the random scheduler, so it only takes the same turns as the
+    // recorded run if it follows the schedule.
+    let replay_flag = format!("-Zmiri-replay-schedule={}", schedule.display());
+    let replayed = run(&[replay_flag.as_str()]);
+    assert!(!replayed.status.success(), "the replayed program was supposed to abort");
+    assert!(!recorded.stdout.is_empty());
+    assert_eq!(
+        String::from_utf8_lossy(&replayed.stdout),
+        String::from_utf8_lossy(&recorded.stdout),
+        "the replay did not reproduce the recorded execution"
+    );
+    fs::remove_file(&schedule).unwrap();
+}
+
+/// Runs a program whose threads take turns depending on the schedule with each scheduling policy,
+/// and checks that the policies interleave the threads as promised.
+fn scheduling_policies(target: &str) {
+    let program = "tests/run-pass/concurrency/scheduler_policies.rs";
+    if filtered_out(program) {
+        return;
+    }
+    if target.contains("windows") {
+        // Concurrency on Windows is not supported yet.
+        return;
+    }
+    eprintln!(
+        "{}",
+        format!("## Running scheduling policy tests against miri for target {}", target)
+            .green()
+            .bold()
+    );
+
+    // How often the threads took turns.
+    let turns = |policy: &str, seed: u64| -> u64 {
+        let mut cmd = miri_command(program, target);
+        cmd.arg(format!("-Zmiri-scheduler={}", policy)).arg(format!("-Zmiri-seed={:x}", seed));
+        let output = cmd.output().expect("failed to run miri");
+        assert!(
+            output.status.success(),
+            "the program failed with -Zmiri-scheduler={}:\n{}",
+            policy,
+            String::from_utf8_lossy(&output.stderr)
+        );
+        String::from_utf8_lossy(&output.stdout).trim().parse().unwrap()
+    };
+
+    // Without preemptions, each thread runs until it terminates.
+    assert_eq!(turns("cooperative", 0), 1);
+    assert_eq!(turns("bounded=0", 0), 1);
+    // These preempt the threads while they run.
+    assert!(turns("round-robin", 0) > 1);
+    assert!(turns("random", 0) > 1);
+    // The randomized policies are deterministic for a given seed. The priority policy only
+    // preempts at a few synchronization points, so it might not preempt at all for some seeds.
+    for policy in ["random", "priority"] {
+        assert_eq!(turns(policy, 1), turns(policy, 1), "{} is not deterministic", policy);
+    }
+    assert!((0..8).any(|seed| turns("priority", seed) > 1), "priority never preempted");
+}
+
+/// Checks that the step profile accounts for the frames that are still on the stack when the
+/// program exits.
+fn step_profile(target: &str) {
+    let program = "tests/run-pass/step_profile_exit.rs";
+    if filtered_out(program) {
+        return;
+    }
+    eprintln!(
+        "{}",
+        format!("## Running step profile test against miri for target {}", target).green().bold()
+    );
+
+    let profile = env::temp_dir().join("miri_step_profile.txt");
+    fs::remove_file(&profile).ok();
+    let output = miri_command(program, target)
+        .arg(format!("-Zmiri-profile-interpreted={}", profile.display()))
+        .output()
+        .expect("failed to run miri");
+    assert!(output.status.success(), "{}", String::from_utf8_lossy(&output.stderr));
+    let report = fs::read_to_string(&profile).expect("no step profile was written");
+    fs::remove_file(&profile).unwrap();
+
+    // The exclusive and inclusive steps of the function with the given name.
+    let steps = |name: &str| -> (u64, u64) {
+        let line = report
+            .lines()
+            .find(|line| {
+                let function = line.split_whitespace().nth(2).unwrap_or("");
+                function == name || function.ends_with(&format!("::{}", name))
+            })
+            .unwrap_or_else(|| panic!("`{}` is missing from the step profile:\n{}", name, report));
+        let mut counts = line.split_whitespace().map(|count| count.parse::<u64>().unwrap());
+        (counts.next().unwrap(), counts.next().unwrap())
+    };
+    let (spin_exclusive, spin_inclusive) = steps("spin");
+    let (_, outer_inclusive) = steps("outer");
+    let (_, main_inclusive) = steps("main");
+    // `spin` returned every time, `outer` and `main` did not. `spin` ran three times, twice of
+    // them below `outer`.
+    assert!(spin_inclusive >= spin_exclusive && spin_exclusive > 0, "{}", report);
+    assert!(outer_inclusive > spin_inclusive * 2 / 3, "{}", report);
+    assert!(main_inclusive > outer_inclusive + spin_inclusive / 3, "{}", report);
+}
+
+/// Checks that the stable allocation IDs do not depend on how the threads are interleaved, and that
+/// tracking an allocation by its stable ID finds the same allocation in another run.
+fn stable_alloc_ids(target: &str) {
+    let program = "tests/run-pass/concurrency/stable_alloc_ids.rs";
+    if filtered_out(program) {
+        return;
+    }
+    if target.contains("windows") {
+        // Concurrency on Windows is not supported yet.
+        return;
+    }
+    eprintln!(
+        "{}",
+        format!("## Running stable allocation ID test against miri for target {}", target)
+            .green()
+            .bold()
+    );
+
+    let run = |flags: &[&str]| -> String {
+        let output = miri_command(program, target)
+            .args(["-Zmiri-scheduler=random", "-Zmiri-print-alloc-ids"])
+            .args(flags)
+            .output()
+            .expect("failed to run miri");
+        let stderr = String::from_utf8_lossy(&output.stderr).into_owned();
+        assert!(output.status.success(), "the program did not finish successfully:\n{}", stderr);
+        stderr
+    };
+    // The stable IDs, in the order of the `AllocId`s they were printed with.
+    let stable_ids = |stderr: &str| -> Vec<String> {
+        stderr
+            .lines()
+            .filter_map(|line| line.split(" -> stable id ").nth(1))
+            .map(str::to_owned)
+            .collect()
+    };
+
+    let first = run(&["-Zmiri-seed=1"]);
+    let second = run(&["-Zmiri-seed=2"]);
+    let mut first_ids = stable_ids(&first);
+    let mut second_ids = stable_ids(&second);
+    assert!(!first_ids.is_empty(), "no allocation ids were printed:\n{}", first);
+    // Some allocation of a spawned thread.
+    let tracked = first_ids.iter().filter(|id| !id.starts_with("0:")).max().unwrap().clone();
+    first_ids.sort();
+    second_ids.sort();
+    assert_eq!(first_ids, second_ids, "the stable ids differ between the runs");
+
+    let tracked_flag = format!("-Zmiri-track-alloc-id={}", tracked);
+    let third = run(&["-Zmiri-seed=3", tracked_flag.as_str()]);
+    let created = format!("(stable id {})", tracked);
+    assert!(
+        third.lines().any(|line| line.contains("created allocation") && line.contains(&created)),
+        "tracking {} did not find the allocation:\n{}",
+        tracked,
+        third
+    );
+}
+
+/// Checks the SARIF output for a program that stops with an error against the `.sarif` file next
+/// to it.
+fn sarif(target: &str) {
+    let program = "tests/compile-fail/unsupported_foreign_function.rs";
+    if filtered_out(program) {
+        return;
+    }
+    eprintln!(
+        "{}",
+        format!("## Running SARIF output test against miri for target {}", target).green().bold()
+    );
+
+    let sarif = env::temp_dir().join("miri_test.sarif");
+    fs::remove_file(&sarif).ok();
+    let output = miri_command(program, target)
+        .arg(format!("-Zmiri-sarif={}", sarif.display()))
+        .output()
+        .expect("failed to run miri");
+    assert!(!output.status.success(), "the program was supposed to stop with an error");
+    assert_eq!(
+        fs::read_to_string(&sarif).expect("no SARIF output was written"),
+        fs::read_to_string(Path::new(program).with_extension("sarif")).unwrap()
+    );
+    fs::remove_file(&sarif).unwrap();
+}
+
+/// Traces the Stacked Borrows operations of a program that stops with an error, and checks that
+/// the trace shows which access invalidated the pointer that was used.
+fn sb_trace(target: &str) {
+    // `xref` is invalidated by the read in `callee` (line 14), and used on line 9.
+    let program = "tests/compile-fail/stacked_borrows/illegal_read1.rs";
+    if filtered_out(program) {
+        return;
+    }
+    eprintln!(
+        "{}",
+        format!("## Running Stacked Borrows trace test against miri for target {}", target)
+            .green()
+            .bold()
+    );
+
+    // The error mentions the allocation that we need to trace.
+    let output = miri_command(program, target).output().expect("failed to run miri");
+    let stderr = String::from_utf8_lossy(&output.stderr);
+    let alloc_id = stderr
+        .split("alloc")
+        .find_map(|rest| {
+            let digits: String = rest.chars().take_while(char::is_ascii_digit).collect();
+            (!digits.is_empty() && rest[digits.len()..].starts_with('[')).then(|| digits)
+        })
+        .expect("the error does not mention the allocation");
+
+    let trace = env::temp_dir().join("miri_sb_trace.json");
+    fs::remove_file(&trace).ok();
+    let output = miri_command(program, target)
+        .arg(format!("-Zmiri-sb-trace={}:{}", alloc_id, trace.display()))
+        .output()
+        .expect("failed to run miri");
+    assert!(!output.status.success(), "the program was supposed to stop with an error");
+    let trace_json = fs::read_to_string(&trace).expect("no trace was written");
+    fs::remove_file(&trace).unwrap();
+
+    // Each operation is on a line of its own.
+    let ops: Vec<&str> = trace_json.lines().filter(|line| line.starts_with(r#"{"id":"#)).collect();
+    let tag =
+        |op: &str| op.split(r#""tag":""#).nth(1).unwrap().split('"').next().unwrap().to_owned();
+    assert!(ops[0].contains(r#""kind":"allocate""#), "{}", trace_json);
+    let invalidating = ops
+        .iter()
+        .find(|op| op.contains(&format!("{}:14:", program)))
+        .unwrap_or_else(|| panic!("the read in `callee` was not traced:\n{}", trace_json));
+    assert!(invalidating.contains(r#""kind":"read""#), "{}", trace_json);
+    let popped = invalidating.split(r#""popped":["#).nth(1).unwrap();
+    assert!(popped.starts_with('{'), "the read in `callee` did not pop anything:\n{}", trace_json);
+    let failed = ops.last().unwrap();
+    assert!(failed.contains(r#""failed":true"#), "{}", trace_json);
+    assert!(failed.contains(&format!("{}:9:", program)), "{}", trace_json);
+    assert_eq!(tag(failed), tag(popped), "the failed access used a different tag:\n{}", trace_json);
+}
+
+/// Checks that the core dump of a program that stops with an error shows the allocation that the
+/// error is about, and that no core dump is written for programs that exit normally or stop with
+/// an expected error.
+fn core_dump(target: &str) {
+    let program = "tests/compile-fail/dangling_pointers/out_of_bounds_read1.rs";
+    if filtered_out(program) {
+        return;
+    }
+    eprintln!(
+        "{}",
+        format!("## Running core dump test against miri for target {}", target).green().bold()
+    );
+
+    let dump = env::temp_dir().join("miri_core_dump.txt");
+    let run = |program: &str| -> Output {
+        fs::remove_file(&dump).ok();
+        miri_command(program, target)
+            .arg(format!("-Zmiri-core-dump={}", dump.display()))
+            .output()
+            .expect("failed to run miri")
+    };
+
+    let output = run(program);
+    assert!(!output.status.success(), "the program was supposed to stop with an error");
+    let contents = fs::read_to_string(&dump).expect("no core dump was written");
+    assert!(contents.starts_with("error: Undefined Behavior: "), "{}", contents);
+    assert!(contents.contains("(active):"), "{}", contents);
+    // The error is about the buffer of the vector `[1, 2]`.
+    let allocs = contents
+        .split("\nallocations the error is about:\n")
+        .nth(1)
+        .unwrap_or_else(|| panic!("the core dump has no allocations:\n{}", contents));
+    assert!(allocs.contains(": 2 bytes, aligned to 1 bytes"), "{}", contents);
+    assert!(allocs.contains("  0x0000: [01, 02]"), "{}", contents);
+
+    for program in ["tests/run-pass/exit.rs", "tests/run-pass/expect_error.rs"] {
+        let output = run(program);
+        assert!(output.status.success(), "{} was supposed to succeed", program);
+        assert!(!dump.exists(), "a core dump was written for {}", program);
+    }
+}
+
+/// Drives the debugger with commands on stdin: stops at a line breakpoint, inspects the locals,
+/// memory and borrow stacks, and steps. Inspecting must not affect the program, which still has
+/// to run to completion.
+fn debugger(target: &str) {
+    let program = "tests/run-pass/debugger.rs";
+    if filtered_out(program) {
+        return;
+    }
+    eprintln!(
+        "{}",
+        format!("## Running debugger test against miri for target {}", target).green().bold()
+    );
+
+    let mut child = miri_command(program, target)
+        .arg("-Zmiri-debugger")
+        .stdin(Stdio::piped())
+        .stdout(Stdio::piped())
+        .stderr(Stdio::piped())
+        .spawn()
+        .expect("failed to run miri");
+    let mut stdin = child.stdin.take().unwrap();
+    let mut stdout = BufReader::new(child.stdout.take().unwrap());
+    // The debugger stops before the first step.
+    stdin.write_all(b"break debugger.rs:12\ncontinue\n").unwrap();
+    // The program prints the address of `bytes` before it reaches the breakpoint.
+    let mut addr = String::new();
+    stdout.read_line(&mut addr).unwrap();
+    let addr = addr.trim();
+    write!(stdin, "locals\nmemory {addr} 4\nstacks {addr} 4\nstep\ncontinue\n", addr = addr)
+        .unwrap();
+    drop(stdin);
+    let mut stderr = String::new();
+    child.stderr.take().unwrap().read_to_string(&mut stderr).unwrap();
+    let status = child.wait().unwrap();
+
+    assert!(status.success(), "the program did not finish successfully:\n{}", stderr);
+    for expected in [
+        "breakpoint 0 at debugger.rs:12".to_owned(),
+        format!("#0 `sum` at {}:12:", program),
+        "  total = 10".to_owned(),
+        format!("{}: [01, 02, 03, 04]", addr),
+    ] {
+        assert!(stderr.contains(&expected), "`{}` is missing from:\n{}", expected, stderr);
+    }
+    assert!(!stderr.contains("cannot"), "inspecting failed:\n{}", stderr);
+    // The step stopped again.
+    assert_eq!(stderr.matches("#0 `sum` at").count(), 2, "{}", stderr);
+}
+
+/// Checks the output of `miri --explain`, which does not run a program.
+fn explain() {
+    if filtered_out("explain") {
+        return;
+    }
+    eprintln!("{}", "## Running `miri --explain` tests".green().bold());
+
+    let explain = |code: &str| -> Output {
+        Command::new(miri_path()).args(["--explain", code]).output().expect("failed to run miri")
+    };
+
+    let leak = explain("MIRI0005");
+    assert!(leak.status.success());
+    assert_eq!(
+        String::from_utf8_lossy(&leak.stdout),
+        fs::read_to_string("src/error_codes/MIRI0005.md").unwrap()
+    );
+    // Codes are matched case-insensitively.
+    let dangling = explain("miri0007");
+    assert!(dangling.status.success());
+    assert_eq!(
+        String::from_utf8_lossy(&dangling.stdout),
+        fs::read_to_string("src/error_codes/MIRI0007.md").unwrap()
+    );
+    let unknown = explain("MIRI9999");
+    assert!(!unknown.status.success());
+    assert!(unknown.stdout.is_empty());
+    assert_eq!(
+        String::from_utf8_lossy(&unknown.stderr),
+        "error: `MIRI9999` is not a valid Miri error code\n"
+    );
+}
+
 fn get_host() -> String {
     let version_meta =
         rustc_version::VersionMeta::for_command(std::process::Command::new(miri_path()))
@@ -90,4 +508,13 @@ fn main() {
     let target = get_target();
     miri_pass("tests/run-pass", &target);
     compile_fail("tests/compile-fail", &target);
+    schedule_replay(&target);
+    scheduling_policies(&target);
+    stable_alloc_ids(&target);
+    step_profile(&target);
+    explain();
+    sarif(&target);
+    sb_trace(&target);
+    core_dump(&target);
+    debugger(&target);
 }
@@ -0,0 +1,349 @@
+//! Tests for embedding Miri as a library: the programs in `tests/embedding` are run in-process
+//! with `miri::run_analysis`, and the tests check the `Report` that it returns and the hooks
+//! that embedders can register in the `MiriConfig`.
+
+#![feature(rustc_private)]
+
+extern crate rustc_data_structures;
+extern crate rustc_driver;
+extern crate rustc_interface;
+extern crate rustc_metadata;
+extern crate rustc_middle;
+extern crate rustc_session;
+extern crate rustc_span;
+extern crate rustc_target;
+
+use std::env;
+use std::path::PathBuf;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::{Arc, Mutex};
+
+use rustc_data_structures::sync::Lrc;
+use rustc_driver::Compilation;
+use rustc_interface::interface::Config;
+use rustc_middle::mir::{self, RetagKind};
+use rustc_middle::ty::query::ExternProviders;
+use rustc_session::search_paths::PathKind;
+use rustc_span::Symbol;
+use rustc_target::{abi::Size, spec::abi::Abi};
+
+use miri::{
+    AllocId, AllocRange, EmulateByNameResult, ErrorKind, ForeignItemProvider,
+    HelpersEvalContextExt, InterpResult, MemoryKind, MemoryObserver, MiriConfig, MiriEvalContext,
+    MiriMemoryKind, ObserverFactory, OpTy, PlaceTy, Report, Scalar, Tag,
+};
+
+struct Analysis {
+    config: MiriConfig,
+    report: Option<Report>,
+}
+
+impl rustc_driver::Callbacks for Analysis {
+    fn config(&mut self, config: &mut Config) {
+        // The same workaround as in the `miri` binary, see there.
+        config.override_queries = Some(|_, _, external_providers| {
+            external_providers.used_crate_source = |tcx, cnum| {
+                let mut providers = ExternProviders::default();
+                rustc_metadata::provide_extern(&mut providers);
+                let mut crate_source = (providers.used_crate_source)(tcx, cnum);
+                Lrc::make_mut(&mut crate_source).rlib = Some((PathBuf::new(), PathKind::All));
+                crate_source
+            };
+        });
+    }
+
+    fn after_analysis<'tcx>(
+        &mut self,
+        compiler: &rustc_interface::interface::Compiler,
+        queries: &'tcx rustc_interface::Queries<'tcx>,
+    ) -> Compilation {
+        compiler.session().abort_if_errors();
+        queries.global_ctxt().unwrap().peek_mut().enter(|tcx| {
+            let (entry_id, entry_type) = tcx.entry_fn(()).expect("the program has no `main`");
+            let mut config = self.config.clone();
+            config.args.insert(0, compiler.input().filestem().to_string());
+            self.report = Some(miri::run_analysis(tcx, entry_id, entry_type, config));
+        });
+        Compilation::Stop
+    }
+}
+
+/// Runs `tests/embedding/<name>.rs` with `config`, and returns the report.
+fn run(name: &str, config: MiriConfig) -> Report {
+    let mut args = vec!["miri".to_string()];
+    args.extend(miri::MIRI_DEFAULT_ARGS.iter().map(ToString::to_string));
+    args.extend(["--edition", "2018"].iter().map(ToString::to_string));
+    args.push("--sysroot".to_string());
+    args.push(env::var("MIRI_SYSROOT").expect("the embedding tests need `MIRI_SYSROOT`"));
+    if let Ok(target) = env::var("MIRI_TEST_TARGET") {
+        args.push("--target".to_string());
+        args.push(target);
+    }
+    args.push(format!("tests/embedding/{}.rs", name));
+
+    let mut analysis = Analysis { config, report: None };
+    // This fails if the program stopped with an error, since that is reported through the
+    // session; the report says what happened either way.
+    let _ = rustc_driver::RunCompiler::new(&args, &mut analysis).run();
+    analysis.report.expect("the program was not run")
+}
+
+/// Whether the tests run for a Windows target, where threads are not supported yet.
+fn windows_target() -> bool {
+    env::var("MIRI_TEST_TARGET").map_or(cfg!(windows), |target| target.contains("windows"))
+}
+
+#[test]
+fn report_error() {
+    let report = run("report_ub", MiriConfig::default());
+    assert_eq!(report.exit_code, None);
+    assert_eq!(report.return_code(), None);
+    let error = report.error.expect("the use after free was not reported");
+    assert_eq!(error.kind, ErrorKind::UndefinedBehavior);
+    assert_eq!(error.code, Some("MIRI0007"));
+    assert!(error.message.contains("dereferenced after this allocation got freed"));
+    // The error is reported in `read_freed`, which was called by `main`.
+    let innermost = &error.stacktrace[0];
+    assert!(innermost.function.ends_with("read_freed"), "{}", innermost.function);
+    let location = innermost.location.as_ref().unwrap();
+    assert!(location.file.ends_with("report_ub.rs"), "{}", location.file);
+    assert_eq!(location.line, 6);
+    assert!(error.stacktrace[1].function.ends_with("main"));
+}
+
+#[test]
+fn report_memory_leaks() {
+    let report = run("report_leaks", MiriConfig::default());
+    // The program itself exited successfully, but the leak check failed.
+    assert_eq!(report.exit_code, Some(0));
+    assert_eq!(report.return_code(), None);
+    let error = report.error.expect("the leaks were not reported");
+    assert_eq!(error.kind, ErrorKind::MemoryLeak);
+    assert_eq!(error.code, Some("MIRI0005"));
+    assert_eq!(report.leaks.leaked_allocations, 2);
+    assert!(report.leaks.leaked_threads.is_empty());
+
+    // Without the leak check, the leaks are neither reported nor counted.
+    let report = run("report_leaks", MiriConfig { ignore_leaks: true, ..MiriConfig::default() });
+    assert!(report.error.is_none());
+    assert_eq!(report.return_code(), Some(0));
+    assert_eq!(report.leaks.leaked_allocations, 0);
+}
+
+#[test]
+fn report_thread_leaks() {
+    if windows_target() {
+        return;
+    }
+    let report = run("report_thread_leak", MiriConfig::default());
+    assert_eq!(report.exit_code, Some(0));
+    assert_eq!(report.return_code(), None);
+    assert_eq!(report.error.expect("the thread leak was not reported").kind, ErrorKind::ThreadLeak);
+    assert_eq!(report.leaks.leaked_threads, ["sleeper"]);
+}
+
+#[test]
+fn report_statistics() {
+    if windows_target() {
+        return;
+    }
+    let report = run("report_stats", MiriConfig { stats: true, ..MiriConfig::default() });
+    assert!(report.error.is_none(), "{:?}", report.error);
+    assert_eq!(report.exit_code, Some(7));
+    assert_eq!(report.return_code(), Some(7));
+    assert_eq!(report.leaks.leaked_allocations, 0);
+    assert!(report.unsupported_shims.is_empty());
+    let stats = &report.statistics;
+    assert_eq!(stats.executions, 1);
+    assert_eq!(stats.threads, 2);
+    assert!(stats.steps > 4096, "{}", stats.steps);
+    assert!(stats.allocations >= stats.deallocations, "{:?}", stats);
+    assert!(stats.deallocations > 0, "{:?}", stats);
+    assert!(stats.peak_memory >= 4096, "{:?}", stats);
+    // Without `MiriConfig::stats`, the allocations are not counted.
+    let report = run("report_stats", MiriConfig::default());
+    assert_eq!(report.statistics.threads, 2);
+    assert_eq!(report.statistics.allocations, 0);
+    assert_eq!(report.statistics.peak_memory, 0);
+}
+
+/// What the observers of `observed_events` were told.
+#[derive(Default)]
+struct Events {
+    observers: usize,
+    /// The sizes of the allocations made by the Rust allocator.
+    rust_allocs: Vec<u64>,
+    reads: usize,
+    writes: usize,
+    deallocs: usize,
+    retags: usize,
+}
+
+struct Recorder(Arc<Mutex<Events>>);
+
+impl<'tcx> MemoryObserver<'tcx> for Recorder {
+    fn on_alloc(&mut self, _id: AllocId, size: Size, kind: MemoryKind<MiriMemoryKind>) {
+        if kind == MemoryKind::Machine(MiriMemoryKind::Rust) {
+            self.0.lock().unwrap().rust_allocs.push(size.bytes());
+        }
+    }
+
+    fn on_read(&mut self, _id: AllocId, _range: AllocRange) -> InterpResult<'tcx> {
+        self.0.lock().unwrap().reads += 1;
+        Ok(())
+    }
+
+    fn on_write(&mut self, _id: AllocId, _range: AllocRange) -> InterpResult<'tcx> {
+        self.0.lock().unwrap().writes += 1;
+        Ok(())
+    }
+
+    fn on_dealloc(&mut self, _id: AllocId, _range: AllocRange) -> InterpResult<'tcx> {
+        self.0.lock().unwrap().deallocs += 1;
+        Ok(())
+    }
+
+    fn on_retag(&mut self, _kind: RetagKind, _place: &PlaceTy<'tcx, Tag>) -> InterpResult<'tcx> {
+        self.0.lock().unwrap().retags += 1;
+        Ok(())
+    }
+}
+
+#[test]
+fn observed_events() {
+    let events = Arc::new(Mutex::new(Events::default()));
+    let factory_events = Arc::clone(&events);
+    let factory = ObserverFactory(Arc::new(move || {
+        factory_events.lock().unwrap().observers += 1;
+        Box::new(Recorder(Arc::clone(&factory_events)))
+    }));
+    let report =
+        run("observed", MiriConfig { memory_observers: vec![factory], ..MiriConfig::default() });
+    assert!(report.error.is_none(), "{:?}", report.error);
+    assert_eq!(report.return_code(), Some(0));
+
+    let events = events.lock().unwrap();
+    assert_eq!(events.observers, 1);
+    // The box of `observed.rs`.
+    assert!(events.rust_allocs.contains(&10), "{:?}", events.rust_allocs);
+    assert!(events.reads > 0);
+    assert!(events.writes > 0);
+    assert!(events.deallocs > 0);
+    assert!(events.retags > 0);
+}
+
+/// Checks that the allocations of the Rust allocator that are 10 bytes large are only written
+/// once, when they are initialized.
+#[derive(Default)]
+struct WriteOnce {
+    checked: Vec<AllocId>,
+    written: Vec<AllocId>,
+}
+
+impl<'tcx> MemoryObserver<'tcx> for WriteOnce {
+    fn on_alloc(&mut self, id: AllocId, size: Size, kind: MemoryKind<MiriMemoryKind>) {
+        if kind == MemoryKind::Machine(MiriMemoryKind::Rust) && size.bytes() == 10 {
+            self.checked.push(id);
+        }
+    }
+
+    fn on_write(&mut self, id: AllocId, _range: AllocRange) -> InterpResult<'tcx> {
+        if self.checked.contains(&id) {
+            if self.written.contains(&id) {
+                return Err(rustc_middle::err_ub_format!("allocation written twice").into());
+            }
+            self.written.push(id);
+        }
+        Ok(())
+    }
+}
+
+#[test]
+fn observer_errors() {
+    let factory = ObserverFactory(Arc::new(|| Box::new(WriteOnce::default())));
+    let report =
+        run("observed", MiriConfig { memory_observers: vec![factory], ..MiriConfig::default() });
+    let error = report.error.expect("the observer did not stop the program");
+    assert_eq!(error.kind, ErrorKind::UndefinedBehavior);
+    assert!(error.message.contains("written twice"), "{}", error.message);
+    // `Box::new` initialized the box, so the first write in `main` is the second one.
+    let innermost = &error.stacktrace[0];
+    assert!(innermost.function.ends_with("main"), "{}", innermost.function);
+    assert_eq!(innermost.location.as_ref().unwrap().line, 3);
+}
+
+/// Adds `embedding_add`, and overrides `getpid` to return 4242.
+#[derive(Default)]
+struct Provider {
+    /// How often the provider was asked about `embedding_add`.
+    add_lookups: AtomicUsize,
+}
+
+impl ForeignItemProvider for Provider {
+    fn emulate_foreign_item_by_name<'mir, 'tcx>(
+        &self,
+        ecx: &mut MiriEvalContext<'mir, 'tcx>,
+        link_name: Symbol,
+        abi: Abi,
+        args: &[OpTy<'tcx, Tag>],
+        dest: &PlaceTy<'tcx, Tag>,
+        _ret: mir::BasicBlock,
+    ) -> InterpResult<'tcx, EmulateByNameResult<'mir, 'tcx>> {
+        match &*link_name.as_str() {
+            "embedding_add" => {
+                self.add_lookups.fetch_add(1, Ordering::Relaxed);
+                let [a, b] = ecx.check_shim(abi, Abi::C { unwind: false }, link_name, args)?;
+                let a = ecx.read_scalar(a)?.to_u64()?;
+                let b = ecx.read_scalar(b)?.to_u64()?;
+                ecx.write_scalar(Scalar::from_u64(a + b), dest)?;
+            }
+            "getpid" => {
+                let [] = ecx.check_shim(abi, Abi::C { unwind: false }, link_name, args)?;
+                ecx.write_scalar(Scalar::from_i32(4242), dest)?;
+            }
+            _ => return Ok(EmulateByNameResult::NotSupported),
+        }
+        Ok(EmulateByNameResult::NeedsJumping)
+    }
+}
+
+#[test]
+fn provider_adds_item() {
+    let provider = Arc::new(Provider::default());
+    let config = MiriConfig {
+        foreign_item_providers: vec![Arc::clone(&provider) as Arc<dyn ForeignItemProvider>],
+        ..MiriConfig::default()
+    };
+    let report = run("provided_item", config);
+    assert!(report.error.is_none(), "{:?}", report.error);
+    // (0 + 10) + (1 + 10) + (2 + 10)
+    assert_eq!(report.return_code(), Some(33));
+    assert!(report.unsupported_shims.is_empty());
+    // The provider is asked first, so it is the only one that is asked about the item.
+    assert_eq!(provider.add_lookups.load(Ordering::Relaxed), 3);
+
+    // Without the provider, Miri does not know the item.
+    let report = run("provided_item", MiriConfig::default());
+    let error = report.error.expect("the call to an unknown item was not reported");
+    assert_eq!(error.kind, ErrorKind::Unsupported);
+    assert_eq!(error.code, Some("MIRI0009"));
+    assert_eq!(report.unsupported_shims, [("embedding_add".to_string(), 1)]);
+}
+
+#[test]
+fn provider_overrides_shim() {
+    if windows_target() {
+        return;
+    }
+    let config = MiriConfig {
+        foreign_item_providers: vec![Arc::new(Provider::default())],
+        ..MiriConfig::default()
+    };
+    let report = run("overridden_shim", config);
+    assert!(report.error.is_none(), "{:?}", report.error);
+    assert_eq!(report.return_code(), Some(4242));
+
+    // Without the provider, Miri's own shim is used.
+    let report = run("overridden_shim", MiriConfig::default());
+    assert_eq!(report.return_code(), Some(i64::from(miri::PROCESS_ID)));
+}
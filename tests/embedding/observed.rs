@@ -0,0 +1,7 @@
+fn main() {
+    let mut b = Box::new(*b"write once");
+    b[0] = b'W';
+    let r = &mut *b;
+    r[6] = b'O';
+    assert_eq!(&*b, b"Write Once");
+}
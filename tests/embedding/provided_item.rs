@@ -0,0 +1,8 @@
+extern "C" {
+    fn embedding_add(a: u64, b: u64) -> u64;
+}
+
+fn main() {
+    let sum: u64 = (0..3).map(|i| unsafe { embedding_add(i, 10) }).sum();
+    std::process::exit(sum as i32);
+}
@@ -0,0 +1,4 @@
+fn main() {
+    std::mem::forget(Box::new(1u64));
+    std::mem::forget(vec![0u8; 16]);
+}
@@ -0,0 +1,8 @@
+use std::thread;
+
+fn main() {
+    let data = vec![1u8; 4096];
+    let sum =
+        thread::spawn(move || data.iter().map(|&b| u64::from(b)).sum::<u64>()).join().unwrap();
+    std::process::exit(if sum == 4096 { 7 } else { 1 });
+}
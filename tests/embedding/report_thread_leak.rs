@@ -0,0 +1,17 @@
+use std::sync::mpsc;
+use std::thread;
+
+fn main() {
+    let (started, wait) = mpsc::channel();
+    thread::Builder::new()
+        .name("sleeper".to_string())
+        .spawn(move || {
+            started.send(()).unwrap();
+            loop {
+                thread::park();
+            }
+        })
+        .unwrap();
+    // Wait until the thread runs, so that it has set its name.
+    wait.recv().unwrap();
+}
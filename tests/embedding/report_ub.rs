@@ -0,0 +1,11 @@
+fn read_freed() -> i32 {
+    let p = {
+        let b = Box::new(42);
+        &*b as *const i32
+    };
+    unsafe { *p }
+}
+
+fn main() {
+    read_freed();
+}
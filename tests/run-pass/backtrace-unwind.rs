@@ -0,0 +1,77 @@
+// ignore-windows: No libc on Windows
+#![feature(rustc_private)]
+
+extern crate libc;
+
+use std::ffi::{c_void, CStr};
+
+#[repr(C)]
+struct UnwindContext {
+    _private: [u8; 0],
+}
+
+const URC_NO_REASON: i32 = 0;
+const URC_FATAL_PHASE1_ERROR: i32 = 3;
+const URC_END_OF_STACK: i32 = 5;
+
+type TraceFn = extern "C" fn(ctx: *mut UnwindContext, arg: *mut c_void) -> i32;
+
+extern "C" {
+    fn _Unwind_Backtrace(trace: TraceFn, arg: *mut c_void) -> i32;
+    fn _Unwind_GetIP(ctx: *mut UnwindContext) -> libc::uintptr_t;
+    fn _Unwind_GetIPInfo(ctx: *mut UnwindContext, ip_before_insn: *mut libc::c_int)
+    -> libc::uintptr_t;
+    fn _Unwind_FindEnclosingFunction(pc: *mut c_void) -> *mut c_void;
+}
+
+/// Collects the names of all frames, like the `backtrace` crate does.
+extern "C" fn collect(ctx: *mut UnwindContext, arg: *mut c_void) -> i32 {
+    let names = unsafe { &mut *(arg as *mut Vec<(String, *mut c_void)>) };
+    let mut ip_before_insn = 1;
+    let ip = unsafe { _Unwind_GetIPInfo(ctx, &mut ip_before_insn) } as *mut c_void;
+    assert_eq!(ip_before_insn, 0);
+    assert_eq!(unsafe { _Unwind_GetIP(ctx) } as *mut c_void, ip);
+
+    let mut info: libc::Dl_info = unsafe { std::mem::zeroed() };
+    assert_ne!(unsafe { libc::dladdr(ip, &mut info) }, 0);
+    let name = unsafe { CStr::from_ptr(info.dli_sname) }.to_str().unwrap().to_owned();
+    assert_eq!(info.dli_saddr, unsafe { _Unwind_FindEnclosingFunction(ip) });
+    names.push((name, info.dli_saddr));
+    URC_NO_REASON
+}
+
+/// Only looks at the first frame.
+extern "C" fn stop(_ctx: *mut UnwindContext, arg: *mut c_void) -> i32 {
+    let count = unsafe { &mut *(arg as *mut usize) };
+    *count += 1;
+    URC_FATAL_PHASE1_ERROR
+}
+
+#[inline(never)]
+fn func_a() -> Vec<(String, *mut c_void)> {
+    func_b()
+}
+
+#[inline(never)]
+fn func_b() -> Vec<(String, *mut c_void)> {
+    let mut names = Vec::new();
+    let result = unsafe { _Unwind_Backtrace(collect, &mut names as *mut _ as *mut c_void) };
+    assert_eq!(result, URC_END_OF_STACK);
+    names
+}
+
+fn main() {
+    let names = func_a();
+    let names: Vec<_> = names.iter().map(|(name, _)| name.as_str()).collect();
+    assert_eq!(names[..3], ["func_b", "func_a", "main"]);
+    assert_eq!(func_a().get(1).unwrap().1, func_a as *mut c_void);
+
+    let mut count = 0usize;
+    let result = unsafe { _Unwind_Backtrace(stop, &mut count as *mut _ as *mut c_void) };
+    assert_eq!(result, URC_FATAL_PHASE1_ERROR);
+    assert_eq!(count, 1);
+
+    // Addresses that are not in a function are not found.
+    let mut info: libc::Dl_info = unsafe { std::mem::zeroed() };
+    assert_eq!(unsafe { libc::dladdr(&count as *const _ as *const c_void, &mut info) }, 0);
+}
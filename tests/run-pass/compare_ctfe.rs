@@ -0,0 +1,34 @@
+// compile-flags: -Zmiri-compare-ctfe
+#![feature(const_eval_select, core_intrinsics)]
+use std::intrinsics::const_eval_select;
+
+const fn at_compile_time() -> u32 {
+    1
+}
+fn at_run_time() -> u32 {
+    2
+}
+const fn when() -> u32 {
+    unsafe { const_eval_select((), at_compile_time, at_run_time) }
+}
+const fn scaled(x: u32) -> u32 {
+    x * when()
+}
+const fn incremented(x: u32) -> u32 {
+    x + 1
+}
+
+const SAME: (u8, &str, [u16; 3], Option<&[i8]>) = (1, "miri", [1, 2, 3], Some(&[-1, 0]));
+const DIFFERENT: Option<u32> = Some(when());
+
+fn main() {
+    assert_eq!(SAME.1, "miri");
+    // Constants keep the value computed at compile time, the comparison does not change it.
+    assert_eq!(DIFFERENT, Some(1));
+    // Calls with inputs that are only known at run time are compared too, also when they are
+    // made by other `const fn`s.
+    let x = std::env::args().count() as u32;
+    let y = scaled(x);
+    assert_eq!(y, 2);
+    assert_eq!(incremented(x), 2);
+}
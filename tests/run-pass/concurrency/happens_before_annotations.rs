@@ -0,0 +1,46 @@
+// ignore-windows: Concurrency on Windows is not supported yet.
+//! `miri_release` and `miri_acquire` add happens-before edges that the data race detector cannot
+//! see otherwise: the flag below is only accessed with relaxed atomics.
+
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::thread::{spawn, yield_now};
+
+extern "Rust" {
+    fn miri_release(addr: *const u8);
+    fn miri_acquire(addr: *const u8);
+}
+
+#[derive(Copy, Clone)]
+struct EvilSend<T>(pub T);
+
+unsafe impl<T> Send for EvilSend<T> {}
+unsafe impl<T> Sync for EvilSend<T> {}
+
+static READY: AtomicBool = AtomicBool::new(false);
+
+fn main() {
+    let mut var = 0u32;
+    let ptr = EvilSend(&mut var as *mut u32);
+    let key = &READY as *const AtomicBool as *const u8;
+    let key = EvilSend(key);
+
+    let writer = spawn(move || {
+        unsafe {
+            *ptr.0 = 42;
+            miri_release(key.0);
+        }
+        READY.store(true, Ordering::Relaxed);
+    });
+    let reader = spawn(move || {
+        while !READY.load(Ordering::Relaxed) {
+            yield_now();
+        }
+        unsafe {
+            miri_acquire(key.0);
+            assert_eq!(*ptr.0, 42);
+        }
+    });
+
+    writer.join().unwrap();
+    reader.join().unwrap();
+}
@@ -0,0 +1,69 @@
+// ignore-windows: Uses POSIX APIs
+
+#![feature(rustc_private)]
+extern crate libc;
+
+use std::thread;
+
+fn read(fd: i32, buf: &mut [u8]) -> Result<usize, i32> {
+    let res = unsafe { libc::read(fd, buf.as_mut_ptr().cast(), buf.len()) };
+    if res < 0 {
+        Err(std::io::Error::last_os_error().raw_os_error().unwrap())
+    } else {
+        Ok(res as usize)
+    }
+}
+
+fn write(fd: i32, buf: &[u8]) -> Result<usize, i32> {
+    let res = unsafe { libc::write(fd, buf.as_ptr().cast(), buf.len()) };
+    if res < 0 {
+        Err(std::io::Error::last_os_error().raw_os_error().unwrap())
+    } else {
+        Ok(res as usize)
+    }
+}
+
+fn test_pipe() {
+    let mut fds = [-1, -1];
+    assert_eq!(unsafe { libc::pipe(fds.as_mut_ptr()) }, 0);
+    let [read_end, write_end] = fds;
+
+    // The reader blocks until the other thread writes.
+    let reader = thread::spawn(move || {
+        let mut buf = [0; 5];
+        assert_eq!(read(read_end, &mut buf), Ok(5));
+        assert_eq!(&buf, b"hello");
+        // Once the write end is closed, reading returns the remaining data and then 0.
+        assert_eq!(read(read_end, &mut buf), Ok(1));
+        assert_eq!(read(read_end, &mut buf), Ok(0));
+    });
+    assert_eq!(write(write_end, b"hello!"), Ok(6));
+    // Each end can only be used in one direction.
+    assert_eq!(write(read_end, b"x"), Err(libc::EBADF));
+    assert_eq!(unsafe { libc::close(write_end) }, 0);
+    reader.join().unwrap();
+
+    // Without readers, writing fails.
+    let mut fds = [-1, -1];
+    assert_eq!(unsafe { libc::pipe(fds.as_mut_ptr()) }, 0);
+    assert_eq!(unsafe { libc::close(fds[0]) }, 0);
+    assert_eq!(write(fds[1], b"x"), Err(libc::EPIPE));
+    assert_eq!(unsafe { libc::close(fds[1]) }, 0);
+    assert_eq!(unsafe { libc::close(read_end) }, 0);
+}
+
+#[cfg(target_os = "linux")]
+fn test_pipe2() {
+    let mut fds = [-1, -1];
+    assert_eq!(unsafe { libc::pipe2(fds.as_mut_ptr(), libc::O_NONBLOCK | libc::O_CLOEXEC) }, 0);
+    assert_eq!(read(fds[0], &mut [0; 4]), Err(libc::EAGAIN));
+    assert_eq!(unsafe { libc::fcntl(fds[1], libc::F_GETFD) }, libc::FD_CLOEXEC);
+    assert_eq!(unsafe { libc::pipe2(fds.as_mut_ptr(), libc::O_APPEND) }, -1);
+    assert_eq!(std::io::Error::last_os_error().raw_os_error(), Some(libc::EINVAL));
+}
+
+fn main() {
+    test_pipe();
+    #[cfg(target_os = "linux")]
+    test_pipe2();
+}
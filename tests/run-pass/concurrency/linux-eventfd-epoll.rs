@@ -0,0 +1,376 @@
+// Unfortunately, the test framework does not support 'only-linux',
+// so we need to ignore Windows and macOS instead.
+// ignore-macos: Uses Linux-only APIs
+// ignore-windows: Uses Linux-only APIs
+// compile-flags: -Zmiri-disable-isolation
+
+#![feature(rustc_private, future_poll_fn)]
+extern crate libc;
+
+use std::cell::RefCell;
+use std::collections::VecDeque;
+use std::future::Future;
+use std::pin::Pin;
+use std::sync::{Arc, Mutex};
+use std::task::{Context, Poll, Wake, Waker};
+use std::thread;
+use std::time::{Duration, Instant};
+
+fn read_eventfd(fd: i32) -> Result<u64, i32> {
+    let mut value = 0u64;
+    let res = unsafe { libc::read(fd, &mut value as *mut u64 as *mut libc::c_void, 8) };
+    if res < 0 { Err(std::io::Error::last_os_error().raw_os_error().unwrap()) } else { Ok(value) }
+}
+
+fn write_eventfd(fd: i32, value: u64) {
+    let res = unsafe { libc::write(fd, &value as *const u64 as *const libc::c_void, 8) };
+    assert_eq!(res, 8);
+}
+
+/// Waits for events on `epfd`, returning the `u64` data of the ready file descriptors.
+fn epoll_wait(epfd: i32, timeout: i32) -> Vec<u64> {
+    let mut events = [libc::epoll_event { events: 0, u64: 0 }; 4];
+    let n = unsafe { libc::epoll_wait(epfd, events.as_mut_ptr(), 4, timeout) };
+    assert!(n >= 0);
+    events[..n as usize].iter().map(|event| event.u64).collect()
+}
+
+fn epoll_add(epfd: i32, fd: i32, events: i32, data: u64) {
+    let mut event = libc::epoll_event { events: events as u32, u64: data };
+    assert_eq!(unsafe { libc::epoll_ctl(epfd, libc::EPOLL_CTL_ADD, fd, &mut event) }, 0);
+}
+
+fn test_eventfd() {
+    let fd = unsafe { libc::eventfd(3, libc::EFD_NONBLOCK | libc::EFD_CLOEXEC) };
+    assert!(fd >= 0);
+    assert_eq!(unsafe { libc::fcntl(fd, libc::F_GETFD) }, libc::FD_CLOEXEC);
+    assert_eq!(read_eventfd(fd), Ok(3));
+    assert_eq!(read_eventfd(fd), Err(libc::EAGAIN));
+    write_eventfd(fd, 2);
+    write_eventfd(fd, 5);
+    assert_eq!(read_eventfd(fd), Ok(7));
+
+    // Buffers that are too small are rejected.
+    let mut small = [0u8; 4];
+    let res = unsafe { libc::read(fd, small.as_mut_ptr() as *mut libc::c_void, 4) };
+    assert_eq!(res, -1);
+    assert_eq!(std::io::Error::last_os_error().raw_os_error(), Some(libc::EINVAL));
+    assert_eq!(unsafe { libc::close(fd) }, 0);
+
+    // In semaphore mode, every read decrements the counter by one.
+    let fd = unsafe { libc::eventfd(2, libc::EFD_NONBLOCK | libc::EFD_SEMAPHORE) };
+    assert_eq!(unsafe { libc::fcntl(fd, libc::F_GETFD) }, 0);
+    assert_eq!(read_eventfd(fd), Ok(1));
+    assert_eq!(read_eventfd(fd), Ok(1));
+    assert_eq!(read_eventfd(fd), Err(libc::EAGAIN));
+    assert_eq!(unsafe { libc::close(fd) }, 0);
+}
+
+fn test_blocking_eventfd() {
+    let fd = unsafe { libc::eventfd(0, 0) };
+    assert!(fd >= 0);
+
+    // A read of a zero counter blocks until another thread writes.
+    let writer = thread::spawn(move || {
+        thread::sleep(Duration::from_millis(10));
+        write_eventfd(fd, 3);
+    });
+    assert_eq!(read_eventfd(fd), Ok(3));
+    writer.join().unwrap();
+
+    // A write that would overflow the counter blocks until another thread reads it.
+    write_eventfd(fd, u64::MAX - 2);
+    let reader = thread::spawn(move || {
+        thread::sleep(Duration::from_millis(10));
+        read_eventfd(fd)
+    });
+    write_eventfd(fd, 2);
+    assert_eq!(reader.join().unwrap(), Ok(u64::MAX - 2));
+    assert_eq!(read_eventfd(fd), Ok(2));
+    assert_eq!(unsafe { libc::close(fd) }, 0);
+}
+
+fn test_epoll() {
+    let epfd = unsafe { libc::epoll_create1(libc::EPOLL_CLOEXEC) };
+    assert!(epfd >= 0);
+    let fd1 = unsafe { libc::eventfd(0, libc::EFD_NONBLOCK) };
+    let fd2 = unsafe { libc::eventfd(0, libc::EFD_NONBLOCK) };
+    epoll_add(epfd, fd1, libc::EPOLLIN, 1);
+    epoll_add(epfd, fd2, libc::EPOLLIN | libc::EPOLLONESHOT, 2);
+
+    // Registering a file descriptor twice fails.
+    let mut event = libc::epoll_event { events: libc::EPOLLIN as u32, u64: 1 };
+    assert_eq!(unsafe { libc::epoll_ctl(epfd, libc::EPOLL_CTL_ADD, fd1, &mut event) }, -1);
+    assert_eq!(std::io::Error::last_os_error().raw_os_error(), Some(libc::EEXIST));
+
+    assert_eq!(epoll_wait(epfd, 0), Vec::<u64>::new());
+    write_eventfd(fd1, 1);
+    write_eventfd(fd2, 1);
+    assert_eq!(epoll_wait(epfd, 0), vec![1, 2]);
+    // `fd1` stays ready until it is read, while `fd2` is only reported once.
+    assert_eq!(epoll_wait(epfd, 0), vec![1]);
+    assert_eq!(read_eventfd(fd1), Ok(1));
+    assert_eq!(epoll_wait(epfd, 0), Vec::<u64>::new());
+
+    // Rearming `fd2` reports it again.
+    let mut event = libc::epoll_event { events: libc::EPOLLIN as u32, u64: 2 };
+    assert_eq!(unsafe { libc::epoll_ctl(epfd, libc::EPOLL_CTL_MOD, fd2, &mut event) }, 0);
+    assert_eq!(epoll_wait(epfd, 0), vec![2]);
+    let null = std::ptr::null_mut();
+    assert_eq!(unsafe { libc::epoll_ctl(epfd, libc::EPOLL_CTL_DEL, fd2, null) }, 0);
+    assert_eq!(epoll_wait(epfd, 0), Vec::<u64>::new());
+
+    // Waiting times out when nothing becomes ready.
+    let start = Instant::now();
+    assert_eq!(epoll_wait(epfd, 100), Vec::<u64>::new());
+    assert!(start.elapsed() >= Duration::from_millis(100));
+
+    // A blocked thread is woken up by a write from another thread.
+    let writer = thread::spawn(move || {
+        thread::sleep(Duration::from_millis(10));
+        write_eventfd(fd1, 1);
+    });
+    assert_eq!(epoll_wait(epfd, -1), vec![1]);
+    writer.join().unwrap();
+
+    for fd in [fd1, fd2, epfd] {
+        assert_eq!(unsafe { libc::close(fd) }, 0);
+    }
+}
+
+fn test_epoll_close() {
+    let epfd = unsafe { libc::epoll_create1(0) };
+    let fd = unsafe { libc::eventfd(1, libc::EFD_NONBLOCK) };
+    epoll_add(epfd, fd, libc::EPOLLIN, 1);
+    assert_eq!(epoll_wait(epfd, 0), vec![1]);
+
+    // Closing a file descriptor removes it from the interest list, so a new file descriptor that
+    // gets the same number is not registered.
+    assert_eq!(unsafe { libc::close(fd) }, 0);
+    assert_eq!(epoll_wait(epfd, 0), Vec::<u64>::new());
+    let fd = unsafe { libc::eventfd(1, libc::EFD_NONBLOCK) };
+    assert_eq!(epoll_wait(epfd, 0), Vec::<u64>::new());
+    epoll_add(epfd, fd, libc::EPOLLOUT, 2);
+    // An eventfd is writable as long as its counter can be increased.
+    assert_eq!(epoll_wait(epfd, 0), vec![2]);
+
+    for fd in [fd, epfd] {
+        assert_eq!(unsafe { libc::close(fd) }, 0);
+    }
+}
+
+/// A waker that signals the eventfd the executor waits on.
+struct EventFdWaker(i32);
+
+impl Wake for EventFdWaker {
+    fn wake(self: Arc<Self>) {
+        write_eventfd(self.0, 1);
+    }
+}
+
+/// A minimal single-threaded executor, which parks in `epoll_wait` like the executors of async
+/// runtimes do.
+fn block_on<F: Future>(future: F) -> F::Output {
+    let mut future = Box::pin(future);
+    let epfd = unsafe { libc::epoll_create1(0) };
+    let eventfd = unsafe { libc::eventfd(0, libc::EFD_NONBLOCK) };
+    epoll_add(epfd, eventfd, libc::EPOLLIN | libc::EPOLLET, 0);
+    let waker = Waker::from(Arc::new(EventFdWaker(eventfd)));
+    let mut cx = Context::from_waker(&waker);
+    let output = loop {
+        if let Poll::Ready(output) = future.as_mut().poll(&mut cx) {
+            break output;
+        }
+        assert_eq!(epoll_wait(epfd, -1), vec![0]);
+        read_eventfd(eventfd).unwrap();
+    };
+    unsafe {
+        libc::close(eventfd);
+        libc::close(epfd);
+    }
+    output
+}
+
+/// A future that completes after `duration`, driven by a timer thread.
+struct Sleep {
+    duration: Duration,
+    done: Arc<Mutex<bool>>,
+    started: bool,
+}
+
+fn sleep(duration: Duration) -> Sleep {
+    Sleep { duration, done: Arc::new(Mutex::new(false)), started: false }
+}
+
+impl Future for Sleep {
+    type Output = ();
+
+    fn poll(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<()> {
+        if *self.done.lock().unwrap() {
+            return Poll::Ready(());
+        }
+        if !self.started {
+            self.started = true;
+            let (duration, done, waker) = (self.duration, self.done.clone(), cx.waker().clone());
+            thread::spawn(move || {
+                thread::sleep(duration);
+                *done.lock().unwrap() = true;
+                waker.wake();
+            });
+        }
+        Poll::Pending
+    }
+}
+
+/// An unbounded channel whose receiver is a future.
+struct Channel<T> {
+    queue: VecDeque<T>,
+    waker: Option<Waker>,
+}
+
+fn channel<T>() -> Arc<Mutex<Channel<T>>> {
+    Arc::new(Mutex::new(Channel { queue: VecDeque::new(), waker: None }))
+}
+
+fn send<T>(channel: &Mutex<Channel<T>>, value: T) {
+    let mut channel = channel.lock().unwrap();
+    channel.queue.push_back(value);
+    if let Some(waker) = channel.waker.take() {
+        waker.wake();
+    }
+}
+
+async fn recv<T>(channel: &Mutex<Channel<T>>) -> T {
+    std::future::poll_fn(|cx| {
+        let mut channel = channel.lock().unwrap();
+        match channel.queue.pop_front() {
+            Some(value) => Poll::Ready(value),
+            None => {
+                channel.waker = Some(cx.waker().clone());
+                Poll::Pending
+            }
+        }
+    })
+    .await
+}
+
+thread_local! {
+    /// The deadlines of the pending timers of `block_on_reactor`.
+    static TIMERS: RefCell<Vec<Instant>> = RefCell::new(vec![]);
+}
+
+/// An executor with a reactor like the one of tokio: timers do not have threads of their own, but
+/// bound how long the executor waits in `epoll_wait`.
+fn block_on_reactor<F: Future>(future: F) -> F::Output {
+    let mut future = Box::pin(future);
+    let epfd = unsafe { libc::epoll_create1(0) };
+    let eventfd = unsafe { libc::eventfd(0, libc::EFD_NONBLOCK) };
+    epoll_add(epfd, eventfd, libc::EPOLLIN, 0);
+    let waker = Waker::from(Arc::new(EventFdWaker(eventfd)));
+    let mut cx = Context::from_waker(&waker);
+    let output = loop {
+        // Pending timers register themselves again whenever they are polled.
+        TIMERS.with(|timers| timers.borrow_mut().clear());
+        if let Poll::Ready(output) = future.as_mut().poll(&mut cx) {
+            break output;
+        }
+        let deadline = TIMERS.with(|timers| timers.borrow().iter().min().copied());
+        let timeout = match deadline {
+            Some(deadline) => {
+                let micros = deadline.saturating_duration_since(Instant::now()).as_micros();
+                i32::try_from((micros + 999) / 1000).unwrap()
+            }
+            None => -1,
+        };
+        if epoll_wait(epfd, timeout) == vec![0] {
+            read_eventfd(eventfd).unwrap();
+        }
+    };
+    unsafe {
+        libc::close(eventfd);
+        libc::close(epfd);
+    }
+    output
+}
+
+/// A future that completes at `deadline`, driven by the reactor of `block_on_reactor`.
+struct Delay {
+    deadline: Instant,
+}
+
+fn delay(duration: Duration) -> Delay {
+    Delay { deadline: Instant::now() + duration }
+}
+
+impl Future for Delay {
+    type Output = ();
+
+    fn poll(self: Pin<&mut Self>, _cx: &mut Context<'_>) -> Poll<()> {
+        if Instant::now() >= self.deadline {
+            return Poll::Ready(());
+        }
+        TIMERS.with(|timers| timers.borrow_mut().push(self.deadline));
+        Poll::Pending
+    }
+}
+
+fn test_reactor() {
+    let start = Instant::now();
+    let channel = channel();
+    let sender = {
+        let channel = channel.clone();
+        thread::spawn(move || {
+            thread::sleep(Duration::from_millis(50));
+            send(&channel, 1);
+        })
+    };
+    let received = block_on_reactor(async {
+        // The timer fires before the message arrives, and the next one after it.
+        delay(Duration::from_millis(10)).await;
+        let received = recv(&channel).await;
+        delay(Duration::from_millis(20)).await;
+        received
+    });
+    assert_eq!(received, 1);
+    assert!(start.elapsed() >= Duration::from_millis(70));
+    sender.join().unwrap();
+}
+
+fn test_executor() {
+    // Timers do not actually wait, but time still passes for the program.
+    let start = Instant::now();
+    block_on(async {
+        sleep(Duration::from_secs(1)).await;
+        sleep(Duration::from_secs(2)).await;
+    });
+    assert!(start.elapsed() >= Duration::from_secs(3));
+
+    let channel = channel();
+    let sender = {
+        let channel = channel.clone();
+        thread::spawn(move || {
+            for i in 0..3 {
+                thread::sleep(Duration::from_millis(10));
+                send(&channel, i);
+            }
+        })
+    };
+    let received = block_on(async {
+        let mut received = vec![];
+        for _ in 0..3 {
+            received.push(recv(&channel).await);
+        }
+        received
+    });
+    assert_eq!(received, [0, 1, 2]);
+    sender.join().unwrap();
+}
+
+fn main() {
+    test_eventfd();
+    test_blocking_eventfd();
+    test_epoll();
+    test_epoll_close();
+    test_executor();
+    test_reactor();
+}
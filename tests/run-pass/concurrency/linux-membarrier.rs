@@ -0,0 +1,75 @@
+// Unfortunately, the test framework does not support 'only-linux',
+// so we need to ignore Windows and macOS instead.
+// ignore-macos: Uses Linux-only APIs
+// ignore-windows: Uses Linux-only APIs
+
+#![feature(rustc_private)]
+extern crate libc;
+
+use std::io::Error;
+use std::sync::atomic::{compiler_fence, AtomicBool, Ordering};
+use std::thread;
+
+const MEMBARRIER_CMD_QUERY: libc::c_int = 0;
+const MEMBARRIER_CMD_GLOBAL: libc::c_int = 1 << 0;
+const MEMBARRIER_CMD_PRIVATE_EXPEDITED: libc::c_int = 1 << 3;
+const MEMBARRIER_CMD_REGISTER_PRIVATE_EXPEDITED: libc::c_int = 1 << 4;
+
+fn membarrier(cmd: libc::c_int, flags: libc::c_uint) -> libc::c_long {
+    unsafe { libc::syscall(libc::SYS_membarrier, cmd, flags) }
+}
+
+/// The slow side of an asymmetric fence: a `membarrier` synchronizes with the other threads, so
+/// the non-atomic accesses below are not data races even though the flags are only accessed with
+/// relaxed atomics.
+fn synchronizes_with_running_threads() {
+    static mut WRITTEN_BY_THREAD: u32 = 0;
+    static mut WRITTEN_BY_MAIN: u32 = 0;
+    static THREAD_WROTE: AtomicBool = AtomicBool::new(false);
+    static MAIN_WROTE: AtomicBool = AtomicBool::new(false);
+
+    let t = thread::spawn(|| {
+        unsafe { WRITTEN_BY_THREAD = 1 };
+        THREAD_WROTE.store(true, Ordering::Relaxed);
+        // The thread has to keep running until the barrier, which then synchronizes it with the
+        // main thread.
+        while !MAIN_WROTE.load(Ordering::Relaxed) {
+            thread::yield_now();
+        }
+        unsafe { WRITTEN_BY_MAIN }
+    });
+    while !THREAD_WROTE.load(Ordering::Relaxed) {
+        thread::yield_now();
+    }
+    unsafe { WRITTEN_BY_MAIN = 2 };
+    assert_eq!(membarrier(MEMBARRIER_CMD_GLOBAL, 0), 0);
+    assert_eq!(unsafe { WRITTEN_BY_THREAD }, 1);
+    MAIN_WROTE.store(true, Ordering::Relaxed);
+    assert_eq!(t.join().unwrap(), 2);
+}
+
+fn main() {
+    let supported = membarrier(MEMBARRIER_CMD_QUERY, 0);
+    assert!(supported & libc::c_long::from(MEMBARRIER_CMD_PRIVATE_EXPEDITED) != 0);
+
+    // Private expedited barriers need to be registered first.
+    assert_eq!(membarrier(MEMBARRIER_CMD_PRIVATE_EXPEDITED, 0), -1);
+    assert_eq!(Error::last_os_error().raw_os_error(), Some(libc::EPERM));
+    assert_eq!(membarrier(MEMBARRIER_CMD_REGISTER_PRIVATE_EXPEDITED, 0), 0);
+
+    assert_eq!(membarrier(MEMBARRIER_CMD_GLOBAL, 1), -1);
+    assert_eq!(Error::last_os_error().raw_os_error(), Some(libc::EINVAL));
+
+    // The fast side of an asymmetric fence only uses a compiler fence.
+    static FLAG: AtomicBool = AtomicBool::new(false);
+    let t = thread::spawn(|| {
+        FLAG.store(true, Ordering::Relaxed);
+        compiler_fence(Ordering::SeqCst);
+    });
+    assert_eq!(membarrier(MEMBARRIER_CMD_PRIVATE_EXPEDITED, 0), 0);
+    assert_eq!(membarrier(MEMBARRIER_CMD_GLOBAL, 0), 0);
+    t.join().unwrap();
+    assert!(FLAG.load(Ordering::Relaxed));
+
+    synchronizes_with_running_threads();
+}
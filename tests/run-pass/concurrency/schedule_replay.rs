@@ -0,0 +1,34 @@
+// ignore-windows: Concurrency on Windows is not supported yet.
+// compile-flags: -Zmiri-scheduler=random -Zmiri-seed=5ca1ab1e
+
+// The order in which the threads get the lock depends on the schedule. `tests/compiletest.rs`
+// also runs this with the argument `abort`, first recording the schedule and then replaying it,
+// and checks that both runs print the same order.
+
+use std::sync::{Arc, Mutex};
+use std::thread;
+
+fn main() {
+    let order = Arc::new(Mutex::new(Vec::new()));
+    let handles: Vec<_> = (0..3)
+        .map(|i| {
+            let order = Arc::clone(&order);
+            thread::spawn(move || {
+                for _ in 0..5 {
+                    order.lock().unwrap().push(i);
+                    thread::yield_now();
+                }
+            })
+        })
+        .collect();
+    for handle in handles {
+        handle.join().unwrap();
+    }
+    let order = order.lock().unwrap();
+    assert_eq!(order.len(), 15);
+    if std::env::args().nth(1).as_deref() == Some("abort") {
+        // Recording only happens when the program fails.
+        println!("{:?}", *order);
+        std::process::abort();
+    }
+}
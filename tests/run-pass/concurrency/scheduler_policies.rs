@@ -0,0 +1,35 @@
+// ignore-windows: Concurrency on Windows is not supported yet.
+
+// Prints how often the two threads took turns. With the default cooperative scheduler, the first
+// thread runs until it terminates, so they only take turns once. `tests/compiletest.rs` also runs
+// this with the other scheduling policies.
+
+use std::hint::black_box;
+use std::sync::{Arc, Mutex};
+use std::thread;
+
+fn main() {
+    let log = Arc::new(Mutex::new(Vec::new()));
+    let handles: Vec<_> = (0..2)
+        .map(|i| {
+            let log = Arc::clone(&log);
+            thread::spawn(move || {
+                for _ in 0..20 {
+                    // Enough work that the round-robin scheduler switches threads in between.
+                    let mut sum = 0u64;
+                    for j in 0..100 {
+                        sum = black_box(sum + j);
+                    }
+                    log.lock().unwrap().push((i, sum));
+                }
+            })
+        })
+        .collect();
+    for handle in handles {
+        handle.join().unwrap();
+    }
+    let log = log.lock().unwrap();
+    assert_eq!(log.len(), 40);
+    let turns = log.windows(2).filter(|pair| pair[0].0 != pair[1].0).count();
+    println!("{}", turns);
+}
@@ -0,0 +1,25 @@
+// ignore-windows: Concurrency on Windows is not supported yet.
+// compile-flags: -Zmiri-scheduler=random
+
+// Each thread allocates the same way however the threads are interleaved. `tests/compiletest.rs`
+// also runs this with different seeds and `-Zmiri-print-alloc-ids`, and checks that the stable
+// allocation IDs are the same.
+
+use std::thread;
+
+fn main() {
+    let handles: Vec<_> = (0..3)
+        .map(|i| {
+            thread::spawn(move || {
+                let mut values = Vec::new();
+                for j in 0..4 {
+                    values.push(Box::new(i * j));
+                    thread::yield_now();
+                }
+                values.iter().map(|value| **value).sum::<i32>()
+            })
+        })
+        .collect();
+    let sums: Vec<i32> = handles.into_iter().map(|handle| handle.join().unwrap()).collect();
+    assert_eq!(sums, [0, 6, 12]);
+}
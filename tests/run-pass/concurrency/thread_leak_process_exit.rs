@@ -0,0 +1,17 @@
+// ignore-windows: Concurrency on Windows is not supported yet.
+// compile-flags: -Zmiri-thread-leaks=allow
+
+//! Test that threads which are still running when `main` returns are killed like on a real
+//! target: the memory they use is not reported as leaked.
+
+use std::thread;
+
+fn main() {
+    thread::spawn(|| {
+        let _data = Box::new([0u8; 16]);
+        loop {
+            thread::yield_now();
+        }
+    });
+    thread::yield_now();
+}
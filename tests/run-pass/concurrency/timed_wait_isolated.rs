@@ -0,0 +1,125 @@
+// Unfortunately, the test framework does not support 'only-linux',
+// so we need to ignore Windows and macOS instead.
+// ignore-macos: `Condvar::wait_timeout` uses the system time on macOS
+// ignore-windows: Concurrency on Windows is not supported yet.
+
+//! Checks timeouts against the virtual clock that Miri uses with isolation. That clock only moves
+//! when all threads are blocked, by jumping to the earliest deadline, so the waits take exactly as
+//! long as their timeouts.
+
+use std::ops::Range;
+use std::sync::{Arc, Condvar, Mutex};
+use std::thread;
+use std::time::{Duration, Instant};
+
+/// The time that a wait of `millis` milliseconds takes on the virtual clock. Every read of the
+/// clock advances it by a nanosecond, hence the small slack.
+fn around(millis: u64) -> Range<Duration> {
+    Duration::from_millis(millis)..Duration::from_millis(millis) + Duration::from_micros(1)
+}
+
+fn condvar_wait_timeout() {
+    let lock = Mutex::new(());
+    let cvar = Condvar::new();
+    let start = Instant::now();
+    let (_guard, result) =
+        cvar.wait_timeout(lock.lock().unwrap(), Duration::from_millis(100)).unwrap();
+    assert!(result.timed_out());
+    assert!(around(100).contains(&start.elapsed()));
+}
+
+fn condvar_notify_before_timeout() {
+    let pair = Arc::new((Mutex::new(()), Condvar::new()));
+    let pair2 = pair.clone();
+    let (lock, cvar) = &*pair;
+    let guard = lock.lock().unwrap();
+    let start = Instant::now();
+    let handle = thread::spawn(move || {
+        let (lock, cvar) = &*pair2;
+        // Only notify once the main thread waits and thus released the lock.
+        drop(lock.lock().unwrap());
+        cvar.notify_one();
+    });
+    let (_guard, result) = cvar.wait_timeout(guard, Duration::from_secs(10)).unwrap();
+    assert!(!result.timed_out());
+    // No thread had to wait for a timeout, so the clock did not jump.
+    assert!(start.elapsed() < Duration::from_millis(1));
+    handle.join().unwrap();
+}
+
+/// A notification that does not make the condition true looks like a spurious wakeup to
+/// `wait_timeout_while`, which keeps waiting until the original deadline.
+fn condvar_wait_timeout_while() {
+    let pair = Arc::new((Mutex::new(false), Condvar::new()));
+    let pair2 = pair.clone();
+    let (lock, cvar) = &*pair;
+    let start = Instant::now();
+    let handle = thread::spawn(move || {
+        let (lock, cvar) = &*pair2;
+        drop(lock.lock().unwrap());
+        cvar.notify_one();
+        thread::sleep(Duration::from_millis(50));
+        *lock.lock().unwrap() = true;
+        cvar.notify_one();
+    });
+    let (ready, result) = cvar
+        .wait_timeout_while(lock.lock().unwrap(), Duration::from_millis(100), |ready| !*ready)
+        .unwrap();
+    assert!(*ready && !result.timed_out());
+    assert!(around(50).contains(&start.elapsed()));
+    drop(ready);
+    handle.join().unwrap();
+
+    let pair = Arc::new((Mutex::new(false), Condvar::new()));
+    let pair2 = pair.clone();
+    let (lock, cvar) = &*pair;
+    let start = Instant::now();
+    let handle = thread::spawn(move || {
+        let (lock, cvar) = &*pair2;
+        drop(lock.lock().unwrap());
+        cvar.notify_one();
+    });
+    let (ready, result) = cvar
+        .wait_timeout_while(lock.lock().unwrap(), Duration::from_millis(100), |ready| !*ready)
+        .unwrap();
+    assert!(!*ready && result.timed_out());
+    assert!(around(100).contains(&start.elapsed()));
+    drop(ready);
+    handle.join().unwrap();
+}
+
+fn park_timeout() {
+    let start = Instant::now();
+    thread::park_timeout(Duration::from_millis(200));
+    assert!(around(200).contains(&start.elapsed()));
+}
+
+/// A token that is available before `park_timeout` is called makes it return immediately,
+/// and an `unpark` while waiting wakes the thread up before the timeout.
+fn park_timeout_unpark() {
+    let start = Instant::now();
+    thread::current().unpark();
+    thread::park_timeout(Duration::from_secs(10));
+    assert!(start.elapsed() < Duration::from_millis(1));
+
+    let main = thread::current();
+    let start = Instant::now();
+    let t = thread::spawn(move || {
+        thread::park_timeout(Duration::from_secs(10));
+        main.unpark();
+    });
+    thread::sleep(Duration::from_millis(100));
+    t.thread().unpark();
+    // Wait for the other thread to be woken up.
+    thread::park();
+    t.join().unwrap();
+    assert!(around(100).contains(&start.elapsed()));
+}
+
+fn main() {
+    condvar_wait_timeout();
+    condvar_notify_before_timeout();
+    condvar_wait_timeout_while();
+    park_timeout();
+    park_timeout_unpark();
+}
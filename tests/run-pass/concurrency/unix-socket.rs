@@ -0,0 +1,71 @@
+// ignore-windows: Uses POSIX APIs
+
+use std::io::{ErrorKind, Read, Write};
+use std::net::Shutdown;
+use std::os::unix::net::{UnixListener, UnixStream};
+use std::thread;
+
+fn test_pair() {
+    let (mut a, mut b) = UnixStream::pair().unwrap();
+    // The reader blocks until the other thread writes.
+    let reader = thread::spawn(move || {
+        let mut buf = [0; 5];
+        b.read_exact(&mut buf).unwrap();
+        assert_eq!(&buf, b"hello");
+        b.write_all(b"world").unwrap();
+        b
+    });
+    a.write_all(b"hello").unwrap();
+    let mut buf = [0; 5];
+    a.read_exact(&mut buf).unwrap();
+    assert_eq!(&buf, b"world");
+
+    // Once the peer is gone, reading returns the end of the data, and writing fails.
+    drop(reader.join().unwrap());
+    assert_eq!(a.read(&mut buf).unwrap(), 0);
+    assert_eq!(a.write(b"x").unwrap_err().kind(), ErrorKind::BrokenPipe);
+}
+
+fn test_listener() {
+    let path = "/tmp/miri-unix-socket";
+    let listener = UnixListener::bind(path).unwrap();
+    assert_eq!(UnixListener::bind(path).unwrap_err().kind(), ErrorKind::AddrInUse);
+
+    // The listener blocks until a connection was made.
+    let server = thread::spawn(move || {
+        let (mut stream, addr) = listener.accept().unwrap();
+        assert!(addr.is_unnamed());
+        let mut received = String::new();
+        stream.read_to_string(&mut received).unwrap();
+        assert_eq!(received, "ping");
+        stream.write_all(b"pong").unwrap();
+    });
+    let mut stream = UnixStream::connect(path).unwrap();
+    stream.write_all(b"ping").unwrap();
+    stream.shutdown(Shutdown::Write).unwrap();
+    let mut received = String::new();
+    stream.read_to_string(&mut received).unwrap();
+    assert_eq!(received, "pong");
+    server.join().unwrap();
+
+    // The address is free again once the listener is closed.
+    assert_eq!(UnixStream::connect(path).unwrap_err().kind(), ErrorKind::ConnectionRefused);
+    drop(UnixListener::bind(path).unwrap());
+    assert_eq!(UnixStream::connect("/tmp/miri-no-socket").unwrap_err().kind(), ErrorKind::NotFound);
+}
+
+fn test_nonblocking() {
+    let listener = UnixListener::bind("/tmp/miri-unix-socket-nonblocking").unwrap();
+    listener.set_nonblocking(true).unwrap();
+    assert_eq!(listener.accept().unwrap_err().kind(), ErrorKind::WouldBlock);
+
+    let (mut a, _b) = UnixStream::pair().unwrap();
+    a.set_nonblocking(true).unwrap();
+    assert_eq!(a.read(&mut [0; 4]).unwrap_err().kind(), ErrorKind::WouldBlock);
+}
+
+fn main() {
+    test_pair();
+    test_listener();
+    test_nonblocking();
+}
@@ -0,0 +1,17 @@
+// compile-flags: -Zmiri-seed=2a -Zmiri-tag-raw-pointers
+
+extern "Rust" {
+    fn miri_get_seed() -> u64;
+    fn miri_isolation_enabled() -> bool;
+    fn miri_aliasing_model() -> u8;
+    fn miri_strict_provenance() -> bool;
+}
+
+fn main() {
+    unsafe {
+        assert_eq!(miri_get_seed(), 0x2a);
+        assert!(miri_isolation_enabled());
+        assert_eq!(miri_aliasing_model(), 2);
+        assert!(!miri_strict_provenance());
+    }
+}
@@ -0,0 +1,21 @@
+// normalize-stdout-test "0x[0-9a-f]+" -> "$$ADDR"
+
+// `tests/compiletest.rs` also runs this in the debugger, which stops on the line marked below,
+// inspects the state of the program and steps through it. The debugger reads `bytes` and the
+// borrow stacks of `bytes` while it is mutably borrowed, which must not affect the program.
+
+fn sum(bytes: &mut [u8; 4]) -> u32 {
+    let mut total = 0;
+    for b in bytes.iter() {
+        total += u32::from(*b);
+    }
+    bytes[0] = 0; // The debugger stops here.
+    total
+}
+
+fn main() {
+    let mut bytes = [1, 2, 3, 4];
+    println!("{:p}", &bytes);
+    assert_eq!(sum(&mut bytes), 10);
+    assert_eq!(bytes, [0, 2, 3, 4]);
+}
@@ -0,0 +1,18 @@
+// Programs can declare that they are expected to stop with Undefined Behavior.
+
+extern "Rust" {
+    fn miri_expect_error(pattern: *const u8, len: usize);
+}
+
+fn expect_error(pattern: &str) {
+    unsafe { miri_expect_error(pattern.as_ptr(), pattern.len()) }
+}
+
+fn main() {
+    expect_error("dereferenced after this allocation got freed");
+    let b = Box::new(42);
+    let ptr = &*b as *const i32;
+    drop(b);
+    let _val = unsafe { *ptr };
+    unreachable!();
+}
@@ -0,0 +1,19 @@
+// ignore-windows: `thread::sleep` with a timeout is not supported on Windows
+// compile-flags: -Zmiri-disable-isolation -Zmiri-freeze-time=1600000000
+use std::time::{Duration, Instant, SystemTime, UNIX_EPOCH};
+
+fn main() {
+    let now = SystemTime::now();
+    assert_eq!(now.duration_since(UNIX_EPOCH).unwrap(), Duration::from_secs(1_600_000_000));
+
+    let start = Instant::now();
+    // Sleeping returns immediately and does not advance time.
+    std::thread::sleep(Duration::from_secs(3600));
+    assert_eq!(start.elapsed(), Duration::ZERO);
+    assert_eq!(SystemTime::now(), now);
+
+    // Timeouts of other threads expire once the main thread is blocked joining them.
+    let handle = std::thread::spawn(|| std::thread::sleep(Duration::from_secs(60)));
+    handle.join().unwrap();
+    assert_eq!(Instant::now(), start);
+}
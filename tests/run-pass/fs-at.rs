@@ -0,0 +1,105 @@
+// ignore-windows: File handling is not implemented yet
+// compile-flags: -Zmiri-disable-isolation
+
+#![feature(rustc_private)]
+extern crate libc;
+
+use std::ffi::{CStr, CString};
+use std::fs::{create_dir, read, read_link, remove_dir_all, File};
+use std::io::{Error, Write};
+use std::os::unix::ffi::OsStrExt;
+use std::path::{Path, PathBuf};
+
+extern "Rust" {
+    fn miri_host_to_target_path(path: *const u8, out: *mut u8, out_size: usize) -> usize;
+}
+
+fn host_to_target_path(path: String) -> PathBuf {
+    let path = CString::new(path).unwrap();
+    let mut out = Vec::with_capacity(1024);
+    unsafe {
+        let ret = miri_host_to_target_path(path.as_ptr().cast(), out.as_mut_ptr(), out.capacity());
+        assert_eq!(ret, 0);
+        let out = CStr::from_ptr(out.as_ptr().cast());
+        PathBuf::from(out.to_str().unwrap())
+    }
+}
+
+fn tmp() -> PathBuf {
+    std::env::var("MIRI_TEMP").map(host_to_target_path).unwrap_or_else(|_| std::env::temp_dir())
+}
+
+fn errno() -> i32 {
+    Error::last_os_error().raw_os_error().unwrap()
+}
+
+fn c_path(path: &Path) -> CString {
+    CString::new(path.as_os_str().as_bytes()).unwrap()
+}
+
+fn main() {
+    let dir_path = tmp().join("miri_test_fs_at");
+    remove_dir_all(&dir_path).ok();
+    create_dir(&dir_path).unwrap();
+    let dir_name = c_path(&dir_path);
+    let dirfd = unsafe { libc::open(dir_name.as_ptr(), libc::O_RDONLY | libc::O_DIRECTORY) };
+    assert!(dirfd >= 0);
+
+    // Create a file relative to the directory.
+    let name = CString::new("file.txt").unwrap();
+    let flags = libc::O_WRONLY | libc::O_CREAT | libc::O_CLOEXEC;
+    let fd = unsafe { libc::openat(dirfd, name.as_ptr(), flags, 0o666) };
+    assert!(fd >= 0);
+    let bytes = b"Hello, World!";
+    let written = unsafe { libc::write(fd, bytes.as_ptr().cast(), bytes.len()) };
+    assert_eq!(written, bytes.len() as isize);
+    assert_eq!(unsafe { libc::close(fd) }, 0);
+    assert_eq!(read(dir_path.join("file.txt")).unwrap(), bytes);
+
+    // Directories, symbolic links, and renaming.
+    let subdir = CString::new("subdir").unwrap();
+    assert_eq!(unsafe { libc::mkdirat(dirfd, subdir.as_ptr(), 0o755) }, 0);
+    assert!(dir_path.join("subdir").is_dir());
+    let link = CString::new("subdir/link").unwrap();
+    assert_eq!(unsafe { libc::symlinkat(name.as_ptr(), dirfd, link.as_ptr()) }, 0);
+    assert_eq!(read_link(dir_path.join("subdir/link")).unwrap(), Path::new("file.txt"));
+    let mut buf = [0u8; 32];
+    let len = unsafe { libc::readlinkat(dirfd, link.as_ptr(), buf.as_mut_ptr().cast(), 32) };
+    assert_eq!(&buf[..len as usize], b"file.txt");
+    let new_name = CString::new("subdir/renamed.txt").unwrap();
+    let res = unsafe { libc::renameat(dirfd, name.as_ptr(), dirfd, new_name.as_ptr()) };
+    assert_eq!(res, 0);
+    assert_eq!(read(dir_path.join("subdir/renamed.txt")).unwrap(), bytes);
+
+    // Absolute paths ignore the directory, and `AT_FDCWD` is the working directory.
+    let absolute = c_path(&dir_path.join("absolute"));
+    assert_eq!(unsafe { libc::mkdirat(dirfd, absolute.as_ptr(), 0o755) }, 0);
+    assert_eq!(unsafe { libc::unlinkat(libc::AT_FDCWD, absolute.as_ptr(), libc::AT_REMOVEDIR) }, 0);
+
+    // Removing needs `AT_REMOVEDIR` exactly for directories.
+    assert_eq!(unsafe { libc::unlinkat(dirfd, subdir.as_ptr(), 0) }, -1);
+    assert_eq!(unsafe { libc::unlinkat(dirfd, new_name.as_ptr(), libc::AT_REMOVEDIR) }, -1);
+    assert_eq!(errno(), libc::ENOTDIR);
+    assert_eq!(unsafe { libc::unlinkat(dirfd, new_name.as_ptr(), 0) }, 0);
+    assert_eq!(unsafe { libc::unlinkat(dirfd, link.as_ptr(), 0) }, 0);
+    assert_eq!(unsafe { libc::unlinkat(dirfd, subdir.as_ptr(), libc::AT_REMOVEDIR) }, 0);
+    assert!(!dir_path.join("subdir").exists());
+
+    // Invalid directory file descriptors.
+    assert_eq!(unsafe { libc::mkdirat(-5, subdir.as_ptr(), 0o755) }, -1);
+    assert_eq!(errno(), libc::EBADF);
+    let file_path = c_path(&dir_path.join("plain.txt"));
+    File::create(dir_path.join("plain.txt")).unwrap().write_all(bytes).unwrap();
+    let filefd = unsafe { libc::open(file_path.as_ptr(), libc::O_RDONLY) };
+    assert_eq!(unsafe { libc::mkdirat(filefd, subdir.as_ptr(), 0o755) }, -1);
+    assert_eq!(errno(), libc::ENOTDIR);
+    assert_eq!(unsafe { libc::open(file_path.as_ptr(), libc::O_RDONLY | libc::O_DIRECTORY) }, -1);
+    assert_eq!(errno(), libc::ENOTDIR);
+    let empty = CString::new("").unwrap();
+    assert_eq!(unsafe { libc::unlinkat(dirfd, empty.as_ptr(), 0) }, -1);
+    assert_eq!(errno(), libc::ENOENT);
+
+    assert_eq!(unsafe { libc::close(filefd) }, 0);
+    assert_eq!(unsafe { libc::close(dirfd) }, 0);
+    remove_dir_all(&dir_path).unwrap();
+}
@@ -0,0 +1,101 @@
+// ignore-windows: File handling is not implemented yet
+// compile-flags: -Zmiri-disable-isolation
+
+#![feature(rustc_private)]
+extern crate libc;
+
+use std::ffi::{CStr, CString};
+use std::fs::{read, remove_file};
+use std::io::Error;
+use std::os::unix::ffi::OsStrExt;
+use std::path::PathBuf;
+
+extern "Rust" {
+    fn miri_host_to_target_path(path: *const u8, out: *mut u8, out_size: usize) -> usize;
+}
+
+fn host_to_target_path(path: String) -> PathBuf {
+    let path = CString::new(path).unwrap();
+    let mut out = Vec::with_capacity(1024);
+    unsafe {
+        let ret = miri_host_to_target_path(path.as_ptr().cast(), out.as_mut_ptr(), out.capacity());
+        assert_eq!(ret, 0);
+        let out = CStr::from_ptr(out.as_ptr().cast());
+        PathBuf::from(out.to_str().unwrap())
+    }
+}
+
+fn tmp() -> PathBuf {
+    std::env::var("MIRI_TEMP").map(host_to_target_path).unwrap_or_else(|_| std::env::temp_dir())
+}
+
+fn errno() -> i32 {
+    Error::last_os_error().raw_os_error().unwrap()
+}
+
+fn write(fd: i32, bytes: &[u8]) {
+    let written = unsafe { libc::write(fd, bytes.as_ptr().cast(), bytes.len()) };
+    assert_eq!(written, bytes.len() as isize);
+}
+
+fn main() {
+    let path = tmp().join("miri_test_fs_dup.txt");
+    remove_file(&path).ok();
+    let c_path = CString::new(path.as_os_str().as_bytes()).unwrap();
+    let flags = libc::O_RDWR | libc::O_CREAT | libc::O_TRUNC | libc::O_CLOEXEC;
+    let fd = unsafe { libc::open(c_path.as_ptr(), flags, 0o666) };
+    assert!(fd >= 0);
+
+    unsafe {
+        // The duplicates share the offset.
+        let dup = libc::dup(fd);
+        assert!(dup > fd);
+        write(fd, b"Hello");
+        assert_eq!(libc::lseek(dup, 0, libc::SEEK_CUR), 5);
+        write(dup, b", World!");
+        assert_eq!(libc::lseek(fd, 0, libc::SEEK_CUR), 13);
+
+        // They also share the status flags, but not the file descriptor flags.
+        assert_eq!(libc::fcntl(fd, libc::F_SETFL, libc::O_NONBLOCK), 0);
+        assert_ne!(libc::fcntl(dup, libc::F_GETFL) & libc::O_NONBLOCK, 0);
+        assert_ne!(libc::fcntl(fd, libc::F_GETFD) & libc::FD_CLOEXEC, 0);
+        assert_eq!(libc::fcntl(dup, libc::F_GETFD) & libc::FD_CLOEXEC, 0);
+
+        // Closing one of them leaves the other one open.
+        assert_eq!(libc::close(dup), 0);
+        write(fd, b"\n");
+
+        // `dup2` closes the target, and it can redirect the standard streams.
+        let stdout = libc::dup(libc::STDOUT_FILENO);
+        assert_eq!(libc::dup2(fd, libc::STDOUT_FILENO), libc::STDOUT_FILENO);
+        write(libc::STDOUT_FILENO, b"Redirected\n");
+        assert_eq!(libc::dup2(stdout, libc::STDOUT_FILENO), libc::STDOUT_FILENO);
+        assert_eq!(libc::close(stdout), 0);
+        assert_eq!(read(&path).unwrap(), b"Hello, World!\nRedirected\n");
+
+        // Duplicating a file descriptor onto itself.
+        assert_eq!(libc::dup2(fd, fd), fd);
+        #[cfg(target_os = "linux")]
+        {
+            assert_eq!(libc::dup3(fd, fd, 0), -1);
+            assert_eq!(errno(), libc::EINVAL);
+            let dup = libc::dup3(fd, 100, libc::O_CLOEXEC);
+            assert_eq!(dup, 100);
+            assert_ne!(libc::fcntl(dup, libc::F_GETFD) & libc::FD_CLOEXEC, 0);
+            assert_eq!(libc::dup3(fd, dup, libc::O_NONBLOCK), -1);
+            assert_eq!(errno(), libc::EINVAL);
+            assert_eq!(libc::close(dup), 0);
+        }
+
+        // Errors.
+        assert_eq!(libc::dup(-1), -1);
+        assert_eq!(errno(), libc::EBADF);
+        assert_eq!(libc::dup2(100, 100), -1);
+        assert_eq!(errno(), libc::EBADF);
+        assert_eq!(libc::dup2(fd, -1), -1);
+        assert_eq!(errno(), libc::EBADF);
+
+        assert_eq!(libc::close(fd), 0);
+    }
+    remove_file(&path).unwrap();
+}
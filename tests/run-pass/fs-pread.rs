@@ -0,0 +1,77 @@
+// ignore-windows: File handling is not implemented yet
+// compile-flags: -Zmiri-disable-isolation
+
+#![feature(rustc_private)]
+extern crate libc;
+
+use std::ffi::{CStr, CString};
+use std::fs::{remove_file, File, OpenOptions};
+use std::io::{Error, Read, Seek, SeekFrom, Write};
+use std::os::unix::fs::FileExt;
+use std::os::unix::io::AsRawFd;
+use std::path::PathBuf;
+
+extern "Rust" {
+    fn miri_host_to_target_path(path: *const u8, out: *mut u8, out_size: usize) -> usize;
+}
+
+fn host_to_target_path(path: String) -> PathBuf {
+    let path = CString::new(path).unwrap();
+    let mut out = Vec::with_capacity(1024);
+    unsafe {
+        let ret = miri_host_to_target_path(path.as_ptr().cast(), out.as_mut_ptr(), out.capacity());
+        assert_eq!(ret, 0);
+        let out = CStr::from_ptr(out.as_ptr().cast());
+        PathBuf::from(out.to_str().unwrap())
+    }
+}
+
+fn tmp() -> PathBuf {
+    std::env::var("MIRI_TEMP").map(host_to_target_path).unwrap_or_else(|_| std::env::temp_dir())
+}
+
+fn errno() -> i32 {
+    Error::last_os_error().raw_os_error().unwrap()
+}
+
+fn main() {
+    let path = tmp().join("miri_test_fs_pread.txt");
+    remove_file(&path).ok();
+    let mut file =
+        OpenOptions::new().read(true).write(true).create(true).truncate(true).open(&path).unwrap();
+    file.write_all(b"Hello, World!").unwrap();
+    file.seek(SeekFrom::Start(2)).unwrap();
+
+    // Positional reads and writes do not change the file offset.
+    let mut buf = [0; 5];
+    assert_eq!(file.read_at(&mut buf, 7).unwrap(), 5);
+    assert_eq!(&buf, b"World");
+    file.write_all_at(b"Miri", 7).unwrap();
+    assert_eq!(file.stream_position().unwrap(), 2);
+    let mut contents = String::new();
+    file.read_to_string(&mut contents).unwrap();
+    assert_eq!(contents, "llo, Miri!");
+
+    // Reading past the end of the file reads nothing, and writing past it extends the file.
+    assert_eq!(file.read_at(&mut buf, 100).unwrap(), 0);
+    assert_eq!(file.read_at(&mut buf, 10).unwrap(), 3);
+    assert_eq!(&buf[..3], b"ri!");
+    file.write_at(b"?", 14).unwrap();
+    let mut buf = [0; 2];
+    file.read_exact_at(&mut buf, 13).unwrap();
+    assert_eq!(buf, [0, b'?']);
+
+    // Errors.
+    let fd = file.as_raw_fd();
+    unsafe {
+        assert_eq!(libc::pread(fd, buf.as_mut_ptr().cast(), 1, -1), -1);
+        assert_eq!(errno(), libc::EINVAL);
+        assert_eq!(libc::pwrite(-1, buf.as_ptr().cast(), 1, 0), -1);
+        assert_eq!(errno(), libc::EBADF);
+    }
+    let read_only = File::open(&path).unwrap();
+    assert_eq!(read_only.write_at(b"!", 0).unwrap_err().raw_os_error(), Some(libc::EBADF));
+
+    drop(file);
+    remove_file(&path).unwrap();
+}
@@ -0,0 +1,50 @@
+// ignore-windows: File handling is not implemented yet
+// ignore-64bit: Only 32-bit targets have `stat` fields that are too small for a file's values
+// compile-flags: -Zmiri-disable-isolation
+
+#![feature(rustc_private)]
+extern crate libc;
+
+use std::ffi::{CStr, CString};
+use std::fs::{remove_file, File};
+use std::io::Error;
+use std::mem::MaybeUninit;
+use std::os::unix::ffi::OsStrExt;
+use std::path::PathBuf;
+
+extern "Rust" {
+    fn miri_host_to_target_path(path: *const u8, out: *mut u8, out_size: usize) -> usize;
+}
+
+fn host_to_target_path(path: String) -> PathBuf {
+    let path = CString::new(path).unwrap();
+    let mut out = Vec::with_capacity(1024);
+    unsafe {
+        let ret = miri_host_to_target_path(path.as_ptr().cast(), out.as_mut_ptr(), out.capacity());
+        assert_eq!(ret, 0);
+        let out = CStr::from_ptr(out.as_ptr().cast());
+        PathBuf::from(out.to_str().unwrap())
+    }
+}
+
+fn tmp() -> PathBuf {
+    std::env::var("MIRI_TEMP").map(host_to_target_path).unwrap_or_else(|_| std::env::temp_dir())
+}
+
+fn main() {
+    let path = tmp().join("miri_test_fs_stat_overflow.txt");
+    // A sparse file whose size does not fit into a 32-bit `off_t`.
+    File::create(&path).unwrap().set_len(1 << 32).unwrap();
+    let c_path = CString::new(path.as_os_str().as_bytes()).unwrap();
+
+    let mut buf = MaybeUninit::<libc::stat>::uninit();
+    assert_eq!(unsafe { libc::stat(c_path.as_ptr(), buf.as_mut_ptr()) }, -1);
+    assert_eq!(Error::last_os_error().raw_os_error(), Some(libc::EOVERFLOW));
+
+    // `stat64` has room for the size.
+    let mut buf = MaybeUninit::<libc::stat64>::uninit();
+    assert_eq!(unsafe { libc::stat64(c_path.as_ptr(), buf.as_mut_ptr()) }, 0);
+    assert_eq!(unsafe { buf.assume_init() }.st_size, 1 << 32);
+
+    remove_file(&path).unwrap();
+}
@@ -0,0 +1,110 @@
+// ignore-windows: File handling is not implemented yet
+// compile-flags: -Zmiri-disable-isolation
+
+#![feature(rustc_private)]
+extern crate libc;
+
+use std::ffi::{CStr, CString};
+use std::fs::{create_dir, remove_dir_all, File};
+use std::io::{Error, Write};
+use std::mem::MaybeUninit;
+use std::os::unix::ffi::OsStrExt;
+use std::os::unix::fs::{symlink, MetadataExt};
+use std::path::{Path, PathBuf};
+
+extern "Rust" {
+    fn miri_host_to_target_path(path: *const u8, out: *mut u8, out_size: usize) -> usize;
+}
+
+fn host_to_target_path(path: String) -> PathBuf {
+    let path = CString::new(path).unwrap();
+    let mut out = Vec::with_capacity(1024);
+    unsafe {
+        let ret = miri_host_to_target_path(path.as_ptr().cast(), out.as_mut_ptr(), out.capacity());
+        assert_eq!(ret, 0);
+        let out = CStr::from_ptr(out.as_ptr().cast());
+        PathBuf::from(out.to_str().unwrap())
+    }
+}
+
+fn tmp() -> PathBuf {
+    std::env::var("MIRI_TEMP").map(host_to_target_path).unwrap_or_else(|_| std::env::temp_dir())
+}
+
+fn errno() -> i32 {
+    Error::last_os_error().raw_os_error().unwrap()
+}
+
+fn c_path(path: &Path) -> CString {
+    CString::new(path.as_os_str().as_bytes()).unwrap()
+}
+
+/// Calls `f` with a buffer for a `struct stat`, and returns the buffer if `f` succeeded.
+fn stat_with(f: impl FnOnce(*mut libc::stat) -> i32) -> Option<libc::stat> {
+    let mut buf = MaybeUninit::<libc::stat>::uninit();
+    if f(buf.as_mut_ptr()) == 0 { Some(unsafe { buf.assume_init() }) } else { None }
+}
+
+fn main() {
+    let dir_path = tmp().join("miri_test_fs_stat");
+    remove_dir_all(&dir_path).ok();
+    create_dir(&dir_path).unwrap();
+    let bytes = b"Hello, World!";
+    let file_path = dir_path.join("file.txt");
+    File::create(&file_path).unwrap().write_all(bytes).unwrap();
+    let link_path = dir_path.join("link");
+    symlink(&file_path, &link_path).unwrap();
+    let file = c_path(&file_path);
+    let link = c_path(&link_path);
+
+    // `stat` follows symlinks, `lstat` does not.
+    let stat = stat_with(|buf| unsafe { libc::stat(file.as_ptr(), buf) }).unwrap();
+    assert_eq!(stat.st_mode & libc::S_IFMT, libc::S_IFREG);
+    assert_eq!(stat.st_size, bytes.len() as libc::off_t);
+    let metadata = file_path.metadata().unwrap();
+    assert_eq!(stat.st_mode as u32, metadata.mode());
+    assert_eq!(stat.st_ino as u64, metadata.ino());
+    assert_eq!(stat.st_mtime as i64, metadata.mtime());
+    assert_eq!(stat.st_mtime_nsec as i64, metadata.mtime_nsec());
+    let link_stat = stat_with(|buf| unsafe { libc::stat(link.as_ptr(), buf) }).unwrap();
+    assert_eq!(link_stat.st_ino, stat.st_ino);
+    let lstat = stat_with(|buf| unsafe { libc::lstat(link.as_ptr(), buf) }).unwrap();
+    assert_eq!(lstat.st_mode & libc::S_IFMT, libc::S_IFLNK);
+    let dir = c_path(&dir_path);
+    let dir_stat = stat_with(|buf| unsafe { libc::stat(dir.as_ptr(), buf) }).unwrap();
+    assert_eq!(dir_stat.st_mode & libc::S_IFMT, libc::S_IFDIR);
+
+    // `fstat` and `fstatat` agree with `stat`.
+    let fd = unsafe { libc::open(file.as_ptr(), libc::O_RDONLY) };
+    let fstat = stat_with(|buf| unsafe { libc::fstat(fd, buf) }).unwrap();
+    assert_eq!((fstat.st_ino, fstat.st_size), (stat.st_ino, stat.st_size));
+    let dirfd = unsafe { libc::open(dir.as_ptr(), libc::O_RDONLY | libc::O_DIRECTORY) };
+    let name = CString::new("link").unwrap();
+    let at = stat_with(|buf| unsafe { libc::fstatat(dirfd, name.as_ptr(), buf, 0) }).unwrap();
+    assert_eq!(at.st_ino, stat.st_ino);
+    let nofollow = libc::AT_SYMLINK_NOFOLLOW;
+    let at = stat_with(|buf| unsafe { libc::fstatat(dirfd, name.as_ptr(), buf, nofollow) });
+    assert_eq!(at.unwrap().st_mode & libc::S_IFMT, libc::S_IFLNK);
+    #[cfg(target_os = "linux")]
+    {
+        let empty = CString::new("").unwrap();
+        let flags = libc::AT_EMPTY_PATH;
+        let at = stat_with(|buf| unsafe { libc::fstatat(fd, empty.as_ptr(), buf, flags) });
+        assert_eq!(at.unwrap().st_ino, stat.st_ino);
+    }
+
+    // Errors.
+    let missing = c_path(&dir_path.join("missing"));
+    assert!(stat_with(|buf| unsafe { libc::stat(missing.as_ptr(), buf) }).is_none());
+    assert_eq!(errno(), libc::ENOENT);
+    assert!(stat_with(|buf| unsafe { libc::fstatat(dirfd, name.as_ptr(), buf, -1) }).is_none());
+    assert_eq!(errno(), libc::EINVAL);
+    assert!(stat_with(|buf| unsafe { libc::fstatat(fd, name.as_ptr(), buf, 0) }).is_none());
+    assert_eq!(errno(), libc::ENOTDIR);
+    assert!(stat_with(|buf| unsafe { libc::fstat(-1, buf) }).is_none());
+    assert_eq!(errno(), libc::EBADF);
+
+    assert_eq!(unsafe { libc::close(fd) }, 0);
+    assert_eq!(unsafe { libc::close(dirfd) }, 0);
+    remove_dir_all(&dir_path).unwrap();
+}
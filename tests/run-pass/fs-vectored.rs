@@ -0,0 +1,97 @@
+// ignore-windows: File handling is not implemented yet
+// compile-flags: -Zmiri-disable-isolation
+
+#![feature(rustc_private)]
+extern crate libc;
+
+use std::ffi::{CStr, CString};
+use std::fs::{remove_file, File, OpenOptions};
+use std::io::{Error, IoSlice, IoSliceMut, Read, Seek, SeekFrom, Write};
+use std::os::unix::io::{AsRawFd, FromRawFd};
+use std::path::PathBuf;
+
+extern "Rust" {
+    fn miri_host_to_target_path(path: *const u8, out: *mut u8, out_size: usize) -> usize;
+}
+
+fn host_to_target_path(path: String) -> PathBuf {
+    let path = CString::new(path).unwrap();
+    let mut out = Vec::with_capacity(1024);
+    unsafe {
+        let ret = miri_host_to_target_path(path.as_ptr().cast(), out.as_mut_ptr(), out.capacity());
+        assert_eq!(ret, 0);
+        let out = CStr::from_ptr(out.as_ptr().cast());
+        PathBuf::from(out.to_str().unwrap())
+    }
+}
+
+fn tmp() -> PathBuf {
+    std::env::var("MIRI_TEMP").map(host_to_target_path).unwrap_or_else(|_| std::env::temp_dir())
+}
+
+fn errno() -> i32 {
+    Error::last_os_error().raw_os_error().unwrap()
+}
+
+/// Writes `Hello, World!` to `writer` in three pieces, and reads it back from `reader` into three
+/// buffers of different lengths.
+fn test_round_trip(writer: &mut impl Write, reader: &mut impl Read) {
+    let bufs = [IoSlice::new(b"Hello"), IoSlice::new(b""), IoSlice::new(b", World!")];
+    assert_eq!(writer.write_vectored(&bufs).unwrap(), 13);
+
+    let (mut a, mut b, mut c) = ([0; 2], [0; 4], [0; 10]);
+    let mut bufs = [IoSliceMut::new(&mut a), IoSliceMut::new(&mut b), IoSliceMut::new(&mut c)];
+    assert_eq!(reader.read_vectored(&mut bufs).unwrap(), 13);
+    assert_eq!(&a, b"He");
+    assert_eq!(&b, b"llo,");
+    assert_eq!(&c[..7], b" World!");
+    assert_eq!(c[7..], [0; 3]);
+}
+
+fn test_file() {
+    let path = tmp().join("miri_test_fs_vectored.txt");
+    remove_file(&path).ok();
+    let mut file =
+        OpenOptions::new().read(true).write(true).create(true).truncate(true).open(&path).unwrap();
+    // A separate open file, which has its own offset.
+    let mut reader = File::open(&path).unwrap();
+    test_round_trip(&mut file, &mut reader);
+
+    // The buffers after the end of the file are left alone.
+    file.seek(SeekFrom::Start(11)).unwrap();
+    let (mut a, mut b) = ([0; 4], [b'?'; 4]);
+    let mut bufs = [IoSliceMut::new(&mut a), IoSliceMut::new(&mut b)];
+    assert_eq!(file.read_vectored(&mut bufs).unwrap(), 2);
+    assert_eq!(&a, b"d!\0\0");
+    assert_eq!(&b, b"????");
+
+    // Errors.
+    let fd = file.as_raw_fd();
+    let iov = libc::iovec { iov_base: a.as_mut_ptr().cast(), iov_len: a.len() };
+    unsafe {
+        assert_eq!(libc::readv(fd, &iov, -1), -1);
+        assert_eq!(errno(), libc::EINVAL);
+        assert_eq!(libc::writev(fd, &iov, 1025), -1);
+        assert_eq!(errno(), libc::EINVAL);
+        assert_eq!(libc::writev(-1, &iov, 1), -1);
+        assert_eq!(errno(), libc::EBADF);
+    }
+    let err = reader.write_vectored(&[IoSlice::new(b"!")]).unwrap_err();
+    assert_eq!(err.raw_os_error(), Some(libc::EBADF));
+
+    drop(file);
+    remove_file(&path).unwrap();
+}
+
+fn test_pipe() {
+    let mut fds = [-1, -1];
+    assert_eq!(unsafe { libc::pipe(fds.as_mut_ptr()) }, 0);
+    let mut reader = unsafe { File::from_raw_fd(fds[0]) };
+    let mut writer = unsafe { File::from_raw_fd(fds[1]) };
+    test_round_trip(&mut writer, &mut reader);
+}
+
+fn main() {
+    test_file();
+    test_pipe();
+}
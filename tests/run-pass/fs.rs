@@ -31,23 +31,29 @@ fn main() {
     test_file_open_unix_allow_two_args();
     test_file_open_unix_needs_three_args();
     test_file_open_unix_extra_third_arg();
+    test_fcntl_status_flags();
+    test_fcntl_fd_flags();
+}
+
+extern "Rust" {
+    fn miri_host_to_target_path(path: *const u8, out: *mut u8, out_size: usize) -> usize;
+}
+
+/// Convert a path that was set outside of our emulated program, and may therefore have path
+/// separators that don't correspond to our target platform.
+fn host_to_target_path(path: String) -> PathBuf {
+    let path = CString::new(path).unwrap();
+    let mut out = Vec::with_capacity(1024);
+    unsafe {
+        let ret = miri_host_to_target_path(path.as_ptr().cast(), out.as_mut_ptr(), out.capacity());
+        assert_eq!(ret, 0);
+        let out = std::ffi::CStr::from_ptr(out.as_ptr().cast());
+        PathBuf::from(out.to_str().unwrap())
+    }
 }
 
 fn tmp() -> PathBuf {
-    std::env::var("MIRI_TEMP")
-        .map(|tmp| {
-            // MIRI_TEMP is set outside of our emulated
-            // program, so it may have path separators that don't
-            // correspond to our target platform. We normalize them here
-            // before constructing a `PathBuf`
-
-            #[cfg(windows)]
-            return PathBuf::from(tmp.replace("/", "\\"));
-
-            #[cfg(not(windows))]
-            return PathBuf::from(tmp.replace("\\", "/"));
-        })
-        .unwrap_or_else(|_| std::env::temp_dir())
+    std::env::var("MIRI_TEMP").map(host_to_target_path).unwrap_or_else(|_| std::env::temp_dir())
 }
 
 /// Prepare: compute filename and make sure the file does not exist.
@@ -132,6 +138,79 @@ fn test_file_open_unix_extra_third_arg() {
     let _fd = unsafe { libc::open(name_ptr, libc::O_RDONLY, 42) };
 }
 
+fn test_fcntl_status_flags() {
+    use std::os::unix::ffi::OsStrExt;
+
+    let path = prepare_with_content("test_fcntl_status_flags.txt", b"abc");
+
+    let mut name = path.into_os_string();
+    name.push("\0");
+    let name_ptr = name.as_bytes().as_ptr().cast::<libc::c_char>();
+    let mut buf = [0u8; 3];
+    unsafe {
+        let fd = libc::open(name_ptr, libc::O_RDONLY);
+        assert_eq!(libc::fcntl(fd, libc::F_GETFL) & libc::O_ACCMODE, libc::O_RDONLY);
+        assert_eq!(libc::write(fd, buf.as_ptr().cast(), 3), -1);
+        assert_eq!(Error::last_os_error().raw_os_error(), Some(libc::EBADF));
+
+        // Duplicates share the status flags.
+        let dup = libc::fcntl(fd, libc::F_DUPFD, 0);
+        assert_eq!(libc::fcntl(fd, libc::F_SETFL, libc::O_NONBLOCK), 0);
+        assert_eq!(libc::fcntl(dup, libc::F_GETFL) & libc::O_NONBLOCK, libc::O_NONBLOCK);
+        assert_eq!(libc::fcntl(dup, libc::F_SETFL, 0), 0);
+        assert_eq!(libc::fcntl(fd, libc::F_GETFL) & libc::O_NONBLOCK, 0);
+        assert_eq!(libc::read(dup, buf.as_mut_ptr().cast(), 3), 3);
+        assert_eq!(&buf, b"abc");
+        libc::close(dup);
+        libc::close(fd);
+
+        let fd = libc::open(name_ptr, libc::O_WRONLY | libc::O_APPEND);
+        assert_eq!(
+            libc::fcntl(fd, libc::F_GETFL) & (libc::O_ACCMODE | libc::O_APPEND),
+            libc::O_WRONLY | libc::O_APPEND
+        );
+        assert_eq!(libc::read(fd, buf.as_mut_ptr().cast(), 3), -1);
+        assert_eq!(Error::last_os_error().raw_os_error(), Some(libc::EBADF));
+        libc::close(fd);
+    }
+}
+
+fn test_fcntl_fd_flags() {
+    use std::os::unix::ffi::OsStrExt;
+    use std::os::unix::io::AsRawFd;
+
+    let path = prepare_with_content("test_fcntl_fd_flags.txt", b"abc");
+
+    // `std` opens files with `O_CLOEXEC`.
+    let file = File::open(&path).unwrap();
+    assert_eq!(unsafe { libc::fcntl(file.as_raw_fd(), libc::F_GETFD) }, libc::FD_CLOEXEC);
+
+    let mut name = path.into_os_string();
+    name.push("\0");
+    let name_ptr = name.as_bytes().as_ptr().cast::<libc::c_char>();
+    unsafe {
+        let fd = libc::open(name_ptr, libc::O_RDONLY);
+        assert_eq!(libc::fcntl(fd, libc::F_GETFD), 0);
+
+        // The flags belong to the file descriptor, duplicates do not share them.
+        let dup = libc::fcntl(fd, libc::F_DUPFD_CLOEXEC, 0);
+        assert_eq!(libc::fcntl(dup, libc::F_GETFD), libc::FD_CLOEXEC);
+        assert_eq!(libc::fcntl(fd, libc::F_GETFD), 0);
+        assert_eq!(libc::fcntl(dup, libc::F_SETFD, 0), 0);
+        assert_eq!(libc::fcntl(dup, libc::F_GETFD), 0);
+        assert_eq!(libc::fcntl(fd, libc::F_SETFD, libc::FD_CLOEXEC), 0);
+        assert_eq!(libc::fcntl(fd, libc::F_GETFD), libc::FD_CLOEXEC);
+        let dup2 = libc::fcntl(fd, libc::F_DUPFD, 0);
+        assert_eq!(libc::fcntl(dup2, libc::F_GETFD), 0);
+        libc::close(dup2);
+        libc::close(dup);
+        libc::close(fd);
+
+        assert_eq!(libc::fcntl(fd, libc::F_SETFD, 0), -1);
+        assert_eq!(Error::last_os_error().raw_os_error(), Some(libc::EBADF));
+    }
+}
+
 fn test_file_clone() {
     let bytes = b"Hello, World!\n";
     let path = prepare_with_content("miri_test_fs_file_clone.txt", bytes);
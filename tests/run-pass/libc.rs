@@ -5,9 +5,28 @@
 
 extern crate libc;
 
+extern "Rust" {
+    fn miri_host_to_target_path(path: *const u8, out: *mut u8, out_size: usize) -> usize;
+}
+
+/// Convert a path that was set outside of our emulated program to the target conventions.
+#[cfg(target_os = "linux")]
+fn host_to_target_path(path: String) -> std::path::PathBuf {
+    use std::ffi::{CStr, CString};
+
+    let path = CString::new(path).unwrap();
+    let mut out = Vec::with_capacity(1024);
+    unsafe {
+        let ret = miri_host_to_target_path(path.as_ptr().cast(), out.as_mut_ptr(), out.capacity());
+        assert_eq!(ret, 0);
+        let out = CStr::from_ptr(out.as_ptr().cast());
+        std::path::PathBuf::from(out.to_str().unwrap())
+    }
+}
+
 #[cfg(target_os = "linux")]
 fn tmp() -> std::path::PathBuf {
-    std::env::var("MIRI_TEMP").map(std::path::PathBuf::from).unwrap_or_else(|_| std::env::temp_dir())
+    std::env::var("MIRI_TEMP").map(host_to_target_path).unwrap_or_else(|_| std::env::temp_dir())
 }
 
 #[cfg(target_os = "linux")]
@@ -212,6 +231,53 @@ fn test_prctl_thread_name() {
     }
 }
 
+/// Test that `sched_getaffinity` reports the emulated CPUs, and reflects what
+/// `sched_setaffinity` set.
+#[cfg(target_os = "linux")]
+fn test_sched_affinity() {
+    use std::mem;
+    unsafe {
+        let mut set: libc::cpu_set_t = mem::zeroed();
+        assert_eq!(libc::sched_getaffinity(0, mem::size_of::<libc::cpu_set_t>(), &mut set), 0);
+        assert!(libc::CPU_ISSET(0, &set));
+        assert_eq!(libc::CPU_COUNT(&set), 1);
+
+        // A mask without any existing CPU is rejected.
+        let mut other: libc::cpu_set_t = mem::zeroed();
+        libc::CPU_SET(5, &mut other);
+        assert_eq!(libc::sched_setaffinity(0, mem::size_of::<libc::cpu_set_t>(), &other), -1);
+        assert_eq!(std::io::Error::last_os_error().raw_os_error(), Some(libc::EINVAL));
+
+        // Setting the CPU we have succeeds, and the non-existing one is dropped.
+        libc::CPU_SET(0, &mut other);
+        assert_eq!(libc::sched_setaffinity(0, mem::size_of::<libc::cpu_set_t>(), &other), 0);
+        let mut set: libc::cpu_set_t = mem::zeroed();
+        assert_eq!(libc::sched_getaffinity(0, mem::size_of::<libc::cpu_set_t>(), &mut set), 0);
+        assert!(libc::CPU_ISSET(0, &set));
+        assert!(!libc::CPU_ISSET(5, &set));
+
+        // The size of the set does not have to be a whole number of `c_ulong`s.
+        let size = mem::size_of::<libc::cpu_set_t>() - 1;
+        assert_eq!(libc::sched_setaffinity(0, size, &set), 0);
+        let mut partial: libc::cpu_set_t = mem::zeroed();
+        assert_eq!(libc::sched_getaffinity(0, size, &mut partial), 0);
+        assert!(libc::CPU_EQUAL(&set, &partial));
+
+        // A spawned thread inherits the mask of the thread that spawned it.
+        let set = std::thread::spawn(|| {
+            let mut set: libc::cpu_set_t = mem::zeroed();
+            assert_eq!(
+                libc::sched_getaffinity(0, mem::size_of::<libc::cpu_set_t>(), &mut set),
+                0
+            );
+            set
+        })
+        .join()
+        .unwrap();
+        assert!(libc::CPU_EQUAL(&set, &partial));
+    }
+}
+
 /// Tests whether each thread has its own `__errno_location`.
 fn test_thread_local_errno() {
     #[cfg(not(target_os = "macos"))]
@@ -230,6 +296,30 @@ fn test_thread_local_errno() {
     }
 }
 
+fn test_mem_functions() {
+    extern "C" {
+        fn bcmp(s1: *const libc::c_void, s2: *const libc::c_void, n: libc::size_t) -> libc::c_int;
+    }
+
+    let a = *b"hello world";
+    let b = *b"hello there";
+    unsafe {
+        assert_eq!(libc::memcmp(a.as_ptr().cast(), b.as_ptr().cast(), 6), 0);
+        assert!(libc::memcmp(a.as_ptr().cast(), b.as_ptr().cast(), a.len()) > 0);
+        assert_eq!(bcmp(a.as_ptr().cast(), b.as_ptr().cast(), 6), 0);
+        assert_ne!(bcmp(a.as_ptr().cast(), b.as_ptr().cast(), a.len()), 0);
+
+        let found = libc::memchr(a.as_ptr().cast(), b'o'.into(), a.len());
+        assert_eq!(found, a.as_ptr().add(4) as *mut libc::c_void);
+        // The search stops at the first match, so the length may be larger than the buffer.
+        let found = libc::memchr(a.as_ptr().cast(), b'w'.into(), 1 << 20);
+        assert_eq!(found, a.as_ptr().add(6) as *mut libc::c_void);
+        assert!(libc::memchr(a.as_ptr().cast(), b'z'.into(), a.len()).is_null());
+
+        assert_eq!(libc::strlen(b"hello\0world\0".as_ptr().cast()), 5);
+    }
+}
+
 fn main() {
     #[cfg(target_os = "linux")]
     test_posix_fadvise();
@@ -248,5 +338,10 @@ fn main() {
     #[cfg(target_os = "linux")]
     test_prctl_thread_name();
 
+    #[cfg(target_os = "linux")]
+    test_sched_affinity();
+
     test_thread_local_errno();
+
+    test_mem_functions();
 }
@@ -0,0 +1,129 @@
+// Unfortunately, the test framework does not support 'only-linux',
+// so we need to ignore Windows and macOS instead.
+// ignore-macos: Uses Linux-only APIs
+// ignore-windows: Uses Linux-only APIs
+// compile-flags: -Zmiri-disable-isolation
+
+//! Checks that the shims write the values of structs and buffers in the byte order of the target.
+//! This is most interesting on big-endian targets like `s390x-unknown-linux-gnu`.
+
+#![feature(rustc_private)]
+extern crate libc;
+
+use std::ffi::{CStr, CString};
+use std::fs::{create_dir, remove_dir_all, File};
+use std::io::Write;
+use std::mem;
+use std::os::unix::ffi::OsStrExt;
+use std::path::{Path, PathBuf};
+
+extern "Rust" {
+    fn miri_host_to_target_path(path: *const u8, out: *mut u8, out_size: usize) -> usize;
+}
+
+fn host_to_target_path(path: String) -> PathBuf {
+    let path = CString::new(path).unwrap();
+    let mut out = Vec::with_capacity(1024);
+    unsafe {
+        let ret = miri_host_to_target_path(path.as_ptr().cast(), out.as_mut_ptr(), out.capacity());
+        assert_eq!(ret, 0);
+        let out = CStr::from_ptr(out.as_ptr().cast());
+        PathBuf::from(out.to_str().unwrap())
+    }
+}
+
+fn tmp() -> PathBuf {
+    std::env::var("MIRI_TEMP").map(host_to_target_path).unwrap_or_else(|_| std::env::temp_dir())
+}
+
+fn c_path(path: &Path) -> CString {
+    CString::new(path.as_os_str().as_bytes()).unwrap()
+}
+
+const ULONG_BITS: usize = 8 * mem::size_of::<libc::c_ulong>();
+
+fn test_cpu_set() {
+    unsafe {
+        let cpus = usize::try_from(libc::sysconf(libc::_SC_NPROCESSORS_ONLN)).unwrap();
+        let mut set: libc::cpu_set_t = mem::zeroed();
+        assert_eq!(libc::sched_getaffinity(0, mem::size_of::<libc::cpu_set_t>(), &mut set), 0);
+        assert_eq!(usize::try_from(libc::CPU_COUNT(&set)).unwrap(), cpus);
+        // CPU `n` is bit `n % ULONG_BITS` of `c_ulong` number `n / ULONG_BITS`, wherever that bit
+        // is in memory.
+        let mut words: [libc::c_ulong; 1024 / ULONG_BITS] = mem::transmute(set);
+        for cpu in 0..1024 {
+            let bit = words[cpu / ULONG_BITS] >> (cpu % ULONG_BITS) & 1;
+            assert_eq!(bit == 1, cpu < cpus, "CPU {}", cpu);
+        }
+
+        // Only keep the first and the last CPU.
+        words = [0; 1024 / ULONG_BITS];
+        words[0] = 1;
+        words[(cpus - 1) / ULONG_BITS] |= 1 << ((cpus - 1) % ULONG_BITS);
+        let set: libc::cpu_set_t = mem::transmute(words);
+        assert_eq!(libc::sched_setaffinity(0, mem::size_of::<libc::cpu_set_t>(), &set), 0);
+        let mut set: libc::cpu_set_t = mem::zeroed();
+        assert_eq!(libc::sched_getaffinity(0, mem::size_of::<libc::cpu_set_t>(), &mut set), 0);
+        assert!(libc::CPU_ISSET(0, &set));
+        assert!(libc::CPU_ISSET(cpus - 1, &set));
+        assert_eq!(libc::CPU_COUNT(&set), if cpus > 1 { 2 } else { 1 });
+    }
+}
+
+fn test_eventfd() {
+    unsafe {
+        let fd = libc::eventfd(0x0102_0304, libc::EFD_NONBLOCK);
+        let value = 0x1020_3040_5060_u64;
+        assert_eq!(libc::write(fd, &value as *const u64 as *const libc::c_void, 8), 8);
+        let mut read = 0u64;
+        assert_eq!(libc::read(fd, &mut read as *mut u64 as *mut libc::c_void, 8), 8);
+        assert_eq!(read, 0x1020_3040_5060 + 0x0102_0304);
+        libc::close(fd);
+    }
+}
+
+/// The directory has a single file of 0x0102 bytes, so that both bytes of the size matter.
+fn test_stat_and_readdir() {
+    let dir = tmp().join("miri_test_byte_order");
+    remove_dir_all(&dir).ok();
+    create_dir(&dir).unwrap();
+    let path = dir.join("file.txt");
+    File::create(&path).unwrap().write_all(&[0; 0x0102]).unwrap();
+
+    unsafe {
+        let mut stat: libc::stat = mem::zeroed();
+        assert_eq!(libc::stat(c_path(&path).as_ptr(), &mut stat), 0);
+        assert_eq!(stat.st_size, 0x0102);
+        assert_eq!(stat.st_mode & libc::S_IFMT, libc::S_IFREG);
+        assert_eq!(stat.st_nlink, 1);
+
+        let dirp = libc::opendir(c_path(&dir).as_ptr());
+        assert!(!dirp.is_null());
+        let mut names = vec![];
+        loop {
+            let entry = libc::readdir64(dirp);
+            if entry.is_null() {
+                break;
+            }
+            let name = CStr::from_ptr((*entry).d_name.as_ptr()).to_str().unwrap().to_owned();
+            // The entry ends right after the name and its terminator.
+            let name_offset = (*entry).d_name.as_ptr() as usize - entry as usize;
+            assert_eq!(usize::from((*entry).d_reclen), name_offset + name.len() + 1);
+            if name == "file.txt" {
+                assert_eq!((*entry).d_type, libc::DT_REG);
+            }
+            names.push(name);
+        }
+        assert_eq!(libc::closedir(dirp), 0);
+        names.retain(|name| name != "." && name != "..");
+        assert_eq!(names, ["file.txt"]);
+    }
+
+    remove_dir_all(&dir).unwrap();
+}
+
+fn main() {
+    test_cpu_set();
+    test_eventfd();
+    test_stat_and_readdir();
+}
@@ -0,0 +1,65 @@
+// Unfortunately, compiletest_rs does not support 'only-linux',
+// so we need to ignore Windows and macOS instead.
+// ignore-macos: Uses Linux-only APIs
+// ignore-windows: Uses Linux-only APIs
+// compile-flags: -Zmiri-disable-isolation -Zmiri-deliver-timer-signals
+#![feature(rustc_private)]
+extern crate libc;
+
+use std::mem::MaybeUninit;
+
+extern "C" fn on_alarm(_signum: libc::c_int) {}
+
+fn now(clock: libc::clockid_t) -> libc::timespec {
+    let mut ts = MaybeUninit::<libc::timespec>::uninit();
+    assert_eq!(unsafe { libc::clock_gettime(clock, ts.as_mut_ptr()) }, 0);
+    unsafe { ts.assume_init() }
+}
+
+fn test_absolute(clock: libc::clockid_t) {
+    let mut deadline = now(clock);
+    deadline.tv_sec += 1;
+    let res = unsafe {
+        libc::clock_nanosleep(clock, libc::TIMER_ABSTIME, &deadline, std::ptr::null_mut())
+    };
+    assert_eq!(res, 0);
+    let after = now(clock);
+    assert!((after.tv_sec, after.tv_nsec) >= (deadline.tv_sec, deadline.tv_nsec));
+}
+
+fn test_relative() {
+    let req = libc::timespec { tv_sec: 0, tv_nsec: 1_000_000 };
+    let res =
+        unsafe { libc::clock_nanosleep(libc::CLOCK_MONOTONIC, 0, &req, std::ptr::null_mut()) };
+    assert_eq!(res, 0);
+
+    let bad = libc::timespec { tv_sec: 0, tv_nsec: 1_000_000_000 };
+    let res =
+        unsafe { libc::clock_nanosleep(libc::CLOCK_MONOTONIC, 0, &bad, std::ptr::null_mut()) };
+    assert_eq!(res, libc::EINVAL);
+}
+
+fn test_interrupted() {
+    unsafe {
+        libc::signal(libc::SIGALRM, on_alarm as libc::sighandler_t);
+        let timer = libc::itimerval {
+            it_interval: libc::timeval { tv_sec: 0, tv_usec: 0 },
+            it_value: libc::timeval { tv_sec: 0, tv_usec: 1000 },
+        };
+        assert_eq!(libc::setitimer(libc::ITIMER_REAL, &timer, std::ptr::null_mut()), 0);
+    }
+    let req = libc::timespec { tv_sec: 10, tv_nsec: 0 };
+    let mut rem = libc::timespec { tv_sec: 0, tv_nsec: 0 };
+    let res = unsafe { libc::clock_nanosleep(libc::CLOCK_MONOTONIC, 0, &req, &mut rem) };
+    assert_eq!(res, libc::EINTR);
+    // The timer went off (at least) 1ms into the sleep.
+    assert_eq!(rem.tv_sec, 9);
+    assert!(rem.tv_nsec <= 999_000_000);
+}
+
+fn main() {
+    test_absolute(libc::CLOCK_MONOTONIC);
+    test_absolute(libc::CLOCK_REALTIME);
+    test_relative();
+    test_interrupted();
+}
@@ -0,0 +1,43 @@
+// Unfortunately, compiletest_rs does not support 'only-linux',
+// so we need to ignore Windows and macOS instead.
+// ignore-macos: Uses Linux-only APIs
+// ignore-windows: Uses Linux-only APIs
+// CPU time is derived from the interpreted steps, so this works with isolation enabled.
+#![feature(rustc_private)]
+extern crate libc;
+
+use std::mem::MaybeUninit;
+
+fn cpu_time(clock: libc::clockid_t) -> (i64, i64) {
+    let mut ts = MaybeUninit::<libc::timespec>::uninit();
+    assert_eq!(unsafe { libc::clock_gettime(clock, ts.as_mut_ptr()) }, 0);
+    let ts = unsafe { ts.assume_init() };
+    (ts.tv_sec, ts.tv_nsec)
+}
+
+fn main() {
+    let before = cpu_time(libc::CLOCK_PROCESS_CPUTIME_ID);
+    let mut sum = 0u64;
+    for i in 0..1000 {
+        sum = sum.wrapping_add(i * i);
+    }
+    assert!(sum > 0);
+    let after = cpu_time(libc::CLOCK_PROCESS_CPUTIME_ID);
+    assert!(after > before);
+
+    // Only the main thread ran so far, so it used all the CPU time.
+    let thread = cpu_time(libc::CLOCK_THREAD_CPUTIME_ID);
+    assert!(thread > after);
+
+    let mut usage = MaybeUninit::<libc::rusage>::uninit();
+    assert_eq!(unsafe { libc::getrusage(libc::RUSAGE_SELF, usage.as_mut_ptr()) }, 0);
+    let usage = unsafe { usage.assume_init() };
+    assert_eq!((usage.ru_stime.tv_sec, usage.ru_stime.tv_usec), (0, 0));
+    assert_eq!(usage.ru_maxrss, 0);
+
+    let mut tms = MaybeUninit::<libc::tms>::uninit();
+    assert!(unsafe { libc::times(tms.as_mut_ptr()) } >= 0);
+    let tms = unsafe { tms.assume_init() };
+    assert_eq!(tms.tms_stime, 0);
+    assert_eq!(unsafe { libc::sysconf(libc::_SC_CLK_TCK) }, 100);
+}
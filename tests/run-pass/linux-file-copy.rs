@@ -0,0 +1,151 @@
+// Unfortunately, the test framework does not support 'only-linux',
+// so we need to ignore Windows and macOS instead.
+// ignore-macos: Uses Linux-only APIs
+// ignore-windows: Uses Linux-only APIs
+// compile-flags: -Zmiri-disable-isolation
+#![feature(rustc_private)]
+extern crate libc;
+
+use std::ffi::CString;
+use std::fs::{read, remove_file, File, OpenOptions};
+use std::io::{Error, Seek, SeekFrom, Write};
+use std::os::unix::io::AsRawFd;
+use std::path::PathBuf;
+use std::ptr;
+
+extern "Rust" {
+    fn miri_host_to_target_path(path: *const u8, out: *mut u8, out_size: usize) -> usize;
+}
+
+fn host_to_target_path(path: String) -> PathBuf {
+    let path = CString::new(path).unwrap();
+    let mut out = Vec::with_capacity(1024);
+    unsafe {
+        let ret = miri_host_to_target_path(path.as_ptr().cast(), out.as_mut_ptr(), out.capacity());
+        assert_eq!(ret, 0);
+        let out = std::ffi::CStr::from_ptr(out.as_ptr().cast());
+        PathBuf::from(out.to_str().unwrap())
+    }
+}
+
+fn tmp() -> PathBuf {
+    std::env::var("MIRI_TEMP").map(host_to_target_path).unwrap_or_else(|_| std::env::temp_dir())
+}
+
+/// Prepare: compute filename and make sure the file does not exist.
+fn prepare(filename: &str) -> PathBuf {
+    let path = tmp().join(filename);
+    remove_file(&path).ok();
+    path
+}
+
+fn errno() -> i32 {
+    Error::last_os_error().raw_os_error().unwrap()
+}
+
+fn test_copy_file_range() {
+    let src_path = prepare("miri_test_copy_file_range_src.txt");
+    let dst_path = prepare("miri_test_copy_file_range_dst.txt");
+    File::create(&src_path).unwrap().write_all(b"Hello, World!").unwrap();
+    let src = File::open(&src_path).unwrap();
+    let dst = OpenOptions::new().read(true).write(true).create(true).open(&dst_path).unwrap();
+    let (src_fd, dst_fd) = (src.as_raw_fd(), dst.as_raw_fd());
+    let null = ptr::null_mut();
+
+    // Without offsets, the file offsets are used and advanced.
+    let res = unsafe { libc::copy_file_range(src_fd, null, dst_fd, null, 5, 0) };
+    assert_eq!(res, 5);
+    assert_eq!((&src).seek(SeekFrom::Current(0)).unwrap(), 5);
+    assert_eq!(read(&dst_path).unwrap(), b"Hello");
+
+    // With offsets, the offsets are advanced but the file offsets are not.
+    let mut off_in: libc::loff_t = 7;
+    let mut off_out: libc::loff_t = 10;
+    let res = unsafe { libc::copy_file_range(src_fd, &mut off_in, dst_fd, &mut off_out, 100, 0) };
+    // Only the rest of the file is copied.
+    assert_eq!(res, 6);
+    assert_eq!((off_in, off_out), (13, 16));
+    assert_eq!((&src).seek(SeekFrom::Current(0)).unwrap(), 5);
+    assert_eq!(read(&dst_path).unwrap(), b"Hello\0\0\0\0\0World!");
+
+    // Copying at the end of the file copies nothing.
+    let res = unsafe { libc::copy_file_range(src_fd, &mut off_in, dst_fd, &mut off_out, 10, 0) };
+    assert_eq!(res, 0);
+
+    // Invalid arguments.
+    let res = unsafe { libc::copy_file_range(src_fd, null, dst_fd, null, 1, 1) };
+    assert_eq!((res, errno()), (-1, libc::EINVAL));
+    let res = unsafe { libc::copy_file_range(dst_fd, null, src_fd, null, 1, 0) };
+    assert_eq!((res, errno()), (-1, libc::EBADF));
+    let (mut off_in, mut off_out): (libc::loff_t, libc::loff_t) = (0, 2);
+    let res = unsafe { libc::copy_file_range(dst_fd, &mut off_in, dst_fd, &mut off_out, 4, 0) };
+    assert_eq!((res, errno()), (-1, libc::EINVAL));
+
+    remove_file(&src_path).unwrap();
+    remove_file(&dst_path).unwrap();
+}
+
+fn test_sendfile() {
+    let src_path = prepare("miri_test_sendfile_src.txt");
+    let dst_path = prepare("miri_test_sendfile_dst.txt");
+    File::create(&src_path).unwrap().write_all(b"Hello, World!").unwrap();
+    let src = File::open(&src_path).unwrap();
+    let dst = File::create(&dst_path).unwrap();
+    let (src_fd, dst_fd) = (src.as_raw_fd(), dst.as_raw_fd());
+
+    let mut offset: libc::off_t = 7;
+    let res = unsafe { libc::sendfile(dst_fd, src_fd, &mut offset, 3) };
+    assert_eq!(res, 3);
+    assert_eq!(offset, 10);
+    assert_eq!((&src).seek(SeekFrom::Current(0)).unwrap(), 0);
+    let res = unsafe { libc::sendfile(dst_fd, src_fd, ptr::null_mut(), 5) };
+    assert_eq!(res, 5);
+    assert_eq!((&src).seek(SeekFrom::Current(0)).unwrap(), 5);
+    assert_eq!(read(&dst_path).unwrap(), b"WorHello");
+
+    // The input file must be readable.
+    let res = unsafe { libc::sendfile(src_fd, dst_fd, ptr::null_mut(), 5) };
+    assert_eq!((res, errno()), (-1, libc::EBADF));
+
+    remove_file(&src_path).unwrap();
+    remove_file(&dst_path).unwrap();
+}
+
+fn test_fallocate() {
+    let path = prepare("miri_test_fallocate.txt");
+    let mut file = OpenOptions::new().read(true).write(true).create(true).open(&path).unwrap();
+    file.write_all(b"Hello, World!").unwrap();
+    let fd = file.as_raw_fd();
+
+    // Allocating beyond the end extends the file, unless the size is kept.
+    assert_eq!(unsafe { libc::fallocate(fd, 0, 10, 10) }, 0);
+    assert_eq!(file.metadata().unwrap().len(), 20);
+    assert_eq!(unsafe { libc::fallocate(fd, libc::FALLOC_FL_KEEP_SIZE, 0, 100) }, 0);
+    assert_eq!(file.metadata().unwrap().len(), 20);
+
+    // Punching a hole zeroes the range.
+    let mode = libc::FALLOC_FL_PUNCH_HOLE | libc::FALLOC_FL_KEEP_SIZE;
+    assert_eq!(unsafe { libc::fallocate(fd, mode, 0, 5) }, 0);
+    assert_eq!(&read(&path).unwrap()[..13], b"\0\0\0\0\0, World!");
+    assert_eq!(unsafe { libc::fallocate(fd, libc::FALLOC_FL_ZERO_RANGE, 7, 20) }, 0);
+    let contents = read(&path).unwrap();
+    assert_eq!(&contents[..7], b"\0\0\0\0\0, ");
+    assert_eq!(contents[7..], [0; 20]);
+
+    // Invalid arguments.
+    assert_eq!((unsafe { libc::fallocate(fd, 0, 0, 0) }, errno()), (-1, libc::EINVAL));
+    assert_eq!((unsafe { libc::fallocate(fd, 0, -1, 1) }, errno()), (-1, libc::EINVAL));
+    let mode = libc::FALLOC_FL_PUNCH_HOLE;
+    assert_eq!((unsafe { libc::fallocate(fd, mode, 0, 1) }, errno()), (-1, libc::EOPNOTSUPP));
+    let read_only = File::open(&path).unwrap();
+    let res = unsafe { libc::fallocate(read_only.as_raw_fd(), 0, 0, 1) };
+    assert_eq!((res, errno()), (-1, libc::EBADF));
+
+    remove_file(&path).unwrap();
+}
+
+fn main() {
+    test_copy_file_range();
+    test_sendfile();
+    test_fallocate();
+}
@@ -0,0 +1,90 @@
+// Unfortunately, the test framework does not support 'only-linux',
+// so we need to ignore Windows and macOS instead.
+// ignore-macos: Uses Linux-only APIs
+// ignore-windows: Uses Linux-only APIs
+// compile-flags: -Zmiri-disable-isolation
+#![feature(rustc_private)]
+extern crate libc;
+
+use std::ffi::CString;
+use std::fs::{remove_file, File};
+use std::io::{Read, Seek, SeekFrom, Write};
+use std::os::unix::io::AsRawFd;
+use std::path::PathBuf;
+use std::ptr;
+use std::slice;
+
+const PAGE_SIZE: usize = 4096;
+
+extern "Rust" {
+    fn miri_host_to_target_path(path: *const u8, out: *mut u8, out_size: usize) -> usize;
+}
+
+fn host_to_target_path(path: String) -> PathBuf {
+    let path = CString::new(path).unwrap();
+    let mut out = Vec::with_capacity(1024);
+    unsafe {
+        let ret = miri_host_to_target_path(path.as_ptr().cast(), out.as_mut_ptr(), out.capacity());
+        assert_eq!(ret, 0);
+        let out = std::ffi::CStr::from_ptr(out.as_ptr().cast());
+        PathBuf::from(out.to_str().unwrap())
+    }
+}
+
+fn tmp() -> PathBuf {
+    std::env::var("MIRI_TEMP").map(host_to_target_path).unwrap_or_else(|_| std::env::temp_dir())
+}
+
+fn main() {
+    let path = tmp().join("miri_test_mmap_file.txt");
+    remove_file(&path).ok();
+    let mut contents = vec![b'a'; PAGE_SIZE];
+    contents.extend_from_slice(b"second page");
+    File::create(&path).unwrap().write_all(&contents).unwrap();
+
+    let mut file = File::open(&path).unwrap();
+    let fd = file.as_raw_fd();
+    file.seek(SeekFrom::Start(3)).unwrap();
+    unsafe {
+        // A read-only mapping of the whole file.
+        let len = contents.len();
+        let ptr = libc::mmap(ptr::null_mut(), len, libc::PROT_READ, libc::MAP_PRIVATE, fd, 0);
+        assert_ne!(ptr, libc::MAP_FAILED);
+        let mapped = slice::from_raw_parts(ptr.cast::<u8>(), 2 * PAGE_SIZE);
+        assert_eq!(&mapped[..contents.len()], &contents[..]);
+        // The rest of the last page is zero.
+        assert!(mapped[contents.len()..].iter().all(|&b| b == 0));
+        assert_eq!(libc::munmap(ptr, contents.len()), 0);
+
+        // A copy-on-write mapping at an offset. Writes do not reach the file.
+        let prot = libc::PROT_READ | libc::PROT_WRITE;
+        let offset = PAGE_SIZE as libc::off_t;
+        let ptr = libc::mmap(ptr::null_mut(), 6, prot, libc::MAP_PRIVATE, fd, offset);
+        assert_ne!(ptr, libc::MAP_FAILED);
+        let mapped = slice::from_raw_parts_mut(ptr.cast::<u8>(), 11);
+        assert_eq!(mapped, b"second page");
+        mapped[..6].copy_from_slice(b"SECOND");
+        assert_eq!(mapped, b"SECOND page");
+        assert_eq!(libc::munmap(ptr, 6), 0);
+
+        // The offset has to be a multiple of the page size.
+        let ptr = libc::mmap(ptr::null_mut(), 6, prot, libc::MAP_PRIVATE, fd, 1);
+        assert_eq!(ptr, libc::MAP_FAILED);
+        assert_eq!(std::io::Error::last_os_error().raw_os_error(), Some(libc::EINVAL));
+    }
+    // Mapping the file neither changed it nor moved the file offset.
+    let mut rest = vec![];
+    file.read_to_end(&mut rest).unwrap();
+    assert_eq!(rest, &contents[3..]);
+
+    // Files that are only open for writing cannot be mapped.
+    let file = File::create(&path).unwrap();
+    let ptr = unsafe {
+        libc::mmap(ptr::null_mut(), 6, libc::PROT_READ, libc::MAP_PRIVATE, file.as_raw_fd(), 0)
+    };
+    assert_eq!(ptr, libc::MAP_FAILED);
+    assert_eq!(std::io::Error::last_os_error().raw_os_error(), Some(libc::EACCES));
+    drop(file);
+
+    remove_file(&path).unwrap();
+}
@@ -0,0 +1,110 @@
+// Unfortunately, the test framework does not support 'only-linux',
+// so we need to ignore Windows and macOS instead.
+// ignore-macos: Uses Linux-only APIs
+// ignore-windows: Uses Linux-only APIs
+#![feature(rustc_private)]
+extern crate libc;
+
+use std::ptr;
+use std::slice;
+
+const PAGE_SIZE: usize = 4096;
+
+fn last_error() -> i32 {
+    std::io::Error::last_os_error().raw_os_error().unwrap()
+}
+
+fn mmap(len: usize) -> *mut u8 {
+    let prot = libc::PROT_READ | libc::PROT_WRITE;
+    let flags = libc::MAP_PRIVATE | libc::MAP_ANONYMOUS;
+    let ptr = unsafe { libc::mmap(ptr::null_mut(), len, prot, flags, -1, 0) };
+    assert_ne!(ptr, libc::MAP_FAILED);
+    ptr.cast()
+}
+
+fn test_mmap() {
+    let ptr = mmap(100);
+    assert_eq!(ptr as usize % PAGE_SIZE, 0);
+    // Anonymous mappings are zeroed, and the length is rounded up to whole pages.
+    let pages = unsafe { slice::from_raw_parts_mut(ptr, PAGE_SIZE) };
+    assert!(pages.iter().all(|&b| b == 0));
+    pages[PAGE_SIZE - 1] = 1;
+    // The length passed to `munmap` is rounded up as well.
+    assert_eq!(unsafe { libc::munmap(ptr.cast(), 100) }, 0);
+
+    let prot = libc::PROT_READ | libc::PROT_WRITE;
+    let flags = libc::MAP_PRIVATE | libc::MAP_ANONYMOUS;
+    let ptr = unsafe { libc::mmap(ptr::null_mut(), 0, prot, flags, -1, 0) };
+    assert_eq!(ptr, libc::MAP_FAILED);
+    assert_eq!(last_error(), libc::EINVAL);
+}
+
+fn test_mremap() {
+    let ptr = mmap(PAGE_SIZE);
+    unsafe { ptr.write_bytes(7, PAGE_SIZE) };
+
+    // Without `MREMAP_MAYMOVE`, growing fails since the mapping cannot grow in place.
+    let res = unsafe { libc::mremap(ptr.cast(), PAGE_SIZE, 2 * PAGE_SIZE, 0) };
+    assert_eq!(res, libc::MAP_FAILED);
+    assert_eq!(last_error(), libc::ENOMEM);
+    // Remapping to the same size keeps the mapping where it is.
+    assert_eq!(unsafe { libc::mremap(ptr.cast(), PAGE_SIZE, 100, 0) }, ptr.cast());
+
+    // Growing moves the mapping, keeps the contents, and zeroes the new pages.
+    let ptr = unsafe { libc::mremap(ptr.cast(), PAGE_SIZE, 3 * PAGE_SIZE, libc::MREMAP_MAYMOVE) };
+    assert_ne!(ptr, libc::MAP_FAILED);
+    let pages = unsafe { slice::from_raw_parts_mut(ptr.cast::<u8>(), 3 * PAGE_SIZE) };
+    assert!(pages[..PAGE_SIZE].iter().all(|&b| b == 7));
+    assert!(pages[PAGE_SIZE..].iter().all(|&b| b == 0));
+
+    // Shrinking keeps the beginning of the mapping.
+    let ptr = unsafe { libc::mremap(ptr, 3 * PAGE_SIZE, PAGE_SIZE, libc::MREMAP_MAYMOVE) };
+    assert_ne!(ptr, libc::MAP_FAILED);
+    assert_eq!(unsafe { *ptr.cast::<u8>().add(PAGE_SIZE - 1) }, 7);
+
+    let res = unsafe { libc::mremap(ptr, PAGE_SIZE, 0, libc::MREMAP_MAYMOVE) };
+    assert_eq!(res, libc::MAP_FAILED);
+    assert_eq!(last_error(), libc::EINVAL);
+    assert_eq!(unsafe { libc::munmap(ptr, PAGE_SIZE) }, 0);
+}
+
+fn test_housekeeping() {
+    let ptr = mmap(2 * PAGE_SIZE);
+    unsafe { ptr.write_bytes(1, 2 * PAGE_SIZE) };
+    let page2 = unsafe { ptr.add(PAGE_SIZE) }.cast();
+    unsafe {
+        assert_eq!(libc::msync(ptr.cast(), 2 * PAGE_SIZE, libc::MS_SYNC), 0);
+        assert_eq!(libc::msync(page2, 10, libc::MS_ASYNC | libc::MS_INVALIDATE), 0);
+        assert_eq!(libc::msync(ptr.cast(), PAGE_SIZE, libc::MS_SYNC | libc::MS_ASYNC), -1);
+        assert_eq!(last_error(), libc::EINVAL);
+        assert_eq!(libc::msync(page2, 2 * PAGE_SIZE, libc::MS_SYNC), -1);
+        assert_eq!(last_error(), libc::ENOMEM);
+
+        assert_eq!(libc::madvise(ptr.cast(), 2 * PAGE_SIZE, libc::MADV_WILLNEED), 0);
+        assert_eq!(libc::madvise(ptr.cast(), PAGE_SIZE, -1), -1);
+        assert_eq!(last_error(), libc::EINVAL);
+        assert_eq!(libc::madvise(ptr.add(1).cast(), PAGE_SIZE, libc::MADV_NORMAL), -1);
+        assert_eq!(last_error(), libc::EINVAL);
+        // `MADV_DONTNEED` drops the contents of the pages.
+        assert_eq!(libc::madvise(page2, 1, libc::MADV_DONTNEED), 0);
+        assert_eq!(*ptr.add(PAGE_SIZE - 1), 1);
+        assert!((PAGE_SIZE..2 * PAGE_SIZE).all(|i| *ptr.add(i) == 0));
+
+        // Any memory can be locked, not only mappings.
+        assert_eq!(libc::mlock(ptr.add(10).cast(), 100), 0);
+        assert_eq!(libc::munlock(ptr.add(10).cast(), 100), 0);
+        let local = [0u8; 16];
+        assert_eq!(libc::mlock(local.as_ptr().cast(), local.len()), 0);
+        assert_eq!(libc::mlock(local.as_ptr().cast(), 2 * PAGE_SIZE), -1);
+        assert_eq!(last_error(), libc::ENOMEM);
+        assert_eq!(libc::munlock(local.as_ptr().cast(), local.len()), 0);
+
+        assert_eq!(libc::munmap(ptr.cast(), 2 * PAGE_SIZE), 0);
+    }
+}
+
+fn main() {
+    test_mmap();
+    test_mremap();
+    test_housekeeping();
+}
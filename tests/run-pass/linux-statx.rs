@@ -0,0 +1,97 @@
+// Unfortunately, the test framework does not support 'only-linux',
+// so we need to ignore Windows and macOS instead.
+// ignore-macos: Uses Linux-only APIs
+// ignore-windows: Uses Linux-only APIs
+// compile-flags: -Zmiri-disable-isolation
+#![feature(rustc_private)]
+extern crate libc;
+
+use std::ffi::CString;
+use std::fs::{remove_file, set_permissions, File, Permissions};
+use std::io::{Error, Write};
+use std::mem::MaybeUninit;
+use std::os::unix::ffi::OsStrExt;
+use std::os::unix::fs::{MetadataExt, PermissionsExt};
+use std::os::unix::io::AsRawFd;
+use std::path::{Path, PathBuf};
+
+extern "Rust" {
+    fn miri_host_to_target_path(path: *const u8, out: *mut u8, out_size: usize) -> usize;
+}
+
+fn host_to_target_path(path: String) -> PathBuf {
+    let path = CString::new(path).unwrap();
+    let mut out = Vec::with_capacity(1024);
+    unsafe {
+        let ret = miri_host_to_target_path(path.as_ptr().cast(), out.as_mut_ptr(), out.capacity());
+        assert_eq!(ret, 0);
+        let out = std::ffi::CStr::from_ptr(out.as_ptr().cast());
+        PathBuf::from(out.to_str().unwrap())
+    }
+}
+
+fn tmp() -> PathBuf {
+    std::env::var("MIRI_TEMP").map(host_to_target_path).unwrap_or_else(|_| std::env::temp_dir())
+}
+
+/// Prepare: compute filename and make sure the file does not exist.
+fn prepare(filename: &str) -> PathBuf {
+    let path = tmp().join(filename);
+    remove_file(&path).ok();
+    path
+}
+
+fn errno() -> i32 {
+    Error::last_os_error().raw_os_error().unwrap()
+}
+
+fn statx(dirfd: i32, path: &Path, flags: i32, mask: u32) -> Result<libc::statx, i32> {
+    let path = CString::new(path.as_os_str().as_bytes()).unwrap();
+    let mut buf = MaybeUninit::<libc::statx>::uninit();
+    let res = unsafe { libc::statx(dirfd, path.as_ptr(), flags, mask, buf.as_mut_ptr()) };
+    if res == 0 { Ok(unsafe { buf.assume_init() }) } else { Err(errno()) }
+}
+
+fn main() {
+    let path = prepare("miri_test_statx.txt");
+    File::create(&path).unwrap().write_all(b"Hello, World!").unwrap();
+    set_permissions(&path, Permissions::from_mode(0o640)).unwrap();
+    let metadata = path.metadata().unwrap();
+
+    // The basic fields are filled in, whatever was requested.
+    let buf = statx(libc::AT_FDCWD, &path, 0, libc::STATX_TYPE).unwrap();
+    assert_eq!(buf.stx_mask & libc::STATX_BASIC_STATS, libc::STATX_BASIC_STATS);
+    assert_eq!(u32::from(buf.stx_mode), libc::S_IFREG | 0o640);
+    assert_eq!(buf.stx_size, 13);
+    assert_eq!(buf.stx_ino, metadata.ino());
+    assert_eq!(u64::from(buf.stx_nlink), metadata.nlink());
+    assert_eq!((buf.stx_uid, buf.stx_gid), (metadata.uid(), metadata.gid()));
+    assert_eq!(buf.stx_blocks, metadata.blocks());
+    assert_eq!(buf.stx_mtime.tv_sec, metadata.mtime());
+    assert_eq!(buf.stx_ctime.tv_sec, metadata.ctime());
+    // We know no attributes.
+    assert_eq!((buf.stx_attributes, buf.stx_attributes_mask), (0, 0));
+
+    // The creation time is only filled in if it was requested.
+    assert_eq!(buf.stx_mask & libc::STATX_BTIME, 0);
+    assert_eq!((buf.stx_btime.tv_sec, buf.stx_btime.tv_nsec), (0, 0));
+    let buf = statx(libc::AT_FDCWD, &path, 0, libc::STATX_ALL).unwrap();
+    if buf.stx_mask & libc::STATX_BTIME != 0 {
+        assert!(buf.stx_btime.tv_sec > 0);
+    }
+
+    // An empty path refers to `dirfd`.
+    let file = File::open(&path).unwrap();
+    let buf = statx(file.as_raw_fd(), Path::new(""), libc::AT_EMPTY_PATH, 0).unwrap();
+    assert_eq!(buf.stx_ino, metadata.ino());
+
+    // The reserved mask bit and invalid synchronization types are rejected.
+    let reserved = libc::STATX__RESERVED as u32;
+    assert_eq!(statx(libc::AT_FDCWD, &path, 0, reserved).unwrap_err(), libc::EINVAL);
+    let flags = libc::AT_STATX_SYNC_TYPE;
+    assert_eq!(statx(libc::AT_FDCWD, &path, flags, 0).unwrap_err(), libc::EINVAL);
+    let flags = libc::AT_STATX_DONT_SYNC;
+    assert_eq!(statx(libc::AT_FDCWD, &path, flags, 0).unwrap().stx_size, 13);
+
+    remove_file(&path).unwrap();
+}
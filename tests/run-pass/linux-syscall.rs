@@ -0,0 +1,28 @@
+// Unfortunately, compiletest_rs does not support 'only-linux',
+// so we need to ignore Windows and macOS instead.
+// ignore-macos: Uses Linux-only APIs
+// ignore-windows: Uses Linux-only APIs
+#![feature(rustc_private)]
+extern crate libc;
+
+use std::mem::MaybeUninit;
+use std::thread;
+
+fn gettid() -> libc::c_long {
+    unsafe { libc::syscall(libc::SYS_gettid) }
+}
+
+fn main() {
+    // The main thread has the id of the process.
+    let pid = unsafe { libc::getpid() };
+    assert_eq!(gettid(), pid.into());
+    let other = thread::spawn(gettid).join().unwrap();
+    assert_ne!(other, pid.into());
+
+    assert_eq!(unsafe { libc::syscall(libc::SYS_getpid) }, pid.into());
+
+    let mut ts = MaybeUninit::<libc::timespec>::uninit();
+    let ret =
+        unsafe { libc::syscall(libc::SYS_clock_gettime, libc::CLOCK_MONOTONIC, ts.as_mut_ptr()) };
+    assert_eq!(ret, 0);
+}
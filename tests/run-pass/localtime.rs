@@ -0,0 +1,72 @@
+// ignore-windows: Uses POSIX APIs
+// compile-flags: -Zmiri-utc-offset=+05:30
+#![feature(rustc_private)]
+extern crate libc;
+
+use std::ffi::CStr;
+use std::io::Error;
+use std::mem::{self, MaybeUninit};
+use std::ptr;
+use std::thread;
+use std::time::Duration;
+
+/// With isolation, the system time starts at the Unix epoch and only advances when Miri skips
+/// ahead.
+fn gettimeofday_isolated() {
+    let now = || unsafe {
+        let mut tv = MaybeUninit::<libc::timeval>::uninit();
+        assert_eq!(libc::gettimeofday(tv.as_mut_ptr(), ptr::null_mut()), 0);
+        tv.assume_init()
+    };
+    assert_eq!(now().tv_sec, 0);
+    thread::sleep(Duration::from_secs(2));
+    assert_eq!(now().tv_sec, 2);
+}
+
+/// Times whose local time or year do not fit are reported as overflowing.
+fn overflow() {
+    if mem::size_of::<libc::time_t>() < 8 {
+        return;
+    }
+    unsafe {
+        let mut tm = MaybeUninit::<libc::tm>::uninit();
+        assert!(libc::gmtime_r(&libc::time_t::MAX, tm.as_mut_ptr()).is_null());
+        assert_eq!(Error::last_os_error().raw_os_error(), Some(libc::EOVERFLOW));
+        // Adding the offset of the local time zone overflows.
+        assert!(libc::localtime_r(&(libc::time_t::MAX - 60), tm.as_mut_ptr()).is_null());
+        assert_eq!(Error::last_os_error().raw_os_error(), Some(libc::EOVERFLOW));
+    }
+}
+
+fn main() {
+    // 2022-02-28 23:59:59 UTC, a Monday.
+    let time: libc::time_t = 1646092799;
+    unsafe {
+        let mut tm = MaybeUninit::<libc::tm>::uninit();
+        let tm = &*libc::gmtime_r(&time, tm.as_mut_ptr());
+        assert_eq!((tm.tm_year, tm.tm_mon, tm.tm_mday), (122, 1, 28));
+        assert_eq!((tm.tm_hour, tm.tm_min, tm.tm_sec), (23, 59, 59));
+        assert_eq!((tm.tm_wday, tm.tm_yday, tm.tm_isdst), (1, 58, 0));
+        assert_eq!(tm.tm_gmtoff, 0);
+        assert_eq!(CStr::from_ptr(tm.tm_zone).to_str().unwrap(), "UTC");
+
+        // The local time zone is 5:30 ahead, which is already March.
+        libc::tzset();
+        let mut tm = MaybeUninit::<libc::tm>::uninit();
+        let tm = &*libc::localtime_r(&time, tm.as_mut_ptr());
+        assert_eq!((tm.tm_year, tm.tm_mon, tm.tm_mday), (122, 2, 1));
+        assert_eq!((tm.tm_hour, tm.tm_min, tm.tm_sec), (5, 29, 59));
+        assert_eq!((tm.tm_wday, tm.tm_yday), (2, 59));
+        assert_eq!(tm.tm_gmtoff, 5 * 3600 + 30 * 60);
+        assert_eq!(CStr::from_ptr(tm.tm_zone).to_str().unwrap(), "+0530");
+
+        // Times before the epoch work as well: 1969-12-31 23:59:59 UTC.
+        let mut tm = MaybeUninit::<libc::tm>::uninit();
+        let tm = &*libc::gmtime_r(&-1, tm.as_mut_ptr());
+        assert_eq!((tm.tm_year, tm.tm_mon, tm.tm_mday, tm.tm_hour), (69, 11, 31, 23));
+        assert_eq!((tm.tm_wday, tm.tm_yday), (3, 364));
+    }
+
+    gettimeofday_isolated();
+    overflow();
+}
@@ -0,0 +1,33 @@
+// ignore-windows: No libc on Windows
+#![feature(rustc_private)]
+extern crate libc;
+
+#[cfg(target_os = "linux")]
+unsafe fn usable_size(ptr: *mut libc::c_void) -> usize {
+    libc::malloc_usable_size(ptr)
+}
+
+#[cfg(target_os = "macos")]
+unsafe fn usable_size(ptr: *mut libc::c_void) -> usize {
+    extern "C" {
+        fn malloc_size(ptr: *const libc::c_void) -> usize;
+    }
+    malloc_size(ptr)
+}
+
+fn main() {
+    unsafe {
+        assert_eq!(usable_size(std::ptr::null_mut()), 0);
+
+        let p = libc::malloc(13);
+        assert!(usable_size(p) >= 13);
+        let p = libc::realloc(p, 100);
+        assert!(usable_size(p) >= 100);
+        libc::free(p);
+
+        let mut p = std::ptr::null_mut();
+        assert_eq!(libc::posix_memalign(&mut p, 64, 24), 0);
+        assert!(usable_size(p) >= 24);
+        libc::free(p);
+    }
+}
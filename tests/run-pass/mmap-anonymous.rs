@@ -0,0 +1,47 @@
+// ignore-windows: Uses POSIX APIs
+#![feature(rustc_private)]
+extern crate libc;
+
+use std::ptr;
+use std::slice;
+
+/// A bump allocator on top of an anonymous mapping, like allocator crates use.
+struct Arena {
+    base: *mut u8,
+    len: usize,
+    used: usize,
+}
+
+impl Arena {
+    fn new(len: usize) -> Self {
+        let prot = libc::PROT_READ | libc::PROT_WRITE;
+        let flags = libc::MAP_PRIVATE | libc::MAP_ANONYMOUS;
+        let base = unsafe { libc::mmap(ptr::null_mut(), len, prot, flags, -1, 0) };
+        assert_ne!(base, libc::MAP_FAILED);
+        Arena { base: base.cast(), len, used: 0 }
+    }
+
+    fn alloc(&mut self, size: usize) -> &mut [u8] {
+        assert!(self.used + size <= self.len);
+        let slice = unsafe { slice::from_raw_parts_mut(self.base.add(self.used), size) };
+        self.used += size;
+        slice
+    }
+}
+
+impl Drop for Arena {
+    fn drop(&mut self) {
+        assert_eq!(unsafe { libc::munmap(self.base.cast(), self.len) }, 0);
+    }
+}
+
+fn main() {
+    let mut arena = Arena::new(3 * 4096);
+    let a = arena.alloc(100);
+    // Anonymous mappings are zeroed.
+    assert!(a.iter().all(|&b| b == 0));
+    a.fill(1);
+    let b = arena.alloc(8000);
+    b[7999] = 2;
+    assert_eq!(arena.alloc(1)[0], 0);
+}
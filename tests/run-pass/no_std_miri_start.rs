@@ -0,0 +1,23 @@
+// compile-flags: -Cpanic=abort
+#![feature(core_intrinsics)]
+#![no_std]
+#![no_main]
+
+#[panic_handler]
+fn panic_handler(_: &core::panic::PanicInfo) -> ! {
+    core::intrinsics::abort()
+}
+
+#[no_mangle]
+fn miri_start(argc: isize, argv: *const *const u8) -> isize {
+    // The arguments are set up like for `main`, starting with the name of the program.
+    assert!(argc >= 1);
+    let arg0 = unsafe { *argv };
+    assert!(!arg0.is_null());
+    assert_ne!(unsafe { *arg0 }, 0);
+
+    let mut v = [3, 1, 2];
+    v.sort_unstable();
+    assert_eq!(v, [1, 2, 3]);
+    0
+}
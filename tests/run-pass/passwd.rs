@@ -0,0 +1,46 @@
+// ignore-windows: No libc on Windows
+// compile-flags: -Zmiri-home-dir=/home/tester
+#![feature(rustc_private)]
+extern crate libc;
+
+use std::ffi::CStr;
+use std::mem::MaybeUninit;
+use std::path::Path;
+use std::ptr;
+
+fn main() {
+    // With isolation, `HOME` is not set, so this asks the user database.
+    #[allow(deprecated)]
+    let home = std::env::home_dir().unwrap();
+    assert_eq!(home, Path::new("/home/tester"));
+
+    unsafe {
+        let uid = libc::getuid();
+        let mut pwd = MaybeUninit::<libc::passwd>::uninit();
+        let mut buf = [0 as libc::c_char; 256];
+        let mut result = ptr::null_mut();
+        let name = b"miri\0".as_ptr().cast();
+        let ret =
+            libc::getpwnam_r(name, pwd.as_mut_ptr(), buf.as_mut_ptr(), buf.len(), &mut result);
+        assert_eq!(ret, 0);
+        assert_eq!(result, pwd.as_mut_ptr());
+        let pwd = pwd.assume_init();
+        assert_eq!(pwd.pw_uid, uid);
+        assert_eq!(CStr::from_ptr(pwd.pw_name).to_bytes(), b"miri");
+        assert_eq!(CStr::from_ptr(pwd.pw_dir).to_bytes(), b"/home/tester");
+
+        // The strings do not fit into the buffer.
+        let mut pwd = MaybeUninit::<libc::passwd>::uninit();
+        let mut small = [0 as libc::c_char; 4];
+        let ret =
+            libc::getpwuid_r(uid, pwd.as_mut_ptr(), small.as_mut_ptr(), small.len(), &mut result);
+        assert_eq!(ret, libc::ERANGE);
+        assert!(result.is_null());
+
+        // Unknown users are not an error.
+        let ret =
+            libc::getpwuid_r(uid + 1, pwd.as_mut_ptr(), buf.as_mut_ptr(), buf.len(), &mut result);
+        assert_eq!(ret, 0);
+        assert!(result.is_null());
+    }
+}
@@ -0,0 +1,33 @@
+// Test the extern functions that convert paths between the host and the target conventions.
+
+use std::ffi::{CStr, CString};
+
+extern "Rust" {
+    fn miri_host_to_target_path(path: *const u8, out: *mut u8, out_size: usize) -> usize;
+    fn miri_target_to_host_path(path: *const u8, out: *mut u8, out_size: usize) -> usize;
+}
+
+fn convert(f: unsafe fn(*const u8, *mut u8, usize) -> usize, path: &str) -> String {
+    let path = CString::new(path).unwrap();
+    let mut out = Vec::<u8>::with_capacity(1);
+    unsafe {
+        // The buffer is too small, so we get the required size instead.
+        let size = f(path.as_ptr().cast(), out.as_mut_ptr(), out.capacity());
+        assert_eq!(size, path.as_bytes_with_nul().len());
+        out.reserve(size);
+        assert_eq!(f(path.as_ptr().cast(), out.as_mut_ptr(), out.capacity()), 0);
+        CStr::from_ptr(out.as_ptr().cast()).to_str().unwrap().to_owned()
+    }
+}
+
+fn main() {
+    let target_path = convert(miri_host_to_target_path, "a/b\\c");
+    assert_eq!(target_path.len(), 5);
+    assert_eq!(convert(miri_target_to_host_path, &target_path).len(), 5);
+    if cfg!(windows) {
+        assert_eq!(target_path, "a\\b\\c");
+    }
+    // Round-tripping a target path does not change it.
+    let host_path = convert(miri_target_to_host_path, &target_path);
+    assert_eq!(convert(miri_host_to_target_path, &host_path), target_path);
+}
@@ -0,0 +1,37 @@
+// ignore-windows: No libc on Windows
+// compile-flags: -Zmiri-pretend-tty=stdout,stderr -Zmiri-tty-size=100x30
+#![feature(rustc_private)]
+extern crate libc;
+
+use std::io::Error;
+use std::mem::MaybeUninit;
+
+fn test_isatty() {
+    unsafe {
+        assert_eq!(libc::isatty(libc::STDIN_FILENO), 0);
+        assert_eq!(Error::last_os_error().raw_os_error(), Some(libc::ENOTTY));
+        assert_eq!(libc::isatty(libc::STDOUT_FILENO), 1);
+        assert_eq!(libc::isatty(libc::STDERR_FILENO), 1);
+        assert_eq!(libc::isatty(42), 0);
+        assert_eq!(Error::last_os_error().raw_os_error(), Some(libc::EBADF));
+    }
+}
+
+fn test_winsize() {
+    unsafe {
+        let mut winsize = MaybeUninit::<libc::winsize>::uninit();
+        assert_eq!(libc::ioctl(libc::STDOUT_FILENO, libc::TIOCGWINSZ, winsize.as_mut_ptr()), 0);
+        let winsize = winsize.assume_init();
+        assert_eq!((winsize.ws_col, winsize.ws_row), (100, 30));
+        assert_eq!((winsize.ws_xpixel, winsize.ws_ypixel), (0, 0));
+
+        let mut winsize = MaybeUninit::<libc::winsize>::uninit();
+        assert_eq!(libc::ioctl(libc::STDIN_FILENO, libc::TIOCGWINSZ, winsize.as_mut_ptr()), -1);
+        assert_eq!(Error::last_os_error().raw_os_error(), Some(libc::ENOTTY));
+    }
+}
+
+fn main() {
+    test_isatty();
+    test_winsize();
+}
@@ -0,0 +1,23 @@
+// ignore-windows: No libc on Windows
+// compile-flags: -Zmiri-process-spawn=reject-silent
+#![feature(rustc_private)]
+extern crate libc;
+
+use std::io::{Error, ErrorKind};
+use std::process::Command;
+
+fn main() {
+    // Spawning fails with an error the program can handle.
+    let err = Command::new("true").spawn().unwrap_err();
+    assert_eq!(err.kind(), ErrorKind::Unsupported);
+    assert_eq!(err.raw_os_error(), Some(libc::ENOSYS));
+
+    unsafe {
+        assert_eq!(libc::fork(), -1);
+        assert_eq!(Error::last_os_error().raw_os_error(), Some(libc::ENOSYS));
+
+        let mut status = 0;
+        assert_eq!(libc::waitpid(-1, &mut status, 0), -1);
+        assert_eq!(Error::last_os_error().raw_os_error(), Some(libc::ECHILD));
+    }
+}
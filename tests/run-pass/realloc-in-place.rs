@@ -0,0 +1,26 @@
+// ignore-windows: No libc on Windows
+// compile-flags: -Zmiri-realloc=in-place
+#![feature(rustc_private)]
+extern crate libc;
+
+use std::alloc::{alloc, dealloc, realloc, Layout};
+
+fn main() {
+    unsafe {
+        let p1 = libc::malloc(20) as *mut u8;
+        *p1 = 42;
+        let p2 = libc::realloc(p1 as *mut libc::c_void, 10) as *mut u8;
+        assert_eq!(p1 as usize, p2 as usize);
+        assert_eq!(*p2, 42);
+
+        libc::free(p2 as *mut libc::c_void);
+
+        let layout = Layout::from_size_align(16, 8).unwrap();
+        let p4 = alloc(layout);
+        *p4.add(7) = 7;
+        let p5 = realloc(p4, layout, 8);
+        assert_eq!(p4 as usize, p5 as usize);
+        assert_eq!(*p5.add(7), 7);
+        dealloc(p5, Layout::from_size_align(8, 8).unwrap());
+    }
+}
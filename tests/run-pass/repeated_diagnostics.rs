@@ -0,0 +1,26 @@
+// compile-flags: -Zmiri-warn=int2ptr -Zmiri-backtrace=0 -Zmiri-disable-validation
+// normalize-stderr-test "alloc[0-9]+" -> "ALLOC"
+// normalize-stderr-test "0x[0-9a-f]+ points" -> "$$HEX points"
+// normalize-stderr-test "[0-9]+ \|" -> "LL |"
+
+// A warning in a loop is only shown the first few times, the rest is summarized at the end.
+// Watchpoints were asked for explicitly, so every access to watched memory is shown.
+
+extern "Rust" {
+    fn miri_watch(ptr: *const u8, size: usize);
+}
+
+fn main() {
+    let x = 42u8;
+    let addr = &x as *const u8 as usize;
+    for _ in 0..5 {
+        let ptr = addr as *const u8;
+        assert_eq!(unsafe { *ptr }, 42);
+    }
+
+    let mut y = [0u8; 2];
+    unsafe { miri_watch(y.as_ptr(), 1) };
+    for i in 1..5 {
+        y[0] = i;
+    }
+}
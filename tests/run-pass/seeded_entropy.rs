@@ -0,0 +1,23 @@
+// ignore-windows: No /dev/urandom on Windows
+// compile-flags: -Zmiri-disable-isolation -Zmiri-seed=1
+// With a seed, `/dev/urandom` is emulated even though isolation is disabled.
+use std::fs::File;
+use std::io::Read;
+
+fn main() {
+    let mut file = File::open("/dev/urandom").unwrap();
+    let mut first = [0u8; 16];
+    file.read_exact(&mut first).unwrap();
+
+    // A duplicated descriptor continues the same stream.
+    let mut dup = file.try_clone().unwrap();
+    let mut second = [0u8; 16];
+    dup.read_exact(&mut second).unwrap();
+    assert_ne!(first, second);
+
+    // Writing is accepted and ignored.
+    std::io::Write::write_all(&mut dup, b"entropy").unwrap();
+
+    let mut random = File::open("/dev/random").unwrap();
+    random.read_exact(&mut first).unwrap();
+}
@@ -0,0 +1,15 @@
+// ignore-windows: `thread::sleep` with a timeout is not supported on Windows
+use std::thread;
+use std::time::Duration;
+
+fn main() {
+    // Sleeping does not need access to the host, and does not actually wait.
+    thread::sleep(Duration::from_secs(60 * 60 * 24));
+
+    let handles: Vec<_> = (0..4)
+        .map(|i| thread::spawn(move || thread::sleep(Duration::from_secs(60 * 60 * i))))
+        .collect();
+    for handle in handles {
+        handle.join().unwrap();
+    }
+}
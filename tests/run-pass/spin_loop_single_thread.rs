@@ -0,0 +1,24 @@
+// compile-flags: -Zmiri-disable-isolation -Zmiri-spin-loop-limit=100
+
+// Yielding when there is no other thread to yield to is not a spin deadlock, however often it
+// happens.
+
+use std::hint;
+use std::thread;
+use std::time::{Duration, Instant};
+
+fn main() {
+    // A backoff loop.
+    for _ in 0..1000 {
+        hint::spin_loop();
+    }
+    for _ in 0..1000 {
+        thread::yield_now();
+    }
+
+    // A time-bounded spin.
+    let deadline = Instant::now() + Duration::from_millis(10);
+    while Instant::now() < deadline {
+        hint::spin_loop();
+    }
+}
@@ -0,0 +1,35 @@
+// normalize-stderr-test "alloc[0-9]+" -> "ALLOC"
+// normalize-stderr-test "<[0-9]+>" -> "<TAG>"
+
+use std::ptr;
+
+extern "Rust" {
+    fn miri_print_borrow_stacks(ptr: *const u8, size: usize);
+}
+
+fn print_stacks<T>(ptr: *mut T, size: usize) {
+    unsafe { miri_print_borrow_stacks(ptr as *const u8, size) };
+}
+
+fn main() {
+    let mut x = 0u32;
+    // `addr_of_mut!` does not retag, so `p` uses the tag of `x` itself.
+    let p = ptr::addr_of_mut!(x);
+    print_stacks(p, 4);
+    let r = unsafe { &mut *p };
+    *r = 1;
+    print_stacks(p, 4);
+    // Reading through `p` disables `r`.
+    let _s = unsafe { &*p };
+    print_stacks(p, 4);
+    // Writing through `p` pops everything above it.
+    unsafe { *p = 2 };
+    print_stacks(p, 4);
+
+    // Locations with different stacks are printed separately.
+    let mut pair = [0u32; 2];
+    let q = ptr::addr_of_mut!(pair) as *mut u32;
+    let first = unsafe { &mut *q };
+    *first = 1;
+    print_stacks(q, 8);
+}
@@ -0,0 +1,27 @@
+// The program exits while `main` and `outer` are still on the stack. `tests/compiletest.rs` also
+// runs this with `-Zmiri-profile-interpreted` and checks that their steps are counted anyway.
+// Each function spins for a while itself, so that it shows up among the top functions.
+
+fn spin() -> u64 {
+    let mut sum = 0u64;
+    let mut i = 0;
+    while i < 1000 {
+        sum = sum.wrapping_add(i);
+        i += 1;
+    }
+    sum
+}
+
+fn work() -> u64 {
+    spin()
+}
+
+fn outer() {
+    let sum = spin() + work();
+    std::process::exit(if sum == 2 * 499500 { 0 } else { 1 });
+}
+
+fn main() {
+    spin();
+    outer();
+}
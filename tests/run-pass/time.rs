@@ -17,6 +17,16 @@ fn test_sleep() {
     assert!((after - before).as_millis() >= 100);
 }
 
+// Back-to-back reads of the monotonic clock never return the same time.
+fn test_instant_strictly_monotonic() {
+    let mut last = Instant::now();
+    for _ in 0..10 {
+        let now = Instant::now();
+        assert!(now > last);
+        last = now;
+    }
+}
+
 fn main() {
     // Check `SystemTime`.
     let now1 = SystemTime::now();
@@ -46,6 +56,7 @@ fn main() {
     assert_eq!(now2 - diff, now1);
     duration_sanity(diff);
 
+    test_instant_strictly_monotonic();
     #[cfg(unix)]
     test_sleep();
 }
@@ -0,0 +1,22 @@
+// compile-flags: -Zmiri-disable-isolation -Zmiri-time-anomalies -Zmiri-seed=2a
+
+use std::time::{Instant, SystemTime};
+
+fn main() {
+    let mut backward = false;
+    let mut last_system = SystemTime::now();
+    let mut last_instant = Instant::now();
+    for _ in 0..200 {
+        let system = SystemTime::now();
+        let instant = Instant::now();
+        // The system time may go backwards...
+        if system < last_system {
+            backward = true;
+        }
+        // ...but `Instant` never does.
+        assert!(instant > last_instant);
+        last_system = system;
+        last_instant = instant;
+    }
+    assert!(backward, "the system time never went backwards");
+}
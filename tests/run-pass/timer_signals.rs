@@ -0,0 +1,36 @@
+// ignore-windows: No timers or signals on Windows
+// compile-flags: -Zmiri-deliver-timer-signals
+#![feature(rustc_private)]
+extern crate libc;
+
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::time::Duration;
+
+static FIRED: AtomicBool = AtomicBool::new(false);
+
+extern "C" fn on_alarm(signum: libc::c_int) {
+    assert_eq!(signum, libc::SIGALRM);
+    FIRED.store(true, Ordering::Relaxed);
+}
+
+fn main() {
+    unsafe {
+        // Re-arming reports the time that was left.
+        assert_eq!(libc::alarm(100), 0);
+        assert_eq!(libc::alarm(0), 100);
+
+        let mut current: libc::itimerval = std::mem::zeroed();
+        assert_eq!(libc::getitimer(libc::ITIMER_REAL, &mut current), 0);
+        assert_eq!(current.it_value.tv_sec, 0);
+
+        libc::signal(libc::SIGALRM, on_alarm as libc::sighandler_t);
+        let timer = libc::itimerval {
+            it_interval: libc::timeval { tv_sec: 0, tv_usec: 0 },
+            it_value: libc::timeval { tv_sec: 0, tv_usec: 1000 },
+        };
+        assert_eq!(libc::setitimer(libc::ITIMER_REAL, &timer, std::ptr::null_mut()), 0);
+    }
+    // The handler runs once the deadline has passed and the program takes another step.
+    std::thread::sleep(Duration::from_millis(10));
+    assert!(FIRED.load(Ordering::Relaxed));
+}
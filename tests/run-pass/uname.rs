@@ -0,0 +1,34 @@
+// ignore-windows: No libc on Windows
+// compile-flags: -Zmiri-hostname=testhost -Zmiri-kernel-release=6.1.0
+#![feature(rustc_private)]
+extern crate libc;
+
+use std::ffi::CStr;
+use std::mem::MaybeUninit;
+
+fn to_str(field: &[libc::c_char]) -> &str {
+    unsafe { CStr::from_ptr(field.as_ptr()) }.to_str().unwrap()
+}
+
+fn main() {
+    let mut uts = MaybeUninit::<libc::utsname>::uninit();
+    assert_eq!(unsafe { libc::uname(uts.as_mut_ptr()) }, 0);
+    let uts = unsafe { uts.assume_init() };
+    if cfg!(target_os = "linux") {
+        assert_eq!(to_str(&uts.sysname), "Linux");
+    } else {
+        assert_eq!(to_str(&uts.sysname), "Darwin");
+    }
+    assert_eq!(to_str(&uts.nodename), "testhost");
+    assert_eq!(to_str(&uts.release), "6.1.0");
+    assert!(!to_str(&uts.machine).is_empty());
+
+    let mut buf = [0 as libc::c_char; 64];
+    assert_eq!(unsafe { libc::gethostname(buf.as_mut_ptr(), buf.len()) }, 0);
+    assert_eq!(to_str(&buf), "testhost");
+
+    // The buffer is too small for the name and its terminator.
+    let mut small = [0 as libc::c_char; 8];
+    assert_eq!(unsafe { libc::gethostname(small.as_mut_ptr(), small.len()) }, -1);
+    assert_eq!(std::io::Error::last_os_error().raw_os_error(), Some(libc::ENAMETOOLONG));
+}
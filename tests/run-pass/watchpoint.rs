@@ -0,0 +1,19 @@
+// compile-flags: -Zmiri-backtrace=0 -Zmiri-disable-validation
+// normalize-stderr-test "alloc[0-9]+" -> "ALLOC"
+// normalize-stderr-test "[0-9]+ \|" -> "LL |"
+
+// Every access to watched memory is reported, and accesses next to it are not.
+
+extern "Rust" {
+    fn miri_watch(ptr: *const u8, size: usize);
+}
+
+fn main() {
+    let mut x = [0u8; 4];
+    unsafe { miri_watch(x.as_ptr().add(1), 2) };
+    x[0] = 1;
+    x[1] = 2;
+    let _y = x[2];
+    x[2] = 3;
+    let _z = x[3];
+}
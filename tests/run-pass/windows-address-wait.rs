@@ -0,0 +1,94 @@
+// Unfortunately, compiletest_rs does not support 'only-windows',
+// so we need to ignore Linux and macOS instead.
+// ignore-linux: Uses Windows-only APIs
+// ignore-macos: Uses Windows-only APIs
+use std::ffi::c_void;
+use std::ptr;
+use std::thread;
+use std::time::{Duration, Instant};
+
+type HANDLE = *mut c_void;
+
+const ERROR_TIMEOUT: u32 = 1460;
+const STATUS_SUCCESS: u32 = 0;
+const STATUS_TIMEOUT: u32 = 0x102;
+const STATUS_INVALID_HANDLE: u32 = 0xC0000008;
+const STATUS_INVALID_PARAMETER: u32 = 0xC000000D;
+
+extern "system" {
+    fn WaitOnAddress(addr: *const c_void, compare: *const c_void, size: usize, ms: u32) -> i32;
+    fn WakeByAddressSingle(addr: *const c_void);
+    fn WakeByAddressAll(addr: *const c_void);
+    fn GetLastError() -> u32;
+    fn GetProcAddress(module: HANDLE, name: *const u8) -> *const c_void;
+    fn CloseHandle(handle: HANDLE) -> i32;
+    fn NtCreateKeyedEvent(handle: *mut HANDLE, access: u32, attrs: *mut c_void, flags: u32) -> u32;
+    fn NtWaitForKeyedEvent(handle: HANDLE, key: *const c_void, alert: u8, timeout: *const i64)
+    -> u32;
+    fn NtReleaseKeyedEvent(handle: HANDLE, key: *const c_void, alert: u8, timeout: *const i64)
+    -> u32;
+}
+
+fn wait_on_address() {
+    let value = 1u32;
+    let addr = &value as *const u32 as *const c_void;
+    unsafe {
+        // A different value does not wait.
+        let other = 2u32;
+        assert_eq!(WaitOnAddress(addr, &other as *const u32 as *const c_void, 4, u32::MAX), 1);
+
+        // The same value waits until the timeout, since nothing wakes it.
+        let start = Instant::now();
+        assert_eq!(WaitOnAddress(addr, addr, 4, 100), 0);
+        assert_eq!(GetLastError(), ERROR_TIMEOUT);
+        assert!(start.elapsed() >= Duration::from_millis(100));
+        assert_eq!(WaitOnAddress(addr, addr, 4, 0), 0);
+        assert_eq!(GetLastError(), ERROR_TIMEOUT);
+
+        // Waking an address that nobody waits on does nothing.
+        WakeByAddressSingle(addr);
+        WakeByAddressAll(addr);
+
+        // The functions are also available through `GetProcAddress`.
+        assert!(!GetProcAddress(ptr::null_mut(), b"WaitOnAddress\0".as_ptr()).is_null());
+        assert!(!GetProcAddress(ptr::null_mut(), b"NtWaitForKeyedEvent\0".as_ptr()).is_null());
+    }
+}
+
+fn keyed_events() {
+    let key = 0u64;
+    let key = &key as *const u64 as *const c_void;
+    unsafe {
+        let mut handle = ptr::null_mut();
+        assert_eq!(NtCreateKeyedEvent(&mut handle, 0, ptr::null_mut(), 0), STATUS_SUCCESS);
+
+        // Without a thread waiting, releasing a key times out, and so does waiting without a
+        // thread releasing it.
+        assert_eq!(NtReleaseKeyedEvent(handle, key, 0, &0), STATUS_TIMEOUT);
+        let start = Instant::now();
+        assert_eq!(NtWaitForKeyedEvent(handle, key, 0, &-1_000_000), STATUS_TIMEOUT);
+        assert!(start.elapsed() >= Duration::from_millis(100));
+
+        // Keys must be even.
+        let odd_key = (key as usize + 1) as *const c_void;
+        assert_eq!(NtWaitForKeyedEvent(handle, odd_key, 0, &0), STATUS_INVALID_PARAMETER);
+
+        assert_eq!(CloseHandle(handle), 1);
+        assert_eq!(NtWaitForKeyedEvent(handle, key, 0, &0), STATUS_INVALID_HANDLE);
+    }
+}
+
+fn park() {
+    // Thread parking in `std` uses these functions.
+    thread::current().unpark();
+    thread::park();
+    let start = Instant::now();
+    thread::park_timeout(Duration::from_millis(100));
+    assert!(start.elapsed() >= Duration::from_millis(100));
+}
+
+fn main() {
+    wait_on_address();
+    keyed_events();
+    park();
+}
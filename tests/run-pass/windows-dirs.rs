@@ -0,0 +1,82 @@
+// ignore-linux: Uses Windows-only APIs
+// ignore-macos: Uses Windows-only APIs
+// compile-flags: -Zmiri-temp-dir=C:\Users\miri\Temp -Zmiri-exe-path=C:\Users\miri\bin\test.exe
+use std::ffi::{c_void, OsString};
+use std::os::windows::ffi::OsStringExt;
+use std::path::PathBuf;
+use std::ptr;
+
+const ERROR_INSUFFICIENT_BUFFER: u32 = 122;
+
+#[repr(C)]
+struct Guid {
+    data1: u32,
+    data2: u16,
+    data3: u16,
+    data4: [u8; 8],
+}
+
+const FOLDERID_LOCAL_APP_DATA: Guid = Guid {
+    data1: 0xF1B32785,
+    data2: 0x6FBA,
+    data3: 0x4FCF,
+    data4: [0x9D, 0x55, 0x7B, 0x8E, 0x7F, 0x15, 0x70, 0x91],
+};
+
+extern "system" {
+    fn GetTempPathW(size: u32, buf: *mut u16) -> u32;
+    fn GetModuleFileNameW(module: *mut c_void, buf: *mut u16, size: u32) -> u32;
+    fn GetCurrentProcess() -> *mut c_void;
+    fn OpenProcessToken(process: *mut c_void, access: u32, token: *mut *mut c_void) -> i32;
+    fn GetUserProfileDirectoryW(token: *mut c_void, buf: *mut u16, size: *mut u32) -> i32;
+    fn SHGetKnownFolderPath(
+        id: *const Guid,
+        flags: u32,
+        token: *mut c_void,
+        path: *mut *mut u16,
+    ) -> i32;
+    fn CoTaskMemFree(ptr: *mut c_void);
+    fn CloseHandle(handle: *mut c_void) -> i32;
+    fn GetLastError() -> u32;
+}
+
+fn main() {
+    // With isolation, the temporary directory is the configured one, and the home directory the
+    // default.
+    assert_eq!(std::env::temp_dir(), PathBuf::from(r"C:\Users\miri\Temp\"));
+    #[allow(deprecated)]
+    let home_dir = std::env::home_dir();
+    assert_eq!(home_dir, Some(PathBuf::from(r"C:\Users\miri")));
+    let exe_path = r"C:\Users\miri\bin\test.exe";
+    assert_eq!(std::env::current_exe().unwrap(), PathBuf::from(exe_path));
+
+    unsafe {
+        // Without room for the path, the size it needs (with the terminator) is returned.
+        let size = GetTempPathW(0, ptr::null_mut());
+        let mut buf = vec![0u16; size as usize];
+        assert_eq!(GetTempPathW(size, buf.as_mut_ptr()), size - 1);
+
+        // A buffer that is too small gets as much of the path as fits, with the terminator.
+        let mut buf = [1u16; 4];
+        assert_eq!(GetModuleFileNameW(ptr::null_mut(), buf.as_mut_ptr(), 4), 4);
+        assert_eq!(GetLastError(), ERROR_INSUFFICIENT_BUFFER);
+        assert_eq!(OsString::from_wide(&buf), "C:\\\0");
+
+        let mut token = ptr::null_mut();
+        assert!(OpenProcessToken(GetCurrentProcess(), 0x8, &mut token) != 0);
+        let mut size = 4;
+        let mut buf = vec![0u16; 4];
+        assert_eq!(GetUserProfileDirectoryW(token, buf.as_mut_ptr(), &mut size), 0);
+        assert_eq!(GetLastError(), ERROR_INSUFFICIENT_BUFFER);
+        assert_eq!(size as usize, r"C:\Users\miri".len() + 1);
+        assert!(CloseHandle(token) != 0);
+
+        let mut path = ptr::null_mut();
+        let id = &FOLDERID_LOCAL_APP_DATA;
+        assert_eq!(SHGetKnownFolderPath(id, 0, ptr::null_mut(), &mut path), 0);
+        let len = (0..).find(|&i| *path.add(i) == 0).unwrap();
+        let path_str = OsString::from_wide(std::slice::from_raw_parts(path, len));
+        assert_eq!(path_str, r"C:\Users\miri\AppData\Local");
+        CoTaskMemFree(path.cast());
+    }
+}
@@ -0,0 +1,63 @@
+// Unfortunately, compiletest_rs does not support 'only-windows',
+// so we need to ignore Linux and macOS instead.
+// ignore-linux: Uses Windows-only APIs
+// ignore-macos: Uses Windows-only APIs
+use std::ffi::c_void;
+use std::ptr;
+
+type HANDLE = *mut c_void;
+
+const INFINITE: u32 = u32::MAX;
+const WAIT_OBJECT_0: u32 = 0;
+const WAIT_TIMEOUT: u32 = 0x102;
+const WAIT_FAILED: u32 = u32::MAX;
+
+extern "system" {
+    fn CreateEventW(attrs: *mut c_void, manual: i32, initial: i32, name: *const u16) -> HANDLE;
+    fn SetEvent(event: HANDLE) -> i32;
+    fn ResetEvent(event: HANDLE) -> i32;
+    fn CreateMutexW(attrs: *mut c_void, initial_owner: i32, name: *const u16) -> HANDLE;
+    fn ReleaseMutex(mutex: HANDLE) -> i32;
+    fn WaitForSingleObject(handle: HANDLE, milliseconds: u32) -> u32;
+    fn WaitForMultipleObjects(count: u32, handles: *const HANDLE, all: i32, ms: u32) -> u32;
+    fn CloseHandle(handle: HANDLE) -> i32;
+}
+
+fn main() {
+    unsafe {
+        // An auto-reset event is reset by a successful wait.
+        let auto = CreateEventW(ptr::null_mut(), 0, 1, ptr::null());
+        assert_eq!(WaitForSingleObject(auto, INFINITE), WAIT_OBJECT_0);
+        assert_eq!(WaitForSingleObject(auto, 0), WAIT_TIMEOUT);
+        // Waiting with a timeout does not actually wait.
+        assert_eq!(WaitForSingleObject(auto, 60 * 60 * 1000), WAIT_TIMEOUT);
+
+        // A manual-reset event stays signaled until it is reset.
+        let manual = CreateEventW(ptr::null_mut(), 1, 0, ptr::null());
+        assert_eq!(SetEvent(manual), 1);
+        assert_eq!(WaitForSingleObject(manual, 0), WAIT_OBJECT_0);
+        assert_eq!(WaitForSingleObject(manual, 0), WAIT_OBJECT_0);
+        assert_eq!(ResetEvent(manual), 1);
+        assert_eq!(WaitForSingleObject(manual, 10), WAIT_TIMEOUT);
+
+        let handles = [auto, manual];
+        SetEvent(manual);
+        assert_eq!(WaitForMultipleObjects(2, handles.as_ptr(), 0, 0), WAIT_OBJECT_0 + 1);
+        assert_eq!(WaitForMultipleObjects(2, handles.as_ptr(), 1, 0), WAIT_TIMEOUT);
+        SetEvent(auto);
+        assert_eq!(WaitForMultipleObjects(2, handles.as_ptr(), 1, 0), WAIT_OBJECT_0);
+
+        // Mutexes are recursive, and can only be released by their owner.
+        let mutex = CreateMutexW(ptr::null_mut(), 0, ptr::null());
+        assert_eq!(WaitForSingleObject(mutex, INFINITE), WAIT_OBJECT_0);
+        assert_eq!(WaitForSingleObject(mutex, INFINITE), WAIT_OBJECT_0);
+        assert_eq!(ReleaseMutex(mutex), 1);
+        assert_eq!(ReleaseMutex(mutex), 1);
+        assert_eq!(ReleaseMutex(mutex), 0);
+
+        for handle in [auto, manual, mutex] {
+            assert_eq!(CloseHandle(handle), 1);
+        }
+        assert_eq!(WaitForSingleObject(auto, 0), WAIT_FAILED);
+    }
+}
@@ -0,0 +1,215 @@
+// ignore-linux: Uses Windows-only APIs
+// ignore-macos: Uses Windows-only APIs
+// compile-flags: -Zmiri-disable-isolation
+#![feature(io_error_more)]
+use std::ffi::{c_void, CString, OsString};
+use std::fs::{self, File};
+use std::io::{ErrorKind, Read, Write};
+use std::os::windows::ffi::{OsStrExt, OsStringExt};
+use std::path::{Path, PathBuf};
+use std::ptr;
+
+type HANDLE = *mut c_void;
+
+const GENERIC_WRITE: u32 = 0x40000000;
+const CREATE_ALWAYS: u32 = 2;
+const OPEN_EXISTING: u32 = 3;
+const FILE_FLAG_BACKUP_SEMANTICS: u32 = 0x02000000;
+const VOLUME_NAME_DOS: u32 = 0x0;
+const INVALID_HANDLE_VALUE: HANDLE = -1isize as HANDLE;
+const ERROR_INVALID_HANDLE: u32 = 6;
+const STD_OUTPUT_HANDLE: u32 = -11i32 as u32;
+const STD_ERROR_HANDLE: u32 = -12i32 as u32;
+
+extern "system" {
+    fn CreateFileW(
+        name: *const u16,
+        access: u32,
+        share_mode: u32,
+        security_attributes: *mut c_void,
+        creation_disposition: u32,
+        flags_and_attributes: u32,
+        template_file: HANDLE,
+    ) -> HANDLE;
+    fn GetFinalPathNameByHandleW(file: HANDLE, buf: *mut u16, size: u32, flags: u32) -> u32;
+    fn CloseHandle(handle: HANDLE) -> i32;
+    fn GetStdHandle(which: u32) -> HANDLE;
+    fn GetLastError() -> u32;
+}
+
+extern "Rust" {
+    fn miri_host_to_target_path(path: *const u8, out: *mut u8, out_size: usize) -> usize;
+}
+
+fn host_to_target_path(path: String) -> PathBuf {
+    let path = CString::new(path).unwrap();
+    let mut out = Vec::with_capacity(1024);
+    unsafe {
+        let ret = miri_host_to_target_path(path.as_ptr().cast(), out.as_mut_ptr(), out.capacity());
+        assert_eq!(ret, 0);
+        let out = std::ffi::CStr::from_ptr(out.as_ptr().cast());
+        PathBuf::from(out.to_str().unwrap())
+    }
+}
+
+fn tmp() -> PathBuf {
+    std::env::var("MIRI_TEMP").map(host_to_target_path).unwrap_or_else(|_| std::env::temp_dir())
+}
+
+fn to_wide(path: &Path) -> Vec<u16> {
+    path.as_os_str().encode_wide().chain(Some(0)).collect()
+}
+
+unsafe fn open(path: &[u16], access: u32, disposition: u32, flags: u32) -> HANDLE {
+    CreateFileW(path.as_ptr(), access, 0, ptr::null_mut(), disposition, flags, ptr::null_mut())
+}
+
+unsafe fn final_path(file: HANDLE) -> Vec<u16> {
+    // Without room for the path, the size of the buffer it needs is returned.
+    let size = GetFinalPathNameByHandleW(file, ptr::null_mut(), 0, VOLUME_NAME_DOS);
+    assert!(size > 0);
+    let mut buf = vec![0u16; size as usize];
+    let len = GetFinalPathNameByHandleW(file, buf.as_mut_ptr(), size, VOLUME_NAME_DOS);
+    assert_eq!(len, size - 1);
+    assert_eq!(buf.pop(), Some(0));
+    buf
+}
+
+fn main() {
+    test_final_path();
+    test_file_operations();
+    test_read_dir();
+    test_read_write();
+    test_std_handles();
+}
+
+fn test_final_path() {
+    let path = tmp().join("miri_test_windows_fs.txt");
+    let wide_path = to_wide(&path);
+    unsafe {
+        let file = open(&wide_path, GENERIC_WRITE, CREATE_ALWAYS, 0);
+        assert_ne!(file, INVALID_HANDLE_VALUE);
+        let final_path = final_path(file);
+        assert!(OsString::from_wide(&final_path).to_str().unwrap().starts_with(r"\\?\"));
+        assert!(CloseHandle(file) != 0);
+
+        // The final path can be used to open the file again.
+        let mut wide_final_path = final_path.clone();
+        wide_final_path.push(0);
+        let file = open(&wide_final_path, 0, OPEN_EXISTING, 0);
+        assert_ne!(file, INVALID_HANDLE_VALUE);
+        assert_eq!(self::final_path(file), final_path);
+        assert!(CloseHandle(file) != 0);
+
+        // That is how `canonicalize` works.
+        let canonical = std::fs::canonicalize(&path).unwrap();
+        assert_eq!(canonical.as_os_str(), OsString::from_wide(&final_path));
+
+        // Directories can only be opened with backup semantics.
+        let dir = to_wide(&tmp());
+        assert_eq!(open(&dir, 0, OPEN_EXISTING, 0), INVALID_HANDLE_VALUE);
+        let dir = open(&dir, 0, OPEN_EXISTING, FILE_FLAG_BACKUP_SEMANTICS);
+        assert_ne!(dir, INVALID_HANDLE_VALUE);
+        assert!(CloseHandle(dir) != 0);
+
+        assert_eq!(GetFinalPathNameByHandleW(dir, ptr::null_mut(), 0, VOLUME_NAME_DOS), 0);
+        assert_eq!(GetLastError(), ERROR_INVALID_HANDLE);
+    }
+    fs::remove_file(&path).unwrap();
+}
+
+fn test_file_operations() {
+    let dir = tmp().join("miri_test_windows_fs_dir");
+    let from = dir.join("from.txt");
+    let to = dir.join("to.txt");
+    // Clean up after earlier runs.
+    fs::remove_file(&from).ok();
+    fs::remove_file(&to).ok();
+    fs::remove_dir(&dir).ok();
+
+    fs::create_dir(&dir).unwrap();
+    assert_eq!(fs::create_dir(&dir).unwrap_err().kind(), ErrorKind::AlreadyExists);
+
+    File::create(&from).unwrap();
+    File::create(&to).unwrap();
+    // `rename` replaces the destination.
+    fs::rename(&from, &to).unwrap();
+    assert_eq!(File::open(&from).unwrap_err().kind(), ErrorKind::NotFound);
+    File::open(&to).unwrap();
+
+    // Directories must be empty to be removed.
+    assert_eq!(fs::remove_dir(&dir).unwrap_err().kind(), ErrorKind::DirectoryNotEmpty);
+    fs::remove_file(&to).unwrap();
+    assert_eq!(fs::remove_file(&to).unwrap_err().kind(), ErrorKind::NotFound);
+    fs::remove_dir(&dir).unwrap();
+}
+
+fn test_read_dir() {
+    let dir = tmp().join("miri_test_windows_fs_read_dir");
+    let names = ["a.txt", "b.txt"];
+    let sub_dir = dir.join("sub");
+    // Clean up after earlier runs.
+    for name in names {
+        fs::remove_file(dir.join(name)).ok();
+    }
+    fs::remove_dir(&sub_dir).ok();
+    fs::remove_dir(&dir).ok();
+
+    fs::create_dir(&dir).unwrap();
+    // Empty directories can be listed.
+    assert_eq!(fs::read_dir(&dir).unwrap().count(), 0);
+
+    for name in names {
+        File::create(dir.join(name)).unwrap();
+    }
+    fs::create_dir(&sub_dir).unwrap();
+    let mut entries = fs::read_dir(&dir)
+        .unwrap()
+        .map(|entry| {
+            let entry = entry.unwrap();
+            (entry.file_name().into_string().unwrap(), entry.file_type().unwrap().is_dir())
+        })
+        .collect::<Vec<_>>();
+    entries.sort();
+    assert_eq!(
+        entries,
+        [("a.txt".to_owned(), false), ("b.txt".to_owned(), false), ("sub".to_owned(), true)]
+    );
+    assert_eq!(fs::read_dir(dir.join("missing")).unwrap_err().kind(), ErrorKind::NotFound);
+
+    for name in names {
+        fs::remove_file(dir.join(name)).unwrap();
+    }
+    fs::remove_dir(&sub_dir).unwrap();
+    fs::remove_dir(&dir).unwrap();
+}
+
+fn test_read_write() {
+    let path = tmp().join("miri_test_windows_fs_read_write.txt");
+    let bytes = b"Hello, World!\n";
+    let mut file = File::create(&path).unwrap();
+    file.write_all(bytes).unwrap();
+    drop(file);
+
+    let mut file = File::open(&path).unwrap();
+    let mut contents = [0u8; 32];
+    let n = file.read(&mut contents).unwrap();
+    assert_eq!(&contents[..n], bytes);
+    // Reads at the end of the file read nothing.
+    assert_eq!(file.read(&mut contents).unwrap(), 0);
+    drop(file);
+
+    fs::remove_file(&path).unwrap();
+}
+
+fn test_std_handles() {
+    unsafe {
+        let stdout = GetStdHandle(STD_OUTPUT_HANDLE);
+        let stderr = GetStdHandle(STD_ERROR_HANDLE);
+        assert_ne!(stdout, INVALID_HANDLE_VALUE);
+        assert_ne!(stdout, stderr);
+        assert_eq!(GetStdHandle(STD_OUTPUT_HANDLE), stdout);
+        assert_eq!(GetStdHandle(0), INVALID_HANDLE_VALUE);
+        assert_eq!(GetLastError(), ERROR_INVALID_HANDLE);
+    }
+}
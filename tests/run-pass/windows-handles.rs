@@ -0,0 +1,82 @@
+// Unfortunately, compiletest_rs does not support 'only-windows',
+// so we need to ignore Linux and macOS instead.
+// ignore-linux: Uses Windows-only APIs
+// ignore-macos: Uses Windows-only APIs
+use std::ffi::c_void;
+use std::ptr;
+
+type HANDLE = *mut c_void;
+
+const DUPLICATE_CLOSE_SOURCE: u32 = 1;
+const DUPLICATE_SAME_ACCESS: u32 = 2;
+const ERROR_INVALID_HANDLE: u32 = 6;
+const ERROR_INVALID_PARAMETER: u32 = 87;
+const WAIT_OBJECT_0: u32 = 0;
+const WAIT_TIMEOUT: u32 = 0x102;
+
+extern "system" {
+    fn GetCurrentProcess() -> HANDLE;
+    fn GetCurrentThread() -> HANDLE;
+    fn DuplicateHandle(
+        source_process: HANDLE,
+        source: HANDLE,
+        target_process: HANDLE,
+        target: *mut HANDLE,
+        access: u32,
+        inherit: i32,
+        options: u32,
+    ) -> i32;
+    fn CloseHandle(handle: HANDLE) -> i32;
+    fn CreateEventW(attrs: *mut c_void, manual: i32, initial: i32, name: *const u16) -> HANDLE;
+    fn SetEvent(event: HANDLE) -> i32;
+    fn WaitForSingleObject(handle: HANDLE, milliseconds: u32) -> u32;
+    fn GetLastError() -> u32;
+}
+
+unsafe fn duplicate(handle: HANDLE, options: u32) -> Option<HANDLE> {
+    let process = GetCurrentProcess();
+    let mut duplicate = ptr::null_mut();
+    let ok = DuplicateHandle(process, handle, process, &mut duplicate, 0, 0, options);
+    if ok != 0 { Some(duplicate) } else { None }
+}
+
+fn main() {
+    unsafe {
+        // Pseudo-handles are constants, and closing them does nothing.
+        assert_eq!(GetCurrentProcess() as isize, -1);
+        assert_eq!(GetCurrentThread() as isize, -2);
+        assert_eq!(CloseHandle(GetCurrentProcess()), 1);
+        assert_eq!(CloseHandle(GetCurrentThread()), 1);
+
+        // Duplicating them gives real handles, which work as process handles as well.
+        let process = duplicate(GetCurrentProcess(), DUPLICATE_SAME_ACCESS).unwrap();
+        assert!(process as isize > 0);
+        let thread = duplicate(GetCurrentThread(), DUPLICATE_SAME_ACCESS).unwrap();
+        assert_eq!(WaitForSingleObject(thread, 0), WAIT_TIMEOUT);
+        let mut event_copy = ptr::null_mut();
+        let event = CreateEventW(ptr::null_mut(), 1, 0, ptr::null());
+        assert_eq!(DuplicateHandle(process, event, process, &mut event_copy, 0, 0, 0), 1);
+        assert_ne!(event_copy, event);
+
+        // Duplicates refer to the same object, which lives until its last handle is closed.
+        assert_eq!(SetEvent(event_copy), 1);
+        assert_eq!(WaitForSingleObject(event, 0), WAIT_OBJECT_0);
+        assert_eq!(CloseHandle(event), 1);
+        assert_eq!(WaitForSingleObject(event_copy, 0), WAIT_OBJECT_0);
+        let moved = duplicate(event_copy, DUPLICATE_CLOSE_SOURCE).unwrap();
+        assert_eq!(WaitForSingleObject(event_copy, 0), u32::MAX);
+        assert_eq!(WaitForSingleObject(moved, 0), WAIT_OBJECT_0);
+
+        // Errors.
+        assert_eq!(duplicate(ptr::null_mut(), 0), None);
+        assert_eq!(GetLastError(), ERROR_INVALID_HANDLE);
+        assert_eq!(duplicate(moved, 4), None);
+        assert_eq!(GetLastError(), ERROR_INVALID_PARAMETER);
+        assert_eq!(CloseHandle(0x1234 as HANDLE), 0);
+        assert_eq!(GetLastError(), ERROR_INVALID_HANDLE);
+
+        for handle in [moved, thread, process] {
+            assert_eq!(CloseHandle(handle), 1);
+        }
+    }
+}
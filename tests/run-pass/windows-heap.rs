@@ -0,0 +1,40 @@
+// ignore-linux: Uses Windows-only APIs
+// ignore-macos: Uses Windows-only APIs
+use std::ffi::c_void;
+use std::ptr;
+
+type HANDLE = *mut c_void;
+
+const HEAP_ZERO_MEMORY: u32 = 0x8;
+
+extern "system" {
+    fn GetProcessHeap() -> HANDLE;
+    fn HeapAlloc(heap: HANDLE, flags: u32, size: usize) -> *mut c_void;
+    fn HeapReAlloc(heap: HANDLE, flags: u32, mem: *mut c_void, size: usize) -> *mut c_void;
+    fn HeapFree(heap: HANDLE, flags: u32, mem: *mut c_void) -> i32;
+    fn HeapSize(heap: HANDLE, flags: u32, mem: *const c_void) -> usize;
+    fn HeapValidate(heap: HANDLE, flags: u32, mem: *const c_void) -> i32;
+}
+
+fn main() {
+    unsafe {
+        let heap = GetProcessHeap();
+        assert!(!heap.is_null());
+
+        let p = HeapAlloc(heap, HEAP_ZERO_MEMORY, 20);
+        assert_eq!(HeapSize(heap, 0, p), 20);
+        assert_eq!(*(p as *const u8).add(19), 0);
+        assert_ne!(HeapValidate(heap, 0, p), 0);
+        assert_ne!(HeapValidate(heap, 0, ptr::null()), 0);
+        // Only the start of a block is a valid block.
+        assert_eq!(HeapValidate(heap, 0, (p as *const u8).add(1) as *const c_void), 0);
+
+        let p = HeapReAlloc(heap, 0, p, 50);
+        assert_eq!(HeapSize(heap, 0, p), 50);
+        assert_ne!(HeapFree(heap, 0, p), 0);
+
+        // Not a heap block.
+        let local = 0u64;
+        assert_eq!(HeapValidate(heap, 0, &local as *const u64 as *const c_void), 0);
+    }
+}
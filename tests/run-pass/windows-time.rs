@@ -0,0 +1,138 @@
+// Unfortunately, compiletest_rs does not support 'only-windows',
+// so we need to ignore Linux and macOS instead.
+// ignore-linux: Uses Windows-only APIs
+// ignore-macos: Uses Windows-only APIs
+// compile-flags: -Zmiri-disable-isolation
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+const ERROR_INVALID_PARAMETER: u32 = 87;
+
+#[repr(C)]
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+#[allow(non_snake_case)]
+struct FILETIME {
+    dwLowDateTime: u32,
+    dwHighDateTime: u32,
+}
+
+#[repr(C)]
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+#[allow(non_snake_case)]
+struct SYSTEMTIME {
+    wYear: u16,
+    wMonth: u16,
+    wDayOfWeek: u16,
+    wDay: u16,
+    wHour: u16,
+    wMinute: u16,
+    wSecond: u16,
+    wMilliseconds: u16,
+}
+
+extern "system" {
+    fn GetSystemTimeAsFileTime(file_time: *mut FILETIME);
+    fn GetSystemTimePreciseAsFileTime(file_time: *mut FILETIME);
+    fn SystemTimeToFileTime(system_time: *const SYSTEMTIME, file_time: *mut FILETIME) -> i32;
+    fn FileTimeToSystemTime(file_time: *const FILETIME, system_time: *mut SYSTEMTIME) -> i32;
+    fn GetLastError() -> u32;
+}
+
+/// The number of 100ns intervals from 1601-01-01 to 1970-01-01.
+const INTERVALS_TO_UNIX_EPOCH: u64 = 11_644_473_600 * 10_000_000;
+
+fn ticks(file_time: FILETIME) -> u64 {
+    u64::from(file_time.dwHighDateTime) << 32 | u64::from(file_time.dwLowDateTime)
+}
+
+fn file_time(ticks: u64) -> FILETIME {
+    FILETIME { dwLowDateTime: ticks as u32, dwHighDateTime: (ticks >> 32) as u32 }
+}
+
+fn system_time(date: [u16; 3], time: [u16; 4]) -> SYSTEMTIME {
+    SYSTEMTIME {
+        wYear: date[0],
+        wMonth: date[1],
+        wDayOfWeek: 0,
+        wDay: date[2],
+        wHour: time[0],
+        wMinute: time[1],
+        wSecond: time[2],
+        wMilliseconds: time[3],
+    }
+}
+
+fn to_file_time(system_time: SYSTEMTIME) -> Option<u64> {
+    let mut file_time = FILETIME::default();
+    let ok = unsafe { SystemTimeToFileTime(&system_time, &mut file_time) };
+    if ok != 0 { Some(ticks(file_time)) } else { None }
+}
+
+fn to_system_time(ticks: u64) -> Option<SYSTEMTIME> {
+    let mut system_time = SYSTEMTIME::default();
+    let ok = unsafe { FileTimeToSystemTime(&file_time(ticks), &mut system_time) };
+    if ok != 0 { Some(system_time) } else { None }
+}
+
+fn now(precise: bool) -> SystemTime {
+    let mut file_time = FILETIME::default();
+    unsafe {
+        if precise {
+            GetSystemTimePreciseAsFileTime(&mut file_time);
+        } else {
+            GetSystemTimeAsFileTime(&mut file_time);
+        }
+    }
+    UNIX_EPOCH + Duration::from_nanos((ticks(file_time) - INTERVALS_TO_UNIX_EPOCH) * 100)
+}
+
+fn main() {
+    // The epochs.
+    assert_eq!(to_file_time(system_time([1601, 1, 1], [0, 0, 0, 0])), Some(0));
+    let unix_epoch = system_time([1970, 1, 1], [0, 0, 0, 0]);
+    assert_eq!(to_file_time(unix_epoch), Some(INTERVALS_TO_UNIX_EPOCH));
+    // 1970-01-01 was a Thursday.
+    let expected = SYSTEMTIME { wDayOfWeek: 4, ..unix_epoch };
+    assert_eq!(to_system_time(INTERVALS_TO_UNIX_EPOCH), Some(expected));
+
+    // Round trips, including a leap day.
+    for (date, time) in [
+        ([2000, 2, 29], [23, 59, 59, 999]),
+        ([2022, 6, 15], [12, 34, 56, 789]),
+        ([30827, 12, 31], [23, 59, 59, 999]),
+    ] {
+        let ticks = to_file_time(system_time(date, time)).unwrap();
+        let round_trip = to_system_time(ticks).unwrap();
+        assert_eq!(SYSTEMTIME { wDayOfWeek: 0, ..round_trip }, system_time(date, time));
+        assert_eq!(to_file_time(round_trip), Some(ticks));
+    }
+    // 2022-06-15 was a Wednesday.
+    let wednesday = to_file_time(system_time([2022, 6, 15], [0, 0, 0, 0])).unwrap();
+    assert_eq!(to_system_time(wednesday).unwrap().wDayOfWeek, 3);
+    // `FileTimeToSystemTime` rounds down to milliseconds.
+    let ms = to_system_time(INTERVALS_TO_UNIX_EPOCH + 19_999).unwrap().wMilliseconds;
+    assert_eq!(ms, 1);
+
+    // Invalid times.
+    for (date, time) in [
+        ([1600, 12, 31], [0, 0, 0, 0]),
+        ([2021, 2, 29], [0, 0, 0, 0]),
+        ([2022, 13, 1], [0, 0, 0, 0]),
+        ([2022, 1, 0], [0, 0, 0, 0]),
+        ([2022, 1, 1], [24, 0, 0, 0]),
+        ([2022, 1, 1], [0, 0, 0, 1000]),
+    ] {
+        assert_eq!(to_file_time(system_time(date, time)), None);
+        assert_eq!(unsafe { GetLastError() }, ERROR_INVALID_PARAMETER);
+    }
+    assert_eq!(to_system_time(1 << 63), None);
+    assert_eq!(unsafe { GetLastError() }, ERROR_INVALID_PARAMETER);
+
+    // The clocks agree with each other and with `SystemTime`.
+    let before = SystemTime::now();
+    let imprecise = now(false);
+    let precise = now(true);
+    let after = SystemTime::now();
+    // `FILETIME`s round down to 100ns.
+    let tick = Duration::from_nanos(100);
+    assert!(before <= imprecise + tick && imprecise <= precise && precise <= after);
+}
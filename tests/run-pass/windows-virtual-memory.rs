@@ -0,0 +1,75 @@
+// ignore-linux: Uses Windows-only APIs
+// ignore-macos: Uses Windows-only APIs
+use std::ffi::c_void;
+use std::ptr;
+
+const MEM_COMMIT: u32 = 0x1000;
+const MEM_RESERVE: u32 = 0x2000;
+const MEM_DECOMMIT: u32 = 0x4000;
+const MEM_RELEASE: u32 = 0x8000;
+const PAGE_NOACCESS: u32 = 0x01;
+const PAGE_READONLY: u32 = 0x02;
+const PAGE_READWRITE: u32 = 0x04;
+const ERROR_INVALID_PARAMETER: u32 = 87;
+const ERROR_INVALID_ADDRESS: u32 = 487;
+
+const PAGE: usize = 4096;
+
+extern "system" {
+    fn VirtualAlloc(address: *mut c_void, size: usize, ty: u32, protect: u32) -> *mut c_void;
+    fn VirtualFree(address: *mut c_void, size: usize, ty: u32) -> i32;
+    fn VirtualProtect(address: *mut c_void, size: usize, new: u32, old: *mut u32) -> i32;
+    fn GetLastError() -> u32;
+}
+
+fn main() {
+    unsafe {
+        // Reserve four pages and commit the second one.
+        let region = VirtualAlloc(ptr::null_mut(), 4 * PAGE, MEM_RESERVE, PAGE_NOACCESS);
+        let region = region as *mut u8;
+        assert!(!region.is_null());
+        assert_eq!(region as usize % 0x10000, 0);
+        let page = VirtualAlloc(region.add(PAGE + 10).cast(), 1, MEM_COMMIT, PAGE_READWRITE);
+        assert_eq!(page as *mut u8, region.add(PAGE));
+        assert_eq!(*region.add(PAGE + 10), 0);
+        *region.add(PAGE + 10) = 42;
+
+        // Committing again keeps the contents.
+        let page = VirtualAlloc(page, PAGE, MEM_COMMIT, PAGE_READWRITE);
+        assert_eq!(*region.add(PAGE + 10), 42);
+
+        let mut old = 0;
+        assert_ne!(VirtualProtect(page, PAGE, PAGE_READONLY, &mut old), 0);
+        assert_eq!(old, PAGE_READWRITE);
+        assert_eq!(*region.add(PAGE + 10), 42);
+
+        // Only committed pages can be protected.
+        assert_eq!(VirtualProtect(region.cast(), 2 * PAGE, PAGE_READWRITE, &mut old), 0);
+        assert_eq!(GetLastError(), ERROR_INVALID_ADDRESS);
+
+        // Decommitted pages are zero again when they are committed next.
+        assert_ne!(VirtualFree(page, PAGE, MEM_DECOMMIT), 0);
+        VirtualAlloc(page, PAGE, MEM_COMMIT, PAGE_READWRITE);
+        assert_eq!(*region.add(PAGE + 10), 0);
+
+        // Regions can only be released as a whole.
+        assert_eq!(VirtualFree(page, 0, MEM_RELEASE), 0);
+        assert_eq!(GetLastError(), ERROR_INVALID_ADDRESS);
+        assert_eq!(VirtualFree(region.cast(), PAGE, MEM_RELEASE), 0);
+        assert_eq!(GetLastError(), ERROR_INVALID_PARAMETER);
+        assert_ne!(VirtualFree(region.cast(), 0, MEM_RELEASE), 0);
+
+        // Reserving and committing at once.
+        let p = VirtualAlloc(ptr::null_mut(), 100, MEM_RESERVE | MEM_COMMIT, PAGE_READWRITE);
+        let slice = std::slice::from_raw_parts_mut(p as *mut u8, PAGE);
+        assert!(slice.iter().all(|&b| b == 0));
+        slice[PAGE - 1] = 1;
+        assert_ne!(VirtualFree(p, 0, MEM_RELEASE), 0);
+
+        // Addresses outside of any region.
+        let mut local = 0u8;
+        let p = VirtualAlloc((&mut local as *mut u8).cast(), 1, MEM_COMMIT, PAGE_READWRITE);
+        assert!(p.is_null());
+        assert_eq!(GetLastError(), ERROR_INVALID_ADDRESS);
+    }
+}